@@ -0,0 +1,5 @@
+-- v10: Page thumbnails
+-- Path of a downscaled preview image per enriched page, and when we last looked for one.
+
+ALTER TABLE metadata ADD COLUMN thumbnail_path TEXT;
+ALTER TABLE metadata ADD COLUMN thumbnail_checked_at INTEGER;
@@ -0,0 +1,15 @@
+-- v11: Page archives
+-- Snapshots of pages saved to disk so they survive link rot; a URL can be archived many times.
+
+CREATE TABLE IF NOT EXISTS archive (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url_id TEXT NOT NULL REFERENCES url(id) ON DELETE CASCADE,
+    -- URL actually archived, after redirects
+    final_url TEXT NOT NULL,
+    path TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_archive_url ON archive (url_id, created_at);
@@ -0,0 +1,9 @@
+-- v12: Shortened URL expansion
+-- Destination of every resolved short link, so later imports can be merged without a request.
+
+CREATE TABLE IF NOT EXISTS url_redirect (
+    source_url TEXT PRIMARY KEY,
+    -- Canonical URL the short link resolved to
+    target_url TEXT NOT NULL,
+    resolved_at INTEGER NOT NULL
+);
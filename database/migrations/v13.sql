@@ -0,0 +1,5 @@
+-- v13: Reading time
+-- Article length measured whenever a page's content is fetched.
+
+ALTER TABLE metadata ADD COLUMN word_count INTEGER;
+ALTER TABLE metadata ADD COLUMN reading_time_sec INTEGER;
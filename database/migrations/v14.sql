@@ -0,0 +1,33 @@
+-- v14: Query views
+-- Stable, documented views that generated and ad-hoc SQL is allowed to read.
+-- Times are exposed both as unix seconds (*_ts) and as UTC text (YYYY-MM-DD HH:MM:SS).
+
+CREATE VIEW IF NOT EXISTS history_pages AS
+SELECT u.id AS page_id,
+       u.url AS page_url,
+       u.title,
+       u.domain,
+       u.category,
+       datetime(u.first_seen, 'unixepoch') AS first_seen,
+       datetime(u.last_seen, 'unixepoch') AS last_seen,
+       m.summary,
+       m.keywords,
+       m.topic_cluster AS topic,
+       m.word_count,
+       m.reading_time_sec
+FROM url u
+LEFT JOIN metadata m ON m.url_id = u.id;
+
+CREATE VIEW IF NOT EXISTS history_visits AS
+SELECT v.id AS visit_id,
+       v.url_id AS page_id,
+       u.url AS page_url,
+       u.title,
+       u.domain,
+       u.category,
+       v.visited_at AS visited_ts,
+       datetime(v.visited_at, 'unixepoch') AS visited_at,
+       v.duration_sec,
+       v.device_name
+FROM visit v
+JOIN url u ON u.id = v.url_id;
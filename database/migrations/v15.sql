@@ -0,0 +1,33 @@
+-- v15: Normalized tags
+-- Replaces the JSON array in metadata.tags with tag and url_tag tables.
+
+CREATE TABLE IF NOT EXISTS tag (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS url_tag (
+    url_id TEXT NOT NULL REFERENCES url(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tag(id) ON DELETE CASCADE,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (url_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_url_tag_tag ON url_tag (tag_id);
+
+-- Convert existing JSON tags (anything that isn't a JSON array is ignored)
+INSERT OR IGNORE INTO tag (name, created_at)
+SELECT DISTINCT trim(j.value), CAST(strftime('%s', 'now') AS INTEGER)
+FROM metadata m, json_each(m.tags) j
+WHERE json_valid(m.tags) AND json_type(m.tags) = 'array'
+  AND j.type = 'text' AND trim(j.value) != '';
+
+INSERT OR IGNORE INTO url_tag (url_id, tag_id, created_at)
+SELECT m.url_id, t.id, CAST(strftime('%s', 'now') AS INTEGER)
+FROM metadata m, json_each(m.tags) j
+JOIN tag t ON t.name = trim(j.value)
+WHERE json_valid(m.tags) AND json_type(m.tags) = 'array'
+  AND j.type = 'text' AND trim(j.value) != '';
+
+ALTER TABLE metadata DROP COLUMN tags;
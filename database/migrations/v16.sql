@@ -0,0 +1,26 @@
+-- v16: Favorites and collections
+-- A favorite flag on URLs and user-defined, ordered lists of URLs.
+
+ALTER TABLE url ADD COLUMN is_favorite INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_url_favorite ON url (is_favorite) WHERE is_favorite = 1;
+
+CREATE TABLE IF NOT EXISTS collection (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    description TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS collection_item (
+    collection_id INTEGER NOT NULL REFERENCES collection(id) ON DELETE CASCADE,
+    url_id TEXT NOT NULL REFERENCES url(id) ON DELETE CASCADE,
+    -- 0-based order within the collection
+    position INTEGER NOT NULL,
+    note TEXT,
+    added_at INTEGER NOT NULL,
+    PRIMARY KEY (collection_id, url_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_collection_item_order ON collection_item (collection_id, position);
@@ -0,0 +1,23 @@
+-- v17: Manual edits
+-- Log of user edits to URLs and visits, and tombstones that stop re-imports
+-- from restoring deleted visits. Tombstones match on the URL string because
+-- every import assigns new ids.
+
+CREATE TABLE IF NOT EXISTS url_edit (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url_id TEXT NOT NULL REFERENCES url(id) ON DELETE CASCADE,
+    -- title, domain, visit_deleted or visit_added
+    field TEXT NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    edited_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_url_edit_url ON url_edit (url_id, edited_at);
+
+CREATE TABLE IF NOT EXISTS visit_tombstone (
+    url TEXT NOT NULL,
+    visited_at INTEGER NOT NULL,
+    deleted_at INTEGER NOT NULL,
+    PRIMARY KEY (url, visited_at)
+);
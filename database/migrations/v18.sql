@@ -0,0 +1,25 @@
+-- v18: Operation journal
+-- Before-images of the latest destructive operations, used to undo them.
+-- Each image holds the rows of one table matching a condition, as JSON.
+
+CREATE TABLE IF NOT EXISTS operation_journal (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- delete_visits, merge_urls, merge_tags, delete_tag, delete_collection, tag_urls or untag_urls
+    kind TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS operation_image (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    operation_id INTEGER NOT NULL REFERENCES operation_journal(id) ON DELETE CASCADE,
+    table_name TEXT NOT NULL,
+    -- SQL condition selecting every row the operation could touch, with JSON parameters
+    condition TEXT NOT NULL,
+    params TEXT NOT NULL,
+    -- JSON array of column names and JSON array of rows (arrays of values)
+    columns TEXT NOT NULL,
+    rows TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_operation_image_operation ON operation_image (operation_id);
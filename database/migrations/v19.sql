@@ -0,0 +1,19 @@
+-- v19: Import audit log
+-- One row per import run: which files were read (with device names and
+-- per-file counts), what was inserted, warnings and timing.
+
+CREATE TABLE IF NOT EXISTS import_run (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    started_at INTEGER NOT NULL,
+    -- NULL while running, or if the run was interrupted
+    finished_at INTEGER,
+    duration_sec REAL,
+    -- JSON array of {path, device_name, urls, visits, error}
+    files TEXT NOT NULL,
+    urls_inserted INTEGER NOT NULL DEFAULT 0,
+    visits_inserted INTEGER NOT NULL DEFAULT 0,
+    -- JSON array of warning messages
+    warnings TEXT NOT NULL DEFAULT '[]'
+);
+
+CREATE INDEX IF NOT EXISTS idx_import_run_started ON import_run (started_at);
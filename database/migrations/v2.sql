@@ -0,0 +1,8 @@
+-- v2: Persisted application settings
+-- Values are stored as JSON so each setting can have its own typed structure
+
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
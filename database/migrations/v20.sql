@@ -0,0 +1,10 @@
+-- v20: Import run ownership
+-- New URLs and visits remember the import run that added them, so a bad
+-- import can be rolled back. Rows added before this version have no run.
+
+ALTER TABLE url ADD COLUMN import_run_id INTEGER REFERENCES import_run(id) ON DELETE SET NULL;
+ALTER TABLE visit ADD COLUMN import_run_id INTEGER REFERENCES import_run(id) ON DELETE SET NULL;
+ALTER TABLE import_run ADD COLUMN rolled_back_at INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_url_import_run ON url (import_run_id);
+CREATE INDEX IF NOT EXISTS idx_visit_import_run ON visit (import_run_id);
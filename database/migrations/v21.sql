@@ -0,0 +1,49 @@
+-- v21: Unique URLs and visits
+-- Imports used to check for existing rows before inserting, which let
+-- concurrent imports create duplicates. Existing duplicates are folded into
+-- the oldest row before the unique indexes are created. Rows pointing at a
+-- duplicate are moved to the kept URL first, since deleting it cascades;
+-- where the kept URL already has the row (same tag, collection, embedding,
+-- job or metadata) the duplicate's copy is left to the cascade.
+
+CREATE TEMP TABLE url_duplicate AS
+SELECT d.id AS duplicate_id, k.id AS kept_id
+FROM url d
+JOIN (SELECT url, MIN(rowid) AS kept_rowid FROM url GROUP BY url) g ON g.url = d.url
+JOIN url k ON k.rowid = g.kept_rowid
+WHERE d.rowid <> g.kept_rowid;
+
+UPDATE visit SET url_id = (SELECT kept_id FROM url_duplicate WHERE duplicate_id = visit.url_id)
+WHERE url_id IN (SELECT duplicate_id FROM url_duplicate);
+
+UPDATE OR IGNORE metadata SET url_id = (SELECT kept_id FROM url_duplicate WHERE duplicate_id = metadata.url_id)
+WHERE url_id IN (SELECT duplicate_id FROM url_duplicate);
+
+UPDATE OR IGNORE embedding SET url_id = (SELECT kept_id FROM url_duplicate WHERE duplicate_id = embedding.url_id)
+WHERE url_id IN (SELECT duplicate_id FROM url_duplicate);
+
+UPDATE OR IGNORE enrichment_job SET url_id = (SELECT kept_id FROM url_duplicate WHERE duplicate_id = enrichment_job.url_id)
+WHERE url_id IN (SELECT duplicate_id FROM url_duplicate);
+
+UPDATE archive SET url_id = (SELECT kept_id FROM url_duplicate WHERE duplicate_id = archive.url_id)
+WHERE url_id IN (SELECT duplicate_id FROM url_duplicate);
+
+UPDATE OR IGNORE url_tag SET url_id = (SELECT kept_id FROM url_duplicate WHERE duplicate_id = url_tag.url_id)
+WHERE url_id IN (SELECT duplicate_id FROM url_duplicate);
+
+UPDATE OR IGNORE collection_item SET url_id = (SELECT kept_id FROM url_duplicate WHERE duplicate_id = collection_item.url_id)
+WHERE url_id IN (SELECT duplicate_id FROM url_duplicate);
+
+UPDATE url_edit SET url_id = (SELECT kept_id FROM url_duplicate WHERE duplicate_id = url_edit.url_id)
+WHERE url_id IN (SELECT duplicate_id FROM url_duplicate);
+
+DELETE FROM url WHERE id IN (SELECT duplicate_id FROM url_duplicate);
+
+DROP TABLE url_duplicate;
+
+DELETE FROM visit WHERE rowid NOT IN (
+    SELECT MIN(rowid) FROM visit GROUP BY url_id, visited_at, source_file
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_url_url_unique ON url (url);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_visit_unique ON visit (url_id, visited_at, source_file);
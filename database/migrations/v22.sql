@@ -0,0 +1,19 @@
+-- v22: Query path indexes
+-- Timeline, date-range and domain queries scanned whole tables on large
+-- histories. Lookups of visits by URL are served by idx_visit_unique (v21),
+-- whose first column is url_id.
+
+-- Timeline buckets, date filters and sessions
+CREATE INDEX IF NOT EXISTS idx_visit_visited_at ON visit (visited_at);
+
+-- Per-device statistics over a date range
+CREATE INDEX IF NOT EXISTS idx_visit_device ON visit (device_name, visited_at);
+
+-- Domain filters and grouping
+CREATE INDEX IF NOT EXISTS idx_url_domain ON url (domain);
+
+-- Enrichment backlog and enriched counts
+CREATE INDEX IF NOT EXISTS idx_metadata_enriched ON metadata (is_enriched);
+
+-- Let the planner pick up the new indexes
+ANALYZE;
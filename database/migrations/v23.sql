@@ -0,0 +1,40 @@
+-- v23: Cached history statistics
+-- get_stats stores its result here; triggers mark it dirty whenever a change
+-- could affect it. The WHERE dirty = 0 guard keeps bulk imports to one write.
+
+CREATE TABLE IF NOT EXISTS stats_cache (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    dirty INTEGER NOT NULL DEFAULT 1,
+    -- JSON of the last computed statistics
+    stats TEXT,
+    computed_at INTEGER
+);
+
+INSERT OR IGNORE INTO stats_cache (id) VALUES (1);
+
+CREATE TRIGGER IF NOT EXISTS stats_dirty_url_insert AFTER INSERT ON url
+BEGIN UPDATE stats_cache SET dirty = 1 WHERE dirty = 0; END;
+
+CREATE TRIGGER IF NOT EXISTS stats_dirty_url_delete AFTER DELETE ON url
+BEGIN UPDATE stats_cache SET dirty = 1 WHERE dirty = 0; END;
+
+CREATE TRIGGER IF NOT EXISTS stats_dirty_url_update AFTER UPDATE OF domain ON url
+BEGIN UPDATE stats_cache SET dirty = 1 WHERE dirty = 0; END;
+
+CREATE TRIGGER IF NOT EXISTS stats_dirty_visit_insert AFTER INSERT ON visit
+BEGIN UPDATE stats_cache SET dirty = 1 WHERE dirty = 0; END;
+
+CREATE TRIGGER IF NOT EXISTS stats_dirty_visit_delete AFTER DELETE ON visit
+BEGIN UPDATE stats_cache SET dirty = 1 WHERE dirty = 0; END;
+
+CREATE TRIGGER IF NOT EXISTS stats_dirty_visit_update AFTER UPDATE OF url_id, visited_at ON visit
+BEGIN UPDATE stats_cache SET dirty = 1 WHERE dirty = 0; END;
+
+CREATE TRIGGER IF NOT EXISTS stats_dirty_metadata_insert AFTER INSERT ON metadata
+BEGIN UPDATE stats_cache SET dirty = 1 WHERE dirty = 0; END;
+
+CREATE TRIGGER IF NOT EXISTS stats_dirty_metadata_delete AFTER DELETE ON metadata
+BEGIN UPDATE stats_cache SET dirty = 1 WHERE dirty = 0; END;
+
+CREATE TRIGGER IF NOT EXISTS stats_dirty_metadata_update AFTER UPDATE OF is_enriched ON metadata
+BEGIN UPDATE stats_cache SET dirty = 1 WHERE dirty = 0; END;
@@ -0,0 +1,35 @@
+-- v24: Device registry
+-- Devices become records of their own so differently spelled names of one
+-- machine can be renamed and merged. visit.device_name stays as the display
+-- name and is kept in sync with the linked device.
+
+CREATE TABLE IF NOT EXISTS device (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    -- macos, ios, ipados or NULL when unknown
+    platform TEXT,
+    first_imported_at INTEGER,
+    last_imported_at INTEGER
+);
+
+ALTER TABLE visit ADD COLUMN device_id INTEGER REFERENCES device(id) ON DELETE SET NULL;
+
+-- Register the device names already in the history, dated by their import runs
+INSERT OR IGNORE INTO device (name, platform, first_imported_at, last_imported_at)
+SELECT v.device_name,
+       CASE
+           WHEN v.device_name LIKE '%iphone%' THEN 'ios'
+           WHEN v.device_name LIKE '%ipad%' THEN 'ipados'
+           WHEN v.device_name LIKE '%mac%' THEN 'macos'
+       END,
+       MIN(r.started_at),
+       MAX(r.started_at)
+FROM visit v
+LEFT JOIN import_run r ON r.id = v.import_run_id
+WHERE v.device_name IS NOT NULL AND trim(v.device_name) != ''
+GROUP BY v.device_name;
+
+UPDATE visit SET device_id = (SELECT id FROM device WHERE name = visit.device_name)
+WHERE device_name IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_visit_device_id ON visit (device_id);
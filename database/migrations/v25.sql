@@ -0,0 +1,68 @@
+-- v25: Normalized keywords
+-- metadata.keywords keeps the JSON array written by enrichment; keyword and
+-- url_keyword index it so keywords can be counted, filtered and graphed
+-- without parsing JSON. Triggers keep the index in sync with the column.
+
+CREATE TABLE IF NOT EXISTS keyword (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS url_keyword (
+    url_id TEXT NOT NULL REFERENCES url(id) ON DELETE CASCADE,
+    keyword_id INTEGER NOT NULL REFERENCES keyword(id) ON DELETE CASCADE,
+    PRIMARY KEY (url_id, keyword_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_url_keyword_keyword ON url_keyword (keyword_id);
+
+-- Older rows may hold a comma separated list; rewrite them as JSON arrays
+UPDATE metadata
+SET keywords = '["' || replace(replace(replace(keywords, '\', ''), '"', ''), ',', '","') || '"]'
+WHERE keywords IS NOT NULL AND trim(keywords) != ''
+  AND NOT (json_valid(keywords) AND json_type(keywords) = 'array');
+
+INSERT OR IGNORE INTO keyword (name)
+SELECT DISTINCT lower(trim(j.value))
+FROM metadata m, json_each(m.keywords) j
+WHERE json_valid(m.keywords) AND json_type(m.keywords) = 'array'
+  AND j.type = 'text' AND trim(j.value) != '';
+
+INSERT OR IGNORE INTO url_keyword (url_id, keyword_id)
+SELECT m.url_id, k.id
+FROM metadata m, json_each(m.keywords) j
+JOIN keyword k ON k.name = lower(trim(j.value))
+WHERE json_valid(m.keywords) AND json_type(m.keywords) = 'array'
+  AND j.type = 'text';
+
+CREATE TRIGGER IF NOT EXISTS keywords_metadata_insert AFTER INSERT ON metadata
+WHEN json_valid(NEW.keywords) AND json_type(NEW.keywords) = 'array'
+BEGIN
+    INSERT OR IGNORE INTO keyword (name)
+    SELECT lower(trim(j.value)) FROM json_each(NEW.keywords) j
+    WHERE j.type = 'text' AND trim(j.value) != '';
+
+    INSERT OR IGNORE INTO url_keyword (url_id, keyword_id)
+    SELECT NEW.url_id, k.id FROM json_each(NEW.keywords) j
+    JOIN keyword k ON k.name = lower(trim(j.value))
+    WHERE j.type = 'text';
+END;
+
+CREATE TRIGGER IF NOT EXISTS keywords_metadata_update AFTER UPDATE OF keywords, url_id ON metadata
+BEGIN
+    DELETE FROM url_keyword WHERE url_id = OLD.url_id;
+
+    INSERT OR IGNORE INTO keyword (name)
+    SELECT lower(trim(j.value)) FROM json_each(CASE WHEN json_valid(NEW.keywords) AND json_type(NEW.keywords) = 'array' THEN NEW.keywords ELSE '[]' END) j
+    WHERE j.type = 'text' AND trim(j.value) != '';
+
+    INSERT OR IGNORE INTO url_keyword (url_id, keyword_id)
+    SELECT NEW.url_id, k.id FROM json_each(CASE WHEN json_valid(NEW.keywords) AND json_type(NEW.keywords) = 'array' THEN NEW.keywords ELSE '[]' END) j
+    JOIN keyword k ON k.name = lower(trim(j.value))
+    WHERE j.type = 'text';
+END;
+
+CREATE TRIGGER IF NOT EXISTS keywords_metadata_delete AFTER DELETE ON metadata
+BEGIN
+    DELETE FROM url_keyword WHERE url_id = OLD.url_id;
+END;
@@ -0,0 +1,60 @@
+-- v26: Per-domain aggregates
+-- domain_stats holds visit and URL counts per domain so domain lists and
+-- top-domain queries don't aggregate every visit. Triggers only flag changed
+-- domains as dirty; refresh_domain_stats recomputes them after imports and
+-- before reads.
+
+CREATE TABLE IF NOT EXISTS domain_stats (
+    domain TEXT PRIMARY KEY,
+    url_count INTEGER NOT NULL DEFAULT 0,
+    visit_count INTEGER NOT NULL DEFAULT 0,
+    first_visit INTEGER,
+    last_visit INTEGER,
+    -- Most common category of the domain's URLs
+    category TEXT,
+    favicon TEXT,
+    dirty INTEGER NOT NULL DEFAULT 1,
+    updated_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_domain_stats_visits ON domain_stats (visit_count DESC);
+CREATE INDEX IF NOT EXISTS idx_domain_stats_dirty ON domain_stats (dirty) WHERE dirty = 1;
+
+INSERT OR IGNORE INTO domain_stats (domain, dirty)
+SELECT DISTINCT domain, 1 FROM url;
+
+CREATE TRIGGER IF NOT EXISTS domain_stats_url_insert AFTER INSERT ON url
+BEGIN
+    INSERT INTO domain_stats (domain, dirty) VALUES (NEW.domain, 1)
+    ON CONFLICT (domain) DO UPDATE SET dirty = 1 WHERE dirty = 0;
+END;
+
+CREATE TRIGGER IF NOT EXISTS domain_stats_url_delete AFTER DELETE ON url
+BEGIN
+    UPDATE domain_stats SET dirty = 1 WHERE domain = OLD.domain AND dirty = 0;
+END;
+
+CREATE TRIGGER IF NOT EXISTS domain_stats_url_update AFTER UPDATE OF domain, category ON url
+BEGIN
+    UPDATE domain_stats SET dirty = 1 WHERE domain = OLD.domain AND dirty = 0;
+    INSERT INTO domain_stats (domain, dirty) VALUES (NEW.domain, 1)
+    ON CONFLICT (domain) DO UPDATE SET dirty = 1 WHERE dirty = 0;
+END;
+
+CREATE TRIGGER IF NOT EXISTS domain_stats_visit_insert AFTER INSERT ON visit
+BEGIN
+    UPDATE domain_stats SET dirty = 1
+    WHERE domain = (SELECT domain FROM url WHERE id = NEW.url_id) AND dirty = 0;
+END;
+
+CREATE TRIGGER IF NOT EXISTS domain_stats_visit_delete AFTER DELETE ON visit
+BEGIN
+    UPDATE domain_stats SET dirty = 1
+    WHERE domain = (SELECT domain FROM url WHERE id = OLD.url_id) AND dirty = 0;
+END;
+
+CREATE TRIGGER IF NOT EXISTS domain_stats_visit_update AFTER UPDATE OF url_id, visited_at ON visit
+BEGIN
+    UPDATE domain_stats SET dirty = 1
+    WHERE domain IN (SELECT domain FROM url WHERE id IN (OLD.url_id, NEW.url_id)) AND dirty = 0;
+END;
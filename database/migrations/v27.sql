@@ -0,0 +1,25 @@
+-- v27: Background jobs
+-- Imports, enrichment runs, graph rebuilds and maintenance are recorded as
+-- jobs. Queued jobs survive a restart and are picked up by the worker again.
+
+CREATE TABLE IF NOT EXISTS job (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- import, enrichment, rebuild_graph or compact
+    kind TEXT NOT NULL,
+    -- JSON job request, including the kind
+    request TEXT NOT NULL,
+    -- queued, running, done, failed or cancelled
+    state TEXT NOT NULL DEFAULT 'queued',
+    -- 0.0 to 1.0
+    progress REAL NOT NULL DEFAULT 0,
+    message TEXT,
+    error TEXT,
+    -- JSON result of a finished job
+    result TEXT,
+    cancel_requested INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    started_at INTEGER,
+    finished_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_job_state ON job (state, id);
@@ -0,0 +1,5 @@
+-- v28: Visit referrers
+-- Visits captured live by the browser extension can carry the page they were
+-- opened from. Visits read from history files leave it empty.
+
+ALTER TABLE visit ADD COLUMN referrer TEXT;
@@ -0,0 +1,16 @@
+-- v29: User scripts
+-- Rhai scripts run at hook points: before imported pages are inserted, after
+-- an import and once a night, to rewrite, skip or tag pages.
+
+CREATE TABLE IF NOT EXISTS script (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    -- pre_insert, post_import or nightly
+    hook TEXT NOT NULL,
+    source TEXT NOT NULL,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_script_hook ON script (hook, enabled);
@@ -0,0 +1,27 @@
+-- v3: Knowledge graph nodes and edges
+-- Nodes are keyed by (node_type, key) so rebuilding the graph is idempotent
+
+CREATE TABLE IF NOT EXISTS node (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- url, domain, topic, entity or session
+    node_type TEXT NOT NULL,
+    -- url.id, domain name, topic name, entity name or session key
+    key TEXT NOT NULL,
+    label TEXT NOT NULL,
+    -- Number of visits (or mentions) backing this node
+    weight REAL NOT NULL DEFAULT 0,
+    UNIQUE (node_type, key)
+);
+
+CREATE TABLE IF NOT EXISTS edge (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source_id INTEGER NOT NULL REFERENCES node(id) ON DELETE CASCADE,
+    target_id INTEGER NOT NULL REFERENCES node(id) ON DELETE CASCADE,
+    -- visited, linked_from, same_topic, in_domain, has_topic, mentions or co_visited
+    edge_type TEXT NOT NULL,
+    weight REAL NOT NULL DEFAULT 1,
+    UNIQUE (source_id, target_id, edge_type)
+);
+
+CREATE INDEX IF NOT EXISTS idx_edge_target ON edge (target_id);
+CREATE INDEX IF NOT EXISTS idx_node_type ON node (node_type);
@@ -0,0 +1,17 @@
+-- v30: Saved searches
+-- Named search filters the app and automations can run again. A search can
+-- cover a rolling window of recent days instead of fixed dates.
+
+CREATE TABLE IF NOT EXISTS saved_search (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    query TEXT,
+    domain TEXT,
+    category TEXT,
+    tag TEXT,
+    keyword TEXT,
+    -- Only visits in the last N days; NULL searches all history
+    days INTEGER,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,18 @@
+-- v31: LAN sync peers
+-- Instances paired for sync over the local network. The fingerprint pins the
+-- peer's TLS certificate; the secret authorizes its requests and encrypts the
+-- bundles exchanged with it.
+
+CREATE TABLE IF NOT EXISTS sync_peer (
+    -- The peer's instance id, announced over mDNS
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- SHA-256 of the peer's certificate, lowercase hex
+    fingerprint TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    paired_at INTEGER NOT NULL,
+    -- Changes made on the peer up to this time have been pulled
+    pulled_until INTEGER,
+    last_synced_at INTEGER,
+    last_error TEXT
+);
@@ -0,0 +1,16 @@
+-- v32: Import sources
+-- Content fingerprints of the history files imported, so a copy of a
+-- History.db imported from another path is merged with the original instead
+-- of doubling its visits. Visits of a copy are stored under the original's
+-- path (canonical_path).
+
+CREATE TABLE IF NOT EXISTS import_source (
+    path TEXT PRIMARY KEY,
+    canonical_path TEXT NOT NULL,
+    -- JSON SourceFingerprint: sampled (visit id, hash) pairs and the visit count
+    fingerprint TEXT NOT NULL,
+    first_imported_at INTEGER NOT NULL,
+    last_imported_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_import_source_canonical ON import_source (canonical_path);
@@ -0,0 +1,36 @@
+-- v33: Report templates
+-- Named, parameterized report queries (read-only SQL or search filters) that
+-- run on demand or on a schedule, and the outputs of their runs.
+
+CREATE TABLE IF NOT EXISTS report_template (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    description TEXT,
+    -- JSON TemplateQuery: {"kind": "sql", "sql": ...} or {"kind": "filters", ...}
+    query TEXT NOT NULL,
+    -- JSON array of {name, label, default}
+    parameters TEXT NOT NULL DEFAULT '[]',
+    -- daily, weekly or NULL for on demand only
+    schedule TEXT,
+    max_rows INTEGER NOT NULL DEFAULT 1000,
+    last_run_at INTEGER,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS report_output (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    template_id INTEGER NOT NULL REFERENCES report_template(id) ON DELETE CASCADE,
+    -- manual or schedule
+    trigger TEXT NOT NULL,
+    -- JSON object of the parameter values used
+    parameters TEXT NOT NULL DEFAULT '{}',
+    started_at INTEGER NOT NULL,
+    elapsed_ms INTEGER NOT NULL DEFAULT 0,
+    row_count INTEGER NOT NULL DEFAULT 0,
+    -- JSON ConsoleResult; NULL when the run failed
+    result TEXT,
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_report_output_template ON report_output (template_id, started_at);
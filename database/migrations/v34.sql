@@ -0,0 +1,16 @@
+-- v34: Import progress
+-- Imports commit in chunks; after each chunk the rows committed so far are
+-- recorded per file, so an interrupted import of the same file resumes there.
+
+CREATE TABLE IF NOT EXISTS import_progress (
+    import_run_id INTEGER NOT NULL REFERENCES import_run(id) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    urls_total INTEGER NOT NULL,
+    visits_total INTEGER NOT NULL,
+    urls_done INTEGER NOT NULL DEFAULT 0,
+    visits_done INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (import_run_id, path)
+);
+
+CREATE INDEX IF NOT EXISTS idx_import_progress_path ON import_progress (path);
@@ -0,0 +1,57 @@
+-- v35: Campaign parameters
+-- utm_source, utm_medium, utm_campaign, utm_term and utm_content of the
+-- links that led to a page, read at import before privacy rules or duplicate
+-- merging strip them from the URL. Missing key parameters are stored as ''.
+
+CREATE TABLE IF NOT EXISTS campaign (
+    url_id TEXT NOT NULL REFERENCES url(id) ON DELETE CASCADE,
+    source TEXT NOT NULL DEFAULT '',
+    medium TEXT NOT NULL DEFAULT '',
+    name TEXT NOT NULL DEFAULT '',
+    term TEXT,
+    content TEXT,
+    PRIMARY KEY (url_id, source, medium, name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_campaign_source ON campaign (source);
+CREATE INDEX IF NOT EXISTS idx_campaign_name ON campaign (name);
+
+-- Backfill from the URLs stored so far, reading each parameter up to the
+-- next '&' of the query string (without the fragment)
+INSERT OR IGNORE INTO campaign (url_id, source, medium, name, term, content)
+WITH query AS (
+    SELECT id,
+           '&' || CASE WHEN instr(rest, '#') > 0 THEN substr(rest, 1, instr(rest, '#') - 1) ELSE rest END || '&' AS q
+    FROM (SELECT id, substr(url, instr(url, '?') + 1) AS rest FROM url WHERE instr(url, '?') > 0 AND instr(url, 'utm_') > 0)
+),
+params AS (
+    SELECT id,
+           substr(q, instr(q, '&utm_source=') + 12) AS source_rest,
+           instr(q, '&utm_source=') AS has_source,
+           substr(q, instr(q, '&utm_medium=') + 12) AS medium_rest,
+           instr(q, '&utm_medium=') AS has_medium,
+           substr(q, instr(q, '&utm_campaign=') + 14) AS name_rest,
+           instr(q, '&utm_campaign=') AS has_name,
+           substr(q, instr(q, '&utm_term=') + 10) AS term_rest,
+           instr(q, '&utm_term=') AS has_term,
+           substr(q, instr(q, '&utm_content=') + 13) AS content_rest,
+           instr(q, '&utm_content=') AS has_content
+    FROM query
+),
+campaign_values AS (
+    SELECT id,
+           CASE WHEN has_source > 0 THEN substr(source_rest, 1, instr(source_rest, '&') - 1) ELSE '' END AS source,
+           CASE WHEN has_medium > 0 THEN substr(medium_rest, 1, instr(medium_rest, '&') - 1) ELSE '' END AS medium,
+           CASE WHEN has_name > 0 THEN substr(name_rest, 1, instr(name_rest, '&') - 1) ELSE '' END AS name,
+           CASE WHEN has_term > 0 THEN substr(term_rest, 1, instr(term_rest, '&') - 1) END AS term,
+           CASE WHEN has_content > 0 THEN substr(content_rest, 1, instr(content_rest, '&') - 1) END AS content
+    FROM params
+)
+SELECT id,
+       lower(trim(replace(replace(source, '+', ' '), '%20', ' '))),
+       lower(trim(replace(replace(medium, '+', ' '), '%20', ' '))),
+       trim(replace(replace(name, '+', ' '), '%20', ' ')),
+       NULLIF(trim(replace(replace(term, '+', ' '), '%20', ' ')), ''),
+       NULLIF(trim(replace(replace(content, '+', ' '), '%20', ' ')), '')
+FROM campaign_values
+WHERE source != '' OR medium != '' OR name != '';
@@ -0,0 +1,9 @@
+-- v36: Visit origins
+-- Where each visit came from (search, social, newsletter, direct, internal or
+-- external link), classified from its referrer and campaign parameters. NULL
+-- until refresh_visit_origins classifies it, after imports and before reads.
+
+ALTER TABLE visit ADD COLUMN origin TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_visit_origin ON visit (origin, visited_at);
+CREATE INDEX IF NOT EXISTS idx_visit_origin_pending ON visit (id) WHERE origin IS NULL;
@@ -0,0 +1,68 @@
+-- v37: Tracker blocklists
+-- Domains of ad networks and trackers, from the built-in list and lists the
+-- user loads (EasyList, Disconnect, hosts files). domain_stats.tracker holds
+-- the kind a domain matched, checked by refresh_tracker_flags after imports,
+-- list changes and before reads; tracker_checked = 0 marks domains to check.
+
+CREATE TABLE IF NOT EXISTS blocklist (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    source TEXT,
+    domain_count INTEGER NOT NULL DEFAULT 0,
+    loaded_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS blocklist_domain (
+    blocklist_id INTEGER NOT NULL REFERENCES blocklist (id) ON DELETE CASCADE,
+    domain TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    PRIMARY KEY (blocklist_id, domain)
+);
+
+CREATE INDEX IF NOT EXISTS idx_blocklist_domain_domain ON blocklist_domain (domain);
+
+ALTER TABLE domain_stats ADD COLUMN tracker TEXT;
+ALTER TABLE domain_stats ADD COLUMN tracker_checked INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_domain_stats_tracker_pending ON domain_stats (domain) WHERE tracker_checked = 0;
+
+-- Common ad networks, analytics and click-tracking redirectors
+INSERT OR IGNORE INTO blocklist (name, source, loaded_at) VALUES ('Built-in', NULL, strftime('%s', 'now'));
+
+INSERT OR IGNORE INTO blocklist_domain (blocklist_id, domain, kind)
+SELECT b.id, d.domain, d.kind
+FROM blocklist b
+JOIN (
+    SELECT 'doubleclick.net' AS domain, 'ads' AS kind
+    UNION ALL SELECT 'googlesyndication.com', 'ads'
+    UNION ALL SELECT 'googleadservices.com', 'ads'
+    UNION ALL SELECT 'adservice.google.com', 'ads'
+    UNION ALL SELECT 'adnxs.com', 'ads'
+    UNION ALL SELECT 'criteo.com', 'ads'
+    UNION ALL SELECT 'taboola.com', 'ads'
+    UNION ALL SELECT 'outbrain.com', 'ads'
+    UNION ALL SELECT 'rubiconproject.com', 'ads'
+    UNION ALL SELECT 'pubmatic.com', 'ads'
+    UNION ALL SELECT 'amazon-adsystem.com', 'ads'
+    UNION ALL SELECT 'ads.linkedin.com', 'ads'
+    UNION ALL SELECT 'awin1.com', 'ads'
+    UNION ALL SELECT 'linksynergy.com', 'ads'
+    UNION ALL SELECT 'google-analytics.com', 'tracker'
+    UNION ALL SELECT 'googletagmanager.com', 'tracker'
+    UNION ALL SELECT 'scorecardresearch.com', 'tracker'
+    UNION ALL SELECT 'hotjar.com', 'tracker'
+    UNION ALL SELECT 'mixpanel.com', 'tracker'
+    UNION ALL SELECT 'segment.io', 'tracker'
+    UNION ALL SELECT 'list-manage.com', 'tracker'
+    UNION ALL SELECT 'ct.sendgrid.net', 'tracker'
+    UNION ALL SELECT 'mandrillapp.com', 'tracker'
+    UNION ALL SELECT 'hubspotlinks.com', 'tracker'
+    UNION ALL SELECT 'hs-analytics.net', 'tracker'
+    UNION ALL SELECT 'exct.net', 'tracker'
+    UNION ALL SELECT 'mailgun.org', 'tracker'
+    UNION ALL SELECT 'app.link', 'tracker'
+) d
+WHERE b.name = 'Built-in';
+
+UPDATE blocklist SET domain_count = (SELECT COUNT(*) FROM blocklist_domain WHERE blocklist_id = blocklist.id)
+WHERE name = 'Built-in';
@@ -0,0 +1,8 @@
+-- v38: Access walls
+-- Pages that only showed a paywall or a login form when their content was
+-- fetched ('paywall' or 'login'); NULL when readable or not fetched yet.
+-- Enrichment skips them and search can filter on them.
+
+ALTER TABLE metadata ADD COLUMN access_wall TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_metadata_access_wall ON metadata (access_wall) WHERE access_wall IS NOT NULL;
@@ -0,0 +1,18 @@
+-- v39: Content fingerprints
+-- A SimHash of each fetched page's main text: the baseline the user read and
+-- the latest fetch. changed_at is set once the two differ materially, and
+-- cleared when the user visits the page again or acknowledges the change.
+
+CREATE TABLE IF NOT EXISTS content_fingerprint (
+    url_id TEXT PRIMARY KEY REFERENCES url(id) ON DELETE CASCADE,
+    baseline_hash INTEGER NOT NULL,
+    baseline_words INTEGER NOT NULL,
+    baseline_at INTEGER NOT NULL,
+    latest_hash INTEGER NOT NULL,
+    latest_words INTEGER NOT NULL,
+    checked_at INTEGER NOT NULL,
+    changed_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_content_fingerprint_checked ON content_fingerprint (checked_at);
+CREATE INDEX IF NOT EXISTS idx_content_fingerprint_changed ON content_fingerprint (changed_at) WHERE changed_at IS NOT NULL;
@@ -0,0 +1,43 @@
+-- v4: Temporal graph snapshots
+-- Graph tables only hold derived data, so they are recreated with a snapshot
+-- column; snapshot 0 is the main (all-time) graph and is rebuilt on demand.
+
+DROP TABLE IF EXISTS edge;
+DROP TABLE IF EXISTS node;
+
+CREATE TABLE IF NOT EXISTS graph_snapshot (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    label TEXT NOT NULL UNIQUE,
+    start_at INTEGER NOT NULL,
+    end_at INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE node (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- 0 for the main graph, otherwise graph_snapshot.id
+    snapshot_id INTEGER NOT NULL DEFAULT 0,
+    -- url, domain, topic, entity or session
+    node_type TEXT NOT NULL,
+    -- url.id, domain name, topic name, entity name or session key
+    key TEXT NOT NULL,
+    label TEXT NOT NULL,
+    -- Number of visits (or mentions) backing this node
+    weight REAL NOT NULL DEFAULT 0,
+    UNIQUE (snapshot_id, node_type, key)
+);
+
+CREATE TABLE edge (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    snapshot_id INTEGER NOT NULL DEFAULT 0,
+    source_id INTEGER NOT NULL REFERENCES node(id) ON DELETE CASCADE,
+    target_id INTEGER NOT NULL REFERENCES node(id) ON DELETE CASCADE,
+    -- visited, linked_from, same_topic, in_domain, has_topic, mentions or co_visited
+    edge_type TEXT NOT NULL,
+    weight REAL NOT NULL DEFAULT 1,
+    UNIQUE (source_id, target_id, edge_type)
+);
+
+CREATE INDEX IF NOT EXISTS idx_edge_target ON edge (target_id);
+CREATE INDEX IF NOT EXISTS idx_edge_snapshot ON edge (snapshot_id);
+CREATE INDEX IF NOT EXISTS idx_node_type ON node (snapshot_id, node_type);
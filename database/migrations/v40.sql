@@ -0,0 +1,19 @@
+-- v40: Domain provenance
+-- Registrar and registration date of each domain's registrable domain, from
+-- RDAP, and the address and country it is hosted in, from DNS and the address
+-- registry. Rows are filled by the optional domain enrichment job and
+-- refreshed once they are older than its interval; error keeps why the last
+-- lookup failed.
+
+CREATE TABLE IF NOT EXISTS domain_provenance (
+    domain TEXT PRIMARY KEY,
+    registrable_domain TEXT NOT NULL,
+    registrar TEXT,
+    registered_at INTEGER,
+    ip_address TEXT,
+    country TEXT,
+    checked_at INTEGER NOT NULL,
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_domain_provenance_checked ON domain_provenance (checked_at);
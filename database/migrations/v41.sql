@@ -0,0 +1,153 @@
+-- v41: Domain organizations
+-- The organization owning a domain (youtube.com -> Google), from the bundled
+-- mappings below and ones the user adds; subdomains inherit the mapping of
+-- their parent. domain_stats.registrable_domain and .organization are filled
+-- by refresh_organizations, which picks up domains with organization_checked
+-- = 0, so stats and the graph can roll domains up to either level.
+
+CREATE TABLE IF NOT EXISTS organization_domain (
+    domain TEXT PRIMARY KEY,
+    organization TEXT NOT NULL,
+    user_defined INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS idx_organization_domain_organization ON organization_domain (organization);
+
+ALTER TABLE domain_stats ADD COLUMN registrable_domain TEXT;
+ALTER TABLE domain_stats ADD COLUMN organization TEXT;
+ALTER TABLE domain_stats ADD COLUMN organization_checked INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_domain_stats_organization_pending ON domain_stats (domain) WHERE organization_checked = 0;
+CREATE INDEX IF NOT EXISTS idx_domain_stats_organization ON domain_stats (organization) WHERE organization IS NOT NULL;
+
+INSERT OR IGNORE INTO organization_domain (domain, organization) VALUES
+    ('google.com', 'Google'),
+    ('youtube.com', 'Google'),
+    ('gmail.com', 'Google'),
+    ('googleapis.com', 'Google'),
+    ('gstatic.com', 'Google'),
+    ('googleusercontent.com', 'Google'),
+    ('blogger.com', 'Google'),
+    ('android.com', 'Google'),
+    ('chromium.org', 'Google'),
+    ('withgoogle.com', 'Google'),
+    ('goo.gl', 'Google'),
+    ('youtu.be', 'Google'),
+    ('googlevideo.com', 'Google'),
+    ('doubleclick.net', 'Google'),
+    ('google-analytics.com', 'Google'),
+    ('googletagmanager.com', 'Google'),
+    ('googlesyndication.com', 'Google'),
+    ('googleadservices.com', 'Google'),
+    ('facebook.com', 'Meta'),
+    ('fb.com', 'Meta'),
+    ('fb.me', 'Meta'),
+    ('instagram.com', 'Meta'),
+    ('whatsapp.com', 'Meta'),
+    ('messenger.com', 'Meta'),
+    ('threads.net', 'Meta'),
+    ('meta.com', 'Meta'),
+    ('oculus.com', 'Meta'),
+    ('microsoft.com', 'Microsoft'),
+    ('live.com', 'Microsoft'),
+    ('outlook.com', 'Microsoft'),
+    ('office.com', 'Microsoft'),
+    ('office365.com', 'Microsoft'),
+    ('bing.com', 'Microsoft'),
+    ('msn.com', 'Microsoft'),
+    ('linkedin.com', 'Microsoft'),
+    ('lnkd.in', 'Microsoft'),
+    ('github.com', 'Microsoft'),
+    ('githubusercontent.com', 'Microsoft'),
+    ('xbox.com', 'Microsoft'),
+    ('skype.com', 'Microsoft'),
+    ('azure.com', 'Microsoft'),
+    ('visualstudio.com', 'Microsoft'),
+    ('windows.com', 'Microsoft'),
+    ('aka.ms', 'Microsoft'),
+    ('npmjs.com', 'Microsoft'),
+    ('amazon.com', 'Amazon'),
+    ('amazon.co.uk', 'Amazon'),
+    ('amazon.de', 'Amazon'),
+    ('amazon.fr', 'Amazon'),
+    ('amazon.es', 'Amazon'),
+    ('amazon.it', 'Amazon'),
+    ('amazon.ca', 'Amazon'),
+    ('amazon.co.jp', 'Amazon'),
+    ('amazon.com.br', 'Amazon'),
+    ('amzn.to', 'Amazon'),
+    ('amazonaws.com', 'Amazon'),
+    ('twitch.tv', 'Amazon'),
+    ('imdb.com', 'Amazon'),
+    ('goodreads.com', 'Amazon'),
+    ('audible.com', 'Amazon'),
+    ('zappos.com', 'Amazon'),
+    ('wholefoodsmarket.com', 'Amazon'),
+    ('primevideo.com', 'Amazon'),
+    ('apple.com', 'Apple'),
+    ('icloud.com', 'Apple'),
+    ('apple.co', 'Apple'),
+    ('itunes.com', 'Apple'),
+    ('twitter.com', 'X Corp'),
+    ('x.com', 'X Corp'),
+    ('t.co', 'X Corp'),
+    ('twimg.com', 'X Corp'),
+    ('wikipedia.org', 'Wikimedia Foundation'),
+    ('wikimedia.org', 'Wikimedia Foundation'),
+    ('wiktionary.org', 'Wikimedia Foundation'),
+    ('wikidata.org', 'Wikimedia Foundation'),
+    ('wikibooks.org', 'Wikimedia Foundation'),
+    ('wikiquote.org', 'Wikimedia Foundation'),
+    ('wikivoyage.org', 'Wikimedia Foundation'),
+    ('wordpress.com', 'Automattic'),
+    ('wp.me', 'Automattic'),
+    ('tumblr.com', 'Automattic'),
+    ('gravatar.com', 'Automattic'),
+    ('stackoverflow.com', 'Stack Exchange'),
+    ('stackexchange.com', 'Stack Exchange'),
+    ('superuser.com', 'Stack Exchange'),
+    ('serverfault.com', 'Stack Exchange'),
+    ('askubuntu.com', 'Stack Exchange'),
+    ('mathoverflow.net', 'Stack Exchange'),
+    ('reddit.com', 'Reddit'),
+    ('redd.it', 'Reddit'),
+    ('redditmedia.com', 'Reddit'),
+    ('wired.com', 'Condé Nast'),
+    ('newyorker.com', 'Condé Nast'),
+    ('arstechnica.com', 'Condé Nast'),
+    ('vogue.com', 'Condé Nast'),
+    ('gq.com', 'Condé Nast'),
+    ('vanityfair.com', 'Condé Nast'),
+    ('theverge.com', 'Vox Media'),
+    ('vox.com', 'Vox Media'),
+    ('polygon.com', 'Vox Media'),
+    ('eater.com', 'Vox Media'),
+    ('yahoo.com', 'Yahoo'),
+    ('aol.com', 'Yahoo'),
+    ('engadget.com', 'Yahoo'),
+    ('techcrunch.com', 'Yahoo'),
+    ('atlassian.com', 'Atlassian'),
+    ('atlassian.net', 'Atlassian'),
+    ('bitbucket.org', 'Atlassian'),
+    ('trello.com', 'Atlassian'),
+    ('salesforce.com', 'Salesforce'),
+    ('slack.com', 'Salesforce'),
+    ('tableau.com', 'Salesforce'),
+    ('heroku.com', 'Salesforce'),
+    ('adobe.com', 'Adobe'),
+    ('behance.net', 'Adobe'),
+    ('typekit.net', 'Adobe'),
+    ('tiktok.com', 'ByteDance'),
+    ('bytedance.com', 'ByteDance'),
+    ('capcut.com', 'ByteDance'),
+    ('alibaba.com', 'Alibaba'),
+    ('aliexpress.com', 'Alibaba'),
+    ('taobao.com', 'Alibaba'),
+    ('nytimes.com', 'The New York Times'),
+    ('nyt.com', 'The New York Times'),
+    ('mozilla.org', 'Mozilla'),
+    ('firefox.com', 'Mozilla'),
+    ('mozilla.net', 'Mozilla'),
+    ('spotify.com', 'Spotify'),
+    ('spoti.fi', 'Spotify'),
+    ('netflix.com', 'Netflix');
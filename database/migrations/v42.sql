@@ -0,0 +1,21 @@
+-- v42: Wikidata links
+-- The Wikidata item (QID) an entity keyword or a domain stands for, so graph
+-- nodes can be joined with external knowledge bases. qid is NULL when no item
+-- matched, so the lookup isn't repeated before it is due again; user_defined
+-- rows were set by hand and are never looked up. Graph builds copy the QIDs
+-- onto node.wikidata_id.
+
+CREATE TABLE IF NOT EXISTS wikidata_link (
+    node_type TEXT NOT NULL,
+    key TEXT NOT NULL,
+    qid TEXT,
+    label TEXT,
+    description TEXT,
+    user_defined INTEGER NOT NULL DEFAULT 0,
+    linked_at INTEGER NOT NULL,
+    PRIMARY KEY (node_type, key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_wikidata_link_qid ON wikidata_link (qid) WHERE qid IS NOT NULL;
+
+ALTER TABLE node ADD COLUMN wikidata_id TEXT;
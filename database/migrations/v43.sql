@@ -0,0 +1,11 @@
+-- v43: Sentiment
+-- Tone of a fetched page's main text, from -1 (negative) to 1 (positive),
+-- scored with a word list when the optional tone scoring is on.
+-- sentiment_words counts the words that carried a valence; sentiment_at is
+-- set once a page was looked at, even when it had too little text to score.
+
+ALTER TABLE metadata ADD COLUMN sentiment REAL;
+ALTER TABLE metadata ADD COLUMN sentiment_words INTEGER;
+ALTER TABLE metadata ADD COLUMN sentiment_at INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_metadata_sentiment ON metadata (sentiment) WHERE sentiment IS NOT NULL;
@@ -0,0 +1,17 @@
+-- v44: Flashcards
+-- Question and answer pairs generated by the enrichment provider from the
+-- text of technical articles, for export to spaced-repetition decks.
+-- metadata.flashcards_at is set once a page was sent to the provider, even
+-- when no cards came back, so it isn't sent again.
+
+CREATE TABLE IF NOT EXISTS flashcard (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url_id TEXT NOT NULL REFERENCES url(id) ON DELETE CASCADE,
+    question TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_flashcard_url ON flashcard (url_id);
+
+ALTER TABLE metadata ADD COLUMN flashcards_at INTEGER;
@@ -0,0 +1,7 @@
+-- v45: Rows added by journaled operations
+-- Primary keys of the rows each image's condition matched once the operation
+-- finished but not before, as a JSON array of key arrays. Undo deletes only
+-- these, leaving rows added by later changes alone. NULL for operations
+-- journaled before this column existed.
+
+ALTER TABLE operation_image ADD COLUMN added TEXT;
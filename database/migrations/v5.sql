@@ -0,0 +1,5 @@
+-- v5: Precomputed graph layout
+-- Coordinates written by the optional backend layout pass; NULL until computed.
+
+ALTER TABLE node ADD COLUMN x REAL;
+ALTER TABLE node ADD COLUMN y REAL;
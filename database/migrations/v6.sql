@@ -0,0 +1,13 @@
+-- v6: Embeddings for semantic search
+-- One vector per URL, stored as little-endian f32 values normalised to unit length.
+
+CREATE TABLE IF NOT EXISTS embedding (
+    url_id TEXT PRIMARY KEY REFERENCES url(id) ON DELETE CASCADE,
+    -- Model that produced the vector; vectors of different models are not comparable
+    model TEXT NOT NULL,
+    dimensions INTEGER NOT NULL,
+    vector BLOB NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_embedding_model ON embedding (model);
@@ -0,0 +1,6 @@
+-- v7: URL categories
+-- Coarse category (news, development, docs, ...) used as a search and analytics facet.
+
+ALTER TABLE url ADD COLUMN category TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_url_category ON url (category);
@@ -0,0 +1,17 @@
+-- v8: Enrichment job queue
+-- One job per URL; survives restarts so long enrichment runs can resume.
+
+CREATE TABLE IF NOT EXISTS enrichment_job (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url_id TEXT NOT NULL UNIQUE REFERENCES url(id) ON DELETE CASCADE,
+    -- pending, running, done or failed
+    status TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- Earliest time the job may run (pushed back after failures)
+    next_attempt_at INTEGER NOT NULL,
+    last_error TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_enrichment_job_due ON enrichment_job (status, next_attempt_at);
@@ -0,0 +1,18 @@
+-- v9: Enrichment usage tracking
+-- Requests, tokens and cost per provider and model for every enrichment run.
+
+CREATE TABLE IF NOT EXISTS enrichment_usage (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    run_id TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    requests INTEGER NOT NULL DEFAULT 0,
+    failed_requests INTEGER NOT NULL DEFAULT 0,
+    prompt_tokens INTEGER NOT NULL DEFAULT 0,
+    completion_tokens INTEGER NOT NULL DEFAULT 0,
+    -- Estimated from the configured prices at the time of the request
+    cost REAL NOT NULL DEFAULT 0,
+    started_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    UNIQUE (run_id, provider, model)
+);
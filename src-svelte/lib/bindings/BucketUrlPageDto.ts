@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { UrlSummaryDto } from "./UrlSummaryDto";
+
+export type BucketUrlPageDto = { urls: Array<UrlSummaryDto>, total_count: number, page: number, page_size: number, };
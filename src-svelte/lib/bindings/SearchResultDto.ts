@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type SearchResultDto = { id: string, url: string, title: string | null, domain: string, first_seen: string, last_seen: string, visit_count: number, summary: string | null, keywords: string | null, is_enriched: boolean, access_wall: string | null, tags: Array<string>, last_visit: string | null, };
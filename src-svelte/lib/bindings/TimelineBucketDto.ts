@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { UrlSummaryDto } from "./UrlSummaryDto";
+
+export type TimelineBucketDto = { "type": "hour", hour: number, count: number, timestamp: string, urls: Array<UrlSummaryDto>, } | { "type": "day", date: string, count: number, timestamp: string, urls: Array<UrlSummaryDto>, } | { "type": "domain", domain: string, count: number, urls: Array<UrlSummaryDto>, };
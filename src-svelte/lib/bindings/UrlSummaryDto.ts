@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type UrlSummaryDto = { id: string, url: string, title: string | null, domain: string, visit_count: number, last_visit: string | null, };
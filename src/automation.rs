@@ -0,0 +1,179 @@
+// Automation
+// `historykg://run/...` links that run an action without opening a view, so
+// Apple Shortcuts can run a saved search, import the history or write a
+// weekly report on a schedule
+//
+// Actions answer through the x-callback-url convention: `x-success` is opened
+// with the result as query parameters and `x-error` with `errorMessage`. Since
+// any web page can open a link, automation is off until the user enables it,
+// callbacks may only go back to Shortcuts, and reports are only written to
+// the reports folder.
+
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use url::Url as UrlParser;
+
+use crate::db::settings::get_setting;
+use crate::db::DatabaseConnection;
+use crate::deeplink::SCHEME;
+use crate::error::{AppError, ErrorKind};
+
+/// Settings key of the automation switch
+pub const AUTOMATION_SETTING: &str = "automation";
+
+/// Event telling the frontend an automation ran, or why it didn't
+pub const AUTOMATION_EVENT: &str = "automation";
+
+/// Host of automation links, `historykg://run/<action>`
+const RUN_HOST: &str = "run";
+
+/// Only callback scheme allowed, so results can't be sent to a web page
+const CALLBACK_SCHEME: &str = "shortcuts";
+
+/// Folder in Documents reports are written to
+pub const REPORTS_FOLDER: &str = "History Reports";
+
+/// Results a saved search returns unless the link asks for fewer
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+/// Most results a saved search returns to a callback
+const MAX_SEARCH_LIMIT: usize = 100;
+
+/// Whether links may run actions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutomationSettings {
+    /// Whether `historykg://run/` links are accepted
+    pub enabled: bool,
+}
+
+/// Gets the automation settings
+pub fn get_automation_settings(conn: &DatabaseConnection) -> crate::db::Result<AutomationSettings> {
+    conn.with_connection(|c| Ok(get_setting(c, AUTOMATION_SETTING)?.unwrap_or_default()))
+}
+
+/// An action a link runs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AutomationAction {
+    /// `historykg://run/search?name=Reading&limit=10`
+    Search {
+        name: String,
+        limit: usize,
+    },
+    /// `historykg://run/import`, or `?path=<file>&device=<name>` for another history file
+    Import {
+        path: Option<String>,
+        device: Option<String>,
+    },
+    /// `historykg://run/report?period=week&format=html`
+    Report {
+        period: String,
+        format: Option<String>,
+    },
+}
+
+/// A parsed automation link
+#[derive(Debug, Clone)]
+pub struct AutomationRequest {
+    /// What to run
+    pub action: AutomationAction,
+    /// Opened with the result when the action succeeds
+    pub success: Option<UrlParser>,
+    /// Opened with the error message when it fails
+    pub error: Option<UrlParser>,
+}
+
+/// Returns true for `historykg://run/...` links
+pub fn is_automation_link(link: &str) -> bool {
+    UrlParser::parse(link)
+        .is_ok_and(|parsed| parsed.scheme() == SCHEME && parsed.host_str() == Some(RUN_HOST))
+}
+
+impl AutomationRequest {
+    /// Parses a `historykg://run/` link
+    pub fn parse(link: &str) -> Result<Self, AppError> {
+        let invalid = |reason: &str| AppError::invalid_input(format!("Invalid automation link '{}': {}", link, reason));
+
+        let parsed = UrlParser::parse(link).map_err(|e| invalid(&e.to_string()))?;
+        if !is_automation_link(link) {
+            return Err(invalid("not a historykg://run/ link"));
+        }
+        let action = parsed.path_segments().into_iter().flatten()
+            .find(|segment| !segment.is_empty())
+            .unwrap_or_default()
+            .to_string();
+        let param = |name: &str| parsed.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .filter(|value| !value.is_empty());
+
+        let callback = |name: &str| -> Result<Option<UrlParser>, AppError> {
+            match param(name) {
+                Some(url) => {
+                    let url = UrlParser::parse(&url).map_err(|_| invalid(&format!("{} is not a URL", name)))?;
+                    if url.scheme() != CALLBACK_SCHEME {
+                        return Err(invalid(&format!("{} must be a {}:// URL", name, CALLBACK_SCHEME)));
+                    }
+                    Ok(Some(url))
+                },
+                None => Ok(None),
+            }
+        };
+
+        let action = match action.as_str() {
+            "search" => AutomationAction::Search {
+                name: param("name").ok_or_else(|| invalid("a search needs ?name="))?,
+                limit: match param("limit") {
+                    Some(limit) => limit.parse::<usize>()
+                        .map_err(|_| invalid("limit must be a number"))?
+                        .clamp(1, MAX_SEARCH_LIMIT),
+                    None => DEFAULT_SEARCH_LIMIT,
+                },
+            },
+            "import" => AutomationAction::Import {
+                path: param("path"),
+                device: param("device"),
+            },
+            "report" => AutomationAction::Report {
+                period: param("period").unwrap_or_else(|| "week".to_string()),
+                format: param("format"),
+            },
+            other => return Err(invalid(&format!("unknown action '{}'", other))),
+        };
+
+        Ok(AutomationRequest {
+            action,
+            success: callback("x-success")?,
+            error: callback("x-error")?,
+        })
+    }
+}
+
+/// Adds the result to a callback URL
+pub fn callback_url(callback: &UrlParser, values: &[(String, String)]) -> String {
+    let mut url = callback.clone();
+    if !values.is_empty() {
+        let mut pairs = url.query_pairs_mut();
+        for (key, value) in values {
+            pairs.append_pair(key, value);
+        }
+    }
+    url.to_string()
+}
+
+/// Path of a new report in the reports folder, which is created if missing.
+/// `period` must already be a valid report period.
+pub fn report_path(period: &str, html: bool) -> Result<PathBuf, AppError> {
+    let dir = tauri::api::path::document_dir()
+        .ok_or_else(|| AppError::new(ErrorKind::Config, "No Documents folder to write reports to"))?
+        .join(REPORTS_FOLDER);
+    fs::create_dir_all(&dir)
+        .map_err(|e| AppError::wrap("Failed to create the reports folder", e))?;
+
+    let extension = if html { "html" } else { "md" };
+    Ok(dir.join(format!("history-{}-{}.{}", period, Local::now().format("%Y-%m-%d"), extension)))
+}
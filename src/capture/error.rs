@@ -0,0 +1,68 @@
+// Capture Error Handling
+// Defines error types for the browser extension capture endpoint
+
+use std::fmt;
+use std::error::Error;
+
+use crate::db::DatabaseError;
+use crate::privacy::PrivacyError;
+use crate::scripting::ScriptError;
+
+/// Represents errors that can occur while capturing visits from the extension
+#[derive(Debug)]
+pub enum CaptureError {
+    /// Reading settings or storing visits failed
+    Database(DatabaseError),
+    /// The privacy rules could not be applied
+    Privacy(PrivacyError),
+    /// The pre-insert scripts could not be loaded
+    Script(ScriptError),
+    /// The listener could not be started
+    Server(String),
+    /// The capture settings are invalid
+    Invalid(String),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CaptureError::Database(err) => write!(f, "Database error: {}", err),
+            CaptureError::Privacy(err) => write!(f, "Privacy error: {}", err),
+            CaptureError::Script(err) => write!(f, "Script error: {}", err),
+            CaptureError::Server(msg) => write!(f, "Capture server error: {}", msg),
+            CaptureError::Invalid(msg) => write!(f, "Invalid capture settings: {}", msg),
+        }
+    }
+}
+
+impl Error for CaptureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CaptureError::Database(err) => Some(err),
+            CaptureError::Privacy(err) => Some(err),
+            CaptureError::Script(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DatabaseError> for CaptureError {
+    fn from(err: DatabaseError) -> Self {
+        CaptureError::Database(err)
+    }
+}
+
+impl From<PrivacyError> for CaptureError {
+    fn from(err: PrivacyError) -> Self {
+        CaptureError::Privacy(err)
+    }
+}
+
+impl From<ScriptError> for CaptureError {
+    fn from(err: ScriptError) -> Self {
+        CaptureError::Script(err)
+    }
+}
+
+/// Result type for capture operations
+pub type Result<T> = std::result::Result<T, CaptureError>;
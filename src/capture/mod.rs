@@ -0,0 +1,204 @@
+// Capture Module
+// Live capture of visits sent by a companion browser extension, for browsers
+// whose history database can't be read
+
+// Module organization:
+// - server.rs: Localhost HTTP listener the extension posts visits to
+// - error.rs: Error handling
+
+pub mod server;
+pub mod error;
+
+pub use error::{CaptureError, Result};
+pub use server::CaptureServer;
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url as UrlParser;
+use uuid::Uuid;
+
+use crate::db::settings::{get_setting, set_setting};
+use crate::db::DatabaseConnection;
+use crate::extractor::{Campaign, RawHistoryData, Url, Visit};
+use crate::privacy;
+use crate::scripting::{Hook, HookScripts};
+
+/// Settings key of the capture endpoint
+pub const CAPTURE_SETTING: &str = "capture";
+
+/// Port the endpoint listens on unless configured
+pub const DEFAULT_CAPTURE_PORT: u16 = 47615;
+
+/// Source file recorded on captured visits
+pub const CAPTURE_SOURCE: &str = "browser-extension";
+
+/// Shortest token accepted, so it can't be guessed by other local software
+const MIN_TOKEN_LEN: usize = 16;
+
+/// Capture endpoint configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureSettings {
+    /// Whether the endpoint listens
+    pub enabled: bool,
+    /// Port on 127.0.0.1
+    pub port: u16,
+    /// Shared token the extension sends as `Authorization: Bearer <token>`
+    pub token: String,
+    /// Device the captured visits are attributed to
+    pub device_name: Option<String>,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        CaptureSettings {
+            enabled: false,
+            port: DEFAULT_CAPTURE_PORT,
+            token: new_token(),
+            device_name: None,
+        }
+    }
+}
+
+impl CaptureSettings {
+    /// Checks the settings before they are stored
+    pub fn validate(&self) -> Result<()> {
+        if self.enabled && self.token.trim().len() < MIN_TOKEN_LEN {
+            return Err(CaptureError::Invalid(
+                format!("Capture token must be at least {} characters", MIN_TOKEN_LEN)
+            ));
+        }
+        if self.port == 0 {
+            return Err(CaptureError::Invalid("Capture port cannot be 0".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Generates a random token for the extension
+pub fn new_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// A visit as the extension reports it
+#[derive(Debug, Clone, Deserialize)]
+pub struct CapturedVisit {
+    /// Visited URL
+    pub url: String,
+    /// Page title
+    pub title: Option<String>,
+    /// When the visit happened; now if left out
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Seconds spent on the page
+    pub duration_sec: Option<f64>,
+    /// URL of the page the visit was opened from
+    pub referrer: Option<String>,
+}
+
+/// Outcome of storing captured visits
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CaptureSummary {
+    /// Visits received
+    pub received: usize,
+    /// URLs not seen before
+    pub urls_inserted: usize,
+    /// Visits stored
+    pub visits_inserted: usize,
+    /// Visits dropped by the privacy rules
+    pub excluded: usize,
+    /// Visits that could not be used, e.g. with an invalid URL
+    pub rejected: Vec<String>,
+}
+
+/// Gets the capture settings
+pub fn get_capture_settings(conn: &DatabaseConnection) -> Result<CaptureSettings> {
+    Ok(conn.with_connection(|c| get_setting(c, CAPTURE_SETTING))?.unwrap_or_default())
+}
+
+/// Validates and stores the capture settings
+pub fn set_capture_settings(conn: &DatabaseConnection, settings: &CaptureSettings) -> Result<()> {
+    settings.validate()?;
+    Ok(conn.with_connection(|c| set_setting(c, CAPTURE_SETTING, settings))?)
+}
+
+/// Turns captured visits into extracted history, one URL per distinct page
+fn to_history_data(visits: &[CapturedVisit], device_name: Option<String>, summary: &mut CaptureSummary) -> RawHistoryData {
+    let mut history_data = RawHistoryData::new(PathBuf::from(CAPTURE_SOURCE), device_name.clone());
+
+    for captured in visits {
+        let domain = match UrlParser::parse(&captured.url).ok().and_then(|url| url.host_str().map(str::to_string)) {
+            Some(domain) => domain,
+            None => {
+                summary.rejected.push(format!("Invalid URL: {}", captured.url));
+                continue;
+            },
+        };
+        let visited_at = captured.timestamp.unwrap_or_else(Utc::now);
+
+        let url_id = match history_data.urls.iter_mut().find(|url| url.url == captured.url) {
+            Some(url) => {
+                url.first_seen = url.first_seen.min(visited_at);
+                url.last_seen = url.last_seen.max(visited_at);
+                if url.title.is_none() {
+                    url.title = captured.title.clone();
+                }
+                url.id
+            },
+            None => {
+                let id = Uuid::new_v4();
+                history_data.urls.push(Url {
+                    id,
+                    url: captured.url.clone(),
+                    title: captured.title.clone(),
+                    domain,
+                    first_seen: visited_at,
+                    last_seen: visited_at,
+                    campaign: Campaign::from_url(&captured.url),
+                });
+                id
+            },
+        };
+
+        history_data.visits.push(Visit {
+            id: Uuid::new_v4(),
+            url_id,
+            visited_at,
+            visit_count: 1,
+            source_file: CAPTURE_SOURCE.to_string(),
+            device_name: device_name.clone(),
+            duration_sec: captured.duration_sec.filter(|secs| *secs >= 0.0),
+            referrer: captured.referrer.clone().filter(|referrer| !referrer.is_empty()),
+        });
+    }
+
+    history_data
+}
+
+/// Stores captured visits through the import pipeline, after the privacy rules
+pub fn record_visits(conn: &DatabaseConnection, visits: &[CapturedVisit], device_name: Option<String>) -> Result<CaptureSummary> {
+    let mut summary = CaptureSummary { received: visits.len(), ..Default::default() };
+    let mut history_data = to_history_data(visits, device_name, &mut summary);
+
+    let excluded = privacy::load_filter(conn)?.apply(&mut history_data);
+    summary.excluded = excluded.visits;
+
+    let scripts = HookScripts::load(conn, Hook::PreInsert)?;
+    let scripted = scripts.apply(&mut history_data);
+    summary.excluded += scripted.visits_skipped;
+    summary.rejected.extend(scripts.compile_errors().iter().cloned().chain(scripted.errors));
+
+    let stats = crate::db::operations::insert_history_data(
+        conn,
+        &history_data,
+        None,
+        crate::db::imports::DEFAULT_IMPORT_BATCH_SIZE,
+        None,
+    )?;
+    summary.urls_inserted = stats.urls_inserted;
+    summary.visits_inserted = stats.visits_inserted;
+    summary.rejected.extend(stats.errors);
+
+    Ok(summary)
+}
@@ -0,0 +1,121 @@
+// Capture - Server
+// Localhost HTTP listener accepting visits from the browser extension
+
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+use std::thread;
+
+use serde::Deserialize;
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use super::error::{CaptureError, Result};
+use super::{CaptureSummary, CapturedVisit};
+
+/// Largest request body accepted
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// A single visit or a batch of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VisitPayload {
+    One(CapturedVisit),
+    Many(Vec<CapturedVisit>),
+}
+
+/// Running listener; it stops when dropped
+pub struct CaptureServer {
+    server: Arc<Server>,
+}
+
+impl CaptureServer {
+    /// Listens on 127.0.0.1:`port`. Authorized `POST /visits` requests are
+    /// handed to `on_visits`; `GET /status` lets the extension check its token.
+    pub fn start<F>(port: u16, token: String, on_visits: F) -> Result<Self>
+    where
+        F: Fn(Vec<CapturedVisit>) -> std::result::Result<CaptureSummary, String> + Send + 'static,
+    {
+        let server = Arc::new(Server::http(("127.0.0.1", port))
+            .map_err(|e| CaptureError::Server(format!("Failed to listen on port {}: {}", port, e)))?);
+
+        let listener = Arc::clone(&server);
+        thread::spawn(move || {
+            // Ends once the server is unblocked on drop
+            for mut request in listener.incoming_requests() {
+                let response = handle(&mut request, &token, &on_visits);
+                let _ = request.respond(response);
+            }
+        });
+
+        Ok(CaptureServer { server })
+    }
+}
+
+impl Drop for CaptureServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+/// Builds a JSON response
+fn json_response(status: u16, body: serde_json::Value) -> Response<Cursor<Vec<u8>>> {
+    let content_type = Header::from_bytes("Content-Type", "application/json")
+        .expect("static header is valid");
+    Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type)
+}
+
+/// Compares two tokens without stopping at the first difference
+pub fn tokens_equal(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Returns true if the request carries the shared token
+fn is_authorized(request: &Request, token: &str) -> bool {
+    request.headers().iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .is_some_and(|sent| tokens_equal(sent.trim(), token))
+}
+
+/// Answers one request
+fn handle<F>(request: &mut Request, token: &str, on_visits: &F) -> Response<Cursor<Vec<u8>>>
+where
+    F: Fn(Vec<CapturedVisit>) -> std::result::Result<CaptureSummary, String>,
+{
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    let known = path == "/visits" || path == "/status";
+    if !known {
+        return json_response(404, json!({ "error": "Not found" }));
+    }
+    if !is_authorized(request, token) {
+        return json_response(401, json!({ "error": "Missing or wrong token" }));
+    }
+
+    let method = request.method().clone();
+    match (method, path.as_str()) {
+        (Method::Get, "/status") => json_response(200, json!({ "ok": true })),
+        (Method::Post, "/visits") => {
+            let mut body = String::new();
+            if request.as_reader().take(MAX_BODY_BYTES + 1).read_to_string(&mut body).is_err() {
+                return json_response(400, json!({ "error": "Body is not valid UTF-8" }));
+            }
+            if body.len() as u64 > MAX_BODY_BYTES {
+                return json_response(413, json!({ "error": "Body is too large" }));
+            }
+
+            let visits = match serde_json::from_str(&body) {
+                Ok(VisitPayload::One(visit)) => vec![visit],
+                Ok(VisitPayload::Many(visits)) => visits,
+                Err(e) => return json_response(400, json!({ "error": format!("Invalid visits: {}", e) })),
+            };
+
+            match on_visits(visits) {
+                Ok(summary) => json_response(200, json!(summary)),
+                Err(e) => json_response(500, json!({ "error": e })),
+            }
+        },
+        _ => json_response(405, json!({ "error": "Method not allowed" })),
+    }
+}
@@ -0,0 +1,1187 @@
+// Database Analytics
+// Aggregate reporting queries built on top of the history tables
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Timelike, Utc};
+use rusqlite::{Connection, ToSql};
+use serde::{Serialize, Deserialize};
+
+use super::error::{DatabaseError, Result};
+use super::connection::DatabaseConnection;
+use super::models::{UrlRecord, UrlWithVisits};
+use super::query::{QueryBuilder, row_error};
+use super::settings;
+use super::domains;
+use super::trackers;
+use super::journal::placeholders;
+
+/// Number of top domains reported per device
+const DEVICE_TOP_DOMAINS: usize = 10;
+
+/// Default number of pages returned by `get_top_pages`
+pub const DEFAULT_TOP_PAGES_LIMIT: usize = 25;
+
+/// Usage statistics for a single device
+pub struct DeviceStats {
+    /// Device name as recorded at import (None for visits without a device)
+    pub device_name: Option<String>,
+    /// Number of distinct URLs visited on this device
+    pub url_count: usize,
+    /// Total number of visits on this device
+    pub visit_count: usize,
+    /// Date of first visit on this device
+    pub first_visit: Option<DateTime<Utc>>,
+    /// Date of most recent visit on this device
+    pub last_visit: Option<DateTime<Utc>>,
+    /// Top domains by visit count on this device
+    pub top_domains: Vec<(String, usize)>,
+    /// Visit counts per hour of day (index 0-23, UTC)
+    pub activity_hours: Vec<usize>,
+}
+
+/// Gets URL/visit counts, top domains and activity hours broken down by device
+pub fn get_device_stats(
+    conn: &DatabaseConnection,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+) -> Result<Vec<DeviceStats>> {
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT v.device_name,
+                    COUNT(DISTINCT v.url_id) as url_count,
+                    COUNT(*) as visit_count,
+                    MIN(v.visited_at) as first_visit,
+                    MAX(v.visited_at) as last_visit
+             FROM visit v"
+        );
+        query.date_range("v.visited_at", start_date, end_date)
+            .group_by("v.device_name")
+            .order_by("visit_count DESC");
+
+        let mut devices = query.fetch_all(c, |row| {
+            let device_name: Option<String> = row.get(0)?;
+            let url_count: i64 = row.get(1)?;
+            let visit_count: i64 = row.get(2)?;
+            let first_visit_ts: Option<i64> = row.get(3)?;
+            let last_visit_ts: Option<i64> = row.get(4)?;
+
+            Ok(DeviceStats {
+                device_name,
+                url_count: url_count as usize,
+                visit_count: visit_count as usize,
+                first_visit: first_visit_ts.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+                last_visit: last_visit_ts.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+                top_domains: Vec::new(),
+                activity_hours: vec![0; 24],
+            })
+        })?;
+
+        // Fill in the per-device breakdowns
+        for device in devices.iter_mut() {
+            device.top_domains = get_device_top_domains(c, &device.device_name, start_date, end_date)?;
+            device.activity_hours = get_device_activity_hours(c, &device.device_name, start_date, end_date)?;
+        }
+
+        Ok(devices)
+    })
+}
+
+/// Gets the most visited domains for a device
+fn get_device_top_domains(
+    conn: &Connection,
+    device_name: &Option<String>,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+) -> Result<Vec<(String, usize)>> {
+    let mut query = QueryBuilder::new(
+        "SELECT u.domain, COUNT(*) as count
+         FROM visit v
+         JOIN url u ON v.url_id = u.id"
+    );
+
+    // `IS ?` also matches visits without a device name when binding NULL
+    query.filter("v.device_name IS ?", device_name.clone())
+        .date_range("v.visited_at", start_date, end_date)
+        .group_by("u.domain")
+        .order_by("count DESC")
+        .limit(DEVICE_TOP_DOMAINS);
+
+    query.fetch_all(conn, |row| {
+        let domain: String = row.get(0)?;
+        let count: i64 = row.get(1)?;
+        Ok((domain, count as usize))
+    })
+}
+
+/// Gets the number of visits per hour of day for a device
+fn get_device_activity_hours(
+    conn: &Connection,
+    device_name: &Option<String>,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+) -> Result<Vec<usize>> {
+    hourly_distribution(conn, Some(device_name), start_date, end_date)
+}
+
+/// Counts visits per hour of day (UTC), optionally for a single device
+fn hourly_distribution(
+    conn: &Connection,
+    device_name: Option<&Option<String>>,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+) -> Result<Vec<usize>> {
+    let mut query = QueryBuilder::new(
+        "SELECT CAST(strftime('%H', datetime(v.visited_at, 'unixepoch')) AS INTEGER) as hour,
+                COUNT(*) as count
+         FROM visit v"
+    );
+
+    if let Some(device_name) = device_name {
+        query.filter("v.device_name IS ?", device_name.clone());
+    }
+
+    query.date_range("v.visited_at", start_date, end_date)
+        .group_by("hour");
+
+    let rows = query.fetch_all(conn, |row| {
+        let hour: i64 = row.get(0)?;
+        let count: i64 = row.get(1)?;
+        Ok((hour as usize, count as usize))
+    })?;
+
+    let mut hours = vec![0; 24];
+    for (hour, count) in rows {
+        if hour < hours.len() {
+            hours[hour] = count;
+        }
+    }
+
+    Ok(hours)
+}
+
+/// Gets the number of visits per hour of day (UTC) within a date range
+pub fn get_hourly_distribution(
+    conn: &DatabaseConnection,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+) -> Result<Vec<usize>> {
+    conn.with_connection(|c| hourly_distribution(c, None, start_date, end_date))
+}
+
+/// Gets the most visited domains within a date range
+pub fn get_top_domains(
+    conn: &DatabaseConnection,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+    limit: usize,
+) -> Result<Vec<(String, usize)>> {
+    conn.with_connection(|c| {
+        // All-time totals come from the per-domain aggregates
+        if start_date.is_none() && end_date.is_none() {
+            return domains::top_domains(c, limit);
+        }
+
+        let mut query = QueryBuilder::new(
+            "SELECT u.domain, COUNT(*) as count
+             FROM visit v
+             JOIN url u ON v.url_id = u.id"
+        );
+
+        query.date_range("v.visited_at", start_date, end_date);
+        if let Some(exclusion) = trackers::stats_exclusion(c)? {
+            query.condition(exclusion);
+        }
+        query.group_by("u.domain")
+            .order_by("count DESC, u.domain ASC")
+            .limit(limit);
+
+        query.fetch_all(c, |row| {
+            let domain: String = row.get(0)?;
+            let count: i64 = row.get(1)?;
+            Ok((domain, count as usize))
+        })
+    })
+}
+
+/// Gets the number of visits per URL category within a date range, uncategorized URLs last
+pub fn get_category_stats(
+    conn: &DatabaseConnection,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+) -> Result<Vec<(Option<String>, usize)>> {
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT u.category, COUNT(*) as count
+             FROM visit v
+             JOIN url u ON v.url_id = u.id"
+        );
+
+        query.date_range("v.visited_at", start_date, end_date)
+            .group_by("u.category")
+            .order_by("u.category IS NULL, count DESC");
+
+        query.fetch_all(c, |row| {
+            let category: Option<String> = row.get(0)?;
+            let count: i64 = row.get(1)?;
+            Ok((category, count as usize))
+        })
+    })
+}
+
+/// Gets URLs whose first ever visit falls within the date range, most visited first
+pub fn get_new_discoveries(
+    conn: &DatabaseConnection,
+    start_date: DateTime<Utc>,
+    end_date: DateTime<Utc>,
+    limit: usize,
+) -> Result<Vec<UrlWithVisits>> {
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT u.id, u.url, u.title, u.domain, u.first_seen, u.last_seen,
+                    COUNT(v.id) as visit_count,
+                    MAX(v.visited_at) as last_visit
+             FROM url u
+             JOIN visit v ON u.id = v.url_id"
+        );
+
+        query.filter("v.visited_at <= ?", end_date.timestamp())
+            .group_by("u.id")
+            .having_filter("MIN(v.visited_at) >= ?", start_date.timestamp())
+            .order_by("visit_count DESC, last_visit DESC")
+            .limit(limit);
+
+        query.fetch_all(c, |row| {
+            let url = UrlRecord::from_row(row).map_err(row_error)?;
+            let visit_count: i64 = row.get(6)?;
+            let last_visit_ts: Option<i64> = row.get(7)?;
+
+            Ok(UrlWithVisits {
+                url,
+                visit_count: visit_count as usize,
+                last_visit: last_visit_ts.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+            })
+        })
+    })
+}
+
+/// Parameters for the top pages report
+pub struct TopPagesParams {
+    /// Only count visits on or after this date
+    pub start_date: Option<DateTime<Utc>>,
+    /// Only count visits on or before this date
+    pub end_date: Option<DateTime<Utc>>,
+    /// Restrict to a single domain
+    pub domain: Option<String>,
+    /// Maximum number of pages to return
+    pub limit: Option<usize>,
+}
+
+/// Gets the most visited individual URLs within the given filters
+pub fn get_top_pages(conn: &DatabaseConnection, params: &TopPagesParams) -> Result<Vec<UrlWithVisits>> {
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT u.id, u.url, u.title, u.domain, u.first_seen, u.last_seen,
+                    COUNT(v.id) as visit_count,
+                    MAX(v.visited_at) as last_visit
+             FROM url u
+             JOIN visit v ON u.id = v.url_id"
+        );
+
+        query.date_range("v.visited_at", params.start_date, params.end_date);
+
+        if let Some(ref domain) = params.domain {
+            query.filter("u.domain = ?", domain.clone());
+        } else if let Some(exclusion) = trackers::stats_exclusion(c)? {
+            query.condition(exclusion);
+        }
+
+        query.group_by("u.id")
+            .order_by("visit_count DESC, last_visit DESC")
+            .limit(params.limit.unwrap_or(DEFAULT_TOP_PAGES_LIMIT));
+
+        query.fetch_all(c, |row| {
+            let url = UrlRecord::from_row(row).map_err(row_error)?;
+            let visit_count: i64 = row.get(6)?;
+            let last_visit_ts: Option<i64> = row.get(7)?;
+
+            Ok(UrlWithVisits {
+                url,
+                visit_count: visit_count as usize,
+                last_visit: last_visit_ts.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+            })
+        })
+    })
+}
+
+/// Parameters for the skimmed articles report
+pub struct SkimParams {
+    /// Only consider visits on or after this date
+    pub start_date: Option<DateTime<Utc>>,
+    /// Only consider visits on or before this date
+    pub end_date: Option<DateTime<Utc>>,
+    /// Only report pages estimated to take at least this long to read
+    pub min_reading_secs: u64,
+    /// Only report pages where no visit lasted longer than this
+    pub max_dwell_secs: f64,
+    /// Maximum number of pages to return
+    pub limit: usize,
+}
+
+/// A long page that was opened but barely read
+pub struct SkimmedArticle {
+    /// The page and its visit count
+    pub page: UrlWithVisits,
+    /// Number of words in the main content
+    pub word_count: u64,
+    /// Estimated time to read the page in seconds
+    pub reading_time_sec: u64,
+    /// Longest time spent on the page in a single visit
+    pub longest_visit_secs: f64,
+}
+
+/// Finds long-form pages whose visits were all much shorter than their reading
+/// time, longest articles first. Pages without recorded visit durations are skipped.
+pub fn get_skimmed_articles(conn: &DatabaseConnection, params: &SkimParams) -> Result<Vec<SkimmedArticle>> {
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT u.id, u.url, u.title, u.domain, u.first_seen, u.last_seen,
+                    COUNT(v.id) as visit_count,
+                    MAX(v.visited_at) as last_visit,
+                    m.word_count, m.reading_time_sec,
+                    MAX(v.duration_sec) as longest_visit
+             FROM url u
+             JOIN metadata m ON m.url_id = u.id
+             JOIN visit v ON u.id = v.url_id"
+        );
+
+        query.filter("m.reading_time_sec >= ?", params.min_reading_secs as i64)
+            .condition("v.duration_sec IS NOT NULL")
+            .date_range("v.visited_at", params.start_date, params.end_date)
+            .group_by("u.id")
+            .having_filter("MAX(v.duration_sec) <= ?", params.max_dwell_secs)
+            .order_by("m.reading_time_sec DESC, last_visit DESC")
+            .limit(params.limit);
+
+        query.fetch_all(c, |row| {
+            let url = UrlRecord::from_row(row).map_err(row_error)?;
+            let visit_count: i64 = row.get(6)?;
+            let last_visit_ts: Option<i64> = row.get(7)?;
+
+            Ok(SkimmedArticle {
+                page: UrlWithVisits {
+                    url,
+                    visit_count: visit_count as usize,
+                    last_visit: last_visit_ts.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+                },
+                word_count: row.get::<_, i64>(8)? as u64,
+                reading_time_sec: row.get::<_, i64>(9)? as u64,
+                longest_visit_secs: row.get(10)?,
+            })
+        })
+    })
+}
+
+/// Default length of the trend comparison window in days
+pub const DEFAULT_TREND_WINDOW_DAYS: i64 = 7;
+
+/// Longest comparison window for trends, in days
+pub const MAX_TREND_WINDOW_DAYS: i64 = 3650;
+
+/// Minimum visits in the current window for an item to be considered trending
+const TREND_MIN_VISITS: i64 = 3;
+
+/// What to detect trends for
+pub enum TrendDimension {
+    /// Group visits by domain
+    Domain,
+    /// Group visits by the topic cluster assigned during enrichment
+    Topic,
+}
+
+/// Parameters for rising-trend detection
+pub struct TrendParams {
+    /// Dimension to group visits by
+    pub dimension: TrendDimension,
+    /// Length of each comparison window in days
+    pub window_days: i64,
+    /// End of the current window (defaults to now)
+    pub as_of: Option<DateTime<Utc>>,
+    /// Maximum number of items to return
+    pub limit: usize,
+}
+
+/// A domain or topic whose visit frequency is accelerating
+pub struct TrendItem {
+    /// Domain name or topic cluster
+    pub key: String,
+    /// Visits in the current window
+    pub current_count: usize,
+    /// Visits in the preceding window of the same length
+    pub previous_count: usize,
+    /// Relative growth between the windows (1.0 = doubled)
+    pub growth: f64,
+}
+
+/// Detects domains or topics whose visits grew between the previous and current window,
+/// ranked by smoothed relative growth
+pub fn get_trending(conn: &DatabaseConnection, params: &TrendParams) -> Result<Vec<TrendItem>> {
+    let window_secs = params.window_days.clamp(1, MAX_TREND_WINDOW_DAYS) * 24 * 60 * 60;
+    let window_end = params.as_of.unwrap_or_else(Utc::now).timestamp();
+    let current_start = window_end - window_secs;
+    let previous_start = current_start - window_secs;
+
+    let (key_column, base) = match params.dimension {
+        TrendDimension::Domain => (
+            "u.domain",
+            "FROM visit v
+             JOIN url u ON v.url_id = u.id",
+        ),
+        TrendDimension::Topic => (
+            "m.topic_cluster",
+            "FROM visit v
+             JOIN metadata m ON v.url_id = m.url_id",
+        ),
+    };
+
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::with_params(
+            &format!(
+                "SELECT {key} as trend_key,
+                        SUM(CASE WHEN v.visited_at >= ? THEN 1 ELSE 0 END) as current_count,
+                        SUM(CASE WHEN v.visited_at < ? THEN 1 ELSE 0 END) as previous_count
+                 {base}",
+                key = key_column,
+                base = base,
+            ),
+            vec![Box::new(current_start), Box::new(current_start)],
+        );
+
+        query.filter("v.visited_at >= ?", previous_start)
+            .filter("v.visited_at < ?", window_end)
+            .condition(&format!("{} IS NOT NULL", key_column))
+            .group_by("trend_key")
+            .having_filter("current_count >= ? AND current_count > previous_count", TREND_MIN_VISITS);
+
+        let mut items = query.fetch_all(c, |row| {
+            let key: String = row.get(0)?;
+            let current_count: i64 = row.get(1)?;
+            let previous_count: i64 = row.get(2)?;
+
+            Ok(TrendItem {
+                key,
+                current_count: current_count as usize,
+                previous_count: previous_count as usize,
+                growth: (current_count - previous_count) as f64 / previous_count.max(1) as f64,
+            })
+        })?;
+
+        // Add-one smoothing keeps brand new items with a handful of visits from
+        // outranking established ones that grew substantially
+        let score = |item: &TrendItem| {
+            (item.current_count as f64 - item.previous_count as f64) / (item.previous_count as f64 + 1.0)
+        };
+
+        items.sort_by(|a, b| {
+            score(b).partial_cmp(&score(a))
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(b.current_count.cmp(&a.current_count))
+        });
+        items.truncate(params.limit);
+
+        Ok(items)
+    })
+}
+
+/// Visits closer together than this are treated as the same stay, not a return
+const RETURN_MIN_GAP_SECS: i64 = 30 * 60;
+
+/// What to compute revisitation for
+pub enum RevisitDimension {
+    /// Individual URLs
+    Url,
+    /// Whole domains
+    Domain,
+}
+
+/// Parameters for the revisitation report
+pub struct RevisitParams {
+    /// Dimension to analyse
+    pub dimension: RevisitDimension,
+    /// Only consider visits on or after this date
+    pub start_date: Option<DateTime<Utc>>,
+    /// Only consider visits on or before this date
+    pub end_date: Option<DateTime<Utc>>,
+    /// Only report items returned to at least this many times
+    pub min_returns: usize,
+    /// Maximum number of items to return
+    pub limit: usize,
+}
+
+/// Revisitation statistics for a single URL or domain
+pub struct RevisitItem {
+    /// URL id or domain name
+    pub key: String,
+    /// URL string or domain name, for display
+    pub label: String,
+    /// Page title (URL dimension only)
+    pub title: Option<String>,
+    /// Total number of visits
+    pub visit_count: usize,
+    /// Number of separate returns (visits at least 30 minutes after the previous one)
+    pub return_count: usize,
+    /// Share of stays that were returns (0.0 for one-off pages)
+    pub return_rate: f64,
+    /// Median time between returns in seconds
+    pub median_return_secs: Option<f64>,
+}
+
+/// Result of the revisitation report
+pub struct RevisitReport {
+    /// Items that were returned to, most returns first
+    pub items: Vec<RevisitItem>,
+    /// Number of items visited in a single stay only
+    pub one_off_count: usize,
+    /// Number of items returned to at least once
+    pub revisited_count: usize,
+}
+
+/// Accumulates consecutive visit timestamps for one key
+struct RevisitAccumulator {
+    key: String,
+    label: String,
+    title: Option<String>,
+    visit_count: usize,
+    last_visit: i64,
+    gaps: Vec<i64>,
+}
+
+impl RevisitAccumulator {
+    /// Converts the accumulated visits into a report item
+    fn finish(mut self) -> RevisitItem {
+        let return_count = self.gaps.len();
+        let stays = return_count + 1;
+
+        self.gaps.sort_unstable();
+        let median_return_secs = match return_count {
+            0 => None,
+            n if n % 2 == 1 => Some(self.gaps[n / 2] as f64),
+            n => Some((self.gaps[n / 2 - 1] + self.gaps[n / 2]) as f64 / 2.0),
+        };
+
+        RevisitItem {
+            key: self.key,
+            label: self.label,
+            title: self.title,
+            visit_count: self.visit_count,
+            return_count,
+            return_rate: return_count as f64 / stays as f64,
+            median_return_secs,
+        }
+    }
+}
+
+/// Computes per-URL or per-domain return rates and median time-to-return,
+/// distinguishing one-off visits from pages that are revisited
+pub fn get_revisitation_report(conn: &DatabaseConnection, params: &RevisitParams) -> Result<RevisitReport> {
+    let base = match params.dimension {
+        RevisitDimension::Url => {
+            "SELECT u.id, u.url, u.title, v.visited_at
+             FROM visit v
+             JOIN url u ON v.url_id = u.id"
+        },
+        RevisitDimension::Domain => {
+            "SELECT u.domain, u.domain, NULL, v.visited_at
+             FROM visit v
+             JOIN url u ON v.url_id = u.id"
+        },
+    };
+
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(base);
+        query.date_range("v.visited_at", params.start_date, params.end_date)
+            .order_by("1, v.visited_at");
+
+        let mut report = RevisitReport {
+            items: Vec::new(),
+            one_off_count: 0,
+            revisited_count: 0,
+        };
+        let mut current: Option<RevisitAccumulator> = None;
+
+        // Visits arrive ordered by key then time, so each key is processed in one pass
+        let finish = |acc: RevisitAccumulator, report: &mut RevisitReport| {
+            let item = acc.finish();
+            if item.return_count == 0 {
+                report.one_off_count += 1;
+            } else {
+                report.revisited_count += 1;
+            }
+            if item.return_count >= params.min_returns.max(1) {
+                report.items.push(item);
+            }
+        };
+
+        query.for_each(c, |row| {
+            let key: String = row.get(0)?;
+            let visited_at: i64 = row.get(3)?;
+
+            match current {
+                Some(ref mut acc) if acc.key == key => {
+                    if visited_at - acc.last_visit >= RETURN_MIN_GAP_SECS {
+                        acc.gaps.push(visited_at - acc.last_visit);
+                    }
+                    acc.visit_count += 1;
+                    acc.last_visit = visited_at;
+                },
+                _ => {
+                    if let Some(acc) = current.take() {
+                        finish(acc, &mut report);
+                    }
+                    current = Some(RevisitAccumulator {
+                        key,
+                        label: row.get(1)?,
+                        title: row.get(2)?,
+                        visit_count: 1,
+                        last_visit: visited_at,
+                        gaps: Vec::new(),
+                    });
+                },
+            }
+
+            Ok(())
+        })?;
+
+        if let Some(acc) = current.take() {
+            finish(acc, &mut report);
+        }
+
+        report.items.sort_by(|a, b| {
+            b.return_count.cmp(&a.return_count)
+                .then(b.visit_count.cmp(&a.visit_count))
+        });
+        report.items.truncate(params.limit);
+
+        Ok(report)
+    })
+}
+
+/// Default number of pages returned per year by `get_on_this_day`
+pub const DEFAULT_ON_THIS_DAY_PER_YEAR: usize = 5;
+
+/// A page visited on the same calendar date in a previous year
+pub struct OnThisDayItem {
+    /// Year of the visits
+    pub year: i32,
+    /// The page with its visit count and last visit on that day
+    pub page: UrlWithVisits,
+    /// Whether the page has AI metadata
+    pub is_enriched: bool,
+    /// Summary of the page, if enriched
+    pub summary: Option<String>,
+}
+
+/// Gets notable pages visited on the given calendar date in years before `today`,
+/// ranked by visit count and enrichment within each year (newest year first)
+pub fn get_on_this_day(
+    conn: &DatabaseConnection,
+    month: u32,
+    day: u32,
+    today: DateTime<Utc>,
+    per_year: usize,
+) -> Result<Vec<OnThisDayItem>> {
+    let current_year = today.format("%Y").to_string();
+
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT u.id, u.url, u.title, u.domain, u.first_seen, u.last_seen,
+                    COUNT(v.id) as visit_count,
+                    MAX(v.visited_at) as last_visit,
+                    CAST(strftime('%Y', datetime(v.visited_at, 'unixepoch')) AS INTEGER) as year,
+                    COALESCE(m.is_enriched, 0) as is_enriched,
+                    m.summary
+             FROM visit v
+             JOIN url u ON v.url_id = u.id
+             LEFT JOIN metadata m ON m.url_id = u.id"
+        );
+
+        query.filter(
+                "strftime('%m-%d', datetime(v.visited_at, 'unixepoch')) = ?",
+                format!("{:02}-{:02}", month, day),
+            )
+            .filter("strftime('%Y', datetime(v.visited_at, 'unixepoch')) < ?", current_year)
+            .group_by("year, u.id")
+            .order_by("year DESC, visit_count DESC, is_enriched DESC, last_visit DESC");
+
+        let rows = query.fetch_all(c, |row| {
+            let url = UrlRecord::from_row(row).map_err(row_error)?;
+            let visit_count: i64 = row.get(6)?;
+            let last_visit_ts: Option<i64> = row.get(7)?;
+            let year: i32 = row.get(8)?;
+            let is_enriched: bool = row.get(9)?;
+            let summary: Option<String> = row.get(10)?;
+
+            Ok(OnThisDayItem {
+                year,
+                page: UrlWithVisits {
+                    url,
+                    visit_count: visit_count as usize,
+                    last_visit: last_visit_ts.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+                },
+                is_enriched,
+                summary,
+            })
+        })?;
+
+        // Keep only the top pages of each year
+        let mut items = Vec::new();
+        let mut year_count = 0;
+        let mut last_year = None;
+        for item in rows {
+            if last_year != Some(item.year) {
+                last_year = Some(item.year);
+                year_count = 0;
+            }
+            if year_count < per_year {
+                year_count += 1;
+                items.push(item);
+            }
+        }
+
+        Ok(items)
+    })
+}
+
+/// Settings key under which the working-hours configuration is stored
+pub const WORKING_HOURS_SETTING: &str = "working_hours";
+
+/// A recurring window of working time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkWindow {
+    /// Days of the week this window applies to (0 = Monday ... 6 = Sunday)
+    pub days: Vec<u8>,
+    /// Start of the window in minutes after local midnight
+    pub start_minute: u32,
+    /// End of the window in minutes after local midnight (exclusive)
+    pub end_minute: u32,
+}
+
+/// Working-hours configuration used to split visits into work and leisure time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkingHours {
+    /// Working windows; a visit inside any window counts as work time
+    pub windows: Vec<WorkWindow>,
+    /// Offset of the user's local time from UTC in minutes
+    pub utc_offset_minutes: i32,
+}
+
+impl Default for WorkingHours {
+    /// Monday to Friday, 9:00 to 17:00 UTC
+    fn default() -> Self {
+        Self {
+            windows: vec![WorkWindow {
+                days: vec![0, 1, 2, 3, 4],
+                start_minute: 9 * 60,
+                end_minute: 17 * 60,
+            }],
+            utc_offset_minutes: 0,
+        }
+    }
+}
+
+impl WorkingHours {
+    /// Returns true if the given moment falls inside a working window
+    pub fn is_work_time(&self, at: DateTime<Utc>) -> bool {
+        let local = at + Duration::minutes(self.utc_offset_minutes as i64);
+        let weekday = local.weekday().num_days_from_monday() as u8;
+        let minute = local.hour() * 60 + local.minute();
+        
+        self.windows.iter().any(|window| {
+            window.days.contains(&weekday)
+                && minute >= window.start_minute
+                && minute < window.end_minute
+        })
+    }
+}
+
+/// Gets the stored working-hours configuration, falling back to the default
+pub fn get_working_hours(conn: &DatabaseConnection) -> Result<WorkingHours> {
+    conn.with_connection(|c| {
+        Ok(settings::get_setting(c, WORKING_HOURS_SETTING)?.unwrap_or_default())
+    })
+}
+
+/// Stores the working-hours configuration
+pub fn set_working_hours(conn: &DatabaseConnection, working_hours: &WorkingHours) -> Result<()> {
+    conn.with_connection(|c| settings::set_setting(c, WORKING_HOURS_SETTING, working_hours))
+}
+
+/// Work/leisure split for a single domain
+pub struct DomainWorkSplit {
+    /// Domain name
+    pub domain: String,
+    /// Visits during working hours
+    pub work_visits: usize,
+    /// Visits outside working hours
+    pub leisure_visits: usize,
+    /// Percentage of this domain's visits made during working hours
+    pub work_percentage: f64,
+}
+
+/// Category given to visits of URLs that have none
+pub const UNCATEGORIZED: &str = "uncategorized";
+
+/// Work/leisure split for a single URL category
+pub struct CategoryWorkSplit {
+    /// Category name, or "uncategorized"
+    pub category: String,
+    /// Visits during working hours
+    pub work_visits: usize,
+    /// Visits outside working hours
+    pub leisure_visits: usize,
+    /// Percentage of this category's visits made during working hours
+    pub work_percentage: f64,
+}
+
+/// Work-time vs off-time breakdown of browsing
+pub struct WorkLeisureReport {
+    /// Total visits during working hours
+    pub work_visits: usize,
+    /// Total visits outside working hours
+    pub leisure_visits: usize,
+    /// Percentage of all visits made during working hours
+    pub work_percentage: f64,
+    /// Per-domain split, busiest domains first
+    pub domains: Vec<DomainWorkSplit>,
+    /// Per-category split, busiest categories first
+    pub categories: Vec<CategoryWorkSplit>,
+}
+
+/// Computes a percentage, returning 0 for an empty total
+fn percentage(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
+
+/// Splits visits into work-time and off-time buckets using the stored working hours
+pub fn get_work_leisure_stats(
+    conn: &DatabaseConnection,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+    limit: usize,
+) -> Result<WorkLeisureReport> {
+    let working_hours = get_working_hours(conn)?;
+    
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT u.domain, v.visited_at, u.category
+             FROM visit v
+             JOIN url u ON v.url_id = u.id"
+        );
+        query.date_range("v.visited_at", start_date, end_date);
+        
+        // (work, leisure) counts per domain and per category
+        let mut per_domain: HashMap<String, (usize, usize)> = HashMap::new();
+        let mut per_category: HashMap<String, (usize, usize)> = HashMap::new();
+        
+        query.for_each(c, |row| {
+            let domain: String = row.get(0)?;
+            let visited_at: i64 = row.get(1)?;
+            let category = row.get::<_, Option<String>>(2)?
+                .filter(|category| !category.trim().is_empty())
+                .unwrap_or_else(|| UNCATEGORIZED.to_string());
+            
+            let is_work = matches!(DateTime::from_timestamp(visited_at, 0), Some(at) if working_hours.is_work_time(at));
+            for counts in [per_domain.entry(domain).or_insert((0, 0)), per_category.entry(category).or_insert((0, 0))] {
+                if is_work {
+                    counts.0 += 1;
+                } else {
+                    counts.1 += 1;
+                }
+            }
+            
+            Ok(())
+        })?;
+        
+        let work_visits: usize = per_domain.values().map(|c| c.0).sum();
+        let leisure_visits: usize = per_domain.values().map(|c| c.1).sum();
+        
+        let mut domains: Vec<DomainWorkSplit> = per_domain.into_iter()
+            .map(|(domain, (work, leisure))| DomainWorkSplit {
+                domain,
+                work_visits: work,
+                leisure_visits: leisure,
+                work_percentage: percentage(work, work + leisure),
+            })
+            .collect();
+        
+        domains.sort_by(|a, b| {
+            (b.work_visits + b.leisure_visits).cmp(&(a.work_visits + a.leisure_visits))
+                .then(a.domain.cmp(&b.domain))
+        });
+        domains.truncate(limit);
+        
+        let mut categories: Vec<CategoryWorkSplit> = per_category.into_iter()
+            .map(|(category, (work, leisure))| CategoryWorkSplit {
+                category,
+                work_visits: work,
+                leisure_visits: leisure,
+                work_percentage: percentage(work, work + leisure),
+            })
+            .collect();
+        
+        categories.sort_by(|a, b| {
+            (b.work_visits + b.leisure_visits).cmp(&(a.work_visits + a.leisure_visits))
+                .then(a.category.cmp(&b.category))
+        });
+        
+        Ok(WorkLeisureReport {
+            work_visits,
+            leisure_visits,
+            work_percentage: percentage(work_visits, work_visits + leisure_visits),
+            domains,
+            categories,
+        })
+    })
+}
+
+/// A run of consecutive days with at least one visit
+pub struct DayStreak {
+    /// First day of the streak
+    pub start: NaiveDate,
+    /// Last day of the streak
+    pub end: NaiveDate,
+    /// Number of days in the streak
+    pub days: usize,
+}
+
+/// The longest period without any visit
+pub struct BrowsingGap {
+    /// Last visit before the gap
+    pub start: DateTime<Utc>,
+    /// First visit after the gap
+    pub end: DateTime<Utc>,
+    /// Length of the gap in seconds
+    pub seconds: i64,
+}
+
+/// Aggregate browsing-pattern statistics
+pub struct BrowsingPatterns {
+    /// Total number of visits
+    pub total_visits: usize,
+    /// Number of days with at least one visit
+    pub active_days: usize,
+    /// Average visits per calendar day between the first and last active day
+    pub average_visits_per_day: f64,
+    /// Day with the most visits
+    pub busiest_day: Option<(NaiveDate, usize)>,
+    /// Active day with the fewest visits
+    pub quietest_day: Option<(NaiveDate, usize)>,
+    /// Longest run of consecutive active days
+    pub longest_streak: Option<DayStreak>,
+    /// Longest time between two consecutive visits
+    pub longest_gap: Option<BrowsingGap>,
+}
+
+/// Computes streaks, busiest/quietest days, average daily visits and the longest
+/// browsing gap, optionally restricted to a single domain (days are UTC)
+pub fn get_browsing_patterns(
+    conn: &DatabaseConnection,
+    domain: Option<String>,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+) -> Result<BrowsingPatterns> {
+    conn.with_connection(|c| browsing_patterns(c, domain, start_date, end_date))
+}
+
+/// Computes the browsing patterns on one connection
+pub(super) fn browsing_patterns(
+    c: &Connection,
+    domain: Option<String>,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+) -> Result<BrowsingPatterns> {
+    // Visits per day
+    let mut daily_query = QueryBuilder::new(
+        "SELECT strftime('%Y-%m-%d', datetime(v.visited_at, 'unixepoch')) as day,
+                COUNT(*) as count
+         FROM visit v
+         JOIN url u ON v.url_id = u.id"
+    );
+    daily_query.date_range("v.visited_at", start_date, end_date);
+    if let Some(ref domain) = domain {
+        daily_query.filter("u.domain = ?", domain.clone());
+    }
+    daily_query.group_by("day").order_by("day ASC");
+    
+    let daily_counts = daily_query.fetch_all(c, |row| {
+        let day: String = row.get(0)?;
+        let count: i64 = row.get(1)?;
+        Ok((day, count as usize))
+    })?;
+    
+    let days: Vec<(NaiveDate, usize)> = daily_counts.into_iter()
+        .filter_map(|(day, count)| {
+            NaiveDate::parse_from_str(&day, "%Y-%m-%d").ok().map(|date| (date, count))
+        })
+        .collect();
+    
+    let total_visits: usize = days.iter().map(|(_, count)| count).sum();
+    
+    let average_visits_per_day = match (days.first(), days.last()) {
+        (Some((first, _)), Some((last, _))) => {
+            total_visits as f64 / ((*last - *first).num_days() + 1) as f64
+        },
+        _ => 0.0,
+    };
+    
+    // Earliest day wins ties for both extremes
+    let busiest_day = days.iter()
+        .fold(None, |best: Option<(NaiveDate, usize)>, &(date, count)| match best {
+            Some((_, best_count)) if best_count >= count => best,
+            _ => Some((date, count)),
+        });
+    let quietest_day = days.iter()
+        .fold(None, |best: Option<(NaiveDate, usize)>, &(date, count)| match best {
+            Some((_, best_count)) if best_count <= count => best,
+            _ => Some((date, count)),
+        });
+    
+    // Longest run of consecutive days
+    let mut longest_streak: Option<DayStreak> = None;
+    let mut streak_start: Option<NaiveDate> = None;
+    let mut previous: Option<NaiveDate> = None;
+    for &(date, _) in &days {
+        let continues = previous.is_some_and(|prev| (date - prev).num_days() == 1);
+        if !continues {
+            streak_start = Some(date);
+        }
+        previous = Some(date);
+        
+        if let Some(start) = streak_start {
+            let length = (date - start).num_days() as usize + 1;
+            if longest_streak.as_ref().is_none_or(|s| length > s.days) {
+                longest_streak = Some(DayStreak { start, end: date, days: length });
+            }
+        }
+    }
+    
+    // Longest gap between consecutive visits
+    let mut gap_query = QueryBuilder::new(
+        "SELECT LAG(v.visited_at) OVER (ORDER BY v.visited_at) as previous_visit,
+                v.visited_at,
+                v.visited_at - LAG(v.visited_at) OVER (ORDER BY v.visited_at) as gap
+         FROM visit v
+         JOIN url u ON v.url_id = u.id"
+    );
+    gap_query.date_range("v.visited_at", start_date, end_date);
+    if let Some(ref domain) = domain {
+        gap_query.filter("u.domain = ?", domain.clone());
+    }
+    gap_query.order_by("gap DESC").limit(1);
+    
+    let longest_gap = gap_query.fetch_all(c, |row| {
+        let previous_visit: Option<i64> = row.get(0)?;
+        let visited_at: i64 = row.get(1)?;
+        Ok((previous_visit, visited_at))
+    })?
+    .into_iter()
+    .next()
+    .and_then(|(previous_visit, visited_at)| {
+        let start = DateTime::from_timestamp(previous_visit?, 0)?;
+        let end = DateTime::from_timestamp(visited_at, 0)?;
+        Some(BrowsingGap { start, end, seconds: (end - start).num_seconds() })
+    });
+    
+    Ok(BrowsingPatterns {
+        total_visits,
+        active_days: days.len(),
+        average_visits_per_day,
+        busiest_day,
+        quietest_day,
+        longest_streak,
+        longest_gap,
+    })
+}
+
+/// Default number of months covered by sparklines
+pub const DEFAULT_SPARKLINE_MONTHS: u32 = 6;
+
+/// Most months covered by sparklines
+pub const MAX_SPARKLINE_MONTHS: u32 = 36;
+
+/// Most URLs in one sparkline request
+pub const MAX_SPARKLINE_URLS: usize = 500;
+
+/// Seconds in a sparkline week
+const WEEK_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Weekly visit counts of several URLs over the same weeks
+#[derive(Debug, Clone, Serialize)]
+pub struct Sparklines {
+    /// Start of the first week
+    pub start: DateTime<Utc>,
+    /// Number of weeks, the length of every count array
+    pub weeks: usize,
+    /// Visits per week, oldest first, by URL id; URLs without visits in the
+    /// range get zeros
+    pub counts: HashMap<String, Vec<u32>>,
+}
+
+/// Counts the visits of each URL per week over the `months` months before
+/// `as_of` (now by default), with one query for all URLs
+pub fn get_sparklines(
+    conn: &DatabaseConnection,
+    url_ids: &[String],
+    months: u32,
+    as_of: Option<DateTime<Utc>>,
+) -> Result<Sparklines> {
+    if url_ids.len() > MAX_SPARKLINE_URLS {
+        return Err(DatabaseError::Data(format!("At most {} URLs can be requested at once", MAX_SPARKLINE_URLS)));
+    }
+
+    let months = months.clamp(1, MAX_SPARKLINE_MONTHS);
+    let weeks = (months as usize * 52).div_ceil(12);
+    let end = as_of.unwrap_or_else(Utc::now).timestamp();
+    let start = end - weeks as i64 * WEEK_SECS;
+
+    let mut counts: HashMap<String, Vec<u32>> = url_ids.iter()
+        .map(|id| (id.clone(), vec![0; weeks]))
+        .collect();
+
+    if !url_ids.is_empty() {
+        conn.with_connection(|c| {
+            let mut stmt = c.prepare(&format!(
+                "SELECT url_id, (visited_at - ?) / {week} AS week, COUNT(*)
+                 FROM visit
+                 WHERE visited_at >= ? AND visited_at < ? AND url_id IN ({ids})
+                 GROUP BY url_id, week",
+                week = WEEK_SECS,
+                ids = placeholders(url_ids.len()),
+            ))?;
+
+            let mut params: Vec<&dyn ToSql> = vec![&start, &start, &end];
+            params.extend(url_ids.iter().map(|id| id as &dyn ToSql));
+
+            let mut rows = stmt.query(params.as_slice())?;
+            while let Some(row) = rows.next()? {
+                let url_id: String = row.get(0)?;
+                let week: i64 = row.get(1)?;
+                let visits: i64 = row.get(2)?;
+                if let Some(slot) = counts.get_mut(&url_id).and_then(|weeks| weeks.get_mut(week as usize)) {
+                    *slot = visits as u32;
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(Sparklines {
+        start: DateTime::from_timestamp(start, 0).unwrap_or_default(),
+        weeks,
+        counts,
+    })
+}
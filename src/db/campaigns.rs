@@ -0,0 +1,116 @@
+// Campaign Analytics
+// utm_* parameters of the links that led to pages, and which newsletters,
+// platforms and campaigns drive the most reading
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::connection::DatabaseConnection;
+use super::error::Result;
+use super::query::QueryBuilder;
+use crate::extractor::Campaign;
+
+/// Default number of rows in the campaign report
+pub const DEFAULT_CAMPAIGN_LIMIT: usize = 50;
+
+/// Stores the campaign parameters of a URL; the same parameters are stored once
+pub(crate) fn record_campaign(c: &Connection, url_id: Uuid, campaign: &Campaign) -> Result<()> {
+    c.prepare_cached(
+        "INSERT OR IGNORE INTO campaign (url_id, source, medium, name, term, content)
+         VALUES (?, ?, ?, ?, ?, ?)"
+    )?
+    .execute(params![
+        url_id.to_string(),
+        campaign.source.as_deref().unwrap_or_default(),
+        campaign.medium.as_deref().unwrap_or_default(),
+        campaign.name.as_deref().unwrap_or_default(),
+        campaign.term,
+        campaign.content,
+    ])?;
+    Ok(())
+}
+
+/// What the campaign report groups by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CampaignDimension {
+    /// utm_source
+    #[default]
+    Source,
+    /// utm_medium
+    Medium,
+    /// utm_campaign, with its source
+    Campaign,
+}
+
+/// Parameters of the campaign report
+pub struct CampaignParams {
+    /// What to group by
+    pub dimension: CampaignDimension,
+    /// Only count visits on or after this date
+    pub start_date: Option<DateTime<Utc>>,
+    /// Only count visits on or before this date
+    pub end_date: Option<DateTime<Utc>>,
+    /// Maximum number of rows
+    pub limit: Option<usize>,
+}
+
+/// Reading driven by one source, medium or campaign
+#[derive(Debug, Clone, Serialize)]
+pub struct CampaignStat {
+    /// The source, medium or campaign name; None for links without one
+    pub key: Option<String>,
+    /// Source of the campaign, when grouping by campaign
+    pub source: Option<String>,
+    /// Number of pages reached through it
+    pub pages: usize,
+    /// Number of visits to those pages
+    pub visits: usize,
+    /// Total seconds spent on those pages, where durations are known
+    pub reading_secs: f64,
+    /// Latest visit
+    pub last_visit: Option<DateTime<Utc>>,
+}
+
+/// Ranks sources, media or campaigns by the visits to the pages they led to
+pub fn get_campaign_stats(conn: &DatabaseConnection, params: &CampaignParams) -> Result<Vec<CampaignStat>> {
+    let (key, source) = match params.dimension {
+        CampaignDimension::Source => ("source", "''"),
+        CampaignDimension::Medium => ("medium", "''"),
+        CampaignDimension::Campaign => ("name", "source"),
+    };
+
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(&format!(
+            "SELECT c.campaign_key, c.campaign_source,
+                    COUNT(DISTINCT c.url_id), COUNT(v.id),
+                    COALESCE(SUM(v.duration_sec), 0), MAX(v.visited_at)
+             FROM (
+                 -- A URL reached through several campaigns of one source counts once for it
+                 SELECT DISTINCT url_id, {key} AS campaign_key, {source} AS campaign_source FROM campaign
+             ) c
+             JOIN visit v ON v.url_id = c.url_id",
+            key = key,
+            source = source,
+        ));
+        query.date_range("v.visited_at", params.start_date, params.end_date)
+            .group_by("c.campaign_key, c.campaign_source")
+            .order_by("COUNT(v.id) DESC, c.campaign_key")
+            .limit(params.limit.unwrap_or(DEFAULT_CAMPAIGN_LIMIT));
+
+        query.fetch_all(c, |row| {
+            let key: String = row.get(0)?;
+            let source: String = row.get(1)?;
+            Ok(CampaignStat {
+                key: Some(key).filter(|key| !key.is_empty()),
+                source: Some(source).filter(|source| !source.is_empty()),
+                pages: row.get::<_, i64>(2)? as usize,
+                visits: row.get::<_, i64>(3)? as usize,
+                reading_secs: row.get(4)?,
+                last_visit: row.get::<_, Option<i64>>(5)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+            })
+        })
+    })
+}
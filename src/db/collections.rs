@@ -0,0 +1,344 @@
+// Favorites and Collections
+// Starred URLs and user-defined, ordered reading lists
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+use super::journal::{capture, finish_operation, start_operation};
+use super::models::{UrlRecord, UrlWithVisits};
+use super::query::{QueryBuilder, row_error};
+
+/// A named list of URLs
+#[derive(Debug, Clone, Serialize)]
+pub struct Collection {
+    /// Collection identifier
+    pub id: i64,
+    /// Name (unique, case-insensitive)
+    pub name: String,
+    /// Optional description
+    pub description: Option<String>,
+    /// Number of URLs in the collection
+    pub item_count: usize,
+    /// When the collection was created
+    pub created_at: DateTime<Utc>,
+    /// When the collection or its items last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A URL in a collection
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectionItem {
+    /// URL id
+    pub url_id: String,
+    /// Full URL
+    pub url: String,
+    /// Page title, if known
+    pub title: Option<String>,
+    /// Domain of the URL
+    pub domain: String,
+    /// 0-based position in the collection
+    pub position: usize,
+    /// Optional user note
+    pub note: Option<String>,
+    /// When the URL was added
+    pub added_at: DateTime<Utc>,
+}
+
+/// Trims a collection name, rejecting empty names
+fn normalize_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(DatabaseError::Data("Collection name cannot be empty".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// Maps a database error on the unique name to a readable message
+fn name_conflict(name: &str, err: rusqlite::Error) -> DatabaseError {
+    match err {
+        rusqlite::Error::SqliteFailure(e, _) if e.code == rusqlite::ErrorCode::ConstraintViolation => {
+            DatabaseError::Data(format!("A collection named '{}' already exists", name))
+        },
+        e => DatabaseError::from(e),
+    }
+}
+
+/// Loads a single collection with its item count
+fn get_collection(c: &Connection, id: i64) -> Result<Collection> {
+    c.query_row(
+        "SELECT c.id, c.name, c.description,
+                (SELECT COUNT(*) FROM collection_item i WHERE i.collection_id = c.id),
+                c.created_at, c.updated_at
+         FROM collection c WHERE c.id = ?",
+        [id],
+        collection_from_row,
+    ).optional()?
+    .ok_or_else(|| DatabaseError::NotFound(format!("Collection {} does not exist", id)))
+}
+
+/// Maps a (id, name, description, item_count, created_at, updated_at) row
+fn collection_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Collection> {
+    Ok(Collection {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        item_count: row.get::<_, i64>(3)? as usize,
+        created_at: DateTime::from_timestamp(row.get(4)?, 0).unwrap_or_default(),
+        updated_at: DateTime::from_timestamp(row.get(5)?, 0).unwrap_or_default(),
+    })
+}
+
+/// Marks the collection as changed
+fn touch(c: &Connection, id: i64) -> Result<()> {
+    c.execute("UPDATE collection SET updated_at = ? WHERE id = ?", params![Utc::now().timestamp(), id])?;
+    Ok(())
+}
+
+/// Gets the URL ids of a collection in order
+fn item_order(c: &Connection, id: i64) -> Result<Vec<String>> {
+    let mut stmt = c.prepare("SELECT url_id FROM collection_item WHERE collection_id = ? ORDER BY position")?;
+    let rows = stmt.query_map([id], |row| row.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<Vec<String>>>()?)
+}
+
+/// Stores `order` as the positions 0..n of the collection's items
+fn write_order(c: &Connection, id: i64, order: &[String]) -> Result<()> {
+    let mut stmt = c.prepare("UPDATE collection_item SET position = ? WHERE collection_id = ? AND url_id = ?")?;
+    for (position, url_id) in order.iter().enumerate() {
+        stmt.execute(params![position as i64, id, url_id])?;
+    }
+    Ok(())
+}
+
+/// Sets or clears the favorite flag of URLs, returning the number of updated URLs
+pub fn set_favorite(conn: &DatabaseConnection, url_ids: &[String], favorite: bool) -> Result<usize> {
+    conn.transaction(|tx| {
+        let mut stmt = tx.prepare("UPDATE url SET is_favorite = ? WHERE id = ?")?;
+        let mut updated = 0;
+        for url_id in url_ids {
+            updated += stmt.execute(params![favorite, url_id])?;
+        }
+        Ok(updated)
+    })
+}
+
+/// Gets every favorite URL with its visit count, most recently visited first
+pub fn get_favorites(conn: &DatabaseConnection) -> Result<Vec<UrlWithVisits>> {
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT u.id, u.url, u.title, u.domain, u.first_seen, u.last_seen,
+                    COUNT(v.id) as visit_count,
+                    MAX(v.visited_at) as last_visit
+             FROM url u
+             LEFT JOIN visit v ON u.id = v.url_id"
+        );
+        query.condition("u.is_favorite = 1")
+            .group_by("u.id")
+            .order_by("last_visit DESC");
+
+        query.fetch_all(c, |row| {
+            let url = UrlRecord::from_row(row).map_err(row_error)?;
+            let visit_count: i64 = row.get(6)?;
+            let last_visit_ts: Option<i64> = row.get(7)?;
+
+            Ok(UrlWithVisits {
+                url,
+                visit_count: visit_count as usize,
+                last_visit: last_visit_ts.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+            })
+        })
+    })
+}
+
+/// Lists every collection, alphabetically
+pub fn list_collections(conn: &DatabaseConnection) -> Result<Vec<Collection>> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT c.id, c.name, c.description, COUNT(i.url_id), c.created_at, c.updated_at
+             FROM collection c
+             LEFT JOIN collection_item i ON i.collection_id = c.id
+             GROUP BY c.id
+             ORDER BY c.name COLLATE NOCASE"
+        )?;
+        let rows = stmt.query_map([], collection_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
+
+/// Creates an empty collection
+pub fn create_collection(conn: &DatabaseConnection, name: &str, description: Option<&str>) -> Result<Collection> {
+    let name = normalize_name(name)?;
+
+    conn.with_connection(|c| {
+        let now = Utc::now().timestamp();
+        c.execute(
+            "INSERT INTO collection (name, description, created_at, updated_at) VALUES (?, ?, ?, ?)",
+            params![name, description, now, now],
+        ).map_err(|e| name_conflict(&name, e))?;
+        get_collection(c, c.last_insert_rowid())
+    })
+}
+
+/// Renames a collection and/or changes its description; None leaves a field unchanged
+pub fn update_collection(
+    conn: &DatabaseConnection,
+    id: i64,
+    name: Option<&str>,
+    description: Option<&str>,
+) -> Result<Collection> {
+    let name = name.map(normalize_name).transpose()?;
+
+    conn.transaction(|tx| {
+        get_collection(tx, id)?;
+
+        if let Some(name) = &name {
+            tx.execute("UPDATE collection SET name = ? WHERE id = ?", params![name, id])
+                .map_err(|e| name_conflict(name, e))?;
+        }
+        if let Some(description) = description {
+            // An empty description clears it
+            let description = Some(description.trim()).filter(|d| !d.is_empty());
+            tx.execute("UPDATE collection SET description = ? WHERE id = ?", params![description, id])?;
+        }
+
+        touch(tx, id)?;
+        get_collection(tx, id)
+    })
+}
+
+/// Deletes a collection (the URLs themselves are kept)
+pub fn delete_collection(conn: &DatabaseConnection, id: i64) -> Result<()> {
+    conn.transaction(|tx| {
+        let collection = get_collection(tx, id)?;
+
+        let operation_id = start_operation(tx, "delete_collection", &format!("Deleted collection '{}'", collection.name))?;
+        capture(tx, operation_id, "collection", "id = ?", vec![Value::Integer(id)])?;
+        capture(tx, operation_id, "collection_item", "collection_id = ?", vec![Value::Integer(id)])?;
+
+        tx.execute("DELETE FROM collection WHERE id = ?", [id])?;
+        finish_operation(tx, operation_id)
+    })
+}
+
+/// Gets the items of a collection in order
+pub fn get_collection_items(conn: &DatabaseConnection, id: i64) -> Result<Vec<CollectionItem>> {
+    conn.with_connection(|c| {
+        get_collection(c, id)?;
+
+        let mut stmt = c.prepare(
+            "SELECT u.id, u.url, u.title, u.domain, i.position, i.note, i.added_at
+             FROM collection_item i
+             JOIN url u ON u.id = i.url_id
+             WHERE i.collection_id = ?
+             ORDER BY i.position"
+        )?;
+        let rows = stmt.query_map([id], |row| {
+            Ok(CollectionItem {
+                url_id: row.get(0)?,
+                url: row.get(1)?,
+                title: row.get(2)?,
+                domain: row.get(3)?,
+                position: row.get::<_, i64>(4)? as usize,
+                note: row.get(5)?,
+                added_at: DateTime::from_timestamp(row.get(6)?, 0).unwrap_or_default(),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
+
+/// Adds URLs to a collection at `position` (appended when None); URLs already
+/// in the collection are moved there instead
+pub fn add_to_collection(
+    conn: &DatabaseConnection,
+    id: i64,
+    url_ids: &[String],
+    position: Option<usize>,
+) -> Result<Collection> {
+    conn.transaction(|tx| {
+        get_collection(tx, id)?;
+
+        let now = Utc::now().timestamp();
+        let mut order = item_order(tx, id)?;
+        order.retain(|existing| !url_ids.contains(existing));
+
+        let mut added = Vec::new();
+        for url_id in url_ids {
+            if added.contains(url_id) {
+                continue;
+            }
+            let exists: bool = tx.query_row("SELECT EXISTS(SELECT 1 FROM url WHERE id = ?)", [url_id], |row| row.get(0))?;
+            if !exists {
+                return Err(DatabaseError::NotFound(format!("URL {} does not exist", url_id)));
+            }
+            tx.execute(
+                "INSERT OR IGNORE INTO collection_item (collection_id, url_id, position, added_at) VALUES (?, ?, 0, ?)",
+                params![id, url_id, now],
+            )?;
+            added.push(url_id.clone());
+        }
+
+        let at = position.unwrap_or(order.len()).min(order.len());
+        order.splice(at..at, added);
+        write_order(tx, id, &order)?;
+
+        touch(tx, id)?;
+        get_collection(tx, id)
+    })
+}
+
+/// Removes URLs from a collection
+pub fn remove_from_collection(conn: &DatabaseConnection, id: i64, url_ids: &[String]) -> Result<Collection> {
+    conn.transaction(|tx| {
+        let mut stmt = tx.prepare("DELETE FROM collection_item WHERE collection_id = ? AND url_id = ?")?;
+        for url_id in url_ids {
+            stmt.execute(params![id, url_id])?;
+        }
+
+        let order = item_order(tx, id)?;
+        write_order(tx, id, &order)?;
+
+        touch(tx, id)?;
+        get_collection(tx, id)
+    })
+}
+
+/// Reorders a collection; `url_ids` lists items in their new order, and items
+/// it leaves out keep their relative order after them
+pub fn reorder_collection(conn: &DatabaseConnection, id: i64, url_ids: &[String]) -> Result<Collection> {
+    conn.transaction(|tx| {
+        let current = item_order(tx, id)?;
+
+        let mut order: Vec<String> = Vec::with_capacity(current.len());
+        for url_id in url_ids {
+            if current.contains(url_id) && !order.contains(url_id) {
+                order.push(url_id.clone());
+            }
+        }
+        order.extend(current.into_iter().filter(|url_id| !url_ids.contains(url_id)));
+
+        write_order(tx, id, &order)?;
+
+        touch(tx, id)?;
+        get_collection(tx, id)
+    })
+}
+
+/// Sets or clears the note of a collection item
+pub fn set_collection_note(conn: &DatabaseConnection, id: i64, url_id: &str, note: Option<&str>) -> Result<()> {
+    conn.with_connection(|c| {
+        let note = note.map(str::trim).filter(|n| !n.is_empty());
+        let updated = c.execute(
+            "UPDATE collection_item SET note = ? WHERE collection_id = ? AND url_id = ?",
+            params![note, id, url_id],
+        )?;
+        if updated == 0 {
+            return Err(DatabaseError::Data(format!("URL {} is not in collection {}", url_id, id)));
+        }
+        touch(c, id)
+    })
+}
@@ -2,61 +2,151 @@
 // Handles SQLite connection creation and management
 
 use std::path::{Path, PathBuf};
-use rusqlite::{Connection, OpenFlags};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{CachedStatement, Connection, OpenFlags};
 
+use super::encryption::apply_key;
 use super::error::{DatabaseError, Result};
+use super::interrupt::run_limited;
 
-/// Represents a connection to the database
+/// Maximum number of open connections; WAL lets readers run alongside one writer
+const POOL_SIZE: u32 = 8;
+
+/// How long a connection waits for another connection's write lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Prepared statements each pooled connection keeps compiled, least recently
+/// used first out; covers the import, search and timeline statements
+const STATEMENT_CACHE_CAPACITY: usize = 128;
+
+/// How long closing waits for connections that are still in use
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Represents a connection to the database; clones share the same pool, and
+/// closing one closes them all
+#[derive(Clone)]
 pub struct DatabaseConnection {
     /// Path to the database file
     pub path: PathBuf,
-    /// The SQLite connection wrapped in Arc<Mutex<>> for thread safety
-    connection: Arc<Mutex<Connection>>,
+    /// Pool of SQLite connections so independent commands don't wait on each
+    /// other; None once closed
+    pool: Arc<RwLock<Option<Pool<SqliteConnectionManager>>>>,
+}
+
+/// Prepares every new pooled connection
+fn configure(conn: &mut Connection, key: Option<&str>) -> rusqlite::Result<()> {
+    // The key must be set before anything else reads the file
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key)?;
+    }
+    
+    // Enable foreign keys support
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+    
+    // Set some sensible defaults for performance
+    conn.execute_batch("
+        PRAGMA journal_mode = WAL;
+        PRAGMA synchronous = NORMAL;
+        PRAGMA cache_size = 1000;
+        PRAGMA temp_store = MEMORY;
+    ")
 }
 
 impl DatabaseConnection {
     /// Creates a new database connection
     pub fn new(path: &Path) -> Result<Self> {
-        // Open the SQLite database with appropriate flags
-        let conn = Connection::open_with_flags(
-            path,
-            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
-        ).map_err(|e| DatabaseError::Connection(e.to_string()))?;
+        Self::open(path, None)
+    }
+    
+    /// Creates a new database connection, unlocking an encrypted database with the key
+    pub fn open(path: &Path, key: Option<&str>) -> Result<Self> {
+        // Check the key up front so a wrong passphrase gets a clear error
+        if let Some(key) = key {
+            let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+                .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+            apply_key(&conn, key)?;
+        }
         
-        // Enable foreign keys support
-        conn.execute_batch("PRAGMA foreign_keys = ON;")
-            .map_err(|e| DatabaseError::Query(e.to_string()))?;
+        let key = key.map(str::to_string);
+        let manager = SqliteConnectionManager::file(path)
+            .with_flags(OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE)
+            .with_init(move |conn| configure(conn, key.as_deref()));
         
-        // Set some sensible defaults for performance
-        conn.execute_batch("
-            PRAGMA journal_mode = WAL;
-            PRAGMA synchronous = NORMAL;
-            PRAGMA cache_size = 1000;
-            PRAGMA temp_store = MEMORY;
-        ").map_err(|e| DatabaseError::Query(e.to_string()))?;
+        let pool = Pool::builder()
+            .max_size(POOL_SIZE)
+            .build(manager)
+            .map_err(|e| DatabaseError::Connection(e.to_string()))?;
         
         Ok(Self {
             path: path.to_path_buf(),
-            connection: Arc::new(Mutex::new(conn)),
+            pool: Arc::new(RwLock::new(Some(pool))),
         })
     }
     
-    /// Gets a reference to the connection for operations
-    /// This ensures thread safety with the mutex lock
-    pub fn get(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
-        self.connection.lock()
-            .map_err(|_| DatabaseError::Lock("Failed to acquire database lock".to_string()))
+    /// Gets a connection from the pool; it returns to the pool when dropped
+    pub fn get(&self) -> Result<PooledConnection<SqliteConnectionManager>> {
+        let pool = self.pool.read()
+            .map_err(|_| DatabaseError::Lock("Database pool is unavailable".to_string()))?;
+        pool.as_ref()
+            .ok_or_else(|| DatabaseError::Lock("Database is closed".to_string()))?
+            .get()
+            .map_err(|e| DatabaseError::Lock(format!("Failed to get a database connection: {}", e)))
     }
     
-    /// Executes a function with the database connection
-    /// This pattern ensures the lock is always released
+    /// Closes the database for this connection and every clone of it once
+    /// running queries finish, e.g. before the file is replaced. New requests
+    /// for a connection wait meanwhile, and fail once it is closed, so
+    /// nothing reopens the file behind the caller's back.
+    pub fn close(&self) -> Result<()> {
+        let mut pool = self.pool.write()
+            .map_err(|_| DatabaseError::Lock("Database pool is unavailable".to_string()))?;
+        let deadline = Instant::now() + CLOSE_TIMEOUT;
+        
+        if let Some(open) = pool.as_ref() {
+            loop {
+                let state = open.state();
+                if state.idle_connections == state.connections {
+                    break;
+                }
+                if Instant::now() >= deadline {
+                    return Err(DatabaseError::Lock("Database is still in use".to_string()));
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+        
+        // Dropping the pool closes its idle SQLite connections
+        pool.take();
+        Ok(())
+    }
+    
+    /// Executes a function with a pooled database connection
+    /// This pattern ensures the connection is always returned; within
+    /// `interrupt::with_limits` the function's queries are stopped at the limits
     pub fn with_connection<F, T>(&self, f: F) -> Result<T>
     where
         F: FnOnce(&Connection) -> Result<T>,
     {
         let conn = self.get()?;
-        f(&conn)
+        run_limited(&conn, f)
+    }
+    
+    /// Executes a function with a statement from the pooled connection's cache
+    /// of prepared statements, compiling it only the first time it is used
+    pub fn with_statement<F, T>(&self, sql: &str, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut CachedStatement<'_>) -> Result<T>,
+    {
+        self.with_connection(|conn| {
+            let mut stmt = conn.prepare_cached(sql)?;
+            f(&mut stmt)
+        })
     }
     
     /// Begins a transaction
@@ -64,14 +154,16 @@ impl DatabaseConnection {
     where
         F: FnOnce(&Connection) -> Result<T>,
     {
-        let conn = self.get()?;
+        let mut conn = self.get()?;
         
-        let tx = conn.transaction()
-            .map_err(|e| DatabaseError::Transaction(e.to_string()))?;
+        // Take the write lock immediately so concurrent writers wait for the
+        // busy timeout instead of failing on lock upgrade
+        let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(DatabaseError::transaction)?;
             
         match f(&tx) {
             Ok(result) => {
-                tx.commit().map_err(|e| DatabaseError::Transaction(e.to_string()))?;
+                tx.commit().map_err(DatabaseError::transaction)?;
                 Ok(result)
             },
             Err(e) => {
@@ -85,7 +177,7 @@ impl DatabaseConnection {
     pub fn execute_batch(&self, sql: &str) -> Result<()> {
         self.with_connection(|conn| {
             conn.execute_batch(sql)
-                .map_err(|e| DatabaseError::Query(e.to_string()))?;
+                .map_err(DatabaseError::from)?;
             Ok(())
         })
     }
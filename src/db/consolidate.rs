@@ -0,0 +1,283 @@
+// Database Consolidation
+// Merges another instance's database (e.g. from a second machine) into this one
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::connection::DatabaseConnection;
+use super::encryption::is_encrypted_file;
+use super::error::{DatabaseError, Result};
+
+/// What a database merge brought in
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MergeDatabaseResult {
+    /// URLs that were only in the other database
+    pub urls_added: usize,
+    /// URLs both databases had, merged into one record
+    pub urls_merged: usize,
+    /// Visits that were only in the other database
+    pub visits_added: usize,
+    /// Metadata records added or completed from the other database
+    pub metadata_merged: usize,
+    /// Tag assignments that were only in the other database
+    pub tags_added: usize,
+}
+
+/// Reads the schema version of an attached database
+fn schema_version(c: &Connection, schema: &str) -> Result<i32> {
+    Ok(c.query_row(&format!("SELECT version FROM {}.schema_version LIMIT 1", schema), [], |row| row.get(0))?)
+}
+
+/// Merges the other database's URLs into this one and returns (other id, local id) pairs
+fn merge_urls(c: &Connection, result: &mut MergeDatabaseResult) -> Result<Vec<(String, String)>> {
+    let mut stmt = c.prepare(
+        "SELECT id, url, title, domain, first_seen, last_seen, category, is_favorite FROM other.url"
+    )?;
+    let others = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?,
+            row.get::<_, String>(3)?, row.get::<_, i64>(4)?, row.get::<_, i64>(5)?,
+            row.get::<_, Option<String>>(6)?, row.get::<_, i64>(7)?,
+        ))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut mapping = Vec::with_capacity(others.len());
+    for (other_id, url, title, domain, first_seen, last_seen, category, is_favorite) in others {
+        let existing: Option<String> = c.query_row(
+            "SELECT id FROM main.url WHERE url = ?", [&url], |row| row.get(0),
+        ).optional()?;
+
+        let id = match existing {
+            Some(id) => {
+                c.execute(
+                    "UPDATE main.url SET
+                         first_seen = MIN(first_seen, ?1),
+                         last_seen = MAX(last_seen, ?2),
+                         title = COALESCE(title, ?3),
+                         category = COALESCE(category, ?4),
+                         is_favorite = MAX(is_favorite, ?5)
+                     WHERE id = ?6",
+                    params![first_seen, last_seen, title, category, is_favorite, id],
+                )?;
+                result.urls_merged += 1;
+                id
+            },
+            None => {
+                // Both databases may descend from the same file; never reuse a taken id
+                let taken: bool = c.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM main.url WHERE id = ?)", [&other_id], |row| row.get(0),
+                )?;
+                let id = if taken { Uuid::new_v4().to_string() } else { other_id.clone() };
+
+                c.execute(
+                    "INSERT INTO main.url (id, url, title, domain, first_seen, last_seen, category, is_favorite)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                    params![id, url, title, domain, first_seen, last_seen, category, is_favorite],
+                )?;
+                result.urls_added += 1;
+                id
+            },
+        };
+
+        mapping.push((other_id, id));
+    }
+
+    Ok(mapping)
+}
+
+/// Adds the other database's visits that this one doesn't have yet, skipping
+/// visits deleted on either side
+fn merge_visits(c: &Connection) -> Result<usize> {
+    c.execute(
+        "INSERT OR IGNORE INTO main.visit_tombstone (url, visited_at, deleted_at)
+         SELECT url, visited_at, deleted_at FROM other.visit_tombstone",
+        [],
+    )?;
+    c.execute(
+        "DELETE FROM main.visit WHERE EXISTS (
+             SELECT 1 FROM main.visit_tombstone t JOIN main.url u ON u.url = t.url
+             WHERE u.id = visit.url_id AND t.visited_at = visit.visited_at
+         )",
+        [],
+    )?;
+
+    let mut stmt = c.prepare(
+        "SELECT m.id, v.visited_at, v.visit_count, v.source_file, v.device_name, v.duration_sec
+         FROM other.visit v
+         JOIN temp.merge_url_map m ON m.other_id = v.url_id
+         JOIN main.url u ON u.id = m.id
+         WHERE NOT EXISTS (
+             SELECT 1 FROM main.visit_tombstone t WHERE t.url = u.url AND t.visited_at = v.visited_at
+         )"
+    )?;
+    let visits = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?,
+            row.get::<_, String>(3)?, row.get::<_, Option<String>>(4)?, row.get::<_, Option<f64>>(5)?,
+        ))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut insert = c.prepare(
+        "INSERT INTO main.visit (id, url_id, visited_at, visit_count, source_file, device_name, duration_sec)
+         VALUES (?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT (url_id, visited_at, source_file) DO NOTHING"
+    )?;
+
+    let mut added = 0;
+    for (url_id, visited_at, visit_count, source_file, device_name, duration_sec) in visits {
+        added += insert.execute(params![
+            Uuid::new_v4().to_string(), url_id, visited_at, visit_count, source_file, device_name, duration_sec,
+        ])?;
+    }
+
+    Ok(added)
+}
+
+/// Adds the other database's devices and links the merged visits to them
+fn merge_devices(c: &Connection) -> Result<()> {
+    c.execute(
+        "INSERT INTO main.device (name, platform, first_imported_at, last_imported_at)
+         SELECT name, platform, first_imported_at, last_imported_at FROM other.device
+         WHERE true
+         ON CONFLICT (name) DO UPDATE SET
+             platform = COALESCE(device.platform, excluded.platform),
+             first_imported_at = COALESCE(MIN(device.first_imported_at, excluded.first_imported_at), device.first_imported_at, excluded.first_imported_at),
+             last_imported_at = COALESCE(MAX(device.last_imported_at, excluded.last_imported_at), device.last_imported_at, excluded.last_imported_at)",
+        [],
+    )?;
+    c.execute(
+        "UPDATE main.visit SET device_id = (SELECT id FROM main.device d WHERE d.name = visit.device_name)
+         WHERE device_id IS NULL AND device_name IS NOT NULL",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Merges metadata, preferring enriched values and filling gaps from the other side
+fn merge_metadata(c: &Connection) -> Result<usize> {
+    // Each column keeps the enriched side's value, or this database's on a tie
+    let pick = |column: &str| format!(
+        "{0} = CASE WHEN excluded.is_enriched > metadata.is_enriched
+                    THEN COALESCE(excluded.{0}, metadata.{0})
+                    ELSE COALESCE(metadata.{0}, excluded.{0}) END",
+        column,
+    );
+    let columns = ["summary", "keywords", "topic_cluster", "thumbnail_path", "thumbnail_checked_at", "word_count", "reading_time_sec", "access_wall"];
+    let updates: Vec<String> = columns.iter().map(|column| pick(column)).collect();
+
+    Ok(c.execute(
+        &format!(
+            "INSERT INTO main.metadata (url_id, is_enriched, {columns})
+             SELECT m.id, o.is_enriched, {other_columns}
+             FROM other.metadata o
+             JOIN temp.merge_url_map m ON m.other_id = o.url_id
+             WHERE true
+             ON CONFLICT (url_id) DO UPDATE SET {updates}, is_enriched = MAX(metadata.is_enriched, excluded.is_enriched)",
+            columns = columns.join(", "),
+            other_columns = columns.iter().map(|column| format!("o.{}", column)).collect::<Vec<_>>().join(", "),
+            updates = updates.join(", "),
+        ),
+        [],
+    )?)
+}
+
+/// Adds the other database's tags and tag assignments
+fn merge_tags(c: &Connection) -> Result<usize> {
+    c.execute(
+        "INSERT OR IGNORE INTO main.tag (name, created_at) SELECT name, created_at FROM other.tag",
+        [],
+    )?;
+
+    Ok(c.execute(
+        "INSERT OR IGNORE INTO main.url_tag (url_id, tag_id, created_at)
+         SELECT m.id, t.id, ot.created_at
+         FROM other.url_tag ot
+         JOIN other.tag o ON o.id = ot.tag_id
+         JOIN main.tag t ON t.name = o.name
+         JOIN temp.merge_url_map m ON m.other_id = ot.url_id",
+        [],
+    )?)
+}
+
+/// Merges the attached database into the main one
+fn merge_attached(c: &Connection) -> Result<MergeDatabaseResult> {
+    let mut result = MergeDatabaseResult::default();
+
+    let mapping = merge_urls(c, &mut result)?;
+
+    c.execute_batch(
+        "DROP TABLE IF EXISTS temp.merge_url_map;
+         CREATE TEMP TABLE merge_url_map (other_id TEXT PRIMARY KEY, id TEXT NOT NULL);"
+    )?;
+    {
+        let mut insert = c.prepare("INSERT INTO temp.merge_url_map (other_id, id) VALUES (?, ?)")?;
+        for (other_id, id) in &mapping {
+            insert.execute(params![other_id, id])?;
+        }
+    }
+
+    result.visits_added = merge_visits(c)?;
+    merge_devices(c)?;
+    result.metadata_merged = merge_metadata(c)?;
+    result.tags_added = merge_tags(c)?;
+
+    c.execute(
+        "INSERT OR IGNORE INTO main.url_redirect (source_url, target_url, resolved_at)
+         SELECT source_url, target_url, resolved_at FROM other.url_redirect",
+        [],
+    )?;
+
+    c.execute_batch("DROP TABLE temp.merge_url_map;")?;
+
+    Ok(result)
+}
+
+/// Merges the database at `other_path` into this one: URLs are matched by
+/// address, visits are unioned, the richer metadata wins and tags are combined.
+/// Both databases must be on the same schema version; an encrypted one needs its key.
+pub fn merge_database(conn: &DatabaseConnection, other_path: &Path, key: Option<&str>) -> Result<MergeDatabaseResult> {
+    if !other_path.exists() {
+        return Err(DatabaseError::NotFound(format!("{} does not exist", other_path.display())));
+    }
+    if other_path == conn.path.as_path() {
+        return Err(DatabaseError::Data("Cannot merge a database into itself".to_string()));
+    }
+    if is_encrypted_file(other_path)? && key.is_none() {
+        return Err(DatabaseError::Data("The other database is encrypted; a passphrase is needed".to_string()));
+    }
+
+    let mut c = conn.get()?;
+
+    // An empty key attaches a plain database
+    c.execute(
+        "ATTACH DATABASE ?1 AS other KEY ?2",
+        params![other_path.to_string_lossy(), key.unwrap_or("")],
+    ).map_err(|e| DatabaseError::Connection(format!("Failed to open the other database: {}", e)))?;
+
+    let result = (|| {
+        let version = schema_version(&c, "main")?;
+        let other_version = schema_version(&c, "other")
+            .map_err(|_| DatabaseError::Schema("The other file is not a history database".to_string()))?;
+        if version != other_version {
+            return Err(DatabaseError::Schema(format!(
+                "Schema versions differ ({} here, {} in the other database); open both in the same app version first",
+                version, other_version,
+            )));
+        }
+
+        let tx = c.transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(DatabaseError::transaction)?;
+        let result = merge_attached(&tx)?;
+        tx.commit().map_err(DatabaseError::transaction)?;
+        Ok(result)
+    })();
+
+    c.execute_batch("DETACH DATABASE other;")?;
+
+    result
+}
@@ -0,0 +1,165 @@
+// URL Details
+// Everything stored about one URL for its detail page: the record, a page of
+// its visits with device and source, metadata, tags and collection notes
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+use super::models::{MetadataRecord, UrlRecord};
+use super::operations::get_metadata_for_url;
+use super::origins::VisitOrigin;
+use super::query::row_error;
+use super::tags::get_url_tags;
+
+/// Visits per page unless the caller asks for another size
+pub const DEFAULT_VISIT_PAGE_SIZE: usize = 100;
+
+/// Most visits per page
+pub const MAX_VISIT_PAGE_SIZE: usize = 1000;
+
+/// One visit of the URL
+#[derive(Debug, Clone, Serialize)]
+pub struct VisitDetail {
+    /// Visit id
+    pub id: String,
+    /// When the visit occurred
+    pub visited_at: DateTime<Utc>,
+    /// Device the visit was recorded on, if known
+    pub device: Option<String>,
+    /// History file the visit was imported from
+    pub source_file: String,
+    /// Import run that added the visit, if it was imported
+    pub import_run_id: Option<i64>,
+    /// Visit duration in seconds, if known
+    pub duration_sec: Option<f64>,
+    /// Page the visit was opened from
+    pub referrer: Option<String>,
+    /// Where the visit came from, once classified
+    pub origin: Option<VisitOrigin>,
+}
+
+/// A page of visits, newest first
+#[derive(Debug, Clone, Serialize)]
+pub struct VisitPage {
+    /// Visits on this page
+    pub visits: Vec<VisitDetail>,
+    /// Total number of visits of the URL
+    pub total_count: usize,
+    /// Zero-based page index
+    pub page: usize,
+    /// Number of visits per page
+    pub page_size: usize,
+}
+
+/// A collection the URL belongs to, with the note written for it there
+#[derive(Debug, Clone, Serialize)]
+pub struct UrlAnnotation {
+    /// Collection id
+    pub collection_id: i64,
+    /// Collection name
+    pub collection: String,
+    /// Note on the URL in this collection
+    pub note: Option<String>,
+    /// When the URL was added to the collection
+    pub added_at: DateTime<Utc>,
+}
+
+/// Stored data about one URL
+#[derive(Debug, Clone, Serialize)]
+pub struct UrlDetails {
+    /// The URL record
+    pub url: UrlRecord,
+    /// Whether the URL is a favorite
+    pub is_favorite: bool,
+    /// Requested page of visits
+    pub visits: VisitPage,
+    /// Enrichment metadata, if any
+    pub metadata: Option<MetadataRecord>,
+    /// Tag names assigned to the URL
+    pub tags: Vec<String>,
+    /// Collections holding the URL, with their notes
+    pub annotations: Vec<UrlAnnotation>,
+}
+
+/// Gets a URL with a page of its visits and everything attached to it
+pub fn get_url_details(conn: &DatabaseConnection, url_id: &str, page: usize, page_size: usize) -> Result<UrlDetails> {
+    let id = Uuid::parse_str(url_id)
+        .map_err(|_| DatabaseError::NotFound(format!("URL {} does not exist", url_id)))?;
+    let page_size = page_size.clamp(1, MAX_VISIT_PAGE_SIZE);
+
+    conn.with_connection(|c| {
+        let (url, is_favorite) = c.query_row(
+            "SELECT id, url, title, domain, first_seen, last_seen, is_favorite FROM url WHERE id = ?",
+            [url_id],
+            |row| Ok((UrlRecord::from_row(row).map_err(row_error)?, row.get::<_, bool>(6)?)),
+        )
+        .optional()?
+        .ok_or_else(|| DatabaseError::NotFound(format!("URL {} does not exist", url_id)))?;
+
+        Ok(UrlDetails {
+            url,
+            is_favorite,
+            visits: visit_page(c, url_id, page, page_size)?,
+            metadata: get_metadata_for_url(c, id)?,
+            tags: get_url_tags(c, url_id)?,
+            annotations: annotations(c, url_id)?,
+        })
+    })
+}
+
+/// Loads one page of the URL's visits, newest first
+fn visit_page(c: &Connection, url_id: &str, page: usize, page_size: usize) -> Result<VisitPage> {
+    let offset = page.checked_mul(page_size)
+        .and_then(|offset| i64::try_from(offset).ok())
+        .ok_or_else(|| DatabaseError::Data(format!("Page {} is out of range", page)))?;
+    let total_count: i64 = c.query_row("SELECT COUNT(*) FROM visit WHERE url_id = ?", [url_id], |row| row.get(0))?;
+
+    let mut stmt = c.prepare_cached(
+        "SELECT v.id, v.visited_at, COALESCE(d.name, v.device_name), v.source_file,
+                v.import_run_id, v.duration_sec, v.referrer, v.origin
+         FROM visit v
+         LEFT JOIN device d ON d.id = v.device_id
+         WHERE v.url_id = ?
+         ORDER BY v.visited_at DESC, v.id
+         LIMIT ? OFFSET ?",
+    )?;
+    let visits = stmt.query_map(params![url_id, page_size as i64, offset], |row| {
+        Ok(VisitDetail {
+            id: row.get(0)?,
+            visited_at: DateTime::from_timestamp(row.get(1)?, 0).unwrap_or_default(),
+            device: row.get(2)?,
+            source_file: row.get(3)?,
+            import_run_id: row.get(4)?,
+            duration_sec: row.get(5)?,
+            referrer: row.get(6)?,
+            origin: row.get::<_, Option<String>>(7)?.as_deref().and_then(VisitOrigin::parse),
+        })
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(VisitPage { visits, total_count: total_count as usize, page, page_size })
+}
+
+/// Lists the collections holding the URL, by name
+fn annotations(c: &Connection, url_id: &str) -> Result<Vec<UrlAnnotation>> {
+    let mut stmt = c.prepare(
+        "SELECT c.id, c.name, i.note, i.added_at
+         FROM collection_item i
+         JOIN collection c ON c.id = i.collection_id
+         WHERE i.url_id = ?
+         ORDER BY c.name COLLATE NOCASE",
+    )?;
+    let rows = stmt.query_map([url_id], |row| {
+        Ok(UrlAnnotation {
+            collection_id: row.get(0)?,
+            collection: row.get(1)?,
+            note: row.get(2)?,
+            added_at: DateTime::from_timestamp(row.get(3)?, 0).unwrap_or_default(),
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
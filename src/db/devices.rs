@@ -0,0 +1,157 @@
+// Device Registry
+// Devices visits were imported from, with renaming and merging of duplicates
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+
+/// A device and the number of visits recorded on it
+#[derive(Debug, Clone, Serialize)]
+pub struct Device {
+    /// Device identifier
+    pub id: i64,
+    /// Display name (unique, case-insensitive)
+    pub name: String,
+    /// macos, ios or ipados, when known
+    pub platform: Option<String>,
+    /// First import that included the device
+    pub first_imported_at: Option<DateTime<Utc>>,
+    /// Latest import that included the device
+    pub last_imported_at: Option<DateTime<Utc>>,
+    /// Number of visits on the device
+    pub visit_count: usize,
+}
+
+/// Trims a device name and collapses inner whitespace, rejecting empty names
+fn normalize_name(name: &str) -> Result<String> {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return Err(DatabaseError::Data("Device name cannot be empty".to_string()));
+    }
+    Ok(name)
+}
+
+/// Guesses the platform from the name Safari sync gives a device
+fn guess_platform(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    if name.contains("iphone") {
+        Some("ios")
+    } else if name.contains("ipad") {
+        Some("ipados")
+    } else if name.contains("mac") {
+        Some("macos")
+    } else {
+        None
+    }
+}
+
+/// Maps a device row selected by `SELECT_DEVICE`
+fn device_from_row(row: &rusqlite::Row) -> rusqlite::Result<Device> {
+    Ok(Device {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        platform: row.get(2)?,
+        first_imported_at: row.get::<_, Option<i64>>(3)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+        last_imported_at: row.get::<_, Option<i64>>(4)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+        visit_count: row.get::<_, i64>(5)? as usize,
+    })
+}
+
+/// Columns of a device with its visit count
+const SELECT_DEVICE: &str =
+    "SELECT d.id, d.name, d.platform, d.first_imported_at, d.last_imported_at,
+            (SELECT COUNT(*) FROM visit v WHERE v.device_id = d.id)
+     FROM device d";
+
+/// Loads a single device
+fn get_device(c: &Connection, id: i64) -> Result<Device> {
+    c.query_row(&format!("{} WHERE d.id = ?", SELECT_DEVICE), [id], device_from_row)
+        .optional()?
+        .ok_or_else(|| DatabaseError::NotFound(format!("Device {} does not exist", id)))
+}
+
+/// Returns the id of the device with the given name, registering it if needed.
+/// `imported_at` extends the device's import range when the name comes from an import.
+pub(crate) fn register_device(c: &Connection, name: &str, imported_at: Option<i64>) -> Result<i64> {
+    let name = normalize_name(name)?;
+
+    Ok(c.query_row(
+        "INSERT INTO device (name, platform, first_imported_at, last_imported_at) VALUES (?1, ?2, ?3, ?3)
+         ON CONFLICT (name) DO UPDATE SET
+             first_imported_at = COALESCE(MIN(first_imported_at, excluded.first_imported_at), first_imported_at, excluded.first_imported_at),
+             last_imported_at = COALESCE(MAX(last_imported_at, excluded.last_imported_at), last_imported_at, excluded.last_imported_at)
+         RETURNING id",
+        params![name, guess_platform(&name), imported_at],
+        |row| row.get(0),
+    )?)
+}
+
+/// Lists every device, most visits first
+pub fn list_devices(conn: &DatabaseConnection) -> Result<Vec<Device>> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(&format!("{} ORDER BY 6 DESC, d.name COLLATE NOCASE", SELECT_DEVICE))?;
+        let rows = stmt.query_map([], device_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
+
+/// Renames a device and its visits; fails if another device already has the
+/// name (merge them instead)
+pub fn rename_device(conn: &DatabaseConnection, id: i64, name: &str) -> Result<Device> {
+    let name = normalize_name(name)?;
+
+    conn.transaction(|tx| {
+        let existing: Option<i64> = tx.query_row(
+            "SELECT id FROM device WHERE name = ? AND id != ?",
+            params![name, id],
+            |row| row.get(0),
+        ).optional()?;
+        if existing.is_some() {
+            return Err(DatabaseError::Data(format!("A device named '{}' already exists", name)));
+        }
+
+        if tx.execute("UPDATE device SET name = ? WHERE id = ?", params![name, id])? == 0 {
+            return Err(DatabaseError::NotFound(format!("Device {} does not exist", id)));
+        }
+        tx.execute("UPDATE visit SET device_name = ? WHERE device_id = ?", params![name, id])?;
+
+        get_device(tx, id)
+    })
+}
+
+/// Moves every visit of `source_ids` to `target_id` and deletes the source devices
+pub fn merge_devices(conn: &DatabaseConnection, source_ids: &[i64], target_id: i64) -> Result<Device> {
+    conn.transaction(|tx| {
+        // Fail early if the target doesn't exist
+        let target = get_device(tx, target_id)?;
+
+        for source_id in source_ids.iter().filter(|id| **id != target_id) {
+            let source = get_device(tx, *source_id)?;
+
+            // The target keeps its own platform and takes the widest import range
+            tx.execute(
+                "UPDATE device SET
+                     platform = COALESCE(platform, ?1),
+                     first_imported_at = COALESCE(MIN(first_imported_at, ?2), first_imported_at, ?2),
+                     last_imported_at = COALESCE(MAX(last_imported_at, ?3), last_imported_at, ?3)
+                 WHERE id = ?4",
+                params![
+                    source.platform,
+                    source.first_imported_at.map(|at| at.timestamp()),
+                    source.last_imported_at.map(|at| at.timestamp()),
+                    target_id,
+                ],
+            )?;
+            tx.execute(
+                "UPDATE visit SET device_id = ?, device_name = ? WHERE device_id = ?",
+                params![target_id, target.name, source_id],
+            )?;
+            tx.execute("DELETE FROM device WHERE id = ?", [source_id])?;
+        }
+
+        get_device(tx, target_id)
+    })
+}
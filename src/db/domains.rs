@@ -0,0 +1,375 @@
+// Domain Aggregates
+// Per-domain counts kept in the domain_stats table, and the detail view of
+// one domain with its registration and hosting provenance
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+use super::query::QueryBuilder;
+use super::organizations::refresh_organizations;
+use super::trackers::{refresh_tracker_flags, stats_exclusion, TrackerKind};
+
+/// Default number of domains returned by `list_domains`
+pub const DEFAULT_DOMAIN_PAGE: usize = 100;
+
+/// Aggregates of one domain
+#[derive(Debug, Clone, Serialize)]
+pub struct DomainStats {
+    /// The domain
+    pub domain: String,
+    /// Number of URLs on the domain
+    pub url_count: usize,
+    /// Number of visits to the domain
+    pub visit_count: usize,
+    /// Earliest visit
+    pub first_visit: Option<DateTime<Utc>>,
+    /// Latest visit
+    pub last_visit: Option<DateTime<Utc>>,
+    /// Most common category of the domain's URLs
+    pub category: Option<String>,
+    /// Favicon address
+    pub favicon: Option<String>,
+    /// Ad network or tracker, when a blocklist lists the domain
+    pub tracker: Option<TrackerKind>,
+    /// Organization owning the domain, when known
+    pub organization: Option<String>,
+}
+
+/// Public suffixes of more than one label; the registrable domain under them
+/// keeps one more label, e.g. bbc.co.uk rather than co.uk
+const MULTI_LABEL_SUFFIXES: &[&str] = &[
+    "co.uk", "org.uk", "ac.uk", "gov.uk", "me.uk", "com.au", "net.au", "org.au", "edu.au",
+    "gov.au", "co.nz", "org.nz", "co.jp", "ne.jp", "or.jp", "ac.jp", "com.br", "com.cn",
+    "com.mx", "com.ar", "com.tr", "com.sg", "com.hk", "com.tw", "co.in", "co.kr", "co.za",
+    "co.il", "com.pt", "github.io", "blogspot.com", "herokuapp.com", "netlify.app", "vercel.app",
+];
+
+/// Returns the domain a host was registered under: the public suffix plus one
+/// label, e.g. news.bbc.co.uk -> bbc.co.uk. IP addresses are returned as is.
+pub fn registrable_domain(host: &str) -> String {
+    let host = host.trim_end_matches('.').to_lowercase();
+    if host.parse::<std::net::IpAddr>().is_ok() {
+        return host;
+    }
+
+    let labels: Vec<&str> = host.split('.').collect();
+    let suffix_labels = match labels.len() {
+        0..=2 => return host,
+        n if MULTI_LABEL_SUFFIXES.contains(&labels[n - 2..].join(".").as_str()) => 2,
+        _ => 1,
+    };
+    let keep = (suffix_labels + 1).min(labels.len());
+    labels[labels.len() - keep..].join(".")
+}
+
+/// Default number of top pages in `get_domain_details`
+pub const DEFAULT_DOMAIN_TOP_PAGES: usize = 20;
+
+/// Sort order of the domain list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainOrder {
+    /// Most visited first
+    #[default]
+    Visits,
+    /// Most recently visited first
+    Recent,
+    /// Most URLs first
+    Urls,
+    /// Alphabetical
+    Name,
+}
+
+impl DomainOrder {
+    /// ORDER BY clause for the order
+    fn clause(self) -> &'static str {
+        match self {
+            DomainOrder::Visits => "visit_count DESC, domain",
+            DomainOrder::Recent => "last_visit DESC, domain",
+            DomainOrder::Urls => "url_count DESC, domain",
+            DomainOrder::Name => "domain",
+        }
+    }
+}
+
+/// Recomputes the domains whose URLs or visits changed since the last refresh
+/// and drops domains without URLs, then checks new domains against the
+/// blocklists and organization mappings. Returns the number of domains refreshed.
+pub(crate) fn refresh_domain_stats(c: &Connection) -> Result<usize> {
+    let refreshed = c.execute(
+        "UPDATE domain_stats SET
+             url_count = (SELECT COUNT(*) FROM url u WHERE u.domain = domain_stats.domain),
+             visit_count = (SELECT COUNT(*) FROM url u JOIN visit v ON v.url_id = u.id WHERE u.domain = domain_stats.domain),
+             first_visit = (SELECT MIN(v.visited_at) FROM url u JOIN visit v ON v.url_id = u.id WHERE u.domain = domain_stats.domain),
+             last_visit = (SELECT MAX(v.visited_at) FROM url u JOIN visit v ON v.url_id = u.id WHERE u.domain = domain_stats.domain),
+             category = (
+                 SELECT u.category FROM url u
+                 WHERE u.domain = domain_stats.domain AND u.category IS NOT NULL
+                 GROUP BY u.category ORDER BY COUNT(*) DESC LIMIT 1
+             ),
+             favicon = COALESCE(favicon, 'https://' || domain || '/favicon.ico'),
+             dirty = 0,
+             updated_at = ?
+         WHERE dirty = 1",
+        params![Utc::now().timestamp()],
+    )?;
+
+    if refreshed > 0 {
+        c.execute("DELETE FROM domain_stats WHERE url_count = 0", [])?;
+    }
+    refresh_tracker_flags(c)?;
+    refresh_organizations(c)?;
+
+    Ok(refreshed)
+}
+
+/// Maps a domain_stats row
+fn domain_from_row(row: &rusqlite::Row) -> rusqlite::Result<DomainStats> {
+    Ok(DomainStats {
+        domain: row.get(0)?,
+        url_count: row.get::<_, i64>(1)? as usize,
+        visit_count: row.get::<_, i64>(2)? as usize,
+        first_visit: row.get::<_, Option<i64>>(3)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+        last_visit: row.get::<_, Option<i64>>(4)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+        category: row.get(5)?,
+        favicon: row.get(6)?,
+        tracker: row.get::<_, Option<String>>(7)?.as_deref().and_then(TrackerKind::parse),
+        organization: row.get(8)?,
+    })
+}
+
+/// Lists domains with their aggregates, optionally only those matching `filter`
+pub fn list_domains(
+    conn: &DatabaseConnection,
+    filter: Option<&str>,
+    order: DomainOrder,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<DomainStats>> {
+    conn.with_connection(|c| {
+        refresh_domain_stats(c)?;
+
+        let mut query = QueryBuilder::new(
+            "SELECT domain, url_count, visit_count, first_visit, last_visit, category, favicon, tracker, organization
+             FROM domain_stats"
+        );
+        if let Some(filter) = filter.map(str::trim).filter(|f| !f.is_empty()) {
+            query.filter("domain LIKE ?", format!("%{}%", filter));
+        }
+        query.order_by(order.clause())
+            .limit(limit)
+            .offset(offset);
+
+        query.fetch_all(c, domain_from_row)
+    })
+}
+
+/// A URL of a domain with its visit aggregates
+#[derive(Debug, Clone, Serialize)]
+pub struct DomainUrl {
+    /// URL identifier
+    pub id: String,
+    /// The URL
+    pub url: String,
+    /// Page title
+    pub title: Option<String>,
+    /// Category of the URL
+    pub category: Option<String>,
+    /// Number of visits to the URL
+    pub visit_count: usize,
+    /// Latest visit
+    pub last_visit: Option<DateTime<Utc>>,
+}
+
+/// Calls `f` with every URL of a domain, most visited first, until it returns
+/// false; returns how many URLs it was given
+pub fn for_each_domain_url(
+    conn: &DatabaseConnection,
+    domain: &str,
+    mut f: impl FnMut(DomainUrl) -> bool,
+) -> Result<usize> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare_cached(
+            "SELECT u.id, u.url, u.title, u.category, COUNT(v.id) AS visit_count, MAX(v.visited_at) AS last_visit
+             FROM url u
+             LEFT JOIN visit v ON v.url_id = u.id
+             WHERE u.domain = ?
+             GROUP BY u.id
+             ORDER BY visit_count DESC, last_visit DESC, u.id"
+        )?;
+        let mut rows = stmt.query([domain])?;
+        let mut given = 0;
+        while let Some(row) = rows.next()? {
+            given += 1;
+            let url = DomainUrl {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                title: row.get(2)?,
+                category: row.get(3)?,
+                visit_count: row.get::<_, i64>(4)? as usize,
+                last_visit: row.get::<_, Option<i64>>(5)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+            };
+            if !f(url) {
+                break;
+            }
+        }
+        Ok(given)
+    })
+}
+
+/// Gets the most visited domains of all time from the aggregates, without
+/// trackers when the user excludes them from stats
+pub(crate) fn top_domains(c: &Connection, limit: usize) -> Result<Vec<(String, usize)>> {
+    refresh_domain_stats(c)?;
+
+    let trackers = match stats_exclusion(c)? {
+        Some(_) => "AND tracker IS NULL",
+        None => "",
+    };
+    let mut stmt = c.prepare(&format!(
+        "SELECT domain, visit_count FROM domain_stats
+         WHERE visit_count > 0 {}
+         ORDER BY visit_count DESC, domain
+         LIMIT ?",
+        trackers,
+    ))?;
+    let rows = stmt.query_map([limit as i64], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+/// Length of the periods a domain's visit trend is counted in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrendInterval {
+    /// Calendar days
+    Day,
+    /// Weeks starting on Monday
+    #[default]
+    Week,
+    /// Calendar months
+    Month,
+}
+
+impl TrendInterval {
+    /// SQL expression giving the first day of the period of `v.visited_at`
+    fn period_expr(self) -> &'static str {
+        match self {
+            TrendInterval::Day => "date(v.visited_at, 'unixepoch')",
+            TrendInterval::Week => "date(v.visited_at, 'unixepoch', 'weekday 0', '-6 days')",
+            TrendInterval::Month => "date(v.visited_at, 'unixepoch', 'start of month')",
+        }
+    }
+}
+
+/// Visits to a domain in one period
+#[derive(Debug, Clone, Serialize)]
+pub struct TrendPoint {
+    /// First day of the period, as YYYY-MM-DD
+    pub period: String,
+    /// Number of visits in the period
+    pub visits: usize,
+}
+
+/// Who registered a domain and where it is hosted
+#[derive(Debug, Clone, Serialize)]
+pub struct DomainProvenance {
+    /// Domain the registration belongs to, e.g. bbc.co.uk for news.bbc.co.uk
+    pub registrable_domain: String,
+    /// Registrar the domain was registered with
+    pub registrar: Option<String>,
+    /// When the domain was first registered
+    pub registered_at: Option<DateTime<Utc>>,
+    /// Address the domain resolved to
+    pub ip_address: Option<String>,
+    /// ISO 3166 code of the country the address is registered in
+    pub country: Option<String>,
+    /// When the domain was looked up
+    pub checked_at: DateTime<Utc>,
+    /// Why the last lookup failed, if it did
+    pub error: Option<String>,
+}
+
+/// Gets the stored provenance of a domain; None until the domain enrichment
+/// job looked it up
+pub fn domain_provenance(c: &Connection, domain: &str) -> Result<Option<DomainProvenance>> {
+    Ok(c.query_row(
+        "SELECT registrable_domain, registrar, registered_at, ip_address, country, checked_at, error
+         FROM domain_provenance WHERE domain = ?",
+        [domain],
+        |row| Ok(DomainProvenance {
+            registrable_domain: row.get(0)?,
+            registrar: row.get(1)?,
+            registered_at: row.get::<_, Option<i64>>(2)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+            ip_address: row.get(3)?,
+            country: row.get(4)?,
+            checked_at: DateTime::from_timestamp(row.get(5)?, 0).unwrap_or_default(),
+            error: row.get(6)?,
+        }),
+    ).optional()?)
+}
+
+/// Aggregates, visit trend and most visited pages of one domain
+#[derive(Debug, Clone, Serialize)]
+pub struct DomainDetails {
+    /// Counts, first and last visit, category and favicon
+    #[serde(flatten)]
+    pub stats: DomainStats,
+    /// Interval of the trend periods
+    pub interval: TrendInterval,
+    /// Visits per period, oldest first; periods without visits are left out
+    pub trend: Vec<TrendPoint>,
+    /// Most visited URLs of the domain
+    pub top_pages: Vec<DomainUrl>,
+    /// Registrar, registration date and hosting country, once looked up
+    pub provenance: Option<DomainProvenance>,
+}
+
+/// Gets the detail view of a domain
+pub fn get_domain_details(
+    conn: &DatabaseConnection,
+    domain: &str,
+    interval: TrendInterval,
+    top_pages: usize,
+) -> Result<DomainDetails> {
+    let (stats, trend, provenance) = conn.with_connection(|c| {
+        refresh_domain_stats(c)?;
+
+        let stats = c.query_row(
+            "SELECT domain, url_count, visit_count, first_visit, last_visit, category, favicon, tracker, organization
+             FROM domain_stats WHERE domain = ?",
+            [domain],
+            domain_from_row,
+        )
+        .optional()?
+        .ok_or_else(|| DatabaseError::NotFound(format!("Domain {} does not exist", domain)))?;
+
+        let mut stmt = c.prepare(&format!(
+            "SELECT {period} AS period, COUNT(*)
+             FROM visit v
+             JOIN url u ON u.id = v.url_id
+             WHERE u.domain = ?
+             GROUP BY period
+             ORDER BY period",
+            period = interval.period_expr(),
+        ))?;
+        let trend = stmt.query_map([domain], |row| {
+            Ok(TrendPoint { period: row.get(0)?, visits: row.get::<_, i64>(1)? as usize })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok((stats, trend, domain_provenance(c, domain)?))
+    })?;
+
+    let mut pages = Vec::new();
+    if top_pages > 0 {
+        for_each_domain_url(conn, domain, |url| {
+            pages.push(url);
+            pages.len() < top_pages
+        })?;
+    }
+
+    Ok(DomainDetails { stats, interval, trend, top_pages: pages, provenance })
+}
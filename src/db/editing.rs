@@ -0,0 +1,238 @@
+// Manual Editing
+// User corrections to URLs and visits, logged so imports never silently undo them
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::Serialize;
+use url::Url as UrlParser;
+use uuid::Uuid;
+
+use super::connection::DatabaseConnection;
+use super::devices::register_device;
+use super::error::{DatabaseError, Result};
+use super::journal::{capture, finish_operation, placeholders, start_operation};
+
+/// Source file recorded for visits added by hand
+pub const MANUAL_SOURCE: &str = "manual";
+
+/// A logged edit of a URL or one of its visits
+#[derive(Debug, Clone, Serialize)]
+pub struct UrlEdit {
+    /// Edit identifier
+    pub id: i64,
+    /// Edited URL id
+    pub url_id: String,
+    /// What changed: title, domain, visit_deleted, visit_added or merged
+    pub field: String,
+    /// Value before the edit
+    pub old_value: Option<String>,
+    /// Value after the edit
+    pub new_value: Option<String>,
+    /// When the edit was made
+    pub edited_at: DateTime<Utc>,
+}
+
+/// Appends an entry to the edit log
+fn log_edit(c: &Connection, url_id: &str, field: &str, old_value: Option<&str>, new_value: Option<&str>) -> Result<()> {
+    c.execute(
+        "INSERT INTO url_edit (url_id, field, old_value, new_value, edited_at) VALUES (?, ?, ?, ?, ?)",
+        params![url_id, field, old_value, new_value, Utc::now().timestamp()],
+    )?;
+    Ok(())
+}
+
+/// Reads a text column of a URL, failing if the URL doesn't exist
+fn url_field(c: &Connection, url_id: &str, column: &str) -> Result<Option<String>> {
+    c.query_row(&format!("SELECT {} FROM url WHERE id = ?", column), [url_id], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| DatabaseError::NotFound(format!("URL {} does not exist", url_id)))
+}
+
+/// Sets the title of a URL (an empty title clears it)
+pub fn update_url_title(conn: &DatabaseConnection, url_id: &str, title: &str) -> Result<()> {
+    let title = Some(title.trim()).filter(|t| !t.is_empty());
+
+    conn.transaction(|tx| {
+        let old = url_field(tx, url_id, "title")?;
+        if old.as_deref() == title {
+            return Ok(());
+        }
+
+        tx.execute("UPDATE url SET title = ? WHERE id = ?", params![title, url_id])?;
+        log_edit(tx, url_id, "title", old.as_deref(), title)
+    })
+}
+
+/// Corrects the domain of a URL; its category is cleared so the rules run again
+pub fn update_url_domain(conn: &DatabaseConnection, url_id: &str, domain: &str) -> Result<()> {
+    let domain = domain.trim().to_lowercase();
+    if domain.is_empty() || domain.contains(char::is_whitespace) || domain.contains('/') {
+        return Err(DatabaseError::Data(format!("Invalid domain: {}", domain)));
+    }
+
+    conn.transaction(|tx| {
+        let old = url_field(tx, url_id, "domain")?;
+        if old.as_deref() == Some(domain.as_str()) {
+            return Ok(());
+        }
+
+        tx.execute("UPDATE url SET domain = ?, category = NULL WHERE id = ?", params![domain, url_id])?;
+        log_edit(tx, url_id, "domain", old.as_deref(), Some(&domain))
+    })
+}
+
+/// Deletes visits, leaving tombstones so later imports skip them; returns the
+/// number of deleted visits
+pub fn delete_visits(conn: &DatabaseConnection, visit_ids: &[String]) -> Result<usize> {
+    if visit_ids.is_empty() {
+        return Ok(0);
+    }
+
+    conn.transaction(|tx| {
+        let ids: Vec<Value> = visit_ids.iter().map(|id| Value::Text(id.clone())).collect();
+
+        // Tombstones are keyed by URL and time, which must be looked up before deleting
+        let keys: Vec<Value> = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT u.url, v.visited_at FROM visit v JOIN url u ON u.id = v.url_id WHERE v.id IN ({})",
+                placeholders(ids.len()),
+            ))?;
+            let rows = stmt.query_map(params_from_iter(ids.iter()), |row| Ok([row.get::<_, Value>(0)?, row.get::<_, Value>(1)?]))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?.into_iter().flatten().collect()
+        };
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        let operation_id = start_operation(tx, "delete_visits", &format!("Deleted {} visits", keys.len() / 2))?;
+        capture(tx, operation_id, "visit", &format!("id IN ({})", placeholders(ids.len())), ids)?;
+        capture(
+            tx,
+            operation_id,
+            "visit_tombstone",
+            &format!("(url, visited_at) IN (VALUES {})", vec!["(?, ?)"; keys.len() / 2].join(", ")),
+            keys,
+        )?;
+
+        let now = Utc::now().timestamp();
+        let mut deleted = 0;
+
+        for visit_id in visit_ids {
+            let visit: Option<(String, String, i64)> = tx.query_row(
+                "SELECT v.url_id, u.url, v.visited_at FROM visit v JOIN url u ON u.id = v.url_id WHERE v.id = ?",
+                [visit_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            ).optional()?;
+
+            let (url_id, url, visited_at) = match visit {
+                Some(visit) => visit,
+                None => continue,
+            };
+
+            tx.execute("DELETE FROM visit WHERE id = ?", [visit_id])?;
+            tx.execute(
+                "INSERT OR REPLACE INTO visit_tombstone (url, visited_at, deleted_at) VALUES (?, ?, ?)",
+                params![url, visited_at, now],
+            )?;
+
+            let when = DateTime::from_timestamp(visited_at, 0).map(|at| at.to_rfc3339());
+            log_edit(tx, &url_id, "visit_deleted", when.as_deref(), None)?;
+            deleted += 1;
+        }
+
+        finish_operation(tx, operation_id)?;
+        Ok(deleted)
+    })
+}
+
+/// Records a visit made outside the browser (e.g. reading offline), creating
+/// the URL if it isn't in the history yet; returns the URL id
+pub fn add_manual_visit(
+    conn: &DatabaseConnection,
+    url: &str,
+    title: Option<&str>,
+    visited_at: DateTime<Utc>,
+    duration_sec: Option<f64>,
+    device_name: Option<&str>,
+) -> Result<String> {
+    let parsed = UrlParser::parse(url.trim())
+        .map_err(|e| DatabaseError::Data(format!("Invalid URL {}: {}", url, e)))?;
+    let domain = parsed.host_str()
+        .ok_or_else(|| DatabaseError::Data(format!("URL has no host: {}", url)))?
+        .to_string();
+    let url = parsed.to_string();
+    let title = title.map(str::trim).filter(|t| !t.is_empty());
+    let timestamp = visited_at.timestamp();
+
+    conn.transaction(|tx| {
+        let existing: Option<String> = tx.query_row("SELECT id FROM url WHERE url = ?", [&url], |row| row.get(0)).optional()?;
+
+        let url_id = match existing {
+            Some(url_id) => {
+                tx.execute(
+                    "UPDATE url SET first_seen = MIN(first_seen, ?1), last_seen = MAX(last_seen, ?1),
+                         title = COALESCE(title, ?2)
+                     WHERE id = ?3",
+                    params![timestamp, title, url_id],
+                )?;
+                url_id
+            },
+            None => {
+                let url_id = Uuid::new_v4().to_string();
+                tx.execute(
+                    "INSERT INTO url (id, url, title, domain, first_seen, last_seen) VALUES (?, ?, ?, ?, ?, ?)",
+                    params![url_id, url, title, domain, timestamp, timestamp],
+                )?;
+                tx.execute(
+                    "INSERT INTO metadata (url_id, is_enriched) VALUES (?, 0)",
+                    [&url_id],
+                )?;
+                url_id
+            },
+        };
+
+        let device_id = device_name.map(|name| register_device(tx, name, None)).transpose()?;
+        tx.execute(
+            "INSERT INTO visit (id, url_id, visited_at, visit_count, source_file, device_name, device_id, duration_sec)
+             VALUES (?, ?, ?, 1, ?, ?, ?, ?)",
+            params![Uuid::new_v4().to_string(), url_id, timestamp, MANUAL_SOURCE, device_name, device_id, duration_sec],
+        )?;
+
+        // A manual visit at a deleted time replaces the deletion
+        tx.execute("DELETE FROM visit_tombstone WHERE url = ? AND visited_at = ?", params![url, timestamp])?;
+
+        log_edit(tx, &url_id, "visit_added", None, Some(&visited_at.to_rfc3339()))?;
+        Ok(url_id)
+    })
+}
+
+/// Returns true if the visit was deleted by the user and must not be re-imported
+pub fn is_tombstoned(c: &Connection, url: &str, visited_at: i64) -> Result<bool> {
+    Ok(c.query_row(
+        "SELECT EXISTS(SELECT 1 FROM visit_tombstone WHERE url = ? AND visited_at = ?)",
+        params![url, visited_at],
+        |row| row.get(0),
+    )?)
+}
+
+/// Gets the edit log of a URL, newest first
+pub fn get_url_edits(conn: &DatabaseConnection, url_id: &str) -> Result<Vec<UrlEdit>> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT id, url_id, field, old_value, new_value, edited_at
+             FROM url_edit WHERE url_id = ? ORDER BY edited_at DESC, id DESC"
+        )?;
+        let rows = stmt.query_map([url_id], |row| {
+            Ok(UrlEdit {
+                id: row.get(0)?,
+                url_id: row.get(1)?,
+                field: row.get(2)?,
+                old_value: row.get(3)?,
+                new_value: row.get(4)?,
+                edited_at: DateTime::from_timestamp(row.get(5)?, 0).unwrap_or_default(),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
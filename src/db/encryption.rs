@@ -0,0 +1,174 @@
+// Database Encryption
+// Optional SQLCipher encryption at rest, with the passphrase kept in the OS keychain
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+
+use super::error::{DatabaseError, Result};
+
+/// Keychain service the passphrase is stored under
+const KEYCHAIN_SERVICE: &str = "safari-history-knowledge-graph";
+
+/// Keychain account the passphrase is stored under
+const KEYCHAIN_ACCOUNT: &str = "database-key";
+
+/// Header every unencrypted SQLite file starts with
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Encryption state of the database
+#[derive(Debug, Clone, Serialize)]
+pub struct EncryptionStatus {
+    /// The database file is encrypted
+    pub encrypted: bool,
+    /// A passphrase is stored in the OS keychain
+    pub key_in_keychain: bool,
+}
+
+/// Returns true if the file exists and is not a plain SQLite database
+pub fn is_encrypted_file(path: &Path) -> Result<bool> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut header = [0u8; 16];
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(&header != SQLITE_HEADER),
+        // A new, still empty database
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Gets the status shown in the settings screen
+pub fn encryption_status(path: &Path) -> Result<EncryptionStatus> {
+    Ok(EncryptionStatus {
+        encrypted: is_encrypted_file(path)?,
+        key_in_keychain: stored_key()?.is_some(),
+    })
+}
+
+/// Opens the keychain entry holding the passphrase
+fn keychain_entry() -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT)
+        .map_err(|e| DatabaseError::Other(format!("Keychain error: {}", e)))
+}
+
+/// Reads the passphrase from the OS keychain
+pub fn stored_key() -> Result<Option<String>> {
+    match keychain_entry()?.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(DatabaseError::Other(format!("Keychain error: {}", e))),
+    }
+}
+
+/// Saves the passphrase in the OS keychain
+pub fn store_key(key: &str) -> Result<()> {
+    keychain_entry()?.set_password(key)
+        .map_err(|e| DatabaseError::Other(format!("Keychain error: {}", e)))
+}
+
+/// Removes the passphrase from the OS keychain
+pub fn delete_key() -> Result<()> {
+    match keychain_entry()?.delete_password() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(DatabaseError::Other(format!("Keychain error: {}", e))),
+    }
+}
+
+/// Applies the passphrase to a freshly opened connection and checks it
+pub(crate) fn apply_key(conn: &Connection, key: &str) -> Result<()> {
+    conn.pragma_update(None, "key", key)
+        .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+
+    // SQLCipher only notices a wrong key when the first page is read
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))
+        .map_err(|_| DatabaseError::BadKey("Wrong passphrase or not a database".to_string()))?;
+
+    Ok(())
+}
+
+/// Path of a sibling file used while converting the database
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Removes the WAL and shared-memory files left next to a database
+fn remove_wal_files(path: &Path) -> Result<()> {
+    for suffix in ["-wal", "-shm"] {
+        match fs::remove_file(sibling(path, suffix)) {
+            Ok(()) => {},
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Copies the database at `path` into a new file with a different key (an empty
+/// key produces a plain database), then swaps it into place. The database must
+/// not be open elsewhere while this runs.
+fn export_database(path: &Path, current_key: Option<&str>, new_key: &str) -> Result<()> {
+    let converted = sibling(path, ".converting");
+    let backup = sibling(path, ".backup");
+    let _ = fs::remove_file(&converted);
+
+    {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+            .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+        if let Some(key) = current_key {
+            apply_key(&conn, key)?;
+        }
+
+        // Fold the WAL into the main file so the export sees everything
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        conn.execute(
+            "ATTACH DATABASE ?1 AS converted KEY ?2",
+            [converted.to_string_lossy().as_ref(), new_key],
+        )?;
+        conn.query_row("SELECT sqlcipher_export('converted')", [], |_| Ok(()))?;
+        conn.execute_batch("DETACH DATABASE converted;")?;
+    }
+
+    // Keep the original until the new file is in place
+    fs::rename(path, &backup)?;
+    if let Err(e) = fs::rename(&converted, path) {
+        fs::rename(&backup, path)?;
+        return Err(e.into());
+    }
+    remove_wal_files(path)?;
+    fs::remove_file(&backup)?;
+
+    Ok(())
+}
+
+/// Encrypts a plain database with the passphrase and stores it in the keychain
+pub fn encrypt_database(path: &Path, passphrase: &str) -> Result<()> {
+    if passphrase.is_empty() {
+        return Err(DatabaseError::Data("Passphrase cannot be empty".to_string()));
+    }
+    if is_encrypted_file(path)? {
+        return Err(DatabaseError::Data("Database is already encrypted".to_string()));
+    }
+
+    export_database(path, None, passphrase)?;
+    store_key(passphrase)
+}
+
+/// Decrypts an encrypted database back to a plain one and forgets the passphrase
+pub fn decrypt_database(path: &Path, passphrase: &str) -> Result<()> {
+    if !is_encrypted_file(path)? {
+        return Err(DatabaseError::Data("Database is not encrypted".to_string()));
+    }
+
+    export_database(path, Some(passphrase), "")?;
+    delete_key()
+}
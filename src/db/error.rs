@@ -5,6 +5,8 @@ use std::fmt;
 use std::error::Error;
 use std::io;
 
+use rusqlite::ErrorCode;
+
 /// Represents errors that can occur during database operations
 #[derive(Debug)]
 pub enum DatabaseError {
@@ -22,6 +24,14 @@ pub enum DatabaseError {
     Migration(String),
     /// Lock error (mutex)
     Lock(String),
+    /// SQLite found the database busy or locked by another connection
+    Busy(String),
+    /// Wrong passphrase, or the file is not a database
+    BadKey(String),
+    /// A record or file the operation refers to does not exist
+    NotFound(String),
+    /// A query stopped by its time limit or cancelled
+    Interrupted(String),
     /// I/O error
     Io(io::Error),
     /// Other database error
@@ -38,6 +48,10 @@ impl fmt::Display for DatabaseError {
             DatabaseError::Schema(msg) => write!(f, "Schema error: {}", msg),
             DatabaseError::Migration(msg) => write!(f, "Migration error: {}", msg),
             DatabaseError::Lock(msg) => write!(f, "Lock error: {}", msg),
+            DatabaseError::Busy(msg) => write!(f, "Database is busy: {}", msg),
+            DatabaseError::BadKey(msg) => write!(f, "{}", msg),
+            DatabaseError::NotFound(msg) => write!(f, "{}", msg),
+            DatabaseError::Interrupted(msg) => write!(f, "{}", msg),
             DatabaseError::Io(err) => write!(f, "I/O error: {}", err),
             DatabaseError::Other(msg) => write!(f, "Database error: {}", msg),
         }
@@ -61,7 +75,22 @@ impl From<io::Error> for DatabaseError {
 
 impl From<rusqlite::Error> for DatabaseError {
     fn from(err: rusqlite::Error) -> Self {
-        DatabaseError::Query(err.to_string())
+        match err.sqlite_error_code() {
+            Some(ErrorCode::DatabaseBusy) | Some(ErrorCode::DatabaseLocked) => DatabaseError::Busy(err.to_string()),
+            Some(ErrorCode::NotADatabase) => DatabaseError::BadKey(err.to_string()),
+            _ => DatabaseError::Query(err.to_string()),
+        }
+    }
+}
+
+impl DatabaseError {
+    /// Converts an error beginning or committing a transaction, keeping busy
+    /// errors apart so callers can retry
+    pub(crate) fn transaction(err: rusqlite::Error) -> Self {
+        match DatabaseError::from(err) {
+            DatabaseError::Query(msg) => DatabaseError::Transaction(msg),
+            other => other,
+        }
     }
 }
 
@@ -0,0 +1,274 @@
+// Import Audit Log
+// Records what every import run loaded, when and from which files
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+use super::settings::get_setting;
+
+/// Default number of import runs returned by the history
+pub const DEFAULT_IMPORT_HISTORY: usize = 50;
+
+/// Settings key of the import options
+pub const IMPORT_SETTING: &str = "import";
+
+/// Rows committed per transaction unless configured
+pub const DEFAULT_IMPORT_BATCH_SIZE: usize = 5000;
+
+/// How imports write to the database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportSettings {
+    /// URLs or visits committed per transaction; smaller batches let readers
+    /// in sooner and lose less to a crash, larger ones import faster
+    pub batch_size: usize,
+}
+
+impl Default for ImportSettings {
+    fn default() -> Self {
+        ImportSettings { batch_size: DEFAULT_IMPORT_BATCH_SIZE }
+    }
+}
+
+/// Gets the import options
+pub fn get_import_settings(conn: &DatabaseConnection) -> Result<ImportSettings> {
+    Ok(conn.with_connection(|c| get_setting(c, IMPORT_SETTING))?.unwrap_or_default())
+}
+
+/// Rows of one file an import run has committed
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FileProgress {
+    /// URLs extracted from the file
+    pub urls_total: usize,
+    /// Visits extracted from the file
+    pub visits_total: usize,
+    /// URLs committed
+    pub urls_done: usize,
+    /// Visits committed
+    pub visits_done: usize,
+}
+
+/// Records the rows of a file committed so far; called inside the
+/// transaction committing them, so the record never runs ahead of the data
+pub fn record_progress(c: &Connection, run_id: i64, path: &str, progress: &FileProgress) -> Result<()> {
+    c.prepare_cached(
+        "INSERT INTO import_progress (import_run_id, path, urls_total, visits_total, urls_done, visits_done, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (import_run_id, path) DO UPDATE SET
+             urls_done = ?5, visits_done = ?6, updated_at = ?7",
+    )?.execute(params![
+        run_id,
+        path,
+        progress.urls_total as i64,
+        progress.visits_total as i64,
+        progress.urls_done as i64,
+        progress.visits_done as i64,
+        Utc::now().timestamp(),
+    ])?;
+    Ok(())
+}
+
+/// Progress of the latest interrupted run before `run_id` that imported
+/// `path`, so a new run of the same file can pick up where it stopped
+pub fn interrupted_progress(conn: &DatabaseConnection, path: &str, run_id: i64) -> Result<Option<FileProgress>> {
+    conn.with_connection(|c| {
+        Ok(c.query_row(
+            "SELECT p.urls_total, p.visits_total, p.urls_done, p.visits_done
+             FROM import_progress p
+             JOIN import_run r ON r.id = p.import_run_id
+             WHERE p.path = ? AND r.id < ? AND r.finished_at IS NULL AND r.rolled_back_at IS NULL
+             ORDER BY r.id DESC LIMIT 1",
+            params![path, run_id],
+            |row| Ok(FileProgress {
+                urls_total: row.get::<_, i64>(0)? as usize,
+                visits_total: row.get::<_, i64>(1)? as usize,
+                urls_done: row.get::<_, i64>(2)? as usize,
+                visits_done: row.get::<_, i64>(3)? as usize,
+            }),
+        ).optional()?)
+    })
+}
+
+/// A history file read by an import run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportFile {
+    /// Path of the history file
+    pub path: String,
+    /// Device the file came from, if named
+    pub device_name: Option<String>,
+    /// URLs extracted from the file
+    pub urls: usize,
+    /// Visits extracted from the file
+    pub visits: usize,
+    /// Why the file could not be read
+    pub error: Option<String>,
+}
+
+/// A recorded import run
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportRun {
+    /// Run identifier
+    pub id: i64,
+    /// When the run started
+    pub started_at: DateTime<Utc>,
+    /// When the run finished (None if it was interrupted)
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Total processing time
+    pub duration_sec: Option<f64>,
+    /// Files read by the run
+    pub files: Vec<ImportFile>,
+    /// New URLs added to the history
+    pub urls_inserted: usize,
+    /// New visits added to the history
+    pub visits_inserted: usize,
+    /// Extraction warnings and insertion errors
+    pub warnings: Vec<String>,
+    /// When the run was rolled back, if it was
+    pub rolled_back_at: Option<DateTime<Utc>>,
+}
+
+/// What rolling back an import run removed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RollbackResult {
+    /// Visits removed
+    pub visits_removed: usize,
+    /// URLs removed (URLs with visits from other sources are kept)
+    pub urls_removed: usize,
+}
+
+/// Serializes a value for a JSON column
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| DatabaseError::Data(e.to_string()))
+}
+
+/// Records the start of an import run, returning its id
+pub fn start_import_run(conn: &DatabaseConnection, files: &[ImportFile]) -> Result<i64> {
+    let files = to_json(&files)?;
+
+    conn.with_connection(|c| {
+        c.execute(
+            "INSERT INTO import_run (started_at, files) VALUES (?, ?)",
+            params![Utc::now().timestamp(), files],
+        )?;
+        Ok(c.last_insert_rowid())
+    })
+}
+
+/// Records the outcome of an import run
+pub fn finish_import_run(
+    conn: &DatabaseConnection,
+    run_id: i64,
+    urls_inserted: usize,
+    visits_inserted: usize,
+    warnings: &[String],
+    duration_sec: f64,
+) -> Result<()> {
+    let warnings = to_json(&warnings)?;
+
+    conn.with_connection(|c| {
+        c.execute(
+            "UPDATE import_run
+             SET finished_at = ?, duration_sec = ?, urls_inserted = ?, visits_inserted = ?, warnings = ?
+             WHERE id = ?",
+            params![Utc::now().timestamp(), duration_sec, urls_inserted as i64, visits_inserted as i64, warnings, run_id],
+        )?;
+        Ok(())
+    })
+}
+
+/// Gets the most recent import runs, newest first
+pub fn get_import_history(conn: &DatabaseConnection, limit: usize) -> Result<Vec<ImportRun>> {
+    let rows = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT id, started_at, finished_at, duration_sec, files, urls_inserted, visits_inserted, warnings,
+                    rolled_back_at
+             FROM import_run ORDER BY started_at DESC, id DESC LIMIT ?"
+        )?;
+        let rows = stmt.query_map([limit as i64], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, Option<i64>>(2)?,
+                row.get::<_, Option<f64>>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, i64>(5)?,
+                row.get::<_, i64>(6)?,
+                row.get::<_, String>(7)?,
+                row.get::<_, Option<i64>>(8)?,
+            ))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    rows.into_iter()
+        .map(|(id, started_at, finished_at, duration_sec, files, urls_inserted, visits_inserted, warnings, rolled_back_at)| {
+            Ok(ImportRun {
+                id,
+                started_at: DateTime::from_timestamp(started_at, 0).unwrap_or_default(),
+                finished_at: finished_at.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+                duration_sec,
+                files: serde_json::from_str(&files).map_err(|e| DatabaseError::Data(e.to_string()))?,
+                urls_inserted: urls_inserted as usize,
+                visits_inserted: visits_inserted as usize,
+                warnings: serde_json::from_str(&warnings).map_err(|e| DatabaseError::Data(e.to_string()))?,
+                rolled_back_at: rolled_back_at.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+            })
+        })
+        .collect()
+}
+
+/// Removes every visit an import run added, and the URLs it added unless they
+/// have since been visited from another source
+pub fn rollback_import(conn: &DatabaseConnection, run_id: i64) -> Result<RollbackResult> {
+    conn.transaction(|tx| {
+        let rolled_back_at: Option<Option<i64>> = tx.query_row(
+            "SELECT rolled_back_at FROM import_run WHERE id = ?",
+            [run_id],
+            |row| row.get(0),
+        ).optional()?;
+
+        match rolled_back_at {
+            None => return Err(DatabaseError::NotFound(format!("Import run {} does not exist", run_id))),
+            Some(Some(_)) => return Err(DatabaseError::Data(format!("Import run {} was already rolled back", run_id))),
+            Some(None) => {},
+        }
+
+        // URLs that keep other visits need their time range recomputed afterwards
+        tx.execute(
+            "CREATE TEMP TABLE IF NOT EXISTS rollback_url (url_id TEXT PRIMARY KEY)",
+            [],
+        )?;
+        tx.execute("DELETE FROM rollback_url", [])?;
+        tx.execute(
+            "INSERT OR IGNORE INTO rollback_url SELECT url_id FROM visit WHERE import_run_id = ?",
+            [run_id],
+        )?;
+
+        let visits_removed = tx.execute("DELETE FROM visit WHERE import_run_id = ?", [run_id])?;
+        let urls_removed = tx.execute(
+            "DELETE FROM url WHERE import_run_id = ?1
+               AND NOT EXISTS (SELECT 1 FROM visit v WHERE v.url_id = url.id)",
+            [run_id],
+        )?;
+
+        tx.execute(
+            "UPDATE url SET
+                 first_seen = (SELECT MIN(visited_at) FROM visit v WHERE v.url_id = url.id),
+                 last_seen = (SELECT MAX(visited_at) FROM visit v WHERE v.url_id = url.id)
+             WHERE id IN (SELECT url_id FROM rollback_url)
+               AND EXISTS (SELECT 1 FROM visit v WHERE v.url_id = url.id)",
+            [],
+        )?;
+        tx.execute("DROP TABLE rollback_url", [])?;
+
+        tx.execute(
+            "UPDATE import_run SET rolled_back_at = ? WHERE id = ?",
+            params![Utc::now().timestamp(), run_id],
+        )?;
+
+        Ok(RollbackResult { visits_removed, urls_removed })
+    })
+}
@@ -0,0 +1,129 @@
+// Query Interruption
+// Time limits and cancellation for long reads. A command runs its queries
+// inside `with_limits`; every pooled connection it uses meanwhile checks the
+// limits through SQLite's progress handler and stops the running statement
+// once the time is up or the frontend cancelled the request.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rusqlite::Connection;
+
+use super::error::{DatabaseError, Result};
+
+/// Virtual machine steps between two checks of the limits
+const CHECK_STEPS: i32 = 1000;
+
+/// Limits of the queries run on the current thread
+#[derive(Clone)]
+struct Limits {
+    deadline: Instant,
+    timeout: Duration,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Limits {
+    fn exceeded(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst) || Instant::now() >= self.deadline
+    }
+
+    /// Error for a statement stopped by these limits
+    fn error(&self) -> DatabaseError {
+        if self.cancelled.load(Ordering::SeqCst) {
+            DatabaseError::Interrupted("The query was cancelled".to_string())
+        } else {
+            DatabaseError::Interrupted(format!(
+                "The query ran longer than {} seconds and was stopped", self.timeout.as_secs(),
+            ))
+        }
+    }
+}
+
+thread_local! {
+    static LIMITS: RefCell<Option<Limits>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with its queries stopped after `timeout`, or as soon as
+/// `cancelled` is set
+pub fn with_limits<T>(timeout: Duration, cancelled: Arc<AtomicBool>, f: impl FnOnce() -> T) -> T {
+    let limits = Limits { deadline: Instant::now() + timeout, timeout, cancelled };
+    let previous = LIMITS.with(|current| current.replace(Some(limits)));
+    let result = f();
+    LIMITS.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Runs `f` on a connection under the current thread's limits, if any.
+/// Pooled connections are shared, so the handler is removed again afterwards.
+pub(crate) fn run_limited<T>(conn: &Connection, f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+    let Some(limits) = LIMITS.with(|current| current.borrow().clone()) else {
+        return f(conn);
+    };
+    if limits.exceeded() {
+        return Err(limits.error());
+    }
+
+    let check = limits.clone();
+    conn.progress_handler(CHECK_STEPS, Some(move || check.exceeded()));
+    let result = f(conn);
+    conn.progress_handler(CHECK_STEPS, None::<fn() -> bool>);
+
+    // Interrupted by the limits, rather than failed on its own
+    match result {
+        Err(_) if limits.exceeded() => Err(limits.error()),
+        result => result,
+    }
+}
+
+/// Queries the frontend may cancel, by the request id it chose
+#[derive(Default)]
+pub struct RunningQueries {
+    running: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+/// A registered query; it is forgotten when this is dropped
+pub struct QueryTicket<'a> {
+    queries: &'a RunningQueries,
+    id: String,
+    /// Set when the query is cancelled
+    pub cancelled: Arc<AtomicBool>,
+}
+
+impl Drop for QueryTicket<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.queries.running.lock() {
+            // A newer query may have taken over the id
+            if running.get(&self.id).is_some_and(|flag| Arc::ptr_eq(flag, &self.cancelled)) {
+                running.remove(&self.id);
+            }
+        }
+    }
+}
+
+impl RunningQueries {
+    /// Registers a query under `id`, cancelling an earlier one still running
+    /// under the same id
+    pub fn register(&self, id: &str) -> QueryTicket<'_> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Ok(mut running) = self.running.lock() {
+            if let Some(earlier) = running.insert(id.to_string(), Arc::clone(&cancelled)) {
+                earlier.store(true, Ordering::SeqCst);
+            }
+        }
+        QueryTicket { queries: self, id: id.to_string(), cancelled }
+    }
+
+    /// Cancels the query running under `id`; returns false if none is
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.running.lock().ok().and_then(|mut running| running.remove(id)) {
+            Some(flag) => {
+                flag.store(true, Ordering::SeqCst);
+                true
+            },
+            None => false,
+        }
+    }
+}
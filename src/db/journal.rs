@@ -0,0 +1,339 @@
+// Operation Journal
+// Records before-images of destructive operations so the latest ones can be undone
+
+use std::collections::HashSet;
+
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+
+/// Number of operations kept in the journal
+pub const JOURNAL_LENGTH: usize = 20;
+
+/// A journaled operation that can be undone
+#[derive(Debug, Clone, Serialize)]
+pub struct Operation {
+    /// Operation identifier
+    pub id: i64,
+    /// Kind of operation, e.g. merge_urls
+    pub kind: String,
+    /// Human-readable description
+    pub summary: String,
+    /// When the operation ran
+    pub created_at: DateTime<Utc>,
+}
+
+/// Rows of one table captured before an operation changed them
+struct Image {
+    id: i64,
+    table: String,
+    condition: String,
+    params: Vec<Value>,
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+    /// Primary keys of the rows the operation added; None for operations
+    /// journaled before these were recorded
+    added: Option<Vec<Vec<Value>>>,
+}
+
+/// Returns `?, ?, ...` with `count` placeholders for an IN list
+pub(crate) fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
+
+/// Converts a SQLite value to JSON; blobs are stored as `{"blob": "<base64>"}`
+fn to_json(value: &Value) -> JsonValue {
+    match value {
+        Value::Null => JsonValue::Null,
+        Value::Integer(i) => JsonValue::from(*i),
+        Value::Real(f) => JsonValue::from(*f),
+        Value::Text(s) => JsonValue::from(s.as_str()),
+        Value::Blob(b) => serde_json::json!({ "blob": base64::engine::general_purpose::STANDARD.encode(b) }),
+    }
+}
+
+/// Converts JSON written by `to_json` back to a SQLite value
+fn from_json(value: &JsonValue) -> Result<Value> {
+    match value {
+        JsonValue::Null => Ok(Value::Null),
+        JsonValue::Number(n) => Ok(n.as_i64().map(Value::Integer).unwrap_or_else(|| Value::Real(n.as_f64().unwrap_or_default()))),
+        JsonValue::String(s) => Ok(Value::Text(s.clone())),
+        JsonValue::Object(o) => {
+            let encoded = o.get("blob").and_then(JsonValue::as_str)
+                .ok_or_else(|| DatabaseError::Data("Invalid journal value".to_string()))?;
+            let bytes = base64::engine::general_purpose::STANDARD.decode(encoded)
+                .map_err(|e| DatabaseError::Data(format!("Invalid journal blob: {}", e)))?;
+            Ok(Value::Blob(bytes))
+        },
+        _ => Err(DatabaseError::Data("Invalid journal value".to_string())),
+    }
+}
+
+/// Parses a JSON column of the journal
+fn parse_json<T: serde::de::DeserializeOwned>(text: &str) -> Result<T> {
+    serde_json::from_str(text).map_err(|e| DatabaseError::Data(format!("Invalid journal entry: {}", e)))
+}
+
+/// Starts a journal entry for an operation about to run and drops the oldest
+/// entries beyond `JOURNAL_LENGTH`. Must run in the operation's transaction.
+pub(crate) fn start_operation(c: &Connection, kind: &str, summary: &str) -> Result<i64> {
+    c.execute(
+        "INSERT INTO operation_journal (kind, summary, created_at) VALUES (?, ?, ?)",
+        params![kind, summary, Utc::now().timestamp()],
+    )?;
+    let operation_id = c.last_insert_rowid();
+
+    c.execute(
+        "DELETE FROM operation_journal WHERE id NOT IN (
+             SELECT id FROM operation_journal ORDER BY id DESC LIMIT ?
+         )",
+        [JOURNAL_LENGTH as i64],
+    )?;
+
+    Ok(operation_id)
+}
+
+/// Captures the rows of `table` matching `condition` before the operation changes
+/// them. The condition must select every row the operation adds, changes or
+/// removes, both before and after it runs. Capture parent tables first, and
+/// call `finish_operation` once the operation is done.
+pub(crate) fn capture(c: &Connection, operation_id: i64, table: &str, condition: &str, params: Vec<Value>) -> Result<()> {
+    let mut stmt = c.prepare(&format!("SELECT * FROM {} WHERE {}", table, condition))?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(str::to_string).collect();
+
+    let rows = stmt.query_map(params_from_iter(params.iter()), |row| {
+        (0..columns.len()).map(|i| row.get::<_, Value>(i)).collect::<rusqlite::Result<Vec<_>>>()
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let rows: Vec<Vec<JsonValue>> = rows.iter().map(|row| row.iter().map(to_json).collect()).collect();
+    let params: Vec<JsonValue> = params.iter().map(to_json).collect();
+
+    c.execute(
+        "INSERT INTO operation_image (operation_id, table_name, condition, params, columns, rows)
+         VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            operation_id,
+            table,
+            condition,
+            JsonValue::from(params).to_string(),
+            serde_json::to_string(&columns).unwrap_or_default(),
+            JsonValue::from(rows).to_string(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Records, for every image of the operation, the rows its condition matches
+/// now that were not captured, so undo removes exactly those and not rows
+/// added later. Must run at the end of the operation's transaction.
+pub(crate) fn finish_operation(c: &Connection, operation_id: i64) -> Result<()> {
+    for image in load_images(c, operation_id)? {
+        let added: Vec<JsonValue> = added_keys(c, &image)?.iter()
+            .map(|key| JsonValue::from(key.iter().map(to_json).collect::<Vec<_>>()))
+            .collect();
+        c.execute(
+            "UPDATE operation_image SET added = ? WHERE id = ?",
+            params![JsonValue::from(added).to_string(), image.id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Parses JSON rows of values
+fn parse_rows(text: &str) -> Result<Vec<Vec<Value>>> {
+    parse_json::<Vec<Vec<JsonValue>>>(text)?.iter()
+        .map(|row| row.iter().map(from_json).collect::<Result<Vec<_>>>())
+        .collect()
+}
+
+/// Loads the images of an operation in capture order
+fn load_images(c: &Connection, operation_id: i64) -> Result<Vec<Image>> {
+    let mut stmt = c.prepare(
+        "SELECT id, table_name, condition, params, columns, rows, added FROM operation_image
+         WHERE operation_id = ? ORDER BY id"
+    )?;
+    let raw = stmt.query_map([operation_id], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?, row.get::<_, String>(5)?, row.get::<_, Option<String>>(6)?))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    raw.into_iter()
+        .map(|(id, table, condition, params, columns, rows, added)| {
+            let params = parse_json::<Vec<JsonValue>>(&params)?.iter().map(from_json).collect::<Result<Vec<_>>>()?;
+            Ok(Image {
+                id,
+                table,
+                condition,
+                params,
+                columns: parse_json(&columns)?,
+                rows: parse_rows(&rows)?,
+                added: added.as_deref().map(parse_rows).transpose()?,
+            })
+        })
+        .collect()
+}
+
+/// Gets the primary key columns of a table
+fn primary_key(c: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = c.prepare(&format!("PRAGMA table_info({})", table))?;
+    let mut columns = stmt.query_map([], |row| Ok((row.get::<_, i64>(5)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    columns.retain(|(position, _)| *position > 0);
+    columns.sort();
+
+    if columns.is_empty() {
+        return Err(DatabaseError::Schema(format!("Table {} has no primary key", table)));
+    }
+    Ok(columns.into_iter().map(|(_, name)| name).collect())
+}
+
+/// Renders key values as a comparable string
+fn key_of(values: &[&Value]) -> String {
+    JsonValue::from(values.iter().map(|value| to_json(value)).collect::<Vec<_>>()).to_string()
+}
+
+/// Primary keys of the rows matching the image's condition that were not in the image
+fn added_keys(c: &Connection, image: &Image) -> Result<Vec<Vec<Value>>> {
+    let keys = primary_key(c, &image.table)?;
+    let positions = keys.iter()
+        .map(|key| image.columns.iter().position(|column| column == key)
+            .ok_or_else(|| DatabaseError::Data(format!("Journal image of {} lacks column {}", image.table, key))))
+        .collect::<Result<Vec<usize>>>()?;
+
+    let kept: HashSet<String> = image.rows.iter()
+        .map(|row| key_of(&positions.iter().map(|&i| &row[i]).collect::<Vec<_>>()))
+        .collect();
+
+    let mut stmt = c.prepare(&format!("SELECT {} FROM {} WHERE {}", keys.join(", "), image.table, image.condition))?;
+    let current = stmt.query_map(params_from_iter(image.params.iter()), |row| {
+        (0..keys.len()).map(|i| row.get::<_, Value>(i)).collect::<rusqlite::Result<Vec<_>>>()
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(current.into_iter()
+        .filter(|values| !kept.contains(&key_of(&values.iter().collect::<Vec<_>>())))
+        .collect())
+}
+
+/// Deletes the rows the operation added
+fn remove_added_rows(c: &Connection, image: &Image) -> Result<()> {
+    let added = match &image.added {
+        Some(added) => added,
+        // Without the recorded keys, rows matching now but not captured may
+        // have been added by something else since
+        None if added_keys(c, image)?.is_empty() => return Ok(()),
+        None => return Err(DatabaseError::Data(format!(
+            "Rows of {} changed since the operation ran; it can no longer be undone", image.table,
+        ))),
+    };
+
+    let keys = primary_key(c, &image.table)?;
+    let condition: Vec<String> = keys.iter().map(|key| format!("{} = ?", key)).collect();
+    let mut stmt = c.prepare(&format!("DELETE FROM {} WHERE {}", image.table, condition.join(" AND ")))?;
+    for key in added {
+        stmt.execute(params_from_iter(key.iter()))?;
+    }
+
+    Ok(())
+}
+
+/// Writes the image's rows back, updating rows that still exist
+fn restore_rows(c: &Connection, image: &Image) -> Result<()> {
+    if image.rows.is_empty() {
+        return Ok(());
+    }
+
+    let keys = primary_key(c, &image.table)?;
+    let updates: Vec<String> = image.columns.iter()
+        .filter(|column| !keys.contains(column))
+        .map(|column| format!("{0} = excluded.{0}", column))
+        .collect();
+    let on_conflict = if updates.is_empty() {
+        "DO NOTHING".to_string()
+    } else {
+        format!("DO UPDATE SET {}", updates.join(", "))
+    };
+
+    let mut stmt = c.prepare(&format!(
+        "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT ({}) {}",
+        image.table,
+        image.columns.join(", "),
+        placeholders(image.columns.len()),
+        keys.join(", "),
+        on_conflict,
+    ))?;
+
+    for row in &image.rows {
+        stmt.execute(params_from_iter(row.iter()))?;
+    }
+
+    Ok(())
+}
+
+/// Lists the operations that can be undone, newest first
+pub fn list_operations(conn: &DatabaseConnection) -> Result<Vec<Operation>> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT id, kind, summary, created_at FROM operation_journal ORDER BY id DESC"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Operation {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                summary: row.get(2)?,
+                created_at: DateTime::from_timestamp(row.get(3)?, 0).unwrap_or_default(),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
+
+/// Reverts the most recent journaled operation and removes it from the journal.
+/// Returns the undone operation, or None when there is nothing to undo.
+pub fn undo_last_operation(conn: &DatabaseConnection) -> Result<Option<Operation>> {
+    conn.transaction(undo_latest)
+}
+
+/// Reverts the most recent journaled operation within a transaction
+pub(super) fn undo_latest(tx: &Connection) -> Result<Option<Operation>> {
+    let operation = tx.query_row(
+        "SELECT id, kind, summary, created_at FROM operation_journal ORDER BY id DESC LIMIT 1",
+        [],
+        |row| Ok(Operation {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            summary: row.get(2)?,
+            created_at: DateTime::from_timestamp(row.get(3)?, 0).unwrap_or_default(),
+        }),
+    ).optional()?;
+
+    let operation = match operation {
+        Some(operation) => operation,
+        None => return Ok(None),
+    };
+
+    let images = load_images(tx, operation.id)?;
+
+    // Remove what the operation added, children before parents
+    for image in images.iter().rev() {
+        remove_added_rows(tx, image)?;
+    }
+
+    // Put the captured rows back, parents before children
+    for image in &images {
+        restore_rows(tx, image)?;
+    }
+
+    tx.execute("DELETE FROM operation_journal WHERE id = ?", [operation.id])?;
+
+    Ok(Some(operation))
+}
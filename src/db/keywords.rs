@@ -0,0 +1,54 @@
+// Keywords
+// Queries over the keyword index kept in sync with metadata.keywords
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::connection::DatabaseConnection;
+use super::error::Result;
+use super::query::QueryBuilder;
+
+/// Default number of keywords returned by `top_keywords`
+pub const DEFAULT_TOP_KEYWORDS: usize = 50;
+
+/// A keyword with how often pages carrying it were visited
+#[derive(Debug, Clone, Serialize)]
+pub struct KeywordCount {
+    /// The keyword (lowercase)
+    pub keyword: String,
+    /// Number of distinct pages with the keyword visited in the range
+    pub url_count: usize,
+    /// Number of visits to those pages in the range
+    pub visit_count: usize,
+}
+
+/// Gets the keywords of the most visited pages in the date range, by visit count
+pub fn top_keywords(
+    conn: &DatabaseConnection,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+    limit: usize,
+) -> Result<Vec<KeywordCount>> {
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT k.name,
+                    COUNT(DISTINCT v.url_id) as url_count,
+                    COUNT(*) as visit_count
+             FROM keyword k
+             JOIN url_keyword uk ON uk.keyword_id = k.id
+             JOIN visit v ON v.url_id = uk.url_id"
+        );
+        query.date_range("v.visited_at", start_date, end_date)
+            .group_by("k.id")
+            .order_by("visit_count DESC, k.name")
+            .limit(limit);
+
+        query.fetch_all(c, |row| {
+            Ok(KeywordCount {
+                keyword: row.get(0)?,
+                url_count: row.get::<_, i64>(1)? as usize,
+                visit_count: row.get::<_, i64>(2)? as usize,
+            })
+        })
+    })
+}
@@ -0,0 +1,340 @@
+// Database Maintenance
+// Size and health reporting, compaction of the database file, secure purging
+// and visit retention
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use rusqlite::types::Value;
+use chrono::{Duration, Utc};
+use rusqlite::{params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+
+use super::connection::DatabaseConnection;
+use super::error::Result;
+use super::journal::placeholders;
+use super::settings::get_setting;
+
+/// Settings key of the retention policy
+pub const RETENTION_SETTING: &str = "retention";
+
+/// How long history is kept
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionSettings {
+    /// Visits older than this many days are deleted; None keeps everything
+    pub max_visit_age_days: Option<u32>,
+}
+
+/// Size of the zero buffer used to overwrite files
+const WIPE_CHUNK: usize = 64 * 1024;
+
+/// Indexes the query paths rely on; a missing one means a migration didn't apply
+const EXPECTED_INDEXES: &[&str] = &[
+    "idx_url_url_unique",
+    "idx_visit_unique",
+    "idx_visit_visited_at",
+    "idx_visit_device",
+    "idx_url_domain",
+    "idx_url_category",
+    "idx_metadata_enriched",
+    "idx_url_tag_tag",
+    "idx_edge_target",
+    "idx_node_type",
+];
+
+/// Row count of one table
+#[derive(Debug, Clone, Serialize)]
+pub struct TableSize {
+    /// Table name
+    pub name: String,
+    /// Number of rows
+    pub rows: usize,
+}
+
+/// Whether an expected index exists
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexStatus {
+    /// Index name
+    pub name: String,
+    /// The index exists
+    pub present: bool,
+}
+
+/// Size and health of the database
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseHealth {
+    /// Size of the main database file
+    pub file_size_bytes: u64,
+    /// Size of the write-ahead log
+    pub wal_size_bytes: u64,
+    /// Page size in bytes
+    pub page_size: usize,
+    /// Pages in the database
+    pub page_count: usize,
+    /// Unused pages that compaction would release
+    pub freelist_count: usize,
+    /// Share of pages that are unused (0 to 1)
+    pub fragmentation: f64,
+    /// Row count of every table
+    pub tables: Vec<TableSize>,
+    /// Presence of the indexes the queries need
+    pub indexes: Vec<IndexStatus>,
+    /// Problems reported by the integrity check (empty when the database is fine)
+    pub integrity_errors: Vec<String>,
+}
+
+/// Sizes before and after compaction
+#[derive(Debug, Clone, Serialize)]
+pub struct CompactResult {
+    /// Database and WAL size before
+    pub size_before_bytes: u64,
+    /// Database and WAL size after
+    pub size_after_bytes: u64,
+}
+
+/// Rows removed by a secure purge
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PurgeResult {
+    /// URLs removed
+    pub urls_removed: usize,
+    /// Visits removed
+    pub visits_removed: usize,
+    /// Undo journal operations dropped because they held copies of the URLs
+    pub operations_dropped: usize,
+    /// Archived pages and thumbnails of the URLs overwritten and removed
+    pub files_removed: usize,
+}
+
+/// Size of a file, or 0 if it doesn't exist
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Overwrites a file with zeros, syncs it to disk and removes it. Returns the
+/// number of bytes overwritten. SSDs and copy-on-write filesystems may still
+/// keep old blocks, so this complements full-disk encryption rather than
+/// replacing it.
+pub fn wipe_file(path: &Path) -> io::Result<u64> {
+    let len = fs::metadata(path)?.len();
+    let mut file = OpenOptions::new().write(true).open(path)?;
+
+    let zeros = vec![0u8; WIPE_CHUNK];
+    let mut remaining = len;
+    file.seek(SeekFrom::Start(0))?;
+    while remaining > 0 {
+        let chunk = remaining.min(WIPE_CHUNK as u64) as usize;
+        file.write_all(&zeros[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+
+    fs::remove_file(path)?;
+    Ok(len)
+}
+
+/// Path of the write-ahead log next to the database
+fn wal_path(path: &Path) -> std::path::PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push("-wal");
+    path.with_file_name(name)
+}
+
+/// Reads an integer pragma
+fn pragma_int(c: &Connection, name: &str) -> Result<usize> {
+    Ok(c.query_row(&format!("PRAGMA {}", name), [], |row| row.get::<_, i64>(0))? as usize)
+}
+
+/// Counts the rows of every table
+fn table_sizes(c: &Connection) -> Result<Vec<TableSize>> {
+    let names: Vec<String> = {
+        let mut stmt = c.prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name"
+        )?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    names.into_iter()
+        .map(|name| {
+            let rows: i64 = c.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", name.replace('"', "\"\"")), [], |row| row.get(0))?;
+            Ok(TableSize { name, rows: rows as usize })
+        })
+        .collect()
+}
+
+/// Reports file sizes, page usage, row counts, index presence and integrity
+pub fn get_database_health(conn: &DatabaseConnection) -> Result<DatabaseHealth> {
+    conn.with_connection(|c| {
+        let page_size = pragma_int(c, "page_size")?;
+        let page_count = pragma_int(c, "page_count")?;
+        let freelist_count = pragma_int(c, "freelist_count")?;
+
+        let indexes = EXPECTED_INDEXES.iter()
+            .map(|name| {
+                let present: bool = c.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = ?)",
+                    [name],
+                    |row| row.get(0),
+                )?;
+                Ok(IndexStatus { name: name.to_string(), present })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let integrity_errors: Vec<String> = {
+            let mut stmt = c.prepare("PRAGMA integrity_check")?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<Vec<String>>>()?
+                .into_iter()
+                .filter(|message| message != "ok")
+                .collect()
+        };
+
+        Ok(DatabaseHealth {
+            file_size_bytes: file_size(&conn.path),
+            wal_size_bytes: file_size(&wal_path(&conn.path)),
+            page_size,
+            page_count,
+            freelist_count,
+            fragmentation: if page_count > 0 { freelist_count as f64 / page_count as f64 } else { 0.0 },
+            tables: table_sizes(c)?,
+            indexes,
+            integrity_errors,
+        })
+    })
+}
+
+/// Folds the WAL into the database, rebuilds the file without unused pages
+/// and refreshes the planner statistics
+pub fn compact_database(conn: &DatabaseConnection) -> Result<CompactResult> {
+    let wal = wal_path(&conn.path);
+    let size_before_bytes = file_size(&conn.path) + file_size(&wal);
+
+    conn.with_connection(|c| {
+        c.execute_batch("
+            PRAGMA wal_checkpoint(TRUNCATE);
+            VACUUM;
+            ANALYZE;
+            PRAGMA wal_checkpoint(TRUNCATE);
+        ")?;
+        Ok(())
+    })?;
+
+    Ok(CompactResult {
+        size_before_bytes,
+        size_after_bytes: file_size(&conn.path) + file_size(&wal),
+    })
+}
+
+/// Deletes URLs with their visits and every copy of them the database keeps:
+/// tombstones, redirects, graph nodes and undo journal entries. The purge runs
+/// with `secure_delete` so freed pages are zeroed, then checkpoints the WAL and
+/// vacuums so nothing is left in free pages or old WAL frames. Unlike
+/// `delete_visits` it can't be undone, and re-importing a history file that
+/// still has the URLs brings them back.
+pub fn secure_purge_urls(conn: &DatabaseConnection, url_ids: &[String]) -> Result<PurgeResult> {
+    if url_ids.is_empty() {
+        return Ok(PurgeResult::default());
+    }
+
+    // secure_delete is per connection, so everything runs on one pooled connection
+    let mut c = conn.get()?;
+    c.pragma_update(None, "secure_delete", true)?;
+
+    let result = purge_urls(&mut c, url_ids).and_then(|result| {
+        c.execute_batch("
+            PRAGMA wal_checkpoint(TRUNCATE);
+            VACUUM;
+            PRAGMA wal_checkpoint(TRUNCATE);
+        ")?;
+        Ok(result)
+    });
+
+    // The connection goes back to the pool
+    c.pragma_update(None, "secure_delete", false)?;
+    result
+}
+
+/// Deletes the URLs and their copies in one transaction
+fn purge_urls(c: &mut Connection, url_ids: &[String]) -> Result<PurgeResult> {
+    let tx = c.transaction()?;
+    let ids: Vec<Value> = url_ids.iter().map(|id| Value::Text(id.clone())).collect();
+    let in_list = placeholders(ids.len());
+
+    let urls: Vec<String> = {
+        let mut stmt = tx.prepare(&format!("SELECT url FROM url WHERE id IN ({})", in_list))?;
+        let rows = stmt.query_map(params_from_iter(ids.iter()), |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    // Archived pages and thumbnails on disk, removed after the commit
+    let files: Vec<String> = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT path FROM archive WHERE url_id IN ({0})
+             UNION
+             SELECT thumbnail_path FROM metadata WHERE url_id IN ({0}) AND thumbnail_path IS NOT NULL",
+            in_list,
+        ))?;
+        let rows = stmt.query_map(params_from_iter(ids.iter().chain(ids.iter())), |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    // Journal images are JSON, so any image mentioning the id or address is dropped
+    let mut operations_dropped = 0;
+    for needle in url_ids.iter().chain(urls.iter()) {
+        operations_dropped += tx.execute(
+            "DELETE FROM operation_journal WHERE id IN (
+                 SELECT operation_id FROM operation_image WHERE instr(rows, ?1) > 0 OR instr(params, ?1) > 0
+             )",
+            [needle],
+        )?;
+    }
+
+    for url in &urls {
+        tx.execute("DELETE FROM visit_tombstone WHERE url = ?", [url])?;
+        tx.execute("DELETE FROM url_redirect WHERE source_url = ?1 OR target_url = ?1", [url])?;
+    }
+    tx.execute(
+        &format!("DELETE FROM node WHERE node_type = 'url' AND key IN ({})", in_list),
+        params_from_iter(ids.iter()),
+    )?;
+
+    let visits_removed = tx.execute(
+        &format!("DELETE FROM visit WHERE url_id IN ({})", in_list),
+        params_from_iter(ids.iter()),
+    )?;
+    let urls_removed = tx.execute(
+        &format!("DELETE FROM url WHERE id IN ({})", in_list),
+        params_from_iter(ids.iter()),
+    )?;
+
+    // Drop the aggregates of domains that lost their last URL
+    super::domains::refresh_domain_stats(&tx)?;
+
+    tx.commit()?;
+
+    // Files go only once the rows are gone, so a failed purge keeps both
+    let files_removed = files.iter().filter(|path| wipe_file(Path::new(path)).is_ok()).count();
+
+    Ok(PurgeResult { urls_removed, visits_removed, operations_dropped, files_removed })
+}
+
+/// Deletes visits older than the retention policy allows, returning how many
+/// were deleted. URLs stay so their tags and page data survive.
+pub fn apply_retention(conn: &DatabaseConnection) -> Result<usize> {
+    let settings: RetentionSettings = conn.with_connection(|c| get_setting(c, RETENTION_SETTING))?
+        .unwrap_or_default();
+    let days = match settings.max_visit_age_days {
+        Some(days) if days > 0 => days,
+        _ => return Ok(0),
+    };
+
+    let cutoff = (Utc::now() - Duration::days(days as i64)).timestamp();
+    conn.transaction(|tx| {
+        let deleted = tx.execute("DELETE FROM visit WHERE visited_at < ?", [cutoff])?;
+        super::domains::refresh_domain_stats(tx)?;
+        Ok(deleted)
+    })
+}
@@ -0,0 +1,459 @@
+// Duplicate URL Merging
+// Finds URL records that are probably the same page and folds them into one,
+// including the same article reached through different share links
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use url::Url as UrlParser;
+
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+use super::journal::{capture, finish_operation, placeholders, start_operation};
+use super::settings::get_setting;
+
+/// Default number of duplicate groups returned
+pub const DEFAULT_DUPLICATE_GROUPS: usize = 100;
+
+/// Default number of title clusters returned
+pub const DEFAULT_TITLE_CLUSTERS: usize = 100;
+
+/// Titles shared by more URLs than this are generic ("Inbox", "Search
+/// results") rather than one article
+const MAX_TITLE_CLUSTER_SIZE: usize = 20;
+
+/// Normalized titles shorter than this, in words, are too generic to cluster on
+const MIN_TITLE_WORDS: usize = 3;
+
+/// Separators between an article title and the site name
+const TITLE_SEPARATORS: &[&str] = &[" | ", " - ", " – ", " — ", " · ", " :: "];
+
+/// Query parameters that only track where a click came from
+const TRACKING_PARAMS: &[&str] = &["fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid", "igshid", "yclid"];
+
+/// Settings key of the normalization rules
+pub const NORMALIZATION_SETTING: &str = "normalization";
+
+/// User rules for detecting duplicate URLs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NormalizationSettings {
+    /// Query parameters ignored on top of utm_* and the built-in tracking parameters
+    pub extra_tracking_params: Vec<String>,
+}
+
+/// Tables whose rows belong to a single URL and move with it on merge
+const URL_OWNED_TABLES: &[&str] = &["metadata", "embedding", "enrichment_job", "url_tag", "collection_item", "campaign", "content_fingerprint", "flashcard"];
+
+/// Why URL records were grouped as duplicates
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateReason {
+    /// The URLs only differ in scheme, www., trailing slash, fragment or tracking parameters
+    NormalizedUrl,
+    /// The URLs are on the same domain and have the same title
+    SameTitle,
+}
+
+/// A URL record in a duplicate group
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateUrl {
+    /// URL id
+    pub id: String,
+    /// The complete URL
+    pub url: String,
+    /// Page title
+    pub title: Option<String>,
+    /// Number of visits
+    pub visit_count: i64,
+    /// When the URL was last seen
+    pub last_seen: DateTime<Utc>,
+}
+
+/// URL records that are probably the same page
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    /// Why the records were grouped
+    pub reason: DuplicateReason,
+    /// The normalized URL or title the records share
+    pub key: String,
+    /// Domain of the records
+    pub domain: String,
+    /// Records in the group, most visited first (the suggested primary)
+    pub urls: Vec<DuplicateUrl>,
+}
+
+/// URL records across domains whose titles are the same once site names,
+/// notification counts, case and punctuation are ignored
+#[derive(Debug, Clone, Serialize)]
+pub struct TitleCluster {
+    /// The normalized title the records share
+    pub key: String,
+    /// Domains of the records, alphabetically
+    pub domains: Vec<String>,
+    /// Records in the cluster, most visited first
+    pub urls: Vec<DuplicateUrl>,
+    /// Proposed `merge_urls` primary: the most visited record
+    pub primary_id: String,
+    /// Proposed `merge_urls` duplicates: every other record
+    pub duplicate_ids: Vec<String>,
+}
+
+/// Reduces a page title to the form used to cluster near-identical titles, or
+/// None when too little is left to tell articles apart
+pub fn normalize_title(title: &str) -> Option<String> {
+    let mut title = title.trim();
+
+    // "(3) Title" unread counts
+    if let Some(rest) = title.strip_prefix('(') {
+        if let Some((count, rest)) = rest.split_once(") ") {
+            if !count.is_empty() && count.chars().all(|c| c.is_ascii_digit()) {
+                title = rest.trim_start();
+            }
+        }
+    }
+
+    // "Title | Site name", when the site name is short and the title is not
+    let split = TITLE_SEPARATORS.iter()
+        .filter_map(|separator| title.rfind(separator).map(|at| (at, separator.len())))
+        .max_by_key(|&(at, _)| at);
+    if let Some((at, len)) = split {
+        let (head, tail) = (&title[..at], &title[at + len..]);
+        if tail.split_whitespace().count() <= 4 && head.split_whitespace().count() >= MIN_TITLE_WORDS {
+            title = head;
+        }
+    }
+
+    let words: Vec<String> = title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.len() < MIN_TITLE_WORDS {
+        return None;
+    }
+    Some(words.join(" "))
+}
+
+/// Reduces a URL to the form used to detect duplicates
+pub fn normalize_url(url: &str) -> Option<String> {
+    normalize_url_with(url, &[])
+}
+
+/// Reduces a URL to the form used to detect duplicates, also dropping the
+/// `extra_params` query parameters (lowercase)
+pub fn normalize_url_with(url: &str, extra_params: &[String]) -> Option<String> {
+    let mut parsed = UrlParser::parse(url).ok()?;
+    parsed.set_fragment(None);
+
+    let host = parsed.host_str()?.to_lowercase();
+    let host = host.trim_start_matches("www.").to_string();
+
+    let query: Vec<(String, String)> = parsed.query_pairs()
+        .filter(|(key, _)| {
+            let key = key.to_lowercase();
+            !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_str()) && !extra_params.contains(&key)
+        })
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    let mut normalized = format!("{}{}", host, parsed.path().trim_end_matches('/'));
+    if !query.is_empty() {
+        let query: Vec<String> = query.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        normalized.push('?');
+        normalized.push_str(&query.join("&"));
+    }
+
+    Some(normalized)
+}
+
+/// Finds groups of URL records that are probably the same page, largest groups first
+pub fn find_probable_duplicates(conn: &DatabaseConnection, limit: usize) -> Result<Vec<DuplicateGroup>> {
+    let settings: NormalizationSettings = conn.with_connection(|c| get_setting(c, NORMALIZATION_SETTING))?
+        .unwrap_or_default();
+    let extra_params: Vec<String> = settings.extra_tracking_params.iter()
+        .map(|param| param.trim().to_lowercase())
+        .filter(|param| !param.is_empty())
+        .collect();
+
+    let urls: Vec<(DuplicateUrl, String)> = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT u.id, u.url, u.title, u.domain, u.last_seen,
+                    (SELECT COUNT(*) FROM visit v WHERE v.url_id = u.id) AS visit_count
+             FROM url u
+             ORDER BY visit_count DESC, u.last_seen DESC"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((DuplicateUrl {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                title: row.get(2)?,
+                last_seen: DateTime::from_timestamp(row.get(4)?, 0).unwrap_or_default(),
+                visit_count: row.get(5)?,
+            }, row.get(3)?))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    // Group by normalized URL first; records already grouped are not grouped again by title
+    let mut by_url: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, (url, _)) in urls.iter().enumerate() {
+        if let Some(normalized) = normalize_url_with(&url.url, &extra_params) {
+            by_url.entry(normalized).or_default().push(index);
+        }
+    }
+
+    let mut grouped = vec![false; urls.len()];
+    let mut groups: Vec<(DuplicateReason, String, Vec<usize>)> = Vec::new();
+
+    for (key, members) in by_url {
+        if members.len() > 1 {
+            members.iter().for_each(|&index| grouped[index] = true);
+            groups.push((DuplicateReason::NormalizedUrl, key, members));
+        }
+    }
+
+    let mut by_title: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    for (index, (url, domain)) in urls.iter().enumerate() {
+        let title = url.title.as_deref().map(str::trim).unwrap_or_default();
+        if !grouped[index] && !title.is_empty() {
+            by_title.entry((domain.clone(), title.to_string())).or_default().push(index);
+        }
+    }
+
+    for ((_, title), members) in by_title {
+        if members.len() > 1 {
+            groups.push((DuplicateReason::SameTitle, title, members));
+        }
+    }
+
+    groups.sort_by(|a, b| b.2.len().cmp(&a.2.len()).then_with(|| a.1.cmp(&b.1)));
+    groups.truncate(limit);
+
+    Ok(groups.into_iter()
+        .map(|(reason, key, mut members)| {
+            // Indexes follow the query order, so this restores most visited first
+            members.sort_unstable();
+            DuplicateGroup {
+                reason,
+                key,
+                domain: urls[members[0]].1.clone(),
+                urls: members.into_iter().map(|index| urls[index].0.clone()).collect(),
+            }
+        })
+        .collect())
+}
+
+/// Finds URL records on any domain whose titles are identical or nearly so,
+/// often one article reached through different share links, largest clusters
+/// first, each with a proposed merge
+pub fn find_title_clusters(conn: &DatabaseConnection, limit: usize) -> Result<Vec<TitleCluster>> {
+    let urls: Vec<(DuplicateUrl, String)> = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT u.id, u.url, u.title, u.domain, u.last_seen,
+                    (SELECT COUNT(*) FROM visit v WHERE v.url_id = u.id) AS visit_count
+             FROM url u
+             WHERE u.title IS NOT NULL AND TRIM(u.title) != ''
+             ORDER BY visit_count DESC, u.last_seen DESC"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((DuplicateUrl {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                title: row.get(2)?,
+                last_seen: DateTime::from_timestamp(row.get(4)?, 0).unwrap_or_default(),
+                visit_count: row.get(5)?,
+            }, row.get(3)?))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    // Members keep the query order, most visited first
+    let mut by_title: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, (url, _)) in urls.iter().enumerate() {
+        if let Some(key) = url.title.as_deref().and_then(normalize_title) {
+            by_title.entry(key).or_default().push(index);
+        }
+    }
+
+    let mut clusters: Vec<(String, Vec<usize>)> = by_title.into_iter()
+        .filter(|(_, members)| members.len() > 1 && members.len() <= MAX_TITLE_CLUSTER_SIZE)
+        .collect();
+    clusters.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+    clusters.truncate(limit);
+
+    Ok(clusters.into_iter()
+        .map(|(key, members)| {
+            let mut domains: Vec<String> = members.iter().map(|&index| urls[index].1.clone()).collect();
+            domains.sort();
+            domains.dedup();
+
+            let members: Vec<DuplicateUrl> = members.into_iter().map(|index| urls[index].0.clone()).collect();
+            TitleCluster {
+                key,
+                domains,
+                primary_id: members[0].id.clone(),
+                duplicate_ids: members[1..].iter().map(|url| url.id.clone()).collect(),
+                urls: members,
+            }
+        })
+        .collect())
+}
+
+/// Re-points the URL nodes and edges of `source_id` to those of `target_id`
+/// in every graph snapshot
+fn merge_graph_nodes(c: &Connection, source_id: &str, target_id: &str) -> rusqlite::Result<()> {
+    let pairs: Vec<(i64, Option<i64>)> = {
+        let mut stmt = c.prepare(
+            "SELECT s.id, t.id FROM node s
+             LEFT JOIN node t ON t.snapshot_id = s.snapshot_id AND t.node_type = 'url' AND t.key = ?2
+             WHERE s.node_type = 'url' AND s.key = ?1"
+        )?;
+        let rows = stmt.query_map(params![source_id, target_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    for (source_node, target_node) in pairs {
+        let target_node = match target_node {
+            Some(target_node) => target_node,
+            None => {
+                // The target isn't in this snapshot, the source node becomes it
+                c.execute("UPDATE node SET key = ? WHERE id = ?", params![target_id, source_node])?;
+                continue;
+            },
+        };
+
+        c.execute(
+            "UPDATE node SET weight = weight + (SELECT weight FROM node WHERE id = ?1) WHERE id = ?2",
+            params![source_node, target_node],
+        )?;
+        c.execute("UPDATE OR IGNORE edge SET source_id = ? WHERE source_id = ?", params![target_node, source_node])?;
+        c.execute("UPDATE OR IGNORE edge SET target_id = ? WHERE target_id = ?", params![target_node, source_node])?;
+        c.execute("DELETE FROM edge WHERE source_id = ?1 AND target_id = ?1", [target_node])?;
+
+        // Edges the target already had are dropped with the node
+        c.execute("DELETE FROM node WHERE id = ?", [source_node])?;
+    }
+
+    Ok(())
+}
+
+/// Moves everything attached to the URL record `source_id` onto `target_id`
+/// and deletes the source. Where both records have data, the target's is kept.
+pub(crate) fn merge_url_records(c: &Connection, source_id: &str, target_id: &str) -> rusqlite::Result<()> {
+    // A visit both records have (same time and file) is kept once
+    c.execute("UPDATE OR IGNORE visit SET url_id = ? WHERE url_id = ?", params![target_id, source_id])?;
+    c.execute("DELETE FROM visit WHERE url_id = ?", [source_id])?;
+
+    c.execute(
+        "UPDATE url SET
+             first_seen = MIN(first_seen, (SELECT first_seen FROM url WHERE id = ?1)),
+             last_seen = MAX(last_seen, (SELECT last_seen FROM url WHERE id = ?1)),
+             title = COALESCE(title, (SELECT title FROM url WHERE id = ?1)),
+             is_favorite = MAX(is_favorite, (SELECT is_favorite FROM url WHERE id = ?1))
+         WHERE id = ?2",
+        params![source_id, target_id],
+    )?;
+
+    for table in URL_OWNED_TABLES {
+        c.execute(
+            &format!("UPDATE OR IGNORE {} SET url_id = ? WHERE url_id = ?", table),
+            params![target_id, source_id],
+        )?;
+    }
+    c.execute("UPDATE archive SET url_id = ? WHERE url_id = ?", params![target_id, source_id])?;
+    c.execute("UPDATE url_edit SET url_id = ? WHERE url_id = ?", params![target_id, source_id])?;
+
+    merge_graph_nodes(c, source_id, target_id)?;
+
+    c.execute("DELETE FROM url WHERE id = ?", [source_id])?;
+
+    Ok(())
+}
+
+/// Captures everything a merge of `duplicates` into the primary record can
+/// touch; returns the journal entry to finish once the merge is done
+fn journal_merge(c: &Connection, primary_id: &str, primary_url: &str, duplicates: &[(String, String)]) -> Result<i64> {
+    let ids: Vec<Value> = std::iter::once(primary_id.to_string())
+        .chain(duplicates.iter().map(|(id, _)| id.clone()))
+        .map(Value::Text)
+        .collect();
+    let urls: Vec<Value> = duplicates.iter().map(|(_, url)| Value::Text(url.clone())).collect();
+    let in_ids = placeholders(ids.len());
+
+    let operation_id = start_operation(
+        c,
+        "merge_urls",
+        &format!("Merged {} duplicates into {}", duplicates.len(), primary_url),
+    )?;
+
+    capture(c, operation_id, "url", &format!("id IN ({})", in_ids), ids.clone())?;
+    for table in ["visit"].iter().chain(URL_OWNED_TABLES).chain(&["archive", "url_edit"]) {
+        capture(c, operation_id, table, &format!("url_id IN ({})", in_ids), ids.clone())?;
+    }
+    capture(c, operation_id, "url_redirect", &format!("source_url IN ({})", placeholders(urls.len())), urls)?;
+
+    let url_nodes = format!("SELECT id FROM node WHERE node_type = 'url' AND key IN ({})", in_ids);
+    capture(c, operation_id, "node", &format!("node_type = 'url' AND key IN ({})", in_ids), ids.clone())?;
+    capture(
+        c,
+        operation_id,
+        "edge",
+        &format!("source_id IN ({0}) OR target_id IN ({0})", url_nodes),
+        ids.iter().chain(ids.iter()).cloned().collect(),
+    )?;
+
+    Ok(operation_id)
+}
+
+/// Merges the duplicate URL records into the primary one; returns the number merged.
+/// Re-imports of a duplicate's URL are folded into the primary again after import.
+pub fn merge_urls(conn: &DatabaseConnection, primary_id: &str, duplicate_ids: &[String]) -> Result<usize> {
+    conn.transaction(|tx| {
+        let primary_url: String = tx.query_row("SELECT url FROM url WHERE id = ?", [primary_id], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| DatabaseError::NotFound(format!("URL {} does not exist", primary_id)))?;
+
+        let mut duplicates: Vec<(String, String)> = Vec::new();
+        for duplicate_id in duplicate_ids.iter().filter(|id| id.as_str() != primary_id) {
+            let duplicate_url: Option<String> = tx.query_row(
+                "SELECT url FROM url WHERE id = ?", [duplicate_id], |row| row.get(0),
+            ).optional()?;
+
+            if let Some(duplicate_url) = duplicate_url {
+                if !duplicates.iter().any(|(id, _)| id == duplicate_id) {
+                    duplicates.push((duplicate_id.clone(), duplicate_url));
+                }
+            }
+        }
+        if duplicates.is_empty() {
+            return Ok(0);
+        }
+
+        let operation_id = journal_merge(tx, primary_id, &primary_url, &duplicates)?;
+
+        let now = Utc::now().timestamp();
+        let mut merged = 0;
+
+        for (duplicate_id, duplicate_url) in &duplicates {
+            merge_url_records(tx, duplicate_id, primary_id)?;
+
+            // Recorded like a resolved short link so imports apply the merge again
+            tx.execute(
+                "INSERT OR REPLACE INTO url_redirect (source_url, target_url, resolved_at) VALUES (?, ?, ?)",
+                params![duplicate_url, primary_url, now],
+            )?;
+            tx.execute(
+                "INSERT INTO url_edit (url_id, field, old_value, new_value, edited_at) VALUES (?, 'merged', ?, NULL, ?)",
+                params![primary_id, duplicate_url, now],
+            )?;
+            merged += 1;
+        }
+
+        finish_operation(tx, operation_id)?;
+        Ok(merged)
+    })
+}
@@ -8,6 +8,54 @@ use rusqlite::Connection;
 use super::connection::DatabaseConnection;
 use super::error::{DatabaseError, Result};
 
+/// Versioned migrations applied on top of the initial schema (version 1), in order
+const MIGRATIONS: &[(i32, &str)] = &[
+    (2, include_str!("../../database/migrations/v2.sql")),
+    (3, include_str!("../../database/migrations/v3.sql")),
+    (4, include_str!("../../database/migrations/v4.sql")),
+    (5, include_str!("../../database/migrations/v5.sql")),
+    (6, include_str!("../../database/migrations/v6.sql")),
+    (7, include_str!("../../database/migrations/v7.sql")),
+    (8, include_str!("../../database/migrations/v8.sql")),
+    (9, include_str!("../../database/migrations/v9.sql")),
+    (10, include_str!("../../database/migrations/v10.sql")),
+    (11, include_str!("../../database/migrations/v11.sql")),
+    (12, include_str!("../../database/migrations/v12.sql")),
+    (13, include_str!("../../database/migrations/v13.sql")),
+    (14, include_str!("../../database/migrations/v14.sql")),
+    (15, include_str!("../../database/migrations/v15.sql")),
+    (16, include_str!("../../database/migrations/v16.sql")),
+    (17, include_str!("../../database/migrations/v17.sql")),
+    (18, include_str!("../../database/migrations/v18.sql")),
+    (19, include_str!("../../database/migrations/v19.sql")),
+    (20, include_str!("../../database/migrations/v20.sql")),
+    (21, include_str!("../../database/migrations/v21.sql")),
+    (22, include_str!("../../database/migrations/v22.sql")),
+    (23, include_str!("../../database/migrations/v23.sql")),
+    (24, include_str!("../../database/migrations/v24.sql")),
+    (25, include_str!("../../database/migrations/v25.sql")),
+    (26, include_str!("../../database/migrations/v26.sql")),
+    (27, include_str!("../../database/migrations/v27.sql")),
+    (28, include_str!("../../database/migrations/v28.sql")),
+    (29, include_str!("../../database/migrations/v29.sql")),
+    (30, include_str!("../../database/migrations/v30.sql")),
+    (31, include_str!("../../database/migrations/v31.sql")),
+    (32, include_str!("../../database/migrations/v32.sql")),
+    (33, include_str!("../../database/migrations/v33.sql")),
+    (34, include_str!("../../database/migrations/v34.sql")),
+    (35, include_str!("../../database/migrations/v35.sql")),
+    (36, include_str!("../../database/migrations/v36.sql")),
+    (37, include_str!("../../database/migrations/v37.sql")),
+    (38, include_str!("../../database/migrations/v38.sql")),
+    (39, include_str!("../../database/migrations/v39.sql")),
+    (40, include_str!("../../database/migrations/v40.sql")),
+    (41, include_str!("../../database/migrations/v41.sql")),
+    (42, include_str!("../../database/migrations/v42.sql")),
+    (43, include_str!("../../database/migrations/v43.sql")),
+    (44, include_str!("../../database/migrations/v44.sql")),
+    (45, include_str!("../../database/migrations/v45.sql")),
+];
+
 /// Applies all migrations to ensure the database schema is up-to-date
 pub fn apply_migrations(conn: &DatabaseConnection) -> Result<()> {
     // Check if the database has been initialized
@@ -16,8 +64,20 @@ pub fn apply_migrations(conn: &DatabaseConnection) -> Result<()> {
         apply_initial_schema(conn)?;
     }
     
-    // Additional migrations can be applied here in the future
-    // Each migration should be versioned and only applied if needed
+    // Apply each versioned migration that is newer than the stored version
+    let current_version = conn.with_connection(get_schema_version)?;
+    
+    for (version, sql) in MIGRATIONS {
+        if *version <= current_version {
+            continue;
+        }
+        
+        conn.transaction(|tx| {
+            tx.execute_batch(sql)
+                .map_err(|e| DatabaseError::Migration(format!("Failed to apply migration v{}: {}", version, e)))?;
+            update_schema_version(tx, *version)
+        })?;
+    }
     
     Ok(())
 }
@@ -3,29 +3,85 @@
 
 // Module organization:
 // - connection.rs: Database connection management
+// - encryption.rs: SQLCipher encryption and keychain storage
 // - models.rs: ORM-like data models
 // - operations.rs: CRUD operations
 // - migrations.rs: Schema migrations and initialization
+// - query.rs: Parameter-binding query builder
+// - analytics.rs: Aggregate reporting queries
+// - campaigns.rs: utm_* campaign parameters and the sources they credit
+// - origins.rs: Where visits came from (search, social, newsletter, ...)
+// - sentiment.rs: Tone of the pages read, overall, per category and per domain
+// - settings.rs: Key/value settings storage
+// - tags.rs: Tag management
+// - devices.rs: Device registry
+// - keywords.rs: Keyword index queries
+// - domains.rs: Per-domain aggregates
+// - trackers.rs: Ad and tracker blocklists and the domains they classify
+// - organizations.rs: Organizations owning domains and rollups to them
+// - collections.rs: Favorites and ordered collections
+// - reading_list.rs: Long pages skimmed once on the main topics, to read later
+// - searches.rs: Saved searches
+// - editing.rs: Manual URL and visit edits
+// - details.rs: Everything stored about one URL, for its detail page
+// - merge.rs: Duplicate URL detection and merging
+// - consolidate.rs: Merging another instance's database into this one
+// - journal.rs: Undo journal for destructive operations
+// - imports.rs: Import run audit log
+// - sources.rs: Fingerprints of imported files, recognizing copies
+// - readonly.rs: Validated read-only queries over whitelisted views
+// - interrupt.rs: Time limits and cancellation of long reads
+// - maintenance.rs: Database health report, compaction, secure purging and retention
 // - error.rs: Error handling
 
 pub mod connection;
+pub mod encryption;
 pub mod models;
 pub mod operations;
 pub mod migrations;
 pub mod error;
+pub mod query;
+pub mod analytics;
+pub mod campaigns;
+pub mod origins;
+pub mod sentiment;
+pub mod settings;
+pub mod tags;
+pub mod devices;
+pub mod keywords;
+pub mod domains;
+pub mod trackers;
+pub mod organizations;
+pub mod collections;
+pub mod reading_list;
+pub mod searches;
+pub mod editing;
+pub mod details;
+pub mod merge;
+pub mod consolidate;
+pub mod journal;
+pub mod imports;
+pub mod sources;
+pub mod readonly;
+pub mod interrupt;
+pub mod maintenance;
 
 pub use connection::DatabaseConnection;
-pub use models::{VisitRecord, UrlRecord, MetadataRecord};
+pub use models::{VisitRecord, UrlRecord, MetadataRecord, UrlWithVisits};
 pub use operations::{insert_history_data, search_history, get_stats};
 pub use error::{DatabaseError, Result};
 
-/// Initialize the database, creating schema if needed
-pub fn initialize_database(db_path: &std::path::Path) -> Result<DatabaseConnection> {
+/// Initialize the database, creating schema if needed; an encrypted database
+/// needs its key
+pub fn initialize_database(db_path: &std::path::Path, key: Option<&str>) -> Result<DatabaseConnection> {
     // Create connection
-    let conn = connection::DatabaseConnection::new(db_path)?;
+    let conn = connection::DatabaseConnection::open(db_path, key)?;
     
     // Apply migrations to ensure schema is up-to-date
     migrations::apply_migrations(&conn)?;
     
     Ok(conn)
 }
+
+#[cfg(test)]
+mod tests;
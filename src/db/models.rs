@@ -43,6 +43,8 @@ pub struct VisitRecord {
     pub device_name: Option<String>,
     /// Optional visit duration in seconds
     pub duration_sec: Option<f64>,
+    /// Page the visit was opened from
+    pub referrer: Option<String>,
 }
 
 /// Represents a metadata record in the database
@@ -54,12 +56,23 @@ pub struct MetadataRecord {
     pub summary: Option<String>,
     /// Keywords extracted from the content (JSON array as string)
     pub keywords: Option<String>,
-    /// User or AI assigned tags (JSON array as string)
-    pub tags: Option<String>,
     /// Topic cluster assignment
     pub topic_cluster: Option<String>,
     /// Whether this URL has been enriched with AI
     pub is_enriched: bool,
+    /// "paywall" or "login" when fetching the page only showed a wall
+    pub access_wall: Option<String>,
+}
+
+/// A URL record together with aggregated visit information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlWithVisits {
+    /// The URL record
+    pub url: UrlRecord,
+    /// Number of visits to this URL
+    pub visit_count: usize,
+    /// Most recent visit, if any
+    pub last_visit: Option<DateTime<Utc>>,
 }
 
 // Implementation for UrlRecord
@@ -134,6 +147,7 @@ impl VisitRecord {
         source_file: String,
         device_name: Option<String>,
         duration_sec: Option<f64>,
+        referrer: Option<String>,
     ) -> Self {
         Self {
             id: Uuid::new_v4(),
@@ -143,11 +157,12 @@ impl VisitRecord {
             source_file,
             device_name,
             duration_sec,
+            referrer,
         }
     }
     
     /// Converts this record to SQLite parameters for insertion
-    pub fn to_params(&self) -> [&dyn rusqlite::ToSql; 7] {
+    pub fn to_params(&self) -> [&dyn rusqlite::ToSql; 8] {
         [
             &self.id.to_string(),
             &self.url_id.to_string(),
@@ -156,6 +171,7 @@ impl VisitRecord {
             &self.source_file,
             &self.device_name,
             &self.duration_sec,
+            &self.referrer,
         ]
     }
     
@@ -177,6 +193,7 @@ impl VisitRecord {
         let source_file: String = row.get(4)?;
         let device_name: Option<String> = row.get(5)?;
         let duration_sec: Option<f64> = row.get(6)?;
+        let referrer: Option<String> = row.get(7)?;
             
         Ok(Self {
             id,
@@ -186,6 +203,7 @@ impl VisitRecord {
             source_file,
             device_name,
             duration_sec,
+            referrer,
         })
     }
 }
@@ -197,7 +215,6 @@ impl MetadataRecord {
         url_id: Uuid,
         summary: Option<String>,
         keywords: Option<String>,
-        tags: Option<String>,
         topic_cluster: Option<String>,
         is_enriched: bool,
     ) -> Self {
@@ -205,9 +222,9 @@ impl MetadataRecord {
             url_id,
             summary,
             keywords,
-            tags,
             topic_cluster,
             is_enriched,
+            access_wall: None,
         }
     }
     
@@ -217,19 +234,18 @@ impl MetadataRecord {
             url_id,
             summary: None,
             keywords: None,
-            tags: None,
             topic_cluster: None,
             is_enriched: false,
+            access_wall: None,
         }
     }
     
     /// Converts this record to SQLite parameters for insertion
-    pub fn to_params(&self) -> [&dyn rusqlite::ToSql; 6] {
+    pub fn to_params(&self) -> [&dyn rusqlite::ToSql; 5] {
         [
             &self.url_id.to_string(),
             &self.summary,
             &self.keywords,
-            &self.tags,
             &self.topic_cluster,
             &self.is_enriched,
         ]
@@ -243,17 +259,17 @@ impl MetadataRecord {
             
         let summary: Option<String> = row.get(1)?;
         let keywords: Option<String> = row.get(2)?;
-        let tags: Option<String> = row.get(3)?;
-        let topic_cluster: Option<String> = row.get(4)?;
-        let is_enriched: bool = row.get(5)?;
+        let topic_cluster: Option<String> = row.get(3)?;
+        let is_enriched: bool = row.get(4)?;
+        let access_wall: Option<String> = row.get(5)?;
             
         Ok(Self {
             url_id,
             summary,
             keywords,
-            tags,
             topic_cluster,
             is_enriched,
+            access_wall,
         })
     }
 }
@@ -2,139 +2,236 @@
 // CRUD operations for history data
 
 use chrono::{DateTime, Utc};
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::collections::HashMap;
 
 use super::error::{DatabaseError, Result};
-use super::models::{UrlRecord, VisitRecord, MetadataRecord};
+use super::models::{UrlRecord, VisitRecord, MetadataRecord, UrlWithVisits};
 use super::connection::DatabaseConnection;
+use super::query::{QueryBuilder, row_error};
+use super::tags::get_url_tags;
+use super::editing::is_tombstoned;
+use super::devices::register_device;
+use super::domains::{refresh_domain_stats, top_domains};
+use super::imports::{record_progress, FileProgress};
+use super::campaigns::record_campaign;
+use super::origins::refresh_visit_origins;
 use crate::extractor::models::RawHistoryData;
 
-/// Inserts extracted history data into the database
-pub fn insert_history_data(conn: &DatabaseConnection, history_data: &RawHistoryData) -> Result<InsertStats> {
+/// Inserts extracted history data into the database; new rows are attributed
+/// to the import run, if given
+///
+/// URLs and then visits are committed `batch_size` at a time, so readers get
+/// in between batches and a crash only loses the batch in flight. With an
+/// import run, each batch records how far the file got; `resume` is such a
+/// record from an interrupted run of the same file, whose committed visits
+/// are skipped when the file still holds as many URLs and visits.
+pub fn insert_history_data(
+    conn: &DatabaseConnection,
+    history_data: &RawHistoryData,
+    import_run_id: Option<i64>,
+    batch_size: usize,
+    resume: Option<&FileProgress>,
+) -> Result<InsertStats> {
     let mut stats = InsertStats::default();
+    let batch_size = batch_size.max(1);
+    let path = history_data.source.file_path.display().to_string();
+    let mut progress = FileProgress {
+        urls_total: history_data.urls.len(),
+        visits_total: history_data.visits.len(),
+        ..FileProgress::default()
+    };
     
-    // Use a transaction for better performance and atomicity
-    conn.transaction(|tx| {
-        // First, insert all URLs
-        for url in &history_data.urls {
-            match insert_url(tx, &UrlRecord {
-                id: url.id,
-                url: url.url.clone(),
-                title: url.title.clone(),
-                domain: url.domain.clone(),
-                first_seen: url.first_seen,
-                last_seen: url.last_seen,
-            }) {
-                Ok(_) => stats.urls_inserted += 1,
-                Err(e) => {
-                    stats.errors.push(format!("Failed to insert URL {}: {}", url.url, e));
-                    continue; // Skip visits for this URL
+    // Stored URL ids by import id; a URL already in the database keeps its id
+    let mut stored_ids: HashMap<Uuid, Uuid> = HashMap::new();
+    
+    // First, insert all URLs. Inserting a URL again only widens its seen range,
+    // so a resumed import goes through them all to learn their stored ids.
+    for batch in history_data.urls.chunks(batch_size) {
+        conn.transaction(|tx| {
+            for url in batch {
+                let url_id = match insert_url(tx, &UrlRecord {
+                    id: url.id,
+                    url: url.url.clone(),
+                    title: url.title.clone(),
+                    domain: url.domain.clone(),
+                    first_seen: url.first_seen,
+                    last_seen: url.last_seen,
+                }, import_run_id) {
+                    Ok((url_id, inserted)) => {
+                        stats.urls_inserted += inserted as usize;
+                        url_id
+                    },
+                    Err(e) => {
+                        stats.errors.push(format!("Failed to insert URL {}: {}", url.url, e));
+                        continue; // Skip visits for this URL
+                    }
+                };
+                stored_ids.insert(url.id, url_id);
+                
+                // Insert empty metadata record
+                match insert_metadata(tx, &MetadataRecord::empty(url_id)) {
+                    Ok(_) => stats.metadata_inserted += 1,
+                    Err(e) => {
+                        stats.errors.push(format!("Failed to insert metadata for URL {}: {}", url.url, e));
+                    }
+                }
+                
+                if let Some(ref campaign) = url.campaign {
+                    if let Err(e) = record_campaign(tx, url_id, campaign) {
+                        stats.errors.push(format!("Failed to record campaign of URL {}: {}", url.url, e));
+                    }
                 }
             }
             
-            // Insert empty metadata record
-            match insert_metadata(tx, &MetadataRecord::empty(url.id)) {
-                Ok(_) => stats.metadata_inserted += 1,
-                Err(e) => {
-                    stats.errors.push(format!("Failed to insert metadata for URL {}: {}", url.url, e));
-                }
+            progress.urls_done += batch.len();
+            if let Some(run_id) = import_run_id {
+                record_progress(tx, run_id, &path, &progress)?;
             }
-        }
-        
-        // Then, insert all visits
-        for visit in &history_data.visits {
-            match insert_visit(tx, &VisitRecord {
-                id: visit.id,
-                url_id: visit.url_id,
-                visited_at: visit.visited_at,
-                visit_count: visit.visit_count,
-                source_file: visit.source_file.clone(),
-                device_name: visit.device_name.clone(),
-                duration_sec: visit.duration_sec,
-            }) {
-                Ok(_) => stats.visits_inserted += 1,
-                Err(e) => {
-                    stats.errors.push(format!("Failed to insert visit {}: {}", visit.id, e));
+            Ok(())
+        })?;
+    }
+    
+    // URL strings by import id, to match visits against user deletions
+    let urls_by_id: HashMap<Uuid, &str> = history_data.urls.iter()
+        .map(|url| (url.id, url.url.as_str()))
+        .collect();
+    
+    // Registered device ids by name
+    let imported_at = Utc::now().timestamp();
+    let mut device_ids: HashMap<String, Option<i64>> = HashMap::new();
+    
+    // Visits an interrupted run of the same file already committed
+    let skipped = resume
+        .filter(|resume| resume.urls_total == progress.urls_total && resume.visits_total == progress.visits_total)
+        .map_or(0, |resume| resume.visits_done.min(progress.visits_total));
+    progress.visits_done = skipped;
+    
+    // Then, insert all visits
+    for batch in history_data.visits[skipped..].chunks(batch_size) {
+        conn.transaction(|tx| {
+            for visit in batch {
+                // Visits of URLs that failed to insert are skipped
+                let url_id = match stored_ids.get(&visit.url_id) {
+                    Some(url_id) => *url_id,
+                    None => continue,
+                };
+                
+                // Skip visits the user deleted
+                if let Some(url) = urls_by_id.get(&visit.url_id) {
+                    if is_tombstoned(tx, url, visit.visited_at.timestamp())? {
+                        continue;
+                    }
+                }
+                
+                // Blank device names leave the visit without a device
+                let device_id = match &visit.device_name {
+                    Some(name) => match device_ids.get(name) {
+                        Some(device_id) => *device_id,
+                        None => {
+                            let device_id = register_device(tx, name, Some(imported_at)).ok();
+                            device_ids.insert(name.clone(), device_id);
+                            device_id
+                        },
+                    },
+                    None => None,
+                };
+                
+                match insert_visit(tx, &VisitRecord {
+                    id: visit.id,
+                    url_id,
+                    visited_at: visit.visited_at,
+                    visit_count: visit.visit_count,
+                    source_file: visit.source_file.clone(),
+                    device_name: visit.device_name.clone(),
+                    duration_sec: visit.duration_sec,
+                    referrer: visit.referrer.clone(),
+                }, device_id, import_run_id) {
+                    Ok(inserted) => stats.visits_inserted += inserted as usize,
+                    Err(e) => {
+                        stats.errors.push(format!("Failed to insert visit {}: {}", visit.id, e));
+                    }
                 }
             }
-        }
-        
-        Ok(stats)
-    })
+            
+            progress.visits_done += batch.len();
+            if let Some(run_id) = import_run_id {
+                record_progress(tx, run_id, &path, &progress)?;
+            }
+            Ok(())
+        })?;
+    }
+    
+    // Bring the per-domain aggregates and visit origins up to date with the new visits
+    conn.transaction(|tx| {
+        refresh_domain_stats(tx)?;
+        refresh_visit_origins(tx)
+    })?;
+    
+    Ok(stats)
 }
 
-/// Inserts a URL record into the database
-fn insert_url(conn: &Connection, url: &UrlRecord) -> Result<()> {
-    // Check if URL already exists (by URL string)
-    let existing = conn.query_row(
-        "SELECT id FROM url WHERE url = ?",
-        [&url.url],
-        |row| {
-            let id_str: String = row.get(0)?;
-            Ok(id_str)
-        },
-    );
+/// Inserts a URL record, or widens the seen range of the existing record for
+/// the same URL. Returns the stored record's id and whether it was new.
+fn insert_url(conn: &Connection, url: &UrlRecord, import_run_id: Option<i64>) -> Result<(Uuid, bool)> {
+    // Title and domain of existing URLs are left alone so manual edits survive re-imports
+    let id: String = conn.prepare_cached(
+        "INSERT INTO url (id, url, title, domain, first_seen, last_seen, import_run_id)
+         VALUES (?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT (url) DO UPDATE SET
+             first_seen = MIN(first_seen, excluded.first_seen),
+             last_seen = MAX(last_seen, excluded.last_seen)
+         RETURNING id",
+    )?.query_row(
+        params![
+            url.id.to_string(),
+            url.url,
+            url.title,
+            url.domain,
+            url.first_seen.timestamp(),
+            url.last_seen.timestamp(),
+            import_run_id,
+        ],
+        |row| row.get(0),
+    ).map_err(DatabaseError::from)?;
     
-    match existing {
-        Ok(_) => {
-            // URL exists, update last_seen time if newer
-            conn.execute(
-                "UPDATE url SET last_seen = MAX(last_seen, ?) WHERE url = ?",
-                params![url.last_seen.timestamp(), url.url],
-            ).map_err(|e| DatabaseError::Query(e.to_string()))?;
-        },
-        Err(rusqlite::Error::QueryReturnedNoRows) => {
-            // URL doesn't exist, insert it
-            conn.execute(
-                "INSERT INTO url (id, url, title, domain, first_seen, last_seen)
-                 VALUES (?, ?, ?, ?, ?, ?)",
-                url.to_params(),
-            ).map_err(|e| DatabaseError::Query(e.to_string()))?;
-        },
-        Err(e) => return Err(DatabaseError::Query(e.to_string())),
-    }
+    let id = Uuid::parse_str(&id)
+        .map_err(|e| DatabaseError::Data(format!("Invalid URL ID: {}", e)))?;
     
-    Ok(())
+    Ok((id, id == url.id))
 }
 
-/// Inserts a visit record into the database
-fn insert_visit(conn: &Connection, visit: &VisitRecord) -> Result<()> {
-    // Check if the exact same visit already exists
-    let existing = conn.query_row(
-        "SELECT id FROM visit WHERE url_id = ? AND visited_at = ? AND source_file = ?",
-        params![visit.url_id.to_string(), visit.visited_at.timestamp(), visit.source_file],
-        |row| {
-            let id_str: String = row.get(0)?;
-            Ok(id_str)
-        },
-    );
+/// Inserts a visit record into the database, returning true if it was new
+fn insert_visit(conn: &Connection, visit: &VisitRecord, device_id: Option<i64>, import_run_id: Option<i64>) -> Result<bool> {
+    // The same visit read from the same file again is skipped
+    let inserted = conn.prepare_cached(
+        "INSERT INTO visit (id, url_id, visited_at, visit_count, source_file, device_name, device_id, duration_sec, referrer, import_run_id)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT (url_id, visited_at, source_file) DO NOTHING",
+    )?.execute(
+        params![
+            visit.id.to_string(),
+            visit.url_id.to_string(),
+            visit.visited_at.timestamp(),
+            visit.visit_count,
+            visit.source_file,
+            visit.device_name,
+            device_id,
+            visit.duration_sec,
+            visit.referrer,
+            import_run_id,
+        ],
+    ).map_err(DatabaseError::from)?;
     
-    match existing {
-        Ok(_) => {
-            // Visit already exists, skip
-            return Ok(());
-        },
-        Err(rusqlite::Error::QueryReturnedNoRows) => {
-            // Visit doesn't exist, insert it
-            conn.execute(
-                "INSERT INTO visit (id, url_id, visited_at, visit_count, source_file, device_name, duration_sec)
-                 VALUES (?, ?, ?, ?, ?, ?, ?)",
-                visit.to_params(),
-            ).map_err(|e| DatabaseError::Query(e.to_string()))?;
-        },
-        Err(e) => return Err(DatabaseError::Query(e.to_string())),
-    }
-    
-    Ok(())
+    Ok(inserted > 0)
 }
 
 /// Inserts a metadata record into the database
 fn insert_metadata(conn: &Connection, metadata: &MetadataRecord) -> Result<()> {
     // Check if metadata for this URL already exists
-    let existing = conn.query_row(
-        "SELECT url_id FROM metadata WHERE url_id = ?",
+    let existing = conn.prepare_cached("SELECT url_id FROM metadata WHERE url_id = ?")?.query_row(
         [metadata.url_id.to_string()],
         |row| {
             let id_str: String = row.get(0)?;
@@ -146,30 +243,31 @@ fn insert_metadata(conn: &Connection, metadata: &MetadataRecord) -> Result<()> {
         Ok(_) => {
             // Metadata exists, only update if we have enrichment
             if metadata.is_enriched {
-                conn.execute(
-                    "UPDATE metadata SET summary = ?, keywords = ?, tags = ?, 
+                conn.prepare_cached(
+                    "UPDATE metadata SET summary = ?, keywords = ?,
                      topic_cluster = ?, is_enriched = ?
                      WHERE url_id = ?",
+                )?.execute(
                     params![
                         metadata.summary,
                         metadata.keywords,
-                        metadata.tags,
                         metadata.topic_cluster,
                         metadata.is_enriched,
                         metadata.url_id.to_string()
                     ],
-                ).map_err(|e| DatabaseError::Query(e.to_string()))?;
+                ).map_err(DatabaseError::from)?;
             }
         },
         Err(rusqlite::Error::QueryReturnedNoRows) => {
             // Metadata doesn't exist, insert it
-            conn.execute(
-                "INSERT INTO metadata (url_id, summary, keywords, tags, topic_cluster, is_enriched)
-                 VALUES (?, ?, ?, ?, ?, ?)",
+            conn.prepare_cached(
+                "INSERT INTO metadata (url_id, summary, keywords, topic_cluster, is_enriched)
+                 VALUES (?, ?, ?, ?, ?)",
+            )?.execute(
                 metadata.to_params(),
-            ).map_err(|e| DatabaseError::Query(e.to_string()))?;
+            ).map_err(DatabaseError::from)?;
         },
-        Err(e) => return Err(DatabaseError::Query(e.to_string())),
+        Err(e) => return Err(DatabaseError::from(e)),
     }
     
     Ok(())
@@ -178,9 +276,9 @@ fn insert_metadata(conn: &Connection, metadata: &MetadataRecord) -> Result<()> {
 /// Statistics for inserted records
 #[derive(Debug, Default)]
 pub struct InsertStats {
-    /// Number of URLs inserted
+    /// Number of new URLs inserted (existing URLs are only updated)
     pub urls_inserted: usize,
-    /// Number of visits inserted
+    /// Number of new visits inserted (duplicates are skipped)
     pub visits_inserted: usize,
     /// Number of metadata records inserted
     pub metadata_inserted: usize,
@@ -206,6 +304,14 @@ pub struct SearchParams {
     pub query: Option<String>,
     /// Filter by domain
     pub domain: Option<String>,
+    /// Filter by URL category
+    pub category: Option<String>,
+    /// Filter by tag name
+    pub tag: Option<String>,
+    /// Filter by keyword
+    pub keyword: Option<String>,
+    /// Only pages behind a paywall or login wall (true) or only readable ones (false)
+    pub walled: Option<bool>,
     /// Start date range
     pub start_date: Option<DateTime<Utc>>,
     /// End date range
@@ -230,6 +336,8 @@ pub struct SearchResult {
     pub url: UrlRecord,
     /// Optional metadata for the URL
     pub metadata: Option<MetadataRecord>,
+    /// Tag names assigned to the URL
+    pub tags: Vec<String>,
     /// Count of visits to this URL
     pub visit_count: usize,
     /// Most recent visit
@@ -240,7 +348,7 @@ pub struct SearchResult {
 pub fn search_history(conn: &DatabaseConnection, params: &SearchParams) -> Result<SearchResults> {
     conn.with_connection(|tx| {
         // Build the query based on the parameters
-        let mut query = String::from(
+        let mut query = QueryBuilder::new(
             "SELECT u.id, u.url, u.title, u.domain, u.first_seen, u.last_seen,
                     COUNT(v.id) as visit_count,
                     MAX(v.visited_at) as last_visit
@@ -248,68 +356,75 @@ pub fn search_history(conn: &DatabaseConnection, params: &SearchParams) -> Resul
              LEFT JOIN visit v ON u.id = v.url_id"
         );
         
-        let mut where_clauses = Vec::new();
-        let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-        
         // Add search conditions
         if let Some(q) = &params.query {
-            where_clauses.push(
+            let like_pattern = format!("%{}%", q);
+            query.filter_many(
                 "(u.url LIKE ? OR u.title LIKE ? OR EXISTS (
                     SELECT 1 FROM metadata m 
                     WHERE m.url_id = u.id AND (
                         m.summary LIKE ? OR 
-                        m.keywords LIKE ? OR 
-                        m.tags LIKE ?
+                        m.keywords LIKE ?
                     )
-                ))".to_string()
+                ) OR EXISTS (
+                    SELECT 1 FROM url_tag ut JOIN tag t ON t.id = ut.tag_id
+                    WHERE ut.url_id = u.id AND t.name LIKE ?
+                ))",
+                vec![
+                    Box::new(like_pattern.clone()),
+                    Box::new(like_pattern.clone()),
+                    Box::new(like_pattern.clone()),
+                    Box::new(like_pattern.clone()),
+                    Box::new(like_pattern),
+                ],
             );
-            
-            let like_pattern = format!("%{}%", q);
-            query_params.push(Box::new(like_pattern.clone()));
-            query_params.push(Box::new(like_pattern.clone()));
-            query_params.push(Box::new(like_pattern.clone()));
-            query_params.push(Box::new(like_pattern.clone()));
-            query_params.push(Box::new(like_pattern));
         }
         
         if let Some(domain) = &params.domain {
-            where_clauses.push("u.domain = ?".to_string());
-            query_params.push(Box::new(domain.clone()));
+            query.filter("u.domain = ?", domain.clone());
         }
         
-        if let Some(start) = params.start_date {
-            where_clauses.push("v.visited_at >= ?".to_string());
-            query_params.push(Box::new(start.timestamp()));
+        if let Some(category) = &params.category {
+            query.filter("u.category = ?", category.clone());
         }
         
-        if let Some(end) = params.end_date {
-            where_clauses.push("v.visited_at <= ?".to_string());
-            query_params.push(Box::new(end.timestamp()));
+        if let Some(tag) = &params.tag {
+            query.filter(
+                "EXISTS (SELECT 1 FROM url_tag ut JOIN tag t ON t.id = ut.tag_id WHERE ut.url_id = u.id AND t.name = ?)",
+                tag.clone(),
+            );
+        }
+        
+        if let Some(keyword) = &params.keyword {
+            query.filter(
+                "EXISTS (SELECT 1 FROM url_keyword uk JOIN keyword k ON k.id = uk.keyword_id WHERE uk.url_id = u.id AND k.name = ?)",
+                keyword.trim().to_lowercase(),
+            );
         }
         
-        // Add WHERE clause if we have conditions
-        if !where_clauses.is_empty() {
-            query.push_str(" WHERE ");
-            query.push_str(&where_clauses.join(" AND "));
+        if let Some(walled) = params.walled {
+            query.condition(if walled {
+                "EXISTS (SELECT 1 FROM metadata m WHERE m.url_id = u.id AND m.access_wall IS NOT NULL)"
+            } else {
+                "NOT EXISTS (SELECT 1 FROM metadata m WHERE m.url_id = u.id AND m.access_wall IS NOT NULL)"
+            });
         }
         
-        // Add GROUP BY and ORDER BY
-        query.push_str(" GROUP BY u.id ORDER BY last_visit DESC");
+        query.date_range("v.visited_at", params.start_date, params.end_date);
+        
+        query.group_by("u.id").order_by("last_visit DESC");
         
-        // Add LIMIT and OFFSET
         if let Some(limit) = params.limit {
-            query.push_str(&format!(" LIMIT {}", limit));
+            query.limit(limit);
         }
         
         if let Some(offset) = params.offset {
-            query.push_str(&format!(" OFFSET {}", offset));
+            query.offset(offset);
         }
         
         // Execute the query
-        let mut stmt = tx.prepare(&query)?;
-        
-        let url_rows = stmt.query_map(rusqlite::params_from_iter(query_params.iter().map(|p| p.as_ref())), |row| {
-            let url = UrlRecord::from_row(row)?;
+        let url_rows = query.fetch_all(tx, |row| {
+            let url = UrlRecord::from_row(row).map_err(row_error)?;
             let visit_count: i64 = row.get(6)?;
             let last_visit_ts: Option<i64> = row.get(7)?;
             
@@ -322,15 +437,15 @@ pub fn search_history(conn: &DatabaseConnection, params: &SearchParams) -> Resul
         
         // Collect results
         let mut urls = Vec::new();
-        for row_result in url_rows {
-            let (url, visit_count, last_visit) = row_result?;
-            
-            // Get metadata for this URL
+        for (url, visit_count, last_visit) in url_rows {
+            // Get metadata and tags for this URL
             let metadata = get_metadata_for_url(tx, url.id)?;
+            let tags = get_url_tags(tx, &url.id.to_string())?;
             
             urls.push(SearchResult {
                 url,
                 metadata,
+                tags,
                 visit_count,
                 last_visit,
             });
@@ -338,21 +453,7 @@ pub fn search_history(conn: &DatabaseConnection, params: &SearchParams) -> Resul
         
         // Get total count (without limit/offset)
         let total_count = if params.limit.is_some() || params.offset.is_some() {
-            // Build count query with same WHERE clauses
-            let mut count_query = String::from("SELECT COUNT(DISTINCT u.id) FROM url u LEFT JOIN visit v ON u.id = v.url_id");
-            
-            if !where_clauses.is_empty() {
-                count_query.push_str(" WHERE ");
-                count_query.push_str(&where_clauses.join(" AND "));
-            }
-            
-            let count: i64 = tx.query_row(
-                &count_query,
-                rusqlite::params_from_iter(query_params.iter().map(|p| p.as_ref())),
-                |row| row.get(0),
-            )?;
-            
-            count as usize
+            query.count(tx)?
         } else {
             urls.len()
         };
@@ -365,20 +466,23 @@ pub fn search_history(conn: &DatabaseConnection, params: &SearchParams) -> Resul
 }
 
 /// Gets metadata for a URL
-fn get_metadata_for_url(conn: &Connection, url_id: Uuid) -> Result<Option<MetadataRecord>> {
-    match conn.query_row(
-        "SELECT url_id, summary, keywords, tags, topic_cluster, is_enriched
+pub(crate) fn get_metadata_for_url(conn: &Connection, url_id: Uuid) -> Result<Option<MetadataRecord>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT url_id, summary, keywords, topic_cluster, is_enriched, access_wall
          FROM metadata WHERE url_id = ?",
+    )?;
+    match stmt.query_row(
         [url_id.to_string()],
         |row| MetadataRecord::from_row(row),
     ) {
         Ok(metadata) => Ok(Some(metadata)),
         Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(DatabaseError::Query(e.to_string())),
+        Err(e) => Err(DatabaseError::from(e)),
     }
 }
 
 /// Statistics about the browsing history
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryStats {
     /// Total number of URLs
     pub url_count: usize,
@@ -396,85 +500,236 @@ pub struct HistoryStats {
     pub top_domains: Vec<(String, usize)>,
 }
 
-/// Gets statistics about the browsing history
-pub fn get_stats(conn: &DatabaseConnection) -> Result<HistoryStats> {
-    conn.with_connection(|tx| {
-        // Get URL and visit counts
-        let url_count: i64 = tx.query_row(
-            "SELECT COUNT(*) FROM url",
-            [],
-            |row| row.get(0),
-        )?;
-        
-        let visit_count: i64 = tx.query_row(
-            "SELECT COUNT(*) FROM visit",
-            [],
-            |row| row.get(0),
-        )?;
-        
-        let domain_count: i64 = tx.query_row(
-            "SELECT COUNT(DISTINCT domain) FROM url",
-            [],
-            |row| row.get(0),
-        )?;
-        
-        let enriched_count: i64 = tx.query_row(
-            "SELECT COUNT(*) FROM metadata WHERE is_enriched = 1",
-            [],
-            |row| row.get(0),
+/// A page offered while the user types
+#[derive(Debug, Clone, Serialize)]
+pub struct Suggestion {
+    /// URL id
+    pub id: String,
+    /// Full URL
+    pub url: String,
+    /// Page title
+    pub title: Option<String>,
+    /// Domain name
+    pub domain: String,
+    /// Number of visits
+    pub visit_count: usize,
+}
+
+/// Suggests pages whose URL or title contains the text, domains and titles
+/// starting with it first, then the most visited
+pub fn suggest(conn: &DatabaseConnection, text: &str, limit: usize) -> Result<Vec<Suggestion>> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    let contains = format!("%{}%", text);
+    let prefix = format!("{}%", text);
+
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT u.id, u.url, u.title, u.domain,
+                    (SELECT COUNT(*) FROM visit v WHERE v.url_id = u.id) AS visit_count
+             FROM url u
+             WHERE u.url LIKE ?1 OR u.title LIKE ?1
+             ORDER BY (u.domain LIKE ?2 OR u.domain LIKE 'www.' || ?2 OR u.title LIKE ?2) DESC,
+                      visit_count DESC
+             LIMIT ?3",
         )?;
-        
-        // Get first and last visit times
-        let first_visit_ts: Option<i64> = tx.query_row(
-            "SELECT MIN(visited_at) FROM visit",
+        let rows = stmt.query_map(params![contains, prefix, limit as i64], |row| {
+            Ok(Suggestion {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                title: row.get(2)?,
+                domain: row.get(3)?,
+                visit_count: row.get::<_, i64>(4)? as usize,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
+
+/// A URL and its title, as needed to open or share it
+#[derive(Debug, Clone, Serialize)]
+pub struct PageLink {
+    /// URL id
+    pub id: String,
+    /// Full URL
+    pub url: String,
+    /// Page title
+    pub title: Option<String>,
+}
+
+/// Gets the URLs with the given ids, in the order given; unknown ids are skipped
+pub fn get_page_links(conn: &DatabaseConnection, ids: &[String]) -> Result<Vec<PageLink>> {
+    conn.with_statement("SELECT id, url, title FROM url WHERE id = ?", |stmt| {
+        let mut links = Vec::with_capacity(ids.len());
+        for id in ids {
+            let link = stmt.query_row([id], |row| Ok(PageLink {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                title: row.get(2)?,
+            })).optional()?;
+            links.extend(link);
+        }
+        Ok(links)
+    })
+}
+
+/// Gets statistics about the browsing history, from the cache unless the
+/// history changed since they were computed
+pub fn get_stats(conn: &DatabaseConnection) -> Result<HistoryStats> {
+    conn.with_connection(|c| {
+        let cached: Option<String> = c.query_row(
+            "SELECT stats FROM stats_cache WHERE id = 1 AND dirty = 0",
             [],
             |row| row.get(0),
-        )?;
+        ).optional()?.flatten();
         
-        let last_visit_ts: Option<i64> = tx.query_row(
-            "SELECT MAX(visited_at) FROM visit",
-            [],
-            |row| row.get(0),
-        )?;
+        if let Some(stats) = cached.and_then(|json| serde_json::from_str(&json).ok()) {
+            return Ok(stats);
+        }
         
-        let first_visit = first_visit_ts.map(|ts| {
-            DateTime::from_timestamp(ts, 0).unwrap_or_else(|| Utc::now())
-        });
+        // Claim the cache first: a write while computing marks it dirty again,
+        // and the result below is then not stored as current
+        c.execute("UPDATE stats_cache SET dirty = 0, stats = NULL WHERE id = 1", [])?;
         
-        let last_visit = last_visit_ts.map(|ts| {
-            DateTime::from_timestamp(ts, 0).unwrap_or_else(|| Utc::now())
-        });
+        let stats = compute_stats(c)?;
         
-        // Get top domains
-        let mut stmt = tx.prepare(
-            "SELECT domain, COUNT(*) as count
-             FROM url u
-             JOIN visit v ON u.id = v.url_id
-             GROUP BY domain
-             ORDER BY count DESC
-             LIMIT 10"
+        let json = serde_json::to_string(&stats)
+            .map_err(|e| DatabaseError::Data(e.to_string()))?;
+        c.execute(
+            "UPDATE stats_cache SET stats = ?, computed_at = ? WHERE id = 1 AND dirty = 0",
+            params![json, Utc::now().timestamp()],
         )?;
         
-        let domain_rows = stmt.query_map([], |row| {
-            let domain: String = row.get(0)?;
-            let count: i64 = row.get(1)?;
-            Ok((domain, count as usize))
-        })?;
-        
-        let mut top_domains = Vec::new();
-        for row_result in domain_rows {
-            top_domains.push(row_result?);
-        }
-        
-        Ok(HistoryStats {
-            url_count: url_count as usize,
-            visit_count: visit_count as usize,
-            domain_count: domain_count as usize,
-            first_visit,
-            last_visit,
-            enriched_count: enriched_count as usize,
-            top_domains,
-        })
+        Ok(stats)
+    })
+}
+
+/// Counts the visits at or after `since`
+pub fn count_visits_since(conn: &DatabaseConnection, since: DateTime<Utc>) -> Result<usize> {
+    conn.with_statement("SELECT COUNT(*) FROM visit WHERE visited_at >= ?", |stmt| {
+        let count: i64 = stmt.query_row([since.timestamp()], |row| row.get(0))?;
+        Ok(count as usize)
+    })
+}
+
+/// Default number of visits returned by `get_recent`
+pub const DEFAULT_RECENT_LIMIT: usize = 20;
+
+/// Most visits returned by `get_recent`
+pub const MAX_RECENT_LIMIT: usize = 200;
+
+/// A recent visit, for the "jump back in" list
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentVisit {
+    /// Visit id
+    pub visit_id: String,
+    /// URL id
+    pub url_id: String,
+    /// The URL
+    pub url: String,
+    /// Page title, if known
+    pub title: Option<String>,
+    /// Domain of the URL
+    pub domain: String,
+    /// Favicon of the domain
+    pub favicon: String,
+    /// When the visit occurred
+    pub visited_at: DateTime<Utc>,
+}
+
+/// Gets the latest visits, newest first, optionally only those after `since`.
+/// Walks the visited_at index backwards, so it stays cheap enough to poll.
+pub fn get_recent(conn: &DatabaseConnection, limit: usize, since: Option<DateTime<Utc>>) -> Result<Vec<RecentVisit>> {
+    conn.with_statement(
+        "SELECT v.id, u.id, u.url, u.title, u.domain,
+                COALESCE(ds.favicon, 'https://' || u.domain || '/favicon.ico'), v.visited_at
+         FROM visit v INDEXED BY idx_visit_visited_at
+         JOIN url u ON u.id = v.url_id
+         LEFT JOIN domain_stats ds ON ds.domain = u.domain
+         WHERE v.visited_at > ?
+         ORDER BY v.visited_at DESC
+         LIMIT ?",
+        |stmt| {
+            let since = since.map_or(i64::MIN, |since| since.timestamp());
+            let limit = limit.clamp(1, MAX_RECENT_LIMIT) as i64;
+            let rows = stmt.query_map(params![since, limit], |row| {
+                Ok(RecentVisit {
+                    visit_id: row.get(0)?,
+                    url_id: row.get(1)?,
+                    url: row.get(2)?,
+                    title: row.get(3)?,
+                    domain: row.get(4)?,
+                    favicon: row.get(5)?,
+                    visited_at: DateTime::from_timestamp(row.get(6)?, 0).unwrap_or_default(),
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+        },
+    )
+}
+
+/// Computes the statistics with one aggregate query each
+fn compute_stats(c: &Connection) -> Result<HistoryStats> {
+    // Get URL and visit counts
+    let url_count: i64 = c.query_row(
+        "SELECT COUNT(*) FROM url",
+        [],
+        |row| row.get(0),
+    )?;
+    
+    let visit_count: i64 = c.query_row(
+        "SELECT COUNT(*) FROM visit",
+        [],
+        |row| row.get(0),
+    )?;
+    
+    refresh_domain_stats(c)?;
+    let domain_count: i64 = c.query_row(
+        "SELECT COUNT(*) FROM domain_stats",
+        [],
+        |row| row.get(0),
+    )?;
+    
+    let enriched_count: i64 = c.query_row(
+        "SELECT COUNT(*) FROM metadata WHERE is_enriched = 1",
+        [],
+        |row| row.get(0),
+    )?;
+    
+    // Get first and last visit times
+    let first_visit_ts: Option<i64> = c.query_row(
+        "SELECT MIN(visited_at) FROM visit",
+        [],
+        |row| row.get(0),
+    )?;
+    
+    let last_visit_ts: Option<i64> = c.query_row(
+        "SELECT MAX(visited_at) FROM visit",
+        [],
+        |row| row.get(0),
+    )?;
+    
+    let first_visit = first_visit_ts.map(|ts| {
+        DateTime::from_timestamp(ts, 0).unwrap_or_else(|| Utc::now())
+    });
+    
+    let last_visit = last_visit_ts.map(|ts| {
+        DateTime::from_timestamp(ts, 0).unwrap_or_else(|| Utc::now())
+    });
+    
+    // Get top domains
+    let top_domains = top_domains(c, 10)?;
+    
+    Ok(HistoryStats {
+        url_count: url_count as usize,
+        visit_count: visit_count as usize,
+        domain_count: domain_count as usize,
+        first_visit,
+        last_visit,
+        enriched_count: enriched_count as usize,
+        top_domains,
     })
 }
 
@@ -511,7 +766,7 @@ pub enum TimelineItem {
         /// Timestamp for this hour (for display purposes)
         timestamp: DateTime<Utc>,
         /// Optional sample of URLs visited in this hour
-        urls: Option<Vec<crate::db::models::UrlWithVisits>>,
+        urls: Option<Vec<UrlWithVisits>>,
     },
     /// Daily grouping item
     Daily {
@@ -520,7 +775,7 @@ pub enum TimelineItem {
         /// Number of visits on this day
         count: u32,
         /// Optional sample of URLs visited on this day
-        urls: Option<Vec<crate::db::models::UrlWithVisits>>,
+        urls: Option<Vec<UrlWithVisits>>,
     },
     /// Domain grouping item
     Domain {
@@ -529,7 +784,7 @@ pub enum TimelineItem {
         /// Number of visits to this domain
         count: u32,
         /// Optional sample of URLs for this domain
-        urls: Option<Vec<crate::db::models::UrlWithVisits>>,
+        urls: Option<Vec<UrlWithVisits>>,
     },
 }
 
@@ -539,20 +794,29 @@ pub fn get_timeline_data(
     params: &TimelineParams,
 ) -> Result<Vec<TimelineItem>> {
     // Use existing connection to perform query
-    conn.use_connection(|c| match params.group_by {
+    conn.with_connection(|c| match params.group_by {
         TimelineGrouping::Hour => get_hourly_timeline_data(c, params),
         TimelineGrouping::Day => get_daily_timeline_data(c, params),
         TimelineGrouping::Domain => get_domain_timeline_data(c, params),
     })
 }
 
+/// Applies the date range and domain filters shared by all timeline queries
+fn apply_timeline_filters(query: &mut QueryBuilder, params: &TimelineParams) {
+    query.date_range("visit.visited_at", params.start_date, params.end_date);
+    
+    if let Some(ref domain) = params.domain {
+        query.filter("url.domain = ?", domain.clone());
+    }
+}
+
 /// Gets timeline data grouped by hour of day
 fn get_hourly_timeline_data(
     conn: &Connection,
     params: &TimelineParams,
 ) -> Result<Vec<TimelineItem>> {
     // Build query to group visits by hour of day
-    let mut query = String::from(
+    let mut query = QueryBuilder::new(
         "SELECT 
             strftime('%H', datetime(visited_at, 'unixepoch')) as hour,
             COUNT(*) as count,
@@ -561,37 +825,12 @@ fn get_hourly_timeline_data(
          JOIN url ON visit.url_id = url.id"
     );
     
-    // Add WHERE clauses for filters
-    let mut conditions = Vec::new();
-    let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-    
-    if let Some(ref start_date) = params.start_date {
-        conditions.push("visited_at >= ?");
-        query_params.push(Box::new(start_date.timestamp()));
-    }
-    
-    if let Some(ref end_date) = params.end_date {
-        conditions.push("visited_at <= ?");
-        query_params.push(Box::new(end_date.timestamp()));
-    }
-    
-    if let Some(ref domain) = params.domain {
-        conditions.push("url.domain = ?");
-        query_params.push(Box::new(domain.clone()));
-    }
-    
-    if !conditions.is_empty() {
-        query.push_str(" WHERE ");
-        query.push_str(&conditions.join(" AND "));
-    }
+    apply_timeline_filters(&mut query, params);
     
     // Group by hour and order by visit count
-    query.push_str(" GROUP BY hour ORDER BY count DESC, hour ASC");
+    query.group_by("hour").order_by("count DESC, hour ASC");
     
-    // Execute query
-    let mut stmt = conn.prepare(&query)?;
-    
-    let results = stmt.query_map(rusqlite::params_from_iter(query_params.iter().map(|p| p.as_ref())), |row| {
+    let mut timeline_items = query.fetch_all(conn, |row| {
         let hour_str: String = row.get(0)?;
         let count: u32 = row.get(1)?;
         let timestamp: i64 = row.get(2)?;
@@ -611,11 +850,6 @@ fn get_hourly_timeline_data(
         })
     })?;
     
-    let mut timeline_items = Vec::new();
-    for result in results {
-        timeline_items.push(result?);
-    }
-    
     // Fetch sample URLs for each hour (if timeline items exist)
     if !timeline_items.is_empty() {
         fetch_sample_urls_for_timeline(&mut timeline_items, conn, params)?;
@@ -630,7 +864,7 @@ fn get_daily_timeline_data(
     params: &TimelineParams,
 ) -> Result<Vec<TimelineItem>> {
     // Build query to group visits by day
-    let mut query = String::from(
+    let mut query = QueryBuilder::new(
         "SELECT 
             strftime('%Y-%m-%d', datetime(visited_at, 'unixepoch')) as day,
             COUNT(*) as count,
@@ -639,37 +873,12 @@ fn get_daily_timeline_data(
          JOIN url ON visit.url_id = url.id"
     );
     
-    // Add WHERE clauses for filters
-    let mut conditions = Vec::new();
-    let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-    
-    if let Some(ref start_date) = params.start_date {
-        conditions.push("visited_at >= ?");
-        query_params.push(Box::new(start_date.timestamp()));
-    }
-    
-    if let Some(ref end_date) = params.end_date {
-        conditions.push("visited_at <= ?");
-        query_params.push(Box::new(end_date.timestamp()));
-    }
-    
-    if let Some(ref domain) = params.domain {
-        conditions.push("url.domain = ?");
-        query_params.push(Box::new(domain.clone()));
-    }
-    
-    if !conditions.is_empty() {
-        query.push_str(" WHERE ");
-        query.push_str(&conditions.join(" AND "));
-    }
+    apply_timeline_filters(&mut query, params);
     
     // Group by day and order by date (newest first)
-    query.push_str(" GROUP BY day ORDER BY day DESC");
+    query.group_by("day").order_by("day DESC");
     
-    // Execute query
-    let mut stmt = conn.prepare(&query)?;
-    
-    let results = stmt.query_map(rusqlite::params_from_iter(query_params.iter().map(|p| p.as_ref())), |row| {
+    let mut timeline_items = query.fetch_all(conn, |row| {
         let _day_str: String = row.get(0)?;
         let count: u32 = row.get(1)?;
         let timestamp: i64 = row.get(2)?;
@@ -685,11 +894,6 @@ fn get_daily_timeline_data(
         })
     })?;
     
-    let mut timeline_items = Vec::new();
-    for result in results {
-        timeline_items.push(result?);
-    }
-    
     // Fetch sample URLs for each day (if timeline items exist)
     if !timeline_items.is_empty() {
         fetch_sample_urls_for_timeline(&mut timeline_items, conn, params)?;
@@ -704,7 +908,7 @@ fn get_domain_timeline_data(
     params: &TimelineParams,
 ) -> Result<Vec<TimelineItem>> {
     // Build query to group visits by domain
-    let mut query = String::from(
+    let mut query = QueryBuilder::new(
         "SELECT 
             url.domain,
             COUNT(*) as count
@@ -712,37 +916,12 @@ fn get_domain_timeline_data(
          JOIN url ON visit.url_id = url.id"
     );
     
-    // Add WHERE clauses for filters
-    let mut conditions = Vec::new();
-    let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-    
-    if let Some(ref start_date) = params.start_date {
-        conditions.push("visited_at >= ?");
-        query_params.push(Box::new(start_date.timestamp()));
-    }
-    
-    if let Some(ref end_date) = params.end_date {
-        conditions.push("visited_at <= ?");
-        query_params.push(Box::new(end_date.timestamp()));
-    }
-    
-    if let Some(ref domain) = params.domain {
-        conditions.push("url.domain = ?");
-        query_params.push(Box::new(domain.clone()));
-    }
-    
-    if !conditions.is_empty() {
-        query.push_str(" WHERE ");
-        query.push_str(&conditions.join(" AND "));
-    }
+    apply_timeline_filters(&mut query, params);
     
     // Group by domain and order by visit count
-    query.push_str(" GROUP BY url.domain ORDER BY count DESC LIMIT 100");
+    query.group_by("url.domain").order_by("count DESC").limit(100);
     
-    // Execute query
-    let mut stmt = conn.prepare(&query)?;
-    
-    let results = stmt.query_map(rusqlite::params_from_iter(query_params.iter().map(|p| p.as_ref())), |row| {
+    let mut timeline_items = query.fetch_all(conn, |row| {
         let domain: String = row.get(0)?;
         let count: u32 = row.get(1)?;
         
@@ -753,11 +932,6 @@ fn get_domain_timeline_data(
         })
     })?;
     
-    let mut timeline_items = Vec::new();
-    for result in results {
-        timeline_items.push(result?);
-    }
-    
     // Fetch sample URLs for each domain (if timeline items exist)
     if !timeline_items.is_empty() {
         fetch_sample_urls_for_timeline(&mut timeline_items, conn, params)?;
@@ -766,151 +940,212 @@ fn get_domain_timeline_data(
     Ok(timeline_items)
 }
 
+/// Base query selecting URLs with their visit aggregates, used for timeline samples
+const URL_WITH_VISITS_QUERY: &str =
+    "SELECT url.id, url.url, url.title, url.domain, 
+     COUNT(visit.id) as visit_count,
+     MAX(visit.visited_at) as last_visit
+     FROM visit
+     JOIN url ON visit.url_id = url.id";
+
+/// Number of sample URLs attached to each timeline item
+const TIMELINE_SAMPLE_SIZE: usize = 5;
+
+/// Default page size for bucket drill-down
+pub const DEFAULT_BUCKET_PAGE_SIZE: usize = 50;
+
+/// Identifies a single timeline bucket (as returned by `get_timeline_data`)
+pub enum TimelineBucket {
+    /// Hour of day (0-23)
+    Hour(u8),
+    /// Day in `YYYY-MM-DD` format (UTC)
+    Day(String),
+    /// Domain name
+    Domain(String),
+}
+
+impl TimelineBucket {
+    /// Returns the bucket a timeline item belongs to
+    fn from_item(item: &TimelineItem) -> Self {
+        match item {
+            TimelineItem::Hourly { hour, .. } => TimelineBucket::Hour(*hour),
+            TimelineItem::Daily { date, .. } => TimelineBucket::Day(date.format("%Y-%m-%d").to_string()),
+            TimelineItem::Domain { domain, .. } => TimelineBucket::Domain(domain.clone()),
+        }
+    }
+    
+    /// Value `bucket_expr` gives for the visits in this bucket
+    fn key(&self) -> String {
+        match self {
+            TimelineBucket::Hour(hour) => format!("{:02}", hour),
+            TimelineBucket::Day(day) => day.clone(),
+            TimelineBucket::Domain(domain) => domain.clone(),
+        }
+    }
+}
+
+/// SQL expression giving the bucket a visit falls into for a grouping
+fn bucket_expr(group_by: &TimelineGrouping) -> &'static str {
+    match group_by {
+        TimelineGrouping::Hour => "strftime('%H', datetime(visit.visited_at, 'unixepoch'))",
+        TimelineGrouping::Day => "strftime('%Y-%m-%d', datetime(visit.visited_at, 'unixepoch'))",
+        TimelineGrouping::Domain => "url.domain",
+    }
+}
+
+/// A page of URLs belonging to a timeline bucket
+pub struct BucketUrlPage {
+    /// URLs on this page, most visited first
+    pub urls: Vec<UrlWithVisits>,
+    /// Total number of URLs in the bucket
+    pub total_count: usize,
+    /// Zero-based page index
+    pub page: usize,
+    /// Number of URLs per page
+    pub page_size: usize,
+}
+
+/// Builds the query selecting the URLs visited within a bucket, honouring the timeline filters
+fn bucket_urls_query(bucket: &TimelineBucket, params: &TimelineParams) -> QueryBuilder {
+    let mut query = QueryBuilder::new(URL_WITH_VISITS_QUERY);
+    
+    let grouping = match bucket {
+        TimelineBucket::Hour(_) => TimelineGrouping::Hour,
+        TimelineBucket::Day(_) => TimelineGrouping::Day,
+        TimelineBucket::Domain(_) => TimelineGrouping::Domain,
+    };
+    query.filter(&format!("{} = ?", bucket_expr(&grouping)), bucket.key());
+    
+    apply_timeline_filters(&mut query, params);
+    
+    query.group_by("url.id").order_by("visit_count DESC, last_visit DESC, url.id");
+    query
+}
+
+/// Gets the full, paginated list of URLs for a single timeline bucket
+pub fn get_timeline_bucket_urls(
+    conn: &DatabaseConnection,
+    params: &TimelineParams,
+    bucket: &TimelineBucket,
+    page: usize,
+    page_size: usize,
+) -> Result<BucketUrlPage> {
+    let page_size = if page_size == 0 { DEFAULT_BUCKET_PAGE_SIZE } else { page_size };
+    let offset = page.checked_mul(page_size)
+        .filter(|&offset| i64::try_from(offset).is_ok())
+        .ok_or_else(|| DatabaseError::Data(format!("Page {} is out of range", page)))?;
+    
+    conn.with_connection(|c| {
+        let mut query = bucket_urls_query(bucket, params);
+        let total_count = query.count(c)?;
+        
+        query.limit(page_size).offset(offset);
+        let urls = fetch_urls_with_visits(c, &query)?;
+        
+        Ok(BucketUrlPage {
+            urls,
+            total_count,
+            page,
+            page_size,
+        })
+    })
+}
+
 /// Helper function to fetch sample URLs for timeline items
+///
+/// One windowed query ranks the URLs within every bucket at once and keeps
+/// the top `TIMELINE_SAMPLE_SIZE` of each, in the order `bucket_urls_query`
+/// lists them.
 fn fetch_sample_urls_for_timeline(
     timeline_items: &mut Vec<TimelineItem>,
     conn: &Connection,
     params: &TimelineParams,
 ) -> Result<()> {
-    // For each timeline item, fetch a sample of URLs
+    let bucket = bucket_expr(&params.group_by);
+    let mut ranked = QueryBuilder::new(&format!(
+        "SELECT url.id, url.url, url.title, url.domain,
+         COUNT(visit.id) as visit_count,
+         MAX(visit.visited_at) as last_visit,
+         {bucket} as bucket,
+         ROW_NUMBER() OVER (
+             PARTITION BY {bucket}
+             ORDER BY COUNT(visit.id) DESC, MAX(visit.visited_at) DESC, url.id
+         ) as sample_rank
+         FROM visit
+         JOIN url ON visit.url_id = url.id",
+        bucket = bucket,
+    ));
+    apply_timeline_filters(&mut ranked, params);
+    ranked.group_by("bucket, url.id");
+    
+    let sql = format!(
+        "SELECT id, url, title, domain, visit_count, last_visit, bucket
+         FROM ({}) WHERE sample_rank <= {} ORDER BY bucket, sample_rank",
+        ranked.sql(),
+        TIMELINE_SAMPLE_SIZE,
+    );
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let rows = stmt.query_map(ranked.params().as_slice(), |row| {
+        Ok((row.get::<_, String>(6)?, url_with_visits_from_row(row)?))
+    })?;
+    
+    let mut samples: HashMap<String, Vec<UrlWithVisits>> = HashMap::new();
+    for row in rows {
+        let (bucket, url) = row?;
+        samples.entry(bucket).or_default().push(url);
+    }
+    
     for item in timeline_items.iter_mut() {
+        let sample = samples.remove(&TimelineBucket::from_item(item).key()).unwrap_or_default();
+        
         match item {
-            TimelineItem::Hourly { hour, timestamp, urls, .. } => {
-                // Fetch sample URLs for this hour
-                let mut query = String::from(
-                    "SELECT url.id, url.url, url.title, url.domain, 
-                     COUNT(visit.id) as visit_count,
-                     MAX(visit.visited_at) as last_visit
-                     FROM visit
-                     JOIN url ON visit.url_id = url.id
-                     WHERE strftime('%H', datetime(visited_at, 'unixepoch')) = ?"
-                );
-                
-                let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-                query_params.push(Box::new(format!("{:02}", hour)));
-                
-                if let Some(ref start_date) = params.start_date {
-                    query.push_str(" AND visited_at >= ?");
-                    query_params.push(Box::new(start_date.timestamp()));
-                }
-                
-                if let Some(ref end_date) = params.end_date {
-                    query.push_str(" AND visited_at <= ?");
-                    query_params.push(Box::new(end_date.timestamp()));
-                }
-                
-                if let Some(ref domain) = params.domain {
-                    query.push_str(" AND url.domain = ?");
-                    query_params.push(Box::new(domain.clone()));
-                }
-                
-                query.push_str(" GROUP BY url.id ORDER BY visit_count DESC LIMIT 5");
-                
-                *urls = Some(fetch_urls_with_query(conn, &query, &query_params)?);
-            },
-            TimelineItem::Daily { date, urls, .. } => {
-                // Extract day string from the date
-                let day_str = date.format("%Y-%m-%d").to_string();
-                
-                // Fetch sample URLs for this day
-                let mut query = String::from(
-                    "SELECT url.id, url.url, url.title, url.domain, 
-                     COUNT(visit.id) as visit_count,
-                     MAX(visit.visited_at) as last_visit
-                     FROM visit
-                     JOIN url ON visit.url_id = url.id
-                     WHERE strftime('%Y-%m-%d', datetime(visited_at, 'unixepoch')) = ?"
-                );
-                
-                let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-                query_params.push(Box::new(day_str));
-                
-                if let Some(ref domain) = params.domain {
-                    query.push_str(" AND url.domain = ?");
-                    query_params.push(Box::new(domain.clone()));
-                }
-                
-                query.push_str(" GROUP BY url.id ORDER BY visit_count DESC LIMIT 5");
-                
-                *urls = Some(fetch_urls_with_query(conn, &query, &query_params)?);
-            },
-            TimelineItem::Domain { domain, urls, .. } => {
-                // Fetch sample URLs for this domain
-                let mut query = String::from(
-                    "SELECT url.id, url.url, url.title, url.domain, 
-                     COUNT(visit.id) as visit_count,
-                     MAX(visit.visited_at) as last_visit
-                     FROM visit
-                     JOIN url ON visit.url_id = url.id
-                     WHERE url.domain = ?"
-                );
-                
-                let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-                query_params.push(Box::new(domain.clone()));
-                
-                if let Some(ref start_date) = params.start_date {
-                    query.push_str(" AND visited_at >= ?");
-                    query_params.push(Box::new(start_date.timestamp()));
-                }
-                
-                if let Some(ref end_date) = params.end_date {
-                    query.push_str(" AND visited_at <= ?");
-                    query_params.push(Box::new(end_date.timestamp()));
-                }
-                
-                query.push_str(" GROUP BY url.id ORDER BY visit_count DESC LIMIT 5");
-                
-                *urls = Some(fetch_urls_with_query(conn, &query, &query_params)?);
-            },
+            TimelineItem::Hourly { urls, .. }
+            | TimelineItem::Daily { urls, .. }
+            | TimelineItem::Domain { urls, .. } => *urls = Some(sample),
         }
     }
     
     Ok(())
 }
 
-/// Helper function to fetch URLs with a given query
-fn fetch_urls_with_query(
+/// Helper function to fetch URLs with visit aggregates using a built query
+///
+/// The query must select `id, url, title, domain, visit_count, last_visit` in that order.
+fn fetch_urls_with_visits(
     conn: &Connection,
-    query: &str,
-    params: &[Box<dyn rusqlite::ToSql>],
-) -> Result<Vec<crate::db::models::UrlWithVisits>> {
-    let mut stmt = conn.prepare(query)?;
-    
-    let url_iter = stmt.query_map(rusqlite::params_from_iter(params.iter().map(|p| p.as_ref())), |row| {
-        let id_str: String = row.get(0)?;
-        let url: String = row.get(1)?;
-        let title: Option<String> = row.get(2)?;
-        let domain: String = row.get(3)?;
-        let visit_count: i32 = row.get(4)?;
-        let last_visit_ts: Option<i64> = row.get(5)?;
-        
-        // Parse UUID from string
-        let id = Uuid::parse_str(&id_str)
-            .map_err(|e| rusqlite::Error::InvalidColumnType(0, format!("Invalid UUID: {}", e)))?;
-        
-        // Convert timestamp to DateTime if available
-        let last_visit = last_visit_ts.map(|ts| {
-            DateTime::from_timestamp(ts, 0).unwrap_or_else(|| Utc::now())
-        });
-        
-        Ok(crate::db::models::UrlWithVisits {
-            url: crate::db::models::UrlRecord {
-                id,
-                url,
-                title,
-                domain,
-                first_seen: Utc::now(), // Not used in this context
-                last_seen: Utc::now(),  // Not used in this context
-            },
-            visit_count: visit_count as usize,
-            last_visit,
-        })
-    })?;
+    query: &QueryBuilder,
+) -> Result<Vec<UrlWithVisits>> {
+    query.fetch_all(conn, url_with_visits_from_row)
+}
+
+/// Maps a row starting with `id, url, title, domain, visit_count, last_visit`
+fn url_with_visits_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<UrlWithVisits> {
+    let id_str: String = row.get(0)?;
+    let url: String = row.get(1)?;
+    let title: Option<String> = row.get(2)?;
+    let domain: String = row.get(3)?;
+    let visit_count: i64 = row.get(4)?;
+    let last_visit_ts: Option<i64> = row.get(5)?;
     
-    let mut urls = Vec::new();
-    for url_result in url_iter {
-        urls.push(url_result?);
-    }
+    // Parse UUID from string
+    let id = Uuid::parse_str(&id_str)
+        .map_err(|e| rusqlite::Error::InvalidColumnType(0, format!("Invalid UUID: {}", e), rusqlite::types::Type::Text))?;
     
-    Ok(urls)
+    // Convert timestamp to DateTime if available
+    let last_visit = last_visit_ts.map(|ts| {
+        DateTime::from_timestamp(ts, 0).unwrap_or_else(|| Utc::now())
+    });
+    
+    Ok(UrlWithVisits {
+        url: UrlRecord {
+            id,
+            url,
+            title,
+            domain,
+            first_seen: Utc::now(), // Not used in this context
+            last_seen: Utc::now(),  // Not used in this context
+        },
+        visit_count: visit_count as usize,
+        last_visit,
+    })
 }
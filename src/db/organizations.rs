@@ -0,0 +1,180 @@
+// Domain Organizations
+// The organizations owning visited domains (youtube.com -> Google), from the
+// bundled mappings and the user's own, and domain aggregates rolled up to
+// registrable domains or organizations
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::connection::DatabaseConnection;
+use super::domains::{refresh_domain_stats, registrable_domain};
+use super::error::{DatabaseError, Result};
+use super::query::QueryBuilder;
+use super::trackers::{domain_suffixes, stats_exclusion};
+
+/// Domains checked per round trip by `refresh_organizations`
+const CHECK_BATCH: usize = 5000;
+
+/// Default number of groups returned by `get_domain_rollup`
+pub const DEFAULT_ROLLUP_LIMIT: usize = 50;
+
+/// Level domain aggregates are grouped at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainRollup {
+    /// Each domain on its own, e.g. news.bbc.co.uk
+    #[default]
+    Domain,
+    /// Domains under the same registration, e.g. bbc.co.uk
+    Registrable,
+    /// Domains of the same organization, e.g. Google; domains without a
+    /// known owner stay grouped by registrable domain
+    Organization,
+}
+
+impl DomainRollup {
+    /// SQL expression over `domain_stats` giving the group of a domain
+    fn group_expr(self) -> &'static str {
+        match self {
+            DomainRollup::Domain => "domain",
+            DomainRollup::Registrable => "COALESCE(registrable_domain, domain)",
+            DomainRollup::Organization => "COALESCE(organization, registrable_domain, domain)",
+        }
+    }
+}
+
+/// Fills the registrable domain and organization of the domains added since
+/// the last check or whose mapping changed. Returns the number checked.
+pub(crate) fn refresh_organizations(c: &Connection) -> Result<usize> {
+    let mut select = c.prepare_cached(
+        "SELECT domain FROM domain_stats INDEXED BY idx_domain_stats_organization_pending
+         WHERE organization_checked = 0
+         LIMIT ?"
+    )?;
+    let mut lookup = c.prepare_cached("SELECT organization FROM organization_domain WHERE domain = ?")?;
+    let mut update = c.prepare_cached(
+        "UPDATE domain_stats SET registrable_domain = ?, organization = ?, organization_checked = 1 WHERE domain = ?"
+    )?;
+
+    let mut checked = 0;
+    loop {
+        let pending = select.query_map([CHECK_BATCH as i64], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for domain in &pending {
+            // The most specific mapping wins, so a user's mapping of a
+            // subdomain overrides the bundled one of its parent
+            let mut organization: Option<String> = None;
+            for suffix in domain_suffixes(domain) {
+                organization = lookup.query_row([suffix], |row| row.get(0)).optional()?;
+                if organization.is_some() {
+                    break;
+                }
+            }
+            update.execute(params![registrable_domain(domain), organization, domain])?;
+        }
+
+        checked += pending.len();
+        if pending.len() < CHECK_BATCH {
+            return Ok(checked);
+        }
+    }
+}
+
+/// Maps a domain, and its subdomains, to the organization owning it, or
+/// removes its mapping when `organization` is None. Mappings the user sets
+/// replace the bundled ones.
+pub fn set_domain_organization(conn: &DatabaseConnection, domain: &str, organization: Option<&str>) -> Result<()> {
+    let domain = domain.trim().trim_start_matches("www.").to_lowercase();
+    if domain.is_empty() {
+        return Err(DatabaseError::Data("Domain cannot be empty".to_string()));
+    }
+    let organization = organization.map(str::trim);
+    if organization == Some("") {
+        return Err(DatabaseError::Data("Organization cannot be empty".to_string()));
+    }
+
+    conn.transaction(|tx| {
+        match organization {
+            Some(organization) => tx.execute(
+                "INSERT OR REPLACE INTO organization_domain (domain, organization, user_defined) VALUES (?, ?, 1)",
+                params![domain, organization],
+            )?,
+            None => {
+                let removed = tx.execute("DELETE FROM organization_domain WHERE domain = ?", [&domain])?;
+                if removed == 0 {
+                    return Err(DatabaseError::NotFound(format!("Organization of domain {} does not exist", domain)));
+                }
+                removed
+            },
+        };
+
+        tx.execute(
+            "UPDATE domain_stats SET organization_checked = 0 WHERE domain = ?1 OR domain LIKE '%.' || ?1",
+            [&domain],
+        )?;
+        Ok(())
+    })
+}
+
+/// Domains grouped at one rollup level, with their summed aggregates
+#[derive(Debug, Clone, Serialize)]
+pub struct DomainGroup {
+    /// Organization, registrable domain or domain the group stands for
+    pub name: String,
+    /// True if the group is a known organization rather than a domain
+    pub is_organization: bool,
+    /// Number of domains in the group
+    pub domain_count: usize,
+    /// Number of URLs on the group's domains
+    pub url_count: usize,
+    /// Number of visits to the group's domains
+    pub visit_count: usize,
+    /// Earliest visit
+    pub first_visit: Option<DateTime<Utc>>,
+    /// Latest visit
+    pub last_visit: Option<DateTime<Utc>>,
+    /// The group's most visited domains, most visited first
+    pub top_domains: Vec<String>,
+}
+
+/// Most domains listed in `DomainGroup::top_domains`
+const GROUP_TOP_DOMAINS: usize = 5;
+
+/// Gets the most visited groups of domains at a rollup level, without
+/// trackers when the user excludes them from stats
+pub fn get_domain_rollup(conn: &DatabaseConnection, rollup: DomainRollup, limit: usize) -> Result<Vec<DomainGroup>> {
+    conn.with_connection(|c| {
+        refresh_domain_stats(c)?;
+
+        let mut query = QueryBuilder::new(&format!(
+            "SELECT {group} AS name, MAX(organization IS NOT NULL AND {group} = organization),
+                    COUNT(*), SUM(url_count), SUM(visit_count) AS visits, MIN(first_visit), MAX(last_visit),
+                    json_group_array(domain)
+             FROM (SELECT * FROM domain_stats ORDER BY visit_count DESC, domain)",
+            group = rollup.group_expr(),
+        ));
+        query.condition("visit_count > 0");
+        if stats_exclusion(c)?.is_some() {
+            query.condition("tracker IS NULL");
+        }
+        query.group_by("name")
+            .order_by("visits DESC, name")
+            .limit(limit);
+
+        query.fetch_all(c, |row| {
+            let domains: Vec<String> = serde_json::from_str(&row.get::<_, String>(7)?).unwrap_or_default();
+            Ok(DomainGroup {
+                name: row.get(0)?,
+                is_organization: row.get(1)?,
+                domain_count: row.get::<_, i64>(2)? as usize,
+                url_count: row.get::<_, i64>(3)? as usize,
+                visit_count: row.get::<_, i64>(4)? as usize,
+                first_visit: row.get::<_, Option<i64>>(5)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+                last_visit: row.get::<_, Option<i64>>(6)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+                top_domains: domains.into_iter().take(GROUP_TOP_DOMAINS).collect(),
+            })
+        })
+    })
+}
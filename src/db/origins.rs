@@ -0,0 +1,233 @@
+// Visit Origins
+// Classifies where each visit came from (search, social, newsletter, direct,
+// internal or external link) from its referrer and campaign parameters, and
+// counts visits per origin
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use url::Url as UrlParser;
+
+use super::connection::DatabaseConnection;
+use super::error::Result;
+use super::query::QueryBuilder;
+
+/// Visits classified per round trip by `refresh_visit_origins`
+const CLASSIFY_BATCH: usize = 5000;
+
+/// Hosts of search engines; a host matches itself and its subdomains
+const SEARCH_HOSTS: &[&str] = &[
+    "bing.com", "duckduckgo.com", "search.yahoo.com", "ecosia.org", "kagi.com",
+    "startpage.com", "search.brave.com", "baidu.com", "qwant.com",
+];
+
+/// Search engines running under country domains (google.de, google.co.uk)
+const SEARCH_NAMES: &[&str] = &["google", "yandex"];
+
+/// Hosts of social networks and link aggregators
+const SOCIAL_HOSTS: &[&str] = &[
+    "twitter.com", "x.com", "t.co", "facebook.com", "instagram.com", "linkedin.com", "lnkd.in",
+    "reddit.com", "news.ycombinator.com", "bsky.app", "threads.net", "mastodon.social",
+    "youtube.com", "tiktok.com", "pinterest.com", "lobste.rs",
+];
+
+/// Hosts of webmail and newsletter platforms
+const NEWSLETTER_HOSTS: &[&str] = &[
+    "mail.google.com", "outlook.live.com", "outlook.office.com", "mail.yahoo.com",
+    "substack.com", "buttondown.email", "beehiiv.com", "mailchi.mp",
+];
+
+/// Where a visit came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VisitOrigin {
+    /// A search engine results page
+    Search,
+    /// A social network or link aggregator
+    Social,
+    /// An email or newsletter
+    Newsletter,
+    /// Typed, bookmarked or opened from outside the browser
+    Direct,
+    /// A link on the same site
+    Internal,
+    /// A link on another site
+    External,
+}
+
+impl VisitOrigin {
+    /// Returns the value stored in the `origin` column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VisitOrigin::Search => "search",
+            VisitOrigin::Social => "social",
+            VisitOrigin::Newsletter => "newsletter",
+            VisitOrigin::Direct => "direct",
+            VisitOrigin::Internal => "internal",
+            VisitOrigin::External => "external",
+        }
+    }
+
+    /// Parses a value from the `origin` column
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "search" => Some(VisitOrigin::Search),
+            "social" => Some(VisitOrigin::Social),
+            "newsletter" => Some(VisitOrigin::Newsletter),
+            "direct" => Some(VisitOrigin::Direct),
+            "internal" => Some(VisitOrigin::Internal),
+            "external" => Some(VisitOrigin::External),
+            _ => None,
+        }
+    }
+}
+
+/// Lowercased host without a leading www.
+fn bare_host(host: &str) -> String {
+    let host = host.to_lowercase();
+    host.strip_prefix("www.").map(str::to_string).unwrap_or(host)
+}
+
+/// Whether `host` is one of `hosts` or a subdomain of one
+fn host_in(host: &str, hosts: &[&str]) -> bool {
+    hosts.iter().any(|known| host == *known || host.ends_with(&format!(".{}", known)))
+}
+
+/// Whether `host` is a search engine's results host
+fn is_search_host(host: &str) -> bool {
+    host_in(host, SEARCH_HOSTS) || host.split('.').next().is_some_and(|name| SEARCH_NAMES.contains(&name))
+}
+
+/// Classifies a visit to a page on `domain` from the page it was opened from
+/// and the utm_source / utm_medium of its URL. Campaign parameters win, as
+/// mail clients and apps rarely send a referrer.
+pub fn classify_origin(
+    domain: &str,
+    referrer: Option<&str>,
+    campaign_source: Option<&str>,
+    campaign_medium: Option<&str>,
+) -> VisitOrigin {
+    match campaign_medium.map(str::to_lowercase).as_deref() {
+        Some("email" | "e-mail" | "newsletter") => return VisitOrigin::Newsletter,
+        Some("social" | "social-media" | "social_media") => return VisitOrigin::Social,
+        Some("cpc" | "ppc" | "search" | "paid_search") => return VisitOrigin::Search,
+        _ => {},
+    }
+
+    let referrer_host = referrer
+        .filter(|referrer| !referrer.trim().is_empty())
+        .and_then(|referrer| UrlParser::parse(referrer).ok())
+        .and_then(|parsed| parsed.host_str().map(bare_host));
+
+    let Some(host) = referrer_host else {
+        // A campaign naming a network, e.g. utm_source=twitter, without a referrer
+        return match campaign_source.map(bare_host) {
+            Some(source) if host_in(&source, SOCIAL_HOSTS) || host_in(&format!("{}.com", source), SOCIAL_HOSTS) => VisitOrigin::Social,
+            Some(source) if source.contains("newsletter") => VisitOrigin::Newsletter,
+            _ => VisitOrigin::Direct,
+        };
+    };
+
+    if host_in(&host, NEWSLETTER_HOSTS) {
+        VisitOrigin::Newsletter
+    } else if is_search_host(&host) {
+        VisitOrigin::Search
+    } else if host_in(&host, SOCIAL_HOSTS) {
+        VisitOrigin::Social
+    } else if host == bare_host(domain) {
+        VisitOrigin::Internal
+    } else {
+        VisitOrigin::External
+    }
+}
+
+/// Classifies the visits that have no origin yet. Returns the number classified.
+pub(crate) fn refresh_visit_origins(c: &Connection) -> Result<usize> {
+    let mut select = c.prepare_cached(
+        "SELECT v.id, u.domain, v.referrer,
+                (SELECT NULLIF(cp.source, '') FROM campaign cp WHERE cp.url_id = v.url_id LIMIT 1),
+                (SELECT NULLIF(cp.medium, '') FROM campaign cp WHERE cp.url_id = v.url_id LIMIT 1)
+         FROM visit v INDEXED BY idx_visit_origin_pending
+         JOIN url u ON u.id = v.url_id
+         WHERE v.origin IS NULL
+         LIMIT ?"
+    )?;
+    let mut update = c.prepare_cached("UPDATE visit SET origin = ? WHERE id = ?")?;
+
+    let mut classified = 0;
+    loop {
+        let pending = select.query_map([CLASSIFY_BATCH as i64], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for (id, domain, referrer, source, medium) in &pending {
+            let origin = classify_origin(domain, referrer.as_deref(), source.as_deref(), medium.as_deref());
+            update.execute(params![origin.as_str(), id])?;
+        }
+
+        classified += pending.len();
+        if pending.len() < CLASSIFY_BATCH {
+            return Ok(classified);
+        }
+    }
+}
+
+/// Visits and pages of one origin
+#[derive(Debug, Clone, Serialize)]
+pub struct OriginStat {
+    /// The origin
+    pub origin: VisitOrigin,
+    /// Number of visits
+    pub visits: usize,
+    /// Number of distinct pages visited
+    pub pages: usize,
+    /// Share of all visits in the range, between 0 and 1
+    pub share: f64,
+}
+
+/// Counts visits per origin, most common first, optionally for one domain
+pub fn get_origin_stats(
+    conn: &DatabaseConnection,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+    domain: Option<String>,
+) -> Result<Vec<OriginStat>> {
+    conn.transaction(|tx| {
+        refresh_visit_origins(tx)?;
+
+        let mut query = QueryBuilder::new(
+            "SELECT v.origin, COUNT(*), COUNT(DISTINCT v.url_id)
+             FROM visit v
+             JOIN url u ON u.id = v.url_id"
+        );
+        query.date_range("v.visited_at", start_date, end_date)
+            .condition("v.origin IS NOT NULL");
+        if let Some(domain) = domain {
+            query.filter("u.domain = ?", domain);
+        }
+        query.group_by("v.origin").order_by("COUNT(*) DESC");
+
+        let rows = query.fetch_all(tx, |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize, row.get::<_, i64>(2)? as usize))
+        })?;
+
+        let total: usize = rows.iter().map(|(_, visits, _)| visits).sum();
+        Ok(rows.into_iter()
+            .filter_map(|(origin, visits, pages)| {
+                VisitOrigin::parse(&origin).map(|origin| OriginStat {
+                    origin,
+                    visits,
+                    pages,
+                    share: visits as f64 / total.max(1) as f64,
+                })
+            })
+            .collect())
+    })
+}
@@ -0,0 +1,258 @@
+// Query Builder
+// Small internal helper for composing SELECT statements with bound parameters
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, Row, ToSql};
+
+use super::error::{DatabaseError, Result};
+
+/// Builds a SELECT statement from a base query, filters and modifiers.
+///
+/// Every value (including LIMIT and OFFSET) is passed to SQLite as a bound
+/// parameter; only static SQL fragments supplied by our own code are ever
+/// concatenated into the statement.
+pub struct QueryBuilder {
+    /// The SELECT ... FROM ... JOIN ... part of the statement
+    base: String,
+    /// Parameters bound to placeholders in the base statement
+    base_params: Vec<Box<dyn ToSql>>,
+    /// WHERE conditions, joined with AND
+    conditions: Vec<String>,
+    /// Parameters bound to the conditions, in order
+    params: Vec<Box<dyn ToSql>>,
+    /// Optional GROUP BY expression
+    group_by: Option<String>,
+    /// Optional HAVING condition (applied after grouping)
+    having: Option<String>,
+    /// Parameters bound to the HAVING condition
+    having_params: Vec<Box<dyn ToSql>>,
+    /// Optional ORDER BY expression
+    order_by: Option<String>,
+    /// Maximum number of rows to return
+    limit: Option<i64>,
+    /// Number of rows to skip
+    offset: Option<i64>,
+}
+
+impl QueryBuilder {
+    /// Creates a new builder from a base SELECT statement (without WHERE)
+    pub fn new(base: &str) -> Self {
+        Self {
+            base: base.to_string(),
+            base_params: Vec::new(),
+            conditions: Vec::new(),
+            params: Vec::new(),
+            group_by: None,
+            having: None,
+            having_params: Vec::new(),
+            order_by: None,
+            limit: None,
+            offset: None,
+        }
+    }
+
+    /// Creates a new builder whose base statement contains `?` placeholders
+    /// (e.g. in the SELECT list), bound in order to `params`
+    pub fn with_params(base: &str, params: Vec<Box<dyn ToSql>>) -> Self {
+        let mut builder = Self::new(base);
+        builder.base_params = params;
+        builder
+    }
+
+    /// Adds a condition containing a single `?` placeholder bound to `value`
+    pub fn filter<T: ToSql + 'static>(&mut self, condition: &str, value: T) -> &mut Self {
+        self.conditions.push(condition.to_string());
+        self.params.push(Box::new(value));
+        self
+    }
+
+    /// Adds a condition containing one `?` placeholder per value in `values`
+    pub fn filter_many(&mut self, condition: &str, values: Vec<Box<dyn ToSql>>) -> &mut Self {
+        self.conditions.push(condition.to_string());
+        self.params.extend(values);
+        self
+    }
+
+    /// Adds a condition without any parameters
+    pub fn condition(&mut self, condition: &str) -> &mut Self {
+        self.conditions.push(condition.to_string());
+        self
+    }
+
+    /// Adds `column >= start` and `column <= end` conditions for the given range
+    pub fn date_range(
+        &mut self,
+        column: &str,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+    ) -> &mut Self {
+        if let Some(start) = start {
+            self.filter(&format!("{} >= ?", column), start.timestamp());
+        }
+
+        if let Some(end) = end {
+            self.filter(&format!("{} <= ?", column), end.timestamp());
+        }
+
+        self
+    }
+
+    /// Sets the GROUP BY expression
+    pub fn group_by(&mut self, expr: &str) -> &mut Self {
+        self.group_by = Some(expr.to_string());
+        self
+    }
+
+    /// Sets the HAVING condition
+    pub fn having(&mut self, condition: &str) -> &mut Self {
+        self.having = Some(condition.to_string());
+        self.having_params.clear();
+        self
+    }
+
+    /// Sets a HAVING condition containing a single `?` placeholder bound to `value`
+    pub fn having_filter<T: ToSql + 'static>(&mut self, condition: &str, value: T) -> &mut Self {
+        self.having = Some(condition.to_string());
+        self.having_params = vec![Box::new(value)];
+        self
+    }
+
+    /// Sets the ORDER BY expression
+    pub fn order_by(&mut self, expr: &str) -> &mut Self {
+        self.order_by = Some(expr.to_string());
+        self
+    }
+
+    /// Limits the number of returned rows
+    pub fn limit(&mut self, limit: usize) -> &mut Self {
+        self.limit = Some(limit as i64);
+        self
+    }
+
+    /// Skips the given number of rows
+    pub fn offset(&mut self, offset: usize) -> &mut Self {
+        self.offset = Some(offset as i64);
+        self
+    }
+
+    /// Returns true if any WHERE condition has been added
+    pub fn has_conditions(&self) -> bool {
+        !self.conditions.is_empty()
+    }
+
+    /// Builds the statement without LIMIT/OFFSET
+    fn unpaged_sql(&self) -> String {
+        let mut sql = self.base.clone();
+
+        if !self.conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&self.conditions.join(" AND "));
+        }
+
+        if let Some(ref group_by) = self.group_by {
+            sql.push_str(" GROUP BY ");
+            sql.push_str(group_by);
+        }
+
+        if let Some(ref having) = self.having {
+            sql.push_str(" HAVING ");
+            sql.push_str(having);
+        }
+
+        sql
+    }
+
+    /// Builds the full SQL statement
+    pub fn sql(&self) -> String {
+        let mut sql = self.unpaged_sql();
+
+        if let Some(ref order_by) = self.order_by {
+            sql.push_str(" ORDER BY ");
+            sql.push_str(order_by);
+        }
+
+        // SQLite only accepts OFFSET after a LIMIT, -1 means "no limit"
+        if self.limit.is_some() || self.offset.is_some() {
+            sql.push_str(" LIMIT ?");
+        }
+
+        if self.offset.is_some() {
+            sql.push_str(" OFFSET ?");
+        }
+
+        sql
+    }
+
+    /// Returns the bound parameters matching `sql()`
+    pub fn params(&self) -> Vec<&dyn ToSql> {
+        let mut params = self.count_params();
+
+        if self.limit.is_some() || self.offset.is_some() {
+            params.push(self.limit.as_ref().unwrap_or(&-1));
+        }
+
+        if let Some(ref offset) = self.offset {
+            params.push(offset);
+        }
+
+        params
+    }
+
+    /// Builds a statement counting the rows the query would return without LIMIT/OFFSET
+    pub fn count_sql(&self) -> String {
+        format!("SELECT COUNT(*) FROM ({})", self.unpaged_sql())
+    }
+
+    /// Returns the bound parameters matching `count_sql()`
+    pub fn count_params(&self) -> Vec<&dyn ToSql> {
+        self.base_params.iter()
+            .chain(self.params.iter())
+            .chain(self.having_params.iter())
+            .map(|p| p.as_ref())
+            .collect()
+    }
+
+    /// Executes the query and maps every row with `f`
+    pub fn fetch_all<T, F>(&self, conn: &Connection, f: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&Row<'_>) -> rusqlite::Result<T>,
+    {
+        let mut stmt = conn.prepare_cached(&self.sql())?;
+        let rows = stmt.query_map(self.params().as_slice(), f)?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+
+        Ok(results)
+    }
+
+    /// Executes the query and calls `f` for every row without collecting the results
+    pub fn for_each<F>(&self, conn: &Connection, mut f: F) -> Result<()>
+    where
+        F: FnMut(&Row<'_>) -> Result<()>,
+    {
+        let mut stmt = conn.prepare_cached(&self.sql())?;
+        let mut rows = stmt.query(self.params().as_slice())?;
+
+        while let Some(row) = rows.next()? {
+            f(row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Executes the count statement and returns the number of matching rows
+    pub fn count(&self, conn: &Connection) -> Result<usize> {
+        let count: i64 = conn.prepare_cached(&self.count_sql())?
+            .query_row(self.count_params().as_slice(), |row| row.get(0))?;
+
+        Ok(count as usize)
+    }
+}
+
+/// Converts a database error into a rusqlite error so it can be returned from row mappers
+pub fn row_error(err: DatabaseError) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(Box::new(err))
+}
@@ -0,0 +1,144 @@
+// Reading List
+// Long pages opened once and left quickly, on the topics read most, ranked
+// into a list to come back to and saved as a collection
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use super::collections::{add_to_collection, create_collection, list_collections, Collection};
+use super::connection::DatabaseConnection;
+use super::error::Result;
+use super::query::QueryBuilder;
+
+/// Default shortest estimated reading time of a listed page, in seconds
+pub const DEFAULT_MIN_READING_SECS: u64 = 300;
+
+/// Default longest visit of a listed page, in seconds
+pub const DEFAULT_MAX_DWELL_SECS: f64 = 60.0;
+
+/// Default number of topic clusters pages are picked from
+pub const DEFAULT_TOP_TOPICS: usize = 5;
+
+/// Default number of days of visits the top topics are counted over
+pub const DEFAULT_TOPIC_DAYS: i64 = 90;
+
+/// Default number of pages listed
+pub const DEFAULT_READING_LIST_LIMIT: usize = 30;
+
+/// Name of the collection the list is saved to when none is given
+pub const DEFAULT_READING_LIST_COLLECTION: &str = "Reading list";
+
+/// Parameters for the reading list
+pub struct ReadingListParams {
+    /// Only list pages estimated to take at least this long to read
+    pub min_reading_secs: u64,
+    /// Only list pages whose visit lasted at most this long
+    pub max_dwell_secs: f64,
+    /// Number of most visited topic clusters to pick pages from
+    pub top_topics: usize,
+    /// Days of visits the topic clusters are ranked over
+    pub topic_days: i64,
+    /// Maximum number of pages to list
+    pub limit: usize,
+}
+
+/// A page on the reading list
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadingListItem {
+    /// URL id
+    pub url_id: String,
+    /// Full URL
+    pub url: String,
+    /// Page title, if known
+    pub title: Option<String>,
+    /// Domain of the URL
+    pub domain: String,
+    /// Topic cluster the page was picked for
+    pub topic: String,
+    /// Number of words in the main content
+    pub word_count: u64,
+    /// Estimated time to read the page in seconds
+    pub reading_time_sec: u64,
+    /// When the page was opened
+    pub visited_at: DateTime<Utc>,
+    /// Time spent on the page, as recorded or estimated from the next visit
+    pub dwell_secs: f64,
+}
+
+/// Builds the reading list: long-form pages visited once, briefly, in the
+/// most visited topic clusters, leaving out pages behind a wall or already
+/// in a collection. Topics take turns so the list isn't all one subject;
+/// within a topic the most recently opened pages come first.
+pub fn get_reading_list(conn: &DatabaseConnection, params: &ReadingListParams) -> Result<Vec<ReadingListItem>> {
+    conn.with_connection(|c| {
+        let topics_since = (Utc::now() - Duration::days(params.topic_days)).timestamp();
+
+        // Browsers that don't record durations leave the visit lasting until
+        // the next page opened on the same device
+        let mut query = QueryBuilder::with_params(
+            "WITH topics AS (
+                 SELECT m.topic_cluster AS topic, COUNT(*) AS visits
+                 FROM visit v
+                 JOIN metadata m ON m.url_id = v.url_id
+                 WHERE m.topic_cluster IS NOT NULL AND m.topic_cluster <> '' AND v.visited_at >= ?
+                 GROUP BY m.topic_cluster
+                 ORDER BY visits DESC, topic
+                 LIMIT ?
+             ),
+             candidates AS (
+                 SELECT u.id, u.url, u.title, u.domain, t.topic, t.visits AS topic_visits,
+                        m.word_count, m.reading_time_sec, MAX(v.visited_at) AS visited_at,
+                        MAX(COALESCE(v.duration_sec, (
+                            SELECT MIN(n.visited_at) FROM visit n
+                            WHERE n.visited_at > v.visited_at AND n.device_name IS v.device_name
+                        ) - v.visited_at)) AS dwell
+                 FROM url u
+                 JOIN metadata m ON m.url_id = u.id
+                 JOIN topics t ON t.topic = m.topic_cluster
+                 JOIN visit v ON v.url_id = u.id
+                 WHERE m.reading_time_sec >= ?
+                   AND m.access_wall IS NULL
+                   AND NOT EXISTS (SELECT 1 FROM collection_item i WHERE i.url_id = u.id)
+                 GROUP BY u.id
+                 HAVING COUNT(v.id) = 1
+             )
+             SELECT id, url, title, domain, topic, word_count, reading_time_sec, visited_at, dwell
+             FROM (
+                 SELECT *, ROW_NUMBER() OVER (PARTITION BY topic ORDER BY visited_at DESC, id) AS turn
+                 FROM candidates
+             )",
+            vec![Box::new(topics_since), Box::new(params.top_topics as i64), Box::new(params.min_reading_secs as i64)],
+        );
+        query.filter("dwell <= ?", params.max_dwell_secs)
+            .order_by("turn, topic_visits DESC, topic")
+            .limit(params.limit);
+
+        query.fetch_all(c, |row| {
+            Ok(ReadingListItem {
+                url_id: row.get(0)?,
+                url: row.get(1)?,
+                title: row.get(2)?,
+                domain: row.get(3)?,
+                topic: row.get(4)?,
+                word_count: row.get::<_, i64>(5)? as u64,
+                reading_time_sec: row.get::<_, i64>(6)? as u64,
+                visited_at: DateTime::from_timestamp(row.get(7)?, 0).unwrap_or_default(),
+                dwell_secs: row.get(8)?,
+            })
+        })
+    })
+}
+
+/// Appends the listed pages to the collection named `name`, creating it if
+/// needed, so the list can be regenerated into the same collection
+pub fn save_reading_list(conn: &DatabaseConnection, name: &str, items: &[ReadingListItem]) -> Result<Collection> {
+    let existing = list_collections(conn)?.into_iter()
+        .find(|collection| collection.name.eq_ignore_ascii_case(name.trim()));
+    let collection = match existing {
+        Some(collection) => collection,
+        None => create_collection(conn, name, Some("Long reads opened once and left quickly"))?,
+    };
+
+    let url_ids: Vec<String> = items.iter().map(|item| item.url_id.clone()).collect();
+    add_to_collection(conn, collection.id, &url_ids, None)
+}
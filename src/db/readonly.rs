@@ -0,0 +1,521 @@
+// Read-only Queries
+// Validates and runs generated or user-written SELECT statements against
+// whitelisted views, and the power users' SQL console over whitelisted tables
+
+use std::time::{Duration, Instant};
+
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+
+/// Views that read-only queries may select from
+pub const QUERY_VIEWS: &[&str] = &["history_pages", "history_visits"];
+
+/// Default maximum number of rows returned by a read-only query
+pub const DEFAULT_MAX_ROWS: usize = 500;
+
+/// Longest a read-only query against the query views may run
+pub const SELECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Tables and views the SQL console may read; settings, sync peers, scripts
+/// and the undo journal are left out as they are app state rather than
+/// history. Reads are held to this list by the validator and by an
+/// authorizer on the statement itself.
+pub const CONSOLE_TABLES: &[&str] = &[
+    "history_pages", "history_visits", "url", "visit", "metadata", "tag", "url_tag",
+    "collection", "collection_item", "device", "keyword", "url_keyword", "url_redirect",
+    "url_edit", "visit_tombstone", "import_run", "saved_search", "domain_stats", "node",
+    "edge", "archive", "enrichment_usage",
+];
+
+/// Rows the console returns unless asked for fewer or more
+pub const DEFAULT_CONSOLE_ROWS: usize = 1000;
+
+/// Most rows the console returns
+pub const MAX_CONSOLE_ROWS: usize = 10_000;
+
+/// Time a console query may run unless asked otherwise
+pub const DEFAULT_CONSOLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest a console query may run
+pub const MAX_CONSOLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// SQLite steps between checks of a query deadline
+const TIMEOUT_CHECK_STEPS: i32 = 1000;
+
+/// Tabular result of a read-only query
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryTable {
+    /// Column names in select order
+    pub columns: Vec<String>,
+    /// Row values; integers, reals, text and null map to their JSON counterparts
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// True if more rows matched than were returned
+    pub truncated: bool,
+}
+
+/// Type of the values in a result column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnKind {
+    Integer,
+    Real,
+    Text,
+    Blob,
+    /// Only NULLs, or no rows
+    Null,
+    /// Values of more than one type, as SQLite allows
+    Mixed,
+}
+
+/// A column of a console result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsoleColumn {
+    /// Column name
+    pub name: String,
+    /// Type declared in the schema, for columns read straight from a table
+    pub declared_type: Option<String>,
+    /// Type of the returned values
+    pub kind: ColumnKind,
+}
+
+/// Result of a console query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsoleResult {
+    /// Columns in select order
+    pub columns: Vec<ConsoleColumn>,
+    /// Row values, as in `QueryTable`
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// True if more rows matched than were returned
+    pub truncated: bool,
+    /// Time the query took
+    pub elapsed_ms: u64,
+}
+
+/// A lexical token of a SQL statement
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Keyword or identifier (quoted identifiers are unquoted)
+    Word(String),
+    /// String literal (content is irrelevant for validation)
+    Literal,
+    /// Any other character
+    Symbol(char),
+}
+
+/// Splits a statement into tokens, dropping whitespace and comments
+fn tokenize(sql: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    // Finds the closing delimiter starting at `from`, returning the index after it
+    let close = |from: usize, delimiter: char| -> Result<usize> {
+        chars[from..].iter()
+            .position(|c| *c == delimiter)
+            .map(|end| from + end + 1)
+            .ok_or_else(|| DatabaseError::Query(format!("Unterminated {} in query", delimiter)))
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            i = chars[i..].iter().position(|c| *c == '\n').map_or(chars.len(), |end| i + end + 1);
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            let rest: String = chars[i + 2..].iter().collect();
+            let end = rest.find("*/").ok_or_else(|| DatabaseError::Query("Unterminated comment in query".to_string()))?;
+            i += 2 + rest[..end].chars().count() + 2;
+        } else if c == '\'' {
+            // '' inside a literal is an escaped quote, which simply starts a new literal here
+            i = close(i + 1, '\'')?;
+            tokens.push(Token::Literal);
+        } else if c == '"' || c == '`' || c == '[' {
+            let delimiter = if c == '[' { ']' } else { c };
+            let end = close(i + 1, delimiter)?;
+            tokens.push(Token::Word(chars[i + 1..end - 1].iter().collect::<String>().to_lowercase()));
+            i = end;
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$') {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect::<String>().to_lowercase()));
+        } else {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Keywords ending a FROM clause
+const FROM_END_KEYWORDS: &[&str] = &[
+    "where", "group", "order", "limit", "having", "window", "union", "intersect", "except",
+];
+
+/// Index of the `)` closing the `(` at `open`
+fn closing_paren(tokens: &[Token], open: usize) -> Result<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol('(') => depth += 1,
+            Token::Symbol(')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i);
+                }
+            },
+            _ => {},
+        }
+    }
+    Err(DatabaseError::Query("Unbalanced parentheses in query".to_string()))
+}
+
+/// A common table expression and the tokens it is visible in
+struct CteScope {
+    name: String,
+    /// Index of its WITH keyword
+    start: usize,
+    /// Index of the `)` closing the statement it belongs to, or the end
+    end: usize,
+}
+
+/// Finds the names each WITH clause defines (`name AS (...)` or
+/// `name(columns) AS [NOT] [MATERIALIZED] (...)`), visible only within the
+/// statement the clause belongs to
+fn cte_scopes(tokens: &[Token]) -> Result<Vec<CteScope>> {
+    let word = |i: usize, expected: &str| matches!(tokens.get(i), Some(Token::Word(w)) if w == expected);
+    let mut scopes = Vec::new();
+
+    for (with, _) in tokens.iter().enumerate().filter(|(_, token)| **token == Token::Word("with".to_string())) {
+        // The statement ends where the parenthesis around it closes
+        let mut depth = 0i32;
+        let mut end = tokens.len();
+        for (i, token) in tokens.iter().enumerate().skip(with + 1) {
+            match token {
+                Token::Symbol('(') => depth += 1,
+                Token::Symbol(')') if depth == 0 => {
+                    end = i;
+                    break;
+                },
+                Token::Symbol(')') => depth -= 1,
+                _ => {},
+            }
+        }
+
+        let mut i = with + 1;
+        if word(i, "recursive") {
+            i += 1;
+        }
+        while let Some(Token::Word(name)) = tokens.get(i) {
+            i += 1;
+            if tokens.get(i) == Some(&Token::Symbol('(')) {
+                i = closing_paren(tokens, i)? + 1;
+            }
+            if !word(i, "as") {
+                break;
+            }
+            i += 1;
+            if word(i, "not") {
+                i += 1;
+            }
+            if word(i, "materialized") {
+                i += 1;
+            }
+            if tokens.get(i) != Some(&Token::Symbol('(')) {
+                break;
+            }
+            i = closing_paren(tokens, i)? + 1;
+            scopes.push(CteScope { name: name.clone(), start: with, end });
+
+            if tokens.get(i) != Some(&Token::Symbol(',')) {
+                break;
+            }
+            i += 1;
+        }
+    }
+
+    Ok(scopes)
+}
+
+/// Checks the table references of the FROM clause starting at `start`: the
+/// first one and every one after a comma or JOIN at the clause's own level,
+/// including those inside parenthesised joins. Subqueries are skipped here;
+/// their own FROM clauses are checked separately.
+fn check_from_clause(tokens: &[Token], start: usize, is_allowed: &dyn Fn(&str, usize) -> bool, allowed: &[&str]) -> Result<()> {
+    let mut i = start;
+    let mut expect_table = true;
+
+    while let Some(token) = tokens.get(i) {
+        if expect_table {
+            expect_table = false;
+            match token {
+                Token::Symbol('(') => {
+                    let close = closing_paren(tokens, i)?;
+                    let is_subquery = matches!(
+                        tokens.get(i + 1),
+                        Some(Token::Word(word)) if word == "select" || word == "with" || word == "values"
+                    );
+                    if !is_subquery {
+                        check_from_clause(&tokens[..close], i + 1, is_allowed, allowed)?;
+                    }
+                    i = close + 1;
+                },
+                Token::Word(name) => {
+                    if tokens.get(i + 1) == Some(&Token::Symbol('.')) {
+                        return Err(DatabaseError::Query(format!("Schema-qualified tables are not allowed: {}", name)));
+                    }
+                    if tokens.get(i + 1) == Some(&Token::Symbol('(')) {
+                        return Err(DatabaseError::Query(format!("Table-valued functions are not allowed: {}", name)));
+                    }
+                    if !is_allowed(name, i) {
+                        return Err(DatabaseError::Query(format!(
+                            "Unknown table '{}'; queries may only read from {}", name, allowed.join(", "),
+                        )));
+                    }
+                    i += 1;
+                },
+                _ => return Err(DatabaseError::Query("Expected a table name after FROM or JOIN".to_string())),
+            }
+            continue;
+        }
+
+        match token {
+            // Expressions of ON clauses, skipped as a whole
+            Token::Symbol('(') => i = closing_paren(tokens, i)? + 1,
+            Token::Symbol(')') => return Ok(()),
+            Token::Symbol(',') => {
+                expect_table = true;
+                i += 1;
+            },
+            Token::Word(word) if word == "join" => {
+                expect_table = true;
+                i += 1;
+            },
+            Token::Word(word) if FROM_END_KEYWORDS.contains(&word.as_str()) => return Ok(()),
+            // Aliases, join kinds, INDEXED BY and ON conditions
+            _ => i += 1,
+        }
+    }
+
+    if expect_table {
+        return Err(DatabaseError::Query("Expected a table name after FROM or JOIN".to_string()));
+    }
+    Ok(())
+}
+
+/// Checks that a statement is a single SELECT (or WITH ... SELECT) that only
+/// reads from `allowed` views and the common table expressions in scope,
+/// returning the statement without a trailing semicolon
+pub fn validate_select(sql: &str, allowed: &[&str]) -> Result<String> {
+    let sql = sql.trim().trim_end_matches(';').trim();
+    let tokens = tokenize(sql)?;
+
+    if tokens.contains(&Token::Symbol(';')) {
+        return Err(DatabaseError::Query("Only a single statement is allowed".to_string()));
+    }
+
+    match tokens.first() {
+        Some(Token::Word(word)) if word == "select" || word == "with" => {},
+        _ => return Err(DatabaseError::Query("Only SELECT queries are allowed".to_string())),
+    }
+
+    let ctes = cte_scopes(&tokens)?;
+    let is_allowed = |name: &str, at: usize| {
+        allowed.iter().any(|view| view.eq_ignore_ascii_case(name))
+            || ctes.iter().any(|cte| cte.name == name && cte.start < at && at < cte.end)
+    };
+
+    // Every FROM clause, at any depth, only reads allowed tables; the FROM of
+    // `IS [NOT] DISTINCT FROM` is a comparison
+    for (i, token) in tokens.iter().enumerate() {
+        let is_comparison = i > 0 && tokens[i - 1] == Token::Word("distinct".to_string());
+        if *token == Token::Word("from".to_string()) && !is_comparison {
+            check_from_clause(&tokens, i + 1, &is_allowed, allowed)?;
+        }
+    }
+
+    Ok(sql.to_string())
+}
+
+/// Runs `f` with an authorizer that lets statements prepared meanwhile read
+/// only the `allowed` tables and views of the main database, so SQLite itself
+/// enforces what the validator checks
+fn with_read_whitelist<T>(c: &Connection, allowed: &'static [&'static str], f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let is_allowed = move |name: &str| allowed.iter().any(|table| table.eq_ignore_ascii_case(name));
+    c.authorizer(Some(move |context: AuthContext<'_>| {
+        match context.action {
+            // Reads made by an allowed view are allowed too
+            AuthAction::Read { table_name, .. }
+                if context.database_name == Some("main")
+                    && (is_allowed(table_name) || context.accessor.is_some_and(is_allowed)) => Authorization::Allow,
+            AuthAction::Select | AuthAction::Function { .. } | AuthAction::Recursive => Authorization::Allow,
+            _ => Authorization::Deny,
+        }
+    }));
+
+    let result = f();
+    c.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+    result
+}
+
+/// Converts a column value to JSON; blobs are reported by size only
+fn value_to_json(value: ValueRef<'_>) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(i) => serde_json::Value::from(i),
+        ValueRef::Real(f) => serde_json::Value::from(f),
+        ValueRef::Text(text) => serde_json::Value::String(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(blob) => serde_json::Value::String(format!("<{} bytes>", blob.len())),
+    }
+}
+
+/// Runs `f` with the connection switched to query-only and its statements
+/// stopped after `timeout`
+fn with_read_only_deadline<T>(c: &Connection, timeout: Duration, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    // Pooled connections are shared, so both switches are undone whatever happens
+    c.pragma_update(None, "query_only", true)?;
+    let deadline = Instant::now() + timeout;
+    c.progress_handler(TIMEOUT_CHECK_STEPS, Some(move || Instant::now() >= deadline));
+
+    let result = f();
+
+    c.progress_handler(TIMEOUT_CHECK_STEPS, None::<fn() -> bool>);
+    c.pragma_update(None, "query_only", false)?;
+
+    // Interrupted by the deadline, rather than failed on its own
+    match result {
+        Err(_) if Instant::now() >= deadline => Err(DatabaseError::Query(format!(
+            "Query ran longer than {} seconds and was stopped", timeout.as_secs(),
+        ))),
+        result => result,
+    }
+}
+
+/// Validates and runs a SELECT against the query views, returning at most
+/// `max_rows` rows; it is stopped after `SELECT_TIMEOUT`
+pub fn run_select(conn: &DatabaseConnection, sql: &str, max_rows: usize) -> Result<QueryTable> {
+    let sql = validate_select(sql, QUERY_VIEWS)?;
+
+    conn.with_connection(|c| with_read_only_deadline(c, SELECT_TIMEOUT, || with_read_whitelist(c, QUERY_VIEWS, || {
+        let mut stmt = c.prepare(&sql)?;
+
+        // SQLite's own check, in case something slipped through the validator
+        if !stmt.readonly() {
+            return Err(DatabaseError::Query("Only read-only queries are allowed".to_string()));
+        }
+
+        let columns: Vec<String> = stmt.column_names().into_iter().map(str::to_string).collect();
+        let mut rows = stmt.query([])?;
+
+        let mut table = QueryTable { columns, rows: Vec::new(), truncated: false };
+        while let Some(row) = rows.next()? {
+            if table.rows.len() >= max_rows {
+                table.truncated = true;
+                break;
+            }
+            let values = (0..table.columns.len())
+                .map(|i| row.get_ref(i).map(value_to_json))
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            table.rows.push(values);
+        }
+
+        Ok(table)
+    })))
+}
+
+/// Type of a value, for the column kinds
+fn value_kind(value: ValueRef<'_>) -> ColumnKind {
+    match value {
+        ValueRef::Null => ColumnKind::Null,
+        ValueRef::Integer(_) => ColumnKind::Integer,
+        ValueRef::Real(_) => ColumnKind::Real,
+        ValueRef::Text(_) => ColumnKind::Text,
+        ValueRef::Blob(_) => ColumnKind::Blob,
+    }
+}
+
+/// Runs the validated statement; the connection is already read-only and timed
+fn run_console_statement(c: &Connection, sql: &str, params: &[(String, Value)], max_rows: usize) -> Result<ConsoleResult> {
+    let started = Instant::now();
+    let mut stmt = c.prepare(sql)?;
+    if !stmt.readonly() {
+        return Err(DatabaseError::Query("Only read-only queries are allowed".to_string()));
+    }
+
+    // Named parameters (:name, @name or $name) take the value given for `name`
+    for index in 1..=stmt.parameter_count() {
+        let name = stmt.parameter_name(index)
+            .ok_or_else(|| DatabaseError::Query("Use named parameters like :name instead of ?".to_string()))?
+            .trim_start_matches([':', '@', '$'])
+            .to_string();
+        let value = params.iter().find(|(param, _)| *param == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| DatabaseError::Query(format!("No value for parameter :{}", name)))?;
+        stmt.raw_bind_parameter(index, value)?;
+    }
+
+    let mut columns: Vec<ConsoleColumn> = stmt.columns().iter()
+        .map(|column| ConsoleColumn {
+            name: column.name().to_string(),
+            declared_type: column.decl_type().map(str::to_string),
+            kind: ColumnKind::Null,
+        })
+        .collect();
+
+    let mut rows = stmt.raw_query();
+    let mut result_rows = Vec::new();
+    let mut truncated = false;
+    while let Some(row) = rows.next()? {
+        if result_rows.len() >= max_rows {
+            truncated = true;
+            break;
+        }
+        let mut values = Vec::with_capacity(columns.len());
+        for (i, column) in columns.iter_mut().enumerate() {
+            let value = row.get_ref(i)?;
+            column.kind = match (column.kind, value_kind(value)) {
+                (kind, ColumnKind::Null) => kind,
+                (ColumnKind::Null, kind) => kind,
+                (current, kind) if current == kind => kind,
+                _ => ColumnKind::Mixed,
+            };
+            values.push(value_to_json(value));
+        }
+        result_rows.push(values);
+    }
+
+    Ok(ConsoleResult {
+        columns,
+        rows: result_rows,
+        truncated,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// Runs a power user's SELECT over `CONSOLE_TABLES` on a connection switched
+/// to read-only, binding its named parameters from `params`, returning at
+/// most `max_rows` rows and stopping it after `timeout`
+pub fn run_console_query(
+    conn: &DatabaseConnection,
+    sql: &str,
+    params: &[(String, Value)],
+    max_rows: usize,
+    timeout: Duration,
+) -> Result<ConsoleResult> {
+    let sql = validate_select(sql, CONSOLE_TABLES)?;
+    let max_rows = max_rows.clamp(1, MAX_CONSOLE_ROWS);
+    let timeout = timeout.min(MAX_CONSOLE_TIMEOUT);
+
+    conn.with_connection(|c| with_read_only_deadline(c, timeout, || {
+        with_read_whitelist(c, CONSOLE_TABLES, || run_console_statement(c, &sql, params, max_rows))
+    }))
+}
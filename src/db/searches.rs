@@ -0,0 +1,185 @@
+// Saved Searches
+// Named search filters that can be run again, e.g. from a Shortcut
+
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+use super::operations::SearchParams;
+
+/// A stored search
+#[derive(Debug, Clone, Serialize)]
+pub struct SavedSearch {
+    /// Search identifier
+    pub id: i64,
+    /// Name (unique, case-insensitive)
+    pub name: String,
+    /// Text to search for
+    pub query: Option<String>,
+    /// Only this domain
+    pub domain: Option<String>,
+    /// Only this category
+    pub category: Option<String>,
+    /// Only URLs with this tag
+    pub tag: Option<String>,
+    /// Only URLs with this keyword
+    pub keyword: Option<String>,
+    /// Only visits in the last N days
+    pub days: Option<u32>,
+    /// When the search was saved
+    pub created_at: DateTime<Utc>,
+    /// When the search was last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A search as saved from the app; without an id it is created
+#[derive(Debug, Clone, Deserialize)]
+pub struct SavedSearchInput {
+    /// Search to update
+    pub id: Option<i64>,
+    /// Name (unique, case-insensitive)
+    pub name: String,
+    /// Text to search for
+    pub query: Option<String>,
+    /// Only this domain
+    pub domain: Option<String>,
+    /// Only this category
+    pub category: Option<String>,
+    /// Only URLs with this tag
+    pub tag: Option<String>,
+    /// Only URLs with this keyword
+    pub keyword: Option<String>,
+    /// Only visits in the last N days
+    pub days: Option<u32>,
+}
+
+impl SavedSearch {
+    /// Search parameters for running the search now
+    pub fn to_params(&self, limit: Option<usize>) -> SearchParams {
+        SearchParams {
+            query: self.query.clone(),
+            domain: self.domain.clone(),
+            category: self.category.clone(),
+            tag: self.tag.clone(),
+            keyword: self.keyword.clone(),
+            walled: None,
+            start_date: self.days.map(|days| Utc::now() - Duration::days(i64::from(days))),
+            end_date: None,
+            limit,
+            offset: None,
+        }
+    }
+}
+
+/// Columns of a saved search row
+const SELECT_SEARCH: &str =
+    "SELECT id, name, query, domain, category, tag, keyword, days, created_at, updated_at FROM saved_search";
+
+/// Maps a `SELECT_SEARCH` row
+fn search_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<SavedSearch> {
+    Ok(SavedSearch {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        query: row.get(2)?,
+        domain: row.get(3)?,
+        category: row.get(4)?,
+        tag: row.get(5)?,
+        keyword: row.get(6)?,
+        days: row.get(7)?,
+        created_at: DateTime::from_timestamp(row.get(8)?, 0).unwrap_or_default(),
+        updated_at: DateTime::from_timestamp(row.get(9)?, 0).unwrap_or_default(),
+    })
+}
+
+/// Maps a database error on the unique name to a readable message
+fn name_conflict(name: &str, err: rusqlite::Error) -> DatabaseError {
+    match err {
+        rusqlite::Error::SqliteFailure(e, _) if e.code == rusqlite::ErrorCode::ConstraintViolation => {
+            DatabaseError::Data(format!("A saved search named '{}' already exists", name))
+        },
+        e => DatabaseError::from(e),
+    }
+}
+
+/// Loads a saved search by id
+fn get_search(c: &Connection, id: i64) -> Result<SavedSearch> {
+    c.query_row(&format!("{} WHERE id = ?", SELECT_SEARCH), [id], search_from_row)
+        .optional()?
+        .ok_or_else(|| DatabaseError::NotFound(format!("Saved search {} does not exist", id)))
+}
+
+/// Trims a text filter, treating blank as none
+fn filter_value(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+}
+
+/// Lists every saved search, alphabetically
+pub fn list_saved_searches(conn: &DatabaseConnection) -> Result<Vec<SavedSearch>> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(&format!("{} ORDER BY name COLLATE NOCASE", SELECT_SEARCH))?;
+        let rows = stmt.query_map([], search_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
+
+/// Finds a saved search by name (case-insensitive)
+pub fn find_saved_search(conn: &DatabaseConnection, name: &str) -> Result<SavedSearch> {
+    conn.with_connection(|c| {
+        c.query_row(&format!("{} WHERE name = ?", SELECT_SEARCH), [name.trim()], search_from_row)
+            .optional()?
+            .ok_or_else(|| DatabaseError::Data(format!("No saved search named '{}'", name)))
+    })
+}
+
+/// Creates or updates a saved search
+pub fn save_search(conn: &DatabaseConnection, input: &SavedSearchInput) -> Result<SavedSearch> {
+    let name = input.name.trim().to_string();
+    if name.is_empty() {
+        return Err(DatabaseError::Data("Saved search name cannot be empty".to_string()));
+    }
+    let values = (
+        filter_value(&input.query),
+        filter_value(&input.domain),
+        filter_value(&input.category),
+        filter_value(&input.tag),
+        filter_value(&input.keyword),
+        input.days.filter(|days| *days > 0),
+    );
+
+    conn.with_connection(|c| {
+        let now = Utc::now().timestamp();
+        let id = match input.id {
+            Some(id) => {
+                get_search(c, id)?;
+                c.execute(
+                    "UPDATE saved_search SET name = ?, query = ?, domain = ?, category = ?, tag = ?,
+                            keyword = ?, days = ?, updated_at = ?
+                     WHERE id = ?",
+                    params![name, values.0, values.1, values.2, values.3, values.4, values.5, now, id],
+                ).map_err(|e| name_conflict(&name, e))?;
+                id
+            },
+            None => {
+                c.execute(
+                    "INSERT INTO saved_search (name, query, domain, category, tag, keyword, days, created_at, updated_at)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    params![name, values.0, values.1, values.2, values.3, values.4, values.5, now, now],
+                ).map_err(|e| name_conflict(&name, e))?;
+                c.last_insert_rowid()
+            },
+        };
+        get_search(c, id)
+    })
+}
+
+/// Deletes a saved search
+pub fn delete_saved_search(conn: &DatabaseConnection, id: i64) -> Result<()> {
+    conn.with_connection(|c| {
+        if c.execute("DELETE FROM saved_search WHERE id = ?", [id])? == 0 {
+            return Err(DatabaseError::NotFound(format!("Saved search {} does not exist", id)));
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,129 @@
+// Sentiment Analytics
+// How the tone of the pages read is spread, overall, per category and per
+// domain, weighted by visits
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::connection::DatabaseConnection;
+use super::error::Result;
+use super::query::QueryBuilder;
+
+/// Scores within this distance of zero count as neutral
+pub const NEUTRAL_BAND: f64 = 0.05;
+
+/// Number of domains listed as most negative and most positive
+const SENTIMENT_DOMAINS: usize = 10;
+
+/// Fewest visits a domain needs to be listed among the most negative or
+/// positive, so one odd page doesn't decide it
+const MIN_DOMAIN_VISITS: usize = 3;
+
+/// Visits to scored pages of one category or domain, by tone
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ToneBreakdown {
+    /// Category or domain; None for uncategorized pages and the overall total
+    pub name: Option<String>,
+    /// Visits to scored pages
+    pub visits: usize,
+    /// Distinct scored pages visited
+    pub pages: usize,
+    /// Visit-weighted average score, from -1 to 1
+    pub average: f64,
+    /// Visits to negative pages
+    pub negative: usize,
+    /// Visits to neutral pages
+    pub neutral: usize,
+    /// Visits to positive pages
+    pub positive: usize,
+}
+
+impl ToneBreakdown {
+    /// Maps a row of (name, visits, pages, score sum, negative, positive)
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let visits = row.get::<_, i64>(1)? as usize;
+        let negative = row.get::<_, i64>(4)? as usize;
+        let positive = row.get::<_, i64>(5)? as usize;
+        Ok(ToneBreakdown {
+            name: row.get(0)?,
+            visits,
+            pages: row.get::<_, i64>(2)? as usize,
+            average: row.get::<_, f64>(3)? / visits.max(1) as f64,
+            negative,
+            neutral: visits - negative - positive,
+            positive,
+        })
+    }
+}
+
+/// Tone of the pages read in a date range
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SentimentStats {
+    /// All scored visits
+    pub overall: ToneBreakdown,
+    /// Per category, most visited first
+    pub categories: Vec<ToneBreakdown>,
+    /// Domains read most negatively, most negative first
+    pub most_negative_domains: Vec<ToneBreakdown>,
+    /// Domains read most positively, most positive first
+    pub most_positive_domains: Vec<ToneBreakdown>,
+}
+
+/// Builds the query over visits of scored pages, grouped by `group`
+fn tone_query(
+    group: &str,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+    category: Option<&str>,
+) -> QueryBuilder {
+    let mut query = QueryBuilder::new(&format!(
+        "SELECT {group}, COUNT(*), COUNT(DISTINCT v.url_id), TOTAL(m.sentiment),
+                COALESCE(SUM(m.sentiment <= -{band}), 0), COALESCE(SUM(m.sentiment >= {band}), 0)
+         FROM visit v
+         JOIN url u ON u.id = v.url_id
+         JOIN metadata m ON m.url_id = v.url_id",
+        group = group,
+        band = NEUTRAL_BAND,
+    ));
+    query.date_range("v.visited_at", start_date, end_date)
+        .condition("m.sentiment IS NOT NULL");
+    if let Some(category) = category {
+        query.filter("u.category = ?", category.to_string());
+    }
+    query
+}
+
+/// Gets how the tone of the pages read is spread in a date range, optionally
+/// within one category such as "news"
+pub fn get_sentiment_stats(
+    conn: &DatabaseConnection,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+    category: Option<&str>,
+) -> Result<SentimentStats> {
+    conn.with_connection(|c| {
+        let overall = tone_query("NULL", start_date, end_date, category)
+            .fetch_all(c, ToneBreakdown::from_row)?
+            .into_iter()
+            .next()
+            .filter(|overall| overall.visits > 0)
+            .unwrap_or_default();
+
+        let mut query = tone_query("u.category", start_date, end_date, category);
+        query.group_by("u.category").order_by("COUNT(*) DESC, u.category IS NULL, u.category");
+        let categories = query.fetch_all(c, ToneBreakdown::from_row)?;
+
+        let domains = |order: &str| {
+            let mut query = tone_query("u.domain", start_date, end_date, category);
+            query.group_by("u.domain")
+                .having_filter("COUNT(*) >= ?", MIN_DOMAIN_VISITS as i64)
+                .order_by(order)
+                .limit(SENTIMENT_DOMAINS);
+            query.fetch_all(c, ToneBreakdown::from_row)
+        };
+        let most_negative_domains = domains("TOTAL(m.sentiment) / COUNT(*) ASC, u.domain")?;
+        let most_positive_domains = domains("TOTAL(m.sentiment) / COUNT(*) DESC, u.domain")?;
+
+        Ok(SentimentStats { overall, categories, most_negative_domains, most_positive_domains })
+    })
+}
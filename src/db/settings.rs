@@ -0,0 +1,57 @@
+// Settings Storage
+// Key/value persistence for application settings (values stored as JSON)
+
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::error::{DatabaseError, Result};
+
+/// Reads a setting and deserializes it, returning None if it has never been set
+pub fn get_setting<T: DeserializeOwned>(conn: &Connection, key: &str) -> Result<Option<T>> {
+    let value = conn.query_row(
+        "SELECT value FROM settings WHERE key = ?",
+        [key],
+        |row| row.get::<_, String>(0),
+    );
+    
+    match value {
+        Ok(json) => serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| DatabaseError::Data(format!("Invalid value for setting '{}': {}", key, e))),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(DatabaseError::Query(e.to_string())),
+    }
+}
+
+/// Serializes and stores a setting, replacing any previous value
+pub fn set_setting<T: Serialize>(conn: &Connection, key: &str, value: &T) -> Result<()> {
+    let json = serde_json::to_string(value)
+        .map_err(|e| DatabaseError::Data(format!("Failed to serialize setting '{}': {}", key, e)))?;
+    
+    conn.execute(
+        "INSERT INTO settings (key, value, updated_at) VALUES (?, ?, ?)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+        params![key, json, Utc::now().timestamp()],
+    ).map_err(|e| DatabaseError::Query(e.to_string()))?;
+    
+    Ok(())
+}
+
+/// Reads every setting as a JSON object keyed by setting name
+pub fn list_settings(conn: &Connection) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let mut stmt = conn.prepare("SELECT key, value FROM settings ORDER BY key")
+        .map_err(|e| DatabaseError::Query(e.to_string()))?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| DatabaseError::Query(e.to_string()))?;
+    
+    let mut settings = serde_json::Map::new();
+    for row in rows {
+        let (key, json) = row.map_err(|e| DatabaseError::Query(e.to_string()))?;
+        let value = serde_json::from_str(&json)
+            .map_err(|e| DatabaseError::Data(format!("Invalid value for setting '{}': {}", key, e)))?;
+        settings.insert(key, value);
+    }
+    
+    Ok(settings)
+}
@@ -0,0 +1,129 @@
+// Import Sources
+// Fingerprints of imported history files, telling copies of one database
+// apart from different databases whatever path they were imported from
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::extractor::SourceFingerprint;
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+
+/// An imported history file
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportSource {
+    /// Path the file was imported from
+    pub path: String,
+    /// Path its visits are stored under; another path when the file is a copy
+    pub canonical_path: String,
+    /// Visits the file held when last imported
+    pub visits: u64,
+    /// When the file was first imported
+    pub first_imported_at: DateTime<Utc>,
+    /// When it was last imported
+    pub last_imported_at: DateTime<Utc>,
+}
+
+/// Where the visits of a file being imported go
+#[derive(Debug, Clone)]
+pub struct SourceResolution {
+    /// Path to store the visits under
+    pub canonical_path: String,
+    /// Set when the file was just recognized as a copy of this path
+    pub copy_of: Option<String>,
+}
+
+fn parse_fingerprint(json: &str) -> Result<SourceFingerprint> {
+    serde_json::from_str(json)
+        .map_err(|e| DatabaseError::Data(format!("Invalid source fingerprint: {}", e)))
+}
+
+/// Lists the imported history files, copies next to their originals
+pub fn list_import_sources(conn: &DatabaseConnection) -> Result<Vec<ImportSource>> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT path, canonical_path, fingerprint, first_imported_at, last_imported_at
+             FROM import_source
+             ORDER BY canonical_path, path != canonical_path, path"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?, row.get::<_, i64>(4)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        rows.into_iter().map(|(path, canonical_path, fingerprint, first, last)| {
+            Ok(ImportSource {
+                path,
+                canonical_path,
+                visits: parse_fingerprint(&fingerprint)?.visits,
+                first_imported_at: DateTime::from_timestamp(first, 0).unwrap_or_default(),
+                last_imported_at: DateTime::from_timestamp(last, 0).unwrap_or_default(),
+            })
+        }).collect()
+    })
+}
+
+/// Finds the original of a file among the other known sources
+fn find_original(c: &Connection, path: &str, fingerprint: &SourceFingerprint) -> Result<Option<String>> {
+    let mut stmt = c.prepare("SELECT canonical_path, fingerprint FROM import_source WHERE path != ?")?;
+    let known = stmt.query_map([path], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for (canonical_path, known_fingerprint) in known {
+        if canonical_path != path && parse_fingerprint(&known_fingerprint)?.same_source(fingerprint) {
+            return Ok(Some(canonical_path));
+        }
+    }
+    Ok(None)
+}
+
+/// Records a file about to be imported and decides which path its visits are
+/// stored under: its own, or the original's when it is a copy of a known file
+pub fn resolve_source(conn: &DatabaseConnection, path: &str, fingerprint: &SourceFingerprint) -> Result<SourceResolution> {
+    let json = serde_json::to_string(fingerprint)
+        .map_err(|e| DatabaseError::Data(format!("Failed to serialize source fingerprint: {}", e)))?;
+    let now = Utc::now().timestamp();
+
+    conn.transaction(|tx| {
+        let known: Option<String> = tx.query_row(
+            "SELECT canonical_path FROM import_source WHERE path = ?",
+            [path],
+            |row| row.get(0),
+        ).optional()?;
+
+        // A file keeps the path it was first resolved to
+        if let Some(canonical_path) = known {
+            tx.execute(
+                "UPDATE import_source SET fingerprint = ?, last_imported_at = ? WHERE path = ?",
+                params![json, now, path],
+            )?;
+            return Ok(SourceResolution { canonical_path, copy_of: None });
+        }
+
+        let original = find_original(tx, path, fingerprint)?;
+        let canonical_path = original.clone().unwrap_or_else(|| path.to_string());
+        tx.execute(
+            "INSERT INTO import_source (path, canonical_path, fingerprint, first_imported_at, last_imported_at)
+             VALUES (?, ?, ?, ?, ?)",
+            params![path, canonical_path, json, now, now],
+        )?;
+        Ok(SourceResolution { canonical_path, copy_of: original })
+    })
+}
+
+/// Moves the visits stored under a copy's path to the original's, dropping
+/// the ones the original already has; returns how many were dropped
+pub fn merge_copy_visits(conn: &DatabaseConnection, copy_path: &str, canonical_path: &str) -> Result<usize> {
+    conn.transaction(|tx| {
+        tx.execute(
+            "UPDATE OR IGNORE visit SET source_file = ? WHERE source_file = ?",
+            params![canonical_path, copy_path],
+        )?;
+        // What is left collided with a visit of the original
+        Ok(tx.execute("DELETE FROM visit WHERE source_file = ?", [copy_path])?)
+    })
+}
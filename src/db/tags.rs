@@ -0,0 +1,244 @@
+// Tag Management
+// User tags stored in the tag and url_tag tables
+
+use chrono::Utc;
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+use super::journal::{capture, finish_operation, placeholders, start_operation};
+
+/// A tag and the number of URLs carrying it
+#[derive(Debug, Clone, Serialize)]
+pub struct Tag {
+    /// Tag identifier
+    pub id: i64,
+    /// Display name (unique, case-insensitive)
+    pub name: String,
+    /// Number of tagged URLs
+    pub url_count: usize,
+}
+
+/// Trims a tag name and collapses inner whitespace, rejecting empty names
+fn normalize_name(name: &str) -> Result<String> {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return Err(DatabaseError::Data("Tag name cannot be empty".to_string()));
+    }
+    Ok(name)
+}
+
+/// Loads a single tag with its URL count
+fn get_tag(c: &Connection, id: i64) -> Result<Tag> {
+    c.query_row(
+        "SELECT t.id, t.name, (SELECT COUNT(*) FROM url_tag ut WHERE ut.tag_id = t.id)
+         FROM tag t WHERE t.id = ?",
+        [id],
+        |row| Ok(Tag { id: row.get(0)?, name: row.get(1)?, url_count: row.get::<_, i64>(2)? as usize }),
+    ).optional()?
+    .ok_or_else(|| DatabaseError::NotFound(format!("Tag {} does not exist", id)))
+}
+
+/// Returns the id of the tag with the given name, creating it if needed
+fn ensure_tag(c: &Connection, name: &str) -> Result<i64> {
+    let name = normalize_name(name)?;
+
+    c.execute(
+        "INSERT OR IGNORE INTO tag (name, created_at) VALUES (?, ?)",
+        params![name, Utc::now().timestamp()],
+    )?;
+
+    Ok(c.query_row("SELECT id FROM tag WHERE name = ?", [&name], |row| row.get(0))?)
+}
+
+/// Lists every tag with its URL count, most used first
+pub fn list_tags(conn: &DatabaseConnection) -> Result<Vec<Tag>> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT t.id, t.name, COUNT(ut.url_id) as url_count
+             FROM tag t
+             LEFT JOIN url_tag ut ON ut.tag_id = t.id
+             GROUP BY t.id
+             ORDER BY url_count DESC, t.name COLLATE NOCASE"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Tag { id: row.get(0)?, name: row.get(1)?, url_count: row.get::<_, i64>(2)? as usize })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
+
+/// Creates a tag, returning the existing one if the name is already taken
+pub fn create_tag(conn: &DatabaseConnection, name: &str) -> Result<Tag> {
+    conn.with_connection(|c| {
+        let id = ensure_tag(c, name)?;
+        get_tag(c, id)
+    })
+}
+
+/// Renames a tag; fails if another tag already has the name (merge them instead)
+pub fn rename_tag(conn: &DatabaseConnection, id: i64, name: &str) -> Result<Tag> {
+    let name = normalize_name(name)?;
+
+    conn.with_connection(|c| {
+        let existing: Option<i64> = c.query_row(
+            "SELECT id FROM tag WHERE name = ? AND id != ?",
+            params![name, id],
+            |row| row.get(0),
+        ).optional()?;
+        if existing.is_some() {
+            return Err(DatabaseError::Data(format!("A tag named '{}' already exists", name)));
+        }
+
+        if c.execute("UPDATE tag SET name = ? WHERE id = ?", params![name, id])? == 0 {
+            return Err(DatabaseError::NotFound(format!("Tag {} does not exist", id)));
+        }
+        get_tag(c, id)
+    })
+}
+
+/// Moves every URL tagged with one of `source_ids` to `target_id` and deletes the sources
+pub fn merge_tags(conn: &DatabaseConnection, source_ids: &[i64], target_id: i64) -> Result<Tag> {
+    conn.transaction(|tx| {
+        // Fail early if the target doesn't exist
+        let target = get_tag(tx, target_id)?;
+
+        let mut tag_ids: Vec<i64> = source_ids.iter().copied().filter(|id| *id != target_id).collect();
+        tag_ids.push(target_id);
+        let ids: Vec<Value> = tag_ids.iter().map(|id| Value::Integer(*id)).collect();
+        let operation_id = start_operation(
+            tx,
+            "merge_tags",
+            &format!("Merged {} tags into '{}'", tag_ids.len() - 1, target.name),
+        )?;
+        capture(tx, operation_id, "tag", &format!("id IN ({})", placeholders(ids.len())), ids.clone())?;
+        capture(tx, operation_id, "url_tag", &format!("tag_id IN ({})", placeholders(ids.len())), ids)?;
+
+        for source_id in source_ids.iter().filter(|id| **id != target_id) {
+            tx.execute(
+                "INSERT OR IGNORE INTO url_tag (url_id, tag_id, created_at)
+                 SELECT url_id, ?, created_at FROM url_tag WHERE tag_id = ?",
+                params![target_id, source_id],
+            )?;
+            tx.execute("DELETE FROM tag WHERE id = ?", [source_id])?;
+        }
+
+        finish_operation(tx, operation_id)?;
+        get_tag(tx, target_id)
+    })
+}
+
+/// Deletes a tag and removes it from every URL
+pub fn delete_tag(conn: &DatabaseConnection, id: i64) -> Result<()> {
+    conn.transaction(|tx| {
+        let tag = get_tag(tx, id)?;
+
+        let operation_id = start_operation(tx, "delete_tag", &format!("Deleted tag '{}'", tag.name))?;
+        capture(tx, operation_id, "tag", "id = ?", vec![Value::Integer(id)])?;
+        capture(tx, operation_id, "url_tag", "tag_id = ?", vec![Value::Integer(id)])?;
+
+        tx.execute("DELETE FROM tag WHERE id = ?", [id])?;
+        finish_operation(tx, operation_id)
+    })
+}
+
+/// Adds every tag in `names` (created as needed) to every URL, returning the
+/// number of new URL/tag pairs
+pub fn tag_urls(conn: &DatabaseConnection, url_ids: &[String], names: &[String]) -> Result<usize> {
+    if url_ids.is_empty() || names.is_empty() {
+        return Ok(0);
+    }
+
+    let names = names.iter()
+        .map(|name| normalize_name(name))
+        .collect::<Result<Vec<String>>>()?;
+
+    conn.transaction(|tx| {
+        // Tags are matched by name so undo also removes tags this call created
+        let name_values: Vec<Value> = names.iter().map(|name| Value::Text(name.clone())).collect();
+        let url_values: Vec<Value> = url_ids.iter().map(|id| Value::Text(id.clone())).collect();
+        let operation_id = start_operation(
+            tx,
+            "tag_urls",
+            &format!("Tagged {} URLs with {}", url_ids.len(), names.join(", ")),
+        )?;
+        capture(tx, operation_id, "tag", &format!("name IN ({})", placeholders(names.len())), name_values.clone())?;
+        capture(
+            tx,
+            operation_id,
+            "url_tag",
+            &format!(
+                "url_id IN ({}) AND tag_id IN (SELECT id FROM tag WHERE name IN ({}))",
+                placeholders(url_ids.len()),
+                placeholders(names.len()),
+            ),
+            url_values.into_iter().chain(name_values).collect(),
+        )?;
+
+        let tag_ids = names.iter()
+            .map(|name| ensure_tag(tx, name))
+            .collect::<Result<Vec<i64>>>()?;
+
+        let now = Utc::now().timestamp();
+        let mut stmt = tx.prepare(
+            "INSERT OR IGNORE INTO url_tag (url_id, tag_id, created_at)
+             SELECT id, ?, ? FROM url WHERE id = ?"
+        )?;
+
+        let mut added = 0;
+        for url_id in url_ids {
+            for tag_id in &tag_ids {
+                added += stmt.execute(params![tag_id, now, url_id])?;
+            }
+        }
+        finish_operation(tx, operation_id)?;
+        Ok(added)
+    })
+}
+
+/// Removes the given tags from every URL, returning the number of removed pairs
+pub fn untag_urls(conn: &DatabaseConnection, url_ids: &[String], tag_ids: &[i64]) -> Result<usize> {
+    if url_ids.is_empty() || tag_ids.is_empty() {
+        return Ok(0);
+    }
+
+    conn.transaction(|tx| {
+        let url_values: Vec<Value> = url_ids.iter().map(|id| Value::Text(id.clone())).collect();
+        let tag_values: Vec<Value> = tag_ids.iter().map(|id| Value::Integer(*id)).collect();
+        let operation_id = start_operation(
+            tx,
+            "untag_urls",
+            &format!("Removed {} tags from {} URLs", tag_ids.len(), url_ids.len()),
+        )?;
+        capture(
+            tx,
+            operation_id,
+            "url_tag",
+            &format!("url_id IN ({}) AND tag_id IN ({})", placeholders(url_ids.len()), placeholders(tag_ids.len())),
+            url_values.into_iter().chain(tag_values).collect(),
+        )?;
+
+        let mut stmt = tx.prepare("DELETE FROM url_tag WHERE url_id = ? AND tag_id = ?")?;
+
+        let mut removed = 0;
+        for url_id in url_ids {
+            for tag_id in tag_ids {
+                removed += stmt.execute(params![url_id, tag_id])?;
+            }
+        }
+        finish_operation(tx, operation_id)?;
+        Ok(removed)
+    })
+}
+
+/// Gets the tag names of a URL in alphabetical order
+pub fn get_url_tags(c: &Connection, url_id: &str) -> Result<Vec<String>> {
+    let mut stmt = c.prepare_cached(
+        "SELECT t.name FROM url_tag ut JOIN tag t ON t.id = ut.tag_id
+         WHERE ut.url_id = ? ORDER BY t.name COLLATE NOCASE"
+    )?;
+    let rows = stmt.query_map([url_id], |row| row.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<Vec<String>>>()?)
+}
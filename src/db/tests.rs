@@ -0,0 +1,310 @@
+#[cfg(test)]
+mod tests {
+    use crate::db::query::QueryBuilder;
+    use crate::db::analytics::{browsing_patterns, WorkingHours, WorkWindow};
+    use crate::db::readonly::{validate_select, CONSOLE_TABLES};
+    use crate::db::journal::{capture, finish_operation, start_operation, undo_latest};
+    use crate::db::merge::{normalize_title, normalize_url};
+    use crate::db::origins::{classify_origin, VisitOrigin};
+    use crate::db::trackers::{parse_blocklist, TrackerKind};
+    use crate::db::domains::registrable_domain;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use rusqlite::types::Value;
+    use rusqlite::Connection;
+
+    // Helper to create an in-memory database with a few numbered rows
+    fn create_numbers_db() -> Connection {
+        let conn = Connection::open_in_memory().expect("Failed to open in-memory database");
+        
+        conn.execute_batch(
+            "CREATE TABLE numbers (n INTEGER NOT NULL, label TEXT NOT NULL);
+             INSERT INTO numbers (n, label) VALUES
+                (1, 'odd'), (2, 'even'), (3, 'odd'), (4, 'even'), (5, 'odd');"
+        ).expect("Failed to create numbers table");
+        
+        conn
+    }
+
+    #[test]
+    fn test_query_builder_binds_limit_and_offset() {
+        let mut query = QueryBuilder::new("SELECT n FROM numbers");
+        query.filter("label = ?", "odd".to_string())
+            .order_by("n ASC")
+            .limit(2)
+            .offset(1);
+        
+        // LIMIT and OFFSET must be placeholders, never literal values
+        let sql = query.sql();
+        assert!(sql.ends_with("LIMIT ? OFFSET ?"));
+        assert_eq!(query.params().len(), 3);
+        
+        let conn = create_numbers_db();
+        let rows: Vec<i64> = query.fetch_all(&conn, |row| row.get(0))
+            .expect("Query failed");
+        assert_eq!(rows, vec![3, 5]);
+    }
+    
+    #[test]
+    fn test_query_builder_offset_without_limit() {
+        let mut query = QueryBuilder::new("SELECT n FROM numbers");
+        query.order_by("n ASC").offset(3);
+        
+        let conn = create_numbers_db();
+        let rows: Vec<i64> = query.fetch_all(&conn, |row| row.get(0))
+            .expect("Query failed");
+        assert_eq!(rows, vec![4, 5]);
+    }
+    
+    #[test]
+    fn test_query_builder_count_ignores_paging() {
+        let mut query = QueryBuilder::new("SELECT label, COUNT(*) FROM numbers");
+        query.filter("n > ?", 1i64)
+            .group_by("label")
+            .limit(1);
+        
+        let conn = create_numbers_db();
+        assert_eq!(query.count(&conn).expect("Count failed"), 2);
+    }
+    
+    #[test]
+    fn test_query_builder_treats_values_as_data() {
+        // A value that looks like SQL must not change the statement
+        let mut query = QueryBuilder::new("SELECT n FROM numbers");
+        query.filter("label = ?", "odd' OR '1'='1".to_string());
+        
+        let conn = create_numbers_db();
+        let rows: Vec<i64> = query.fetch_all(&conn, |row| row.get(0))
+            .expect("Query failed");
+        assert!(rows.is_empty());
+    }
+    
+    #[test]
+    fn test_working_hours_respect_offset_and_days() {
+        let working_hours = WorkingHours {
+            windows: vec![WorkWindow {
+                days: vec![0, 1, 2, 3, 4],
+                start_minute: 9 * 60,
+                end_minute: 17 * 60,
+            }],
+            utc_offset_minutes: -180,
+        };
+        
+        // Wednesday 13:00 UTC is 10:00 local time
+        let wednesday = Utc.with_ymd_and_hms(2024, 3, 6, 13, 0, 0).unwrap();
+        assert!(working_hours.is_work_time(wednesday));
+        
+        // Wednesday 10:00 UTC is 07:00 local time
+        let early = Utc.with_ymd_and_hms(2024, 3, 6, 10, 0, 0).unwrap();
+        assert!(!working_hours.is_work_time(early));
+        
+        // Saturday is never a working day
+        let saturday = Utc.with_ymd_and_hms(2024, 3, 9, 13, 0, 0).unwrap();
+        assert!(!working_hours.is_work_time(saturday));
+    }
+    
+    #[test]
+    fn test_browsing_patterns_streaks_and_gaps() {
+        let conn = Connection::open_in_memory().expect("Failed to open in-memory database");
+        conn.execute_batch(
+            "CREATE TABLE url (id TEXT PRIMARY KEY, domain TEXT NOT NULL);
+             CREATE TABLE visit (id INTEGER PRIMARY KEY, url_id TEXT NOT NULL, visited_at INTEGER NOT NULL);
+             INSERT INTO url (id, domain) VALUES ('a', 'example.com'), ('b', 'other.org');"
+        ).expect("Failed to create history tables");
+        
+        // example.com on March 1, 2, 4, 5 and 6; other.org fills in March 3
+        let visits = [
+            ("a", (1, 10)), ("a", (2, 10)), ("a", (2, 12)), ("b", (3, 12)),
+            ("a", (4, 9)), ("a", (5, 9)), ("a", (6, 9)), ("a", (6, 20)),
+        ];
+        for (url_id, (day, hour)) in visits {
+            let at = Utc.with_ymd_and_hms(2024, 3, day, hour, 0, 0).unwrap().timestamp();
+            conn.execute("INSERT INTO visit (url_id, visited_at) VALUES (?, ?)", rusqlite::params![url_id, at]).unwrap();
+        }
+        let march = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        
+        // The missing day breaks the streak, and the longest gap spans it
+        let patterns = browsing_patterns(&conn, Some("example.com".to_string()), None, None).unwrap();
+        assert_eq!(patterns.total_visits, 7);
+        assert_eq!(patterns.active_days, 5);
+        let streak = patterns.longest_streak.unwrap();
+        assert_eq!((streak.start, streak.end, streak.days), (march(4), march(6), 3));
+        let gap = patterns.longest_gap.unwrap();
+        assert_eq!(gap.start, Utc.with_ymd_and_hms(2024, 3, 2, 12, 0, 0).unwrap());
+        assert_eq!(gap.end, Utc.with_ymd_and_hms(2024, 3, 4, 9, 0, 0).unwrap());
+        assert_eq!(gap.seconds, 45 * 60 * 60);
+        
+        // Earliest of the tied days wins
+        assert_eq!(patterns.busiest_day, Some((march(2), 2)));
+        assert_eq!(patterns.quietest_day, Some((march(1), 1)));
+        
+        // Across every domain the days are consecutive
+        let patterns = browsing_patterns(&conn, None, None, None).unwrap();
+        let streak = patterns.longest_streak.unwrap();
+        assert_eq!((streak.start, streak.end, streak.days), (march(1), march(6), 6));
+        assert_eq!(patterns.longest_gap.unwrap().seconds, 24 * 60 * 60);
+    }
+    
+    #[test]
+    fn test_validate_select_only_reads_allowed_views() {
+        let allowed = &["history_visits", "history_pages"];
+        
+        // Joins, comma lists, aliases and CTEs over the allowed views are fine
+        assert!(validate_select(
+            "SELECT p.domain, COUNT(*) FROM history_visits v JOIN history_pages AS p ON p.page_id = v.page_id GROUP BY 1;",
+            allowed,
+        ).is_ok());
+        assert!(validate_select(
+            "WITH recent AS (SELECT * FROM history_visits WHERE visited_ts > 0) SELECT * FROM recent, history_pages",
+            allowed,
+        ).is_ok());
+        
+        // Base tables, writes, multiple statements and schema tricks are not
+        assert!(validate_select("SELECT * FROM url", allowed).is_err());
+        assert!(validate_select("SELECT * FROM history_pages, settings", allowed).is_err());
+        assert!(validate_select("DELETE FROM history_visits", allowed).is_err());
+        assert!(validate_select("SELECT 1; DROP TABLE url", allowed).is_err());
+        assert!(validate_select("SELECT * FROM main.url", allowed).is_err());
+        assert!(validate_select("SELECT * FROM pragma_table_info('url')", allowed).is_err());
+        assert!(validate_select("SELECT * FROM history_pages WHERE page_id IN (SELECT url_id FROM \"visit\")", allowed).is_err());
+        
+        // Table names inside string literals and comments don't count
+        assert!(validate_select("SELECT 'FROM url' AS text FROM history_pages -- FROM url", allowed).is_ok());
+        assert!(validate_select("SELECT * FROM history_pages WHERE title IS DISTINCT FROM url", allowed).is_ok());
+        
+        // Subqueries and parenthesised joins in FROM are checked, and so is every table after them
+        assert!(validate_select("SELECT * FROM (SELECT * FROM history_pages) p, history_visits", allowed).is_ok());
+        assert!(validate_select("SELECT * FROM (history_pages p JOIN history_visits v ON (v.page_id = p.page_id))", allowed).is_ok());
+        assert!(validate_select("SELECT * FROM (SELECT 1) x, settings", allowed).is_err());
+        assert!(validate_select("SELECT * FROM (SELECT 1) AS x JOIN url ON 1", allowed).is_err());
+        assert!(validate_select("SELECT * FROM (history_pages JOIN url)", allowed).is_err());
+        
+        // A CTE name only counts within its own statement
+        assert!(validate_select("SELECT (WITH settings AS (SELECT 1) SELECT 1), value FROM settings", allowed).is_err());
+        assert!(validate_select(
+            "SELECT * FROM history_pages WHERE page_id IN (WITH ids AS (SELECT page_id FROM history_visits) SELECT * FROM ids)",
+            allowed,
+        ).is_ok());
+    }
+    
+    #[test]
+    fn test_console_tables_leave_out_secrets() {
+        assert!(validate_select("SELECT u.url, COUNT(*) FROM visit v JOIN url u ON u.id = v.url_id GROUP BY 1", CONSOLE_TABLES).is_ok());
+        
+        // Keys, sync secrets and scripts stay out of reach
+        assert!(validate_select("SELECT * FROM settings", CONSOLE_TABLES).is_err());
+        assert!(validate_select("SELECT secret FROM sync_peer", CONSOLE_TABLES).is_err());
+        assert!(validate_select("SELECT * FROM url, script", CONSOLE_TABLES).is_err());
+        assert!(validate_select("SELECT * FROM (SELECT 1) x, sync_peer", CONSOLE_TABLES).is_err());
+        assert!(validate_select("SELECT (WITH sync_peer AS (SELECT 1) SELECT 1), secret FROM sync_peer", CONSOLE_TABLES).is_err());
+    }
+    
+    #[test]
+    fn test_normalize_url_ignores_cosmetic_differences() {
+        let normalized = normalize_url("https://example.com/article?id=7").unwrap();
+        
+        assert_eq!(normalize_url("http://www.Example.com/article/?id=7#comments").as_ref(), Some(&normalized));
+        assert_eq!(normalize_url("https://example.com/article?utm_source=feed&id=7&fbclid=abc").as_ref(), Some(&normalized));
+        
+        // Meaningful query parameters and paths still differ
+        assert_ne!(normalize_url("https://example.com/article?id=8").as_ref(), Some(&normalized));
+        assert_ne!(normalize_url("https://example.com/articles?id=7").as_ref(), Some(&normalized));
+    }
+
+    #[test]
+    fn test_normalize_title_drops_site_names_and_counts() {
+        let normalized = normalize_title("Why SQLite Is So Fast").unwrap();
+        
+        assert_eq!(normalize_title("(3) Why SQLite is so fast | Hacker News").as_ref(), Some(&normalized));
+        assert_eq!(normalize_title("Why SQLite is so fast — The Example Blog").as_ref(), Some(&normalized));
+        assert_eq!(normalize_title("  why sqlite is, so fast!  ").as_ref(), Some(&normalized));
+        
+        // Short, generic titles are not clustered at all
+        assert_eq!(normalize_title("Inbox | Mail"), None);
+        assert_eq!(normalize_title("Home"), None);
+    }
+
+    #[test]
+    fn test_classify_origin() {
+        let classify = |referrer| classify_origin("example.com", referrer, None, None);
+        
+        assert_eq!(classify(Some("https://www.google.co.uk/search?q=sqlite")), VisitOrigin::Search);
+        assert_eq!(classify(Some("https://duckduckgo.com/")), VisitOrigin::Search);
+        assert_eq!(classify(Some("https://old.reddit.com/r/rust/")), VisitOrigin::Social);
+        assert_eq!(classify(Some("https://mail.google.com/mail/u/0/")), VisitOrigin::Newsletter);
+        assert_eq!(classify(Some("https://www.example.com/index")), VisitOrigin::Internal);
+        assert_eq!(classify(Some("https://docs.google.com/document/d/1")), VisitOrigin::External);
+        assert_eq!(classify(None), VisitOrigin::Direct);
+        
+        // Campaign parameters win over the referrer, and stand in for a missing one
+        assert_eq!(classify_origin("example.com", Some("https://t.co/x"), None, Some("Email")), VisitOrigin::Newsletter);
+        assert_eq!(classify_origin("example.com", None, Some("twitter"), None), VisitOrigin::Social);
+    }
+
+    #[test]
+    fn test_parse_blocklist_formats() {
+        let easylist = "[Adblock Plus 2.0]\n! Title: EasyList\n||ads.example.com^\n||track.example.net^$third-party\n\
+                        ||cdn.example.org/banner.js\n@@||ads.example.com^$document\nexample.com##.ad\n||*.wild.example^";
+        assert_eq!(
+            parse_blocklist(easylist, TrackerKind::Ads).unwrap(),
+            vec![("ads.example.com".to_string(), TrackerKind::Ads), ("track.example.net".to_string(), TrackerKind::Ads)]
+        );
+        
+        let hosts = "# hosts\n127.0.0.1 localhost\n0.0.0.0 Click.Example.com # redirect\nplain.example.io\n";
+        assert_eq!(
+            parse_blocklist(hosts, TrackerKind::Tracker).unwrap(),
+            vec![("click.example.com".to_string(), TrackerKind::Tracker), ("plain.example.io".to_string(), TrackerKind::Tracker)]
+        );
+        
+        // Disconnect lists carry their own categories; social services are skipped
+        let disconnect = r#"{"categories": {
+            "Advertising": [{"AdCo": {"https://adco.example": ["adco.example", "shared.example"]}}],
+            "Analytics": [{"Stats": {"https://stats.example": ["stats.example", "shared.example"]}}],
+            "Social": [{"Friends": {"https://friends.example": ["friends.example"]}}]
+        }}"#;
+        assert_eq!(
+            parse_blocklist(disconnect, TrackerKind::Tracker).unwrap(),
+            vec![
+                ("adco.example".to_string(), TrackerKind::Ads),
+                ("shared.example".to_string(), TrackerKind::Ads),
+                ("stats.example".to_string(), TrackerKind::Tracker),
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_registrable_domain() {
+        assert_eq!(registrable_domain("www.example.com"), "example.com");
+        assert_eq!(registrable_domain("example.com"), "example.com");
+        assert_eq!(registrable_domain("news.bbc.co.uk"), "bbc.co.uk");
+        assert_eq!(registrable_domain("Docs.Rust-Lang.ORG."), "rust-lang.org");
+        assert_eq!(registrable_domain("user.github.io"), "user.github.io");
+        assert_eq!(registrable_domain("192.168.1.10"), "192.168.1.10");
+        assert_eq!(registrable_domain("localhost"), "localhost");
+    }
+
+    #[test]
+    fn test_undo_keeps_rows_added_after_the_operation() {
+        let conn = Connection::open_in_memory().expect("Failed to open in-memory database");
+        conn.execute_batch(include_str!("../../database/migrations/v18.sql")).unwrap();
+        conn.execute_batch(include_str!("../../database/migrations/v45.sql")).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE item (id INTEGER PRIMARY KEY, grp TEXT NOT NULL);
+             INSERT INTO item (id, grp) VALUES (1, 'a'), (2, 'a');"
+        ).unwrap();
+
+        let operation_id = start_operation(&conn, "test", "Test").unwrap();
+        capture(&conn, operation_id, "item", "grp = ?", vec![Value::Text("a".to_string())]).unwrap();
+        conn.execute_batch("DELETE FROM item WHERE id = 1; INSERT INTO item (id, grp) VALUES (3, 'a');").unwrap();
+        finish_operation(&conn, operation_id).unwrap();
+
+        // Matches the captured condition, but the operation didn't add it
+        conn.execute("INSERT INTO item (id, grp) VALUES (4, 'a')", []).unwrap();
+
+        assert!(undo_latest(&conn).unwrap().is_some());
+        let mut stmt = conn.prepare("SELECT id FROM item ORDER BY id").unwrap();
+        let ids: Vec<i64> = stmt.query_map([], |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(ids, vec![1, 2, 4]);
+    }
+}
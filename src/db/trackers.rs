@@ -0,0 +1,352 @@
+// Tracker Blocklists
+// Ad and tracker domains from the built-in list and user-loaded blocklists
+// (EasyList, Disconnect, hosts files), the classification of visited domains
+// against them, and the options that keep them out of stats and the graph
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::connection::DatabaseConnection;
+use super::error::{DatabaseError, Result};
+use super::settings::get_setting;
+
+/// Settings key of the tracker options
+pub const TRACKER_SETTING: &str = "trackers";
+
+/// Domains checked per round trip by `refresh_tracker_flags`
+const CHECK_BATCH: usize = 5000;
+
+/// Condition keeping visits of tracker domains out of a query over `url u`
+pub(crate) const NOT_TRACKER_DOMAIN: &str =
+    "u.domain NOT IN (SELECT domain FROM domain_stats WHERE tracker IS NOT NULL)";
+
+/// Where tracker domains are left out
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackerSettings {
+    /// Leave tracker domains out of top domains and top pages
+    pub exclude_from_stats: bool,
+    /// Leave tracker domains and their pages out of built graphs
+    pub exclude_from_graph: bool,
+}
+
+/// What a blocklisted domain does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackerKind {
+    /// Serves or redirects through ads
+    Ads,
+    /// Tracks, measures or redirects clicks
+    Tracker,
+}
+
+impl TrackerKind {
+    /// Returns the value stored in the `kind` and `tracker` columns
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackerKind::Ads => "ads",
+            TrackerKind::Tracker => "tracker",
+        }
+    }
+
+    /// Parses a stored value
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ads" => Some(TrackerKind::Ads),
+            "tracker" => Some(TrackerKind::Tracker),
+            _ => None,
+        }
+    }
+}
+
+/// Normalizes a blocklist entry to a domain, or None when it is not one
+fn blocklist_domain(entry: &str) -> Option<String> {
+    let domain = entry.trim().trim_end_matches('.').to_lowercase();
+    let valid = domain.contains('.')
+        && !domain.starts_with(['.', '-'])
+        && domain.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '.' || ch == '-')
+        && !domain.chars().all(|ch| ch.is_ascii_digit() || ch == '.');
+    valid.then_some(domain)
+}
+
+/// Reads the domains of a Disconnect services.json. Advertising counts as ads;
+/// analytics, email, fingerprinting and cryptomining as trackers. Social and
+/// content services are skipped, as they list sites people actually read.
+fn parse_disconnect(text: &str) -> Result<Vec<(String, TrackerKind)>> {
+    let json: serde_json::Value = serde_json::from_str(text)
+        .map_err(|e| DatabaseError::Data(format!("Invalid Disconnect list: {}", e)))?;
+    let categories = json.get("categories").and_then(|c| c.as_object())
+        .ok_or_else(|| DatabaseError::Data("Invalid Disconnect list: no categories".to_string()))?;
+
+    let mut domains = Vec::new();
+    for (category, services) in categories {
+        let kind = match category.as_str() {
+            "Advertising" => TrackerKind::Ads,
+            "Analytics" | "Email" | "EmailAggressive" | "FingerprintingInvasive"
+            | "FingerprintingGeneral" | "Cryptomining" => TrackerKind::Tracker,
+            _ => continue,
+        };
+        // [{ "Company": { "https://company.example": ["domain", ...] } }]
+        let lists = services.as_array().into_iter().flatten()
+            .filter_map(|service| service.as_object())
+            .flat_map(|service| service.values())
+            .filter_map(|sites| sites.as_object())
+            .flat_map(|sites| sites.values())
+            .filter_map(|list| list.as_array());
+        for list in lists {
+            domains.extend(list.iter()
+                .filter_map(|domain| domain.as_str().and_then(blocklist_domain))
+                .map(|domain| (domain, kind)));
+        }
+    }
+    Ok(domains)
+}
+
+/// Reads the domain of one line of an Adblock Plus, hosts or plain domain list.
+/// Only whole-domain rules count: `||ads.example^`, not paths, wildcards,
+/// exceptions or element hiding.
+fn parse_line(line: &str) -> Option<String> {
+    let line = line.trim();
+    let element_hiding = ["##", "#@#", "#?#", "#$#"].iter().any(|marker| line.contains(marker));
+    if line.is_empty() || line.starts_with(['!', '#', '[']) || line.starts_with("@@") || element_hiding {
+        return None;
+    }
+
+    if let Some(rule) = line.strip_prefix("||") {
+        let end = rule.find(['^', '$', '/']).unwrap_or(rule.len());
+        let (domain, rest) = rule.split_at(end);
+        if (rest.starts_with('/') && rest.len() > 1) || domain.contains('*') {
+            return None;
+        }
+        return blocklist_domain(domain);
+    }
+
+    // 0.0.0.0 ads.example, 127.0.0.1 ads.example, or a bare domain
+    let fields: Vec<&str> = line.split('#').next()?.split_whitespace().collect();
+    match fields.as_slice() {
+        [address, domain, ..] if address.parse::<std::net::IpAddr>().is_ok() => blocklist_domain(domain),
+        [domain] => blocklist_domain(domain),
+        _ => None,
+    }
+}
+
+/// Parses a blocklist into its domains, sorted and without duplicates. A
+/// Disconnect JSON list carries its own kinds; every domain of a line-based
+/// list (EasyList, EasyPrivacy, hosts files) gets `kind`.
+pub fn parse_blocklist(text: &str, kind: TrackerKind) -> Result<Vec<(String, TrackerKind)>> {
+    let entries = if text.trim_start().starts_with('{') {
+        parse_disconnect(text)?
+    } else {
+        text.lines().filter_map(parse_line).map(|domain| (domain, kind)).collect()
+    };
+
+    // Ads wins when a domain is listed as both
+    let mut domains = BTreeMap::new();
+    for (domain, kind) in entries {
+        domains.entry(domain)
+            .and_modify(|existing: &mut TrackerKind| *existing = (*existing).min(kind))
+            .or_insert(kind);
+    }
+    Ok(domains.into_iter().collect())
+}
+
+/// Parent domains a blocklist entry can match a host through, the host first
+pub(crate) fn domain_suffixes(domain: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(domain), |d| d.split_once('.').map(|(_, parent)| parent))
+        .filter(|d| d.contains('.'))
+}
+
+/// Checks the domains not matched against the blocklists since they were
+/// added or a list changed. Returns the number checked.
+pub(crate) fn refresh_tracker_flags(c: &Connection) -> Result<usize> {
+    let mut select = c.prepare_cached(
+        "SELECT domain FROM domain_stats INDEXED BY idx_domain_stats_tracker_pending
+         WHERE tracker_checked = 0
+         LIMIT ?"
+    )?;
+    // 'ads' sorts before 'tracker', so ads wins when lists disagree
+    let mut lookup = c.prepare_cached("SELECT MIN(kind) FROM blocklist_domain WHERE domain = ?")?;
+    let mut update = c.prepare_cached("UPDATE domain_stats SET tracker = ?, tracker_checked = 1 WHERE domain = ?")?;
+
+    let mut checked = 0;
+    loop {
+        let pending = select.query_map([CHECK_BATCH as i64], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for domain in &pending {
+            let mut kind: Option<String> = None;
+            for suffix in domain_suffixes(domain) {
+                kind = lookup.query_row([suffix], |row| row.get(0))?;
+                if kind.is_some() {
+                    break;
+                }
+            }
+            update.execute(params![kind, domain])?;
+        }
+
+        checked += pending.len();
+        if pending.len() < CHECK_BATCH {
+            return Ok(checked);
+        }
+    }
+}
+
+/// Reads the tracker options
+pub(crate) fn tracker_settings(c: &Connection) -> Result<TrackerSettings> {
+    Ok(get_setting(c, TRACKER_SETTING)?.unwrap_or_default())
+}
+
+/// The condition leaving tracker domains out of stats, when the user asked
+/// for it; flags are brought up to date first
+pub(crate) fn stats_exclusion(c: &Connection) -> Result<Option<&'static str>> {
+    if !tracker_settings(c)?.exclude_from_stats {
+        return Ok(None);
+    }
+    refresh_tracker_flags(c)?;
+    Ok(Some(NOT_TRACKER_DOMAIN))
+}
+
+/// A loaded blocklist
+#[derive(Debug, Clone, Serialize)]
+pub struct Blocklist {
+    /// Blocklist id
+    pub id: i64,
+    /// Name shown in settings; loading a list under the same name replaces it
+    pub name: String,
+    /// File the list was loaded from; None for the built-in list
+    pub source: Option<String>,
+    /// Number of domains on the list
+    pub domain_count: usize,
+    /// When the list was loaded
+    pub loaded_at: DateTime<Utc>,
+}
+
+/// Maps a blocklist row
+fn blocklist_from_row(row: &rusqlite::Row) -> rusqlite::Result<Blocklist> {
+    Ok(Blocklist {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        source: row.get(2)?,
+        domain_count: row.get::<_, i64>(3)? as usize,
+        loaded_at: DateTime::from_timestamp(row.get(4)?, 0).unwrap_or_default(),
+    })
+}
+
+/// Loads a blocklist file, replacing the list of the same name, and
+/// reclassifies every domain. The name defaults to the file name.
+pub fn load_blocklist(
+    conn: &DatabaseConnection,
+    path: &Path,
+    name: Option<String>,
+    kind: TrackerKind,
+) -> Result<Blocklist> {
+    let text = std::fs::read_to_string(path)?;
+    let domains = parse_blocklist(&text, kind)?;
+    if domains.is_empty() {
+        return Err(DatabaseError::Data(format!("No domains found in {}", path.display())));
+    }
+
+    let name = name.map(|name| name.trim().to_string()).filter(|name| !name.is_empty())
+        .or_else(|| path.file_name().map(|file| file.to_string_lossy().into_owned()))
+        .ok_or_else(|| DatabaseError::Data("Blocklist name cannot be empty".to_string()))?;
+    let source = path.display().to_string();
+
+    conn.transaction(|tx| {
+        let id: i64 = tx.query_row(
+            "INSERT INTO blocklist (name, source, domain_count, loaded_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (name) DO UPDATE SET source = ?2, domain_count = ?3, loaded_at = ?4
+             RETURNING id",
+            params![name, source, domains.len() as i64, Utc::now().timestamp()],
+            |row| row.get(0),
+        )?;
+
+        tx.execute("DELETE FROM blocklist_domain WHERE blocklist_id = ?", [id])?;
+        let mut insert = tx.prepare_cached("INSERT INTO blocklist_domain (blocklist_id, domain, kind) VALUES (?, ?, ?)")?;
+        for (domain, kind) in &domains {
+            insert.execute(params![id, domain, kind.as_str()])?;
+        }
+
+        tx.execute("UPDATE domain_stats SET tracker_checked = 0", [])?;
+        refresh_tracker_flags(tx)?;
+
+        Ok(tx.query_row(
+            "SELECT id, name, source, domain_count, loaded_at FROM blocklist WHERE id = ?",
+            [id],
+            blocklist_from_row,
+        )?)
+    })
+}
+
+/// Lists the loaded blocklists, the built-in one first
+pub fn list_blocklists(conn: &DatabaseConnection) -> Result<Vec<Blocklist>> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT id, name, source, domain_count, loaded_at FROM blocklist
+             ORDER BY source IS NOT NULL, name COLLATE NOCASE"
+        )?;
+        let rows = stmt.query_map([], blocklist_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
+
+/// Removes a blocklist and reclassifies every domain
+pub fn delete_blocklist(conn: &DatabaseConnection, id: i64) -> Result<()> {
+    conn.transaction(|tx| {
+        if tx.execute("DELETE FROM blocklist WHERE id = ?", [id])? == 0 {
+            return Err(DatabaseError::NotFound(format!("Blocklist {} does not exist", id)));
+        }
+        tx.execute("UPDATE domain_stats SET tracker_checked = 0", [])?;
+        refresh_tracker_flags(tx)?;
+        Ok(())
+    })
+}
+
+/// A visited domain classified as an ad network or tracker
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackerDomain {
+    /// The domain
+    pub domain: String,
+    /// What it was classified as
+    pub kind: TrackerKind,
+    /// Blocklist entry it matched, the domain itself or a parent
+    pub matched: Option<String>,
+    /// Number of visits to the domain
+    pub visit_count: usize,
+}
+
+/// Lists the visited domains classified as ads or trackers, most visited first
+pub fn get_tracker_domains(conn: &DatabaseConnection, limit: usize) -> Result<Vec<TrackerDomain>> {
+    conn.with_connection(|c| {
+        super::domains::refresh_domain_stats(c)?;
+
+        let mut stmt = c.prepare(
+            "SELECT domain, tracker, visit_count FROM domain_stats
+             WHERE tracker IS NOT NULL
+             ORDER BY visit_count DESC, domain
+             LIMIT ?"
+        )?;
+        let rows = stmt.query_map([limit as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)? as usize))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut lookup = c.prepare_cached("SELECT 1 FROM blocklist_domain WHERE domain = ? LIMIT 1")?;
+        let mut domains = Vec::with_capacity(rows.len());
+        for (domain, kind, visit_count) in rows {
+            let Some(kind) = TrackerKind::parse(&kind) else { continue };
+            let mut matched = None;
+            for suffix in domain_suffixes(&domain) {
+                if lookup.query_row([suffix], |_| Ok(())).optional()?.is_some() {
+                    matched = Some(suffix.to_string());
+                    break;
+                }
+            }
+            domains.push(TrackerDomain { domain, kind, matched, visit_count });
+        }
+        Ok(domains)
+    })
+}
@@ -0,0 +1,129 @@
+// Deep Links
+// `historykg://` links that open a view of the app from other apps, e.g. a
+// search, a page's details or a day on the timeline linked from notes
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+use url::Url as UrlParser;
+
+use crate::error::AppError;
+
+/// URL scheme registered with the OS
+pub const SCHEME: &str = "historykg";
+
+/// Event telling the frontend to open the linked view
+pub const DEEP_LINK_EVENT: &str = "deep-link";
+
+/// A view a link opens
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "view", rename_all = "snake_case")]
+pub enum DeepLink {
+    /// `historykg://search?q=rust+async&domain=docs.rs`
+    Search {
+        query: String,
+        domain: Option<String>,
+    },
+    /// `historykg://url/<id>` or `historykg://url?u=<encoded URL>`
+    Url {
+        id: Option<String>,
+        url: Option<String>,
+    },
+    /// `historykg://timeline/2024-05-01`
+    Timeline {
+        date: NaiveDate,
+    },
+}
+
+impl DeepLink {
+    /// Parses a `historykg://` link
+    pub fn parse(link: &str) -> Result<Self, AppError> {
+        let invalid = |reason: &str| AppError::invalid_input(format!("Invalid link '{}': {}", link, reason));
+
+        let parsed = UrlParser::parse(link).map_err(|e| invalid(&e.to_string()))?;
+        if parsed.scheme() != SCHEME {
+            return Err(invalid("not a historykg:// link"));
+        }
+
+        // `historykg://search` puts the view in the host, `historykg:///search` in the path
+        let mut segments: Vec<String> = parsed.host_str().map(str::to_string).into_iter()
+            .chain(parsed.path_segments().into_iter().flatten().map(str::to_string))
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        let view = if segments.is_empty() { String::new() } else { segments.remove(0) };
+        let param = |name: &str| parsed.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .filter(|value| !value.is_empty());
+
+        match view.as_str() {
+            "search" => Ok(DeepLink::Search {
+                query: param("q").unwrap_or_default(),
+                domain: param("domain"),
+            }),
+            "url" => {
+                let id = segments.first().cloned().or_else(|| param("id"));
+                let url = param("u");
+                if id.is_none() && url.is_none() {
+                    return Err(invalid("a URL link needs an id or ?u="));
+                }
+                Ok(DeepLink::Url { id, url })
+            },
+            "timeline" => {
+                let date = segments.first().cloned().or_else(|| param("date"))
+                    .ok_or_else(|| invalid("a timeline link needs a date"))?;
+                let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .map_err(|_| invalid("dates are written YYYY-MM-DD"))?;
+                Ok(DeepLink::Timeline { date })
+            },
+            other => Err(invalid(&format!("unknown view '{}'", other))),
+        }
+    }
+
+    /// Builds the link opening this view
+    pub fn to_link(&self) -> String {
+        let mut link = UrlParser::parse(&format!("{}://", SCHEME)).expect("scheme URL is valid");
+        match self {
+            DeepLink::Search { query, domain } => {
+                link.set_host(Some("search")).expect("static host is valid");
+                let mut pairs = link.query_pairs_mut();
+                pairs.append_pair("q", query);
+                if let Some(domain) = domain {
+                    pairs.append_pair("domain", domain);
+                }
+            },
+            DeepLink::Url { id, url } => {
+                link.set_host(Some("url")).expect("static host is valid");
+                match (id, url) {
+                    (Some(id), _) => link.set_path(id),
+                    (None, Some(url)) => {
+                        link.query_pairs_mut().append_pair("u", url);
+                    },
+                    (None, None) => {},
+                }
+            },
+            DeepLink::Timeline { date } => {
+                link.set_host(Some("timeline")).expect("static host is valid");
+                link.set_path(&date.format("%Y-%m-%d").to_string());
+            },
+        }
+        link.to_string()
+    }
+}
+
+/// Brings the main window forward and tells the frontend to open the link.
+/// The link is also kept until the frontend takes it, in case the app was
+/// launched by it and no view is listening yet.
+pub fn open(app_handle: &tauri::AppHandle, link: DeepLink) {
+    if let Some(window) = app_handle.get_window("main") {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+
+    let state = app_handle.state::<crate::AppState>();
+    if let Ok(mut pending) = state.pending_deep_link.lock() {
+        *pending = Some(link.clone());
+    }
+    let _ = app_handle.emit_all(DEEP_LINK_EVENT, link);
+}
@@ -0,0 +1,167 @@
+// Response DTOs
+// Typed shapes of the rows that search and timeline commands return, so field
+// names stay stable and the frontend gets matching TypeScript types. Bindings
+// are written to src-svelte/lib/bindings by `cargo test`.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::db::models::UrlWithVisits;
+use crate::db::operations::{BucketUrlPage, SearchResult, TimelineItem};
+
+/// A URL with its visit count, as listed in timelines, top pages and favorites
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export, export_to = "src-svelte/lib/bindings/")]
+pub struct UrlSummaryDto {
+    #[ts(type = "string")]
+    pub id: Uuid,
+    pub url: String,
+    pub title: Option<String>,
+    pub domain: String,
+    pub visit_count: usize,
+    #[ts(type = "string | null")]
+    pub last_visit: Option<DateTime<Utc>>,
+}
+
+impl From<&UrlWithVisits> for UrlSummaryDto {
+    fn from(url: &UrlWithVisits) -> Self {
+        Self {
+            id: url.url.id,
+            url: url.url.url.clone(),
+            title: url.url.title.clone(),
+            domain: url.url.domain.clone(),
+            visit_count: url.visit_count,
+            last_visit: url.last_visit,
+        }
+    }
+}
+
+/// Converts a list of URLs
+pub fn url_summaries(urls: &[UrlWithVisits]) -> Vec<UrlSummaryDto> {
+    urls.iter().map(UrlSummaryDto::from).collect()
+}
+
+/// One row of search results; metadata fields are null until the URL is enriched
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export, export_to = "src-svelte/lib/bindings/")]
+pub struct SearchResultDto {
+    #[ts(type = "string")]
+    pub id: Uuid,
+    pub url: String,
+    pub title: Option<String>,
+    pub domain: String,
+    #[ts(type = "string")]
+    pub first_seen: DateTime<Utc>,
+    #[ts(type = "string")]
+    pub last_seen: DateTime<Utc>,
+    pub visit_count: usize,
+    pub summary: Option<String>,
+    pub keywords: Option<String>,
+    pub is_enriched: bool,
+    pub access_wall: Option<String>,
+    pub tags: Vec<String>,
+    #[ts(type = "string | null")]
+    pub last_visit: Option<DateTime<Utc>>,
+}
+
+impl From<SearchResult> for SearchResultDto {
+    fn from(result: SearchResult) -> Self {
+        let (summary, keywords, is_enriched, access_wall) = match result.metadata {
+            Some(metadata) => (metadata.summary, metadata.keywords, metadata.is_enriched, metadata.access_wall),
+            None => (None, None, false, None),
+        };
+        Self {
+            id: result.url.id,
+            url: result.url.url,
+            title: result.url.title,
+            domain: result.url.domain,
+            first_seen: result.url.first_seen,
+            last_seen: result.url.last_seen,
+            visit_count: result.visit_count,
+            summary,
+            keywords,
+            is_enriched,
+            access_wall,
+            tags: result.tags,
+            last_visit: result.last_visit,
+        }
+    }
+}
+
+/// One bucket of the timeline, told apart by `type`
+#[derive(Debug, Clone, Serialize, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[ts(export, export_to = "src-svelte/lib/bindings/")]
+pub enum TimelineBucketDto {
+    /// Visits in one hour of the day
+    Hour {
+        hour: u8,
+        count: u32,
+        #[ts(type = "string")]
+        timestamp: DateTime<Utc>,
+        urls: Vec<UrlSummaryDto>,
+    },
+    /// Visits on one day; `timestamp` repeats `date` for charts
+    Day {
+        #[ts(type = "string")]
+        date: DateTime<Utc>,
+        count: u32,
+        #[ts(type = "string")]
+        timestamp: DateTime<Utc>,
+        urls: Vec<UrlSummaryDto>,
+    },
+    /// Visits to one domain
+    Domain {
+        domain: String,
+        count: u32,
+        urls: Vec<UrlSummaryDto>,
+    },
+}
+
+impl From<&TimelineItem> for TimelineBucketDto {
+    fn from(item: &TimelineItem) -> Self {
+        let urls = |urls: &Option<Vec<UrlWithVisits>>| urls.as_deref().map(url_summaries).unwrap_or_default();
+        match item {
+            TimelineItem::Hourly { hour, count, timestamp, urls: sample } => Self::Hour {
+                hour: *hour,
+                count: *count,
+                timestamp: *timestamp,
+                urls: urls(sample),
+            },
+            TimelineItem::Daily { date, count, urls: sample } => Self::Day {
+                date: *date,
+                count: *count,
+                timestamp: *date,
+                urls: urls(sample),
+            },
+            TimelineItem::Domain { domain, count, urls: sample } => Self::Domain {
+                domain: domain.clone(),
+                count: *count,
+                urls: urls(sample),
+            },
+        }
+    }
+}
+
+/// A page of the URLs in one timeline bucket
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export, export_to = "src-svelte/lib/bindings/")]
+pub struct BucketUrlPageDto {
+    pub urls: Vec<UrlSummaryDto>,
+    pub total_count: usize,
+    pub page: usize,
+    pub page_size: usize,
+}
+
+impl From<&BucketUrlPage> for BucketUrlPageDto {
+    fn from(page: &BucketUrlPage) -> Self {
+        Self {
+            urls: url_summaries(&page.urls),
+            total_count: page.total_count,
+            page: page.page,
+            page_size: page.page_size,
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// Enrichment - Question Answering
+// Answers questions about the history from semantically retrieved pages, with citations
+
+use chrono::{DateTime, Utc};
+use rusqlite::OptionalExtension;
+
+use crate::db::DatabaseConnection;
+use super::embeddings::semantic_search;
+use super::error::{EnrichmentError, Result};
+use super::provider::{EmbeddingProvider, EnrichmentProvider};
+use super::usage::{new_run_id, record_request};
+
+/// Default number of pages retrieved as context for an answer
+pub const DEFAULT_ASK_SOURCES: usize = 8;
+
+/// Instructions sent as the system message
+const ASK_SYSTEM_PROMPT: &str = "You answer questions about the user's own browsing history. \
+Use only the numbered pages provided. Cite every page you rely on with its number in square \
+brackets, e.g. [2]. If the pages don't answer the question, say so briefly instead of guessing.";
+
+/// A page given to the model as context
+#[derive(Debug, Clone)]
+pub struct AnswerSource {
+    /// Number used to cite the page in the answer
+    pub number: usize,
+    /// Page URL id
+    pub url_id: String,
+    /// Page URL
+    pub url: String,
+    /// Page title, if known
+    pub title: Option<String>,
+    /// Enrichment summary, if any
+    pub summary: Option<String>,
+    /// Number of visits to the page
+    pub visit_count: usize,
+    /// Most recent visit
+    pub last_visit: Option<DateTime<Utc>>,
+    /// Similarity of the page to the question
+    pub score: f32,
+    /// Whether the answer cites the page
+    pub cited: bool,
+}
+
+/// An answer to a question about the history
+#[derive(Debug, Clone)]
+pub struct HistoryAnswer {
+    /// The model's answer, with [n] citations
+    pub answer: String,
+    /// Pages the answer was based on
+    pub sources: Vec<AnswerSource>,
+}
+
+/// Returns the source numbers cited as [n] (or [n, m]) in an answer
+fn cited_numbers(answer: &str) -> Vec<usize> {
+    let mut numbers = Vec::new();
+    let mut rest = answer;
+
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let end = match rest.find(']') {
+            Some(end) => end,
+            None => break,
+        };
+        numbers.extend(rest[..end].split(',').filter_map(|n| n.trim().parse::<usize>().ok()));
+        rest = &rest[end + 1..];
+    }
+
+    numbers
+}
+
+/// Builds the user message listing the retrieved pages and the question
+fn ask_prompt(question: &str, sources: &[AnswerSource]) -> String {
+    let mut prompt = String::from("Pages from my history:\n\n");
+
+    for source in sources {
+        prompt.push_str(&format!(
+            "[{}] {}\nURL: {}\n",
+            source.number,
+            source.title.as_deref().unwrap_or("(untitled)"),
+            source.url,
+        ));
+        if let Some(summary) = &source.summary {
+            prompt.push_str(&format!("Summary: {}\n", summary));
+        }
+        prompt.push_str(&format!("Visits: {}", source.visit_count));
+        if let Some(last_visit) = source.last_visit {
+            prompt.push_str(&format!(", last on {}", last_visit.format("%Y-%m-%d")));
+        }
+        prompt.push_str("\n\n");
+    }
+
+    prompt.push_str(&format!("Question: {}", question));
+    prompt
+}
+
+/// Answers a question from the `k` pages most similar to it, citing them by number
+pub fn ask_history(
+    conn: &DatabaseConnection,
+    chat: &dyn EnrichmentProvider,
+    embedder: &dyn EmbeddingProvider,
+    question: &str,
+    k: usize,
+) -> Result<HistoryAnswer> {
+    let matches = semantic_search(conn, embedder, question, k)?;
+    if matches.is_empty() {
+        return Err(EnrichmentError::Config("No pages have embeddings yet; embed your history first".to_string()));
+    }
+
+    let mut sources = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT m.summary,
+                    (SELECT COUNT(*) FROM visit v WHERE v.url_id = ?1),
+                    (SELECT MAX(v.visited_at) FROM visit v WHERE v.url_id = ?1)
+             FROM url u
+             LEFT JOIN metadata m ON m.url_id = u.id
+             WHERE u.id = ?1"
+        )?;
+
+        let mut sources = Vec::with_capacity(matches.len());
+        for (i, page) in matches.into_iter().enumerate() {
+            let details: Option<(Option<String>, i64, Option<i64>)> = stmt
+                .query_row([&page.url_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .optional()?;
+            let (summary, visit_count, last_visit) = details.unwrap_or((None, 0, None));
+
+            sources.push(AnswerSource {
+                number: i + 1,
+                url_id: page.url_id,
+                url: page.url,
+                title: page.title,
+                summary,
+                visit_count: visit_count as usize,
+                last_visit: last_visit.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+                score: page.score,
+                cited: false,
+            });
+        }
+        Ok(sources)
+    })?;
+
+    let run_id = new_run_id("ask");
+    let completion = match chat.complete(ASK_SYSTEM_PROMPT, &ask_prompt(question, &sources), false) {
+        Ok(completion) => completion,
+        Err(e) => {
+            conn.with_connection(|c| record_request(c, &run_id, chat, None, true))?;
+            return Err(e);
+        },
+    };
+    conn.with_connection(|c| record_request(c, &run_id, chat, completion.usage, false))?;
+
+    for number in cited_numbers(&completion.content) {
+        if let Some(source) = sources.iter_mut().find(|source| source.number == number) {
+            source.cited = true;
+        }
+    }
+
+    Ok(HistoryAnswer {
+        answer: completion.content.trim().to_string(),
+        sources,
+    })
+}
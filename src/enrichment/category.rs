@@ -0,0 +1,323 @@
+// Enrichment - URL Categorization
+// Assigns coarse categories to URLs from domain rules, falling back to a model
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use crate::db::DatabaseConnection;
+use super::error::Result;
+use super::provider::{EnrichmentProvider, PageInput};
+use super::save_enrichment;
+use super::usage::{new_run_id, record_request};
+
+/// Coarse category of a URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    /// News outlets and tech press
+    News,
+    /// Code hosting, Q&A and developer tools
+    Development,
+    /// Technical documentation
+    Docs,
+    /// Online stores
+    Shopping,
+    /// Social networks and forums
+    Social,
+    /// Video and music streaming
+    Streaming,
+    /// Search engine result pages
+    Search,
+    /// Webmail
+    Email,
+    /// Encyclopedias and papers
+    Reference,
+    /// Banking, payments and investing
+    Finance,
+    /// Adult content
+    Adult,
+    /// Classified, but none of the above
+    Other,
+}
+
+impl Category {
+    /// All categories, in display order
+    pub const ALL: [Category; 12] = [
+        Category::News,
+        Category::Development,
+        Category::Docs,
+        Category::Shopping,
+        Category::Social,
+        Category::Streaming,
+        Category::Search,
+        Category::Email,
+        Category::Reference,
+        Category::Finance,
+        Category::Adult,
+        Category::Other,
+    ];
+
+    /// Name stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::News => "news",
+            Category::Development => "development",
+            Category::Docs => "docs",
+            Category::Shopping => "shopping",
+            Category::Social => "social",
+            Category::Streaming => "streaming",
+            Category::Search => "search",
+            Category::Email => "email",
+            Category::Reference => "reference",
+            Category::Finance => "finance",
+            Category::Adult => "adult",
+            Category::Other => "other",
+        }
+    }
+
+    /// Parses a stored category name
+    pub fn parse(value: &str) -> Option<Self> {
+        Category::ALL.iter().copied().find(|c| c.as_str() == value)
+    }
+
+    /// Maps a free-form label (e.g. a model's answer) onto a category
+    pub fn from_label(label: &str) -> Option<Self> {
+        let label = label.trim().to_lowercase();
+
+        if let Some(category) = Category::parse(&label) {
+            return Some(category);
+        }
+
+        // Whole words only, so "storage" is not a store; the longest synonym
+        // wins, so "social media" is social rather than news
+        let words = label_words(&label);
+        LABEL_SYNONYMS.iter()
+            .flat_map(|(category, synonyms)| synonyms.iter().map(move |synonym| (*category, *synonym)))
+            .filter(|(_, synonym)| contains_phrase(&words, synonym))
+            .fold(None, |best: Option<(Category, &str)>, candidate| match best {
+                Some(best) if best.1.len() >= candidate.1.len() => Some(best),
+                _ => Some(candidate),
+            })
+            .map(|(category, _)| category)
+    }
+}
+
+/// Words of a lowercase label; hyphens stay, so "e-commerce" is one word
+fn label_words(label: &str) -> Vec<&str> {
+    label.split(|c: char| !c.is_alphanumeric() && c != '-')
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Whether the words contain the phrase's words in a row, the last one
+/// possibly plural ("online stores")
+fn contains_phrase(words: &[&str], phrase: &str) -> bool {
+    let phrase: Vec<&str> = phrase.split(' ').collect();
+    let last = phrase.len() - 1;
+    words.windows(phrase.len()).any(|window| {
+        window.iter().zip(&phrase).enumerate().all(|(i, (word, expected))| {
+            word == expected || (i == last && word.strip_suffix('s') == Some(expected))
+        })
+    })
+}
+
+/// Words in free-form labels that map onto a category
+const LABEL_SYNONYMS: &[(Category, &[&str])] = &[
+    (Category::Development, &["programming", "software", "coding", "developer", "engineering"]),
+    (Category::Docs, &["documentation", "manual", "api reference", "tutorial"]),
+    (Category::News, &["journalism", "politics", "current events", "media"]),
+    (Category::Shopping, &["e-commerce", "ecommerce", "retail", "store", "shop"]),
+    (Category::Social, &["social media", "forum", "community", "networking"]),
+    (Category::Streaming, &["video", "music", "entertainment", "podcast", "movies"]),
+    (Category::Finance, &["banking", "investing", "money", "crypto"]),
+    (Category::Reference, &["encyclopedia", "education", "dictionary", "science"]),
+    (Category::Email, &["mail", "messaging"]),
+    (Category::Adult, &["pornography", "porn", "nsfw", "explicit"]),
+];
+
+/// Domains (matched on the domain or any parent domain) per category
+const DOMAIN_RULES: &[(Category, &[&str])] = &[
+    (Category::Development, &[
+        "github.com", "gitlab.com", "bitbucket.org", "stackoverflow.com", "stackexchange.com",
+        "crates.io", "npmjs.com", "pypi.org", "hub.docker.com", "news.ycombinator.com",
+        "dev.to", "codepen.io", "replit.com", "vercel.com", "netlify.com",
+    ]),
+    (Category::Docs, &[
+        "docs.rs", "readthedocs.io", "developer.mozilla.org", "docs.python.org",
+        "doc.rust-lang.org", "learn.microsoft.com", "developer.apple.com", "devdocs.io",
+    ]),
+    (Category::News, &[
+        "nytimes.com", "theguardian.com", "bbc.co.uk", "bbc.com", "cnn.com", "reuters.com",
+        "apnews.com", "washingtonpost.com", "bloomberg.com", "theverge.com", "arstechnica.com",
+        "techcrunch.com", "wired.com", "economist.com", "ft.com", "news.google.com",
+    ]),
+    (Category::Shopping, &[
+        "amazon.com", "amazon.co.uk", "amazon.de", "ebay.com", "etsy.com", "aliexpress.com",
+        "walmart.com", "target.com", "bestbuy.com", "ikea.com", "mercadolivre.com.br",
+    ]),
+    (Category::Social, &[
+        "facebook.com", "instagram.com", "twitter.com", "x.com", "linkedin.com", "reddit.com",
+        "mastodon.social", "threads.net", "tiktok.com", "pinterest.com", "bsky.app",
+    ]),
+    (Category::Streaming, &[
+        "youtube.com", "youtu.be", "netflix.com", "twitch.tv", "spotify.com", "vimeo.com",
+        "disneyplus.com", "primevideo.com", "hulu.com", "soundcloud.com", "music.apple.com",
+    ]),
+    (Category::Search, &[
+        "google.com", "bing.com", "duckduckgo.com", "search.yahoo.com", "ecosia.org",
+        "kagi.com", "search.brave.com",
+    ]),
+    (Category::Email, &[
+        "mail.google.com", "outlook.live.com", "outlook.office.com", "mail.yahoo.com",
+        "proton.me", "fastmail.com",
+    ]),
+    (Category::Reference, &[
+        "wikipedia.org", "wiktionary.org", "britannica.com", "arxiv.org", "scholar.google.com",
+    ]),
+    (Category::Finance, &[
+        "paypal.com", "coinbase.com", "robinhood.com", "finance.yahoo.com", "wise.com",
+    ]),
+    (Category::Adult, &[
+        "pornhub.com", "xvideos.com", "xhamster.com", "xnxx.com", "onlyfans.com", "chaturbate.com",
+    ]),
+];
+
+/// Returns true if `domain` is `rule` or a subdomain of it
+pub(crate) fn domain_matches(domain: &str, rule: &str) -> bool {
+    domain == rule || domain.ends_with(&format!(".{}", rule))
+}
+
+/// Classifies a URL from its domain and path alone
+pub fn classify_url(url: &str, domain: &str) -> Option<Category> {
+    let domain = domain.trim_start_matches("www.").to_lowercase();
+
+    // The most specific rule wins, so mail.google.com isn't filed under search
+    let by_domain = DOMAIN_RULES.iter()
+        .flat_map(|(category, domains)| domains.iter().map(move |rule| (*category, *rule)))
+        .filter(|(_, rule)| domain_matches(&domain, rule))
+        .max_by_key(|(_, rule)| rule.len())
+        .map(|(category, _)| category);
+
+    if by_domain.is_some() {
+        return by_domain;
+    }
+
+    // Documentation sites on otherwise unknown domains
+    let lower = url.to_lowercase();
+    if domain.starts_with("docs.") || domain.starts_with("developer.") || lower.contains("/docs/") {
+        return Some(Category::Docs);
+    }
+
+    if domain.starts_with("news.") {
+        return Some(Category::News);
+    }
+
+    if domain.starts_with("shop.") || domain.starts_with("store.") {
+        return Some(Category::Shopping);
+    }
+
+    None
+}
+
+/// Outcome of a categorization run
+#[derive(Debug, Clone, Default)]
+pub struct CategorizeRun {
+    /// URLs categorized by the domain rules
+    pub by_rules: usize,
+    /// URLs categorized from an AI-assigned category
+    pub by_model: usize,
+    /// URLs that are still uncategorized
+    pub uncategorized: usize,
+    /// Provider errors, one per failed URL
+    pub errors: Vec<String>,
+}
+
+/// Categorizes URLs without a category (or all URLs when `recategorize` is set).
+///
+/// Domain rules are tried first. Otherwise the AI category of already enriched
+/// pages is reused, and when a provider is given, up to `model_limit` remaining
+/// pages are enriched to obtain one.
+pub fn categorize_urls(
+    conn: &DatabaseConnection,
+    provider: Option<&dyn EnrichmentProvider>,
+    model_limit: usize,
+    recategorize: bool,
+) -> Result<CategorizeRun> {
+    let sql = if recategorize {
+        "SELECT u.id, u.url, u.title, u.domain, m.topic_cluster
+         FROM url u LEFT JOIN metadata m ON m.url_id = u.id"
+    } else {
+        "SELECT u.id, u.url, u.title, u.domain, m.topic_cluster
+         FROM url u LEFT JOIN metadata m ON m.url_id = u.id
+         WHERE u.category IS NULL"
+    };
+
+    let pages: Vec<(PageInput, Option<String>)> = conn.with_connection(|c| {
+        let mut stmt = c.prepare(sql)?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                PageInput {
+                    url_id: row.get(0)?,
+                    url: row.get(1)?,
+                    title: row.get(2)?,
+                    domain: row.get(3)?,
+                },
+                row.get(4)?,
+            ))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    let mut run = CategorizeRun::default();
+    let mut assigned: Vec<(String, Category)> = Vec::new();
+    let mut unmatched: Vec<PageInput> = Vec::new();
+
+    for (page, ai_label) in pages {
+        if let Some(category) = classify_url(&page.url, &page.domain) {
+            assigned.push((page.url_id, category));
+            run.by_rules += 1;
+        } else if let Some(category) = ai_label.as_deref().and_then(Category::from_label) {
+            assigned.push((page.url_id, category));
+            run.by_model += 1;
+        } else {
+            unmatched.push(page);
+        }
+    }
+
+    run.uncategorized = unmatched.len();
+
+    if let Some(provider) = provider {
+        let run_id = new_run_id("categorize");
+
+        for page in unmatched.iter().take(model_limit) {
+            match provider.enrich(page) {
+                Ok(enrichment) => {
+                    conn.with_connection(|c| {
+                        save_enrichment(c, &page.url_id, &enrichment)?;
+                        record_request(c, &run_id, provider, enrichment.usage, false)
+                    })?;
+                    let category = enrichment.category.as_deref()
+                        .and_then(Category::from_label)
+                        .unwrap_or(Category::Other);
+                    assigned.push((page.url_id.clone(), category));
+                    run.by_model += 1;
+                    run.uncategorized -= 1;
+                },
+                Err(e) => {
+                    conn.with_connection(|c| record_request(c, &run_id, provider, None, true))?;
+                    run.errors.push(format!("{} ({}): {}", page.url, provider.name(), e));
+                },
+            }
+        }
+    }
+
+    conn.transaction(|tx| {
+        let mut stmt = tx.prepare("UPDATE url SET category = ? WHERE id = ?")?;
+        for (url_id, category) in &assigned {
+            stmt.execute(params![category.as_str(), url_id])?;
+        }
+        Ok(())
+    })?;
+
+    Ok(run)
+}
@@ -0,0 +1,168 @@
+// Enrichment - Embeddings
+// Stores one embedding vector per URL and retrieves pages by cosine similarity
+
+use chrono::Utc;
+use rusqlite::params;
+
+use crate::db::DatabaseConnection;
+use super::error::{EnrichmentError, Result};
+use super::provider::EmbeddingProvider;
+
+/// Number of texts sent to the provider per request
+const EMBEDDING_BATCH_SIZE: usize = 32;
+
+/// Default number of pages embedded by a single run
+pub const DEFAULT_EMBED_LIMIT: usize = 1000;
+
+/// A page found by semantic search
+#[derive(Debug, Clone)]
+pub struct SemanticMatch {
+    /// Matching URL id
+    pub url_id: String,
+    /// Matching URL
+    pub url: String,
+    /// Page title, if known
+    pub title: Option<String>,
+    /// Cosine similarity to the query, between -1 and 1
+    pub score: f32,
+}
+
+/// Scales a vector to unit length so cosine similarity becomes a dot product
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+/// Encodes a vector as little-endian f32 bytes
+fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Decodes little-endian f32 bytes into a vector
+pub(crate) fn decode_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes.chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+/// Builds the text embedded for a page from its title, URL and any enrichment
+fn page_text(url: &str, title: Option<String>, summary: Option<String>, keywords: Option<String>) -> String {
+    let mut parts = Vec::new();
+
+    if let Some(title) = title.filter(|t| !t.is_empty()) {
+        parts.push(title);
+    }
+    parts.push(url.to_string());
+    if let Some(summary) = summary.filter(|s| !s.is_empty()) {
+        parts.push(summary);
+    }
+    if let Some(keywords) = keywords.filter(|k| !k.is_empty()) {
+        parts.push(crate::graph::builder::parse_keywords(&keywords).join(", "));
+    }
+
+    parts.join("\n")
+}
+
+/// Embeds up to `limit` pages that have no vector for the provider's model yet,
+/// returning the number of stored vectors
+pub fn embed_missing(conn: &DatabaseConnection, provider: &dyn EmbeddingProvider, limit: usize) -> Result<usize> {
+    let model = provider.model().to_string();
+
+    let pages: Vec<(String, String)> = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT u.id, u.url, u.title, m.summary, m.keywords
+             FROM url u
+             LEFT JOIN metadata m ON m.url_id = u.id
+             LEFT JOIN embedding e ON e.url_id = u.id AND e.model = ?
+             WHERE e.url_id IS NULL
+             LIMIT ?"
+        )?;
+        let rows = stmt.query_map(params![model, limit as i64], |row| {
+            let url: String = row.get(1)?;
+            Ok((row.get(0)?, page_text(&url, row.get(2)?, row.get(3)?, row.get(4)?)))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    let mut stored = 0;
+
+    for batch in pages.chunks(EMBEDDING_BATCH_SIZE) {
+        let texts: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+        let vectors = provider.embed(&texts)?;
+        // Vectors are matched to pages by position, so a short answer can't be trusted
+        if vectors.len() != batch.len() {
+            return Err(EnrichmentError::InvalidResponse(format!(
+                "Asked for {} embeddings, got {}", batch.len(), vectors.len(),
+            )));
+        }
+
+        stored += conn.transaction(|tx| {
+            let now = Utc::now().timestamp();
+            let mut written = 0;
+            for ((url_id, _), vector) in batch.iter().zip(vectors) {
+                let vector = normalize(vector);
+                written += tx.execute(
+                    "INSERT INTO embedding (url_id, model, dimensions, vector, created_at) VALUES (?, ?, ?, ?, ?)
+                     ON CONFLICT (url_id) DO UPDATE SET model = excluded.model, dimensions = excluded.dimensions,
+                         vector = excluded.vector, created_at = excluded.created_at",
+                    params![url_id, model, vector.len() as i64, encode_vector(&vector), now],
+                )?;
+            }
+            Ok(written)
+        })?;
+    }
+
+    Ok(stored)
+}
+
+/// Finds the `k` pages whose embeddings are most similar to the text
+pub fn semantic_search(
+    conn: &DatabaseConnection,
+    provider: &dyn EmbeddingProvider,
+    text: &str,
+    k: usize,
+) -> Result<Vec<SemanticMatch>> {
+    let query = provider.embed(&[text.to_string()])?
+        .into_iter()
+        .next()
+        .map(normalize)
+        .ok_or_else(|| EnrichmentError::InvalidResponse("No embedding returned for query".to_string()))?;
+
+    let model = provider.model().to_string();
+
+    let mut matches = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT e.url_id, u.url, u.title, e.vector
+             FROM embedding e
+             JOIN url u ON e.url_id = u.id
+             WHERE e.model = ? AND e.dimensions = ?"
+        )?;
+        let mut rows = stmt.query(params![model, query.len() as i64])?;
+
+        let mut matches = Vec::new();
+        while let Some(row) = rows.next()? {
+            let bytes: Vec<u8> = row.get(3)?;
+            let score = decode_vector(&bytes).iter()
+                .zip(&query)
+                .map(|(a, b)| a * b)
+                .sum::<f32>();
+
+            matches.push(SemanticMatch {
+                url_id: row.get(0)?,
+                url: row.get(1)?,
+                title: row.get(2)?,
+                score,
+            });
+        }
+
+        Ok(matches)
+    })?;
+
+    matches.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    matches.truncate(k);
+
+    Ok(matches)
+}
@@ -0,0 +1,67 @@
+// Enrichment Error Handling
+// Defines error types for AI enrichment
+
+use std::fmt;
+use std::error::Error;
+
+use crate::db::DatabaseError;
+
+/// Represents errors that can occur while enriching pages
+#[derive(Debug)]
+pub enum EnrichmentError {
+    /// Reading or saving metadata failed
+    Database(DatabaseError),
+    /// The provider could not be reached or returned an error status
+    Http(String),
+    /// The provider answered with something we could not parse
+    InvalidResponse(String),
+    /// The provider is missing or misconfigured
+    Config(String),
+}
+
+impl fmt::Display for EnrichmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnrichmentError::Database(err) => write!(f, "Database error: {}", err),
+            EnrichmentError::Http(msg) => write!(f, "Provider request failed: {}", msg),
+            EnrichmentError::InvalidResponse(msg) => write!(f, "Invalid provider response: {}", msg),
+            EnrichmentError::Config(msg) => write!(f, "Enrichment configuration error: {}", msg),
+        }
+    }
+}
+
+impl Error for EnrichmentError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EnrichmentError::Database(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DatabaseError> for EnrichmentError {
+    fn from(err: DatabaseError) -> Self {
+        EnrichmentError::Database(err)
+    }
+}
+
+impl From<rusqlite::Error> for EnrichmentError {
+    fn from(err: rusqlite::Error) -> Self {
+        EnrichmentError::Database(DatabaseError::from(err))
+    }
+}
+
+impl From<ureq::Error> for EnrichmentError {
+    fn from(err: ureq::Error) -> Self {
+        match err {
+            ureq::Error::Status(code, response) => {
+                let body = response.into_string().unwrap_or_default();
+                EnrichmentError::Http(format!("HTTP {}: {}", code, body))
+            },
+            ureq::Error::Transport(transport) => EnrichmentError::Http(transport.to_string()),
+        }
+    }
+}
+
+/// Result type for enrichment operations
+pub type Result<T> = std::result::Result<T, EnrichmentError>;
@@ -0,0 +1,235 @@
+// Enrichment - Flashcards
+// Generates question and answer pairs from the text of technical articles,
+// turning what was read into spaced-repetition study material
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{DatabaseConnection, DatabaseError};
+use crate::web::access::detect_access_wall;
+use crate::web::fetch::{agent, fetch, MAX_PAGE_BYTES};
+use crate::web::html::visible_text;
+use crate::web::reading::{main_content, reading_stats};
+use super::error::{EnrichmentError, Result};
+use super::provider::EnrichmentProvider;
+use super::usage::{new_run_id, record_request};
+
+/// Default number of pages sent to the provider by one run
+pub const DEFAULT_FLASHCARD_PAGES: usize = 10;
+
+/// Most cards kept per page
+pub const MAX_CARDS_PER_PAGE: usize = 5;
+
+/// Categories of the pages cards are generated for
+const TECHNICAL_CATEGORIES: &[&str] = &["development", "docs", "reference"];
+
+/// Fewest words of article text worth generating cards from
+const MIN_ARTICLE_WORDS: u64 = 300;
+
+/// Most characters of article text sent to the provider
+const MAX_PROMPT_CHARS: usize = 12_000;
+
+/// Instructions sent as the system message
+const FLASHCARD_SYSTEM_PROMPT: &str = "You write flashcards for spaced-repetition study from a technical \
+article the user read. Reply with a single JSON object and nothing else, with the key \"cards\": a list of \
+up to 5 objects with the keys \"question\" and \"answer\". Ask about concepts, definitions and how things \
+work, not about the article itself. Questions must stand on their own; answers are one or two sentences.";
+
+/// A question and answer pair generated from a page
+#[derive(Debug, Clone, Serialize)]
+pub struct Flashcard {
+    /// Card identifier
+    pub id: i64,
+    /// URL id of the page the card came from
+    pub url_id: String,
+    /// Page URL
+    pub url: String,
+    /// Page title, if known
+    pub title: Option<String>,
+    /// Category of the page
+    pub category: Option<String>,
+    /// Front of the card
+    pub question: String,
+    /// Back of the card
+    pub answer: String,
+    /// When the card was generated
+    pub created_at: DateTime<Utc>,
+}
+
+/// Outcome of a flashcard run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FlashcardRun {
+    /// Pages cards were generated for
+    pub pages: usize,
+    /// Cards generated
+    pub cards: usize,
+    /// Pages with too little readable text, or behind a wall
+    pub skipped: usize,
+    /// One message per page that could not be fetched or answered
+    pub errors: Vec<String>,
+}
+
+/// A card as the model writes it
+#[derive(Debug, Deserialize)]
+struct CardReply {
+    #[serde(default)]
+    question: String,
+    #[serde(default)]
+    answer: String,
+}
+
+/// The model's reply
+#[derive(Debug, Deserialize)]
+struct CardsReply {
+    #[serde(default)]
+    cards: Vec<CardReply>,
+}
+
+/// Parses the JSON object a model replied with into (question, answer)
+/// pairs, tolerating surrounding text such as Markdown code fences
+pub fn parse_flashcards(content: &str) -> Result<Vec<(String, String)>> {
+    let json = match (content.find('{'), content.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &content[start..=end],
+        _ => return Err(EnrichmentError::InvalidResponse(format!("No JSON object in: {}", content))),
+    };
+    let reply: CardsReply = serde_json::from_str(json)
+        .map_err(|e| EnrichmentError::InvalidResponse(format!("{}: {}", e, json)))?;
+
+    Ok(reply.cards.into_iter()
+        .map(|card| (card.question.trim().to_string(), card.answer.trim().to_string()))
+        .filter(|(question, answer)| !question.is_empty() && !answer.is_empty())
+        .take(MAX_CARDS_PER_PAGE)
+        .collect())
+}
+
+/// Builds the user message with the article's title and text
+fn flashcard_prompt(title: Option<&str>, url: &str, text: &str) -> String {
+    let text = match text.char_indices().nth(MAX_PROMPT_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    };
+    format!("Title: {}\nURL: {}\n\n{}", title.unwrap_or("(untitled)"), url, text)
+}
+
+/// Fetches the readable text of an article; None for pages that aren't HTML,
+/// are too short or show a wall
+fn article_text(agent: &ureq::Agent, url: &str) -> crate::web::Result<Option<String>> {
+    let page = fetch(agent, url, MAX_PAGE_BYTES)?;
+    if !page.is_html() {
+        return Ok(None);
+    }
+    let html = page.text();
+    let stats = reading_stats(&html);
+    if stats.word_count < MIN_ARTICLE_WORDS || detect_access_wall(&page.final_url, &html, &stats).is_some() {
+        return Ok(None);
+    }
+    Ok(Some(visible_text(main_content(&html))))
+}
+
+/// Marks a page as sent to the provider, replacing its cards
+fn save_flashcards(c: &Connection, url_id: &str, cards: &[(String, String)]) -> rusqlite::Result<()> {
+    let now = Utc::now().timestamp();
+    c.execute("DELETE FROM flashcard WHERE url_id = ?", [url_id])?;
+    let mut stmt = c.prepare_cached("INSERT INTO flashcard (url_id, question, answer, created_at) VALUES (?, ?, ?, ?)")?;
+    for (question, answer) in cards {
+        stmt.execute(params![url_id, question, answer, now])?;
+    }
+    c.execute("UPDATE metadata SET flashcards_at = ? WHERE url_id = ?", params![now, url_id])?;
+    Ok(())
+}
+
+/// Generates cards for up to `limit` enriched technical articles without any,
+/// most visited first
+pub fn generate_flashcards(conn: &DatabaseConnection, provider: &dyn EnrichmentProvider, limit: usize) -> Result<FlashcardRun> {
+    let pages: Vec<(String, String, Option<String>)> = conn.with_connection(|c| {
+        let mut stmt = c.prepare(&format!(
+            "SELECT u.id, u.url, u.title
+             FROM metadata m
+             JOIN url u ON u.id = m.url_id
+             WHERE m.is_enriched = 1 AND m.flashcards_at IS NULL AND m.access_wall IS NULL
+               AND u.category IN ({})
+             ORDER BY (SELECT COUNT(*) FROM visit v WHERE v.url_id = u.id) DESC, u.id
+             LIMIT ?",
+            TECHNICAL_CATEGORIES.iter().map(|category| format!("'{}'", category)).collect::<Vec<_>>().join(", "),
+        ))?;
+        let rows = stmt.query_map([limit as i64], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    let agent = agent();
+    let run_id = new_run_id("flashcards");
+    let mut run = FlashcardRun::default();
+
+    for (url_id, url, title) in pages {
+        let text = match article_text(&agent, &url) {
+            Ok(Some(text)) => text,
+            Ok(None) => {
+                // Stamped so the page isn't fetched again
+                conn.with_connection(|c| Ok(save_flashcards(c, &url_id, &[])?))?;
+                run.skipped += 1;
+                continue;
+            },
+            Err(e) => {
+                run.errors.push(format!("{}: {}", url, e));
+                continue;
+            },
+        };
+
+        let prompt = flashcard_prompt(title.as_deref(), &url, &text);
+        let cards = provider.complete(FLASHCARD_SYSTEM_PROMPT, &prompt, true)
+            .and_then(|completion| Ok((parse_flashcards(&completion.content)?, completion.usage)));
+        match cards {
+            Ok((cards, usage)) => {
+                conn.with_connection(|c| {
+                    save_flashcards(c, &url_id, &cards)?;
+                    record_request(c, &run_id, provider, usage, false)
+                })?;
+                run.pages += 1;
+                run.cards += cards.len();
+            },
+            Err(e) => {
+                conn.with_connection(|c| record_request(c, &run_id, provider, None, true))?;
+                run.errors.push(format!("{} ({}): {}", url, provider.name(), e));
+            },
+        }
+    }
+
+    Ok(run)
+}
+
+/// Gets the generated cards, optionally of one page, newest pages first
+pub fn get_flashcards(conn: &DatabaseConnection, url_id: Option<&str>) -> crate::db::Result<Vec<Flashcard>> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT f.id, f.url_id, u.url, u.title, u.category, f.question, f.answer, f.created_at
+             FROM flashcard f
+             JOIN url u ON u.id = f.url_id
+             WHERE ?1 IS NULL OR f.url_id = ?1
+             ORDER BY f.created_at DESC, f.url_id, f.id"
+        )?;
+        let rows = stmt.query_map([url_id], |row| {
+            Ok(Flashcard {
+                id: row.get(0)?,
+                url_id: row.get(1)?,
+                url: row.get(2)?,
+                title: row.get(3)?,
+                category: row.get(4)?,
+                question: row.get(5)?,
+                answer: row.get(6)?,
+                created_at: DateTime::from_timestamp(row.get(7)?, 0).unwrap_or_default(),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
+
+/// Deletes a card the user doesn't want to study
+pub fn delete_flashcard(conn: &DatabaseConnection, id: i64) -> crate::db::Result<()> {
+    conn.with_connection(|c| {
+        if c.execute("DELETE FROM flashcard WHERE id = ?", [id])? == 0 {
+            return Err(DatabaseError::NotFound(format!("Flashcard {} does not exist", id)));
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,242 @@
+// Enrichment Module
+// Fills page metadata (summary, keywords, category) using pluggable AI providers
+
+// Module organization:
+// - provider.rs: Provider trait, prompt and response parsing
+// - openai.rs: OpenAI-compatible chat completions provider
+// - ollama.rs: Local Ollama provider
+// - embeddings.rs: Embedding storage and semantic search
+// - category.rs: Rule-based and model-assisted URL categorization
+// - ask.rs: Question answering over retrieved pages
+// - nlsql.rs: Natural language to read-only SQL
+// - flashcards.rs: Study questions and answers from technical articles
+// - queue.rs: Persistent job queue with retries and rate limiting
+// - usage.rs: Token, request and cost tracking
+// - error.rs: Error handling
+
+pub mod provider;
+pub mod openai;
+pub mod ollama;
+pub mod embeddings;
+pub mod category;
+pub mod ask;
+pub mod nlsql;
+pub mod flashcards;
+pub mod queue;
+pub mod usage;
+pub mod error;
+
+pub use error::{EnrichmentError, Result};
+pub use provider::{Completion, EmbeddingProvider, Enrichment, EnrichmentProvider, PageInput};
+pub use openai::{OpenAiConfig, OpenAiProvider};
+pub use ollama::{OllamaConfig, OllamaProvider};
+pub use embeddings::{embed_missing, semantic_search, SemanticMatch};
+pub use ask::{ask_history, AnswerSource, HistoryAnswer, DEFAULT_ASK_SOURCES};
+pub use nlsql::{query_history, NlQueryResult};
+pub use category::{categorize_urls, classify_url, Category, CategorizeRun};
+pub use queue::{QueueControl, QueueSettings, QueueStatus};
+pub use usage::{get_enrichment_usage, UsageReport};
+
+use rusqlite::{params, Connection, ToSql};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{self, DatabaseConnection, DatabaseError};
+use crate::db::settings::{get_setting, set_setting};
+use crate::plugins::PluginProvider;
+
+/// Settings key holding the enrichment configuration
+pub const ENRICHMENT_SETTING: &str = "enrichment";
+
+/// Maximum number of pages processed by a single "enrich all" run
+pub const DEFAULT_ENRICH_BATCH: usize = 100;
+
+/// Available enrichment backends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    /// OpenAI or any API following its chat completions format
+    #[default]
+    OpenAi,
+    /// Local Ollama server, nothing leaves the machine
+    Ollama,
+    /// An installed enrichment plugin
+    Plugin,
+}
+
+/// Which provider to use and how to reach it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnrichmentSettings {
+    /// Selected provider
+    pub provider: ProviderKind,
+    /// Provider used for embeddings (may differ, e.g. to keep search local)
+    pub embedding_provider: ProviderKind,
+    /// Settings for the OpenAI-compatible provider
+    pub openai: OpenAiConfig,
+    /// Settings for the Ollama provider
+    pub ollama: OllamaConfig,
+    /// Plugin used when the provider is `plugin`
+    pub plugin: PluginConfig,
+    /// Background queue tuning
+    pub queue: QueueSettings,
+}
+
+/// Outcome of an enrichment run
+#[derive(Debug, Clone, Default)]
+pub struct EnrichmentRun {
+    /// Number of pages enriched and saved
+    pub enriched: usize,
+    /// Number of pages the provider failed on
+    pub failed: usize,
+    /// Error message for every failed page
+    pub errors: Vec<String>,
+}
+
+/// Loads the enrichment settings, falling back to defaults
+pub fn get_enrichment_settings(conn: &DatabaseConnection) -> Result<EnrichmentSettings> {
+    let settings = conn.with_connection(|c| get_setting(c, ENRICHMENT_SETTING))?;
+    Ok(settings.unwrap_or_default())
+}
+
+/// Stores the enrichment settings, saving a new OpenAI API key in the keychain
+pub fn set_enrichment_settings(conn: &DatabaseConnection, settings: &EnrichmentSettings) -> Result<()> {
+    let mut settings = settings.clone();
+    openai::save_api_key(&mut settings.openai)?;
+    Ok(conn.with_connection(|c| set_setting(c, ENRICHMENT_SETTING, &settings))?)
+}
+
+/// Which enrichment plugin to use and the options passed to it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginConfig {
+    /// Id of the installed plugin
+    pub id: Option<String>,
+    /// Options the plugin reads, such as its API key and model
+    pub options: serde_json::Value,
+}
+
+/// Creates the provider selected in the settings
+pub fn create_provider(settings: &EnrichmentSettings) -> Result<Box<dyn EnrichmentProvider>> {
+    match settings.provider {
+        ProviderKind::OpenAi => Ok(Box::new(OpenAiProvider::new(settings.openai.clone())?)),
+        ProviderKind::Ollama => Ok(Box::new(OllamaProvider::new(settings.ollama.clone())?)),
+        ProviderKind::Plugin => {
+            let id = settings.plugin.id.as_deref()
+                .ok_or_else(|| EnrichmentError::Config("No enrichment plugin selected".to_string()))?;
+            let provider = PluginProvider::load(id, settings.plugin.options.clone())
+                .map_err(|e| EnrichmentError::Config(e.to_string()))?;
+            Ok(Box::new(provider))
+        },
+    }
+}
+
+/// Creates the embedding provider selected in the settings
+pub fn create_embedding_provider(settings: &EnrichmentSettings) -> Result<Box<dyn EmbeddingProvider>> {
+    match settings.embedding_provider {
+        ProviderKind::OpenAi => Ok(Box::new(OpenAiProvider::new(settings.openai.clone())?)),
+        ProviderKind::Ollama => Ok(Box::new(OllamaProvider::new(settings.ollama.clone())?)),
+        ProviderKind::Plugin => Err(EnrichmentError::Config("Plugins don't provide embeddings".to_string())),
+    }
+}
+
+/// Maps a url row (id, url, title, domain) to a provider input
+fn page_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<PageInput> {
+    Ok(PageInput {
+        url_id: row.get(0)?,
+        url: row.get(1)?,
+        title: row.get(2)?,
+        domain: row.get(3)?,
+    })
+}
+
+/// Loads the pages with the given URL ids
+pub fn get_pages(conn: &DatabaseConnection, url_ids: &[String]) -> Result<Vec<PageInput>> {
+    if url_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let placeholders = vec!["?"; url_ids.len()].join(", ");
+    let sql = format!("SELECT id, url, title, domain FROM url WHERE id IN ({})", placeholders);
+    let params: Vec<&dyn ToSql> = url_ids.iter().map(|id| id as &dyn ToSql).collect();
+
+    Ok(conn.with_connection(|c| {
+        let mut stmt = c.prepare(&sql)?;
+        let pages = stmt.query_map(params.as_slice(), page_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(pages)
+    })?)
+}
+
+/// Loads up to `limit` pages that have not been enriched yet, most visited
+/// first; pages found behind a paywall or login wall are left out
+pub fn get_unenriched_pages(conn: &DatabaseConnection, limit: usize) -> Result<Vec<PageInput>> {
+    Ok(conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT u.id, u.url, u.title, u.domain
+             FROM url u
+             LEFT JOIN metadata m ON m.url_id = u.id
+             WHERE m.url_id IS NULL OR (m.is_enriched = 0 AND m.access_wall IS NULL)
+             ORDER BY (SELECT COUNT(*) FROM visit v WHERE v.url_id = u.id) DESC
+             LIMIT ?"
+        )?;
+        let pages = stmt.query_map([limit as i64], page_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(pages)
+    })?)
+}
+
+/// Saves generated metadata for a page and marks it as enriched.
+/// The category is stored as the page's topic cluster.
+pub fn save_enrichment(conn: &Connection, url_id: &str, enrichment: &Enrichment) -> db::Result<()> {
+    let keywords = serde_json::to_string(&enrichment.keywords)
+        .map_err(|e| DatabaseError::Data(e.to_string()))?;
+
+    let updated = conn.execute(
+        "UPDATE metadata SET summary = ?, keywords = ?, topic_cluster = COALESCE(?, topic_cluster), is_enriched = 1
+         WHERE url_id = ?",
+        params![enrichment.summary, keywords, enrichment.category, url_id],
+    )?;
+
+    if updated == 0 {
+        conn.execute(
+            "INSERT INTO metadata (url_id, summary, keywords, topic_cluster, is_enriched)
+             VALUES (?, ?, ?, ?, 1)",
+            params![url_id, enrichment.summary, keywords, enrichment.category],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Runs the provider over every page, saving results as they arrive.
+/// A failing page is recorded and skipped so one bad answer doesn't stop the run.
+pub fn enrich_pages(
+    conn: &DatabaseConnection,
+    provider: &dyn EnrichmentProvider,
+    pages: &[PageInput],
+) -> Result<EnrichmentRun> {
+    let mut run = EnrichmentRun::default();
+    let run_id = usage::new_run_id("batch");
+
+    for page in pages {
+        match provider.enrich(page) {
+            Ok(enrichment) => {
+                conn.with_connection(|c| {
+                    save_enrichment(c, &page.url_id, &enrichment)?;
+                    usage::record_request(c, &run_id, provider, enrichment.usage, false)
+                })?;
+                run.enriched += 1;
+            },
+            Err(e) => {
+                conn.with_connection(|c| usage::record_request(c, &run_id, provider, None, true))?;
+                run.failed += 1;
+                run.errors.push(format!("{} ({}): {}", page.url, provider.name(), e));
+            },
+        }
+    }
+
+    Ok(run)
+}
+
+#[cfg(test)]
+mod tests;
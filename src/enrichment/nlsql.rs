@@ -0,0 +1,90 @@
+// Enrichment - Natural Language Queries
+// Turns questions into read-only SQL over the query views and runs it
+
+use serde::Deserialize;
+
+use crate::db::DatabaseConnection;
+use crate::db::readonly::{run_select, QueryTable};
+use super::error::{EnrichmentError, Result};
+use super::provider::EnrichmentProvider;
+use super::usage::{new_run_id, record_request};
+
+/// Instructions and schema sent as the system message
+const NL_SQL_SYSTEM_PROMPT: &str = "You translate questions about a personal browsing history into \
+a single read-only SQLite SELECT statement. Reply with a JSON object {\"sql\": \"...\"} and nothing else.
+
+Only these views exist:
+
+history_pages(page_id TEXT, page_url TEXT, title TEXT, domain TEXT, category TEXT,
+    first_seen TEXT, last_seen TEXT, summary TEXT, keywords TEXT, topic TEXT,
+    word_count INTEGER, reading_time_sec INTEGER)
+    -- one row per page; category is one of news, development, docs, shopping, social,
+    -- streaming, search, email, reference, finance, adult, other
+
+history_visits(visit_id TEXT, page_id TEXT, page_url TEXT, title TEXT, domain TEXT, category TEXT,
+    visited_ts INTEGER, visited_at TEXT, duration_sec REAL, device_name TEXT)
+    -- one row per visit; visited_ts is unix seconds, visited_at is 'YYYY-MM-DD HH:MM:SS' UTC;
+    -- duration_sec is the time spent on the page and may be NULL
+
+Rules: use only these views, never modify data, match domains with LIKE (e.g. domain LIKE '%youtube.com'), \
+give columns readable aliases, convert seconds to hours or minutes when the question asks for them, \
+and assume the current year when a month is named without one.";
+
+/// A question answered with generated SQL
+#[derive(Debug, Clone)]
+pub struct NlQueryResult {
+    /// The generated statement
+    pub sql: String,
+    /// Rows returned by the statement
+    pub table: QueryTable,
+}
+
+#[derive(Deserialize)]
+struct GeneratedSql {
+    sql: String,
+}
+
+/// Extracts the statement from the model's JSON reply
+fn parse_generated_sql(content: &str) -> Result<String> {
+    let start = content.find('{');
+    let end = content.rfind('}');
+
+    let json = match (start, end) {
+        (Some(start), Some(end)) if start < end => &content[start..=end],
+        _ => return Err(EnrichmentError::InvalidResponse(format!("No JSON object in: {}", content))),
+    };
+
+    let generated: GeneratedSql = serde_json::from_str(json)
+        .map_err(|e| EnrichmentError::InvalidResponse(format!("{}: {}", e, json)))?;
+
+    Ok(generated.sql.trim().to_string())
+}
+
+/// Asks the model for SQL answering `question`, validates it against the
+/// query views and returns at most `max_rows` rows
+pub fn query_history(
+    conn: &DatabaseConnection,
+    provider: &dyn EnrichmentProvider,
+    question: &str,
+    max_rows: usize,
+) -> Result<NlQueryResult> {
+    let run_id = new_run_id("nl_sql");
+    let prompt = format!("Today is {}.\nQuestion: {}", chrono::Utc::now().format("%Y-%m-%d"), question);
+
+    let completion = match provider.complete(NL_SQL_SYSTEM_PROMPT, &prompt, true) {
+        Ok(completion) => completion,
+        Err(e) => {
+            conn.with_connection(|c| record_request(c, &run_id, provider, None, true))?;
+            return Err(e);
+        },
+    };
+    conn.with_connection(|c| record_request(c, &run_id, provider, completion.usage, false))?;
+
+    let sql = parse_generated_sql(&completion.content)?;
+
+    // Generated SQL goes through the same validation as anything typed by a user
+    let table = run_select(conn, &sql, max_rows)
+        .map_err(|e| EnrichmentError::InvalidResponse(format!("Generated query was rejected ({}): {}", e, sql)))?;
+
+    Ok(NlQueryResult { sql, table })
+}
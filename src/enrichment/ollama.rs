@@ -0,0 +1,158 @@
+// Enrichment - Ollama Provider
+// Generates metadata with a locally running Ollama model, fully offline
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::error::{EnrichmentError, Result};
+use super::provider::{Completion, EmbeddingProvider, EnrichmentProvider, TokenUsage};
+
+/// Default Ollama server address
+pub const DEFAULT_OLLAMA_HOST: &str = "http://localhost:11434";
+
+/// Default local model
+pub const DEFAULT_OLLAMA_MODEL: &str = "llama3.1";
+
+/// Default local embedding model
+pub const DEFAULT_OLLAMA_EMBEDDING_MODEL: &str = "nomic-embed-text";
+
+/// Local models can be slow, especially on the first request while loading
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Connection details for an Ollama server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OllamaConfig {
+    /// Server address, e.g. http://localhost:11434
+    pub host: String,
+    /// Model name as shown by `ollama list`
+    pub model: String,
+    /// Embedding model name as shown by `ollama list`
+    pub embedding_model: String,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            host: DEFAULT_OLLAMA_HOST.to_string(),
+            model: DEFAULT_OLLAMA_MODEL.to_string(),
+            embedding_model: DEFAULT_OLLAMA_EMBEDDING_MODEL.to_string(),
+        }
+    }
+}
+
+/// Enrichment provider backed by Ollama's chat API
+pub struct OllamaProvider {
+    config: OllamaConfig,
+    agent: ureq::Agent,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    message: Option<ChatMessage>,
+    /// Tokens in the prompt
+    prompt_eval_count: Option<u64>,
+    /// Tokens generated
+    eval_count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ChatMessage {
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+impl OllamaProvider {
+    /// Builds a full endpoint URL from the configured host
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.config.host.trim_end_matches('/'), path)
+    }
+
+    /// Creates a provider for the configured server and model
+    pub fn new(config: OllamaConfig) -> Result<Self> {
+        if config.model.trim().is_empty() {
+            return Err(EnrichmentError::Config("No Ollama model configured".to_string()));
+        }
+
+        let agent = ureq::AgentBuilder::new()
+            .timeout(REQUEST_TIMEOUT)
+            .build();
+
+        Ok(Self { config, agent })
+    }
+}
+
+impl EnrichmentProvider for OllamaProvider {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn chat_model(&self) -> &str {
+        &self.config.model
+    }
+
+    fn complete(&self, system: &str, prompt: &str, json: bool) -> Result<Completion> {
+        let mut request = json!({
+            "model": self.config.model,
+            "stream": false,
+            "options": { "temperature": 0.2 },
+            "messages": [
+                { "role": "system", "content": system },
+                { "role": "user", "content": prompt },
+            ],
+        });
+        if json {
+            request["format"] = json!("json");
+        }
+
+        let response: ChatResponse = self.agent.post(&self.endpoint("api/chat"))
+            .send_json(request)?
+            .into_json()
+            .map_err(|e| EnrichmentError::InvalidResponse(e.to_string()))?;
+
+        let usage = TokenUsage {
+            prompt_tokens: response.prompt_eval_count.unwrap_or(0),
+            completion_tokens: response.eval_count.unwrap_or(0),
+        };
+
+        let content = response.message
+            .map(|message| message.content)
+            .ok_or_else(|| EnrichmentError::InvalidResponse("Empty completion".to_string()))?;
+
+        Ok(Completion { content, usage: Some(usage) })
+    }
+}
+
+impl EmbeddingProvider for OllamaProvider {
+    fn model(&self) -> &str {
+        &self.config.embedding_model
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let response: EmbedResponse = self.agent.post(&self.endpoint("api/embed"))
+            .send_json(json!({
+                "model": self.config.embedding_model,
+                "input": texts,
+            }))?
+            .into_json()
+            .map_err(|e| EnrichmentError::InvalidResponse(e.to_string()))?;
+
+        if response.embeddings.len() != texts.len() {
+            return Err(EnrichmentError::InvalidResponse(format!(
+                "Expected {} embeddings, got {}", texts.len(), response.embeddings.len(),
+            )));
+        }
+
+        Ok(response.embeddings)
+    }
+}
@@ -0,0 +1,246 @@
+// Enrichment - OpenAI-compatible Provider
+// Calls any chat completions API that follows the OpenAI request format
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::error::{EnrichmentError, Result};
+use super::provider::{Completion, EmbeddingProvider, EnrichmentProvider, Pricing, TokenUsage};
+
+/// Default API base URL
+pub const DEFAULT_OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Default chat model
+pub const DEFAULT_OPENAI_MODEL: &str = "gpt-4o-mini";
+
+/// Default embedding model
+pub const DEFAULT_OPENAI_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+/// Environment variable used when no API key is configured
+const API_KEY_ENV: &str = "OPENAI_API_KEY";
+
+/// Keychain service the API key is stored under
+const KEYCHAIN_SERVICE: &str = "safari-history-knowledge-graph";
+
+/// Keychain account the API key is stored under
+const KEYCHAIN_ACCOUNT: &str = "openai-api-key";
+
+/// Time allowed for a single request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Connection details for an OpenAI-compatible API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenAiConfig {
+    /// API base URL, e.g. https://api.openai.com/v1
+    pub base_url: String,
+    /// New API key to save in the OS keychain, or an empty one to remove it.
+    /// Never serialized, so it stays out of the settings table, the frontend
+    /// and takeouts.
+    #[serde(skip_serializing)]
+    pub api_key: Option<String>,
+    /// Whether an API key is saved in the keychain (without one, the
+    /// OPENAI_API_KEY environment variable is used)
+    pub api_key_set: bool,
+    /// Chat model name
+    pub model: String,
+    /// Embedding model name
+    pub embedding_model: String,
+    /// Token prices of the chat model, used for cost tracking
+    pub pricing: Pricing,
+}
+
+impl Default for OpenAiConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_OPENAI_BASE_URL.to_string(),
+            api_key: None,
+            api_key_set: false,
+            model: DEFAULT_OPENAI_MODEL.to_string(),
+            embedding_model: DEFAULT_OPENAI_EMBEDDING_MODEL.to_string(),
+            // Published gpt-4o-mini prices
+            pricing: Pricing { input_per_million: 0.15, output_per_million: 0.60 },
+        }
+    }
+}
+
+/// Opens the keychain entry holding the API key
+fn keychain_entry() -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT)
+        .map_err(|e| EnrichmentError::Config(format!("Keychain error: {}", e)))
+}
+
+/// Reads the API key from the OS keychain
+fn stored_api_key() -> Result<Option<String>> {
+    match keychain_entry()?.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(EnrichmentError::Config(format!("Keychain error: {}", e))),
+    }
+}
+
+/// Moves a new API key out of the config into the OS keychain, removing the
+/// saved one when the new key is empty, and updates the `api_key_set` flag.
+/// Without a new key the config is left as it is.
+pub fn save_api_key(config: &mut OpenAiConfig) -> Result<()> {
+    let key = match config.api_key.take() {
+        Some(key) => key,
+        None => return Ok(()),
+    };
+
+    let entry = keychain_entry()?;
+    let result = if key.trim().is_empty() {
+        match entry.delete_password() {
+            Err(keyring::Error::NoEntry) => Ok(()),
+            result => result,
+        }
+    } else {
+        entry.set_password(key.trim())
+    };
+    result.map_err(|e| EnrichmentError::Config(format!("Keychain error: {}", e)))?;
+
+    config.api_key_set = !key.trim().is_empty();
+    Ok(())
+}
+
+/// Enrichment provider backed by an OpenAI-compatible chat completions API
+pub struct OpenAiProvider {
+    config: OpenAiConfig,
+    api_key: String,
+    agent: ureq::Agent,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChatUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatMessage {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl OpenAiProvider {
+    /// Builds a full endpoint URL from the configured base URL
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.config.base_url.trim_end_matches('/'), path)
+    }
+
+    /// Creates a provider, resolving the API key from the config, the keychain
+    /// or the environment
+    pub fn new(config: OpenAiConfig) -> Result<Self> {
+        let stored = if config.api_key_set { stored_api_key()? } else { None };
+        let api_key = config.api_key.clone()
+            .filter(|key| !key.is_empty())
+            .or(stored)
+            .or_else(|| std::env::var(API_KEY_ENV).ok())
+            .ok_or_else(|| EnrichmentError::Config(format!("No API key configured and {} is not set", API_KEY_ENV)))?;
+
+        let agent = ureq::AgentBuilder::new()
+            .timeout(REQUEST_TIMEOUT)
+            .build();
+
+        Ok(Self { config, api_key, agent })
+    }
+}
+
+impl EnrichmentProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn chat_model(&self) -> &str {
+        &self.config.model
+    }
+
+    fn pricing(&self) -> Pricing {
+        self.config.pricing
+    }
+
+    fn complete(&self, system: &str, prompt: &str, json: bool) -> Result<Completion> {
+        let mut request = json!({
+            "model": self.config.model,
+            "temperature": 0.2,
+            "messages": [
+                { "role": "system", "content": system },
+                { "role": "user", "content": prompt },
+            ],
+        });
+        if json {
+            request["response_format"] = json!({ "type": "json_object" });
+        }
+
+        let response: ChatResponse = self.agent.post(&self.endpoint("chat/completions"))
+            .set("Authorization", &format!("Bearer {}", self.api_key))
+            .send_json(request)?
+            .into_json()
+            .map_err(|e| EnrichmentError::InvalidResponse(e.to_string()))?;
+
+        let usage = response.usage.map(|usage| TokenUsage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        });
+
+        let content = response.choices.into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| EnrichmentError::InvalidResponse("Empty completion".to_string()))?;
+
+        Ok(Completion { content, usage })
+    }
+}
+
+impl EmbeddingProvider for OpenAiProvider {
+    fn model(&self) -> &str {
+        &self.config.embedding_model
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut response: EmbeddingResponse = self.agent.post(&self.endpoint("embeddings"))
+            .set("Authorization", &format!("Bearer {}", self.api_key))
+            .send_json(json!({
+                "model": self.config.embedding_model,
+                "input": texts,
+            }))?
+            .into_json()
+            .map_err(|e| EnrichmentError::InvalidResponse(e.to_string()))?;
+
+        if response.data.len() != texts.len() {
+            return Err(EnrichmentError::InvalidResponse(format!(
+                "Expected {} embeddings, got {}", texts.len(), response.data.len(),
+            )));
+        }
+
+        response.data.sort_by_key(|data| data.index);
+        Ok(response.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
@@ -0,0 +1,160 @@
+// Enrichment - Provider Interface
+// Common trait, prompt and response parsing shared by all providers
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::error::{EnrichmentError, Result};
+
+/// Maximum number of keywords kept per page
+pub const MAX_KEYWORDS: usize = 8;
+
+/// The page details sent to a provider
+#[derive(Debug, Clone)]
+pub struct PageInput {
+    /// URL id the results belong to
+    pub url_id: String,
+    /// Full URL
+    pub url: String,
+    /// Page title, if known
+    pub title: Option<String>,
+    /// Domain of the URL
+    pub domain: String,
+}
+
+/// The metadata a provider generated for a page
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Enrichment {
+    /// One or two sentence summary
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// Lowercase keywords
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Short category name (e.g. "programming")
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Tokens the provider reported for the request, if any
+    #[serde(skip)]
+    pub usage: Option<TokenUsage>,
+}
+
+/// Tokens consumed by one provider request
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TokenUsage {
+    /// Tokens in the prompt
+    pub prompt_tokens: u64,
+    /// Tokens in the generated answer
+    pub completion_tokens: u64,
+}
+
+/// Text generated by a chat model
+#[derive(Debug, Clone, Default)]
+pub struct Completion {
+    /// Generated message
+    pub content: String,
+    /// Tokens the provider reported for the request, if any
+    pub usage: Option<TokenUsage>,
+}
+
+/// Price of a model in currency units (usually USD) per million tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pricing {
+    /// Price per million prompt tokens
+    pub input_per_million: f64,
+    /// Price per million completion tokens
+    pub output_per_million: f64,
+}
+
+impl Pricing {
+    /// Cost of the given token counts
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.input_per_million + completion_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// A service that generates summaries, keywords and categories for pages
+pub trait EnrichmentProvider: Send + Sync {
+    /// Short provider name used in logs and errors
+    fn name(&self) -> &str;
+
+    /// Name of the model generating the metadata
+    fn chat_model(&self) -> &str;
+
+    /// Token prices of the model (free for local models)
+    fn pricing(&self) -> Pricing {
+        Pricing::default()
+    }
+
+    /// Sends a system and user message to the chat model, optionally asking
+    /// for a JSON object as the reply
+    fn complete(&self, system: &str, prompt: &str, json: bool) -> Result<Completion>;
+
+    /// Generates metadata for a single page
+    fn enrich(&self, page: &PageInput) -> Result<Enrichment> {
+        let completion = self.complete(SYSTEM_PROMPT, &page_prompt(page), true)?;
+        let mut enrichment = parse_enrichment(&completion.content)?;
+        enrichment.usage = completion.usage;
+        Ok(enrichment)
+    }
+}
+
+/// A service that turns text into embedding vectors
+pub trait EmbeddingProvider: Send + Sync {
+    /// Name of the embedding model, stored alongside every vector
+    fn model(&self) -> &str;
+
+    /// Embeds every text, returning one vector per input in the same order
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Instructions sent as the system message
+pub const SYSTEM_PROMPT: &str = "You label pages from a personal browsing history. \
+Reply with a single JSON object and nothing else, using the keys \
+\"summary\" (one sentence describing what the page is about), \
+\"keywords\" (up to 8 short lowercase keywords) and \
+\"category\" (one or two lowercase words such as \"programming\" or \"news\").";
+
+/// Builds the user message describing a page
+pub fn page_prompt(page: &PageInput) -> String {
+    format!(
+        "URL: {}\nDomain: {}\nTitle: {}",
+        page.url,
+        page.domain,
+        page.title.as_deref().unwrap_or("(untitled)"),
+    )
+}
+
+/// Parses the JSON object a model replied with, tolerating surrounding text
+/// such as Markdown code fences
+pub fn parse_enrichment(content: &str) -> Result<Enrichment> {
+    let start = content.find('{');
+    let end = content.rfind('}');
+
+    let json = match (start, end) {
+        (Some(start), Some(end)) if start < end => &content[start..=end],
+        _ => return Err(EnrichmentError::InvalidResponse(format!("No JSON object in: {}", content))),
+    };
+
+    let mut enrichment: Enrichment = serde_json::from_str(json)
+        .map_err(|e| EnrichmentError::InvalidResponse(format!("{}: {}", e, json)))?;
+
+    enrichment.summary = enrichment.summary
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    enrichment.category = enrichment.category
+        .map(|c| c.trim().to_lowercase())
+        .filter(|c| !c.is_empty());
+
+    let mut seen = HashSet::new();
+    enrichment.keywords = enrichment.keywords.iter()
+        .map(|k| k.trim().to_lowercase())
+        .filter(|k| !k.is_empty() && seen.insert(k.clone()))
+        .take(MAX_KEYWORDS)
+        .collect();
+
+    Ok(enrichment)
+}
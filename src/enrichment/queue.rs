@@ -0,0 +1,413 @@
+// Enrichment - Job Queue
+// Persistent, resumable enrichment queue with retries, backoff and rate limiting
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{self, DatabaseConnection, DatabaseError};
+use super::error::{EnrichmentError, Result};
+use super::provider::EnrichmentProvider;
+use super::{get_pages, save_enrichment};
+use super::usage::{new_run_id, record_request};
+
+/// First retry delay; doubled after every further failure
+const BACKOFF_BASE_SECS: i64 = 30;
+
+/// Longest delay between two attempts of a job
+const BACKOFF_MAX_SECS: i64 = 60 * 60;
+
+/// Longest time an idle worker sleeps before checking for due retries
+const IDLE_POLL: Duration = Duration::from_secs(5);
+
+/// Queue tuning, stored with the enrichment settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueSettings {
+    /// Number of pages enriched in parallel
+    pub concurrency: usize,
+    /// Maximum provider requests per minute across all workers (0 for unlimited)
+    pub requests_per_minute: u32,
+    /// Attempts before a job is marked as failed
+    pub max_attempts: u32,
+}
+
+impl Default for QueueSettings {
+    fn default() -> Self {
+        Self {
+            concurrency: 2,
+            requests_per_minute: 60,
+            max_attempts: 5,
+        }
+    }
+}
+
+/// Number of jobs in each state
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QueueStatus {
+    /// Waiting to run (including scheduled retries)
+    pub pending: usize,
+    /// Currently being enriched
+    pub running: usize,
+    /// Enriched successfully
+    pub done: usize,
+    /// Gave up after the maximum number of attempts
+    pub failed: usize,
+    /// Not sent to the provider because the page is behind a paywall or login wall
+    pub skipped: usize,
+    /// Whether workers are currently processing the queue
+    pub active: bool,
+}
+
+/// Shared flags controlling the background workers
+#[derive(Debug, Default)]
+pub struct QueueControl {
+    running: AtomicBool,
+    stop: AtomicBool,
+}
+
+impl QueueControl {
+    /// Returns true while workers are processing the queue
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Asks the workers to stop after their current job
+    pub fn request_stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Spaces provider requests evenly to stay under a requests-per-minute budget
+struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_minute: u32) -> Self {
+        let interval = if requests_per_minute == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs(60) / requests_per_minute
+        };
+
+        Self { interval, next: Mutex::new(Instant::now()) }
+    }
+
+    /// Blocks until the caller may send the next request
+    fn wait(&self) {
+        let wait_until = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+
+        let now = Instant::now();
+        if wait_until > now {
+            thread::sleep(wait_until - now);
+        }
+    }
+}
+
+/// Adds jobs for the given URLs; URLs already queued are reset to pending
+pub fn enqueue(conn: &DatabaseConnection, url_ids: &[String]) -> Result<usize> {
+    let now = Utc::now().timestamp();
+
+    Ok(conn.transaction(|tx| {
+        let mut stmt = tx.prepare(
+            "INSERT INTO enrichment_job (url_id, status, attempts, next_attempt_at, created_at, updated_at)
+             VALUES (?1, 'pending', 0, ?2, ?2, ?2)
+             ON CONFLICT (url_id) DO UPDATE SET status = 'pending', attempts = 0,
+                 next_attempt_at = excluded.next_attempt_at, last_error = NULL, updated_at = excluded.updated_at
+             WHERE status <> 'running'"
+        )?;
+
+        let mut queued = 0;
+        for url_id in url_ids {
+            queued += stmt.execute(params![url_id, now])?;
+        }
+        Ok(queued)
+    })?)
+}
+
+/// Adds jobs for every URL that is neither enriched nor already queued
+pub fn enqueue_unenriched(conn: &DatabaseConnection) -> Result<usize> {
+    let now = Utc::now().timestamp();
+
+    Ok(conn.with_connection(|c| {
+        let queued = c.execute(
+            "INSERT INTO enrichment_job (url_id, status, attempts, next_attempt_at, created_at, updated_at)
+             SELECT u.id, 'pending', 0, ?1, ?1, ?1
+             FROM url u
+             LEFT JOIN metadata m ON m.url_id = u.id
+             WHERE (m.url_id IS NULL OR (m.is_enriched = 0 AND m.access_wall IS NULL))
+               AND NOT EXISTS (SELECT 1 FROM enrichment_job j WHERE j.url_id = u.id)",
+            [now],
+        )?;
+        Ok(queued)
+    })?)
+}
+
+/// Puts jobs interrupted by an app shutdown back in the queue
+pub fn recover_interrupted(conn: &DatabaseConnection) -> Result<usize> {
+    Ok(conn.with_connection(|c| {
+        Ok(c.execute("UPDATE enrichment_job SET status = 'pending' WHERE status = 'running'", [])?)
+    })?)
+}
+
+/// Counts jobs per state
+pub fn queue_status(conn: &DatabaseConnection, control: &QueueControl) -> Result<QueueStatus> {
+    let mut status = conn.with_connection(|c| {
+        let mut stmt = c.prepare("SELECT status, COUNT(*) FROM enrichment_job GROUP BY status")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?;
+
+        let mut status = QueueStatus::default();
+        for row in rows {
+            let (state, count) = row?;
+            let count = count as usize;
+            match state.as_str() {
+                "pending" => status.pending = count,
+                "running" => status.running = count,
+                "done" => status.done = count,
+                "failed" => status.failed = count,
+                "skipped" => status.skipped = count,
+                _ => {},
+            }
+        }
+        Ok(status)
+    })?;
+
+    status.active = control.is_running();
+    Ok(status)
+}
+
+/// Marks the next due job as running and returns (job id, url id)
+fn claim_job(c: &Connection, now: i64) -> db::Result<Option<(i64, String)>> {
+    let job = c.query_row(
+        "UPDATE enrichment_job SET status = 'running', attempts = attempts + 1, updated_at = ?1
+         WHERE id = (
+             SELECT id FROM enrichment_job
+             WHERE status = 'pending' AND next_attempt_at <= ?1
+             ORDER BY next_attempt_at, id
+             LIMIT 1
+         )
+         RETURNING id, url_id",
+        [now],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional()?;
+
+    Ok(job)
+}
+
+/// Returns the time of the earliest scheduled retry, if any job is still pending
+fn next_due(c: &Connection) -> db::Result<Option<i64>> {
+    Ok(c.query_row(
+        "SELECT MIN(next_attempt_at) FROM enrichment_job WHERE status = 'pending'",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+/// Delay before retrying a job that has failed `attempts` times
+fn backoff_secs(attempts: u32) -> i64 {
+    let exponent = attempts.saturating_sub(1).min(16);
+    (BACKOFF_BASE_SECS << exponent).min(BACKOFF_MAX_SECS)
+}
+
+/// Records a failed attempt, scheduling a retry or giving up
+fn fail_job(c: &Connection, job_id: i64, error: &str, max_attempts: u32) -> db::Result<()> {
+    let attempts: u32 = c.query_row("SELECT attempts FROM enrichment_job WHERE id = ?", [job_id], |row| row.get(0))?;
+    let now = Utc::now().timestamp();
+
+    let (status, next_attempt_at) = if attempts >= max_attempts {
+        ("failed", now)
+    } else {
+        ("pending", now + backoff_secs(attempts))
+    };
+
+    c.execute(
+        "UPDATE enrichment_job SET status = ?, next_attempt_at = ?, last_error = ?, updated_at = ? WHERE id = ?",
+        params![status, next_attempt_at, error, now, job_id],
+    )?;
+
+    Ok(())
+}
+
+/// Gets the wall fetching the page ran into ("paywall" or "login"), if any
+fn access_wall(c: &Connection, url_id: &str) -> db::Result<Option<String>> {
+    let wall: Option<Option<String>> = c.query_row(
+        "SELECT access_wall FROM metadata WHERE url_id = ?",
+        [url_id],
+        |row| row.get(0),
+    ).optional()?;
+    Ok(wall.flatten())
+}
+
+/// Records a job left out because its page is behind a wall
+fn skip_job(c: &Connection, job_id: i64, wall: &str) -> db::Result<()> {
+    let reason = match wall {
+        "login" => "Page requires a login",
+        _ => "Page is behind a paywall",
+    };
+    c.execute(
+        "UPDATE enrichment_job SET status = 'skipped', last_error = ?, updated_at = ? WHERE id = ?",
+        params![reason, Utc::now().timestamp(), job_id],
+    )?;
+    Ok(())
+}
+
+/// Runs a closure with the shared database connection
+fn with_db<T>(
+    db: &RwLock<Option<DatabaseConnection>>,
+    f: impl FnOnce(&DatabaseConnection) -> Result<T>,
+) -> Result<T> {
+    let guard = db.read()
+        .map_err(|_| EnrichmentError::Database(DatabaseError::Lock("Failed to acquire database lock".to_string())))?;
+    let conn = guard.as_ref()
+        .ok_or_else(|| EnrichmentError::Database(DatabaseError::Connection("Database not initialized".to_string())))?;
+    f(conn)
+}
+
+/// Processes one job, returning false once no job is due
+fn process_next(
+    db: &RwLock<Option<DatabaseConnection>>,
+    run_id: &str,
+    provider: &dyn EnrichmentProvider,
+    limiter: &RateLimiter,
+    settings: &QueueSettings,
+) -> Result<bool> {
+    // Claim a job and load its page without holding the lock during the request
+    let claimed = with_db(db, |conn| {
+        let job = conn.with_connection(|c| claim_job(c, Utc::now().timestamp()))?;
+        match job {
+            Some((job_id, url_id)) => {
+                let wall = conn.with_connection(|c| access_wall(c, &url_id))?;
+                let page = get_pages(conn, &[url_id])?.into_iter().next();
+                Ok(Some((job_id, page, wall)))
+            },
+            None => Ok(None),
+        }
+    })?;
+
+    let (job_id, page, wall) = match claimed {
+        Some(claimed) => claimed,
+        None => return Ok(false),
+    };
+
+    // A provider would only describe the wall, so the page is left unenriched
+    if let Some(wall) = wall {
+        with_db(db, |conn| Ok(conn.with_connection(|c| skip_job(c, job_id, &wall))?))?;
+        return Ok(true);
+    }
+
+    let page = match page {
+        Some(page) => page,
+        None => {
+            // The URL was deleted since it was queued
+            with_db(db, |conn| Ok(conn.with_connection(|c| fail_job(c, job_id, "URL no longer exists", 0))?))?;
+            return Ok(true);
+        },
+    };
+
+    limiter.wait();
+    let outcome = provider.enrich(&page);
+
+    with_db(db, |conn| {
+        conn.transaction(|tx| match &outcome {
+            Ok(enrichment) => {
+                save_enrichment(tx, &page.url_id, enrichment)?;
+                record_request(tx, run_id, provider, enrichment.usage, false)?;
+                tx.execute(
+                    "UPDATE enrichment_job SET status = 'done', last_error = NULL, updated_at = ? WHERE id = ?",
+                    params![Utc::now().timestamp(), job_id],
+                )?;
+                Ok(())
+            },
+            Err(e) => {
+                record_request(tx, run_id, provider, None, true)?;
+                fail_job(tx, job_id, &e.to_string(), settings.max_attempts)
+            },
+        })?;
+        Ok(())
+    })?;
+
+    Ok(true)
+}
+
+/// Works through the queue until it is empty or a stop is requested.
+///
+/// Runs `settings.concurrency` workers sharing one rate limit, calling
+/// `on_progress` after every processed job. Jobs whose retry is scheduled
+/// later keep the workers waiting, so a run only ends once every job is done,
+/// has failed for good, or the queue is stopped.
+pub fn run_queue(
+    db: &RwLock<Option<DatabaseConnection>>,
+    control: &QueueControl,
+    provider: Arc<dyn EnrichmentProvider>,
+    settings: &QueueSettings,
+    on_progress: &(dyn Fn(&QueueStatus) + Sync),
+) -> Result<()> {
+    if control.running.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    control.stop.store(false, Ordering::SeqCst);
+
+    let run_id = new_run_id("queue");
+    let limiter = RateLimiter::new(settings.requests_per_minute);
+    let first_error: Mutex<Option<EnrichmentError>> = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for _ in 0..settings.concurrency.max(1) {
+            let provider = Arc::clone(&provider);
+            let limiter = &limiter;
+            let run_id = run_id.as_str();
+            let first_error = &first_error;
+
+            scope.spawn(move || {
+                while !control.stop.load(Ordering::SeqCst) {
+                    match process_next(db, run_id, provider.as_ref(), limiter, settings) {
+                        Ok(true) => {
+                            if let Ok(status) = with_db(db, |conn| queue_status(conn, control)) {
+                                on_progress(&status);
+                            }
+                        },
+                        Ok(false) => {
+                            // Nothing due right now: wait for the next retry or finish
+                            let next = with_db(db, |conn| Ok(conn.with_connection(next_due)?));
+                            match next {
+                                Ok(Some(due)) => {
+                                    let wait = (due - Utc::now().timestamp()).max(1) as u64;
+                                    thread::sleep(Duration::from_secs(wait).min(IDLE_POLL));
+                                },
+                                _ => break,
+                            }
+                        },
+                        Err(e) => {
+                            // Database problems stop every worker, provider errors are per job
+                            first_error.lock().unwrap_or_else(|e| e.into_inner()).get_or_insert(e);
+                            control.stop.store(true, Ordering::SeqCst);
+                        },
+                    }
+                }
+            });
+        }
+    });
+
+    control.running.store(false, Ordering::SeqCst);
+
+    if let Ok(status) = with_db(db, |conn| queue_status(conn, control)) {
+        on_progress(&status);
+    }
+
+    match first_error.into_inner().unwrap_or_else(|e| e.into_inner()) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,15 @@
+#[cfg(test)]
+mod tests {
+    use crate::enrichment::category::Category;
+
+    #[test]
+    fn test_category_labels_match_whole_words() {
+        assert_eq!(Category::from_label("Social"), Some(Category::Social));
+        assert_eq!(Category::from_label("Social media"), Some(Category::Social));
+        assert_eq!(Category::from_label("News media"), Some(Category::News));
+        assert_eq!(Category::from_label("Online stores"), Some(Category::Shopping));
+        assert_eq!(Category::from_label("API reference"), Some(Category::Docs));
+        assert_eq!(Category::from_label("Cloud storage"), None);
+        assert_eq!(Category::from_label("Woodworking workshop"), None);
+    }
+}
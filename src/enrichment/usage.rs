@@ -0,0 +1,223 @@
+// Enrichment - Usage Tracking
+// Records requests, tokens and cost per provider and run, and estimates future runs
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use crate::db::{self, DatabaseConnection};
+use crate::plugins::{find_plugin, PluginKind};
+use super::error::Result;
+use super::provider::{page_prompt, EnrichmentProvider, PageInput, Pricing, TokenUsage, SYSTEM_PROMPT};
+use super::{EnrichmentSettings, ProviderKind};
+
+/// Assumed answer length when no run has been recorded yet
+const DEFAULT_COMPLETION_TOKENS: f64 = 80.0;
+
+/// Rough characters-per-token ratio for English text
+const CHARS_PER_TOKEN: f64 = 4.0;
+
+/// Usage of one provider and model during one run
+#[derive(Debug, Clone, Serialize)]
+pub struct RunUsage {
+    /// Run identifier (e.g. "queue-1718000000000")
+    pub run_id: String,
+    /// Provider name
+    pub provider: String,
+    /// Model name
+    pub model: String,
+    /// Requests sent
+    pub requests: u64,
+    /// Requests that failed
+    pub failed_requests: u64,
+    /// Prompt tokens consumed
+    pub prompt_tokens: u64,
+    /// Completion tokens generated
+    pub completion_tokens: u64,
+    /// Estimated cost
+    pub cost: f64,
+    /// When the run started
+    pub started_at: DateTime<Utc>,
+    /// When the run last sent a request
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Projected cost of enriching every page that is not enriched yet
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageEstimate {
+    /// Provider the estimate is for
+    pub provider: String,
+    /// Model the estimate is for
+    pub model: String,
+    /// Pages still to enrich
+    pub pending_pages: usize,
+    /// Expected prompt tokens per page
+    pub prompt_tokens_per_page: f64,
+    /// Expected completion tokens per page
+    pub completion_tokens_per_page: f64,
+    /// Expected total cost
+    pub estimated_cost: f64,
+    /// True when the per-page figures come from recorded runs rather than a guess
+    pub from_history: bool,
+}
+
+/// Recorded usage and the estimate for the remaining pages
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    /// Usage per run, newest first
+    pub runs: Vec<RunUsage>,
+    /// Total requests over all runs
+    pub total_requests: u64,
+    /// Total tokens over all runs
+    pub total_tokens: u64,
+    /// Total cost over all runs
+    pub total_cost: f64,
+    /// Estimate for enriching the rest of the history
+    pub estimate: UsageEstimate,
+}
+
+/// Creates an identifier for a new run of the given kind
+pub fn new_run_id(kind: &str) -> String {
+    format!("{}-{}", kind, Utc::now().timestamp_millis())
+}
+
+/// Adds one request to the usage of a run
+pub fn record_request(
+    c: &Connection,
+    run_id: &str,
+    provider: &dyn EnrichmentProvider,
+    usage: Option<TokenUsage>,
+    failed: bool,
+) -> db::Result<()> {
+    let usage = usage.unwrap_or_default();
+    let cost = provider.pricing().cost(usage.prompt_tokens, usage.completion_tokens);
+    let now = Utc::now().timestamp();
+
+    c.execute(
+        "INSERT INTO enrichment_usage
+             (run_id, provider, model, requests, failed_requests, prompt_tokens, completion_tokens, cost, started_at, updated_at)
+         VALUES (?1, ?2, ?3, 1, ?4, ?5, ?6, ?7, ?8, ?8)
+         ON CONFLICT (run_id, provider, model) DO UPDATE SET
+             requests = requests + 1,
+             failed_requests = failed_requests + excluded.failed_requests,
+             prompt_tokens = prompt_tokens + excluded.prompt_tokens,
+             completion_tokens = completion_tokens + excluded.completion_tokens,
+             cost = cost + excluded.cost,
+             updated_at = excluded.updated_at",
+        params![
+            run_id,
+            provider.name(),
+            provider.chat_model(),
+            failed as i64,
+            usage.prompt_tokens as i64,
+            usage.completion_tokens as i64,
+            cost,
+            now,
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Provider name, chat model and prices currently selected in the settings
+fn selected_model(settings: &EnrichmentSettings) -> (String, String, Pricing) {
+    match settings.provider {
+        ProviderKind::OpenAi => ("openai".to_string(), settings.openai.model.clone(), settings.openai.pricing),
+        ProviderKind::Ollama => ("ollama".to_string(), settings.ollama.model.clone(), Pricing::default()),
+        ProviderKind::Plugin => {
+            // Plugins name themselves after their id, as PluginProvider does
+            let id = settings.plugin.id.clone().unwrap_or_default();
+            match find_plugin(&id, PluginKind::Enrichment) {
+                Ok((manifest, _)) => (id, manifest.model.unwrap_or(manifest.id), manifest.pricing),
+                Err(_) => (id.clone(), id, Pricing::default()),
+            }
+        },
+    }
+}
+
+/// Gets the recorded usage per run and estimates the cost of enriching the remaining pages
+pub fn get_enrichment_usage(conn: &DatabaseConnection, settings: &EnrichmentSettings) -> Result<UsageReport> {
+    let (provider, model, pricing) = selected_model(settings);
+
+    Ok(conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT run_id, provider, model, requests, failed_requests, prompt_tokens, completion_tokens,
+                    cost, started_at, updated_at
+             FROM enrichment_usage
+             ORDER BY started_at DESC, run_id"
+        )?;
+        let runs = stmt.query_map([], |row| {
+            Ok(RunUsage {
+                run_id: row.get(0)?,
+                provider: row.get(1)?,
+                model: row.get(2)?,
+                requests: row.get::<_, i64>(3)? as u64,
+                failed_requests: row.get::<_, i64>(4)? as u64,
+                prompt_tokens: row.get::<_, i64>(5)? as u64,
+                completion_tokens: row.get::<_, i64>(6)? as u64,
+                cost: row.get(7)?,
+                started_at: DateTime::from_timestamp(row.get(8)?, 0).unwrap_or_default(),
+                updated_at: DateTime::from_timestamp(row.get(9)?, 0).unwrap_or_default(),
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        // Pages still to enrich and the average length of what would be sent for them
+        let (pending_pages, average_chars): (i64, Option<f64>) = c.query_row(
+            "SELECT COUNT(*), AVG(LENGTH(u.url) + LENGTH(u.domain) + LENGTH(COALESCE(u.title, '')))
+             FROM url u
+             LEFT JOIN metadata m ON m.url_id = u.id
+             WHERE m.url_id IS NULL OR m.is_enriched = 0",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        // Per-page averages observed for the selected model, if it has been used
+        let observed = runs.iter()
+            .filter(|run| run.provider == provider && run.model == model)
+            .fold((0u64, 0u64, 0u64), |(requests, prompt, completion), run| {
+                let successful = run.requests - run.failed_requests;
+                (requests + successful, prompt + run.prompt_tokens, completion + run.completion_tokens)
+            });
+
+        let (prompt_per_page, completion_per_page, from_history) = if observed.0 > 0 && observed.1 > 0 {
+            (observed.1 as f64 / observed.0 as f64, observed.2 as f64 / observed.0 as f64, true)
+        } else {
+            let sample = PageInput {
+                url_id: String::new(),
+                url: String::new(),
+                title: None,
+                domain: String::new(),
+            };
+            let fixed_chars = (SYSTEM_PROMPT.len() + page_prompt(&sample).len()) as f64;
+            let prompt = (fixed_chars + average_chars.unwrap_or(0.0)) / CHARS_PER_TOKEN;
+            (prompt, DEFAULT_COMPLETION_TOKENS, false)
+        };
+
+        let pending_pages = pending_pages as usize;
+        let estimated_cost = pricing.cost(
+            (prompt_per_page * pending_pages as f64).round() as u64,
+            (completion_per_page * pending_pages as f64).round() as u64,
+        );
+
+        let total_requests = runs.iter().map(|run| run.requests).sum();
+        let total_tokens = runs.iter().map(|run| run.prompt_tokens + run.completion_tokens).sum();
+        let total_cost = runs.iter().map(|run| run.cost).sum();
+
+        Ok(UsageReport {
+            runs,
+            total_requests,
+            total_tokens,
+            total_cost,
+            estimate: UsageEstimate {
+                provider: provider.to_string(),
+                model,
+                pending_pages,
+                prompt_tokens_per_page: prompt_per_page,
+                completion_tokens_per_page: completion_per_page,
+                estimated_cost,
+                from_history,
+            },
+        })
+    })?)
+}
@@ -0,0 +1,270 @@
+// Application Error Handling
+// Defines the structured error every command returns to the frontend
+
+use std::fmt;
+use std::error::Error;
+use std::io;
+
+use serde::Serialize;
+
+use crate::capture::CaptureError;
+use crate::db::DatabaseError;
+use crate::enrichment::EnrichmentError;
+use crate::export::ExportError;
+use crate::extractor::ExtractionError;
+use crate::mcp::McpError;
+use crate::plugins::PluginError;
+use crate::privacy::PrivacyError;
+use crate::report::ReportError;
+use crate::scripting::ScriptError;
+use crate::settings::SettingsError;
+use crate::sync::SyncError;
+use crate::web::WebError;
+
+/// Category of an error the frontend can branch on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The request itself was invalid (bad date, empty name, ...)
+    InvalidInput,
+    /// A record or file the request refers to does not exist
+    NotFound,
+    /// The database hasn't been opened yet
+    NotInitialized,
+    /// The app is locked, or the database is encrypted and the passphrase is missing or wrong
+    Locked,
+    /// The database is busy with another writer
+    Busy,
+    /// A long query was cancelled or ran past its time limit
+    Interrupted,
+    /// A database query or schema problem
+    Database,
+    /// Reading or writing a file failed
+    Io,
+    /// A web page could not be fetched
+    Network,
+    /// The AI provider failed or answered with something unusable
+    Provider,
+    /// Settings are missing or invalid
+    Config,
+    /// Anything else
+    Internal,
+}
+
+/// Error returned from every command
+#[derive(Debug, Clone, Serialize)]
+pub struct AppError {
+    /// Category of the error
+    pub kind: ErrorKind,
+    /// Message to show the user
+    pub message: String,
+    /// Underlying error, when the message adds context to one
+    pub details: Option<String>,
+    /// Retrying the same request may succeed
+    pub retryable: bool,
+}
+
+impl AppError {
+    /// Creates an error of the given kind; busy, network and provider errors are retryable
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        AppError {
+            kind,
+            message: message.into(),
+            details: None,
+            retryable: matches!(kind, ErrorKind::Busy | ErrorKind::Network | ErrorKind::Provider),
+        }
+    }
+
+    /// Creates an invalid input error
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        AppError::new(ErrorKind::InvalidInput, message)
+    }
+
+    /// Creates the error returned before the database is opened
+    pub fn not_initialized() -> Self {
+        AppError::new(ErrorKind::NotInitialized, "Database not initialized")
+    }
+
+    /// Creates an internal error
+    pub fn internal(message: impl Into<String>) -> Self {
+        AppError::new(ErrorKind::Internal, message)
+    }
+
+    /// Wraps an error with what was being done, e.g. "Failed to list tags",
+    /// keeping its kind and the original message as details
+    pub fn wrap(context: &str, err: impl Into<AppError>) -> Self {
+        let err = err.into();
+        AppError {
+            message: format!("{}: {}", context, err.message),
+            details: Some(err.message),
+            ..err
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for AppError {}
+
+impl From<DatabaseError> for AppError {
+    fn from(err: DatabaseError) -> Self {
+        let message = err.to_string();
+        let kind = match &err {
+            DatabaseError::Lock(_) | DatabaseError::Busy(_) => ErrorKind::Busy,
+            DatabaseError::Interrupted(_) => ErrorKind::Interrupted,
+            DatabaseError::BadKey(_) => ErrorKind::Locked,
+            DatabaseError::NotFound(_) => ErrorKind::NotFound,
+            DatabaseError::Data(_) => ErrorKind::InvalidInput,
+            DatabaseError::Io(e) if e.kind() == io::ErrorKind::NotFound => ErrorKind::NotFound,
+            DatabaseError::Io(_) => ErrorKind::Io,
+            DatabaseError::Other(_) => ErrorKind::Internal,
+            _ => ErrorKind::Database,
+        };
+        AppError::new(kind, message)
+    }
+}
+
+impl From<WebError> for AppError {
+    fn from(err: WebError) -> Self {
+        match err {
+            WebError::Database(err) => AppError::from(err),
+            WebError::Http(_) | WebError::Status(_) => AppError::new(ErrorKind::Network, err.to_string()),
+            WebError::Io(_) => AppError::new(ErrorKind::Io, err.to_string()),
+            WebError::Content(_) => AppError::new(ErrorKind::InvalidInput, err.to_string()),
+        }
+    }
+}
+
+impl From<EnrichmentError> for AppError {
+    fn from(err: EnrichmentError) -> Self {
+        match err {
+            EnrichmentError::Database(err) => AppError::from(err),
+            EnrichmentError::Http(_) | EnrichmentError::InvalidResponse(_) => AppError::new(ErrorKind::Provider, err.to_string()),
+            EnrichmentError::Config(_) => AppError::new(ErrorKind::Config, err.to_string()),
+        }
+    }
+}
+
+impl From<ReportError> for AppError {
+    fn from(err: ReportError) -> Self {
+        match err {
+            ReportError::Database(err) => AppError::from(err),
+            ReportError::Io(_) => AppError::new(ErrorKind::Io, err.to_string()),
+            ReportError::InvalidPeriod(_) | ReportError::InvalidTemplate(_) => AppError::new(ErrorKind::InvalidInput, err.to_string()),
+        }
+    }
+}
+
+impl From<ExtractionError> for AppError {
+    fn from(err: ExtractionError) -> Self {
+        match err {
+            ExtractionError::Io(ref e) if e.kind() == io::ErrorKind::NotFound => AppError::new(ErrorKind::NotFound, err.to_string()),
+            ExtractionError::Io(_) => AppError::new(ErrorKind::Io, err.to_string()),
+            ExtractionError::Other(_) => AppError::new(ErrorKind::Internal, err.to_string()),
+            _ => AppError::new(ErrorKind::InvalidInput, err.to_string()),
+        }
+    }
+}
+
+impl From<ExportError> for AppError {
+    fn from(err: ExportError) -> Self {
+        match err {
+            ExportError::Database(err) => AppError::from(err),
+            ExportError::Io(_) => AppError::new(ErrorKind::Io, err.to_string()),
+            ExportError::Encoding(_) => AppError::internal(err.to_string()),
+            ExportError::Plugin(err) => AppError::from(err),
+        }
+    }
+}
+
+impl From<CaptureError> for AppError {
+    fn from(err: CaptureError) -> Self {
+        match err {
+            CaptureError::Database(err) => AppError::from(err),
+            CaptureError::Privacy(err) => AppError::from(err),
+            CaptureError::Script(err) => AppError::from(err),
+            CaptureError::Server(_) => AppError::new(ErrorKind::Io, err.to_string()),
+            CaptureError::Invalid(_) => AppError::invalid_input(err.to_string()),
+        }
+    }
+}
+
+impl From<McpError> for AppError {
+    fn from(err: McpError) -> Self {
+        match err {
+            McpError::Database(err) => AppError::from(err),
+            McpError::Server(_) => AppError::new(ErrorKind::Io, err.to_string()),
+            McpError::InvalidParams(_) => AppError::invalid_input(err.to_string()),
+            McpError::UnknownSession(_) => AppError::new(ErrorKind::NotFound, err.to_string()),
+        }
+    }
+}
+
+impl From<ScriptError> for AppError {
+    fn from(err: ScriptError) -> Self {
+        match err {
+            ScriptError::Database(err) => AppError::from(err),
+            // Scripts are the user's code, so their failures are bad input
+            ScriptError::Compile(_) | ScriptError::Runtime(_) | ScriptError::Invalid(_) => AppError::invalid_input(err.to_string()),
+            ScriptError::NotFound(_) => AppError::new(ErrorKind::NotFound, err.to_string()),
+        }
+    }
+}
+
+impl From<PluginError> for AppError {
+    fn from(err: PluginError) -> Self {
+        match err {
+            PluginError::Io(_) => AppError::new(ErrorKind::Io, err.to_string()),
+            PluginError::Manifest(_) => AppError::new(ErrorKind::Config, err.to_string()),
+            PluginError::NotFound(_) => AppError::new(ErrorKind::NotFound, err.to_string()),
+            PluginError::Wasm(_) | PluginError::Plugin(_) | PluginError::Denied(_) => AppError::new(ErrorKind::Provider, err.to_string()),
+        }
+    }
+}
+
+impl From<PrivacyError> for AppError {
+    fn from(err: PrivacyError) -> Self {
+        match err {
+            PrivacyError::Database(err) => AppError::from(err),
+            PrivacyError::InvalidRule(_) => AppError::invalid_input(err.to_string()),
+        }
+    }
+}
+
+impl From<SettingsError> for AppError {
+    fn from(err: SettingsError) -> Self {
+        match err {
+            SettingsError::Database(err) => AppError::from(err),
+            SettingsError::Invalid(_) => AppError::invalid_input(err.to_string()),
+        }
+    }
+}
+
+impl From<SyncError> for AppError {
+    fn from(err: SyncError) -> Self {
+        match err {
+            SyncError::Database(err) => AppError::from(err),
+            SyncError::Io(_) => AppError::new(ErrorKind::Io, err.to_string()),
+            SyncError::Network(_) => AppError::new(ErrorKind::Network, err.to_string()),
+            // A wrong passphrase or another file is the user's input
+            SyncError::Bundle(_) | SyncError::Invalid(_) => AppError::invalid_input(err.to_string()),
+        }
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(err: io::Error) -> Self {
+        let kind = if err.kind() == io::ErrorKind::NotFound { ErrorKind::NotFound } else { ErrorKind::Io };
+        AppError::new(kind, err.to_string())
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(err: tauri::Error) -> Self {
+        AppError::internal(err.to_string())
+    }
+}
@@ -0,0 +1,134 @@
+// Backend Events
+// Typed events emitted to every window when data changes, so views can
+// refresh when told instead of polling, and windows showing the same data
+// (e.g. a search and a graph window) stay in sync
+
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::jobs::Job;
+use crate::lock::LockStatus;
+
+/// Sent when URLs, visits or their tags change
+pub const HISTORY_UPDATED: &str = "history-updated";
+
+/// Sent when an enrichment run or the enrichment queue finishes
+pub const ENRICHMENT_COMPLETED: &str = "enrichment-completed";
+
+/// Sent whenever a job is queued, makes progress or finishes
+pub const JOB_PROGRESS: &str = "job-progress";
+
+/// Sent once the database is open, so windows waiting for it can load
+pub const DATABASE_OPENED: &str = "database-opened";
+
+/// Sent when the app is locked or unlocked, or the lock is set up or removed
+pub const LOCK_CHANGED: &str = "lock-changed";
+
+/// Sent when a plugin logs a message
+pub const PLUGIN_LOG: &str = "plugin-log";
+
+/// Handle used by code that runs without one, such as plugin host functions
+static APP_HANDLE: OnceLock<tauri::AppHandle> = OnceLock::new();
+
+/// What changed the history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryChange {
+    /// History files or another database were imported
+    Imported,
+    /// The browser extension sent visits
+    Captured,
+    /// An import was rolled back
+    RolledBack,
+    /// Titles, domains, devices or manual visits were edited
+    Edited,
+    /// Visits were deleted
+    Deleted,
+    /// Duplicate URLs were merged
+    Merged,
+    /// URLs were purged or redacted by the privacy rules
+    Purged,
+    /// An operation was undone
+    Undone,
+    /// Tags were assigned, removed, renamed or merged
+    Tagged,
+    /// Every piece of data was erased
+    Erased,
+}
+
+/// Payload of `history-updated`
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryUpdated {
+    /// What changed the history
+    pub change: HistoryChange,
+    /// When it changed
+    pub at: DateTime<Utc>,
+}
+
+/// Payload of `enrichment-completed`
+#[derive(Debug, Clone, Serialize)]
+pub struct EnrichmentCompleted {
+    /// Pages enriched by the run
+    pub enriched: usize,
+    /// Pages the run failed to enrich
+    pub failed: usize,
+    /// When the run finished
+    pub at: DateTime<Utc>,
+}
+
+/// Tells every window the history changed, and updates the tray badge
+pub fn emit_history_updated(app_handle: &tauri::AppHandle, change: HistoryChange) {
+    let _ = app_handle.emit_all(HISTORY_UPDATED, HistoryUpdated { change, at: Utc::now() });
+    crate::tray::refresh_badge(app_handle);
+}
+
+/// Payload of `plugin-log`
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginLog {
+    /// Plugin that logged the message
+    pub plugin_id: String,
+    /// The message
+    pub message: String,
+    /// When it was logged
+    pub at: DateTime<Utc>,
+}
+
+/// Keeps the app handle for events sent from code that has none
+pub fn set_app_handle(app_handle: tauri::AppHandle) {
+    let _ = APP_HANDLE.set(app_handle);
+}
+
+/// Tells every window a plugin logged a message; dropped before the app is set up
+pub fn emit_plugin_log(plugin_id: &str, message: &str) {
+    if let Some(app_handle) = APP_HANDLE.get() {
+        let _ = app_handle.emit_all(PLUGIN_LOG, PluginLog {
+            plugin_id: plugin_id.to_string(),
+            message: message.to_string(),
+            at: Utc::now(),
+        });
+    }
+}
+
+/// Tells every window an enrichment run finished
+pub fn emit_enrichment_completed(app_handle: &tauri::AppHandle, enriched: usize, failed: usize) {
+    let _ = app_handle.emit_all(ENRICHMENT_COMPLETED, EnrichmentCompleted { enriched, failed, at: Utc::now() });
+}
+
+/// Tells every window a job changed
+pub fn emit_job_progress(app_handle: &tauri::AppHandle, job: &Job) {
+    let _ = app_handle.emit_all(JOB_PROGRESS, job.clone());
+}
+
+/// Tells every window the database is open
+pub fn emit_database_opened(app_handle: &tauri::AppHandle) {
+    let _ = app_handle.emit_all(DATABASE_OPENED, ());
+}
+
+/// Tells every window the lock changed, and updates the tray badge
+pub fn emit_lock_changed(app_handle: &tauri::AppHandle, status: &LockStatus) {
+    let _ = app_handle.emit_all(LOCK_CHANGED, status.clone());
+    crate::tray::refresh_badge(app_handle);
+}
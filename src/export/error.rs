@@ -0,0 +1,89 @@
+// Export Error Handling
+// Defines error types for exporting history data
+
+use std::fmt;
+use std::error::Error;
+use std::io;
+
+use crate::db::DatabaseError;
+use crate::plugins::PluginError;
+
+/// Represents errors that can occur while exporting history
+#[derive(Debug)]
+pub enum ExportError {
+    /// Reading the history failed
+    Database(DatabaseError),
+    /// The export file could not be written
+    Io(io::Error),
+    /// Rows could not be encoded in the output format
+    Encoding(String),
+    /// An exporter plugin failed
+    Plugin(PluginError),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::Database(err) => write!(f, "Database error: {}", err),
+            ExportError::Io(err) => write!(f, "I/O error: {}", err),
+            ExportError::Encoding(msg) => write!(f, "Encoding error: {}", msg),
+            ExportError::Plugin(err) => write!(f, "Plugin error: {}", err),
+        }
+    }
+}
+
+impl Error for ExportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExportError::Database(err) => Some(err),
+            ExportError::Io(err) => Some(err),
+            ExportError::Plugin(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DatabaseError> for ExportError {
+    fn from(err: DatabaseError) -> Self {
+        ExportError::Database(err)
+    }
+}
+
+impl From<rusqlite::Error> for ExportError {
+    fn from(err: rusqlite::Error) -> Self {
+        ExportError::Database(DatabaseError::from(err))
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(err: io::Error) -> Self {
+        ExportError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(err: serde_json::Error) -> Self {
+        ExportError::Encoding(err.to_string())
+    }
+}
+
+impl From<parquet::errors::ParquetError> for ExportError {
+    fn from(err: parquet::errors::ParquetError) -> Self {
+        ExportError::Encoding(err.to_string())
+    }
+}
+
+impl From<arrow_schema::ArrowError> for ExportError {
+    fn from(err: arrow_schema::ArrowError) -> Self {
+        ExportError::Encoding(err.to_string())
+    }
+}
+
+impl From<PluginError> for ExportError {
+    fn from(err: PluginError) -> Self {
+        ExportError::Plugin(err)
+    }
+}
+
+/// Result type for export operations
+pub type Result<T> = std::result::Result<T, ExportError>;
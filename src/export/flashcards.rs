@@ -0,0 +1,66 @@
+// Export - Flashcards
+// Writes generated flashcards as an Anki-compatible tab separated deck
+
+use std::fs;
+use std::path::Path;
+
+use crate::db::DatabaseConnection;
+use crate::enrichment::flashcards::{get_flashcards, Flashcard};
+use super::error::Result;
+use super::{partial_path, ExportError, ExportSummary};
+
+/// Tag added to every exported card
+const DECK_TAG: &str = "browsing-history";
+
+/// Escapes text for an HTML field; tabs and line breaks would split the row
+fn html_field(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\t', " ")
+        .replace("\r\n", "<br>")
+        .replace(['\r', '\n'], "<br>")
+}
+
+/// Turns a category into an Anki tag, which can't contain spaces
+fn tag(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+/// Writes the cards as a deck Anki imports with File > Import: the question
+/// on the front, the answer with a link to the source page on the back
+pub fn write_flashcard_deck(cards: &[Flashcard]) -> String {
+    let mut deck = String::from("#separator:tab\n#html:true\n#tags column:3\n");
+    for card in cards {
+        let source = card.title.as_deref().map(str::trim).filter(|title| !title.is_empty()).unwrap_or(&card.url);
+        let back = format!(
+            "{}<br><br><a href=\"{}\">{}</a>",
+            html_field(&card.answer),
+            html_field(&card.url),
+            html_field(source),
+        );
+        let tags: Vec<String> = std::iter::once(DECK_TAG.to_string())
+            .chain(card.category.as_deref().map(tag))
+            .collect();
+        deck.push_str(&format!("{}\t{}\t{}\n", html_field(&card.question), back, tags.join(" ")));
+    }
+    deck
+}
+
+/// Exports every generated card to the `path` file as an Anki text deck
+pub fn export_flashcards(conn: &DatabaseConnection, path: &Path) -> Result<ExportSummary> {
+    let cards = get_flashcards(conn, None)?;
+
+    // Written next to the target first so a failed export leaves no half file
+    let partial = partial_path(path);
+    fs::write(&partial, write_flashcard_deck(&cards)).and_then(|_| fs::rename(&partial, path)).map_err(|e| {
+        let _ = fs::remove_file(&partial);
+        ExportError::from(e)
+    })?;
+
+    Ok(ExportSummary {
+        path: path.display().to_string(),
+        rows: cards.len(),
+    })
+}
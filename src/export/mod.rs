@@ -0,0 +1,255 @@
+// Export Module
+// Streams visits with their page data to JSON lines, CSV, Parquet or plugin
+// formats for analysis in tools like pandas or DuckDB, to daily-note outlines
+// and to flashcard decks
+
+// Module organization:
+// - writers.rs: One writer per output format
+// - outline.rs: Logseq and Roam daily-note outlines
+// - flashcards.rs: Anki decks of generated flashcards
+// - takeout.rs: Complete takeout and secure erasure of all data
+// - error.rs: Error handling
+
+pub mod writers;
+pub mod outline;
+pub mod flashcards;
+pub mod takeout;
+pub mod error;
+
+pub use error::{ExportError, Result};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::DatabaseConnection;
+use crate::db::query::QueryBuilder;
+use crate::plugins::PluginWriter;
+use writers::{CsvWriter, JsonLinesWriter, ParquetWriter, RowWriter};
+
+/// Number of rows written between progress reports
+const PROGRESS_INTERVAL: usize = 1000;
+
+/// Output format of an export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// One JSON object per line
+    JsonLines,
+    /// Comma separated values with a header row
+    Csv,
+    /// Apache Parquet
+    Parquet,
+    /// Format of the exporter plugin with this id
+    Plugin(String),
+}
+
+/// Filters selecting the visits to export
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportFilter {
+    /// Only visits at or after this time
+    pub start_date: Option<DateTime<Utc>>,
+    /// Only visits at or before this time
+    pub end_date: Option<DateTime<Utc>>,
+    /// Only visits to this domain
+    pub domain: Option<String>,
+    /// Only visits to URLs in this category
+    pub category: Option<String>,
+    /// Only visits to URLs with this tag
+    pub tag: Option<String>,
+    /// Only visits on this device
+    pub device_name: Option<String>,
+}
+
+/// One exported visit with its page data
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportRow {
+    /// Visit identifier
+    pub visit_id: String,
+    /// When the visit happened
+    pub visited_at: DateTime<Utc>,
+    /// URL identifier
+    pub url_id: String,
+    /// The visited address
+    pub url: String,
+    /// Page title
+    pub title: Option<String>,
+    /// Domain of the URL
+    pub domain: String,
+    /// URL category
+    pub category: Option<String>,
+    /// Device the visit was made on
+    pub device_name: Option<String>,
+    /// Time spent on the page, when known
+    pub duration_sec: Option<f64>,
+    /// History file the visit was imported from
+    pub source_file: String,
+    /// AI summary of the page
+    pub summary: Option<String>,
+    /// Page keywords
+    pub keywords: Vec<String>,
+    /// User tags of the page
+    pub tags: Vec<String>,
+}
+
+/// Progress of a running export
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportProgress {
+    /// Rows written so far
+    pub rows_written: usize,
+    /// Rows the export will write
+    pub total_rows: usize,
+}
+
+/// Outcome of an export
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportSummary {
+    /// File the rows were written to
+    pub path: String,
+    /// Number of rows written
+    pub rows: usize,
+}
+
+/// Builds the visit query for the filter
+fn export_query(filter: &ExportFilter) -> QueryBuilder {
+    // Keywords and tags are joined with "\u{1f}" so names containing commas survive
+    let mut query = QueryBuilder::new(
+        "SELECT v.id, v.visited_at, u.id, u.url, u.title, u.domain, u.category,
+                v.device_name, v.duration_sec, v.source_file, m.summary,
+                (SELECT group_concat(k.name, char(31)) FROM url_keyword uk JOIN keyword k ON k.id = uk.keyword_id WHERE uk.url_id = u.id),
+                (SELECT group_concat(t.name, char(31)) FROM url_tag ut JOIN tag t ON t.id = ut.tag_id WHERE ut.url_id = u.id)
+         FROM visit v
+         JOIN url u ON u.id = v.url_id
+         LEFT JOIN metadata m ON m.url_id = u.id"
+    );
+
+    query.date_range("v.visited_at", filter.start_date, filter.end_date);
+    if let Some(domain) = &filter.domain {
+        query.filter("u.domain = ?", domain.clone());
+    }
+    if let Some(category) = &filter.category {
+        query.filter("u.category = ?", category.clone());
+    }
+    if let Some(tag) = &filter.tag {
+        query.filter(
+            "EXISTS (SELECT 1 FROM url_tag ut JOIN tag t ON t.id = ut.tag_id WHERE ut.url_id = u.id AND t.name = ?)",
+            tag.clone(),
+        );
+    }
+    if let Some(device_name) = &filter.device_name {
+        query.filter("v.device_name = ?", device_name.clone());
+    }
+    query.order_by("v.visited_at, v.id");
+
+    query
+}
+
+/// Splits a list joined with the unit separator
+fn split_list(value: Option<String>) -> Vec<String> {
+    value.map(|v| v.split('\u{1f}').map(str::to_string).collect()).unwrap_or_default()
+}
+
+/// Maps a row of `export_query`
+fn row_from_sql(row: &rusqlite::Row) -> rusqlite::Result<ExportRow> {
+    Ok(ExportRow {
+        visit_id: row.get(0)?,
+        visited_at: DateTime::from_timestamp(row.get(1)?, 0).unwrap_or_default(),
+        url_id: row.get(2)?,
+        url: row.get(3)?,
+        title: row.get(4)?,
+        domain: row.get(5)?,
+        category: row.get(6)?,
+        device_name: row.get(7)?,
+        duration_sec: row.get(8)?,
+        source_file: row.get(9)?,
+        summary: row.get(10)?,
+        keywords: split_list(row.get(11)?),
+        tags: split_list(row.get(12)?),
+    })
+}
+
+/// Calls `f` with every visit matching the filter, in export order, until it
+/// returns false; returns how many rows it was given
+pub fn for_each_row(
+    conn: &DatabaseConnection,
+    filter: &ExportFilter,
+    mut f: impl FnMut(ExportRow) -> bool,
+) -> Result<usize> {
+    let query = export_query(filter);
+    let c = conn.get()?;
+
+    let mut stmt = c.prepare(&query.sql())?;
+    let mut rows = stmt.query(query.params().as_slice())?;
+    let mut given = 0;
+    while let Some(row) = rows.next()? {
+        given += 1;
+        if !f(row_from_sql(row)?) {
+            break;
+        }
+    }
+    Ok(given)
+}
+
+/// Path the export is written to before it is moved into place
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+/// Writes every visit matching the filter to `path`, reporting progress every
+/// few thousand rows. The file only appears once the export is complete.
+pub fn export_history(
+    conn: &DatabaseConnection,
+    format: ExportFormat,
+    filter: &ExportFilter,
+    path: &Path,
+    on_progress: &dyn Fn(&ExportProgress),
+) -> Result<ExportSummary> {
+    let partial = partial_path(path);
+    let query = export_query(filter);
+
+    let rows = conn.get().map_err(ExportError::from).and_then(|c| {
+        let total_rows = query.count(&c)?;
+        let mut progress = ExportProgress { rows_written: 0, total_rows };
+        on_progress(&progress);
+
+        let mut writer: Box<dyn RowWriter> = match format {
+            ExportFormat::JsonLines => Box::new(JsonLinesWriter::create(&partial)?),
+            ExportFormat::Csv => Box::new(CsvWriter::create(&partial)?),
+            ExportFormat::Parquet => Box::new(ParquetWriter::create(&partial)?),
+            ExportFormat::Plugin(id) => Box::new(PluginWriter::create(&id, &partial)?),
+        };
+
+        let mut stmt = c.prepare(&query.sql())?;
+        let mut rows = stmt.query(query.params().as_slice())?;
+        while let Some(row) = rows.next()? {
+            writer.write_row(&row_from_sql(row)?)?;
+            progress.rows_written += 1;
+
+            if progress.rows_written % PROGRESS_INTERVAL == 0 {
+                on_progress(&progress);
+            }
+        }
+
+        writer.finish()?;
+        on_progress(&progress);
+        Ok(progress.rows_written)
+    });
+
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            return Err(e);
+        },
+    };
+    fs::rename(&partial, path)?;
+
+    Ok(ExportSummary {
+        path: path.display().to_string(),
+        rows,
+    })
+}
@@ -0,0 +1,157 @@
+// Export - Outline
+// Daily-note outlines of visited pages for Logseq and Roam
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use chrono::{Datelike, NaiveDate};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::db::DatabaseConnection;
+use super::error::Result;
+use super::{export_query, partial_path, row_from_sql, ExportError, ExportFilter, ExportRow, ExportSummary};
+
+/// Output format of an outline export
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutlineFormat {
+    /// One Markdown journal page per day, named like Logseq's `2024_03_01.md`
+    Markdown,
+    /// A single EDN file of daily pages in Roam's import shape
+    Edn,
+}
+
+/// A page visited on a day, listed once however often it was visited
+struct OutlineEntry {
+    time: String,
+    title: String,
+    url: String,
+    tags: Vec<String>,
+}
+
+/// Groups the visits by day in `tz`, keeping the first visit of each URL per day
+fn group_by_day(rows: Vec<ExportRow>, tz: Tz) -> BTreeMap<NaiveDate, Vec<OutlineEntry>> {
+    let mut days: BTreeMap<NaiveDate, Vec<OutlineEntry>> = BTreeMap::new();
+
+    for row in rows {
+        let local = row.visited_at.with_timezone(&tz);
+        let entries = days.entry(local.date_naive()).or_default();
+        if entries.iter().any(|entry| entry.url == row.url) {
+            continue;
+        }
+
+        entries.push(OutlineEntry {
+            time: local.format("%H:%M").to_string(),
+            title: row.title.filter(|t| !t.trim().is_empty()).unwrap_or_else(|| row.url.clone()),
+            url: row.url,
+            tags: row.tags,
+        });
+    }
+
+    days
+}
+
+/// Formats a tag as an outliner page reference
+fn tag_ref(tag: &str) -> String {
+    if tag.chars().any(|c| c.is_whitespace() || c == '#') {
+        format!("#[[{}]]", tag)
+    } else {
+        format!("#{}", tag)
+    }
+}
+
+/// Formats an entry as the text of one bullet
+fn bullet_text(entry: &OutlineEntry) -> String {
+    // Brackets in titles would end the link text early
+    let title = entry.title.replace('[', "(").replace(']', ")");
+    let mut text = format!("{} [{}]({})", entry.time, title, entry.url);
+    for tag in &entry.tags {
+        text.push(' ');
+        text.push_str(&tag_ref(tag));
+    }
+    text
+}
+
+/// Title of a daily page as Roam names it, e.g. "March 1st, 2024"
+fn daily_page_title(date: NaiveDate) -> String {
+    let day = date.day();
+    let suffix = match (day % 10, day % 100) {
+        (1, 11) | (2, 12) | (3, 13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{} {}{}, {}", date.format("%B"), day, suffix, date.year())
+}
+
+/// Escapes a string for an EDN string literal
+fn edn_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Writes one journal page per day into the `dir` directory
+fn write_markdown(days: &BTreeMap<NaiveDate, Vec<OutlineEntry>>, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    for (date, entries) in days {
+        let page: String = entries.iter()
+            .map(|entry| format!("- {}\n", bullet_text(entry)))
+            .collect();
+        fs::write(dir.join(format!("{}.md", date.format("%Y_%m_%d"))), page)?;
+    }
+
+    Ok(())
+}
+
+/// Writes every day as a page in a single EDN file
+fn write_edn(days: &BTreeMap<NaiveDate, Vec<OutlineEntry>>, path: &Path) -> Result<()> {
+    let mut edn = String::from("[");
+    for (date, entries) in days {
+        edn.push_str(&format!("\n {{:title {}\n  :children [", edn_string(&daily_page_title(*date))));
+        for entry in entries {
+            edn.push_str(&format!("\n   {{:string {}}}", edn_string(&bullet_text(entry))));
+        }
+        edn.push_str("]}");
+    }
+    edn.push_str("]\n");
+
+    // Written next to the target first so a failed export leaves no half file
+    let partial = partial_path(path);
+    fs::write(&partial, edn).and_then(|_| fs::rename(&partial, path)).map_err(|e| {
+        let _ = fs::remove_file(&partial);
+        ExportError::from(e)
+    })
+}
+
+/// Exports the visits matching the filter as daily-note outlines, with days
+/// and times in `tz`. Markdown writes one page per day into the `path`
+/// directory; EDN writes the `path` file. The summary counts the pages listed
+/// across all days.
+pub fn export_outline(
+    conn: &DatabaseConnection,
+    format: OutlineFormat,
+    filter: &ExportFilter,
+    path: &Path,
+    tz: Tz,
+) -> Result<ExportSummary> {
+    let query = export_query(filter);
+    let rows = conn.with_connection(|c| {
+        let mut stmt = c.prepare(&query.sql())?;
+        let rows = stmt.query_map(query.params().as_slice(), row_from_sql)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    let days = group_by_day(rows, tz);
+    match format {
+        OutlineFormat::Markdown => write_markdown(&days, path)?,
+        OutlineFormat::Edn => write_edn(&days, path)?,
+    }
+
+    Ok(ExportSummary {
+        path: path.display().to_string(),
+        rows: days.values().map(Vec::len).sum(),
+    })
+}
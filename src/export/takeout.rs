@@ -0,0 +1,139 @@
+// Export - Takeout
+// Complete archive of everything the app stores, and secure erasure of it
+
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde::Serialize;
+
+use crate::db::{self, DatabaseConnection};
+use super::error::Result;
+use super::{export_history, ExportError, ExportFilter, ExportFormat};
+
+/// Directories of the app data directory copied into a takeout
+const DATA_DIRECTORIES: &[&str] = &["archives", "thumbnails"];
+
+/// Contents of a takeout, also written to its `manifest.json`
+#[derive(Debug, Clone, Serialize)]
+pub struct TakeoutSummary {
+    /// Takeout directory
+    pub path: String,
+    /// When the takeout was made
+    pub created_at: DateTime<Utc>,
+    /// Schema version of the database copy
+    pub schema_version: i32,
+    /// Whether the database copy is encrypted with the database passphrase
+    pub encrypted: bool,
+    /// Visits written to `visits.jsonl`
+    pub visits: usize,
+    /// Files in the takeout, relative to its directory
+    pub files: Vec<String>,
+}
+
+/// Outcome of an erase
+#[derive(Debug, Clone, Serialize)]
+pub struct EraseSummary {
+    /// Files overwritten and removed
+    pub files_erased: usize,
+    /// Bytes overwritten
+    pub bytes_erased: u64,
+}
+
+/// Copies a directory tree, returning the copied files relative to `root`
+fn copy_dir(from: &Path, to: &Path, root: &Path, files: &mut Vec<String>) -> Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target, root, files)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+            files.push(target.strip_prefix(root).unwrap_or(&target).display().to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a complete takeout into the empty or missing `path` directory: a copy
+/// of the database, every visit as JSON lines, the settings, archived pages and
+/// thumbnails, and a manifest listing them
+pub fn export_everything(conn: &DatabaseConnection, data_dir: &Path, path: &Path) -> Result<TakeoutSummary> {
+    if path.exists() && fs::read_dir(path)?.next().is_some() {
+        return Err(ExportError::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} is not empty", path.display()),
+        )));
+    }
+    fs::create_dir_all(path)?;
+
+    let mut files = Vec::new();
+
+    // VACUUM INTO writes a consistent copy even while the WAL holds changes
+    let database = path.join("history.db");
+    let (schema_version, settings) = conn.with_connection(|c| {
+        c.execute("VACUUM INTO ?", params![database.display().to_string()])?;
+        Ok((db::migrations::get_schema_version(c)?, db::settings::list_settings(c)?))
+    })?;
+    files.push("history.db".to_string());
+
+    let visits = export_history(conn, ExportFormat::JsonLines, &ExportFilter::default(), &path.join("visits.jsonl"), &|_| {})?;
+    files.push("visits.jsonl".to_string());
+
+    fs::write(path.join("settings.json"), serde_json::to_string_pretty(&settings)?)?;
+    files.push("settings.json".to_string());
+
+    for name in DATA_DIRECTORIES {
+        let dir = data_dir.join(name);
+        if dir.is_dir() {
+            copy_dir(&dir, &path.join(name), path, &mut files)?;
+        }
+    }
+
+    let summary = TakeoutSummary {
+        path: path.display().to_string(),
+        created_at: Utc::now(),
+        schema_version,
+        encrypted: db::encryption::is_encrypted_file(&database)?,
+        visits: visits.rows,
+        files,
+    };
+    fs::write(path.join("manifest.json"), serde_json::to_string_pretty(&summary)?)?;
+
+    Ok(summary)
+}
+
+/// Wipes every file under `dir` and removes its subdirectories, keeping `dir` itself
+fn wipe_dir(dir: &Path, summary: &mut EraseSummary) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            wipe_dir(&path, summary)?;
+            fs::remove_dir(&path)?;
+        } else {
+            summary.bytes_erased += db::maintenance::wipe_file(&path)?;
+            summary.files_erased += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// Securely erases everything in the app data directory: the database and its
+/// WAL, archived pages, thumbnails and any other cache. The database must be
+/// closed first. The keychain copy of the database passphrase is removed too.
+pub fn erase_all_data(data_dir: &Path) -> Result<EraseSummary> {
+    let mut summary = EraseSummary { files_erased: 0, bytes_erased: 0 };
+
+    if data_dir.is_dir() {
+        wipe_dir(data_dir, &mut summary)?;
+    }
+    db::encryption::delete_key()?;
+
+    Ok(summary)
+}
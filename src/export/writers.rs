@@ -0,0 +1,209 @@
+// Export - Writers
+// Encoders for each export format, fed one row at a time
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampSecondArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use super::error::Result;
+use super::ExportRow;
+
+/// Rows buffered per Parquet row group
+const PARQUET_BATCH_SIZE: usize = 8192;
+
+/// Separator of keyword and tag lists in CSV and Parquet columns
+const LIST_SEPARATOR: &str = "; ";
+
+/// Column names shared by the CSV header and the Parquet schema
+const COLUMNS: &[&str] = &[
+    "visit_id", "visited_at", "url_id", "url", "title", "domain", "category",
+    "device_name", "duration_sec", "source_file", "summary", "keywords", "tags",
+];
+
+/// Writes export rows in one format
+pub trait RowWriter {
+    /// Writes a single row
+    fn write_row(&mut self, row: &ExportRow) -> Result<()>;
+
+    /// Flushes buffered rows and closes the file
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Writes one JSON object per line
+pub struct JsonLinesWriter {
+    out: BufWriter<File>,
+}
+
+impl JsonLinesWriter {
+    /// Creates the output file
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self { out: BufWriter::new(File::create(path)?) })
+    }
+}
+
+impl RowWriter for JsonLinesWriter {
+    fn write_row(&mut self, row: &ExportRow) -> Result<()> {
+        serde_json::to_writer(&mut self.out, row)?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes comma separated values with a header row (RFC 4180 quoting)
+pub struct CsvWriter {
+    out: BufWriter<File>,
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl CsvWriter {
+    /// Creates the output file and writes the header
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{}", COLUMNS.join(","))?;
+        Ok(Self { out })
+    }
+}
+
+impl RowWriter for CsvWriter {
+    fn write_row(&mut self, row: &ExportRow) -> Result<()> {
+        let fields = [
+            row.visit_id.clone(),
+            row.visited_at.to_rfc3339(),
+            row.url_id.clone(),
+            row.url.clone(),
+            row.title.clone().unwrap_or_default(),
+            row.domain.clone(),
+            row.category.clone().unwrap_or_default(),
+            row.device_name.clone().unwrap_or_default(),
+            row.duration_sec.map(|d| d.to_string()).unwrap_or_default(),
+            row.source_file.clone(),
+            row.summary.clone().unwrap_or_default(),
+            row.keywords.join(LIST_SEPARATOR),
+            row.tags.join(LIST_SEPARATOR),
+        ];
+
+        let line: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        writeln!(self.out, "{}", line.join(","))?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes a Parquet file in row groups of `PARQUET_BATCH_SIZE` rows
+pub struct ParquetWriter {
+    schema: SchemaRef,
+    writer: ArrowWriter<File>,
+    buffer: Vec<ExportRow>,
+}
+
+/// Schema of the Parquet export; visited_at is a UTC timestamp
+fn parquet_schema() -> SchemaRef {
+    let nullable = ["title", "category", "device_name", "duration_sec", "summary", "keywords", "tags"];
+
+    Arc::new(Schema::new(COLUMNS.iter()
+        .map(|name| {
+            let data_type = match *name {
+                "visited_at" => DataType::Timestamp(TimeUnit::Second, Some("UTC".into())),
+                "duration_sec" => DataType::Float64,
+                _ => DataType::Utf8,
+            };
+            Field::new(*name, data_type, nullable.contains(name))
+        })
+        .collect::<Vec<_>>()))
+}
+
+/// Builds a string column from the buffered rows
+fn string_column<F>(rows: &[ExportRow], f: F) -> ArrayRef
+where
+    F: Fn(&ExportRow) -> Option<String>,
+{
+    Arc::new(rows.iter().map(f).collect::<StringArray>())
+}
+
+/// Joins a list column, NULL when empty
+fn list_value(values: &[String]) -> Option<String> {
+    (!values.is_empty()).then(|| values.join(LIST_SEPARATOR))
+}
+
+impl ParquetWriter {
+    /// Creates the output file
+    pub fn create(path: &Path) -> Result<Self> {
+        let schema = parquet_schema();
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = ArrowWriter::try_new(File::create(path)?, schema.clone(), Some(properties))?;
+
+        Ok(Self { schema, writer, buffer: Vec::with_capacity(PARQUET_BATCH_SIZE) })
+    }
+
+    /// Writes the buffered rows as one record batch
+    fn flush_batch(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let rows = &self.buffer;
+        let columns: Vec<ArrayRef> = vec![
+            string_column(rows, |r| Some(r.visit_id.clone())),
+            Arc::new(TimestampSecondArray::from(rows.iter().map(|r| r.visited_at.timestamp()).collect::<Vec<_>>())
+                .with_timezone("UTC")),
+            string_column(rows, |r| Some(r.url_id.clone())),
+            string_column(rows, |r| Some(r.url.clone())),
+            string_column(rows, |r| r.title.clone()),
+            string_column(rows, |r| Some(r.domain.clone())),
+            string_column(rows, |r| r.category.clone()),
+            string_column(rows, |r| r.device_name.clone()),
+            Arc::new(rows.iter().map(|r| r.duration_sec).collect::<Float64Array>()),
+            string_column(rows, |r| Some(r.source_file.clone())),
+            string_column(rows, |r| r.summary.clone()),
+            string_column(rows, |r| list_value(&r.keywords)),
+            string_column(rows, |r| list_value(&r.tags)),
+        ];
+
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl RowWriter for ParquetWriter {
+    fn write_row(&mut self, row: &ExportRow) -> Result<()> {
+        self.buffer.push(row.clone());
+        if self.buffer.len() >= PARQUET_BATCH_SIZE {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.flush_batch()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+// Safari History Extractor - Fingerprints
+// Content fingerprints of history databases, so a copy of a History.db
+// imported from another path is recognized as the same source
+//
+// Safari numbers visits itself and stores their times with sub-second
+// precision, so a visit's (row id, raw time) pair only recurs in copies of the
+// same database; another device's history holds the same visits under other
+// ids. A fixed share of those pairs, picked by hash, is kept as the
+// fingerprint. Copies taken at different times hold the same sample wherever
+// their visits overlap, even after Safari added or expired visits.
+
+use std::path::Path;
+
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::error::Result;
+
+/// One visit in this many is sampled
+const SAMPLE_RATE: u64 = 32;
+
+/// Fewest samples two fingerprints must both cover before they are compared
+const MIN_SHARED_SAMPLES: usize = 4;
+
+/// Share of the covered samples that must match for two files to be copies
+const MATCH_THRESHOLD: f64 = 0.9;
+
+/// Sampled visits of a history database
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceFingerprint {
+    /// (Safari visit id, hash of the visit) of the sampled visits, by id
+    pub samples: Vec<(i64, u64)>,
+    /// Visits in the database
+    pub visits: u64,
+}
+
+impl SourceFingerprint {
+    /// Lowest and highest sampled visit id
+    fn id_range(&self) -> Option<(i64, i64)> {
+        Some((self.samples.first()?.0, self.samples.last()?.0))
+    }
+
+    /// Share of the samples both fingerprints cover that are identical, or
+    /// None when they cover too few visits in common to tell
+    pub fn overlap(&self, other: &SourceFingerprint) -> Option<f64> {
+        let ((low_a, high_a), (low_b, high_b)) = (self.id_range()?, other.id_range()?);
+        let (low, high) = (low_a.max(low_b), high_a.min(high_b));
+        if low > high {
+            return None;
+        }
+
+        let covered = |fingerprint: &SourceFingerprint| -> Vec<(i64, u64)> {
+            fingerprint.samples.iter().copied().filter(|(id, _)| *id >= low && *id <= high).collect()
+        };
+        let (mine, theirs) = (covered(self), covered(other));
+        let fewest = mine.len().min(theirs.len());
+        if fewest < MIN_SHARED_SAMPLES {
+            return None;
+        }
+        let shared = mine.iter().filter(|sample| theirs.binary_search(sample).is_ok()).count();
+        Some(shared as f64 / fewest as f64)
+    }
+
+    /// Returns true if both fingerprints come from copies of one database
+    pub fn same_source(&self, other: &SourceFingerprint) -> bool {
+        self.overlap(other).is_some_and(|overlap| overlap >= MATCH_THRESHOLD)
+    }
+}
+
+/// Hash of a visit's id and raw time
+fn visit_hash(id: i64, visit_time: f64) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(id.to_le_bytes())
+        .chain_update(visit_time.to_bits().to_le_bytes())
+        .finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("digest has 8 bytes"))
+}
+
+/// Fingerprints a Safari history database without changing it
+pub fn fingerprint_history_db(path: &Path) -> Result<SourceFingerprint> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+
+    let mut stmt = conn.prepare("SELECT id, visit_time FROM history_visits ORDER BY id")?;
+    let mut rows = stmt.query([])?;
+
+    let mut fingerprint = SourceFingerprint::default();
+    while let Some(row) = rows.next()? {
+        let (id, visit_time): (i64, f64) = (row.get(0)?, row.get(1)?);
+        fingerprint.visits += 1;
+
+        let hash = visit_hash(id, visit_time);
+        if hash.is_multiple_of(SAMPLE_RATE) {
+            fingerprint.samples.push((id, hash));
+        }
+    }
+    Ok(fingerprint)
+}
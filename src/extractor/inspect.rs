@@ -0,0 +1,167 @@
+// Safari History Extractor - Inspection
+// Quick checks of dropped files before they are imported: whether each is a
+// SQLite database, which browser wrote it and roughly how much it holds
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+
+/// First bytes of every SQLite 3 database file
+const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+/// Browser whose history schema a database matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrowserKind {
+    /// Safari's History.db
+    Safari,
+    /// Chrome, Edge, Brave and other Chromium browsers' History file
+    Chromium,
+    /// Firefox's places.sqlite
+    Firefox,
+}
+
+impl BrowserKind {
+    /// Tables holding the URLs and the visits, in that order
+    fn tables(self) -> (&'static str, &'static str) {
+        match self {
+            BrowserKind::Safari => ("history_items", "history_visits"),
+            BrowserKind::Chromium => ("urls", "visits"),
+            BrowserKind::Firefox => ("moz_places", "moz_historyvisits"),
+        }
+    }
+}
+
+/// What was found out about a dropped file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileInspection {
+    /// Path as dropped
+    pub path: String,
+    /// File name, for display
+    pub file_name: String,
+    /// File size in bytes
+    pub size_bytes: Option<u64>,
+    /// The file starts with the SQLite header
+    pub is_sqlite: bool,
+    /// Browser whose schema the database matches
+    pub browser: Option<BrowserKind>,
+    /// Approximate number of URLs
+    pub estimated_urls: Option<u64>,
+    /// Approximate number of visits
+    pub estimated_visits: Option<u64>,
+    /// The file can be imported as it is
+    pub importable: bool,
+    /// Why the file can't be imported
+    pub problem: Option<String>,
+}
+
+/// Returns true if the file starts with the SQLite header
+fn has_sqlite_magic(path: &Path) -> std::io::Result<bool> {
+    let mut header = [0u8; 16];
+    let mut file = File::open(path)?;
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(&header == SQLITE_MAGIC),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns true if the database has the table
+fn has_table(conn: &Connection, table: &str) -> bool {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)",
+        [table],
+        |row| row.get(0),
+    ).unwrap_or(false)
+}
+
+/// Estimates a table's rows from its largest rowid, which avoids scanning
+/// large histories; deleted rows make it an overestimate
+fn estimate_rows(conn: &Connection, table: &str) -> Option<u64> {
+    // Table names come from `BrowserKind::tables`, never from the file
+    conn.query_row(&format!("SELECT COALESCE(MAX(rowid), 0) FROM {}", table), [], |row| row.get::<_, i64>(0))
+        .ok()
+        .map(|rows| rows.max(0) as u64)
+}
+
+/// Inspects one file; problems are reported in the result, never as an error
+pub fn inspect_file(path: &Path) -> FileInspection {
+    let mut inspection = FileInspection {
+        path: path.display().to_string(),
+        file_name: path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+        size_bytes: None,
+        is_sqlite: false,
+        browser: None,
+        estimated_urls: None,
+        estimated_visits: None,
+        importable: false,
+        problem: None,
+    };
+
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        Ok(_) => {
+            inspection.problem = Some("Not a file".to_string());
+            return inspection;
+        },
+        Err(e) => {
+            inspection.problem = Some(format!("Cannot read the file: {}", e));
+            return inspection;
+        },
+    };
+    inspection.size_bytes = Some(metadata.len());
+
+    match has_sqlite_magic(path) {
+        Ok(true) => inspection.is_sqlite = true,
+        Ok(false) => {
+            inspection.problem = Some("Not a SQLite database".to_string());
+            return inspection;
+        },
+        Err(e) => {
+            inspection.problem = Some(format!("Cannot read the file: {}", e));
+            return inspection;
+        },
+    }
+
+    // Read-only, so inspecting never changes or locks the browser's file
+    let conn = match Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX) {
+        Ok(conn) => conn,
+        Err(e) => {
+            inspection.problem = Some(format!("Cannot open the database: {}", e));
+            return inspection;
+        },
+    };
+
+    let browser = [BrowserKind::Safari, BrowserKind::Chromium, BrowserKind::Firefox].into_iter()
+        .find(|browser| {
+            let (urls, visits) = browser.tables();
+            has_table(&conn, urls) && has_table(&conn, visits)
+        });
+    let browser = match browser {
+        Some(browser) => browser,
+        None => {
+            inspection.problem = Some("Not a browser history database".to_string());
+            return inspection;
+        },
+    };
+
+    let (urls, visits) = browser.tables();
+    inspection.browser = Some(browser);
+    inspection.estimated_urls = estimate_rows(&conn, urls);
+    inspection.estimated_visits = estimate_rows(&conn, visits);
+
+    if browser == BrowserKind::Safari {
+        inspection.importable = true;
+    } else {
+        inspection.problem = Some("Only Safari history can be imported so far".to_string());
+    }
+    inspection
+}
+
+/// Inspects every dropped file, in order
+pub fn inspect_files<P: AsRef<Path>>(paths: &[P]) -> Vec<FileInspection> {
+    paths.iter().map(|path| inspect_file(path.as_ref())).collect()
+}
@@ -3,13 +3,19 @@
 
 // We'll organize this module into:
 // - safari.rs: Safari-specific parsing logic
+// - inspect.rs: Checks of files before they are imported
+// - fingerprint.rs: Content fingerprints recognizing copies of one database
 // - models.rs: Data models for extraction
 // - error.rs: Error handling
 
 pub mod safari;
+pub mod inspect;
+pub mod fingerprint;
 pub mod models;
 pub mod error;
 
-pub use safari::{extract_history, parse_history_db};
-pub use models::{Visit, Url, RawHistoryData, ExtractionSource};
+pub use safari::{extract_history, extract_history_since, parse_history_db, parse_history_db_since};
+pub use models::{Campaign, Visit, Url, RawHistoryData, ExtractionSource};
 pub use error::ExtractionError;
+pub use inspect::{inspect_files, BrowserKind, FileInspection};
+pub use fingerprint::{fingerprint_history_db, SourceFingerprint};
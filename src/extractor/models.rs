@@ -5,6 +5,7 @@ use std::path::PathBuf;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use serde::{Serialize, Deserialize};
+use url::Url as UrlParser;
 
 /// Represents a visit to a URL extracted from Safari history
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +24,9 @@ pub struct Visit {
     pub device_name: Option<String>,
     /// Optional duration of the visit in seconds
     pub duration_sec: Option<f64>,
+    /// Page the visit was opened from, if the browser reported it
+    #[serde(default)]
+    pub referrer: Option<String>,
 }
 
 /// Represents a URL from the Safari history
@@ -40,6 +44,52 @@ pub struct Url {
     pub first_seen: DateTime<Utc>,
     /// When this URL was last seen
     pub last_seen: DateTime<Utc>,
+    /// Campaign parameters of the URL, read before privacy rules strip them
+    #[serde(default)]
+    pub campaign: Option<Campaign>,
+}
+
+/// The utm_* parameters of a link. Source and medium are lowercased so
+/// "Newsletter" and "newsletter" count as one.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Campaign {
+    /// utm_source, e.g. a newsletter or platform
+    pub source: Option<String>,
+    /// utm_medium, e.g. email or social
+    pub medium: Option<String>,
+    /// utm_campaign
+    pub name: Option<String>,
+    /// utm_term
+    pub term: Option<String>,
+    /// utm_content
+    pub content: Option<String>,
+}
+
+impl Campaign {
+    /// Reads the campaign parameters of a URL; None if it has no source,
+    /// medium or campaign name
+    pub fn from_url(url: &str) -> Option<Self> {
+        let parsed = UrlParser::parse(url).ok()?;
+        let mut campaign = Campaign::default();
+        for (key, value) in parsed.query_pairs() {
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            match key.to_lowercase().as_str() {
+                "utm_source" => campaign.source = Some(value.to_lowercase()),
+                "utm_medium" => campaign.medium = Some(value.to_lowercase()),
+                "utm_campaign" => campaign.name = Some(value.to_string()),
+                "utm_term" => campaign.term = Some(value.to_string()),
+                "utm_content" => campaign.content = Some(value.to_string()),
+                _ => {},
+            }
+        }
+        if campaign.source.is_none() && campaign.medium.is_none() && campaign.name.is_none() {
+            return None;
+        }
+        Some(campaign)
+    }
 }
 
 /// Information about the source of the extraction
@@ -8,7 +8,7 @@ use uuid::Uuid;
 use url::Url as UrlParser;
 use std::collections::HashMap;
 
-use super::models::{RawHistoryData, Visit, Url, ExtractionSource};
+use super::models::{Campaign, RawHistoryData, Visit, Url, ExtractionSource};
 use super::error::{ExtractionError, Result, FailedFile};
 
 // Safari stores visit timestamps as macOS time (seconds since Jan 1, 2001)
@@ -19,6 +19,17 @@ const MAC_TO_UNIX_EPOCH_OFFSET: i64 = 978307200;
 pub fn extract_history(
     file_path: &Path, 
     device_name: Option<String>
+) -> Result<RawHistoryData> {
+    extract_history_since(file_path, device_name, None)
+}
+
+/// Extracts only the visits made at or after `since` (everything when None),
+/// along with the URLs they belong to, so a file imported before can be
+/// re-read incrementally
+pub fn extract_history_since(
+    file_path: &Path,
+    device_name: Option<String>,
+    since: Option<DateTime<Utc>>
 ) -> Result<RawHistoryData> {
     let path_buf = file_path.to_path_buf();
     
@@ -39,11 +50,14 @@ pub fn extract_history(
     // First, verify this is a Safari history database
     verify_safari_schema(&conn)?;
     
+    // Safari compares visit times in macOS time
+    let since_mac = since.map(|since| since.timestamp() - MAC_TO_UNIX_EPOCH_OFFSET);
+    
     // Extract URLs and build a mapping of Safari's IDs to our UUIDs
-    let url_id_map = extract_urls(&conn, &mut history_data)?;
+    let url_id_map = extract_urls(&conn, &mut history_data, since_mac)?;
     
     // Extract visits using the URL mapping
-    extract_visits(&conn, &mut history_data, &url_id_map)?;
+    extract_visits(&conn, &mut history_data, &url_id_map, since_mac)?;
     
     Ok(history_data)
 }
@@ -72,10 +86,12 @@ fn verify_safari_schema(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
-/// Extracts URLs from the history_items table
+/// Extracts URLs from the history_items table, limited to those visited at
+/// or after `since_mac` when given
 fn extract_urls(
     conn: &Connection,
-    history_data: &mut RawHistoryData
+    history_data: &mut RawHistoryData,
+    since_mac: Option<i64>
 ) -> Result<HashMap<i64, Uuid>> {
     let mut url_id_map = HashMap::new();
     
@@ -84,10 +100,12 @@ fn extract_urls(
                visit_time + 0 as first_visit, 
                last_visited_time + 0 as last_visit
         FROM history_items
+        WHERE ?1 IS NULL
+           OR id IN (SELECT history_item FROM history_visits WHERE visit_time >= ?1)
     ";
     
     let mut stmt = conn.prepare(query)?;
-    let url_rows = stmt.query_map([], |row| Ok(row))?;
+    let url_rows = stmt.query_map([since_mac], |row| Ok(row))?;
     
     for url_result in url_rows {
         let row = url_result?;
@@ -131,6 +149,7 @@ fn process_url_row(
     // Create the URL object
     let url = Url {
         id: url_uuid,
+        campaign: Campaign::from_url(&url_str),
         url: url_str,
         title,
         domain,
@@ -144,20 +163,23 @@ fn process_url_row(
     Ok((safari_id, url_uuid))
 }
 
-/// Extracts visits from the history_visits table
+/// Extracts visits from the history_visits table, limited to those at or
+/// after `since_mac` when given
 fn extract_visits(
     conn: &Connection,
     history_data: &mut RawHistoryData,
-    url_id_map: &HashMap<i64, Uuid>
+    url_id_map: &HashMap<i64, Uuid>,
+    since_mac: Option<i64>
 ) -> Result<()> {
     let query = "
         SELECT id, history_item, visit_time + 0 as visit_time
         FROM history_visits
+        WHERE ?1 IS NULL OR visit_time >= ?1
         ORDER BY visit_time DESC
     ";
     
     let mut stmt = conn.prepare(query)?;
-    let visit_rows = stmt.query_map([], |row| Ok(row))?;
+    let visit_rows = stmt.query_map([since_mac], |row| Ok(row))?;
     
     // Get source file name for tracking
     let source_file = history_data.source.file_path.to_string_lossy().to_string();
@@ -209,6 +231,7 @@ fn process_visit_row(
         source_file: source_file.to_string(),
         device_name: history_data.source.device_name.clone(),
         duration_sec: None, // Safari doesn't track duration directly
+        referrer: None,
     };
     
     // Add to our collection
@@ -253,6 +276,15 @@ fn extract_domain(url_str: &str) -> Result<String> {
 pub fn parse_history_db(
     file_paths: &[PathBuf],
     device_names: Option<&[String]>
+) -> (Vec<RawHistoryData>, Vec<FailedFile>) {
+    parse_history_db_since(file_paths, device_names, None)
+}
+
+/// Parses Safari history.db files, keeping only visits at or after `since`
+pub fn parse_history_db_since(
+    file_paths: &[PathBuf],
+    device_names: Option<&[String]>,
+    since: Option<DateTime<Utc>>
 ) -> (Vec<RawHistoryData>, Vec<FailedFile>) {
     let mut successful = Vec::new();
     let mut failed = Vec::new();
@@ -262,7 +294,7 @@ pub fn parse_history_db(
         let device_name = device_names
             .and_then(|names| names.get(i).cloned());
             
-        match extract_history(file_path, device_name) {
+        match extract_history_since(file_path, device_name, since) {
             Ok(data) => successful.push(data),
             Err(err) => failed.push(FailedFile::new(file_path.clone(), err)),
         }
@@ -100,6 +100,22 @@ mod tests {
         assert!(result.is_err());
     }
     
+    #[test]
+    fn test_campaign_from_url() {
+        let campaign = Campaign::from_url(
+            "https://example.com/post?utm_source=Weekly%20Digest&utm_medium=Email&utm_campaign=Issue+42&id=7"
+        ).expect("Campaign parameters not found");
+        
+        assert_eq!(campaign.source.as_deref(), Some("weekly digest"));
+        assert_eq!(campaign.medium.as_deref(), Some("email"));
+        assert_eq!(campaign.name.as_deref(), Some("Issue 42"));
+        assert_eq!(campaign.term, None);
+        
+        // Only a term or content, or empty values, are not a campaign
+        assert_eq!(Campaign::from_url("https://example.com/?utm_term=rust&utm_source="), None);
+        assert_eq!(Campaign::from_url("https://example.com/?id=7"), None);
+    }
+    
     #[test]
     fn test_verify_safari_schema() {
         // Test schema verification with valid mock database
@@ -162,6 +178,23 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_extract_history_since() {
+        let (db_path, conn) = create_mock_safari_db();
+        insert_mock_data(&conn);
+        drop(conn);
+
+        // Only the visits to test.org pages happened at or after this time
+        let since = mac_to_utc(662860800).expect("Timestamp conversion failed");
+        let history_data = extract_history_since(&db_path, None, Some(since))
+            .expect("Incremental extraction failed");
+
+        assert_eq!(history_data.urls.len(), 2);
+        assert_eq!(history_data.visits.len(), 3);
+        assert!(history_data.urls.iter().all(|u| u.domain == "test.org"));
+        assert!(history_data.warnings.is_empty());
+    }
+
     #[test]
     fn test_parse_history_db_multiple_files() {
         // Test handling of multiple files, including an invalid one
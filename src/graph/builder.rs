@@ -0,0 +1,380 @@
+// Knowledge Graph - Builder
+// Populates the node and edge tables from the history and metadata tables
+
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection};
+
+use crate::db::{DatabaseConnection, DatabaseError, Result};
+use crate::db::domains::refresh_domain_stats;
+use crate::db::trackers::tracker_settings;
+use super::models::{NodeType, EdgeType, GraphStats};
+use super::covisitation::insert_covisitation_edges;
+
+/// Snapshot id of the main (all-time) graph
+pub const MAIN_GRAPH: i64 = 0;
+
+/// Visits further apart than this start a new session
+pub const SESSION_GAP_SECS: i64 = 30 * 60;
+
+/// Consecutive visits closer than this are treated as one page leading to the next
+pub(crate) const LINK_WINDOW_SECS: i64 = 5 * 60;
+
+/// Number of most-visited pages of a topic every other page in it is linked to
+const SAME_TOPIC_NEIGHBOURS: i64 = 5;
+
+/// The slice of history a graph is built from
+#[derive(Debug, Clone, Copy)]
+pub struct GraphScope {
+    /// Snapshot the built nodes and edges belong to
+    pub snapshot_id: i64,
+    /// Earliest visit timestamp included
+    pub start: i64,
+    /// Latest visit timestamp included
+    pub end: i64,
+}
+
+impl GraphScope {
+    /// Scope of the main graph, covering the whole history
+    pub fn full() -> Self {
+        Self { snapshot_id: MAIN_GRAPH, start: i64::MIN, end: i64::MAX }
+    }
+}
+
+/// Rebuilds the whole knowledge graph from existing history and metadata
+pub fn rebuild_graph(conn: &DatabaseConnection) -> Result<GraphStats> {
+    conn.transaction(|tx| build_graph(tx, GraphScope::full()))
+}
+
+/// Replaces the nodes and edges of a scope's snapshot with ones built from its visits
+pub(crate) fn build_graph(tx: &Connection, scope: GraphScope) -> Result<GraphStats> {
+    tx.execute("DELETE FROM edge WHERE snapshot_id = ?", [scope.snapshot_id])?;
+    tx.execute("DELETE FROM node WHERE snapshot_id = ?", [scope.snapshot_id])?;
+
+    insert_base_nodes(tx, &scope)?;
+    insert_base_edges(tx, &scope)?;
+    insert_entities(tx, &scope)?;
+    insert_sessions(tx, &scope)?;
+    insert_covisitation_edges(tx, &scope)?;
+
+    if tracker_settings(tx)?.exclude_from_graph {
+        remove_tracker_nodes(tx, &scope)?;
+    }
+    insert_organizations(tx, &scope)?;
+    apply_wikidata_ids(tx, Some(scope.snapshot_id))?;
+
+    graph_stats(tx, scope.snapshot_id)
+}
+
+/// Removes the nodes of tracker domains and their pages; their edges go with them
+fn remove_tracker_nodes(tx: &Connection, scope: &GraphScope) -> Result<()> {
+    refresh_domain_stats(tx)?;
+
+    tx.execute(
+        "DELETE FROM node
+         WHERE snapshot_id = ?1
+           AND ((node_type = 'domain' AND key IN (SELECT domain FROM domain_stats WHERE tracker IS NOT NULL))
+             OR (node_type = 'url' AND key IN (
+                 SELECT u.id FROM url u JOIN domain_stats d ON d.domain = u.domain
+                 WHERE d.tracker IS NOT NULL
+             )))",
+        [scope.snapshot_id],
+    ).map_err(|e| DatabaseError::Query(format!("Failed to remove tracker nodes: {}", e)))?;
+
+    Ok(())
+}
+
+/// Copies the Wikidata identifiers of entities and domains onto their nodes,
+/// in one snapshot or all of them
+pub(crate) fn apply_wikidata_ids(tx: &Connection, snapshot_id: Option<i64>) -> Result<()> {
+    tx.execute(
+        "UPDATE node SET wikidata_id = (
+             SELECT w.qid FROM wikidata_link w WHERE w.node_type = node.node_type AND w.key = node.key
+         )
+         WHERE node_type IN ('entity', 'domain') AND (?1 IS NULL OR snapshot_id = ?1)",
+        [snapshot_id],
+    ).map_err(|e| DatabaseError::Query(format!("Failed to apply Wikidata identifiers: {}", e)))?;
+
+    Ok(())
+}
+
+/// Inserts a node for each organization owning domains in the graph, weighted
+/// by their visits, with owned-by edges grouping its domains under it
+fn insert_organizations(tx: &Connection, scope: &GraphScope) -> Result<()> {
+    let map_err = |e: rusqlite::Error| DatabaseError::Query(format!("Failed to insert organization nodes: {}", e));
+    refresh_domain_stats(tx)?;
+
+    tx.execute(
+        "INSERT INTO node (snapshot_id, node_type, key, label, weight)
+         SELECT ?1, 'organization', d.organization, d.organization, SUM(dn.weight)
+         FROM node dn
+         JOIN domain_stats d ON d.domain = dn.key
+         WHERE dn.snapshot_id = ?1 AND dn.node_type = 'domain' AND d.organization IS NOT NULL
+         GROUP BY d.organization",
+        [scope.snapshot_id],
+    ).map_err(map_err)?;
+
+    tx.execute(
+        "INSERT INTO edge (snapshot_id, source_id, target_id, edge_type, weight)
+         SELECT ?1, dn.id, orgn.id, 'owned_by', MAX(dn.weight, 1)
+         FROM node dn
+         JOIN domain_stats d ON d.domain = dn.key
+         JOIN node orgn ON orgn.snapshot_id = ?1 AND orgn.node_type = 'organization' AND orgn.key = d.organization
+         WHERE dn.snapshot_id = ?1 AND dn.node_type = 'domain'",
+        [scope.snapshot_id],
+    ).map_err(map_err)?;
+
+    Ok(())
+}
+
+/// Inserts URL, domain and topic nodes for pages visited within the scope
+fn insert_base_nodes(tx: &Connection, scope: &GraphScope) -> Result<()> {
+    let map_err = |e: rusqlite::Error| DatabaseError::Query(format!("Failed to insert graph nodes: {}", e));
+
+    tx.execute(
+        "INSERT INTO node (snapshot_id, node_type, key, label, weight)
+         SELECT ?1, 'url', u.id, COALESCE(u.title, u.url), COUNT(v.id)
+         FROM url u
+         JOIN visit v ON v.url_id = u.id
+         WHERE v.visited_at BETWEEN ?2 AND ?3
+         GROUP BY u.id",
+        params![scope.snapshot_id, scope.start, scope.end],
+    ).map_err(map_err)?;
+
+    tx.execute(
+        "INSERT INTO node (snapshot_id, node_type, key, label, weight)
+         SELECT ?1, 'domain', u.domain, u.domain, COUNT(v.id)
+         FROM url u
+         JOIN visit v ON v.url_id = u.id
+         WHERE v.visited_at BETWEEN ?2 AND ?3
+         GROUP BY u.domain",
+        params![scope.snapshot_id, scope.start, scope.end],
+    ).map_err(map_err)?;
+
+    tx.execute(
+        "INSERT INTO node (snapshot_id, node_type, key, label, weight)
+         SELECT ?1, 'topic', m.topic_cluster, m.topic_cluster, COUNT(*)
+         FROM metadata m
+         JOIN node un ON un.snapshot_id = ?1 AND un.node_type = 'url' AND un.key = m.url_id
+         WHERE m.topic_cluster IS NOT NULL AND m.topic_cluster <> ''
+         GROUP BY m.topic_cluster",
+        [scope.snapshot_id],
+    ).map_err(map_err)?;
+
+    Ok(())
+}
+
+/// Inserts domain, topic and same-topic edges between the base nodes
+fn insert_base_edges(tx: &Connection, scope: &GraphScope) -> Result<()> {
+    let map_err = |e: rusqlite::Error| DatabaseError::Query(format!("Failed to insert graph edges: {}", e));
+
+    tx.execute(
+        "INSERT INTO edge (snapshot_id, source_id, target_id, edge_type, weight)
+         SELECT ?1, un.id, dn.id, 'in_domain', MAX(un.weight, 1)
+         FROM url u
+         JOIN node un ON un.snapshot_id = ?1 AND un.node_type = 'url' AND un.key = u.id
+         JOIN node dn ON dn.snapshot_id = ?1 AND dn.node_type = 'domain' AND dn.key = u.domain",
+        [scope.snapshot_id],
+    ).map_err(map_err)?;
+
+    tx.execute(
+        "INSERT INTO edge (snapshot_id, source_id, target_id, edge_type, weight)
+         SELECT ?1, un.id, tn.id, 'has_topic', 1
+         FROM metadata m
+         JOIN node un ON un.snapshot_id = ?1 AND un.node_type = 'url' AND un.key = m.url_id
+         JOIN node tn ON tn.snapshot_id = ?1 AND tn.node_type = 'topic' AND tn.key = m.topic_cluster",
+        [scope.snapshot_id],
+    ).map_err(map_err)?;
+
+    // Link every page of a topic to the topic's most visited pages,
+    // which keeps the edge count linear in the number of pages
+    tx.execute(
+        "WITH ranked AS (
+             SELECT un.id as node_id, m.topic_cluster as topic,
+                    ROW_NUMBER() OVER (PARTITION BY m.topic_cluster ORDER BY un.weight DESC, un.id) as topic_rank
+             FROM metadata m
+             JOIN node un ON un.snapshot_id = ?1 AND un.node_type = 'url' AND un.key = m.url_id
+             WHERE m.topic_cluster IS NOT NULL AND m.topic_cluster <> ''
+         )
+         INSERT INTO edge (snapshot_id, source_id, target_id, edge_type, weight)
+         SELECT ?1, a.node_id, b.node_id, 'same_topic', 1
+         FROM ranked a
+         JOIN ranked b ON a.topic = b.topic AND b.topic_rank <= ?2 AND a.node_id <> b.node_id
+         WHERE true -- required by SQLite to parse ON CONFLICT after INSERT ... SELECT
+         ON CONFLICT (source_id, target_id, edge_type) DO NOTHING",
+        params![scope.snapshot_id, SAME_TOPIC_NEIGHBOURS],
+    ).map_err(|e| DatabaseError::Query(format!("Failed to insert same-topic edges: {}", e)))?;
+
+    Ok(())
+}
+
+/// Inserts or reinforces a node, returning its id
+pub(crate) fn upsert_node(
+    tx: &Connection,
+    snapshot_id: i64,
+    node_type: NodeType,
+    key: &str,
+    label: &str,
+    weight: f64,
+) -> Result<i64> {
+    let id = tx.query_row(
+        "INSERT INTO node (snapshot_id, node_type, key, label, weight) VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT (snapshot_id, node_type, key) DO UPDATE SET weight = weight + excluded.weight
+         RETURNING id",
+        params![snapshot_id, node_type.as_str(), key, label, weight],
+        |row| row.get(0),
+    )?;
+
+    Ok(id)
+}
+
+/// Inserts or reinforces an edge
+pub(crate) fn upsert_edge(
+    tx: &Connection,
+    snapshot_id: i64,
+    source_id: i64,
+    target_id: i64,
+    edge_type: EdgeType,
+    weight: f64,
+) -> Result<()> {
+    tx.execute(
+        "INSERT INTO edge (snapshot_id, source_id, target_id, edge_type, weight) VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT (source_id, target_id, edge_type) DO UPDATE SET weight = weight + excluded.weight",
+        params![snapshot_id, source_id, target_id, edge_type.as_str(), weight],
+    )?;
+
+    Ok(())
+}
+
+/// Loads the id of every node of a type in a snapshot, keyed by node key
+pub(crate) fn node_ids(tx: &Connection, snapshot_id: i64, node_type: NodeType) -> Result<HashMap<String, i64>> {
+    let mut stmt = tx.prepare("SELECT key, id FROM node WHERE snapshot_id = ? AND node_type = ?")?;
+    let rows = stmt.query_map(params![snapshot_id, node_type.as_str()], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?;
+
+    let mut ids = HashMap::new();
+    for row in rows {
+        let (key, id) = row?;
+        ids.insert(key, id);
+    }
+
+    Ok(ids)
+}
+
+/// Parses the keywords column, which holds a JSON array or a comma separated list
+pub fn parse_keywords(keywords: &str) -> Vec<String> {
+    let raw: Vec<String> = serde_json::from_str(keywords)
+        .unwrap_or_else(|_| keywords.split(',').map(|k| k.to_string()).collect());
+
+    let mut parsed: Vec<String> = raw.into_iter()
+        .map(|k| k.trim().to_lowercase())
+        .filter(|k| !k.is_empty())
+        .collect();
+    parsed.sort();
+    parsed.dedup();
+    parsed
+}
+
+/// Inserts entity nodes from the keyword index and mentions edges to them
+fn insert_entities(tx: &Connection, scope: &GraphScope) -> Result<()> {
+    let url_nodes = node_ids(tx, scope.snapshot_id, NodeType::Url)?;
+
+    let mut stmt = tx.prepare(
+        "SELECT uk.url_id, k.name FROM url_keyword uk JOIN keyword k ON k.id = uk.keyword_id"
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+    for row in rows {
+        let (url_id, keyword) = row?;
+        let url_node = match url_nodes.get(&url_id) {
+            Some(id) => *id,
+            None => continue,
+        };
+
+        let entity_node = upsert_node(tx, scope.snapshot_id, NodeType::Entity, &keyword, &keyword, 1.0)?;
+        upsert_edge(tx, scope.snapshot_id, url_node, entity_node, EdgeType::Mentions, 1.0)?;
+    }
+
+    Ok(())
+}
+
+/// Inserts session nodes with visited edges, plus linked-from edges between
+/// pages opened in quick succession within a session
+fn insert_sessions(tx: &Connection, scope: &GraphScope) -> Result<()> {
+    let url_nodes = node_ids(tx, scope.snapshot_id, NodeType::Url)?;
+
+    let mut stmt = tx.prepare(
+        "SELECT COALESCE(device_name, ''), url_id, visited_at
+         FROM visit
+         WHERE visited_at BETWEEN ? AND ?
+         ORDER BY device_name, visited_at"
+    )?;
+    let mut rows = stmt.query(params![scope.start, scope.end])?;
+
+    let mut session_node: Option<i64> = None;
+    let mut last_device = String::new();
+    let mut last_visit: Option<(i64, i64)> = None; // (url node, visited_at)
+
+    while let Some(row) = rows.next()? {
+        let device: String = row.get(0)?;
+        let url_id: String = row.get(1)?;
+        let visited_at: i64 = row.get(2)?;
+
+        let url_node = match url_nodes.get(&url_id) {
+            Some(id) => *id,
+            None => continue,
+        };
+
+        // Start a new session on a device change or after a long pause
+        let new_session = device != last_device
+            || last_visit.is_none_or(|(_, at)| visited_at - at > SESSION_GAP_SECS);
+
+        if new_session {
+            let key = format!("{}:{}", device, visited_at);
+            let label = format!(
+                "Session {}",
+                chrono::DateTime::from_timestamp(visited_at, 0)
+                    .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default(),
+            );
+            session_node = Some(upsert_node(tx, scope.snapshot_id, NodeType::Session, &key, &label, 0.0)?);
+            last_device = device;
+            last_visit = None;
+        }
+
+        if let Some(session) = session_node {
+            tx.execute("UPDATE node SET weight = weight + 1 WHERE id = ?", [session])?;
+            upsert_edge(tx, scope.snapshot_id, session, url_node, EdgeType::Visited, 1.0)?;
+        }
+
+        if let Some((previous_node, previous_at)) = last_visit {
+            if previous_node != url_node && visited_at - previous_at <= LINK_WINDOW_SECS {
+                upsert_edge(tx, scope.snapshot_id, previous_node, url_node, EdgeType::LinkedFrom, 1.0)?;
+            }
+        }
+
+        last_visit = Some((url_node, visited_at));
+    }
+
+    Ok(())
+}
+
+/// Counts the nodes and edges of a snapshot by type
+pub(crate) fn graph_stats(tx: &Connection, snapshot_id: i64) -> Result<GraphStats> {
+    let mut stats = GraphStats::default();
+
+    let mut stmt = tx.prepare("SELECT node_type, COUNT(*) FROM node WHERE snapshot_id = ? GROUP BY node_type")?;
+    let rows = stmt.query_map([snapshot_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?;
+    for row in rows {
+        let (node_type, count) = row?;
+        stats.nodes.insert(node_type, count as usize);
+    }
+
+    let mut stmt = tx.prepare("SELECT edge_type, COUNT(*) FROM edge WHERE snapshot_id = ? GROUP BY edge_type")?;
+    let rows = stmt.query_map([snapshot_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?;
+    for row in rows {
+        let (edge_type, count) = row?;
+        stats.edges.insert(edge_type, count as usize);
+    }
+
+    Ok(stats)
+}
@@ -0,0 +1,129 @@
+// Knowledge Graph - Domain Co-visitation
+// Links domains that are browsed together within the same session
+
+use std::collections::{BTreeSet, HashMap};
+
+use rusqlite::Connection;
+
+use crate::db::{DatabaseConnection, Result};
+use crate::db::query::QueryBuilder;
+use super::builder::{node_ids, upsert_edge, GraphScope, MAIN_GRAPH, SESSION_GAP_SECS};
+use super::models::{NodeType, EdgeType};
+
+/// Pairs seen together in fewer sessions than this are considered noise
+const MIN_CO_VISITS: usize = 2;
+
+/// A domain frequently browsed together with another
+#[derive(Debug, Clone)]
+pub struct CoVisitedDomain {
+    /// First domain of the pair
+    pub domain: String,
+    /// Second domain of the pair
+    pub other_domain: String,
+    /// Number of sessions in which both domains were visited
+    pub sessions: usize,
+}
+
+/// Counts, for every pair of domains, the number of sessions in scope in which both were visited
+fn count_domain_pairs(tx: &Connection, scope: &GraphScope) -> Result<HashMap<(String, String), usize>> {
+    let mut stmt = tx.prepare(
+        "SELECT COALESCE(v.device_name, ''), u.domain, v.visited_at
+         FROM visit v
+         JOIN url u ON v.url_id = u.id
+         WHERE v.visited_at BETWEEN ? AND ?
+         ORDER BY v.device_name, v.visited_at"
+    )?;
+    let mut rows = stmt.query(rusqlite::params![scope.start, scope.end])?;
+
+    let mut pairs: HashMap<(String, String), usize> = HashMap::new();
+    let mut session_domains: BTreeSet<String> = BTreeSet::new();
+    let mut last: Option<(String, i64)> = None; // (device, visited_at)
+
+    // Adds every pair of the finished session (domains are sorted, so a < b)
+    let flush = |domains: &mut BTreeSet<String>, pairs: &mut HashMap<(String, String), usize>| {
+        let list: Vec<&String> = domains.iter().collect();
+        for (i, a) in list.iter().enumerate() {
+            for b in &list[i + 1..] {
+                *pairs.entry(((*a).clone(), (*b).clone())).or_insert(0) += 1;
+            }
+        }
+        domains.clear();
+    };
+
+    while let Some(row) = rows.next()? {
+        let device: String = row.get(0)?;
+        let domain: String = row.get(1)?;
+        let visited_at: i64 = row.get(2)?;
+
+        let new_session = match last {
+            Some((ref last_device, last_at)) => {
+                *last_device != device || visited_at - last_at > SESSION_GAP_SECS
+            },
+            None => true,
+        };
+
+        if new_session {
+            flush(&mut session_domains, &mut pairs);
+        }
+
+        session_domains.insert(domain);
+        last = Some((device, visited_at));
+    }
+    flush(&mut session_domains, &mut pairs);
+
+    Ok(pairs)
+}
+
+/// Inserts co-visited edges between domain nodes, weighted by shared session count
+pub(crate) fn insert_covisitation_edges(tx: &Connection, scope: &GraphScope) -> Result<usize> {
+    let pairs = count_domain_pairs(tx, scope)?;
+    let domain_nodes = node_ids(tx, scope.snapshot_id, NodeType::Domain)?;
+
+    let mut inserted = 0;
+    for ((a, b), sessions) in pairs {
+        if sessions < MIN_CO_VISITS {
+            continue;
+        }
+
+        if let (Some(source), Some(target)) = (domain_nodes.get(&a), domain_nodes.get(&b)) {
+            upsert_edge(tx, scope.snapshot_id, *source, *target, EdgeType::CoVisited, sessions as f64)?;
+            inserted += 1;
+        }
+    }
+
+    Ok(inserted)
+}
+
+/// Gets the domain pairs most often browsed together, optionally involving a given domain
+pub fn get_co_visited_domains(
+    conn: &DatabaseConnection,
+    domain: Option<String>,
+    limit: usize,
+) -> Result<Vec<CoVisitedDomain>> {
+    conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT s.key, t.key, e.weight
+             FROM edge e
+             JOIN node s ON e.source_id = s.id
+             JOIN node t ON e.target_id = t.id"
+        );
+
+        query.filter("e.snapshot_id = ?", MAIN_GRAPH);
+        query.filter("e.edge_type = ?", EdgeType::CoVisited.as_str().to_string());
+
+        if let Some(domain) = domain {
+            query.filter_many("(s.key = ? OR t.key = ?)", vec![Box::new(domain.clone()), Box::new(domain)]);
+        }
+
+        query.order_by("e.weight DESC").limit(limit);
+
+        query.fetch_all(c, |row| {
+            let weight: f64 = row.get(2)?;
+            Ok(CoVisitedDomain {
+                domain: row.get(0)?,
+                other_domain: row.get(1)?,
+                sessions: weight as usize,
+            })
+        })
+    })
+}
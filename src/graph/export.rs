@@ -0,0 +1,141 @@
+// Knowledge Graph - Export
+// Writes the graph as GraphML or GEXF for tools like Gephi and yEd
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::db::Result;
+use super::store::Graph;
+
+/// File format for graph exports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    /// GraphML (yEd, Gephi, NetworkX)
+    GraphMl,
+    /// GEXF (Gephi)
+    Gexf,
+}
+
+impl GraphFormat {
+    /// Parses a format name ("graphml" or "gexf")
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "graphml" => Some(GraphFormat::GraphMl),
+            "gexf" => Some(GraphFormat::Gexf),
+            _ => None,
+        }
+    }
+}
+
+/// Escapes characters that have a meaning in XML
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Writes the graph to `path` in the given format
+pub fn export_graph(graph: &Graph, format: GraphFormat, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+
+    match format {
+        GraphFormat::GraphMl => write_graphml(graph, &mut out)?,
+        GraphFormat::Gexf => write_gexf(graph, &mut out)?,
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes the graph as GraphML
+fn write_graphml<W: Write>(graph: &Graph, out: &mut W) -> std::io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+    writeln!(out, r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#)?;
+    writeln!(out, r#"  <key id="type" for="node" attr.name="type" attr.type="string"/>"#)?;
+    writeln!(out, r#"  <key id="key" for="node" attr.name="key" attr.type="string"/>"#)?;
+    writeln!(out, r#"  <key id="weight" for="node" attr.name="weight" attr.type="double"/>"#)?;
+    writeln!(out, r#"  <key id="wikidata" for="node" attr.name="wikidata" attr.type="string"/>"#)?;
+    writeln!(out, r#"  <key id="edge_type" for="edge" attr.name="type" attr.type="string"/>"#)?;
+    writeln!(out, r#"  <key id="edge_weight" for="edge" attr.name="weight" attr.type="double"/>"#)?;
+    writeln!(out, r#"  <graph id="history" edgedefault="directed">"#)?;
+
+    for node in &graph.nodes {
+        writeln!(out, r#"    <node id="n{}">"#, node.id)?;
+        writeln!(out, r#"      <data key="label">{}</data>"#, escape_xml(&node.label))?;
+        writeln!(out, r#"      <data key="type">{}</data>"#, node.node_type.as_str())?;
+        writeln!(out, r#"      <data key="key">{}</data>"#, escape_xml(&node.key))?;
+        writeln!(out, r#"      <data key="weight">{}</data>"#, node.weight)?;
+        if let Some(qid) = &node.wikidata_id {
+            writeln!(out, r#"      <data key="wikidata">{}</data>"#, escape_xml(qid))?;
+        }
+        writeln!(out, "    </node>")?;
+    }
+
+    for edge in &graph.edges {
+        writeln!(out, r#"    <edge id="e{}" source="n{}" target="n{}">"#, edge.id, edge.source_id, edge.target_id)?;
+        writeln!(out, r#"      <data key="edge_type">{}</data>"#, edge.edge_type.as_str())?;
+        writeln!(out, r#"      <data key="edge_weight">{}</data>"#, edge.weight)?;
+        writeln!(out, "    </edge>")?;
+    }
+
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")?;
+    Ok(())
+}
+
+/// Writes the graph as GEXF 1.3
+fn write_gexf<W: Write>(graph: &Graph, out: &mut W) -> std::io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<gexf xmlns="http://gexf.net/1.3" xmlns:viz="http://gexf.net/1.3/viz" version="1.3">"#)?;
+    writeln!(out, r#"  <graph mode="static" defaultedgetype="directed">"#)?;
+    writeln!(out, r#"    <attributes class="node">"#)?;
+    writeln!(out, r#"      <attribute id="0" title="type" type="string"/>"#)?;
+    writeln!(out, r#"      <attribute id="1" title="key" type="string"/>"#)?;
+    writeln!(out, r#"      <attribute id="2" title="wikidata" type="string"/>"#)?;
+    writeln!(out, r#"    </attributes>"#)?;
+    writeln!(out, r#"    <attributes class="edge">"#)?;
+    writeln!(out, r#"      <attribute id="0" title="type" type="string"/>"#)?;
+    writeln!(out, r#"    </attributes>"#)?;
+
+    writeln!(out, "    <nodes>")?;
+    for node in &graph.nodes {
+        writeln!(out, r#"      <node id="{}" label="{}">"#, node.id, escape_xml(&node.label))?;
+        writeln!(out, "        <attvalues>")?;
+        writeln!(out, r#"          <attvalue for="0" value="{}"/>"#, node.node_type.as_str())?;
+        writeln!(out, r#"          <attvalue for="1" value="{}"/>"#, escape_xml(&node.key))?;
+        if let Some(qid) = &node.wikidata_id {
+            writeln!(out, r#"          <attvalue for="2" value="{}"/>"#, escape_xml(qid))?;
+        }
+        writeln!(out, "        </attvalues>")?;
+        writeln!(out, r#"        <viz:size value="{}"/>"#, node.weight.max(1.0))?;
+        if let (Some(x), Some(y)) = (node.x, node.y) {
+            writeln!(out, r#"        <viz:position x="{}" y="{}" z="0.0"/>"#, x, y)?;
+        }
+        writeln!(out, "      </node>")?;
+    }
+    writeln!(out, "    </nodes>")?;
+
+    writeln!(out, "    <edges>")?;
+    for edge in &graph.edges {
+        writeln!(
+            out,
+            r#"      <edge id="{}" source="{}" target="{}" weight="{}">"#,
+            edge.id, edge.source_id, edge.target_id, edge.weight,
+        )?;
+        writeln!(out, r#"        <attvalues><attvalue for="0" value="{}"/></attvalues>"#, edge.edge_type.as_str())?;
+        writeln!(out, "      </edge>")?;
+    }
+    writeln!(out, "    </edges>")?;
+
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</gexf>")?;
+    Ok(())
+}
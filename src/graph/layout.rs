@@ -0,0 +1,181 @@
+// Knowledge Graph - Layout
+// Precomputes force-directed node positions so the frontend only has to draw
+
+use std::collections::HashMap;
+
+use rusqlite::params;
+use serde::Deserialize;
+
+use crate::db::{DatabaseConnection, Result};
+use super::builder::MAIN_GRAPH;
+
+/// Default number of simulation steps
+pub const DEFAULT_LAYOUT_ITERATIONS: usize = 300;
+
+/// Ideal distance between two connected nodes
+const IDEAL_EDGE_LENGTH: f64 = 30.0;
+
+/// Pull of every node towards the centre, keeps disconnected components together
+const GRAVITY: f64 = 0.02;
+
+/// Options for the layout pass
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LayoutOptions {
+    /// Snapshot to lay out (the main graph when None)
+    pub snapshot_id: Option<i64>,
+    /// Number of simulation steps
+    pub iterations: usize,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            snapshot_id: None,
+            iterations: DEFAULT_LAYOUT_ITERATIONS,
+        }
+    }
+}
+
+/// Computes Fruchterman-Reingold positions for `node_count` nodes connected by
+/// `edges` (pairs of node indices with a weight).
+///
+/// Repulsion is only applied between nodes in neighbouring grid cells, which
+/// keeps each step close to linear in the number of nodes. Nodes start on a
+/// golden-angle spiral, so the result is deterministic.
+pub fn force_directed(node_count: usize, edges: &[(usize, usize, f64)], iterations: usize) -> Vec<(f64, f64)> {
+    let k = IDEAL_EDGE_LENGTH;
+    let golden_angle = std::f64::consts::PI * (3.0 - 5f64.sqrt());
+
+    let mut positions: Vec<(f64, f64)> = (0..node_count)
+        .map(|i| {
+            let radius = k * (i as f64 + 0.5).sqrt();
+            let angle = i as f64 * golden_angle;
+            (radius * angle.cos(), radius * angle.sin())
+        })
+        .collect();
+
+    if node_count < 2 {
+        return positions;
+    }
+
+    let initial_temperature = k * (node_count as f64).sqrt() / 2.0;
+    let cell_size = 2.0 * k;
+    let mut displacement = vec![(0.0f64, 0.0f64); node_count];
+
+    for step in 0..iterations {
+        let temperature = initial_temperature * (1.0 - step as f64 / iterations as f64);
+        displacement.iter_mut().for_each(|d| *d = (0.0, 0.0));
+
+        // Repulsion between nearby nodes
+        let mut grid: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (i, (x, y)) in positions.iter().enumerate() {
+            let cell = ((x / cell_size).floor() as i64, (y / cell_size).floor() as i64);
+            grid.entry(cell).or_default().push(i);
+        }
+
+        for (&(cx, cy), members) in &grid {
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let neighbours = match grid.get(&(cx + dx, cy + dy)) {
+                        Some(neighbours) => neighbours,
+                        None => continue,
+                    };
+
+                    for &i in members {
+                        for &j in neighbours {
+                            if i == j {
+                                continue;
+                            }
+
+                            let (mut ddx, mut ddy) = (positions[i].0 - positions[j].0, positions[i].1 - positions[j].1);
+                            let mut distance = (ddx * ddx + ddy * ddy).sqrt();
+
+                            // Nudge apart nodes that landed on the same spot
+                            if distance < 0.01 {
+                                ddx = (i as f64 - j as f64) * 0.01;
+                                ddy = 0.01;
+                                distance = (ddx * ddx + ddy * ddy).sqrt();
+                            }
+
+                            if distance < cell_size {
+                                let force = k * k / distance;
+                                displacement[i].0 += ddx / distance * force;
+                                displacement[i].1 += ddy / distance * force;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Attraction along edges, dampened for very heavy edges
+        for &(source, target, weight) in edges {
+            if source == target {
+                continue;
+            }
+
+            let (ddx, ddy) = (positions[source].0 - positions[target].0, positions[source].1 - positions[target].1);
+            let distance = (ddx * ddx + ddy * ddy).sqrt().max(0.01);
+            let force = distance * distance / k * (1.0 + weight.max(0.0)).ln().max(1.0);
+
+            displacement[source].0 -= ddx / distance * force;
+            displacement[source].1 -= ddy / distance * force;
+            displacement[target].0 += ddx / distance * force;
+            displacement[target].1 += ddy / distance * force;
+        }
+
+        // Apply gravity and move every node, capped by the current temperature
+        for (i, position) in positions.iter_mut().enumerate() {
+            let (mut dx, mut dy) = displacement[i];
+            dx -= position.0 * GRAVITY * k;
+            dy -= position.1 * GRAVITY * k;
+
+            let length = (dx * dx + dy * dy).sqrt();
+            if length > 0.0 {
+                let capped = length.min(temperature);
+                position.0 += dx / length * capped;
+                position.1 += dy / length * capped;
+            }
+        }
+    }
+
+    positions
+}
+
+/// Lays out a snapshot of the graph and stores the x/y coordinates of its nodes,
+/// returning the number of positioned nodes
+pub fn compute_layout(conn: &DatabaseConnection, options: &LayoutOptions) -> Result<usize> {
+    let snapshot_id = options.snapshot_id.unwrap_or(MAIN_GRAPH);
+
+    conn.transaction(|tx| {
+        let mut stmt = tx.prepare("SELECT id FROM node WHERE snapshot_id = ? ORDER BY weight DESC, id")?;
+        let node_ids = stmt.query_map([snapshot_id], |row| row.get::<_, i64>(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+
+        let index: HashMap<i64, usize> = node_ids.iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i))
+            .collect();
+
+        let mut stmt = tx.prepare("SELECT source_id, target_id, weight FROM edge WHERE snapshot_id = ?")?;
+        let edges = stmt.query_map([snapshot_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, f64>(2)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .into_iter()
+        .filter_map(|(source, target, weight)| {
+            Some((*index.get(&source)?, *index.get(&target)?, weight))
+        })
+        .collect::<Vec<_>>();
+
+        let positions = force_directed(node_ids.len(), &edges, options.iterations);
+
+        let mut update = tx.prepare("UPDATE node SET x = ?, y = ? WHERE id = ?")?;
+        for (id, (x, y)) in node_ids.iter().zip(positions) {
+            update.execute(params![x, y, id])?;
+        }
+
+        Ok(node_ids.len())
+    })
+}
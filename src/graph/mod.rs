@@ -0,0 +1,33 @@
+// Knowledge Graph Module
+// Builds and queries the graph of URLs, domains, topics, entities and sessions
+
+// Module organization:
+// - models.rs: Node and edge types
+// - builder.rs: Rebuilds the graph tables from history and metadata
+// - covisitation.rs: Domain co-visitation edges
+// - snapshot.rs: Graphs restricted to a time window
+// - paths.rs: Navigation chains between two pages
+// - related.rs: Related-page recommendations
+// - store.rs: Loads filtered nodes and edges, and a node's neighbors
+// - layout.rs: Force-directed layout precomputation
+// - export.rs: GraphML and GEXF export
+
+pub mod models;
+pub mod builder;
+pub mod covisitation;
+pub mod snapshot;
+pub mod paths;
+pub mod related;
+pub mod store;
+pub mod layout;
+pub mod export;
+
+pub use models::{NodeType, EdgeType, GraphNode, GraphEdge, GraphStats};
+pub use builder::rebuild_graph;
+pub use covisitation::{get_co_visited_domains, CoVisitedDomain};
+pub use paths::{find_paths, NavigationPath, PathStep};
+pub use related::{get_related, RelatedPage, DEFAULT_RELATED_LIMIT};
+pub use snapshot::{rebuild_graph_for_range, rebuild_monthly_snapshots, list_snapshots, GraphSnapshot};
+pub use store::{get_neighbors, load_graph, Graph, GraphFilter, GraphNeighbor, DEFAULT_NEIGHBOR_LIMIT};
+pub use layout::{compute_layout, LayoutOptions};
+pub use export::{export_graph, GraphFormat};
@@ -0,0 +1,149 @@
+// Knowledge Graph - Data Models
+// Defines the node and edge types stored in the graph tables
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+/// Kind of entity a graph node represents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeType {
+    /// A visited page (key is the url id)
+    Url,
+    /// A domain name
+    Domain,
+    /// A topic cluster assigned during enrichment
+    Topic,
+    /// An entity or keyword mentioned by a page
+    Entity,
+    /// A browsing session (consecutive visits on one device)
+    Session,
+    /// An organization owning domains
+    Organization,
+}
+
+impl NodeType {
+    /// Returns the value stored in the `node_type` column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeType::Url => "url",
+            NodeType::Domain => "domain",
+            NodeType::Topic => "topic",
+            NodeType::Entity => "entity",
+            NodeType::Session => "session",
+            NodeType::Organization => "organization",
+        }
+    }
+    
+    /// Parses a value from the `node_type` column
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "url" => Some(NodeType::Url),
+            "domain" => Some(NodeType::Domain),
+            "topic" => Some(NodeType::Topic),
+            "entity" => Some(NodeType::Entity),
+            "session" => Some(NodeType::Session),
+            "organization" => Some(NodeType::Organization),
+            _ => None,
+        }
+    }
+}
+
+/// Kind of relationship a graph edge represents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeType {
+    /// Session -> URL: the page was visited during the session
+    Visited,
+    /// URL -> URL: the target was opened shortly after the source in the same session
+    LinkedFrom,
+    /// URL -> URL: both pages share a topic cluster
+    SameTopic,
+    /// URL -> domain
+    InDomain,
+    /// URL -> topic
+    HasTopic,
+    /// URL -> entity
+    Mentions,
+    /// Domain -> domain: both domains were browsed in the same session
+    CoVisited,
+    /// Domain -> organization
+    OwnedBy,
+}
+
+impl EdgeType {
+    /// Returns the value stored in the `edge_type` column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EdgeType::Visited => "visited",
+            EdgeType::LinkedFrom => "linked_from",
+            EdgeType::SameTopic => "same_topic",
+            EdgeType::InDomain => "in_domain",
+            EdgeType::HasTopic => "has_topic",
+            EdgeType::Mentions => "mentions",
+            EdgeType::CoVisited => "co_visited",
+            EdgeType::OwnedBy => "owned_by",
+        }
+    }
+    
+    /// Parses a value from the `edge_type` column
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "visited" => Some(EdgeType::Visited),
+            "linked_from" => Some(EdgeType::LinkedFrom),
+            "same_topic" => Some(EdgeType::SameTopic),
+            "in_domain" => Some(EdgeType::InDomain),
+            "has_topic" => Some(EdgeType::HasTopic),
+            "mentions" => Some(EdgeType::Mentions),
+            "co_visited" => Some(EdgeType::CoVisited),
+            "owned_by" => Some(EdgeType::OwnedBy),
+            _ => None,
+        }
+    }
+}
+
+/// A node in the knowledge graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphNode {
+    /// Row id of the node
+    pub id: i64,
+    /// Kind of node
+    pub node_type: NodeType,
+    /// Identifier of the underlying entity (url id, domain, topic...)
+    pub key: String,
+    /// Display label
+    pub label: String,
+    /// Number of visits or mentions backing the node
+    pub weight: f64,
+    /// Wikidata item of an entity or domain, once linked
+    pub wikidata_id: Option<String>,
+    /// Precomputed layout position (None until a layout pass has run)
+    pub x: Option<f64>,
+    /// Precomputed layout position (None until a layout pass has run)
+    pub y: Option<f64>,
+}
+
+/// An edge in the knowledge graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphEdge {
+    /// Row id of the edge
+    pub id: i64,
+    /// Source node id
+    pub source_id: i64,
+    /// Target node id
+    pub target_id: i64,
+    /// Kind of relationship
+    pub edge_type: EdgeType,
+    /// Strength of the relationship
+    pub weight: f64,
+}
+
+/// Node and edge counts after a rebuild
+#[derive(Debug, Default, Serialize)]
+pub struct GraphStats {
+    /// Number of nodes per node type
+    pub nodes: HashMap<String, usize>,
+    /// Number of edges per edge type
+    pub edges: HashMap<String, usize>,
+}
@@ -0,0 +1,162 @@
+// Knowledge Graph - Path Finding
+// Reconstructs the navigation chains that led from one page to another
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension};
+
+use crate::db::{DatabaseConnection, DatabaseError, Result};
+use super::builder::LINK_WINDOW_SECS;
+
+/// Default maximum number of pages between the two endpoints
+pub const DEFAULT_MAX_HOPS: usize = 6;
+
+/// Largest `max_hops` honoured; longer chains are not navigation paths anyway
+const MAX_HOPS: usize = 50;
+
+/// Only the most recent visits of the starting page are followed
+const MAX_START_VISITS: usize = 500;
+
+/// A page visited along a navigation path
+#[derive(Debug, Clone)]
+pub struct PathStep {
+    /// Visited URL id
+    pub url_id: String,
+    /// Visited URL
+    pub url: String,
+    /// Page title, if known
+    pub title: Option<String>,
+    /// When the page was opened
+    pub visited_at: DateTime<Utc>,
+}
+
+/// A chain of visits leading from the first page to the last
+#[derive(Debug, Clone)]
+pub struct NavigationPath {
+    /// Pages in visit order, including both endpoints
+    pub steps: Vec<PathStep>,
+    /// Number of times the same sequence of pages was followed
+    pub occurrences: usize,
+}
+
+/// Looks up the id of a URL
+fn url_id(c: &rusqlite::Connection, url: &str) -> Result<String> {
+    c.query_row("SELECT id FROM url WHERE url = ?", [url], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| DatabaseError::Query(format!("URL not found in history: {}", url)))
+}
+
+/// Finds navigation chains from `url_a` to `url_b`.
+///
+/// A chain follows consecutive visits on the same device, each opened within
+/// a few minutes of the previous one (the same rule that produces linked-from
+/// edges). Detours that come back to an earlier page are cut out. Identical
+/// chains are merged, and the shortest, most recent ones come first.
+pub fn find_paths(
+    conn: &DatabaseConnection,
+    url_a: &str,
+    url_b: &str,
+    max_hops: usize,
+    limit: usize,
+) -> Result<Vec<NavigationPath>> {
+    // Keeps the visit window below from overflowing
+    let max_hops = max_hops.min(MAX_HOPS);
+
+    conn.with_connection(|c| {
+        let start_id = url_id(c, url_a)?;
+        let target_id = url_id(c, url_b)?;
+
+        let mut stmt = c.prepare(
+            "SELECT COALESCE(device_name, ''), visited_at FROM visit
+             WHERE url_id = ?
+             ORDER BY visited_at DESC
+             LIMIT ?"
+        )?;
+        let starts = stmt.query_map(params![start_id, MAX_START_VISITS as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut following = c.prepare(
+            "SELECT v.url_id, u.url, u.title, v.visited_at
+             FROM visit v
+             JOIN url u ON v.url_id = u.id
+             WHERE COALESCE(v.device_name, '') = ? AND v.visited_at BETWEEN ? AND ?
+             ORDER BY v.visited_at"
+        )?;
+
+        // Paths keyed by their sequence of url ids, in the order they were found
+        let mut found: Vec<NavigationPath> = Vec::new();
+        let mut seen: HashMap<Vec<String>, usize> = HashMap::new();
+
+        for (device, start_at) in starts {
+            let window_end = start_at.saturating_add(LINK_WINDOW_SECS * (max_hops as i64 + 1));
+            let mut rows = following.query(params![device, start_at, window_end])?;
+
+            let mut chain: Vec<PathStep> = Vec::new();
+            let mut last_at = start_at;
+            let mut reached = false;
+
+            while let Some(row) = rows.next()? {
+                let step = PathStep {
+                    url_id: row.get(0)?,
+                    url: row.get(1)?,
+                    title: row.get(2)?,
+                    visited_at: DateTime::from_timestamp(row.get(3)?, 0).unwrap_or_default(),
+                };
+                let visited_at = step.visited_at.timestamp();
+
+                if chain.is_empty() {
+                    if step.url_id == start_id {
+                        chain.push(step);
+                    }
+                    continue;
+                }
+
+                if visited_at - last_at > LINK_WINDOW_SECS {
+                    break;
+                }
+                last_at = visited_at;
+
+                // Reloads and detours back to an earlier page don't add a hop
+                if let Some(position) = chain.iter().position(|s| s.url_id == step.url_id) {
+                    chain.truncate(position + 1);
+                    continue;
+                }
+
+                let is_target = step.url_id == target_id;
+                chain.push(step);
+
+                if is_target {
+                    reached = true;
+                    break;
+                }
+
+                // max_hops hops taken without reaching the target
+                if chain.len() > max_hops {
+                    break;
+                }
+            }
+
+            if !reached {
+                continue;
+            }
+
+            let key: Vec<String> = chain.iter().map(|s| s.url_id.clone()).collect();
+            match seen.get(&key) {
+                Some(&index) => found[index].occurrences += 1,
+                None => {
+                    seen.insert(key, found.len());
+                    found.push(NavigationPath { steps: chain, occurrences: 1 });
+                },
+            }
+        }
+
+        // Starts were walked newest first, so a stable sort keeps recency within equal lengths
+        found.sort_by_key(|path| path.steps.len());
+        found.truncate(limit);
+
+        Ok(found)
+    })
+}
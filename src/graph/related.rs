@@ -0,0 +1,171 @@
+// Knowledge Graph - Related Pages
+// Ranks pages related to a URL by content similarity, co-visitation and topic
+
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::db::{DatabaseConnection, Result};
+use crate::enrichment::embeddings::decode_vector;
+use super::builder::SESSION_GAP_SECS;
+
+/// Default number of related pages returned
+pub const DEFAULT_RELATED_LIMIT: usize = 10;
+
+/// Candidates considered from each signal before ranking
+const CANDIDATES_PER_SIGNAL: usize = 100;
+
+/// Only the most recent visits of the page are used for co-visitation
+const MAX_SOURCE_VISITS: usize = 500;
+
+/// Weight of embedding similarity in the combined score
+const SIMILARITY_WEIGHT: f64 = 0.5;
+
+/// Weight of co-visitation in the combined score
+const CO_VISIT_WEIGHT: f64 = 0.35;
+
+/// Weight of a shared topic cluster in the combined score
+const TOPIC_WEIGHT: f64 = 0.15;
+
+/// A page related to another, with the signals that connect them
+#[derive(Debug, Clone)]
+pub struct RelatedPage {
+    /// Related URL id
+    pub url_id: String,
+    /// Related URL
+    pub url: String,
+    /// Page title, if known
+    pub title: Option<String>,
+    /// Combined score between 0 and 1
+    pub score: f64,
+    /// Cosine similarity of the page embeddings, if both pages have one
+    pub similarity: Option<f32>,
+    /// Number of visits of the page during which the related page was also opened
+    pub co_visits: usize,
+    /// Whether both pages were assigned the same topic cluster
+    pub same_topic: bool,
+}
+
+/// Signals collected for one candidate
+#[derive(Default)]
+struct Signals {
+    similarity: Option<f32>,
+    co_visits: usize,
+    same_topic: bool,
+}
+
+/// Finds the pages whose embeddings are closest to the page's own embedding
+fn similar_pages(c: &Connection, url_id: &str) -> rusqlite::Result<Vec<(String, f32)>> {
+    let source: Option<(String, Vec<u8>)> = c.query_row(
+        "SELECT model, vector FROM embedding WHERE url_id = ?",
+        [url_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional()?;
+
+    let (model, vector) = match source {
+        Some(source) => source,
+        None => return Ok(Vec::new()),
+    };
+    let query = decode_vector(&vector);
+
+    let mut stmt = c.prepare(
+        "SELECT url_id, vector FROM embedding WHERE model = ? AND dimensions = ? AND url_id != ?"
+    )?;
+    let mut rows = stmt.query(params![model, query.len() as i64, url_id])?;
+
+    let mut similar = Vec::new();
+    while let Some(row) = rows.next()? {
+        let bytes: Vec<u8> = row.get(1)?;
+        let score = decode_vector(&bytes).iter().zip(&query).map(|(a, b)| a * b).sum::<f32>();
+        similar.push((row.get(0)?, score));
+    }
+
+    similar.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    similar.truncate(CANDIDATES_PER_SIGNAL);
+    Ok(similar)
+}
+
+/// Counts, for every other page, the visits of the page during whose session it was also opened
+fn co_visited_pages(c: &Connection, url_id: &str) -> rusqlite::Result<Vec<(String, usize)>> {
+    let mut stmt = c.prepare(
+        "SELECT o.url_id, COUNT(DISTINCT s.id) as co_visits
+         FROM (SELECT id, visited_at, device_name FROM visit
+               WHERE url_id = ?1 ORDER BY visited_at DESC LIMIT ?2) s
+         JOIN visit o ON o.device_name IS s.device_name
+             AND o.visited_at BETWEEN s.visited_at - ?3 AND s.visited_at + ?3
+             AND o.url_id != ?1
+         GROUP BY o.url_id
+         ORDER BY co_visits DESC
+         LIMIT ?4"
+    )?;
+    let rows = stmt.query_map(
+        params![url_id, MAX_SOURCE_VISITS as i64, SESSION_GAP_SECS, CANDIDATES_PER_SIGNAL as i64],
+        |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)),
+    )?;
+    rows.collect()
+}
+
+/// Lists pages assigned the same topic cluster as the page, most recently seen first
+fn same_topic_pages(c: &Connection, url_id: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = c.prepare(
+        "SELECT m.url_id
+         FROM metadata m
+         JOIN metadata s ON s.url_id = ?1 AND s.topic_cluster = m.topic_cluster
+         JOIN url u ON u.id = m.url_id
+         WHERE m.url_id != ?1 AND m.topic_cluster IS NOT NULL AND m.topic_cluster != ''
+         ORDER BY u.last_seen DESC
+         LIMIT ?2"
+    )?;
+    let rows = stmt.query_map(params![url_id, CANDIDATES_PER_SIGNAL as i64], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Ranks pages related to `url_id` by combining embedding similarity,
+/// co-visitation within the same session and a shared topic cluster
+pub fn get_related(conn: &DatabaseConnection, url_id: &str, limit: usize) -> Result<Vec<RelatedPage>> {
+    conn.with_connection(|c| {
+        let mut candidates: HashMap<String, Signals> = HashMap::new();
+
+        for (id, similarity) in similar_pages(c, url_id)? {
+            candidates.entry(id).or_default().similarity = Some(similarity);
+        }
+        for (id, co_visits) in co_visited_pages(c, url_id)? {
+            candidates.entry(id).or_default().co_visits = co_visits;
+        }
+        for id in same_topic_pages(c, url_id)? {
+            candidates.entry(id).or_default().same_topic = true;
+        }
+
+        // Co-visits are relative to the most co-visited candidate
+        let max_co_visits = candidates.values().map(|s| s.co_visits).max().unwrap_or(0).max(1) as f64;
+
+        let mut ranked: Vec<(String, Signals, f64)> = candidates.into_iter()
+            .map(|(id, signals)| {
+                let score = SIMILARITY_WEIGHT * signals.similarity.unwrap_or(0.0).max(0.0) as f64
+                    + CO_VISIT_WEIGHT * signals.co_visits as f64 / max_co_visits
+                    + TOPIC_WEIGHT * if signals.same_topic { 1.0 } else { 0.0 };
+                (id, signals, score)
+            })
+            .collect();
+
+        ranked.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(limit);
+
+        let mut stmt = c.prepare("SELECT url, title FROM url WHERE id = ?")?;
+        let mut related = Vec::with_capacity(ranked.len());
+        for (id, signals, score) in ranked {
+            let (url, title): (String, Option<String>) = stmt.query_row([&id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            related.push(RelatedPage {
+                url_id: id,
+                url,
+                title,
+                score,
+                similarity: signals.similarity,
+                co_visits: signals.co_visits,
+                same_topic: signals.same_topic,
+            });
+        }
+
+        Ok(related)
+    })
+}
@@ -0,0 +1,147 @@
+// Knowledge Graph - Temporal Snapshots
+// Materializes the graph restricted to a time window
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use rusqlite::{params, Connection};
+
+use crate::db::{DatabaseConnection, DatabaseError, Result};
+use super::builder::{build_graph, GraphScope};
+use super::models::GraphStats;
+
+/// A stored graph built from the visits of a time window
+#[derive(Debug, Clone)]
+pub struct GraphSnapshot {
+    /// Snapshot id, used as `GraphFilter::snapshot_id`
+    pub id: i64,
+    /// Unique label of the window (e.g. "2024-03" for monthly snapshots)
+    pub label: String,
+    /// Start of the window
+    pub start: DateTime<Utc>,
+    /// End of the window
+    pub end: DateTime<Utc>,
+}
+
+/// Creates (or replaces) a snapshot for the window and builds its graph
+fn build_snapshot(tx: &Connection, start: i64, end: i64, label: &str) -> Result<(i64, GraphStats)> {
+    let snapshot_id: i64 = tx.query_row(
+        "INSERT INTO graph_snapshot (label, start_at, end_at, created_at) VALUES (?, ?, ?, ?)
+         ON CONFLICT (label) DO UPDATE SET start_at = excluded.start_at, end_at = excluded.end_at,
+             created_at = excluded.created_at
+         RETURNING id",
+        params![label, start, end, Utc::now().timestamp()],
+        |row| row.get(0),
+    )?;
+
+    let stats = build_graph(tx, GraphScope { snapshot_id, start, end })?;
+
+    Ok((snapshot_id, stats))
+}
+
+/// Builds the graph from the visits between `start` and `end` as a labelled snapshot,
+/// replacing any previous snapshot with the same label
+pub fn rebuild_graph_for_range(
+    conn: &DatabaseConnection,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    label: Option<String>,
+) -> Result<(i64, GraphStats)> {
+    if end < start {
+        return Err(DatabaseError::Query("Graph range ends before it starts".to_string()));
+    }
+
+    let label = label.unwrap_or_else(|| format!("{}..{}", start.format("%Y-%m-%d"), end.format("%Y-%m-%d")));
+
+    conn.transaction(|tx| build_snapshot(tx, start.timestamp(), end.timestamp(), &label))
+}
+
+/// Returns the first day of the month following the given date
+fn next_month(date: NaiveDate) -> NaiveDate {
+    if date.month() == 12 {
+        NaiveDate::from_ymd_opt(date.year() + 1, 1, 1).unwrap()
+    } else {
+        NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 1).unwrap()
+    }
+}
+
+/// Builds one snapshot per calendar month (UTC) between `start` and `end`,
+/// defaulting to the full span of the visit history
+pub fn rebuild_monthly_snapshots(
+    conn: &DatabaseConnection,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<Vec<GraphSnapshot>> {
+    conn.transaction(|tx| {
+        let (first, last): (Option<i64>, Option<i64>) = tx.query_row(
+            "SELECT MIN(visited_at), MAX(visited_at) FROM visit",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let start = start.map(|s| s.timestamp()).or(first);
+        let end = end.map(|e| e.timestamp()).or(last);
+
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) if start <= end => (start, end),
+            _ => return Ok(Vec::new()),
+        };
+
+        let first_day = DateTime::from_timestamp(start, 0)
+            .ok_or_else(|| DatabaseError::Query(format!("Invalid timestamp: {}", start)))?
+            .date_naive()
+            .with_day(1)
+            .unwrap();
+
+        let mut snapshots = Vec::new();
+        let mut month = first_day;
+
+        loop {
+            let month_start = Utc.from_utc_datetime(&month.and_hms_opt(0, 0, 0).unwrap());
+            if month_start.timestamp() > end {
+                break;
+            }
+
+            let following = next_month(month);
+            let month_end = Utc.from_utc_datetime(&following.and_hms_opt(0, 0, 0).unwrap())
+                - chrono::Duration::seconds(1);
+
+            let label = month.format("%Y-%m").to_string();
+            let (id, _) = build_snapshot(tx, month_start.timestamp(), month_end.timestamp(), &label)?;
+
+            snapshots.push(GraphSnapshot { id, label, start: month_start, end: month_end });
+            month = following;
+        }
+
+        Ok(snapshots)
+    })
+}
+
+/// Lists stored snapshots ordered by window start, optionally restricted to monthly ones
+pub fn list_snapshots(conn: &DatabaseConnection, monthly_only: bool) -> Result<Vec<GraphSnapshot>> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT id, label, start_at, end_at FROM graph_snapshot ORDER BY start_at, label"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?))
+        })?;
+
+        let mut snapshots = Vec::new();
+        for row in rows {
+            let (id, label, start, end) = row?;
+
+            // Monthly snapshots are labelled YYYY-MM
+            if monthly_only && NaiveDate::parse_from_str(&format!("{}-01", label), "%Y-%m-%d").is_err() {
+                continue;
+            }
+
+            snapshots.push(GraphSnapshot {
+                id,
+                label,
+                start: DateTime::from_timestamp(start, 0).unwrap_or_default(),
+                end: DateTime::from_timestamp(end, 0).unwrap_or_default(),
+            });
+        }
+
+        Ok(snapshots)
+    })
+}
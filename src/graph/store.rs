@@ -0,0 +1,177 @@
+// Knowledge Graph - Store
+// Loads (filtered) nodes and edges from the graph tables, and the neighbors
+// of a single node
+
+use std::collections::HashSet;
+
+use rusqlite::{params, OptionalExtension, ToSql};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{DatabaseConnection, Result};
+use crate::db::query::QueryBuilder;
+use super::builder::MAIN_GRAPH;
+use super::models::{NodeType, EdgeType, GraphNode, GraphEdge};
+
+/// Filters applied when loading the graph
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GraphFilter {
+    /// Snapshot to load (the main graph when None)
+    pub snapshot_id: Option<i64>,
+    /// Only include these node types (all when None)
+    pub node_types: Option<Vec<NodeType>>,
+    /// Only include these edge types (all when None)
+    pub edge_types: Option<Vec<EdgeType>>,
+    /// Only include nodes with at least this weight
+    pub min_weight: Option<f64>,
+    /// Keep only the heaviest nodes up to this count
+    pub max_nodes: Option<usize>,
+}
+
+/// A loaded graph
+#[derive(Debug, Clone, Default)]
+pub struct Graph {
+    /// Nodes matching the filter
+    pub nodes: Vec<GraphNode>,
+    /// Edges whose endpoints are both in `nodes`
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Default number of neighbors returned
+pub const DEFAULT_NEIGHBOR_LIMIT: usize = 25;
+
+/// A node connected to another by one edge
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNeighbor {
+    /// The connected node
+    pub node: GraphNode,
+    /// Kind of the connecting edge
+    pub edge_type: EdgeType,
+    /// Strength of the connecting edge
+    pub weight: f64,
+    /// True if the edge points from the queried node to this one
+    pub outgoing: bool,
+}
+
+/// Builds an `column IN (?, ?, ...)` condition with bound values
+fn in_condition(column: &str, values: Vec<&'static str>) -> (String, Vec<Box<dyn ToSql>>) {
+    let placeholders = vec!["?"; values.len()].join(", ");
+    let params = values.into_iter()
+        .map(|v| Box::new(v.to_string()) as Box<dyn ToSql>)
+        .collect();
+    (format!("{} IN ({})", column, placeholders), params)
+}
+
+/// Loads nodes and edges matching the filter
+pub fn load_graph(conn: &DatabaseConnection, filter: &GraphFilter) -> Result<Graph> {
+    conn.with_connection(|c| {
+        let mut node_query = QueryBuilder::new(
+            "SELECT n.id, n.node_type, n.key, n.label, n.weight, n.x, n.y, n.wikidata_id FROM node n"
+        );
+
+        let snapshot_id = filter.snapshot_id.unwrap_or(MAIN_GRAPH);
+        node_query.filter("n.snapshot_id = ?", snapshot_id);
+
+        if let Some(ref node_types) = filter.node_types {
+            let (condition, params) = in_condition("n.node_type", node_types.iter().map(|t| t.as_str()).collect());
+            node_query.filter_many(&condition, params);
+        }
+
+        if let Some(min_weight) = filter.min_weight {
+            node_query.filter("n.weight >= ?", min_weight);
+        }
+
+        node_query.order_by("n.weight DESC, n.id ASC");
+
+        if let Some(max_nodes) = filter.max_nodes {
+            node_query.limit(max_nodes);
+        }
+
+        let nodes: Vec<GraphNode> = node_query.fetch_all(c, |row| {
+            let node_type: String = row.get(1)?;
+            Ok((row.get(0)?, node_type, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?))
+        })?
+        .into_iter()
+        .filter_map(|(id, node_type, key, label, weight, x, y, wikidata_id)| {
+            NodeType::parse(&node_type).map(|node_type| GraphNode { id, node_type, key, label, weight, wikidata_id, x, y })
+        })
+        .collect();
+
+        let node_ids: HashSet<i64> = nodes.iter().map(|n| n.id).collect();
+
+        let mut edge_query = QueryBuilder::new(
+            "SELECT e.id, e.source_id, e.target_id, e.edge_type, e.weight FROM edge e"
+        );
+
+        edge_query.filter("e.snapshot_id = ?", snapshot_id);
+
+        if let Some(ref edge_types) = filter.edge_types {
+            let (condition, params) = in_condition("e.edge_type", edge_types.iter().map(|t| t.as_str()).collect());
+            edge_query.filter_many(&condition, params);
+        }
+
+        let edges: Vec<GraphEdge> = edge_query.fetch_all(c, |row| {
+            let edge_type: String = row.get(3)?;
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, edge_type, row.get(4)?))
+        })?
+        .into_iter()
+        .filter(|(_, source_id, target_id, _, _)| node_ids.contains(source_id) && node_ids.contains(target_id))
+        .filter_map(|(id, source_id, target_id, edge_type, weight)| {
+            EdgeType::parse(&edge_type).map(|edge_type| GraphEdge { id, source_id, target_id, edge_type, weight })
+        })
+        .collect();
+
+        Ok(Graph { nodes, edges })
+    })
+}
+
+/// Gets the nodes connected to the node of `node_type` with `key` in the main
+/// graph, strongest edges first; empty if the node is not in the graph
+pub fn get_neighbors(conn: &DatabaseConnection, node_type: NodeType, key: &str, limit: usize) -> Result<Vec<GraphNeighbor>> {
+    conn.with_connection(|c| {
+        let node_id: Option<i64> = c.query_row(
+            "SELECT id FROM node WHERE snapshot_id = ? AND node_type = ? AND key = ?",
+            params![MAIN_GRAPH, node_type.as_str(), key],
+            |row| row.get(0),
+        ).optional()?;
+        let Some(node_id) = node_id else {
+            return Ok(Vec::new());
+        };
+
+        let mut stmt = c.prepare(
+            "SELECT n.id, n.node_type, n.key, n.label, n.weight, n.x, n.y,
+                    e.edge_type, e.weight, e.source_id = ?1, n.wikidata_id
+             FROM edge e
+             JOIN node n ON n.id = CASE WHEN e.source_id = ?1 THEN e.target_id ELSE e.source_id END
+             WHERE e.source_id = ?1 OR e.target_id = ?1
+             ORDER BY e.weight DESC, n.weight DESC
+             LIMIT ?2"
+        )?;
+        let rows = stmt.query_map(params![node_id, limit as i64], |row| {
+            let node_type: String = row.get(1)?;
+            let edge_type: String = row.get(7)?;
+            Ok((
+                NodeType::parse(&node_type).map(|node_type| GraphNode {
+                    id: row.get(0)?,
+                    node_type,
+                    key: row.get(2)?,
+                    label: row.get(3)?,
+                    weight: row.get(4)?,
+                    wikidata_id: row.get(10)?,
+                    x: row.get(5)?,
+                    y: row.get(6)?,
+                }),
+                EdgeType::parse(&edge_type),
+                row.get::<_, f64>(8)?,
+                row.get::<_, bool>(9)?,
+            ))
+        })?;
+
+        let mut neighbors = Vec::new();
+        for row in rows {
+            if let (Some(node), Some(edge_type), weight, outgoing) = row? {
+                neighbors.push(GraphNeighbor { node, edge_type, weight, outgoing });
+            }
+        }
+        Ok(neighbors)
+    })
+}
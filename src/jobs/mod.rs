@@ -0,0 +1,377 @@
+// Jobs Module
+// Persistent queue of long-running work (imports, enrichment, graph rebuilds,
+// maintenance) with progress, cancellation and a background worker
+
+// Module organization:
+// - worker.rs: Background worker running queued jobs
+// - schedule.rs: Periodic incremental import of the local Safari history, the
+//   nightly script run, scheduled reports, content change checks and domain
+//   enrichment
+// - watch.rs: Filesystem watcher importing history files as they change
+
+pub mod schedule;
+pub mod watch;
+pub mod worker;
+
+pub use schedule::{AutoImportSettings, AUTO_IMPORT_SETTING};
+pub use worker::{run_worker, JobSignal};
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::db::{DatabaseConnection, DatabaseError, Result};
+
+/// Default number of jobs returned by `list_jobs`
+pub const DEFAULT_JOB_LIST: usize = 50;
+
+/// Lifecycle state of a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for the worker
+    Queued,
+    /// Being worked on
+    Running,
+    /// Finished successfully
+    Done,
+    /// Finished with an error
+    Failed,
+    /// Cancelled before or while running
+    Cancelled,
+}
+
+impl JobState {
+    /// Name stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        }
+    }
+
+    /// Parses a stored state name
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "queued" => Ok(JobState::Queued),
+            "running" => Ok(JobState::Running),
+            "done" => Ok(JobState::Done),
+            "failed" => Ok(JobState::Failed),
+            "cancelled" => Ok(JobState::Cancelled),
+            other => Err(DatabaseError::Data(format!("Unknown job state '{}'", other))),
+        }
+    }
+}
+
+/// Work a job performs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobRequest {
+    /// Import Safari history files
+    Import {
+        file_paths: Vec<String>,
+        device_names: Option<Vec<String>>,
+    },
+    /// Import the visits a history file gained since it was last imported
+    IncrementalImport {
+        file_path: String,
+        device_name: Option<String>,
+    },
+    /// Work through the enrichment queue
+    Enrichment,
+    /// Rebuild the knowledge graph
+    RebuildGraph,
+    /// Checkpoint, vacuum and analyze the database
+    Compact,
+    /// Run the nightly user scripts over every page
+    NightlyScripts,
+    /// Run the report templates whose schedule is due
+    ScheduledReports,
+    /// Re-fetch fingerprinted pages and flag the ones whose content changed
+    ContentChecks,
+    /// Look up the registrar, registration date and hosting country of domains
+    DomainEnrichment,
+}
+
+impl JobRequest {
+    /// Kind stored in the job table
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JobRequest::Import { .. } => "import",
+            JobRequest::IncrementalImport { .. } => "incremental_import",
+            JobRequest::Enrichment => "enrichment",
+            JobRequest::RebuildGraph => "rebuild_graph",
+            JobRequest::Compact => "compact",
+            JobRequest::NightlyScripts => "nightly_scripts",
+            JobRequest::ScheduledReports => "scheduled_reports",
+            JobRequest::ContentChecks => "content_checks",
+            JobRequest::DomainEnrichment => "domain_enrichment",
+        }
+    }
+}
+
+/// A recorded job
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    /// Job identifier
+    pub id: i64,
+    /// What the job does
+    pub request: JobRequest,
+    /// Lifecycle state
+    pub state: JobState,
+    /// Progress from 0.0 to 1.0
+    pub progress: f64,
+    /// What the job is doing right now
+    pub message: Option<String>,
+    /// Why the job failed
+    pub error: Option<String>,
+    /// Result of a finished job
+    pub result: Option<JsonValue>,
+    /// Cancellation was requested while the job was running
+    pub cancel_requested: bool,
+    /// When the job was created
+    pub created_at: DateTime<Utc>,
+    /// When the job started running
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the job finished
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Columns of a job row
+const SELECT_JOB: &str =
+    "SELECT id, request, state, progress, message, error, result, cancel_requested,
+            created_at, started_at, finished_at
+     FROM job";
+
+/// Decodes a text column holding JSON
+fn json_column<T: serde::de::DeserializeOwned>(row: &rusqlite::Row, index: usize) -> rusqlite::Result<T> {
+    let json: String = row.get(index)?;
+    serde_json::from_str(&json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+/// Maps a row selected by `SELECT_JOB`
+fn job_from_row(row: &rusqlite::Row) -> rusqlite::Result<Job> {
+    let state: String = row.get(2)?;
+    let state = JobState::parse(&state).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+
+    Ok(Job {
+        id: row.get(0)?,
+        request: json_column(row, 1)?,
+        state,
+        progress: row.get(3)?,
+        message: row.get(4)?,
+        error: row.get(5)?,
+        result: row.get::<_, Option<String>>(6)?.and_then(|json| serde_json::from_str(&json).ok()),
+        cancel_requested: row.get(7)?,
+        created_at: DateTime::from_timestamp(row.get(8)?, 0).unwrap_or_default(),
+        started_at: row.get::<_, Option<i64>>(9)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+        finished_at: row.get::<_, Option<i64>>(10)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+    })
+}
+
+/// Loads one job
+fn load_job(c: &Connection, id: i64) -> Result<Job> {
+    c.query_row(&format!("{} WHERE id = ?", SELECT_JOB), [id], job_from_row)
+        .optional()?
+        .ok_or_else(|| DatabaseError::NotFound(format!("Job {} does not exist", id)))
+}
+
+/// Serializes a request for the job table
+fn request_json(request: &JobRequest) -> Result<String> {
+    serde_json::to_string(request)
+        .map_err(|e| DatabaseError::Data(format!("Failed to serialize job request: {}", e)))
+}
+
+/// Queues a job for the background worker. An identical job that is still
+/// queued is returned instead of queueing it twice.
+pub fn enqueue_job(conn: &DatabaseConnection, request: &JobRequest) -> Result<Job> {
+    let json = request_json(request)?;
+
+    conn.transaction(|tx| {
+        let queued: Option<i64> = tx.query_row(
+            "SELECT id FROM job WHERE request = ? AND state = 'queued' ORDER BY id LIMIT 1",
+            [&json],
+            |row| row.get(0),
+        ).optional()?;
+
+        let id = match queued {
+            Some(id) => id,
+            None => {
+                tx.execute(
+                    "INSERT INTO job (kind, request, state, created_at) VALUES (?, ?, 'queued', ?)",
+                    params![request.kind(), json, Utc::now().timestamp()],
+                )?;
+                tx.last_insert_rowid()
+            },
+        };
+
+        load_job(tx, id)
+    })
+}
+
+/// Records a job that runs right away on the caller's thread
+pub fn start_job(conn: &DatabaseConnection, request: &JobRequest) -> Result<Job> {
+    let json = request_json(request)?;
+    let now = Utc::now().timestamp();
+
+    conn.with_connection(|c| {
+        c.execute(
+            "INSERT INTO job (kind, request, state, created_at, started_at) VALUES (?, ?, 'running', ?, ?)",
+            params![request.kind(), json, now, now],
+        )?;
+        load_job(c, c.last_insert_rowid())
+    })
+}
+
+/// Marks the oldest queued job as running and returns it
+pub(crate) fn claim_next(conn: &DatabaseConnection) -> Result<Option<Job>> {
+    conn.transaction(|tx| {
+        let id: Option<i64> = tx.query_row(
+            "SELECT id FROM job WHERE state = 'queued' ORDER BY id LIMIT 1",
+            [],
+            |row| row.get(0),
+        ).optional()?;
+
+        match id {
+            Some(id) => {
+                tx.execute(
+                    "UPDATE job SET state = 'running', started_at = ? WHERE id = ?",
+                    params![Utc::now().timestamp(), id],
+                )?;
+                load_job(tx, id).map(Some)
+            },
+            None => Ok(None),
+        }
+    })
+}
+
+/// Gets one job
+pub fn get_job(conn: &DatabaseConnection, id: i64) -> Result<Job> {
+    conn.with_connection(|c| load_job(c, id))
+}
+
+/// Lists the most recent jobs, newest first
+pub fn list_jobs(conn: &DatabaseConnection, limit: usize) -> Result<Vec<Job>> {
+    conn.with_connection(|c| {
+        let mut stmt = c.prepare(&format!("{} ORDER BY id DESC LIMIT ?", SELECT_JOB))?;
+        let rows = stmt.query_map([limit as i64], job_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })
+}
+
+/// Cancels a job: a queued job is cancelled right away, a running one is
+/// asked to stop and is cancelled once its work notices
+pub fn cancel_job(conn: &DatabaseConnection, id: i64) -> Result<Job> {
+    conn.transaction(|tx| {
+        let job = load_job(tx, id)?;
+        match job.state {
+            JobState::Queued => {
+                tx.execute(
+                    "UPDATE job SET state = 'cancelled', finished_at = ? WHERE id = ?",
+                    params![Utc::now().timestamp(), id],
+                )?;
+            },
+            JobState::Running => {
+                tx.execute("UPDATE job SET cancel_requested = 1 WHERE id = ?", [id])?;
+            },
+            _ => return Err(DatabaseError::Data(format!("Job {} has already finished", id))),
+        }
+        load_job(tx, id)
+    })
+}
+
+/// Requeues jobs left running by a shutdown; returns how many were requeued
+pub fn recover_interrupted(conn: &DatabaseConnection) -> Result<usize> {
+    conn.with_connection(|c| {
+        Ok(c.execute(
+            "UPDATE job SET state = CASE WHEN cancel_requested = 1 THEN 'cancelled' ELSE 'queued' END,
+                            started_at = NULL, progress = 0, message = NULL
+             WHERE state = 'running'",
+            [],
+        )?)
+    })
+}
+
+/// Handle given to a job's work to report progress and notice cancellation
+pub struct JobContext<'a> {
+    conn: &'a DatabaseConnection,
+    id: i64,
+    on_change: &'a (dyn Fn(&Job) + Sync),
+}
+
+impl<'a> JobContext<'a> {
+    /// Job identifier
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Records progress (0.0 to 1.0) and what the job is doing, notifying listeners.
+    /// Progress is best effort, so failures to record it are ignored.
+    pub fn progress(&self, fraction: f64, message: &str) {
+        let job = self.conn.with_connection(|c| {
+            c.execute(
+                "UPDATE job SET progress = ?, message = ? WHERE id = ?",
+                params![fraction.clamp(0.0, 1.0), message, self.id],
+            )?;
+            load_job(c, self.id)
+        });
+        if let Ok(job) = job {
+            (self.on_change)(&job);
+        }
+    }
+
+    /// Returns true once cancellation was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.conn.with_connection(|c| {
+            Ok(c.query_row("SELECT cancel_requested FROM job WHERE id = ?", [self.id], |row| row.get::<_, bool>(0))?)
+        }).unwrap_or(false)
+    }
+}
+
+/// Runs the work of a started or claimed job and records how it ended: done
+/// with its result, failed with the error, or cancelled if cancellation was
+/// requested meanwhile. Listeners are notified at start and end.
+pub fn run_job<T, E, F>(conn: &DatabaseConnection, job: &Job, on_change: &(dyn Fn(&Job) + Sync), work: F) -> std::result::Result<T, E>
+where
+    T: Serialize,
+    E: fmt::Display + From<DatabaseError>,
+    F: FnOnce(&JobContext) -> std::result::Result<T, E>,
+{
+    on_change(job);
+
+    let context = JobContext { conn, id: job.id, on_change };
+    let outcome = work(&context);
+    let cancelled = context.is_cancelled();
+
+    let (state, result, error) = match &outcome {
+        Ok(value) => (
+            if cancelled { JobState::Cancelled } else { JobState::Done },
+            serde_json::to_string(value).ok(),
+            None,
+        ),
+        Err(_) if cancelled => (JobState::Cancelled, None, None),
+        Err(e) => (JobState::Failed, None, Some(e.to_string())),
+    };
+
+    let finished = conn.with_connection(|c| {
+        c.execute(
+            "UPDATE job SET state = ?, progress = CASE WHEN ? = 'done' THEN 1 ELSE progress END,
+                            result = ?, error = ?, finished_at = ?
+             WHERE id = ?",
+            params![state.as_str(), state.as_str(), result, error, Utc::now().timestamp(), job.id],
+        )?;
+        load_job(c, job.id)
+    })?;
+    on_change(&finished);
+
+    outcome
+}
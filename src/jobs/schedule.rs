@@ -0,0 +1,229 @@
+// Jobs - Schedule
+// Periodic incremental import of this Mac's Safari history, the nightly run
+// of user scripts, scheduled report templates, content change checks and
+// domain enrichment
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Days, Local, Utc};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use crate::db::settings::get_setting;
+use crate::db::{DatabaseConnection, Result};
+use crate::web::changes::get_content_check_settings;
+use crate::web::provenance::get_domain_enrichment_settings;
+use super::{enqueue_job, Job, JobRequest};
+
+/// Settings key of the automatic import schedule
+pub const AUTO_IMPORT_SETTING: &str = "auto_import";
+
+/// Minutes between automatic imports unless configured
+pub const DEFAULT_AUTO_IMPORT_MINUTES: u32 = 60;
+
+/// Local hour after which the nightly scripts run
+pub const NIGHTLY_HOUR: u32 = 3;
+
+/// Schedule for re-importing the local Safari history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoImportSettings {
+    /// Whether automatic imports run
+    pub enabled: bool,
+    /// Minutes between imports
+    pub interval_minutes: u32,
+    /// Also import shortly after the history file changes
+    pub watch: bool,
+    /// History file to import; None is Safari's own `~/Library/Safari/History.db`
+    pub history_path: Option<String>,
+    /// Device the imported visits are attributed to
+    pub device_name: Option<String>,
+}
+
+impl Default for AutoImportSettings {
+    fn default() -> Self {
+        AutoImportSettings {
+            enabled: false,
+            interval_minutes: DEFAULT_AUTO_IMPORT_MINUTES,
+            watch: true,
+            history_path: None,
+            device_name: None,
+        }
+    }
+}
+
+impl AutoImportSettings {
+    /// History file to import, if one is configured or can be found
+    pub fn history_file(&self) -> Option<PathBuf> {
+        self.history_path.as_ref().map(PathBuf::from).or_else(default_history_path)
+    }
+}
+
+/// Safari's history file in the user's Library
+pub fn default_history_path() -> Option<PathBuf> {
+    tauri::api::path::home_dir().map(|home| home.join("Library").join("Safari").join("History.db"))
+}
+
+/// Gets the automatic import schedule
+pub fn get_auto_import_settings(conn: &DatabaseConnection) -> Result<AutoImportSettings> {
+    conn.with_connection(|c| Ok(get_setting(c, AUTO_IMPORT_SETTING)?.unwrap_or_default()))
+}
+
+/// Queues an incremental import of the configured history file, if automatic
+/// imports are on
+pub fn enqueue_import(conn: &DatabaseConnection, settings: &AutoImportSettings) -> Result<Option<Job>> {
+    let path = match settings.history_file() {
+        Some(path) if settings.enabled => path,
+        _ => return Ok(None),
+    };
+
+    enqueue_job(conn, &JobRequest::IncrementalImport {
+        file_path: path.display().to_string(),
+        device_name: settings.device_name.clone(),
+    }).map(Some)
+}
+
+/// Queues an incremental import of the Safari history when automatic imports
+/// are on and the interval has passed since the last one was queued
+pub fn enqueue_due(conn: &DatabaseConnection) -> Result<Option<Job>> {
+    let settings = get_auto_import_settings(conn)?;
+    if !settings.enabled {
+        return Ok(None);
+    }
+
+    let last_queued: Option<i64> = conn.with_connection(|c| {
+        Ok(c.query_row(
+            "SELECT MAX(created_at) FROM job WHERE kind = 'incremental_import'",
+            [],
+            |row| row.get(0),
+        )?)
+    })?;
+    let interval = i64::from(settings.interval_minutes.max(1)) * 60;
+    if last_queued.is_some_and(|last| Utc::now().timestamp() - last < interval) {
+        return Ok(None);
+    }
+
+    enqueue_import(conn, &settings)
+}
+
+/// Queues the nightly scripts once a day, the first time the worker checks
+/// after `NIGHTLY_HOUR`, if any is enabled
+pub fn enqueue_nightly(conn: &DatabaseConnection) -> Result<Option<Job>> {
+    let (has_scripts, last_queued): (bool, Option<i64>) = conn.with_connection(|c| {
+        Ok(c.query_row(
+            "SELECT EXISTS (SELECT 1 FROM script WHERE hook = 'nightly' AND enabled = 1),
+                    (SELECT MAX(created_at) FROM job WHERE kind = 'nightly_scripts')",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?)
+    })?;
+    if !has_scripts {
+        return Ok(None);
+    }
+
+    // Start of the current night: today's run hour, or yesterday's before it
+    let now = Local::now();
+    let night = now.date_naive().and_hms_opt(NIGHTLY_HOUR, 0, 0)
+        .and_then(|time| time.and_local_timezone(Local).earliest())
+        .map(|night| if night > now { night - Days::new(1) } else { night });
+    let due = match night {
+        Some(night) => last_queued.is_none_or(|last| last < night.timestamp()),
+        None => false,
+    };
+    if !due {
+        return Ok(None);
+    }
+
+    enqueue_job(conn, &JobRequest::NightlyScripts).map(Some)
+}
+
+/// Queues a run of the report templates when one is due by its schedule
+pub fn enqueue_reports(conn: &DatabaseConnection) -> Result<Option<Job>> {
+    let now = Utc::now().timestamp();
+    let due: bool = conn.with_connection(|c| {
+        Ok(c.query_row(
+            "SELECT EXISTS (
+                 SELECT 1 FROM report_template
+                 WHERE (schedule = 'daily' AND COALESCE(last_run_at, 0) <= ?1 - 86400)
+                    OR (schedule = 'weekly' AND COALESCE(last_run_at, 0) <= ?1 - 604800)
+             )",
+            [now],
+            |row| row.get(0),
+        )?)
+    })?;
+    if !due {
+        return Ok(None);
+    }
+
+    enqueue_job(conn, &JobRequest::ScheduledReports).map(Some)
+}
+
+/// Queues a content check at most once a day, when checks are on and some
+/// fingerprinted page is due
+pub fn enqueue_content_checks(conn: &DatabaseConnection) -> Result<Option<Job>> {
+    let settings = get_content_check_settings(conn)?;
+    if !settings.enabled {
+        return Ok(None);
+    }
+
+    let now = Utc::now().timestamp();
+    let due_before = now - i64::from(settings.interval_days) * 86400;
+    let due: bool = conn.with_connection(|c| {
+        Ok(c.query_row(
+            "SELECT EXISTS (SELECT 1 FROM content_fingerprint WHERE checked_at <= ?1)
+                AND COALESCE((SELECT MAX(created_at) FROM job WHERE kind = 'content_checks'), 0) <= ?2",
+            params![due_before, now - 86400],
+            |row| row.get(0),
+        )?)
+    })?;
+    if !due {
+        return Ok(None);
+    }
+
+    enqueue_job(conn, &JobRequest::ContentChecks).map(Some)
+}
+
+/// Queues a domain enrichment at most once a day, when it is on and some
+/// domain was never looked up or is due again
+pub fn enqueue_domain_enrichment(conn: &DatabaseConnection) -> Result<Option<Job>> {
+    let settings = get_domain_enrichment_settings(conn)?;
+    if !settings.enabled {
+        return Ok(None);
+    }
+
+    let now = Utc::now().timestamp();
+    let due_before = now - i64::from(settings.interval_days) * 86400;
+    let due: bool = conn.with_connection(|c| {
+        Ok(c.query_row(
+            "SELECT EXISTS (
+                    SELECT 1 FROM domain_stats s
+                    LEFT JOIN domain_provenance p ON p.domain = s.domain
+                    WHERE s.domain LIKE '%.%' AND (p.domain IS NULL OR p.checked_at <= ?1)
+                )
+                AND COALESCE((SELECT MAX(created_at) FROM job WHERE kind = 'domain_enrichment'), 0) <= ?2",
+            params![due_before, now - 86400],
+            |row| row.get(0),
+        )?)
+    })?;
+    if !due {
+        return Ok(None);
+    }
+
+    enqueue_job(conn, &JobRequest::DomainEnrichment).map(Some)
+}
+
+/// Time of the latest visit imported from a file, or from the file it is a
+/// copy of, where an incremental import of it resumes; None if nothing was
+/// imported from it yet
+pub fn import_watermark(conn: &DatabaseConnection, file_path: &str) -> Result<Option<DateTime<Utc>>> {
+    let latest: Option<i64> = conn.with_connection(|c| {
+        Ok(c.query_row(
+            "SELECT MAX(visited_at) FROM visit
+             WHERE source_file = ?1
+                OR source_file = (SELECT canonical_path FROM import_source WHERE path = ?1)",
+            [file_path],
+            |row| row.get(0),
+        )?)
+    })?;
+    Ok(latest.and_then(|ts| DateTime::from_timestamp(ts, 0)))
+}
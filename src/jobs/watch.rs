@@ -0,0 +1,60 @@
+// Jobs - Watch
+// Notices writes to a browser history file so it can be re-imported promptly
+
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Quiet period after the last write before the change is reported, since
+/// browsers write their history in bursts
+const DEBOUNCE: Duration = Duration::from_secs(10);
+
+/// Calls `on_change` once a burst of writes to the history file at `path`
+/// (or its write-ahead log) has settled. Watching stops when the returned
+/// watcher is dropped.
+pub fn watch_history_file<F>(path: &Path, on_change: F) -> notify::Result<RecommendedWatcher>
+where
+    F: Fn() + Send + 'static,
+{
+    let name = path.file_name()
+        .ok_or_else(|| notify::Error::generic("History path has no file name"))?;
+    // Readers only touch the shared-memory file, so our own imports don't trigger a change
+    let mut wal = OsString::from(name);
+    wal.push("-wal");
+    let watched = [name.to_os_string(), wal];
+
+    let (sender, receiver) = mpsc::channel::<()>();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else { return };
+        let written = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+        let ours = event.paths.iter()
+            .any(|path| path.file_name().is_some_and(|name| watched.iter().any(|w| w == name)));
+        if written && ours {
+            let _ = sender.send(());
+        }
+    })?;
+
+    // The directory is watched because SQLite replaces and truncates its files
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    // Ends when the watcher, and with it the sender, is dropped
+    thread::spawn(move || {
+        while receiver.recv().is_ok() {
+            loop {
+                match receiver.recv_timeout(DEBOUNCE) {
+                    Ok(()) => continue,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            on_change();
+        }
+    });
+
+    Ok(watcher)
+}
@@ -0,0 +1,90 @@
+// Jobs - Worker
+// Background thread running queued jobs one at a time
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, RwLock};
+use std::time::Duration;
+
+use serde_json::Value as JsonValue;
+
+use crate::db::DatabaseConnection;
+use crate::error::AppError;
+use super::{claim_next, run_job, schedule, Job, JobContext, JobRequest};
+
+/// How long the worker sleeps when nothing wakes it
+const IDLE_POLL: Duration = Duration::from_secs(30);
+
+/// Wakes the worker when jobs are queued
+#[derive(Debug, Default)]
+pub struct JobSignal {
+    started: AtomicBool,
+    pending: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl JobSignal {
+    /// Returns true the first time it is called, so only one worker is spawned
+    pub fn start(&self) -> bool {
+        !self.started.swap(true, Ordering::SeqCst)
+    }
+
+    /// Wakes the worker to look for queued jobs
+    pub fn notify(&self) {
+        *self.pending.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        self.wake.notify_one();
+    }
+
+    /// Waits until notified or the timeout passes
+    fn wait(&self, timeout: Duration) {
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut pending, _) = self.wake.wait_timeout_while(pending, timeout, |pending| !*pending)
+            .unwrap_or_else(|e| e.into_inner());
+        *pending = false;
+    }
+}
+
+/// Runs queued jobs forever, oldest first, queueing scheduled imports as they
+/// fall due. The connection is read from `db` for every job, so the worker
+/// waits while the database is closed or being re-keyed. `execute` performs a
+/// job's work; its error becomes the job's error.
+pub fn run_worker<F>(
+    db: &RwLock<Option<DatabaseConnection>>,
+    signal: &JobSignal,
+    on_change: &(dyn Fn(&Job) + Sync),
+    execute: F,
+) -> !
+where
+    F: Fn(&DatabaseConnection, &JobContext, &JobRequest) -> Result<JsonValue, AppError>,
+{
+    loop {
+        let conn = db.read().ok().and_then(|guard| guard.as_ref().cloned());
+        if let Some(conn) = &conn {
+            if let Ok(Some(job)) = schedule::enqueue_due(conn) {
+                on_change(&job);
+            }
+            if let Ok(Some(job)) = schedule::enqueue_nightly(conn) {
+                on_change(&job);
+            }
+            if let Ok(Some(job)) = schedule::enqueue_reports(conn) {
+                on_change(&job);
+            }
+            if let Ok(Some(job)) = schedule::enqueue_content_checks(conn) {
+                on_change(&job);
+            }
+            if let Ok(Some(job)) = schedule::enqueue_domain_enrichment(conn) {
+                on_change(&job);
+            }
+        }
+        let job = conn.as_ref().and_then(|conn| claim_next(conn).ok().flatten());
+
+        match (conn, job) {
+            (Some(conn), Some(job)) => {
+                // The outcome is recorded on the job itself
+                let _: Result<JsonValue, AppError> = run_job(&conn, &job, on_change, |context| {
+                    execute(&conn, context, &job.request)
+                });
+            },
+            _ => signal.wait(IDLE_POLL),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// Links
+// Opening history pages in the default browser and copying them as text
+
+use serde::Deserialize;
+use tauri::{ClipboardManager, Manager};
+use url::Url as UrlParser;
+
+use crate::db::operations::PageLink;
+use crate::db::reading_list::ReadingListItem;
+use crate::error::AppError;
+
+/// How copied links are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyFormat {
+    /// `- [Title](url)` per line
+    #[default]
+    Markdown,
+    /// One URL per line
+    Plain,
+}
+
+/// Escapes the characters that would end a Markdown link early
+fn markdown_link(link: &PageLink) -> String {
+    let title = link.title.as_deref().map(str::trim).filter(|title| !title.is_empty()).unwrap_or(&link.url);
+    let title = title.replace('\\', "\\\\").replace('[', "\\[").replace(']', "\\]");
+    let url = link.url.replace(' ', "%20").replace('(', "%28").replace(')', "%29");
+    format!("- [{}]({})", title, url)
+}
+
+/// Writes the links as a list
+pub fn format_links(links: &[PageLink], format: CopyFormat) -> String {
+    let lines: Vec<String> = match format {
+        CopyFormat::Markdown => links.iter().map(markdown_link).collect(),
+        CopyFormat::Plain => links.iter().map(|link| link.url.clone()).collect(),
+    };
+    lines.join("\n")
+}
+
+/// Writes a reading list as Markdown, one section per topic in the order the
+/// topics first appear, each page with its reading time
+pub fn format_reading_list(items: &[ReadingListItem]) -> String {
+    let mut topics: Vec<&str> = Vec::new();
+    for item in items {
+        if !topics.contains(&item.topic.as_str()) {
+            topics.push(&item.topic);
+        }
+    }
+
+    let mut markdown = "# Reading list\n".to_string();
+    for topic in topics {
+        let lines: Vec<String> = items.iter()
+            .filter(|item| item.topic == topic)
+            .map(|item| {
+                let link = PageLink { id: item.url_id.clone(), url: item.url.clone(), title: item.title.clone() };
+                let minutes = (item.reading_time_sec + 30) / 60;
+                format!("{} ({}, {} min read)", markdown_link(&link), item.domain, minutes.max(1))
+            })
+            .collect();
+        markdown.push_str(&format!("\n## {}\n\n{}\n", topic, lines.join("\n")));
+    }
+    markdown
+}
+
+/// Opens a page in the default browser; only web pages are opened, so a
+/// stored `file:` or custom-scheme URL can't launch anything else
+pub fn open_in_browser(app_handle: &tauri::AppHandle, url: &str) -> Result<(), AppError> {
+    let parsed = UrlParser::parse(url)
+        .map_err(|e| AppError::invalid_input(format!("Invalid URL {}: {}", url, e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(AppError::invalid_input(format!("Only web pages can be opened, not {}", url)));
+    }
+
+    tauri::api::shell::open(&app_handle.shell_scope(), parsed.as_str(), None)
+        .map_err(|e| AppError::internal(format!("Failed to open {}: {}", url, e)))
+}
+
+/// Puts text on the clipboard
+pub fn copy_to_clipboard(app_handle: &tauri::AppHandle, text: String) -> Result<(), AppError> {
+    app_handle.clipboard_manager().write_text(text)
+        .map_err(|e| AppError::internal(format!("Failed to copy to the clipboard: {}", e)))
+}
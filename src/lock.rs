@@ -0,0 +1,275 @@
+// Application Lock
+// Passphrase gate in front of every command, verified against the OS keychain,
+// with auto-lock after a period of inactivity. Wrong passphrases make the next
+// attempt wait longer and longer.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, ErrorKind};
+
+/// Keychain service the lock secret is stored under
+const KEYCHAIN_SERVICE: &str = "safari-history-knowledge-graph";
+
+/// Keychain account the lock secret is stored under
+const KEYCHAIN_ACCOUNT: &str = "app-lock";
+
+/// Wrong passphrases allowed before attempts are delayed
+const FREE_ATTEMPTS: u32 = 3;
+
+/// Wait after the first delayed wrong passphrase; doubles with each further one
+const FIRST_DELAY: Duration = Duration::from_secs(5);
+
+/// Longest wait between attempts
+const MAX_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// Minutes of inactivity before the app locks itself, unless configured
+pub const DEFAULT_AUTO_LOCK_MINUTES: u64 = 15;
+
+/// What the keychain holds: an Argon2 hash of the passphrase, never the passphrase
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LockSecret {
+    /// PHC string, salt and parameters included
+    hash: String,
+    /// None disables auto-lock
+    auto_lock_minutes: Option<u64>,
+}
+
+/// Lock state shown by the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct LockStatus {
+    /// A lock passphrase is set
+    pub enabled: bool,
+    /// Commands are refused until `unlock` succeeds
+    pub locked: bool,
+    /// Minutes of inactivity before locking, if auto-lock is on
+    pub auto_lock_minutes: Option<u64>,
+}
+
+/// Mutable lock state; the secret is read from the keychain on first use
+struct LockState {
+    loaded: bool,
+    secret: Option<LockSecret>,
+    unlocked: bool,
+    last_activity: Instant,
+    /// Wrong passphrases since the last right one
+    failed_attempts: u32,
+    /// No passphrase is checked before then
+    retry_after: Option<Instant>,
+}
+
+/// The application lock kept in the app state
+pub struct AppLock {
+    state: Mutex<LockState>,
+}
+
+impl Default for AppLock {
+    fn default() -> Self {
+        AppLock {
+            state: Mutex::new(LockState {
+                loaded: false,
+                secret: None,
+                unlocked: false,
+                last_activity: Instant::now(),
+                failed_attempts: 0,
+                retry_after: None,
+            }),
+        }
+    }
+}
+
+/// Opens the keychain entry holding the lock secret
+fn keychain_entry() -> Result<keyring::Entry, AppError> {
+    keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT)
+        .map_err(|e| AppError::internal(format!("Keychain error: {}", e)))
+}
+
+/// Reads the lock secret from the keychain
+fn read_secret() -> Result<Option<LockSecret>, AppError> {
+    match keychain_entry()?.get_password() {
+        Ok(json) => serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| AppError::internal(format!("Invalid lock secret in keychain: {}", e))),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(AppError::internal(format!("Keychain error: {}", e))),
+    }
+}
+
+/// Writes or removes the lock secret in the keychain
+fn write_secret(secret: Option<&LockSecret>) -> Result<(), AppError> {
+    let entry = keychain_entry()?;
+    let result = match secret {
+        Some(secret) => {
+            let json = serde_json::to_string(secret)
+                .map_err(|e| AppError::internal(format!("Failed to serialize lock secret: {}", e)))?;
+            entry.set_password(&json)
+        },
+        None => match entry.delete_password() {
+            Err(keyring::Error::NoEntry) => Ok(()),
+            result => result,
+        },
+    };
+    result.map_err(|e| AppError::internal(format!("Keychain error: {}", e)))
+}
+
+impl LockSecret {
+    /// Creates the secret for a new passphrase
+    fn new(passphrase: &str, auto_lock_minutes: Option<u64>) -> Result<Self, AppError> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default().hash_password(passphrase.as_bytes(), &salt)
+            .map_err(|e| AppError::internal(format!("Failed to hash the passphrase: {}", e)))?
+            .to_string();
+        Ok(LockSecret { hash, auto_lock_minutes })
+    }
+
+    /// Returns true if the passphrase matches
+    fn verify(&self, passphrase: &str) -> bool {
+        PasswordHash::new(&self.hash)
+            .and_then(|hash| Argon2::default().verify_password(passphrase.as_bytes(), &hash))
+            .is_ok()
+    }
+}
+
+/// Wait imposed after `failed` wrong passphrases in a row
+fn retry_delay(failed: u32) -> Option<Duration> {
+    let doublings = failed.checked_sub(FREE_ATTEMPTS + 1)?;
+    Some(FIRST_DELAY.saturating_mul(1 << doublings.min(16)).min(MAX_DELAY))
+}
+
+impl LockState {
+    /// Loads the secret from the keychain the first time it is needed
+    fn load(&mut self) -> Result<(), AppError> {
+        if !self.loaded {
+            self.secret = read_secret()?;
+            self.loaded = true;
+        }
+        Ok(())
+    }
+
+    /// Locks again if the app was idle longer than the auto-lock interval
+    fn expire(&mut self) {
+        let timeout = self.secret.as_ref()
+            .and_then(|secret| secret.auto_lock_minutes)
+            .map(|minutes| Duration::from_secs(minutes * 60));
+        if let Some(timeout) = timeout {
+            if self.last_activity.elapsed() >= timeout {
+                self.unlocked = false;
+            }
+        }
+    }
+
+    /// Returns true if commands are refused
+    fn is_locked(&self) -> bool {
+        self.secret.is_some() && !self.unlocked
+    }
+
+    /// Checks the passphrase unless attempts are being delayed, counting wrong ones
+    fn verify(&mut self, passphrase: &str) -> Result<(), AppError> {
+        let secret = match &self.secret {
+            Some(secret) => secret,
+            None => return Ok(()),
+        };
+        if let Some(wait) = self.retry_after.and_then(|at| at.checked_duration_since(Instant::now())) {
+            return Err(AppError::new(
+                ErrorKind::Locked,
+                format!("Too many wrong passphrases, try again in {} seconds", wait.as_secs().max(1)),
+            ));
+        }
+
+        if secret.verify(passphrase) {
+            self.failed_attempts = 0;
+            self.retry_after = None;
+            return Ok(());
+        }
+        self.failed_attempts = self.failed_attempts.saturating_add(1);
+        self.retry_after = retry_delay(self.failed_attempts).map(|delay| Instant::now() + delay);
+        Err(AppError::new(ErrorKind::Locked, "Wrong passphrase"))
+    }
+
+    /// Status for the frontend
+    fn status(&self) -> LockStatus {
+        LockStatus {
+            enabled: self.secret.is_some(),
+            locked: self.is_locked(),
+            auto_lock_minutes: self.secret.as_ref().and_then(|secret| secret.auto_lock_minutes),
+        }
+    }
+}
+
+impl AppLock {
+    /// Locks the state, loading the secret and applying auto-lock
+    fn state(&self) -> Result<std::sync::MutexGuard<'_, LockState>, AppError> {
+        let mut state = self.state.lock()
+            .map_err(|_| AppError::internal("Failed to acquire app lock"))?;
+        state.load()?;
+        state.expire();
+        Ok(state)
+    }
+
+    /// Fails with a locked error unless the app is unlocked (or has no lock),
+    /// counting the call as activity
+    pub fn check(&self) -> Result<(), AppError> {
+        let mut state = self.state()?;
+        if state.is_locked() {
+            return Err(AppError::new(ErrorKind::Locked, "App is locked, enter the passphrase to unlock"));
+        }
+        state.last_activity = Instant::now();
+        Ok(())
+    }
+
+    /// Gets whether the lock is enabled and engaged
+    pub fn status(&self) -> Result<LockStatus, AppError> {
+        Ok(self.state()?.status())
+    }
+
+    /// Unlocks the app if the passphrase matches the keychain's
+    pub fn unlock(&self, passphrase: &str) -> Result<LockStatus, AppError> {
+        let mut state = self.state()?;
+        state.verify(passphrase)?;
+        state.unlocked = true;
+        state.last_activity = Instant::now();
+        Ok(state.status())
+    }
+
+    /// Locks the app right away
+    pub fn lock(&self) -> Result<LockStatus, AppError> {
+        let mut state = self.state()?;
+        state.unlocked = false;
+        Ok(state.status())
+    }
+
+    /// Sets a new lock passphrase and auto-lock interval; the app must be
+    /// unlocked. The app stays unlocked until it is idle or locked.
+    pub fn enable(&self, passphrase: &str, auto_lock_minutes: Option<u64>) -> Result<LockStatus, AppError> {
+        if passphrase.trim().is_empty() {
+            return Err(AppError::invalid_input("Lock passphrase cannot be empty"));
+        }
+
+        let mut state = self.state()?;
+        if state.is_locked() {
+            return Err(AppError::new(ErrorKind::Locked, "App is locked, enter the passphrase to unlock"));
+        }
+
+        let secret = LockSecret::new(passphrase, auto_lock_minutes.filter(|minutes| *minutes > 0))?;
+        write_secret(Some(&secret))?;
+        state.secret = Some(secret);
+        state.unlocked = true;
+        state.last_activity = Instant::now();
+        Ok(state.status())
+    }
+
+    /// Removes the lock after checking the passphrase
+    pub fn disable(&self, passphrase: &str) -> Result<LockStatus, AppError> {
+        let mut state = self.state()?;
+        state.verify(passphrase)?;
+
+        write_secret(None)?;
+        state.secret = None;
+        Ok(state.status())
+    }
+}
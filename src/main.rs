@@ -1,79 +1,3636 @@
 // Safari History Knowledge Graph - Main Backend Entry Point
 
 // Import required crates
-use tauri::{self, State, command};
+use tauri::{self, GlobalShortcutManager, Manager, State, command};
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
 
 // Import our modules
+mod automation;
+mod capture;
 mod db;
+mod deeplink;
+mod dto;
+mod error;
+mod enrichment;
+mod events;
+mod export;
 mod extractor;
+mod graph;
+mod jobs;
+mod links;
+mod lock;
+mod mcp;
+mod notifications;
+mod plugins;
+mod privacy;
+mod report;
+mod scripting;
+mod settings;
+mod spotlight;
+mod streaming;
+mod sync;
+mod tray;
+mod web;
 
-// Define app state struct to maintain database connection across commands
+use error::{AppError, ErrorKind};
+
+// Define app state struct to maintain database connection across commands;
+// commands share read access to the pool, opening or re-keying takes the write lock
 struct AppState {
-    db_connection: Mutex<Option<db::DatabaseConnection>>,
+    db_connection: RwLock<Option<db::DatabaseConnection>>,
+    enrichment_queue: Arc<enrichment::QueueControl>,
+    // Token erase_all_data must be called with, and when it was issued
+    erase_token: Mutex<Option<(String, Instant)>>,
+    // Passphrase gate checked before every command touching the history
+    app_lock: lock::AppLock,
+    // Held while the database is opened, so windows starting together share one connection
+    init_lock: Mutex<()>,
+    // Wakes the background worker running queued jobs
+    jobs: jobs::JobSignal,
+    // Watches the auto-imported history file while watching is on
+    history_watcher: Mutex<Option<notify::RecommendedWatcher>>,
+    // Listener receiving visits from the browser extension while capture is on
+    capture_server: Mutex<Option<capture::CaptureServer>>,
+    // Capture paused from the tray, until resumed or the app restarts
+    capture_paused: AtomicBool,
+    // Assistant sessions and whether the user allowed them
+    mcp_sessions: Arc<mcp::McpSessions>,
+    // Model Context Protocol server while it is enabled
+    mcp_server: Mutex<Option<mcp::McpServer>>,
+    // Discovery, pairing and sync with other instances on the network while LAN sync is on
+    lan_sync: Mutex<Option<sync::LanSync>>,
+    // Accelerator of the registered search shortcut
+    global_shortcut: Mutex<Option<String>>,
+    // Last historykg:// link opened, until the frontend takes it
+    pending_deep_link: Mutex<Option<deeplink::DeepLink>>,
+    // Searches and timeline queries the frontend may cancel, by request id
+    running_queries: db::interrupt::RunningQueries,
+    // Result sets being streamed to windows in batches, by stream id
+    streams: Arc<streaming::Streams>,
+}
+
+// How long an erase confirmation token stays valid
+const ERASE_TOKEN_TTL: Duration = Duration::from_secs(5 * 60);
+
+// How often the tray badge is refreshed besides on history changes
+const TRAY_BADGE_INTERVAL: Duration = Duration::from_secs(60);
+
+// How long an automation link that launched the app waits for the database to open
+const AUTOMATION_STARTUP_WAIT: Duration = Duration::from_secs(30);
+
+// How long a history search may run before it is stopped
+const SEARCH_QUERY_TIMEOUT: Duration = Duration::from_secs(20);
+
+// How long a timeline query may run before it is stopped
+const TIMELINE_QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Processing results returned to the frontend
+#[derive(Serialize)]
+struct ProcessingResults {
+    import_run_id: i64,
+    files_processed: usize,
+    urls_processed: usize,
+    visits_processed: usize,
+    urls_inserted: usize,
+    visits_inserted: usize,
+    urls_excluded: usize,
+    visits_excluded: usize,
+    urls_redacted: usize,
+    processing_time_sec: f64,
+    errors: Vec<String>,
+}
+
+// Visits an automatic import or the browser extension added, sent with the
+// "history-imported" event
+#[derive(Serialize, Clone)]
+struct HistoryImportedEvent {
+    file_path: String,
+    urls_inserted: usize,
+    visits_inserted: usize,
+}
+
+// Simplified stats result for frontend
+#[derive(Serialize)]
+struct HistoryStats {
+    url_count: usize,
+    visit_count: usize,
+    domain_count: usize,
+    enriched_count: usize,
+    first_visit: Option<String>,
+    last_visit: Option<String>,
+    top_domains: Vec<(String, usize)>,
+}
+
+// Per-device usage breakdown for frontend
+#[derive(Serialize)]
+struct DeviceStatsResult {
+    device_name: Option<String>,
+    url_count: usize,
+    visit_count: usize,
+    first_visit: Option<String>,
+    last_visit: Option<String>,
+    top_domains: Vec<(String, usize)>,
+    activity_hours: Vec<usize>,
+}
+
+// Trending domain or topic for frontend
+#[derive(Serialize)]
+struct TrendResult {
+    key: String,
+    current_count: usize,
+    previous_count: usize,
+    growth: f64,
+}
+
+// Revisitation statistics for a URL or domain
+#[derive(Serialize)]
+struct RevisitItemResult {
+    key: String,
+    label: String,
+    title: Option<String>,
+    visit_count: usize,
+    return_count: usize,
+    return_rate: f64,
+    median_return_secs: Option<f64>,
+}
+
+// Revisitation report for frontend
+#[derive(Serialize)]
+struct RevisitReportResult {
+    items: Vec<RevisitItemResult>,
+    one_off_count: usize,
+    revisited_count: usize,
+}
+
+// Work/leisure split for a domain
+#[derive(Serialize)]
+struct DomainWorkSplitResult {
+    domain: String,
+    work_visits: usize,
+    leisure_visits: usize,
+    work_percentage: f64,
+}
+
+// Work/leisure split for a URL category
+#[derive(Serialize)]
+struct CategoryWorkSplitResult {
+    category: String,
+    work_visits: usize,
+    leisure_visits: usize,
+    work_percentage: f64,
+}
+
+// Work-time vs off-time report for frontend
+#[derive(Serialize)]
+struct WorkLeisureResult {
+    work_visits: usize,
+    leisure_visits: usize,
+    work_percentage: f64,
+    domains: Vec<DomainWorkSplitResult>,
+    categories: Vec<CategoryWorkSplitResult>,
+}
+
+// Browsing-pattern statistics for frontend
+#[derive(Serialize)]
+struct BrowsingPatternsResult {
+    total_visits: usize,
+    active_days: usize,
+    average_visits_per_day: f64,
+    busiest_day: Option<(String, usize)>,
+    quietest_day: Option<(String, usize)>,
+    longest_streak_days: usize,
+    longest_streak_start: Option<String>,
+    longest_streak_end: Option<String>,
+    longest_gap_seconds: Option<i64>,
+    longest_gap_start: Option<String>,
+    longest_gap_end: Option<String>,
+}
+
+// Pair of domains browsed together for frontend
+#[derive(Serialize)]
+struct CoVisitedDomainResult {
+    domain: String,
+    other_domain: String,
+    sessions: usize,
+}
+
+// Domain dashboard for frontend
+#[derive(Serialize)]
+struct DomainDetailsResult {
+    #[serde(flatten)]
+    details: db::domains::DomainDetails,
+    co_visited: Vec<CoVisitedDomainResult>,
+}
+
+// Summary of a graph export for frontend
+#[derive(Serialize)]
+struct GraphExportResult {
+    path: String,
+    node_count: usize,
+    edge_count: usize,
+}
+
+// Graph snapshot of a time window for frontend
+#[derive(Serialize)]
+struct GraphSnapshotResult {
+    id: i64,
+    label: String,
+    start: String,
+    end: String,
+    nodes: Vec<graph::GraphNode>,
+    edges: Vec<graph::GraphEdge>,
+}
+
+// Page visited along a navigation path for frontend
+#[derive(Serialize)]
+struct PathStepResult {
+    url: String,
+    title: Option<String>,
+    visited_at: String,
+}
+
+// Navigation path between two pages for frontend
+#[derive(Serialize)]
+struct NavigationPathResult {
+    steps: Vec<PathStepResult>,
+    occurrences: usize,
+}
+
+// Outcome of an enrichment run for frontend
+#[derive(Serialize)]
+struct EnrichmentRunResult {
+    enriched: usize,
+    failed: usize,
+    errors: Vec<String>,
+}
+
+// Semantic search match for frontend
+#[derive(Serialize)]
+struct SemanticMatchResult {
+    url_id: String,
+    url: String,
+    title: Option<String>,
+    score: f32,
+}
+
+// Outcome of a categorization run for frontend
+#[derive(Serialize)]
+struct CategorizeResult {
+    by_rules: usize,
+    by_model: usize,
+    uncategorized: usize,
+    errors: Vec<String>,
+}
+
+// Visits per category for frontend
+#[derive(Serialize)]
+struct CategoryStatsResult {
+    category: Option<String>,
+    visit_count: usize,
+}
+
+// Outcome of a thumbnail capture run for frontend
+#[derive(Serialize)]
+struct ThumbnailRunResult {
+    captured: usize,
+    missing: usize,
+    failed: usize,
+    errors: Vec<String>,
+}
+
+// Outcome of an archive run for frontend
+#[derive(Serialize)]
+struct ArchiveRunResult {
+    archives: Vec<web::Archive>,
+    errors: Vec<String>,
+}
+
+// Outcome of a short link expansion run for frontend
+#[derive(Serialize)]
+struct ExpandResult {
+    expanded: usize,
+    merged: usize,
+    failed: usize,
+    errors: Vec<String>,
+}
+
+// Related page for frontend
+#[derive(Serialize)]
+struct RelatedPageResult {
+    url_id: String,
+    url: String,
+    title: Option<String>,
+    score: f64,
+    similarity: Option<f32>,
+    co_visits: usize,
+    same_topic: bool,
+}
+
+impl From<graph::RelatedPage> for RelatedPageResult {
+    fn from(page: graph::RelatedPage) -> Self {
+        Self {
+            url_id: page.url_id,
+            url: page.url,
+            title: page.title,
+            score: page.score,
+            similarity: page.similarity,
+            co_visits: page.co_visits,
+            same_topic: page.same_topic,
+        }
+    }
+}
+
+// URL detail page for frontend
+#[derive(Serialize)]
+struct UrlDetailsResult {
+    #[serde(flatten)]
+    details: db::details::UrlDetails,
+    related: Vec<RelatedPageResult>,
+    neighbors: Vec<graph::GraphNeighbor>,
+}
+
+// Page cited by a history answer for frontend
+#[derive(Serialize)]
+struct AnswerSourceResult {
+    number: usize,
+    url_id: String,
+    url: String,
+    title: Option<String>,
+    visit_count: usize,
+    last_visit: Option<String>,
+    score: f32,
+    cited: bool,
+}
+
+// Answer to a question about the history for frontend
+#[derive(Serialize)]
+struct HistoryAnswerResult {
+    answer: String,
+    sources: Vec<AnswerSourceResult>,
+}
+
+// Natural language query result for frontend
+#[derive(Serialize)]
+struct NlQueryResultResponse {
+    sql: String,
+    table: db::readonly::QueryTable,
+}
+
+// Open the database, emitting "database-opened"; windows calling this after
+// the first share the open connection
+#[command]
+async fn initialize_database(
+    passphrase: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), AppError> {
+    let handle = app_handle.clone();
+    let watch_handle = app_handle.clone();
+    let opened = run_blocking_with_state(handle, move |app_state| {
+        let _init = app_state.init_lock.lock()
+            .map_err(|_| AppError::internal("Failed to acquire initialization lock"))?;
+        
+        // Every window initializes on start; later ones share the open connection
+        let is_open = app_state.db_connection.read()
+            .map_err(|_| AppError::internal("Failed to acquire database lock"))?
+            .is_some();
+        if is_open {
+            return Ok(false);
+        }
+        
+        // Set database path
+        let db_path = get_db_path()?;
+        
+        // An encrypted database is unlocked with the given passphrase or the keychain's
+        let encrypted = db::encryption::is_encrypted_file(&db_path)
+            .map_err(|e| AppError::wrap("Failed to read database", e))?;
+        let key = match (encrypted, passphrase) {
+            (false, _) => None,
+            (true, Some(passphrase)) => Some(passphrase),
+            (true, None) => Some(db::encryption::stored_key()
+                .map_err(|e| AppError::wrap("Failed to read database key", e))?
+                .ok_or_else(|| AppError::new(ErrorKind::Locked, "Database is encrypted, enter its passphrase"))?),
+        };
+        
+        // Create and initialize the database connection
+        let connection = db::initialize_database(&db_path, key.as_deref())
+            .map_err(|e| AppError::wrap("Failed to initialize database", e))?;
+        
+        // Requeue jobs and resume an enrichment queue interrupted by the last shutdown
+        jobs::recover_interrupted(&connection)
+            .map_err(|e| AppError::wrap("Failed to recover jobs", e))?;
+        enrichment::queue::recover_interrupted(&connection)
+            .map_err(|e| AppError::wrap("Failed to recover enrichment queue", e))?;
+        let queue_status = enrichment::queue::queue_status(&connection, &app_state.enrichment_queue)
+            .map_err(|e| AppError::wrap("Failed to get enrichment queue status", e))?;
+        if queue_status.pending > 0 {
+            jobs::enqueue_job(&connection, &jobs::JobRequest::Enrichment)
+                .map_err(|e| AppError::wrap("Failed to queue enrichment job", e))?;
+        }
+        
+        // A missing or unreadable history file shouldn't keep the database closed;
+        // the scheduled imports still run and report the problem on their jobs
+        let _ = update_history_watcher(&watch_handle, &connection);
+        // Likewise a busy capture or MCP port; saving the settings again reports it
+        let _ = update_capture_server(&watch_handle, &connection);
+        let _ = update_mcp_server(&watch_handle, &connection);
+        let _ = update_lan_sync(&watch_handle, &connection);
+        // And a shortcut another app already took
+        let _ = update_global_shortcut(&watch_handle, &connection);
+        
+        // Initialize database
+        let mut state_guard = app_state.db_connection.write()
+            .map_err(|_| AppError::internal("Failed to acquire database lock"))?;
+        *state_guard = Some(connection);
+        
+        Ok(true)
+    }).await?;
+    
+    if opened {
+        events::emit_database_opened(&app_handle);
+        start_job_worker(&app_handle);
+    }
+    
+    Ok(())
+}
+
+// Take the historykg:// link the app was opened with, if the frontend
+// hasn't handled it yet; views open as soon as they mount
+#[command]
+async fn take_pending_deep_link(app_state: State<'_, AppState>) -> Result<Option<deeplink::DeepLink>, AppError> {
+    let mut pending = app_state.pending_deep_link.lock()
+        .map_err(|_| AppError::internal("Failed to acquire deep link lock"))?;
+    Ok(pending.take())
+}
+
+// Build the historykg:// link opening a view, for "copy link" buttons
+#[command]
+async fn make_deep_link(link: deeplink::DeepLink) -> Result<String, AppError> {
+    Ok(link.to_link())
+}
+
+// Get whether the app lock is enabled and engaged
+#[command]
+async fn get_lock_status(app_state: State<'_, AppState>) -> Result<lock::LockStatus, AppError> {
+    app_state.app_lock.status()
+}
+
+// Unlock the app with its passphrase
+#[command]
+async fn unlock(
+    passphrase: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<lock::LockStatus, AppError> {
+    let status = app_state.app_lock.unlock(&passphrase)?;
+    events::emit_lock_changed(&app_handle, &status);
+    Ok(status)
+}
+
+// Lock the app until the passphrase is entered again
+#[command]
+async fn lock_app(app_handle: tauri::AppHandle, app_state: State<'_, AppState>) -> Result<lock::LockStatus, AppError> {
+    let status = app_state.app_lock.lock()?;
+    events::emit_lock_changed(&app_handle, &status);
+    Ok(status)
+}
+
+// Set the app lock passphrase and auto-lock interval (minutes, none to disable)
+#[command]
+async fn enable_app_lock(
+    passphrase: String,
+    auto_lock_minutes: Option<u64>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<lock::LockStatus, AppError> {
+    let status = app_state.app_lock.enable(&passphrase, auto_lock_minutes)?;
+    events::emit_lock_changed(&app_handle, &status);
+    Ok(status)
+}
+
+// Remove the app lock
+#[command]
+async fn disable_app_lock(
+    passphrase: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<lock::LockStatus, AppError> {
+    let status = app_state.app_lock.disable(&passphrase)?;
+    events::emit_lock_changed(&app_handle, &status);
+    Ok(status)
+}
+
+// Get whether the database is encrypted at rest
+#[command]
+async fn is_encrypted() -> Result<db::encryption::EncryptionStatus, AppError> {
+    db::encryption::encryption_status(&get_db_path()?)
+        .map_err(|e| AppError::wrap("Failed to get encryption status", e))
+}
+
+// Encrypt the database with a passphrase kept in the OS keychain
+#[command]
+async fn enable_encryption(
+    passphrase: String,
+    app_handle: tauri::AppHandle,
+) -> Result<(), AppError> {
+    run_blocking_with_state(app_handle, move |app_state| {
+        let db_path = get_db_path()?;
+        
+        if db::encryption::is_encrypted_file(&db_path).map_err(|e| AppError::wrap("Failed to read database", e))? {
+            return Err(AppError::invalid_input("Database is already encrypted"));
+        }
+        
+        let mut state_guard = app_state.db_connection.write()
+            .map_err(|_| AppError::internal("Failed to acquire database lock"))?;
+        
+        // Close the connection so the file can be replaced, once running
+        // queries finish; clones held by background tasks close with it
+        if let Some(connection) = state_guard.as_ref() {
+            connection.close()
+                .map_err(|e| AppError::wrap("Failed to close database", e))?;
+        }
+        state_guard.take();
+        
+        let result = db::encryption::encrypt_database(&db_path, &passphrase)
+            .map_err(|e| AppError::wrap("Failed to encrypt database", e));
+        
+        // Reopen whether or not the conversion worked
+        let encrypted = db::encryption::is_encrypted_file(&db_path)
+            .map_err(|e| AppError::wrap("Failed to read database", e))?;
+        let key = encrypted.then_some(passphrase.as_str());
+        let connection = db::initialize_database(&db_path, key)
+            .map_err(|e| AppError::wrap("Failed to reopen database", e))?;
+        *state_guard = Some(connection);
+        
+        result
+    }).await
+}
+
+// Decrypt the database and remove its passphrase from the keychain
+#[command]
+async fn disable_encryption(
+    passphrase: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), AppError> {
+    run_blocking_with_state(app_handle, move |app_state| {
+        let db_path = get_db_path()?;
+        
+        if !db::encryption::is_encrypted_file(&db_path).map_err(|e| AppError::wrap("Failed to read database", e))? {
+            return Err(AppError::invalid_input("Database is not encrypted"));
+        }
+        
+        let passphrase = match passphrase {
+            Some(passphrase) => passphrase,
+            None => db::encryption::stored_key()
+                .map_err(|e| AppError::wrap("Failed to read database key", e))?
+                .ok_or_else(|| AppError::new(ErrorKind::Locked, "No passphrase given or stored"))?,
+        };
+        
+        let mut state_guard = app_state.db_connection.write()
+            .map_err(|_| AppError::internal("Failed to acquire database lock"))?;
+        
+        // Close the connection so the file can be replaced, once running
+        // queries finish; clones held by background tasks close with it
+        if let Some(connection) = state_guard.as_ref() {
+            connection.close()
+                .map_err(|e| AppError::wrap("Failed to close database", e))?;
+        }
+        state_guard.take();
+        
+        let result = db::encryption::decrypt_database(&db_path, &passphrase)
+            .map_err(|e| AppError::wrap("Failed to decrypt database", e));
+        
+        // Reopen whether or not the conversion worked
+        let encrypted = db::encryption::is_encrypted_file(&db_path)
+            .map_err(|e| AppError::wrap("Failed to read database", e))?;
+        let key = encrypted.then_some(passphrase.as_str());
+        let connection = db::initialize_database(&db_path, key)
+            .map_err(|e| AppError::wrap("Failed to reopen database", e))?;
+        *state_guard = Some(connection);
+        
+        result
+    }).await
+}
+
+// Process uploaded history files
+#[command]
+async fn process_history_files(
+    file_paths: Vec<String>,
+    device_names: Option<Vec<String>>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<ProcessingResults, AppError> {
+    let request = jobs::JobRequest::Import {
+        file_paths: file_paths.clone(),
+        device_names: device_names.clone(),
+    };
+    let handle = app_handle.clone();
+    let results = run_blocking(&app_state, move |db_conn| {
+        run_as_job(&handle, db_conn, &request, |job| {
+            import_history_files(db_conn, &file_paths, device_names.as_deref(), None, job)
+        })
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Imported);
+    
+    Ok(results)
+}
+
+// List the imported history files, showing which ones are copies of another
+#[command]
+async fn get_import_sources(app_state: State<'_, AppState>) -> Result<Vec<db::sources::ImportSource>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::sources::list_import_sources(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get import sources", e))
+    }).await
+}
+
+// Get the log of past import runs, newest first
+#[command]
+async fn get_import_history(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::imports::ImportRun>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::imports::get_import_history(db_conn, limit.unwrap_or(db::imports::DEFAULT_IMPORT_HISTORY))
+            .map_err(|e| AppError::wrap("Failed to get import history", e))
+    }).await
+}
+
+// Remove everything an import run added
+#[command]
+async fn rollback_import(
+    run_id: i64,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<db::imports::RollbackResult, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::imports::rollback_import(db_conn, run_id)
+            .map_err(|e| AppError::wrap("Failed to roll back import", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::RolledBack);
+    
+    Ok(result)
+}
+
+// Check dropped files (SQLite header, browser, approximate size) before they are imported
+#[command]
+async fn inspect_dropped_files(
+    paths: Vec<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<extractor::FileInspection>, AppError> {
+    app_state.app_lock.check()?;
+    tauri::async_runtime::spawn_blocking(move || extractor::inspect_files(&paths))
+        .await
+        .map_err(|e| AppError::wrap("Inspection task failed", e))
+}
+
+// Queue an incremental import of this Mac's Safari history (or the configured history file)
+#[command]
+async fn import_now(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<jobs::Job, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        queue_local_import(&app_handle, db_conn, None)
+    }).await
+}
+
+// Queue an import, enrichment run, graph rebuild or compaction for the background worker
+#[command]
+async fn queue_job(
+    request: jobs::JobRequest,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<jobs::Job, AppError> {
+    let job = run_blocking(&app_state, move |db_conn| {
+        jobs::enqueue_job(db_conn, &request)
+            .map_err(|e| AppError::wrap("Failed to queue job", e))
+    }).await?;
+    
+    events::emit_job_progress(&app_handle, &job);
+    start_job_worker(&app_handle);
+    
+    Ok(job)
+}
+
+// Get the most recent jobs, newest first
+#[command]
+async fn list_jobs(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<jobs::Job>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        jobs::list_jobs(db_conn, limit.unwrap_or(jobs::DEFAULT_JOB_LIST))
+            .map_err(|e| AppError::wrap("Failed to list jobs", e))
+    }).await
+}
+
+// Cancel a queued job, or ask a running one to stop
+#[command]
+async fn cancel_job(
+    id: i64,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<jobs::Job, AppError> {
+    let job = run_blocking(&app_state, move |db_conn| {
+        jobs::cancel_job(db_conn, id)
+            .map_err(|e| AppError::wrap("Failed to cancel job", e))
+    }).await?;
+    
+    // The enrichment queue may be waiting on a retry, so stop it right away
+    if job.state == jobs::JobState::Running && job.request == jobs::JobRequest::Enrichment {
+        app_state.enrichment_queue.request_stop();
+    }
+    events::emit_job_progress(&app_handle, &job);
+    
+    Ok(job)
+}
+
+// Get database size, page usage and integrity
+#[command]
+async fn get_database_health(app_state: State<'_, AppState>) -> Result<db::maintenance::DatabaseHealth, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::maintenance::get_database_health(db_conn)
+            .map_err(|e| AppError::wrap("Failed to check database health", e))
+    }).await
+}
+
+// Checkpoint, vacuum and analyze the database
+#[command]
+async fn compact_database(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<db::maintenance::CompactResult, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        run_as_job(&app_handle, db_conn, &jobs::JobRequest::Compact, |_| {
+            db::maintenance::compact_database(db_conn)
+                .map_err(|e| AppError::wrap("Failed to compact database", e))
+        })
+    }).await
+}
+
+// Get history statistics
+#[command]
+async fn get_history_stats(app_state: State<'_, AppState>) -> Result<HistoryStats, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        // Get stats from database
+        let stats = db::operations::get_stats(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get stats", e))?;
+        
+        // Convert timestamps to ISO strings for frontend
+        let first_visit = stats.first_visit.map(|dt| dt.to_rfc3339());
+        let last_visit = stats.last_visit.map(|dt| dt.to_rfc3339());
+        
+        // Return formatted stats
+        Ok(HistoryStats {
+            url_count: stats.url_count,
+            visit_count: stats.visit_count,
+            domain_count: stats.domain_count,
+            enriched_count: stats.enriched_count,
+            first_visit,
+            last_visit,
+            top_domains: stats.top_domains,
+        })
+    }).await
+}
+
+// Get the latest visits for the "jump back in" panel, which polls this every
+// few seconds; `since` limits it to visits after the newest one already shown
+#[command]
+async fn get_recent(
+    limit: Option<usize>,
+    since: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::operations::RecentVisit>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::operations::get_recent(
+            db_conn,
+            limit.unwrap_or(db::operations::DEFAULT_RECENT_LIMIT),
+            parse_date(since),
+        ).map_err(|e| AppError::wrap("Failed to get recent visits", e))
+    }).await
+}
+
+// Get statistics broken down by device
+#[command]
+async fn get_device_stats(
+    start_date: Option<String>,
+    end_date: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<DeviceStatsResult>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let device_stats = db::analytics::get_device_stats(db_conn, parse_date(start_date), parse_date(end_date))
+            .map_err(|e| AppError::wrap("Failed to get device stats", e))?;
+        
+        Ok(device_stats.into_iter()
+            .map(|stats| DeviceStatsResult {
+                device_name: stats.device_name,
+                url_count: stats.url_count,
+                visit_count: stats.visit_count,
+                first_visit: stats.first_visit.map(|dt| dt.to_rfc3339()),
+                last_visit: stats.last_visit.map(|dt| dt.to_rfc3339()),
+                top_domains: stats.top_domains,
+                activity_hours: stats.activity_hours,
+            })
+            .collect())
+    }).await
+}
+
+// List the registered devices, most visits first
+#[command]
+async fn get_devices(app_state: State<'_, AppState>) -> Result<Vec<db::devices::Device>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::devices::list_devices(db_conn)
+            .map_err(|e| AppError::wrap("Failed to list devices", e))
+    }).await
+}
+
+// Rename a device and its visits
+#[command]
+async fn rename_device(
+    device_id: i64,
+    name: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<db::devices::Device, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::devices::rename_device(db_conn, device_id, &name)
+            .map_err(|e| AppError::wrap("Failed to rename device", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Edited);
+    
+    Ok(result)
+}
+
+// Merge devices into one, moving their visits
+#[command]
+async fn merge_devices(
+    source_ids: Vec<i64>,
+    target_id: i64,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<db::devices::Device, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::devices::merge_devices(db_conn, &source_ids, target_id)
+            .map_err(|e| AppError::wrap("Failed to merge devices", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Edited);
+    
+    Ok(result)
+}
+
+// List domains with their visit and URL counts
+#[command]
+async fn get_domains(
+    filter: Option<String>,
+    order: Option<db::domains::DomainOrder>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::domains::DomainStats>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::domains::list_domains(
+            db_conn,
+            filter.as_deref(),
+            order.unwrap_or_default(),
+            limit.unwrap_or(db::domains::DEFAULT_DOMAIN_PAGE),
+            offset.unwrap_or(0),
+        ).map_err(|e| AppError::wrap("Failed to list domains", e))
+    }).await
+}
+
+// Get the dashboard of one domain: counts, visit trend, top pages, category,
+// favicon, registration and hosting provenance and the domains browsed in the
+// same sessions
+#[command]
+async fn get_domain_details(
+    domain: String,
+    interval: Option<db::domains::TrendInterval>,
+    top_pages: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<DomainDetailsResult, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let details = db::domains::get_domain_details(
+            db_conn,
+            &domain,
+            interval.unwrap_or_default(),
+            top_pages.unwrap_or(db::domains::DEFAULT_DOMAIN_TOP_PAGES),
+        ).map_err(|e| AppError::wrap("Failed to get domain details", e))?;
+        
+        let pairs = graph::get_co_visited_domains(db_conn, Some(domain.clone()), 10)
+            .map_err(|e| AppError::wrap("Failed to get co-visited domains", e))?;
+        
+        // Pairs are stored in either order; list the other domain of each
+        let co_visited = pairs.into_iter()
+            .map(|pair| {
+                let other_domain = if pair.domain == domain { pair.other_domain } else { pair.domain };
+                CoVisitedDomainResult { domain: domain.clone(), other_domain, sessions: pair.sessions }
+            })
+            .collect();
+        
+        Ok(DomainDetailsResult { details, co_visited })
+    }).await
+}
+
+// Load an EasyList, Disconnect or hosts-file blocklist, replacing the list of
+// the same name, and reclassify visited domains
+#[command]
+async fn load_blocklist(
+    path: String,
+    name: Option<String>,
+    kind: Option<db::trackers::TrackerKind>,
+    app_state: State<'_, AppState>,
+) -> Result<db::trackers::Blocklist, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::trackers::load_blocklist(
+            db_conn,
+            Path::new(&path),
+            name,
+            kind.unwrap_or(db::trackers::TrackerKind::Ads),
+        ).map_err(|e| AppError::wrap("Failed to load blocklist", e))
+    }).await
+}
+
+// List the built-in and loaded blocklists
+#[command]
+async fn get_blocklists(app_state: State<'_, AppState>) -> Result<Vec<db::trackers::Blocklist>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::trackers::list_blocklists(db_conn)
+            .map_err(|e| AppError::wrap("Failed to list blocklists", e))
+    }).await
+}
+
+// Remove a blocklist and reclassify visited domains
+#[command]
+async fn delete_blocklist(id: i64, app_state: State<'_, AppState>) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::trackers::delete_blocklist(db_conn, id)
+            .map_err(|e| AppError::wrap("Failed to delete blocklist", e))
+    }).await
+}
+
+// List visited domains classified as ad networks or trackers, most visited first
+#[command]
+async fn get_tracker_domains(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::trackers::TrackerDomain>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::trackers::get_tracker_domains(db_conn, limit.unwrap_or(db::domains::DEFAULT_DOMAIN_PAGE))
+            .map_err(|e| AppError::wrap("Failed to list tracker domains", e))
+    }).await
+}
+
+// Get the most visited domains rolled up to registrable domains or the
+// organizations owning them
+#[command]
+async fn get_domain_rollup(
+    rollup: Option<db::organizations::DomainRollup>,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::organizations::DomainGroup>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::organizations::get_domain_rollup(
+            db_conn,
+            rollup.unwrap_or_default(),
+            limit.unwrap_or(db::organizations::DEFAULT_ROLLUP_LIMIT),
+        ).map_err(|e| AppError::wrap("Failed to roll up domains", e))
+    }).await
+}
+
+// Map a domain to the organization owning it, or remove its mapping
+#[command]
+async fn set_domain_organization(
+    domain: String,
+    organization: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::organizations::set_domain_organization(db_conn, &domain, organization.as_deref())
+            .map_err(|e| AppError::wrap("Failed to set domain organization", e))
+    }).await
+}
+
+// Stream every URL of a domain, most visited first, to the calling window as
+// "stream-batch" events
+#[command]
+async fn stream_domain_urls(
+    stream_id: String,
+    domain: String,
+    batch_size: Option<usize>,
+    window: tauri::Window,
+    app_state: State<'_, AppState>,
+) -> Result<streaming::StreamSummary, AppError> {
+    let streams = Arc::clone(&app_state.streams);
+    
+    run_blocking(&app_state, move |db_conn| {
+        let mut sender = streams.open(window, &stream_id, batch_size);
+        let result = db::domains::for_each_domain_url(db_conn, &domain, |url| sender.push(url));
+        Ok(sender.finish(result.err().map(|e| e.to_string())))
+    }).await
+}
+
+// Acknowledge a "stream-batch" the window has handled, letting the stream send more
+#[command]
+async fn ack_stream(
+    stream_id: String,
+    seq: u64,
+    app_state: State<'_, AppState>,
+) -> Result<bool, AppError> {
+    Ok(app_state.streams.acknowledge(&stream_id, seq))
+}
+
+// Stop a stream; rows already sent are kept by the window
+#[command]
+async fn cancel_stream(
+    stream_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<bool, AppError> {
+    Ok(app_state.streams.cancel(&stream_id))
+}
+
+// Get the most visited individual pages
+#[command]
+async fn get_top_pages(
+    start_date: Option<String>,
+    end_date: Option<String>,
+    domain: Option<String>,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<dto::UrlSummaryDto>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let params = db::analytics::TopPagesParams {
+            start_date: parse_date(start_date),
+            end_date: parse_date(end_date),
+            domain,
+            limit,
+        };
+        
+        let top_pages = db::analytics::get_top_pages(db_conn, &params)
+            .map_err(|e| AppError::wrap("Failed to get top pages", e))?;
+        
+        Ok(dto::url_summaries(&top_pages))
+    }).await
+}
+
+// Rank the newsletters, platforms and campaigns whose links led to the most reading
+#[command]
+async fn get_campaign_stats(
+    dimension: Option<db::campaigns::CampaignDimension>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::campaigns::CampaignStat>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let params = db::campaigns::CampaignParams {
+            dimension: dimension.unwrap_or_default(),
+            start_date: parse_date(start_date),
+            end_date: parse_date(end_date),
+            limit,
+        };
+        
+        db::campaigns::get_campaign_stats(db_conn, &params)
+            .map_err(|e| AppError::wrap("Failed to get campaign stats", e))
+    }).await
+}
+
+// Count visits by where they came from: search, social, newsletter, direct,
+// internal or external link
+#[command]
+async fn get_origin_stats(
+    start_date: Option<String>,
+    end_date: Option<String>,
+    domain: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::origins::OriginStat>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::origins::get_origin_stats(db_conn, parse_date(start_date), parse_date(end_date), domain)
+            .map_err(|e| AppError::wrap("Failed to get visit origins", e))
+    }).await
+}
+
+// Get how the tone of the pages read is spread, overall, per category and per
+// domain, optionally within one category such as news
+#[command]
+async fn get_sentiment_stats(
+    start_date: Option<String>,
+    end_date: Option<String>,
+    category: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<db::sentiment::SentimentStats, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::sentiment::get_sentiment_stats(db_conn, parse_date(start_date), parse_date(end_date), category.as_deref())
+            .map_err(|e| AppError::wrap("Failed to get sentiment stats", e))
+    }).await
+}
+
+// Get weekly visit counts of several URLs for sparklines next to search results
+#[command]
+async fn get_url_sparklines(
+    url_ids: Vec<String>,
+    months: Option<u32>,
+    app_state: State<'_, AppState>,
+) -> Result<db::analytics::Sparklines, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::analytics::get_sparklines(
+            db_conn,
+            &url_ids,
+            months.unwrap_or(db::analytics::DEFAULT_SPARKLINE_MONTHS),
+            None,
+        ).map_err(|e| AppError::wrap("Failed to get sparklines", e))
+    }).await
+}
+
+// Get long-form pages that were opened but left after a few seconds
+#[command]
+async fn get_skimmed_articles(
+    start_date: Option<String>,
+    end_date: Option<String>,
+    min_reading_secs: Option<u64>,
+    max_dwell_secs: Option<f64>,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<serde_json::Value>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let params = db::analytics::SkimParams {
+            start_date: parse_date(start_date),
+            end_date: parse_date(end_date),
+            min_reading_secs: min_reading_secs.unwrap_or(300), // Default to 5-minute reads
+            max_dwell_secs: max_dwell_secs.unwrap_or(10.0),
+            limit: limit.unwrap_or(50),
+        };
+        
+        let articles = db::analytics::get_skimmed_articles(db_conn, &params)
+            .map_err(|e| AppError::wrap("Failed to get skimmed articles", e))?;
+        
+        let mut results = Vec::new();
+        
+        for article in articles {
+            let mut data = serde_json::Map::new();
+            
+            data.insert("word_count".to_string(), serde_json::Value::Number(serde_json::Number::from(article.word_count)));
+            data.insert("reading_time_sec".to_string(), serde_json::Value::Number(serde_json::Number::from(article.reading_time_sec)));
+            data.insert("longest_visit_secs".to_string(), serde_json::json!(article.longest_visit_secs));
+            data.insert("page".to_string(), serde_json::json!(dto::UrlSummaryDto::from(&article.page)));
+            
+            results.push(serde_json::Value::Object(data));
+        }
+        
+        Ok(results)
+    }).await
+}
+
+// Build a reading list of long pages opened once and left quickly, on the
+// topics read most, as Markdown and optionally saved to a collection
+#[command]
+async fn generate_reading_list(
+    min_reading_secs: Option<u64>,
+    max_dwell_secs: Option<f64>,
+    top_topics: Option<usize>,
+    topic_days: Option<i64>,
+    limit: Option<usize>,
+    save_to_collection: Option<bool>,
+    collection_name: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<serde_json::Value, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let params = db::reading_list::ReadingListParams {
+            min_reading_secs: min_reading_secs.unwrap_or(db::reading_list::DEFAULT_MIN_READING_SECS),
+            max_dwell_secs: max_dwell_secs.unwrap_or(db::reading_list::DEFAULT_MAX_DWELL_SECS),
+            top_topics: top_topics.unwrap_or(db::reading_list::DEFAULT_TOP_TOPICS),
+            topic_days: topic_days.unwrap_or(db::reading_list::DEFAULT_TOPIC_DAYS),
+            limit: limit.unwrap_or(db::reading_list::DEFAULT_READING_LIST_LIMIT),
+        };
+        
+        let items = db::reading_list::get_reading_list(db_conn, &params)
+            .map_err(|e| AppError::wrap("Failed to build reading list", e))?;
+        
+        let collection = if save_to_collection.unwrap_or(false) && !items.is_empty() {
+            let name = collection_name.as_deref().unwrap_or(db::reading_list::DEFAULT_READING_LIST_COLLECTION);
+            Some(db::reading_list::save_reading_list(db_conn, name, &items)
+                .map_err(|e| AppError::wrap("Failed to save reading list", e))?)
+        } else {
+            None
+        };
+        
+        Ok(serde_json::json!({
+            "markdown": links::format_reading_list(&items),
+            "items": items,
+            "collection": collection,
+        }))
+    }).await
+}
+
+// Get domains or topics whose visit frequency is accelerating
+#[command]
+async fn get_trending(
+    dimension: Option<String>,
+    window_days: Option<i64>,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<TrendResult>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let params = db::analytics::TrendParams {
+            dimension: match dimension.as_deref() {
+                Some("topic") => db::analytics::TrendDimension::Topic,
+                _ => db::analytics::TrendDimension::Domain, // Default to domain
+            },
+            window_days: window_days.unwrap_or(db::analytics::DEFAULT_TREND_WINDOW_DAYS),
+            as_of: None,
+            limit: limit.unwrap_or(20),
+        };
+        
+        let trends = db::analytics::get_trending(db_conn, &params)
+            .map_err(|e| AppError::wrap("Failed to detect trends", e))?;
+        
+        Ok(trends.into_iter()
+            .map(|item| TrendResult {
+                key: item.key,
+                current_count: item.current_count,
+                previous_count: item.previous_count,
+                growth: item.growth,
+            })
+            .collect())
+    }).await
+}
+
+// Get return rates and time-to-return for URLs or domains
+#[command]
+async fn get_revisitation_report(
+    dimension: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    min_returns: Option<usize>,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<RevisitReportResult, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let params = db::analytics::RevisitParams {
+            dimension: match dimension.as_deref() {
+                Some("domain") => db::analytics::RevisitDimension::Domain,
+                _ => db::analytics::RevisitDimension::Url, // Default to URL
+            },
+            start_date: parse_date(start_date),
+            end_date: parse_date(end_date),
+            min_returns: min_returns.unwrap_or(1),
+            limit: limit.unwrap_or(50),
+        };
+        
+        let report = db::analytics::get_revisitation_report(db_conn, &params)
+            .map_err(|e| AppError::wrap("Failed to compute revisitation report", e))?;
+        
+        Ok(RevisitReportResult {
+            items: report.items.into_iter()
+                .map(|item| RevisitItemResult {
+                    key: item.key,
+                    label: item.label,
+                    title: item.title,
+                    visit_count: item.visit_count,
+                    return_count: item.return_count,
+                    return_rate: item.return_rate,
+                    median_return_secs: item.median_return_secs,
+                })
+                .collect(),
+            one_off_count: report.one_off_count,
+            revisited_count: report.revisited_count,
+        })
+    }).await
+}
+
+// Get notable pages visited on this calendar date in previous years
+#[command]
+async fn get_on_this_day(
+    month: u32,
+    day: u32,
+    per_year: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<serde_json::Value>, AppError> {
+    // Validate the calendar date (leap day allowed)
+    if chrono::NaiveDate::from_ymd_opt(2000, month, day).is_none() {
+        return Err(AppError::invalid_input(format!("Invalid date: {}-{}", month, day)));
+    }
+    
+    run_blocking(&app_state, move |db_conn| {
+        let items = db::analytics::get_on_this_day(
+            db_conn,
+            month,
+            day,
+            Utc::now(),
+            per_year.unwrap_or(db::analytics::DEFAULT_ON_THIS_DAY_PER_YEAR),
+        ).map_err(|e| AppError::wrap("Failed to get on-this-day pages", e))?;
+        
+        let mut results = Vec::new();
+        
+        for item in items {
+            let mut data = serde_json::Map::new();
+            
+            data.insert("year".to_string(), serde_json::Value::Number(serde_json::Number::from(item.year)));
+            data.insert("is_enriched".to_string(), serde_json::Value::Bool(item.is_enriched));
+            if let Some(summary) = item.summary {
+                data.insert("summary".to_string(), serde_json::Value::String(summary));
+            }
+            
+            data.insert("page".to_string(), serde_json::json!(dto::UrlSummaryDto::from(&item.page)));
+            
+            results.push(serde_json::Value::Object(data));
+        }
+        
+        Ok(results)
+    }).await
+}
+
+// Get the configured working hours
+#[command]
+async fn get_working_hours(
+    app_state: State<'_, AppState>,
+) -> Result<db::analytics::WorkingHours, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::analytics::get_working_hours(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get working hours", e))
+    }).await
+}
+
+// Update the configured working hours
+#[command]
+async fn set_working_hours(
+    working_hours: db::analytics::WorkingHours,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    // Validate the windows before storing them
+    for window in &working_hours.windows {
+        if window.start_minute >= window.end_minute || window.end_minute > 24 * 60 {
+            return Err(AppError::invalid_input(format!("Invalid working window: {}-{}", window.start_minute, window.end_minute)));
+        }
+        if window.days.iter().any(|day| *day > 6) {
+            return Err(AppError::invalid_input("Working window days must be between 0 (Monday) and 6 (Sunday)"));
+        }
+    }
+    
+    run_blocking(&app_state, move |db_conn| {
+        db::analytics::set_working_hours(db_conn, &working_hours)
+            .map_err(|e| AppError::wrap("Failed to save working hours", e))
+    }).await
+}
+
+// Get the split of visits between working hours and leisure time
+#[command]
+async fn get_work_leisure_stats(
+    start_date: Option<String>,
+    end_date: Option<String>,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<WorkLeisureResult, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let report = db::analytics::get_work_leisure_stats(
+            db_conn,
+            parse_date(start_date),
+            parse_date(end_date),
+            limit.unwrap_or(50),
+        ).map_err(|e| AppError::wrap("Failed to compute work/leisure stats", e))?;
+        
+        Ok(WorkLeisureResult {
+            work_visits: report.work_visits,
+            leisure_visits: report.leisure_visits,
+            work_percentage: report.work_percentage,
+            domains: report.domains.into_iter()
+                .map(|d| DomainWorkSplitResult {
+                    domain: d.domain,
+                    work_visits: d.work_visits,
+                    leisure_visits: d.leisure_visits,
+                    work_percentage: d.work_percentage,
+                })
+                .collect(),
+            categories: report.categories.into_iter()
+                .map(|c| CategoryWorkSplitResult {
+                    category: c.category,
+                    work_visits: c.work_visits,
+                    leisure_visits: c.leisure_visits,
+                    work_percentage: c.work_percentage,
+                })
+                .collect(),
+        })
+    }).await
+}
+
+// Get streaks, busiest/quietest days and other browsing-pattern stats
+#[command]
+async fn get_browsing_patterns(
+    domain: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<BrowsingPatternsResult, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let patterns = db::analytics::get_browsing_patterns(
+            db_conn,
+            domain,
+            parse_date(start_date),
+            parse_date(end_date),
+        ).map_err(|e| AppError::wrap("Failed to compute browsing patterns", e))?;
+        
+        let format_day = |(date, count): (chrono::NaiveDate, usize)| (date.to_string(), count);
+        
+        Ok(BrowsingPatternsResult {
+            total_visits: patterns.total_visits,
+            active_days: patterns.active_days,
+            average_visits_per_day: patterns.average_visits_per_day,
+            busiest_day: patterns.busiest_day.map(format_day),
+            quietest_day: patterns.quietest_day.map(format_day),
+            longest_streak_days: patterns.longest_streak.as_ref().map_or(0, |s| s.days),
+            longest_streak_start: patterns.longest_streak.as_ref().map(|s| s.start.to_string()),
+            longest_streak_end: patterns.longest_streak.as_ref().map(|s| s.end.to_string()),
+            longest_gap_seconds: patterns.longest_gap.as_ref().map(|g| g.seconds),
+            longest_gap_start: patterns.longest_gap.as_ref().map(|g| g.start.to_rfc3339()),
+            longest_gap_end: patterns.longest_gap.as_ref().map(|g| g.end.to_rfc3339()),
+        })
+    }).await
+}
+
+// Generate a Markdown or HTML report for the last day, week or month,
+// notifying when it is written or fails
+#[command]
+async fn generate_report(
+    period: String,
+    path: String,
+    format: Option<String>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let report_period = report::ReportPeriod::ending_at(&period, Utc::now())
+        .map_err(AppError::from)?;
+    
+    let report_format = match format.as_deref() {
+        Some("html") => report::ReportFormat::Html,
+        _ => report::ReportFormat::Markdown, // Default to Markdown
+    };
+    
+    let result = run_blocking(&app_state, move |db_conn| {
+        report::generate_report(db_conn, report_period, report_format, Path::new(&path))
+            .map_err(|e| AppError::wrap("Failed to generate report", e))?;
+        
+        Ok(path)
+    }).await;
+    
+    notify_report(&app_handle, &result);
+    result
+}
+
+// Generate a self-contained HTML digest of the last day, week or month for
+// emailing, optionally also writing it to a file
+#[command]
+async fn generate_digest(
+    period: String,
+    path: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let digest_period = report::ReportPeriod::ending_at(&period, Utc::now())
+        .map_err(AppError::from)?;
+    
+    run_blocking(&app_state, move |db_conn| {
+        let html = report::digest::generate_digest(db_conn, digest_period)
+            .map_err(|e| AppError::wrap("Failed to generate digest", e))?;
+        
+        if let Some(path) = path {
+            std::fs::write(&path, &html)
+                .map_err(|e| AppError::wrap("Failed to write digest", e))?;
+        }
+        
+        Ok(html)
+    }).await
+}
+
+// Export visits matching the filters to a JSON lines, CSV or Parquet file,
+// emitting "export-progress" events while rows are written
+#[command]
+async fn export_history(
+    format: export::ExportFormat,
+    filters: Option<export::ExportFilter>,
+    path: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<export::ExportSummary, AppError> {
+    let filters = filters.unwrap_or_default();
+    
+    run_blocking(&app_state, move |db_conn| {
+        let emit_progress = |progress: &export::ExportProgress| {
+            let _ = app_handle.emit_all("export-progress", progress.clone());
+        };
+        
+        export::export_history(db_conn, format, &filters, Path::new(&path), &emit_progress)
+            .map_err(|e| AppError::wrap("Failed to export history", e))
+    }).await
+}
+
+// Stream the visits an export with these filters would write to the calling
+// window as "stream-batch" events, for previewing exports of any size
+#[command]
+async fn stream_export_preview(
+    stream_id: String,
+    filters: Option<export::ExportFilter>,
+    batch_size: Option<usize>,
+    window: tauri::Window,
+    app_state: State<'_, AppState>,
+) -> Result<streaming::StreamSummary, AppError> {
+    let filters = filters.unwrap_or_default();
+    let streams = Arc::clone(&app_state.streams);
+    
+    run_blocking(&app_state, move |db_conn| {
+        let mut sender = streams.open(window, &stream_id, batch_size);
+        let result = export::for_each_row(db_conn, &filters, |row| sender.push(row));
+        Ok(sender.finish(result.err().map(|e| e.to_string())))
+    }).await
+}
+
+// Export visits matching the filters as Logseq or Roam daily-note outlines
+#[command]
+async fn export_outline(
+    format: export::outline::OutlineFormat,
+    filters: Option<export::ExportFilter>,
+    path: String,
+    app_state: State<'_, AppState>,
+) -> Result<export::ExportSummary, AppError> {
+    let filters = filters.unwrap_or_default();
+    
+    run_blocking(&app_state, move |db_conn| {
+        let tz = settings::get_timezone(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get timezone", e))?;
+        export::outline::export_outline(db_conn, format, &filters, Path::new(&path), tz)
+            .map_err(|e| AppError::wrap("Failed to export outline", e))
+    }).await
+}
+
+// Export the generated flashcards as a tab separated deck Anki can import
+#[command]
+async fn export_flashcards(
+    path: String,
+    app_state: State<'_, AppState>,
+) -> Result<export::ExportSummary, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        export::flashcards::export_flashcards(db_conn, Path::new(&path))
+            .map_err(|e| AppError::wrap("Failed to export flashcards", e))
+    }).await
+}
+
+// Write a complete takeout (database copy, visits, settings, archives) into a directory
+#[command]
+async fn export_everything(
+    path: String,
+    app_state: State<'_, AppState>,
+) -> Result<export::takeout::TakeoutSummary, AppError> {
+    let data_dir = get_app_data_dir()?;
+    
+    run_blocking(&app_state, move |db_conn| {
+        export::takeout::export_everything(db_conn, &data_dir, Path::new(&path))
+            .map_err(|e| AppError::wrap("Failed to export data", e))
+    }).await
+}
+
+// Issue the token erase_all_data must be confirmed with
+#[command]
+async fn request_erase_token(app_state: State<'_, AppState>) -> Result<String, AppError> {
+    app_state.app_lock.check()?;
+    
+    let token = uuid::Uuid::new_v4().to_string();
+    
+    *app_state.erase_token.lock()
+        .map_err(|_| AppError::internal("Failed to acquire erase token lock"))? = Some((token.clone(), Instant::now()));
+    
+    Ok(token)
+}
+
+// Close the database and securely wipe it with every archive and cache;
+// requires a token from request_erase_token issued in the last few minutes
+#[command]
+async fn erase_all_data(
+    confirmation_token: String,
+    app_handle: tauri::AppHandle,
+) -> Result<export::takeout::EraseSummary, AppError> {
+    let summary = run_blocking_with_state(app_handle.clone(), move |app_state| {
+        let issued = app_state.erase_token.lock()
+            .map_err(|_| AppError::internal("Failed to acquire erase token lock"))?
+            .take();
+        match issued {
+            Some((token, at)) if token == confirmation_token && at.elapsed() < ERASE_TOKEN_TTL => {},
+            _ => return Err(AppError::invalid_input("Invalid or expired confirmation token")),
+        }
+        
+        // Stop background enrichment before the database goes away
+        app_state.enrichment_queue.request_stop();
+        
+        let mut state_guard = app_state.db_connection.write()
+            .map_err(|_| AppError::internal("Failed to acquire database lock"))?;
+        
+        // Close the connection so the files can be overwritten, once running
+        // queries finish; clones held by background tasks close with it
+        if let Some(connection) = state_guard.as_ref() {
+            connection.close()
+                .map_err(|e| AppError::wrap("Failed to close database", e))?;
+        }
+        state_guard.take();
+        
+        export::takeout::erase_all_data(&get_app_data_dir()?)
+            .map_err(|e| AppError::wrap("Failed to erase data", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Erased);
+    
+    Ok(summary)
+}
+
+// Rebuild the knowledge graph from history and metadata
+#[command]
+async fn rebuild_graph(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<graph::GraphStats, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        run_as_job(&app_handle, db_conn, &jobs::JobRequest::RebuildGraph, |_| {
+            graph::rebuild_graph(db_conn)
+                .map_err(|e| AppError::wrap("Failed to rebuild graph", e))
+        })
+    }).await
+}
+
+// Get domains frequently browsed together
+#[command]
+async fn get_co_visited_domains(
+    domain: Option<String>,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<CoVisitedDomainResult>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let pairs = graph::get_co_visited_domains(db_conn, domain, limit.unwrap_or(50))
+            .map_err(|e| AppError::wrap("Failed to get co-visited domains", e))?;
+        
+        Ok(pairs.into_iter()
+            .map(|pair| CoVisitedDomainResult {
+                domain: pair.domain,
+                other_domain: pair.other_domain,
+                sessions: pair.sessions,
+            })
+            .collect())
+    }).await
+}
+
+// Export the knowledge graph as GraphML or GEXF
+#[command]
+async fn export_graph(
+    format: String,
+    path: String,
+    filters: Option<graph::GraphFilter>,
+    app_state: State<'_, AppState>,
+) -> Result<GraphExportResult, AppError> {
+    let graph_format = graph::GraphFormat::parse(&format)
+        .ok_or_else(|| AppError::invalid_input(format!("Unsupported graph format: {}", format)))?;
+    
+    run_blocking(&app_state, move |db_conn| {
+        let loaded = graph::load_graph(db_conn, &filters.unwrap_or_default())
+            .map_err(|e| AppError::wrap("Failed to load graph", e))?;
+        
+        graph::export_graph(&loaded, graph_format, Path::new(&path))
+            .map_err(|e| AppError::wrap("Failed to export graph", e))?;
+        
+        Ok(GraphExportResult {
+            path,
+            node_count: loaded.nodes.len(),
+            edge_count: loaded.edges.len(),
+        })
+    }).await
+}
+
+// Materialize the knowledge graph restricted to a time window
+#[command]
+async fn rebuild_graph_for_range(
+    start_date: String,
+    end_date: String,
+    label: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<graph::GraphStats, AppError> {
+    let start = parse_date(Some(start_date))
+        .ok_or_else(|| AppError::invalid_input("Invalid start date"))?;
+    let end = parse_date(Some(end_date))
+        .ok_or_else(|| AppError::invalid_input("Invalid end date"))?;
+    
+    run_blocking(&app_state, move |db_conn| {
+        let (_, stats) = graph::rebuild_graph_for_range(db_conn, start, end, label)
+            .map_err(|e| AppError::wrap("Failed to rebuild graph for range", e))?;
+        
+        Ok(stats)
+    }).await
+}
+
+// Rebuild one graph snapshot per month
+#[command]
+async fn rebuild_monthly_graph_snapshots(
+    start_date: Option<String>,
+    end_date: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<usize, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let snapshots = graph::rebuild_monthly_snapshots(db_conn, parse_date(start_date), parse_date(end_date))
+            .map_err(|e| AppError::wrap("Failed to rebuild monthly graph snapshots", e))?;
+        
+        Ok(snapshots.len())
+    }).await
+}
+
+// Get the monthly graph snapshots, oldest first, for animating graph evolution
+#[command]
+async fn get_graph_snapshots(
+    filters: Option<graph::GraphFilter>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<GraphSnapshotResult>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let snapshots = graph::list_snapshots(db_conn, true)
+            .map_err(|e| AppError::wrap("Failed to list graph snapshots", e))?;
+        
+        let mut filter = filters.unwrap_or_default();
+        let mut results = Vec::with_capacity(snapshots.len());
+        
+        for snapshot in snapshots {
+            filter.snapshot_id = Some(snapshot.id);
+            let loaded = graph::load_graph(db_conn, &filter)
+                .map_err(|e| AppError::wrap(&format!("Failed to load graph snapshot {}", snapshot.label), e))?;
+            
+            results.push(GraphSnapshotResult {
+                id: snapshot.id,
+                label: snapshot.label,
+                start: snapshot.start.to_rfc3339(),
+                end: snapshot.end.to_rfc3339(),
+                nodes: loaded.nodes,
+                edges: loaded.edges,
+            });
+        }
+        
+        Ok(results)
+    }).await
+}
+
+// Precompute node positions so the frontend only has to draw the graph
+#[command]
+async fn compute_graph_layout(
+    options: Option<graph::LayoutOptions>,
+    app_state: State<'_, AppState>,
+) -> Result<usize, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        graph::compute_layout(db_conn, &options.unwrap_or_default())
+            .map_err(|e| AppError::wrap("Failed to compute graph layout", e))
+    }).await
+}
+
+// Find the navigation chains that led from one page to another
+#[command]
+async fn find_paths(
+    url_a: String,
+    url_b: String,
+    max_hops: Option<usize>,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<NavigationPathResult>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let paths = graph::find_paths(
+            db_conn,
+            &url_a,
+            &url_b,
+            max_hops.unwrap_or(graph::paths::DEFAULT_MAX_HOPS),
+            limit.unwrap_or(10),
+        ).map_err(|e| AppError::wrap("Failed to find paths", e))?;
+        
+        Ok(paths.into_iter()
+            .map(|path| NavigationPathResult {
+                steps: path.steps.into_iter()
+                    .map(|step| PathStepResult {
+                        url: step.url,
+                        title: step.title,
+                        visited_at: step.visited_at.to_rfc3339(),
+                    })
+                    .collect(),
+                occurrences: path.occurrences,
+            })
+            .collect())
+    }).await
+}
+
+// Get pages related to a URL for the "you also looked at" panel
+#[command]
+async fn get_related(
+    url_id: String,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<RelatedPageResult>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let related = graph::get_related(db_conn, &url_id, limit.unwrap_or(graph::DEFAULT_RELATED_LIMIT))
+            .map_err(|e| AppError::wrap("Failed to get related pages", e))?;
+        
+        Ok(related.into_iter().map(RelatedPageResult::from).collect())
+    }).await
+}
+
+// List the installed enrichment and exporter plugins, and where to install more
+#[command]
+async fn list_plugins(app_state: State<'_, AppState>) -> Result<plugins::PluginDirectory, AppError> {
+    app_state.app_lock.check()?;
+    tauri::async_runtime::spawn_blocking(plugins::list_plugins)
+        .await
+        .map_err(|e| AppError::wrap("Plugin task failed", e))?
+        .map_err(|e| AppError::wrap("Failed to list plugins", e))
+}
+
+// Get the AI enrichment provider settings
+#[command]
+async fn get_enrichment_settings(
+    app_state: State<'_, AppState>,
+) -> Result<enrichment::EnrichmentSettings, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        enrichment::get_enrichment_settings(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get enrichment settings", e))
+    }).await
+}
+
+// Update the AI enrichment provider settings
+#[command]
+async fn set_enrichment_settings(
+    settings: enrichment::EnrichmentSettings,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        enrichment::set_enrichment_settings(db_conn, &settings)
+            .map_err(|e| AppError::wrap("Failed to save enrichment settings", e))?;
+        emit_settings_changed(&app_handle, db_conn);
+        Ok(())
+    }).await
+}
+
+// Get every setting
+#[command]
+async fn get_settings(app_state: State<'_, AppState>) -> Result<settings::AppSettings, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        settings::get_settings(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get settings", e))
+    }).await
+}
+
+// Replace every setting, emitting "settings-changed" with the new values
+#[command]
+async fn update_settings(
+    settings: settings::AppSettings,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let handle = app_handle.clone();
+    run_blocking(&app_state, move |db_conn| {
+        settings::set_settings(db_conn, &settings)
+            .map_err(|e| AppError::wrap("Failed to save settings", e))?;
+        emit_settings_changed(&handle, db_conn);
+        update_history_watcher(&handle, db_conn)?;
+        update_capture_server(&handle, db_conn)?;
+        update_mcp_server(&handle, db_conn)?;
+        update_lan_sync(&handle, db_conn)?;
+        update_global_shortcut(&handle, db_conn)
+    }).await?;
+    
+    // Let the worker pick up a changed import schedule right away
+    start_job_worker(&app_handle);
+    
+    Ok(())
+}
+
+// Replace the browser extension's capture token, invalidating the old one
+#[command]
+async fn rotate_capture_token(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<capture::CaptureSettings, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let mut settings = capture::get_capture_settings(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get capture settings", e))?;
+        settings.token = capture::new_token();
+        capture::set_capture_settings(db_conn, &settings)
+            .map_err(|e| AppError::wrap("Failed to save capture settings", e))?;
+        
+        emit_settings_changed(&app_handle, db_conn);
+        update_capture_server(&app_handle, db_conn)?;
+        Ok(settings)
+    }).await
+}
+
+// Stop or restart the browser extension's endpoint without changing the capture settings
+#[command]
+async fn set_capture_paused(
+    paused: bool,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        pause_capture(&app_handle, db_conn, paused)
+    }).await
+}
+
+// Get the assistant sessions connected over MCP
+#[command]
+async fn list_mcp_sessions(app_state: State<'_, AppState>) -> Result<Vec<mcp::McpSession>, AppError> {
+    app_state.app_lock.check()?;
+    Ok(app_state.mcp_sessions.list())
+}
+
+// Allow or refuse an assistant session access to the history
+#[command]
+async fn answer_mcp_session(
+    id: String,
+    allow: bool,
+    app_state: State<'_, AppState>,
+) -> Result<mcp::McpSession, AppError> {
+    app_state.app_lock.check()?;
+    app_state.mcp_sessions.answer(&id, allow)
+        .map_err(|e| AppError::wrap("Failed to answer MCP session", e))
+}
+
+// End an assistant session; its client has to connect and ask again
+#[command]
+async fn end_mcp_session(id: String, app_state: State<'_, AppState>) -> Result<(), AppError> {
+    app_state.app_lock.check()?;
+    app_state.mcp_sessions.close(&id)
+        .map_err(|e| AppError::wrap("Failed to end MCP session", e))
+}
+
+// List the user scripts run at import and every night
+#[command]
+async fn list_scripts(app_state: State<'_, AppState>) -> Result<Vec<scripting::Script>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        scripting::list_scripts(db_conn)
+            .map_err(|e| AppError::wrap("Failed to list scripts", e))
+    }).await
+}
+
+// Create or update a user script; it is saved only if it compiles
+#[command]
+async fn save_script(
+    script: scripting::ScriptInput,
+    app_state: State<'_, AppState>,
+) -> Result<scripting::Script, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        scripting::save_script(db_conn, &script)
+            .map_err(|e| AppError::wrap("Failed to save script", e))
+    }).await
+}
+
+// Delete a user script
+#[command]
+async fn delete_script(id: i64, app_state: State<'_, AppState>) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        scripting::delete_script(db_conn, id)
+            .map_err(|e| AppError::wrap("Failed to delete script", e))
+    }).await
+}
+
+// Run a script on a sample page without saving anything, to try it out
+#[command]
+async fn test_script(
+    source: String,
+    page: scripting::ScriptPage,
+    app_state: State<'_, AppState>,
+) -> Result<scripting::ScriptPage, AppError> {
+    app_state.app_lock.check()?;
+    tauri::async_runtime::spawn_blocking(move || scripting::test_script(&source, page))
+        .await
+        .map_err(|e| AppError::wrap("Script task failed", e))?
+        .map_err(|e| AppError::wrap("Script failed", e))
+}
+
+// Get the rules that keep URLs out of the database
+#[command]
+async fn get_privacy_rules(app_state: State<'_, AppState>) -> Result<privacy::PrivacyRules, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        privacy::get_privacy_rules(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get privacy rules", e))
+    }).await
+}
+
+// Update the rules that keep URLs out of the database
+#[command]
+async fn set_privacy_rules(
+    rules: privacy::PrivacyRules,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        privacy::set_privacy_rules(db_conn, &rules)
+            .map_err(|e| AppError::wrap("Failed to save privacy rules", e))?;
+        emit_settings_changed(&app_handle, db_conn);
+        Ok(())
+    }).await
+}
+
+// Apply the privacy rules to already imported history
+#[command]
+async fn apply_privacy_rules(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<privacy::redact::PrivacyReport, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        privacy::redact::apply_privacy_rules(db_conn)
+            .map_err(|e| AppError::wrap("Failed to apply privacy rules", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Purged);
+    
+    Ok(result)
+}
+
+// Enrich the given URLs with a summary, keywords and category
+#[command]
+async fn enrich_urls(
+    ids: Vec<String>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<EnrichmentRunResult, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        let pages = enrichment::get_pages(db_conn, &ids)
+            .map_err(|e| AppError::wrap("Failed to load URLs", e))?;
+        
+        run_enrichment(db_conn, &pages)
+    }).await?;
+    
+    events::emit_enrichment_completed(&app_handle, result.enriched, result.failed);
+    
+    Ok(result)
+}
+
+// Enrich the most visited URLs that have not been enriched yet
+#[command]
+async fn enrich_all_unenriched(
+    limit: Option<usize>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<EnrichmentRunResult, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        let pages = enrichment::get_unenriched_pages(db_conn, limit.unwrap_or(enrichment::DEFAULT_ENRICH_BATCH))
+            .map_err(|e| AppError::wrap("Failed to load unenriched URLs", e))?;
+        
+        run_enrichment(db_conn, &pages)
+    }).await?;
+    
+    events::emit_enrichment_completed(&app_handle, result.enriched, result.failed);
+    
+    Ok(result)
+}
+
+// Compute embeddings for URLs that don't have one yet
+#[command]
+async fn embed_urls(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<usize, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let settings = enrichment::get_enrichment_settings(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get enrichment settings", e))?;
+        
+        let provider = enrichment::create_embedding_provider(&settings)
+            .map_err(|e| AppError::wrap("Failed to create embedding provider", e))?;
+        
+        enrichment::embed_missing(db_conn, provider.as_ref(), limit.unwrap_or(enrichment::embeddings::DEFAULT_EMBED_LIMIT))
+            .map_err(|e| AppError::wrap("Failed to embed URLs", e))
+    }).await
+}
+
+// Find pages by meaning rather than exact words
+#[command]
+async fn semantic_search(
+    text: String,
+    k: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<SemanticMatchResult>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let settings = enrichment::get_enrichment_settings(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get enrichment settings", e))?;
+        
+        let provider = enrichment::create_embedding_provider(&settings)
+            .map_err(|e| AppError::wrap("Failed to create embedding provider", e))?;
+        
+        let matches = enrichment::semantic_search(db_conn, provider.as_ref(), &text, k.unwrap_or(20))
+            .map_err(|e| AppError::wrap("Failed to run semantic search", e))?;
+        
+        Ok(matches.into_iter()
+            .map(|m| SemanticMatchResult {
+                url_id: m.url_id,
+                url: m.url,
+                title: m.title,
+                score: m.score,
+            })
+            .collect())
+    }).await
+}
+
+// Answer a question about the history using the most relevant pages, with citations
+#[command]
+async fn ask_history(
+    question: String,
+    k: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<HistoryAnswerResult, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let settings = enrichment::get_enrichment_settings(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get enrichment settings", e))?;
+        
+        let chat = enrichment::create_provider(&settings)
+            .map_err(|e| AppError::wrap("Failed to create enrichment provider", e))?;
+        let embedder = enrichment::create_embedding_provider(&settings)
+            .map_err(|e| AppError::wrap("Failed to create embedding provider", e))?;
+        
+        let answer = enrichment::ask_history(
+            db_conn,
+            chat.as_ref(),
+            embedder.as_ref(),
+            &question,
+            k.unwrap_or(enrichment::DEFAULT_ASK_SOURCES),
+        ).map_err(|e| AppError::wrap("Failed to answer question", e))?;
+        
+        Ok(HistoryAnswerResult {
+            answer: answer.answer,
+            sources: answer.sources.into_iter()
+                .map(|source| AnswerSourceResult {
+                    number: source.number,
+                    url_id: source.url_id,
+                    url: source.url,
+                    title: source.title,
+                    visit_count: source.visit_count,
+                    last_visit: source.last_visit.map(|at| at.to_rfc3339()),
+                    score: source.score,
+                    cited: source.cited,
+                })
+                .collect(),
+        })
+    }).await
+}
+
+// Generate study flashcards from enriched technical articles with the enrichment provider
+#[command]
+async fn generate_flashcards(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<enrichment::flashcards::FlashcardRun, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let settings = enrichment::get_enrichment_settings(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get enrichment settings", e))?;
+        
+        let provider = enrichment::create_provider(&settings)
+            .map_err(|e| AppError::wrap("Failed to create enrichment provider", e))?;
+        
+        enrichment::flashcards::generate_flashcards(
+            db_conn,
+            provider.as_ref(),
+            limit.unwrap_or(enrichment::flashcards::DEFAULT_FLASHCARD_PAGES),
+        ).map_err(|e| AppError::wrap("Failed to generate flashcards", e))
+    }).await
+}
+
+// Get the generated flashcards, optionally of one page
+#[command]
+async fn get_flashcards(
+    url_id: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<enrichment::flashcards::Flashcard>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        enrichment::flashcards::get_flashcards(db_conn, url_id.as_deref())
+            .map_err(|e| AppError::wrap("Failed to get flashcards", e))
+    }).await
+}
+
+// Delete a flashcard
+#[command]
+async fn delete_flashcard(
+    id: i64,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        enrichment::flashcards::delete_flashcard(db_conn, id)
+            .map_err(|e| AppError::wrap("Failed to delete flashcard", e))
+    }).await
+}
+
+// Answer a question with a generated read-only SQL query, returning the rows
+#[command]
+async fn query_history_nl(
+    question: String,
+    max_rows: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<NlQueryResultResponse, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let settings = enrichment::get_enrichment_settings(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get enrichment settings", e))?;
+        
+        let provider = enrichment::create_provider(&settings)
+            .map_err(|e| AppError::wrap("Failed to create enrichment provider", e))?;
+        
+        let result = enrichment::query_history(
+            db_conn,
+            provider.as_ref(),
+            &question,
+            max_rows.unwrap_or(db::readonly::DEFAULT_MAX_ROWS),
+        ).map_err(|e| AppError::wrap("Failed to query history", e))?;
+        
+        Ok(NlQueryResultResponse {
+            sql: result.sql,
+            table: result.table,
+        })
+    }).await
+}
+
+// Run a power user's SELECT on a read-only connection, with a row limit and a timeout
+#[command]
+async fn run_readonly_sql(
+    query: String,
+    max_rows: Option<usize>,
+    timeout_secs: Option<u64>,
+    app_state: State<'_, AppState>,
+) -> Result<db::readonly::ConsoleResult, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::readonly::run_console_query(
+            db_conn,
+            &query,
+            &[],
+            max_rows.unwrap_or(db::readonly::DEFAULT_CONSOLE_ROWS),
+            timeout_secs.map_or(db::readonly::DEFAULT_CONSOLE_TIMEOUT, Duration::from_secs),
+        ).map_err(|e| AppError::wrap("Failed to run query", e))
+    }).await
+}
+
+// List the saved report templates
+#[command]
+async fn list_report_templates(
+    app_state: State<'_, AppState>,
+) -> Result<Vec<report::templates::ReportTemplate>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        report::templates::list_templates(db_conn)
+            .map_err(|e| AppError::wrap("Failed to list report templates", e))
+    }).await
+}
+
+// Create or update a report template
+#[command]
+async fn save_report_template(
+    template: report::templates::ReportTemplateInput,
+    app_state: State<'_, AppState>,
+) -> Result<report::templates::ReportTemplate, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        report::templates::save_template(db_conn, &template)
+            .map_err(|e| AppError::wrap("Failed to save report template", e))
+    }).await
+}
+
+// Delete a report template and its stored outputs
+#[command]
+async fn delete_report_template(
+    id: i64,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        report::templates::delete_template(db_conn, id)
+            .map_err(|e| AppError::wrap("Failed to delete report template", e))
+    }).await
+}
+
+// Run a report template now with the given parameter values and store the output
+#[command]
+async fn run_report_template(
+    id: i64,
+    parameters: Option<HashMap<String, String>>,
+    app_state: State<'_, AppState>,
+) -> Result<report::templates::ReportOutput, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        report::templates::run_template(
+            db_conn,
+            id,
+            &parameters.unwrap_or_default(),
+            report::templates::RunTrigger::Manual,
+        ).map_err(|e| AppError::wrap("Failed to run report template", e))
+    }).await
+}
+
+// List a report template's past runs, newest first
+#[command]
+async fn get_report_outputs(
+    template_id: i64,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<report::templates::ReportOutput>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        report::templates::list_outputs(db_conn, template_id, limit.unwrap_or(20))
+            .map_err(|e| AppError::wrap("Failed to list report outputs", e))
+    }).await
+}
+
+// Get one stored report output with its rows
+#[command]
+async fn get_report_output(
+    id: i64,
+    app_state: State<'_, AppState>,
+) -> Result<report::templates::ReportOutput, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        report::templates::get_output(db_conn, id)
+            .map_err(|e| AppError::wrap("Failed to load report output", e))
+    }).await
+}
+
+// Assign categories to URLs, optionally asking the enrichment provider about unknown domains
+#[command]
+async fn categorize_urls(
+    use_model: Option<bool>,
+    model_limit: Option<usize>,
+    recategorize: Option<bool>,
+    app_state: State<'_, AppState>,
+) -> Result<CategorizeResult, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let provider = if use_model.unwrap_or(false) {
+            let settings = enrichment::get_enrichment_settings(db_conn)
+                .map_err(|e| AppError::wrap("Failed to get enrichment settings", e))?;
+            Some(enrichment::create_provider(&settings)
+                .map_err(|e| AppError::wrap("Failed to create enrichment provider", e))?)
+        } else {
+            None
+        };
+        
+        let run = enrichment::categorize_urls(
+            db_conn,
+            provider.as_deref(),
+            model_limit.unwrap_or(enrichment::DEFAULT_ENRICH_BATCH),
+            recategorize.unwrap_or(false),
+        ).map_err(|e| AppError::wrap("Failed to categorize URLs", e))?;
+        
+        Ok(CategorizeResult {
+            by_rules: run.by_rules,
+            by_model: run.by_model,
+            uncategorized: run.uncategorized,
+            errors: run.errors,
+        })
+    }).await
+}
+
+// Get visits per URL category
+#[command]
+async fn get_category_stats(
+    start_date: Option<String>,
+    end_date: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<CategoryStatsResult>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let stats = db::analytics::get_category_stats(db_conn, parse_date(start_date), parse_date(end_date))
+            .map_err(|e| AppError::wrap("Failed to get category stats", e))?;
+        
+        Ok(stats.into_iter()
+            .map(|(category, visit_count)| CategoryStatsResult { category, visit_count })
+            .collect())
+    }).await
+}
+
+// Get the keywords of the most visited pages in a date range
+#[command]
+async fn get_top_keywords(
+    start_date: Option<String>,
+    end_date: Option<String>,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::keywords::KeywordCount>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::keywords::top_keywords(
+            db_conn,
+            parse_date(start_date),
+            parse_date(end_date),
+            limit.unwrap_or(db::keywords::DEFAULT_TOP_KEYWORDS),
+        ).map_err(|e| AppError::wrap("Failed to get top keywords", e))
+    }).await
+}
+
+// Queue URLs for background enrichment (all unenriched URLs when no ids are given) and start the workers
+#[command]
+async fn start_enrichment_queue(
+    ids: Option<Vec<String>>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<enrichment::QueueStatus, AppError> {
+    let control = app_state.enrichment_queue.clone();
+    let (status, job) = run_blocking(&app_state, move |db_conn| {
+        match ids {
+            Some(ids) => enrichment::queue::enqueue(db_conn, &ids),
+            None => enrichment::queue::enqueue_unenriched(db_conn),
+        }.map_err(|e| AppError::wrap("Failed to queue URLs", e))?;
+        
+        let job = jobs::enqueue_job(db_conn, &jobs::JobRequest::Enrichment)
+            .map_err(|e| AppError::wrap("Failed to queue enrichment job", e))?;
+        
+        let status = enrichment::queue::queue_status(db_conn, &control)
+            .map_err(|e| AppError::wrap("Failed to get enrichment queue status", e))?;
+        
+        Ok((status, job))
+    }).await?;
+    
+    events::emit_job_progress(&app_handle, &job);
+    start_job_worker(&app_handle);
+    
+    Ok(status)
+}
+
+// Stop the enrichment workers after their current page; queued jobs are kept
+#[command]
+async fn stop_enrichment_queue(app_state: State<'_, AppState>) -> Result<(), AppError> {
+    app_state.enrichment_queue.request_stop();
+    Ok(())
+}
+
+// Get the number of queued, running, finished and failed enrichment jobs
+#[command]
+async fn get_enrichment_queue_status(
+    app_state: State<'_, AppState>,
+) -> Result<enrichment::QueueStatus, AppError> {
+    let control = app_state.enrichment_queue.clone();
+    run_blocking(&app_state, move |db_conn| {
+        enrichment::queue::queue_status(db_conn, &control)
+            .map_err(|e| AppError::wrap("Failed to get enrichment queue status", e))
+    }).await
+}
+
+// Get enrichment requests, tokens and cost per run, plus an estimate for the remaining pages
+#[command]
+async fn get_enrichment_usage(
+    app_state: State<'_, AppState>,
+) -> Result<enrichment::UsageReport, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let settings = enrichment::get_enrichment_settings(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get enrichment settings", e))?;
+        
+        enrichment::get_enrichment_usage(db_conn, &settings)
+            .map_err(|e| AppError::wrap("Failed to get enrichment usage", e))
+    }).await
+}
+
+// Capture preview thumbnails for enriched pages that don't have one yet
+#[command]
+async fn capture_thumbnails(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<ThumbnailRunResult, AppError> {
+    let thumbnails_dir = get_app_data_dir()?.join("thumbnails");
+    
+    run_blocking(&app_state, move |db_conn| {
+        let run = web::capture_thumbnails(db_conn, &thumbnails_dir, limit.unwrap_or(web::DEFAULT_THUMBNAIL_LIMIT))
+            .map_err(|e| AppError::wrap("Failed to capture thumbnails", e))?;
+        
+        Ok(ThumbnailRunResult {
+            captured: run.captured,
+            missing: run.missing,
+            failed: run.failed,
+            errors: run.errors,
+        })
+    }).await
+}
+
+// Get the thumbnail file path of a URL, if one was captured
+#[command]
+async fn get_thumbnail(
+    url_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Option<String>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        web::get_thumbnail_path(db_conn, &url_id)
+            .map_err(|e| AppError::wrap("Failed to get thumbnail", e))
+    }).await
+}
+
+// Save single-file snapshots of the selected URLs into the archives directory
+#[command]
+async fn archive_urls(
+    url_ids: Vec<String>,
+    app_state: State<'_, AppState>,
+) -> Result<ArchiveRunResult, AppError> {
+    let archives_dir = get_app_data_dir()?.join("archives");
+    
+    run_blocking(&app_state, move |db_conn| {
+        let run = web::archive_urls(db_conn, &archives_dir, &url_ids)
+            .map_err(|e| AppError::wrap("Failed to archive URLs", e))?;
+        
+        Ok(ArchiveRunResult {
+            archives: run.archives,
+            errors: run.errors,
+        })
+    }).await
+}
+
+// List archived snapshots, optionally for a single URL
+#[command]
+async fn get_archives(
+    url_id: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<web::Archive>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        web::list_archives(db_conn, url_id.as_deref())
+            .map_err(|e| AppError::wrap("Failed to list archives", e))
+    }).await
+}
+
+// Re-fetch fingerprinted pages that are due and flag the ones that changed
+#[command]
+async fn check_content_changes(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<web::changes::ContentCheckRun, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let settings = web::changes::get_content_check_settings(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get content check settings", e))?;
+        web::changes::check_content_changes(db_conn, limit.unwrap_or(settings.batch_size), settings.interval_days)
+            .map_err(|e| AppError::wrap("Failed to check pages for changes", e))
+    }).await
+}
+
+// List pages whose content changed since they were last read
+#[command]
+async fn get_changed_pages(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<web::changes::ChangedPage>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        web::changes::get_changed_pages(db_conn, limit.unwrap_or(web::changes::DEFAULT_CHANGED_PAGES))
+            .map_err(|e| AppError::wrap("Failed to get changed pages", e))
+    }).await
+}
+
+// Accept a changed page's current content as read
+#[command]
+async fn acknowledge_content_change(
+    url_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        web::changes::acknowledge_content_change(db_conn, &url_id)
+            .map_err(|e| AppError::wrap("Failed to acknowledge content change", e))
+    }).await
+}
+
+// Look up the registrar, registration date and hosting country of domains
+// not looked up recently, most visited first
+#[command]
+async fn enrich_domains(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<web::provenance::DomainEnrichmentRun, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let settings = web::provenance::get_domain_enrichment_settings(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get domain enrichment settings", e))?;
+        web::provenance::enrich_domains(db_conn, limit.unwrap_or(settings.batch_size), settings.interval_days)
+            .map_err(|e| AppError::wrap("Failed to enrich domains", e))
+    }).await
+}
+
+// Link the most mentioned entities and most visited domains to Wikidata items
+// and store the identifiers on their graph nodes
+#[command]
+async fn link_wikidata(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<web::wikidata::WikidataRun, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        web::wikidata::link_wikidata(db_conn, limit.unwrap_or(web::wikidata::DEFAULT_WIKIDATA_LIMIT))
+            .map_err(|e| AppError::wrap("Failed to link Wikidata items", e))
+    }).await
+}
+
+// Link an entity or domain to a Wikidata item by hand, or mark it as having none
+#[command]
+async fn set_wikidata_link(
+    node_type: graph::NodeType,
+    key: String,
+    qid: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        web::wikidata::set_wikidata_link(db_conn, node_type, &key, qid.as_deref())
+            .map_err(|e| AppError::wrap("Failed to set Wikidata link", e))
+    }).await
+}
+
+// Fetch enriched pages not scored yet and score the tone of their text
+#[command]
+async fn score_sentiment(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<web::tone::SentimentRun, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        web::tone::score_sentiment(db_conn, limit.unwrap_or(web::tone::DEFAULT_SENTIMENT_LIMIT))
+            .map_err(|e| AppError::wrap("Failed to score page tone", e))
+    }).await
+}
+
+// Resolve shortened URLs (t.co, bit.ly, ...) and merge them into their destination
+#[command]
+async fn expand_shortened_urls(
+    limit: Option<usize>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<ExpandResult, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        let run = web::expand_shortened_urls(db_conn, limit.unwrap_or(web::DEFAULT_EXPAND_LIMIT))
+            .map_err(|e| AppError::wrap("Failed to expand shortened URLs", e))?;
+        
+        Ok(ExpandResult {
+            expanded: run.expanded,
+            merged: run.merged,
+            failed: run.failed,
+            errors: run.errors,
+        })
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Merged);
+    
+    Ok(result)
+}
+
+// List all tags with the number of tagged URLs
+#[command]
+async fn get_tags(app_state: State<'_, AppState>) -> Result<Vec<db::tags::Tag>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::tags::list_tags(db_conn)
+            .map_err(|e| AppError::wrap("Failed to list tags", e))
+    }).await
+}
+
+// Create a tag (returns the existing tag if the name is taken)
+#[command]
+async fn create_tag(name: String, app_state: State<'_, AppState>) -> Result<db::tags::Tag, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::tags::create_tag(db_conn, &name)
+            .map_err(|e| AppError::wrap("Failed to create tag", e))
+    }).await
+}
+
+// Rename a tag
+#[command]
+async fn rename_tag(
+    tag_id: i64,
+    name: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<db::tags::Tag, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::tags::rename_tag(db_conn, tag_id, &name)
+            .map_err(|e| AppError::wrap("Failed to rename tag", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Tagged);
+    
+    Ok(result)
+}
+
+// Merge tags into a target tag, deleting the merged tags
+#[command]
+async fn merge_tags(
+    source_ids: Vec<i64>,
+    target_id: i64,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<db::tags::Tag, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::tags::merge_tags(db_conn, &source_ids, target_id)
+            .map_err(|e| AppError::wrap("Failed to merge tags", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Tagged);
+    
+    Ok(result)
+}
+
+// Delete a tag and remove it from every URL
+#[command]
+async fn delete_tag(
+    tag_id: i64,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::tags::delete_tag(db_conn, tag_id)
+            .map_err(|e| AppError::wrap("Failed to delete tag", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Tagged);
+    
+    Ok(result)
+}
+
+// Add tags (created as needed) to URLs in bulk, returning the number of new assignments
+#[command]
+async fn tag_urls(
+    url_ids: Vec<String>,
+    tags: Vec<String>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<usize, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::tags::tag_urls(db_conn, &url_ids, &tags)
+            .map_err(|e| AppError::wrap("Failed to tag URLs", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Tagged);
+    
+    Ok(result)
+}
+
+// Remove tags from URLs in bulk, returning the number of removed assignments
+#[command]
+async fn untag_urls(
+    url_ids: Vec<String>,
+    tag_ids: Vec<i64>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<usize, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::tags::untag_urls(db_conn, &url_ids, &tag_ids)
+            .map_err(|e| AppError::wrap("Failed to untag URLs", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Tagged);
+    
+    Ok(result)
+}
+
+// Star or unstar URLs, returning the number of updated URLs
+#[command]
+async fn set_favorite(
+    url_ids: Vec<String>,
+    favorite: bool,
+    app_state: State<'_, AppState>,
+) -> Result<usize, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::collections::set_favorite(db_conn, &url_ids, favorite)
+            .map_err(|e| AppError::wrap("Failed to update favorites", e))
+    }).await
+}
+
+// Get all favorite URLs
+#[command]
+async fn get_favorites(app_state: State<'_, AppState>) -> Result<Vec<dto::UrlSummaryDto>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let favorites = db::collections::get_favorites(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get favorites", e))?;
+        
+        Ok(dto::url_summaries(&favorites))
+    }).await
+}
+
+// List all collections
+#[command]
+async fn get_collections(
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::collections::Collection>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::collections::list_collections(db_conn)
+            .map_err(|e| AppError::wrap("Failed to list collections", e))
+    }).await
+}
+
+// Create an empty collection
+#[command]
+async fn create_collection(
+    name: String,
+    description: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<db::collections::Collection, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::collections::create_collection(db_conn, &name, description.as_deref())
+            .map_err(|e| AppError::wrap("Failed to create collection", e))
+    }).await
+}
+
+// Rename a collection or change its description
+#[command]
+async fn update_collection(
+    collection_id: i64,
+    name: Option<String>,
+    description: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<db::collections::Collection, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::collections::update_collection(db_conn, collection_id, name.as_deref(), description.as_deref())
+            .map_err(|e| AppError::wrap("Failed to update collection", e))
+    }).await
+}
+
+// Delete a collection, keeping its URLs in the history
+#[command]
+async fn delete_collection(
+    collection_id: i64,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::collections::delete_collection(db_conn, collection_id)
+            .map_err(|e| AppError::wrap("Failed to delete collection", e))
+    }).await
+}
+
+// Get the URLs of a collection in order
+#[command]
+async fn get_collection_items(
+    collection_id: i64,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::collections::CollectionItem>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::collections::get_collection_items(db_conn, collection_id)
+            .map_err(|e| AppError::wrap("Failed to get collection items", e))
+    }).await
+}
+
+// Add URLs to a collection, appended or at the given position
+#[command]
+async fn add_to_collection(
+    collection_id: i64,
+    url_ids: Vec<String>,
+    position: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<db::collections::Collection, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::collections::add_to_collection(db_conn, collection_id, &url_ids, position)
+            .map_err(|e| AppError::wrap("Failed to add to collection", e))
+    }).await
+}
+
+// Remove URLs from a collection
+#[command]
+async fn remove_from_collection(
+    collection_id: i64,
+    url_ids: Vec<String>,
+    app_state: State<'_, AppState>,
+) -> Result<db::collections::Collection, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::collections::remove_from_collection(db_conn, collection_id, &url_ids)
+            .map_err(|e| AppError::wrap("Failed to remove from collection", e))
+    }).await
+}
+
+// Reorder the URLs of a collection
+#[command]
+async fn reorder_collection(
+    collection_id: i64,
+    url_ids: Vec<String>,
+    app_state: State<'_, AppState>,
+) -> Result<db::collections::Collection, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::collections::reorder_collection(db_conn, collection_id, &url_ids)
+            .map_err(|e| AppError::wrap("Failed to reorder collection", e))
+    }).await
+}
+
+// Set or clear the note on a collection item
+#[command]
+async fn set_collection_note(
+    collection_id: i64,
+    url_id: String,
+    note: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::collections::set_collection_note(db_conn, collection_id, &url_id, note.as_deref())
+            .map_err(|e| AppError::wrap("Failed to set collection note", e))
+    }).await
+}
+
+// Rename a URL; the edit is kept across re-imports
+#[command]
+async fn update_url_title(
+    url_id: String,
+    title: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::editing::update_url_title(db_conn, &url_id, &title)
+            .map_err(|e| AppError::wrap("Failed to update title", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Edited);
+    
+    Ok(result)
+}
+
+// Correct the domain of a URL and recategorize it
+#[command]
+async fn update_url_domain(
+    url_id: String,
+    domain: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::editing::update_url_domain(db_conn, &url_id, &domain)
+            .map_err(|e| AppError::wrap("Failed to update domain", e))?;
+        
+        // The category came from the old domain, apply the rules again
+        enrichment::categorize_urls(db_conn, None, 0, false)
+            .map_err(|e| AppError::wrap("Failed to categorize URLs", e))?;
+        
+        Ok(())
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Edited);
+    
+    Ok(result)
+}
+
+// Delete individual visits so they are not re-imported
+#[command]
+async fn delete_visits(
+    visit_ids: Vec<String>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<usize, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::editing::delete_visits(db_conn, &visit_ids)
+            .map_err(|e| AppError::wrap("Failed to delete visits", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Deleted);
+    
+    Ok(result)
+}
+
+// Permanently delete URLs and scrub them from the database file
+#[command]
+async fn secure_purge_urls(
+    url_ids: Vec<String>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<db::maintenance::PurgeResult, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::maintenance::secure_purge_urls(db_conn, &url_ids)
+            .map_err(|e| AppError::wrap("Failed to purge URLs", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Purged);
+    
+    Ok(result)
+}
+
+// Record a visit made outside the browser, e.g. offline reading
+#[command]
+async fn add_manual_visit(
+    url: String,
+    title: Option<String>,
+    visited_at: String,
+    duration_sec: Option<f64>,
+    device_name: Option<String>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        let visited_at = parse_date(Some(visited_at))
+            .ok_or_else(|| AppError::invalid_input("Invalid visit date"))?;
+        
+        db::editing::add_manual_visit(
+            db_conn,
+            &url,
+            title.as_deref(),
+            visited_at,
+            duration_sec,
+            device_name.as_deref(),
+        ).map_err(|e| AppError::wrap("Failed to add visit", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Edited);
+    
+    Ok(result)
+}
+
+// Open a URL in the default browser, recording it as a manual visit if asked
+#[command]
+async fn open_url(
+    id: String,
+    record_visit: Option<bool>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let record_visit = record_visit.unwrap_or(false);
+    let handle = app_handle.clone();
+    run_blocking(&app_state, move |db_conn| {
+        let link = db::operations::get_page_links(db_conn, &[id.clone()])
+            .map_err(|e| AppError::wrap("Failed to get URL", e))?
+            .pop()
+            .ok_or_else(|| AppError::new(ErrorKind::NotFound, format!("URL {} not found", id)))?;
+        
+        links::open_in_browser(&handle, &link.url)?;
+        
+        if record_visit {
+            db::editing::add_manual_visit(db_conn, &link.url, link.title.as_deref(), Utc::now(), None, None)
+                .map_err(|e| AppError::wrap("Failed to record visit", e))?;
+        }
+        Ok(())
+    }).await?;
+    
+    if record_visit {
+        events::emit_history_updated(&app_handle, events::HistoryChange::Edited);
+    }
+    
+    Ok(())
+}
+
+// Copy URLs to the clipboard as a Markdown or plain list, returning the copied text
+#[command]
+async fn copy_urls(
+    ids: Vec<String>,
+    format: Option<links::CopyFormat>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let page_links = run_blocking(&app_state, move |db_conn| {
+        db::operations::get_page_links(db_conn, &ids)
+            .map_err(|e| AppError::wrap("Failed to get URLs", e))
+    }).await?;
+    
+    let text = links::format_links(&page_links, format.unwrap_or_default());
+    links::copy_to_clipboard(&app_handle, text.clone())?;
+    
+    Ok(text)
+}
+
+// Get the edit log of a URL
+#[command]
+async fn get_url_edits(
+    url_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::editing::UrlEdit>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::editing::get_url_edits(db_conn, &url_id)
+            .map_err(|e| AppError::wrap("Failed to get URL edits", e))
+    }).await
+}
+
+// Get everything about one URL for its detail page: a page of its visits,
+// metadata, tags, collection notes, related pages and graph neighbors
+#[command]
+async fn get_url_details(
+    id: String,
+    page: Option<usize>,
+    page_size: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<UrlDetailsResult, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let details = db::details::get_url_details(
+            db_conn,
+            &id,
+            page.unwrap_or(0),
+            page_size.unwrap_or(db::details::DEFAULT_VISIT_PAGE_SIZE),
+        ).map_err(|e| AppError::wrap("Failed to get URL details", e))?;
+        
+        let related = graph::get_related(db_conn, &id, graph::DEFAULT_RELATED_LIMIT)
+            .map_err(|e| AppError::wrap("Failed to get related pages", e))?;
+        let neighbors = graph::get_neighbors(db_conn, graph::NodeType::Url, &id, graph::DEFAULT_NEIGHBOR_LIMIT)
+            .map_err(|e| AppError::wrap("Failed to get graph neighbors", e))?;
+        
+        Ok(UrlDetailsResult {
+            details,
+            related: related.into_iter().map(RelatedPageResult::from).collect(),
+            neighbors,
+        })
+    }).await
+}
+
+// Find URL records that are probably the same page
+#[command]
+async fn find_duplicate_urls(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::merge::DuplicateGroup>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::merge::find_probable_duplicates(db_conn, limit.unwrap_or(db::merge::DEFAULT_DUPLICATE_GROUPS))
+            .map_err(|e| AppError::wrap("Failed to find duplicate URLs", e))
+    }).await
+}
+
+// Find URLs on any domain whose titles are nearly the same, with proposed merges
+#[command]
+async fn find_title_clusters(
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::merge::TitleCluster>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::merge::find_title_clusters(db_conn, limit.unwrap_or(db::merge::DEFAULT_TITLE_CLUSTERS))
+            .map_err(|e| AppError::wrap("Failed to cluster URL titles", e))
+    }).await
+}
+
+// Merge duplicate URL records into a primary one
+#[command]
+async fn merge_urls(
+    primary_id: String,
+    duplicate_ids: Vec<String>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<usize, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::merge::merge_urls(db_conn, &primary_id, &duplicate_ids)
+            .map_err(|e| AppError::wrap("Failed to merge URLs", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Merged);
+    
+    Ok(result)
+}
+
+// Merge another instance's database into this one
+#[command]
+async fn merge_database(
+    other_path: String,
+    passphrase: Option<String>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<db::consolidate::MergeDatabaseResult, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::consolidate::merge_database(db_conn, Path::new(&other_path), passphrase.as_deref())
+            .map_err(|e| AppError::wrap("Failed to merge database", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Imported);
+    
+    Ok(result)
+}
+
+// Write the changes since a date (or the whole history) to an encrypted sync bundle
+#[command]
+async fn create_sync_bundle(
+    since: Option<String>,
+    path: String,
+    passphrase: String,
+    app_state: State<'_, AppState>,
+) -> Result<sync::BundleSummary, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        sync::create_sync_bundle(db_conn, parse_date(since), Path::new(&path), &passphrase)
+            .map_err(|e| AppError::wrap("Failed to create sync bundle", e))
+    }).await
+}
+
+// Merge a sync bundle from another machine into this database
+#[command]
+async fn apply_sync_bundle(
+    path: String,
+    passphrase: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<sync::ApplyBundleResult, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        sync::apply_sync_bundle(db_conn, Path::new(&path), &passphrase)
+            .map_err(|e| AppError::wrap("Failed to apply sync bundle", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Imported);
+    
+    Ok(result)
+}
+
+// Paired instances, instances seen on the network and any pairing request waiting
+#[command]
+async fn get_lan_sync_status(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<sync::LanSyncStatus, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        let app_state = app_handle.state::<AppState>();
+        let service = app_state.lan_sync.lock()
+            .map_err(|_| AppError::internal("Failed to acquire LAN sync lock"))?;
+        sync::lan_sync_status(service.as_ref(), db_conn)
+            .map_err(|e| AppError::wrap("Failed to get LAN sync status", e))
+    }).await
+}
+
+// Let another instance on the network ask to pair for the next two minutes
+#[command]
+async fn allow_lan_pairing(app_handle: tauri::AppHandle, app_state: State<'_, AppState>) -> Result<(), AppError> {
+    app_state.app_lock.check()?;
+    with_lan_sync(&app_handle, |service| {
+        service.allow_pairing();
+        Ok(())
+    })
+}
+
+// Ask an instance on the network to pair, returning the code both screens should show
+#[command]
+async fn request_lan_pairing(
+    peer_id: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<sync::PairingCode, AppError> {
+    app_state.app_lock.check()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        with_lan_sync(&app_handle, |service| {
+            service.request_pairing(&peer_id)
+                .map_err(|e| AppError::wrap("Failed to request pairing", e))
+        })
+    }).await.map_err(|e| AppError::wrap("Pairing task failed", e))?
+}
+
+// Accept or refuse the pairing request shown on this instance
+#[command]
+async fn answer_lan_pairing(
+    accept: bool,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    app_state.app_lock.check()?;
+    with_lan_sync(&app_handle, |service| {
+        service.answer_pairing(accept)
+            .map_err(|e| AppError::wrap("Failed to answer pairing", e))
+    })
+}
+
+// Complete a pairing after the codes matched and the other instance accepted
+#[command]
+async fn confirm_lan_pairing(
+    peer_id: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    app_state.app_lock.check()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        with_lan_sync(&app_handle, |service| {
+            service.confirm_pairing(&peer_id)
+                .map_err(|e| AppError::wrap("Failed to confirm pairing", e))
+        })
+    }).await.map_err(|e| AppError::wrap("Pairing task failed", e))?
+}
+
+// Forget a paired instance; it can no longer pull changes from this one
+#[command]
+async fn unpair_lan_peer(peer_id: String, app_state: State<'_, AppState>) -> Result<bool, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        sync::peers::remove_peer(db_conn, &peer_id)
+            .map_err(|e| AppError::wrap("Failed to unpair instance", e))
+    }).await
+}
+
+// Pull changes from every paired instance on the network without waiting for the interval
+#[command]
+async fn sync_lan_peers_now(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<sync::SyncOutcome>, AppError> {
+    app_state.app_lock.check()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        with_lan_sync(&app_handle, |service| {
+            service.sync_now()
+                .map_err(|e| AppError::wrap("Failed to sync", e))
+        })
+    }).await.map_err(|e| AppError::wrap("Sync task failed", e))?
+}
+
+// List the operations that can be undone, newest first
+#[command]
+async fn get_operations(app_state: State<'_, AppState>) -> Result<Vec<db::journal::Operation>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::journal::list_operations(db_conn)
+            .map_err(|e| AppError::wrap("Failed to get operations", e))
+    }).await
+}
+
+// Undo the most recent delete, merge or bulk tag operation
+#[command]
+async fn undo_last_operation(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<Option<db::journal::Operation>, AppError> {
+    let result = run_blocking(&app_state, move |db_conn| {
+        db::journal::undo_last_operation(db_conn)
+            .map_err(|e| AppError::wrap("Failed to undo operation", e))
+    }).await?;
+    
+    events::emit_history_updated(&app_handle, events::HistoryChange::Undone);
+    
+    Ok(result)
+}
+
+// Suggest pages whose URL or title contains the typed text, for quick lookups
+#[command]
+async fn suggest(
+    text: String,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::operations::Suggestion>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::operations::suggest(db_conn, &text, limit.unwrap_or(8).min(50))
+            .map_err(|e| AppError::wrap("Failed to get suggestions", e))
+    }).await
+}
+
+// Show or hide the search window, as the global shortcut does
+#[command]
+async fn toggle_spotlight(app_handle: tauri::AppHandle) -> Result<(), AppError> {
+    spotlight::toggle(&app_handle)
+}
+
+// Search history
+#[command]
+async fn search_history(
+    query: Option<String>,
+    domain: Option<String>,
+    category: Option<String>,
+    tag: Option<String>,
+    keyword: Option<String>,
+    walled: Option<bool>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    request_id: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<dto::SearchResultDto>, AppError> {
+    run_interruptible(&app_state, request_id, SEARCH_QUERY_TIMEOUT, move |db_conn| {
+        // Parse date strings to DateTime if provided
+        let start = parse_date(start_date);
+        let end = parse_date(end_date);
+        
+        // Set up search parameters
+        let search_params = db::operations::SearchParams {
+            query,
+            domain,
+            category,
+            tag,
+            keyword,
+            walled,
+            start_date: start,
+            end_date: end,
+            limit,
+            offset,
+        };
+        
+        // Perform search
+        let search_results = db::operations::search_history(db_conn, &search_params)
+            .map_err(|e| AppError::wrap("Search error", e))?;
+        
+        Ok(search_results.urls.into_iter().map(dto::SearchResultDto::from).collect())
+    }).await
+}
+
+// List the saved searches
+#[command]
+async fn list_saved_searches(
+    app_state: State<'_, AppState>,
+) -> Result<Vec<db::searches::SavedSearch>, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::searches::list_saved_searches(db_conn)
+            .map_err(|e| AppError::wrap("Failed to list saved searches", e))
+    }).await
+}
+
+// Create or update a saved search
+#[command]
+async fn save_search(
+    search: db::searches::SavedSearchInput,
+    app_state: State<'_, AppState>,
+) -> Result<db::searches::SavedSearch, AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::searches::save_search(db_conn, &search)
+            .map_err(|e| AppError::wrap("Failed to save search", e))
+    }).await
+}
+
+// Delete a saved search
+#[command]
+async fn delete_saved_search(
+    id: i64,
+    app_state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    run_blocking(&app_state, move |db_conn| {
+        db::searches::delete_saved_search(db_conn, id)
+            .map_err(|e| AppError::wrap("Failed to delete saved search", e))
+    }).await
+}
+
+// Run a saved search by name, returning results like search_history
+#[command]
+async fn run_saved_search(
+    name: String,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    request_id: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<dto::SearchResultDto>, AppError> {
+    run_interruptible(&app_state, request_id, SEARCH_QUERY_TIMEOUT, move |db_conn| {
+        let search = db::searches::find_saved_search(db_conn, &name)
+            .map_err(|e| AppError::wrap("Failed to find saved search", e))?;
+        let mut search_params = search.to_params(limit);
+        search_params.offset = offset;
+        
+        let search_results = db::operations::search_history(db_conn, &search_params)
+            .map_err(|e| AppError::wrap("Search error", e))?;
+        
+        Ok(search_results.urls.into_iter().map(dto::SearchResultDto::from).collect())
+    }).await
+}
+
+// Stop a search or timeline query started with this request id; returns false
+// if it already finished
+#[command]
+async fn cancel_query(
+    request_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<bool, AppError> {
+    Ok(app_state.running_queries.cancel(&request_id))
+}
+
+// Get timeline data for visualization
+#[command]
+async fn get_timeline_data(
+    start_date: Option<String>,
+    end_date: Option<String>,
+    domain: Option<String>,
+    group_by: String,
+    request_id: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Vec<dto::TimelineBucketDto>, AppError> {
+    run_interruptible(&app_state, request_id, TIMELINE_QUERY_TIMEOUT, move |db_conn| {
+        // Parse date strings to DateTime if provided
+        let start = parse_date(start_date);
+        let end = parse_date(end_date);
+        
+        // Timeline parameters for the query
+        let timeline_params = db::operations::TimelineParams {
+            start_date: start,
+            end_date: end,
+            domain: domain,
+            group_by: parse_timeline_grouping(&group_by),
+        };
+        
+        // Call database operation to get timeline data
+        let timeline_data = db::operations::get_timeline_data(db_conn, &timeline_params)
+            .map_err(|e| AppError::wrap("Timeline data error", e))?;
+        
+        Ok(timeline_data.iter().map(dto::TimelineBucketDto::from).collect())
+    }).await
 }
 
-// Processing results returned to the frontend
-#[derive(Serialize)]
-struct ProcessingResults {
-    files_processed: usize,
-    urls_processed: usize,
-    visits_processed: usize,
-    processing_time_sec: f64,
-    errors: Vec<String>,
+// Get the full, paginated URL list for a clicked timeline bucket
+#[command]
+async fn get_timeline_bucket_urls(
+    group_by: String,
+    bucket: String,
+    page: Option<usize>,
+    page_size: Option<usize>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    domain: Option<String>,
+    request_id: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<dto::BucketUrlPageDto, AppError> {
+    run_interruptible(&app_state, request_id, TIMELINE_QUERY_TIMEOUT, move |db_conn| {
+        // Identify the bucket from the grouping it was produced by
+        let timeline_bucket = match parse_timeline_grouping(&group_by) {
+            db::operations::TimelineGrouping::Hour => {
+                let hour: u8 = bucket.parse()
+                    .map_err(|_| AppError::invalid_input(format!("Invalid hour bucket: {}", bucket)))?;
+                db::operations::TimelineBucket::Hour(hour)
+            },
+            db::operations::TimelineGrouping::Day => {
+                // Accept either the RFC 3339 timestamp sent with timeline items or a plain date
+                let day = match DateTime::parse_from_rfc3339(&bucket) {
+                    Ok(dt) => dt.with_timezone(&Utc).format("%Y-%m-%d").to_string(),
+                    Err(_) => chrono::NaiveDate::parse_from_str(&bucket, "%Y-%m-%d")
+                        .map_err(|_| AppError::invalid_input(format!("Invalid day bucket: {}", bucket)))?
+                        .format("%Y-%m-%d")
+                        .to_string(),
+                };
+                db::operations::TimelineBucket::Day(day)
+            },
+            db::operations::TimelineGrouping::Domain => db::operations::TimelineBucket::Domain(bucket),
+        };
+        
+        // Reuse the same filters as the timeline view
+        let timeline_params = db::operations::TimelineParams {
+            start_date: parse_date(start_date),
+            end_date: parse_date(end_date),
+            domain,
+            group_by: parse_timeline_grouping(&group_by),
+        };
+        
+        let bucket_page = db::operations::get_timeline_bucket_urls(
+            db_conn,
+            &timeline_params,
+            &timeline_bucket,
+            page.unwrap_or(0),
+            page_size.unwrap_or(db::operations::DEFAULT_BUCKET_PAGE_SIZE),
+        ).map_err(|e| AppError::wrap("Timeline bucket error", e))?;
+        
+        Ok(dto::BucketUrlPageDto::from(&bucket_page))
+    }).await
 }
 
-// Simplified stats result for frontend
-#[derive(Serialize)]
-struct HistoryStats {
-    url_count: usize,
-    visit_count: usize,
-    domain_count: usize,
-    enriched_count: usize,
-    first_visit: Option<String>,
-    last_visit: Option<String>,
-    top_domains: Vec<(String, usize)>,
+// Helper function to tell every window the settings changed, sending the stored values
+fn emit_settings_changed(app_handle: &tauri::AppHandle, db_conn: &db::DatabaseConnection) {
+    if let Ok(settings) = settings::get_settings(db_conn) {
+        let _ = app_handle.emit_all("settings-changed", settings);
+    }
 }
 
-// Initialize the database
-#[command]
-async fn initialize_database(app_state: State<'_, AppState>) -> Result<(), String> {
-    // Get application data directory
+// Helper function to get (and create) the application data directory
+fn get_app_data_dir() -> Result<PathBuf, AppError> {
     let app_data_dir = tauri::api::path::app_data_dir(&tauri::Config::default())
-        .ok_or_else(|| "Failed to get app data directory".to_string())?;
+        .ok_or_else(|| AppError::internal("Failed to get app data directory"))?;
     
     // Create directories if they don't exist
     std::fs::create_dir_all(&app_data_dir)
-        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+        .map_err(|e| AppError::wrap("Failed to create app data directory", e))?;
     
-    // Set database path
-    let db_path = app_data_dir.join("history.db");
+    Ok(app_data_dir)
+}
+
+// Helper function to run database work on the blocking thread pool, so slow
+// queries never stall the async runtime or other commands
+async fn run_blocking<T, F>(app_state: &State<'_, AppState>, f: F) -> Result<T, AppError>
+where
+    F: FnOnce(&db::DatabaseConnection) -> Result<T, AppError> + Send + 'static,
+    T: Send + 'static,
+{
+    app_state.app_lock.check()?;
     
-    // Initialize database
-    let mut state_guard = app_state.db_connection.lock()
-        .map_err(|_| "Failed to acquire database lock".to_string())?;
+    // Get database connection
+    let db_conn = app_state.db_connection.read()
+        .map_err(|_| AppError::internal("Failed to acquire database lock"))?
+        .as_ref()
+        .ok_or_else(AppError::not_initialized)?
+        .clone();
     
-    // Create and initialize the database connection
-    let connection = db::initialize_database(&db_path)
-        .map_err(|e| format!("Failed to initialize database: {}", e))?;
+    tauri::async_runtime::spawn_blocking(move || f(&db_conn))
+        .await
+        .map_err(|e| AppError::wrap("Database task failed", e))?
+}
+
+// Helper function like run_blocking whose queries are stopped after `timeout`,
+// or when the frontend cancels `request_id` with cancel_query
+async fn run_interruptible<T, F>(
+    app_state: &State<'_, AppState>,
+    request_id: Option<String>,
+    timeout: Duration,
+    f: F,
+) -> Result<T, AppError>
+where
+    F: FnOnce(&db::DatabaseConnection) -> Result<T, AppError> + Send + 'static,
+    T: Send + 'static,
+{
+    // Held until the query finishes, so cancel_query can find it meanwhile
+    let ticket = request_id.as_deref().map(|id| app_state.running_queries.register(id));
+    let cancelled = ticket.as_ref()
+        .map_or_else(|| Arc::new(AtomicBool::new(false)), |ticket| Arc::clone(&ticket.cancelled));
     
-    *state_guard = Some(connection);
+    run_blocking(app_state, move |db_conn| {
+        db::interrupt::with_limits(timeout, cancelled, || f(db_conn))
+    }).await
+}
+
+// Helper function to run work that needs the app state itself (e.g. to replace
+// the connection) on the blocking thread pool
+async fn run_blocking_with_state<T, F>(app_handle: tauri::AppHandle, f: F) -> Result<T, AppError>
+where
+    F: FnOnce(&AppState) -> Result<T, AppError> + Send + 'static,
+    T: Send + 'static,
+{
+    app_handle.state::<AppState>().app_lock.check()?;
     
-    Ok(())
+    tauri::async_runtime::spawn_blocking(move || f(&app_handle.state::<AppState>()))
+        .await
+        .map_err(|e| AppError::wrap("Database task failed", e))?
 }
 
-// Process uploaded history files
-#[command]
-async fn process_history_files(
-    file_paths: Vec<String>,
-    device_names: Option<Vec<String>>,
-    app_state: State<'_, AppState>,
-) -> Result<ProcessingResults, String> {
+// Helper function to notify that a report was written, or why it wasn't
+fn notify_report(app_handle: &tauri::AppHandle, result: &Result<String, AppError>) {
+    match result {
+        Ok(path) => notifications::show(app_handle, "Report ready", &format!("Saved to {}", path)),
+        Err(e) => notifications::show(app_handle, "Report failed", &e.message),
+    }
+}
+
+// Helper function to get the database file path
+fn get_db_path() -> Result<PathBuf, AppError> {
+    Ok(get_app_data_dir()?.join("history.db"))
+}
+
+// Helper function to parse an optional RFC 3339 date string from the frontend
+fn parse_date(value: Option<String>) -> Option<DateTime<Utc>> {
+    value.and_then(|s| DateTime::parse_from_rfc3339(&s).ok().map(|dt| dt.with_timezone(&Utc)))
+}
+
+// Helper function to import Safari history files (only visits at or after
+// `since`, when given), reporting per-file progress to the job and stopping
+// between files once the job is cancelled
+fn import_history_files(
+    db_conn: &db::DatabaseConnection,
+    file_paths: &[String],
+    device_names: Option<&[String]>,
+    since: Option<DateTime<Utc>>,
+    job: &jobs::JobContext,
+) -> Result<ProcessingResults, AppError> {
     // Track processing time
     let start_time = Instant::now();
     
@@ -83,9 +3640,10 @@ async fn process_history_files(
         .collect();
     
     // Process files with the extractor
-    let (successful, failed) = extractor::safari::parse_history_db(
+    let (mut successful, failed) = extractor::safari::parse_history_db_since(
         &paths,
-        device_names.as_ref().map(|names| names.as_slice()),
+        device_names,
+        since,
     );
     
     // Collect any errors from failed files
@@ -93,25 +3651,123 @@ async fn process_history_files(
         .map(|f| f.description())
         .collect();
     
-    // Ensure we have a database connection
-    let state_guard = app_state.db_connection.lock()
-        .map_err(|_| "Failed to acquire database lock".to_string())?;
+    // A copy of a file imported before (e.g. a backup of History.db) is stored
+    // under the original's path, so its visits collide with the original's
+    // instead of doubling them; visits the copy added earlier are merged too
+    let mut source_warnings: Vec<String> = Vec::new();
+    for history_data in &mut successful {
+        let path = history_data.source.file_path.to_string_lossy().to_string();
+        let fingerprint = match extractor::fingerprint_history_db(&history_data.source.file_path) {
+            Ok(fingerprint) => fingerprint,
+            Err(e) => {
+                source_warnings.push(format!("{}: failed to fingerprint the file, copies won't be recognized: {}", path, e));
+                continue;
+            },
+        };
+        let resolution = db::sources::resolve_source(db_conn, &path, &fingerprint)
+            .map_err(|e| AppError::wrap("Failed to record import source", e))?;
+        if resolution.canonical_path != path {
+            for visit in &mut history_data.visits {
+                visit.source_file = resolution.canonical_path.clone();
+            }
+        }
+        if let Some(original) = resolution.copy_of {
+            let merged = db::sources::merge_copy_visits(db_conn, &path, &original)
+                .map_err(|e| AppError::wrap("Failed to merge visits of a copied source", e))?;
+            source_warnings.push(format!(
+                "{}: same history as {}, its visits were merged ({} duplicates removed)", path, original, merged,
+            ));
+        }
+    }
+    
+    // Drop excluded URLs and strip redacted ones before anything is written
+    let privacy_filter = privacy::load_filter(db_conn)
+        .map_err(|e| AppError::wrap("Failed to load privacy rules", e))?;
+    let mut excluded = privacy::ExcludedCounts::default();
+    for history_data in &mut successful {
+        let counts = privacy_filter.apply(history_data);
+        excluded.urls += counts.urls;
+        excluded.visits += counts.visits;
+        excluded.redacted += counts.redacted;
+    }
+    
+    // Let the pre-insert scripts rewrite or skip pages
+    let pre_insert = scripting::HookScripts::load(db_conn, scripting::Hook::PreInsert)
+        .map_err(|e| AppError::wrap("Failed to load scripts", e))?;
+    errors.extend(pre_insert.compile_errors().iter().cloned());
+    for history_data in &mut successful {
+        let run = pre_insert.apply(history_data);
+        excluded.urls += run.skipped;
+        excluded.visits += run.visits_skipped;
+        errors.extend(run.errors);
+    }
+    
+    // Describe every file for the import audit log
+    let mut files: Vec<db::imports::ImportFile> = successful.iter()
+        .map(|history_data| db::imports::ImportFile {
+            path: history_data.source.file_path.display().to_string(),
+            device_name: history_data.source.device_name.clone(),
+            urls: history_data.urls.len(),
+            visits: history_data.visits.len(),
+            error: None,
+        })
+        .collect();
+    files.extend(failed.iter().map(|f| db::imports::ImportFile {
+        path: f.path.display().to_string(),
+        device_name: paths.iter().position(|path| *path == f.path)
+            .and_then(|i| device_names.and_then(|names| names.get(i).cloned())),
+        urls: 0,
+        visits: 0,
+        error: Some(f.error.to_string()),
+    }));
     
-    let db_conn = state_guard.as_ref()
-        .ok_or_else(|| "Database not initialized".to_string())?;
+    let import_run_id = db::imports::start_import_run(db_conn, &files)
+        .map_err(|e| AppError::wrap("Failed to record import run", e))?;
+    let import_settings = db::imports::get_import_settings(db_conn)
+        .map_err(|e| AppError::wrap("Failed to load import settings", e))?;
     
     // Initialize variables for tracking stats
     let mut total_urls = 0;
     let mut total_visits = 0;
+    let mut urls_inserted = 0;
+    let mut visits_inserted = 0;
+    let mut warnings: Vec<String> = source_warnings;
     
     // Insert all successfully processed files into the database
-    for history_data in &successful {
+    for (i, history_data) in successful.iter().enumerate() {
+        if job.is_cancelled() {
+            errors.push("Import cancelled".to_string());
+            break;
+        }
+        job.progress(i as f64 / successful.len() as f64,
+            &format!("Importing {}", history_data.source.file_path.display()));
+        
         total_urls += history_data.urls.len();
         total_visits += history_data.visits.len();
         
+        warnings.extend(history_data.warnings.iter()
+            .map(|w| format!("{}: {}", history_data.source.file_path.display(), w)));
+        
+        // Pick up where an interrupted import of the same file stopped
+        let path = history_data.source.file_path.display().to_string();
+        let resume = db::imports::interrupted_progress(db_conn, &path, import_run_id)
+            .map_err(|e| AppError::wrap("Failed to read import progress", e))?;
+        if let Some(resume) = &resume {
+            warnings.push(format!("{}: resuming an interrupted import after {} of {} visits",
+                path, resume.visits_done, resume.visits_total));
+        }
+        
         // Insert the data
-        let insert_result = db::operations::insert_history_data(db_conn, history_data)
-            .map_err(|e| format!("Database error: {}", e))?;
+        let insert_result = db::operations::insert_history_data(
+            db_conn,
+            history_data,
+            Some(import_run_id),
+            import_settings.batch_size,
+            resume.as_ref(),
+        ).map_err(|e| AppError::wrap("Database error", e))?;
+        
+        urls_inserted += insert_result.urls_inserted;
+        visits_inserted += insert_result.visits_inserted;
         
         // Add any insertion errors to the list
         if insert_result.has_errors() {
@@ -119,250 +3775,872 @@ async fn process_history_files(
         }
     }
     
+    // Merge short links we resolved before into their destinations
+    if let Err(e) = web::apply_known_redirects(db_conn) {
+        errors.push(format!("Failed to apply known redirects: {}", e));
+    }
+    
+    // Categorize newly imported URLs with the domain rules
+    if let Err(e) = enrichment::categorize_urls(db_conn, None, 0, false) {
+        errors.push(format!("Failed to categorize URLs: {}", e));
+    }
+    
+    // Tag the imported pages with the post-import scripts
+    match scripting::run_post_import(db_conn, import_run_id) {
+        Ok(run) => errors.extend(run.errors),
+        Err(e) => errors.push(format!("Failed to run post-import scripts: {}", e)),
+    }
+    
+    // Drop visits older than the retention policy allows
+    if let Err(e) = db::maintenance::apply_retention(db_conn) {
+        errors.push(format!("Failed to apply retention: {}", e));
+    }
+    
     // Calculate processing time
     let processing_time = start_time.elapsed().as_secs_f64();
     
+    // Record the outcome in the import audit log
+    warnings.extend(errors.iter().cloned());
+    db::imports::finish_import_run(db_conn, import_run_id, urls_inserted, visits_inserted, &warnings, processing_time)
+        .map_err(|e| AppError::wrap("Failed to record import run", e))?;
+    
     // Return results to the frontend
     Ok(ProcessingResults {
+        import_run_id,
         files_processed: successful.len(),
         urls_processed: total_urls,
         visits_processed: total_visits,
+        urls_inserted,
+        visits_inserted,
+        urls_excluded: excluded.urls,
+        visits_excluded: excluded.visits,
+        urls_redacted: excluded.redacted,
         processing_time_sec: processing_time,
         errors,
     })
 }
 
-// Get history statistics
-#[command]
-async fn get_history_stats(app_state: State<'_, AppState>) -> Result<HistoryStats, String> {
-    // Get database connection
-    let state_guard = app_state.db_connection.lock()
-        .map_err(|_| "Failed to acquire database lock".to_string())?;
-    
-    let db_conn = state_guard.as_ref()
-        .ok_or_else(|| "Database not initialized".to_string())?;
-    
-    // Get stats from database
-    let stats = db::operations::get_stats(db_conn)
-        .map_err(|e| format!("Failed to get stats: {}", e))?;
-    
-    // Convert timestamps to ISO strings for frontend
-    let first_visit = stats.first_visit.map(|dt| dt.to_rfc3339());
-    let last_visit = stats.last_visit.map(|dt| dt.to_rfc3339());
-    
-    // Return formatted stats
-    Ok(HistoryStats {
-        url_count: stats.url_count,
-        visit_count: stats.visit_count,
-        domain_count: stats.domain_count,
-        enriched_count: stats.enriched_count,
-        first_visit,
-        last_visit,
-        top_domains: stats.top_domains,
+// Helper function to enrich pages with the configured provider
+fn run_enrichment(
+    db_conn: &db::DatabaseConnection,
+    pages: &[enrichment::PageInput],
+) -> Result<EnrichmentRunResult, AppError> {
+    let settings = enrichment::get_enrichment_settings(db_conn)
+        .map_err(|e| AppError::wrap("Failed to get enrichment settings", e))?;
+    
+    let provider = enrichment::create_provider(&settings)
+        .map_err(|e| AppError::wrap("Failed to create enrichment provider", e))?;
+    
+    let run = enrichment::enrich_pages(db_conn, provider.as_ref(), pages)
+        .map_err(|e| AppError::wrap("Failed to enrich URLs", e))?;
+    
+    Ok(EnrichmentRunResult {
+        enriched: run.enriched,
+        failed: run.failed,
+        errors: run.errors,
     })
 }
 
-// Search history
-#[command]
-async fn search_history(
-    query: Option<String>,
-    domain: Option<String>,
-    start_date: Option<String>,
-    end_date: Option<String>,
-    limit: Option<usize>,
-    offset: Option<usize>,
-    app_state: State<'_, AppState>,
-) -> Result<Vec<HashMap<String, serde_json::Value>>, String> {
-    // Get database connection
-    let state_guard = app_state.db_connection.lock()
-        .map_err(|_| "Failed to acquire database lock".to_string())?;
-    
-    let db_conn = state_guard.as_ref()
-        .ok_or_else(|| "Database not initialized".to_string())?;
-    
-    // Parse date strings to DateTime if provided
-    let start = start_date.and_then(|s| DateTime::parse_from_rfc3339(&s).ok().map(|dt| dt.with_timezone(&Utc)));
-    let end = end_date.and_then(|s| DateTime::parse_from_rfc3339(&s).ok().map(|dt| dt.with_timezone(&Utc)));
-    
-    // Set up search parameters
-    let search_params = db::operations::SearchParams {
-        query,
-        domain,
-        start_date: start,
-        end_date: end,
-        limit,
-        offset,
-    };
+// Helper function to run work right away as a job, so it shows up in the job list
+fn run_as_job<T, F>(
+    app_handle: &tauri::AppHandle,
+    db_conn: &db::DatabaseConnection,
+    request: &jobs::JobRequest,
+    work: F,
+) -> Result<T, AppError>
+where
+    T: Serialize,
+    F: FnOnce(&jobs::JobContext) -> Result<T, AppError>,
+{
+    let job = jobs::start_job(db_conn, request)
+        .map_err(|e| AppError::wrap("Failed to record job", e))?;
+    
+    jobs::run_job(db_conn, &job, &|job| events::emit_job_progress(app_handle, job), work)
+}
+
+// Helper function to start the background job worker once and wake it for new jobs
+fn start_job_worker(app_handle: &tauri::AppHandle) {
+    let app_state = app_handle.state::<AppState>();
+    
+    if app_state.jobs.start() {
+        let app_handle = app_handle.clone();
+        std::thread::spawn(move || {
+            let app_state = app_handle.state::<AppState>();
+            jobs::run_worker(
+                &app_state.db_connection,
+                &app_state.jobs,
+                &|job| {
+                    events::emit_job_progress(&app_handle, job);
+                    notifications::notify_job(&app_handle, job);
+                },
+                |db_conn, job, request| execute_job(&app_handle, db_conn, job, request),
+            )
+        });
+    }
+    
+    app_state.jobs.notify();
+}
+
+// Helper function to queue an incremental import of the auto-import history
+// file, whether or not automatic imports are on, and wake the worker
+fn queue_local_import(
+    app_handle: &tauri::AppHandle,
+    db_conn: &db::DatabaseConnection,
+    device_name: Option<String>,
+) -> Result<jobs::Job, AppError> {
+    let settings = jobs::schedule::get_auto_import_settings(db_conn)
+        .map_err(|e| AppError::wrap("Failed to get automatic import settings", e))?;
+    let path = settings.history_file()
+        .ok_or_else(|| AppError::new(ErrorKind::Config, "No Safari history file to import"))?;
+    
+    let job = jobs::enqueue_job(db_conn, &jobs::JobRequest::IncrementalImport {
+        file_path: path.display().to_string(),
+        device_name: device_name.or(settings.device_name),
+    }).map_err(|e| AppError::wrap("Failed to queue job", e))?;
+    
+    events::emit_job_progress(app_handle, &job);
+    start_job_worker(app_handle);
+    
+    Ok(job)
+}
+
+// Helper function to pause or resume capture, emitting "capture-paused"
+fn pause_capture(app_handle: &tauri::AppHandle, db_conn: &db::DatabaseConnection, paused: bool) -> Result<(), AppError> {
+    app_handle.state::<AppState>().capture_paused.store(paused, Ordering::SeqCst);
+    update_capture_server(app_handle, db_conn)?;
     
-    // Perform search
-    let search_results = db::operations::search_history(db_conn, &search_params)
-        .map_err(|e| format!("Search error: {}", e))?;
+    tray::set_capture_paused(app_handle, paused);
+    let _ = app_handle.emit_all("capture-paused", paused);
+    Ok(())
+}
+
+// Helper function to run a tray menu action; failures are emitted as "tray-error"
+fn handle_tray_event(app_handle: &tauri::AppHandle, event: tauri::SystemTrayEvent) {
+    let id = match event {
+        tauri::SystemTrayEvent::MenuItemClick { id, .. } => id,
+        _ => return,
+    };
     
-    // Convert results to a format that can be serialized to JSON
-    let mut results = Vec::new();
+    if id == tray::OPEN_SEARCH {
+        return deeplink::open(app_handle, deeplink::DeepLink::Search { query: String::new(), domain: None });
+    }
     
-    for result in search_results.urls {
-        let mut item = HashMap::new();
+    let app_handle = app_handle.clone();
+    std::thread::spawn(move || {
+        let app_state = app_handle.state::<AppState>();
+        let result = app_state.app_lock.check().and_then(|_| {
+            app_state.db_connection.read()
+                .map_err(|_| AppError::internal("Failed to acquire database lock"))?
+                .as_ref()
+                .cloned()
+                .ok_or_else(AppError::not_initialized)
+        }).and_then(|db_conn| match id.as_str() {
+            tray::IMPORT_NOW => queue_local_import(&app_handle, &db_conn, None).map(|_| ()),
+            tray::PAUSE_CAPTURE => {
+                let paused = !app_state.capture_paused.load(Ordering::SeqCst);
+                pause_capture(&app_handle, &db_conn, paused)
+            },
+            _ => Ok(()),
+        });
         
-        // Add URL properties
-        item.insert("id".to_string(), serde_json::Value::String(result.url.id.to_string()));
-        item.insert("url".to_string(), serde_json::Value::String(result.url.url));
-        if let Some(title) = result.url.title {
-            item.insert("title".to_string(), serde_json::Value::String(title));
+        if let Err(e) = result {
+            let _ = app_handle.emit_all("tray-error", e.message);
         }
-        item.insert("domain".to_string(), serde_json::Value::String(result.url.domain));
-        item.insert("first_seen".to_string(), serde_json::Value::String(result.url.first_seen.to_rfc3339()));
-        item.insert("last_seen".to_string(), serde_json::Value::String(result.url.last_seen.to_rfc3339()));
-        item.insert("visit_count".to_string(), serde_json::Value::Number(serde_json::Number::from(result.visit_count)));
+    });
+}
+
+// Helper function to run a `historykg://run/` link off the main thread,
+// answering through its x-callback URLs and an "automation" event
+fn run_automation_link(app_handle: &tauri::AppHandle, link: &str) {
+    let request = match automation::AutomationRequest::parse(link) {
+        Ok(request) => request,
+        Err(e) => {
+            let _ = app_handle.emit_all(automation::AUTOMATION_EVENT, serde_json::json!({ "error": e.message }));
+            return;
+        },
+    };
+    
+    let app_handle = app_handle.clone();
+    std::thread::spawn(move || {
+        let (callback, event) = match run_automation_action(&app_handle, &request.action) {
+            Ok(values) => {
+                let result: HashMap<&str, &str> = values.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+                let event = serde_json::json!({ "action": request.action, "result": result });
+                (request.success.as_ref().map(|url| automation::callback_url(url, &values)), event)
+            },
+            Err(e) => {
+                let values = [("errorMessage".to_string(), e.message.clone())];
+                let event = serde_json::json!({ "action": request.action, "error": e.message });
+                (request.error.as_ref().map(|url| automation::callback_url(url, &values)), event)
+            },
+        };
         
-        // Add metadata if available
-        if let Some(metadata) = result.metadata {
-            if let Some(summary) = metadata.summary {
-                item.insert("summary".to_string(), serde_json::Value::String(summary));
-            }
-            if let Some(keywords) = metadata.keywords {
-                item.insert("keywords".to_string(), serde_json::Value::String(keywords));
-            }
-            item.insert("is_enriched".to_string(), serde_json::Value::Bool(metadata.is_enriched));
+        let _ = app_handle.emit_all(automation::AUTOMATION_EVENT, event);
+        if let Some(url) = callback {
+            let _ = tauri::api::shell::open(&app_handle.shell_scope(), url, None);
         }
-        
-        // Add last visit date if available
-        if let Some(last_visit) = result.last_visit {
-            item.insert("last_visit".to_string(), serde_json::Value::String(last_visit.to_rfc3339()));
+    });
+}
+
+// Helper function to run an automation action if automation is on and the app
+// is unlocked, returning the values passed to the x-success callback
+fn run_automation_action(
+    app_handle: &tauri::AppHandle,
+    action: &automation::AutomationAction,
+) -> Result<Vec<(String, String)>, AppError> {
+    let app_state = app_handle.state::<AppState>();
+    app_state.app_lock.check()?;
+    
+    // A link that launched the app arrives before the frontend opens the database
+    let started = Instant::now();
+    let db_conn = loop {
+        let db_conn = app_state.db_connection.read()
+            .map_err(|_| AppError::internal("Failed to acquire database lock"))?
+            .as_ref()
+            .cloned();
+        match db_conn {
+            Some(db_conn) => break db_conn,
+            None if started.elapsed() < AUTOMATION_STARTUP_WAIT => std::thread::sleep(Duration::from_millis(250)),
+            None => return Err(AppError::not_initialized()),
         }
-        
-        results.push(item);
+    };
+    
+    let settings = automation::get_automation_settings(&db_conn)
+        .map_err(|e| AppError::wrap("Failed to get automation settings", e))?;
+    if !settings.enabled {
+        return Err(AppError::invalid_input("Automation is turned off in Settings"));
     }
     
-    Ok(results)
+    match action {
+        automation::AutomationAction::Search { name, limit } => {
+            let search = db::searches::find_saved_search(&db_conn, name)
+                .map_err(|e| AppError::wrap("Failed to find saved search", e))?;
+            let search_results = db::operations::search_history(&db_conn, &search.to_params(Some(*limit)))
+                .map_err(|e| AppError::wrap("Search error", e))?;
+            
+            let pages: Vec<serde_json::Value> = search_results.urls.iter()
+                .map(|result| serde_json::json!({ "url": result.url.url, "title": result.url.title }))
+                .collect();
+            Ok(vec![
+                ("count".to_string(), pages.len().to_string()),
+                ("total".to_string(), search_results.total_count.to_string()),
+                ("result".to_string(), serde_json::Value::Array(pages).to_string()),
+            ])
+        },
+        automation::AutomationAction::Import { path, device } => {
+            let job = match path {
+                Some(path) => {
+                    let request = jobs::JobRequest::Import {
+                        file_paths: vec![path.clone()],
+                        device_names: device.clone().map(|device| vec![device]),
+                    };
+                    let job = jobs::enqueue_job(&db_conn, &request)
+                        .map_err(|e| AppError::wrap("Failed to queue job", e))?;
+                    events::emit_job_progress(app_handle, &job);
+                    start_job_worker(app_handle);
+                    job
+                },
+                None => queue_local_import(app_handle, &db_conn, device.clone())?,
+            };
+            
+            Ok(vec![("job".to_string(), job.id.to_string())])
+        },
+        automation::AutomationAction::Report { period, format } => {
+            let report_period = report::ReportPeriod::ending_at(period, Utc::now())
+                .map_err(AppError::from)?;
+            let html = format.as_deref() == Some("html");
+            let report_format = if html { report::ReportFormat::Html } else { report::ReportFormat::Markdown };
+            
+            let path = automation::report_path(period, html)?;
+            let result = report::generate_report(&db_conn, report_period, report_format, &path)
+                .map(|_| path.display().to_string())
+                .map_err(|e| AppError::wrap("Failed to generate report", e));
+            notify_report(app_handle, &result);
+            
+            Ok(vec![("path".to_string(), result?)])
+        },
+    }
 }
 
-// Get timeline data for visualization
-#[command]
-async fn get_timeline_data(
-    start_date: Option<String>,
-    end_date: Option<String>,
-    domain: Option<String>,
-    group_by: String,
-    app_state: State<'_, AppState>,
-) -> Result<Vec<serde_json::Value>, String> {
-    // Get database connection
-    let state_guard = app_state.db_connection.lock()
-        .map_err(|_| "Failed to acquire database lock".to_string())?;
-    
-    let db_conn = state_guard.as_ref()
-        .ok_or_else(|| "Database not initialized".to_string())?;
-    
-    // Parse date strings to DateTime if provided
-    let start = start_date.and_then(|s| DateTime::parse_from_rfc3339(&s).ok().map(|dt| dt.with_timezone(&Utc)));
-    let end = end_date.and_then(|s| DateTime::parse_from_rfc3339(&s).ok().map(|dt| dt.with_timezone(&Utc)));
-    
-    // Timeline parameters for the query
-    let timeline_params = db::operations::TimelineParams {
-        start_date: start,
-        end_date: end,
-        domain: domain,
-        group_by: match group_by.as_str() {
-            "hour" => db::operations::TimelineGrouping::Hour,
-            "domain" => db::operations::TimelineGrouping::Domain,
-            _ => db::operations::TimelineGrouping::Day, // Default to day
-        },
+// Helper function to register the search window's global shortcut with the
+// current settings, replacing the previous one
+fn update_global_shortcut(app_handle: &tauri::AppHandle, db_conn: &db::DatabaseConnection) -> Result<(), AppError> {
+    let settings = spotlight::get_spotlight_settings(db_conn)
+        .map_err(|e| AppError::wrap("Failed to get shortcut settings", e))?;
+    
+    let app_state = app_handle.state::<AppState>();
+    let mut registered = app_state.global_shortcut.lock()
+        .map_err(|_| AppError::internal("Failed to acquire shortcut lock"))?;
+    let mut manager = app_handle.global_shortcut_manager();
+    if let Some(shortcut) = registered.take() {
+        let _ = manager.unregister(&shortcut);
+    }
+    
+    if !settings.enabled {
+        return Ok(());
+    }
+    
+    let handle = app_handle.clone();
+    manager.register(&settings.shortcut, move || {
+        if let Err(e) = spotlight::toggle(&handle) {
+            let _ = handle.emit_all("spotlight-error", e.message);
+        }
+    }).map_err(|e| AppError::invalid_input(format!("Failed to register shortcut {}: {}", settings.shortcut, e)))?;
+    
+    *registered = Some(settings.shortcut);
+    Ok(())
+}
+
+// Helper function to watch the auto-imported history file, replacing any
+// previous watcher, so changes are imported without waiting for the schedule
+fn update_history_watcher(app_handle: &tauri::AppHandle, db_conn: &db::DatabaseConnection) -> Result<(), AppError> {
+    let settings = jobs::schedule::get_auto_import_settings(db_conn)
+        .map_err(|e| AppError::wrap("Failed to get automatic import settings", e))?;
+    
+    let app_state = app_handle.state::<AppState>();
+    let mut watcher = app_state.history_watcher.lock()
+        .map_err(|_| AppError::internal("Failed to acquire history watcher lock"))?;
+    *watcher = None;
+    
+    let path = match settings.history_file() {
+        Some(path) if settings.enabled && settings.watch => path,
+        _ => return Ok(()),
+    };
+    
+    let handle = app_handle.clone();
+    let on_change = move || {
+        let app_state = handle.state::<AppState>();
+        let db_conn = app_state.db_connection.read().ok().and_then(|guard| guard.as_ref().cloned());
+        if let Some(db_conn) = db_conn {
+            if let Ok(Some(job)) = jobs::schedule::enqueue_import(&db_conn, &settings) {
+                events::emit_job_progress(&handle, &job);
+                app_state.jobs.notify();
+            }
+        }
     };
     
-    // Call database operation to get timeline data
-    let timeline_data = db::operations::get_timeline_data(db_conn, &timeline_params)
-        .map_err(|e| format!("Timeline data error: {}", e))?;
+    *watcher = Some(jobs::watch::watch_history_file(&path, on_change)
+        .map_err(|e| AppError::internal(format!("Failed to watch {}: {}", path.display(), e)))?);
+    
+    Ok(())
+}
+
+// Helper function to (re)start the listener receiving visits from the browser
+// extension with the current capture settings, or stop it when capture is off
+fn update_capture_server(app_handle: &tauri::AppHandle, db_conn: &db::DatabaseConnection) -> Result<(), AppError> {
+    let settings = capture::get_capture_settings(db_conn)
+        .map_err(|e| AppError::wrap("Failed to get capture settings", e))?;
+    
+    let app_state = app_handle.state::<AppState>();
+    let mut server = app_state.capture_server.lock()
+        .map_err(|_| AppError::internal("Failed to acquire capture server lock"))?;
+    // The old listener must release the port before a new one binds it
+    *server = None;
     
-    // Convert timeline data items to JSON values
-    let mut results = Vec::new();
+    if !settings.enabled || app_state.capture_paused.load(Ordering::SeqCst) {
+        return Ok(());
+    }
     
-    for item in timeline_data {
-        let mut data = serde_json::Map::new();
+    let handle = app_handle.clone();
+    let device_name = settings.device_name.clone();
+    let on_visits = move |visits: Vec<capture::CapturedVisit>| {
+        let app_state = handle.state::<AppState>();
+        let db_conn = app_state.db_connection.read().ok()
+            .and_then(|guard| guard.as_ref().cloned())
+            .ok_or_else(|| "Database is not open".to_string())?;
         
-        match &item {
-            db::operations::TimelineItem::Hourly { hour, count, timestamp, urls } => {
-                data.insert("type".to_string(), serde_json::Value::String("hour".to_string()));
-                data.insert("hour".to_string(), serde_json::Value::Number(serde_json::Number::from(*hour)));
-                data.insert("count".to_string(), serde_json::Value::Number(serde_json::Number::from(*count)));
-                data.insert("timestamp".to_string(), serde_json::Value::String(timestamp.to_rfc3339()));
-                
-                // Add URLs if available
-                if let Some(url_list) = urls {
-                    let urls_json = serialize_urls(url_list);
-                    data.insert("urls".to_string(), urls_json);
-                }
-            },
-            db::operations::TimelineItem::Daily { date, count, urls } => {
-                data.insert("type".to_string(), serde_json::Value::String("day".to_string()));
-                data.insert("date".to_string(), serde_json::Value::String(date.to_rfc3339()));
-                data.insert("count".to_string(), serde_json::Value::Number(serde_json::Number::from(*count)));
-                data.insert("timestamp".to_string(), serde_json::Value::String(date.to_rfc3339()));
-                
-                // Add URLs if available
-                if let Some(url_list) = urls {
-                    let urls_json = serialize_urls(url_list);
-                    data.insert("urls".to_string(), urls_json);
-                }
-            },
-            db::operations::TimelineItem::Domain { domain, count, urls } => {
-                data.insert("type".to_string(), serde_json::Value::String("domain".to_string()));
-                data.insert("domain".to_string(), serde_json::Value::String(domain.clone()));
-                data.insert("count".to_string(), serde_json::Value::Number(serde_json::Number::from(*count)));
-                
-                // Add URLs if available
-                if let Some(url_list) = urls {
-                    let urls_json = serialize_urls(url_list);
-                    data.insert("urls".to_string(), urls_json);
-                }
-            },
+        let summary = capture::record_visits(&db_conn, &visits, device_name.clone())
+            .map_err(|e| e.to_string())?;
+        if summary.visits_inserted > 0 {
+            let _ = handle.emit_all("history-imported", HistoryImportedEvent {
+                file_path: capture::CAPTURE_SOURCE.to_string(),
+                urls_inserted: summary.urls_inserted,
+                visits_inserted: summary.visits_inserted,
+            });
+            events::emit_history_updated(&handle, events::HistoryChange::Captured);
         }
-        
-        results.push(serde_json::Value::Object(data));
+        Ok(summary)
+    };
+    
+    *server = Some(capture::CaptureServer::start(settings.port, settings.token, on_visits)
+        .map_err(|e| AppError::wrap("Failed to start capture server", e))?);
+    
+    Ok(())
+}
+
+// Helper function to (re)start the MCP server with the current settings, or
+// stop it when it is off. Sessions end with the server, so consent is asked again.
+fn update_mcp_server(app_handle: &tauri::AppHandle, db_conn: &db::DatabaseConnection) -> Result<(), AppError> {
+    let settings = mcp::get_mcp_settings(db_conn)
+        .map_err(|e| AppError::wrap("Failed to get MCP settings", e))?;
+    
+    let app_state = app_handle.state::<AppState>();
+    let mut server = app_state.mcp_server.lock()
+        .map_err(|_| AppError::internal("Failed to acquire MCP server lock"))?;
+    *server = None;
+    app_state.mcp_sessions.clear();
+    
+    if !settings.enabled {
+        return Ok(());
     }
     
-    Ok(results)
+    // A locked app answers no tools, without counting as activity
+    let handle = app_handle.clone();
+    let database = move || {
+        let app_state = handle.state::<AppState>();
+        if app_state.app_lock.status().ok().is_none_or(|status| status.locked) {
+            return Err("The app is locked, ask the user to unlock it".to_string());
+        }
+        app_state.db_connection.read().ok()
+            .and_then(|guard| guard.as_ref().cloned())
+            .ok_or_else(|| "The history database is not open".to_string())
+    };
+    
+    let handle = app_handle.clone();
+    let on_session = move |session: &mcp::McpSession| {
+        let _ = handle.emit_all("mcp-session-requested", session.clone());
+    };
+    
+    *server = Some(mcp::McpServer::start(settings.port, app_state.mcp_sessions.clone(), database, on_session)
+        .map_err(|e| AppError::wrap("Failed to start MCP server", e))?);
+    
+    Ok(())
 }
 
-// Helper function to serialize URL objects to JSON
-fn serialize_urls(urls: &[db::models::UrlWithVisits]) -> serde_json::Value {
-    let mut url_array = Vec::new();
+// Helper function to (re)start LAN sync with the current settings, or stop it
+// when it is off. Pulled changes are announced like an import.
+fn update_lan_sync(app_handle: &tauri::AppHandle, db_conn: &db::DatabaseConnection) -> Result<(), AppError> {
+    let settings = sync::peers::get_lan_sync_settings(db_conn)
+        .map_err(|e| AppError::wrap("Failed to get LAN sync settings", e))?;
     
-    for url in urls {
-        let mut url_obj = serde_json::Map::new();
-        
-        url_obj.insert("id".to_string(), serde_json::Value::String(url.url.id.to_string()));
-        url_obj.insert("url".to_string(), serde_json::Value::String(url.url.url.clone()));
-        
-        if let Some(ref title) = url.url.title {
-            url_obj.insert("title".to_string(), serde_json::Value::String(title.clone()));
+    let app_state = app_handle.state::<AppState>();
+    let mut service = app_state.lan_sync.lock()
+        .map_err(|_| AppError::internal("Failed to acquire LAN sync lock"))?;
+    *service = None;
+    
+    if !settings.enabled {
+        return Ok(());
+    }
+    let identity = sync::peers::get_or_create_identity(db_conn)
+        .map_err(|e| AppError::wrap("Failed to get LAN sync identity", e))?;
+    
+    // A locked app neither serves nor pulls changes, without counting as activity
+    let handle = app_handle.clone();
+    let database = move || {
+        let app_state = handle.state::<AppState>();
+        if app_state.app_lock.status().ok().is_none_or(|status| status.locked) {
+            return None;
         }
-        
-        url_obj.insert("domain".to_string(), serde_json::Value::String(url.url.domain.clone()));
-        url_obj.insert("visit_count".to_string(), serde_json::Value::Number(serde_json::Number::from(url.visit_count)));
-        
-        if let Some(last_visit) = url.last_visit {
-            url_obj.insert("last_visit".to_string(), serde_json::Value::String(last_visit.to_rfc3339()));
+        app_state.db_connection.read().ok().and_then(|guard| guard.as_ref().cloned())
+    };
+    
+    let handle = app_handle.clone();
+    let on_event = move |event: sync::LanSyncEvent| {
+        if let sync::LanSyncEvent::Synced { result, .. } = &event {
+            if result.visits_added > 0 || result.visits_removed > 0 || result.urls_added > 0
+                || result.tags_added > 0 || result.collection_items_added > 0 || result.notes_updated > 0 {
+                events::emit_history_updated(&handle, events::HistoryChange::Imported);
+            }
         }
-        
-        url_array.push(serde_json::Value::Object(url_obj));
+        let _ = handle.emit_all("lan-sync", event);
+    };
+    
+    *service = Some(sync::LanSync::start(&settings, identity, database, on_event)
+        .map_err(|e| AppError::wrap("Failed to start LAN sync", e))?);
+    
+    Ok(())
+}
+
+// Helper function to run something with the LAN sync service, which must be on
+fn with_lan_sync<T>(app_handle: &tauri::AppHandle, f: impl FnOnce(&sync::LanSync) -> Result<T, AppError>) -> Result<T, AppError> {
+    let app_state = app_handle.state::<AppState>();
+    let service = app_state.lan_sync.lock()
+        .map_err(|_| AppError::internal("Failed to acquire LAN sync lock"))?;
+    match service.as_ref() {
+        Some(service) => f(service),
+        None => Err(AppError::new(ErrorKind::Config, "LAN sync is off")),
     }
+}
+
+// Helper function to perform a queued job on the worker thread
+fn execute_job(
+    app_handle: &tauri::AppHandle,
+    db_conn: &db::DatabaseConnection,
+    job: &jobs::JobContext,
+    request: &jobs::JobRequest,
+) -> Result<serde_json::Value, AppError> {
+    let result = match request {
+        jobs::JobRequest::Import { file_paths, device_names } => {
+            let results = import_history_files(db_conn, file_paths, device_names.as_deref(), None, job)?;
+            events::emit_history_updated(app_handle, events::HistoryChange::Imported);
+            serde_json::to_value(results)
+        },
+        jobs::JobRequest::IncrementalImport { file_path, device_name } => {
+            let since = jobs::schedule::import_watermark(db_conn, file_path)
+                .map_err(|e| AppError::wrap("Failed to find the last imported visit", e))?;
+            let device_names = device_name.clone().map(|name| vec![name]);
+            let results = import_history_files(db_conn, &[file_path.clone()], device_names.as_deref(), since, job)?;
+            
+            // Tell the UI and keep the knowledge graph current with the new visits
+            if results.visits_inserted > 0 {
+                let _ = app_handle.emit_all("history-imported", HistoryImportedEvent {
+                    file_path: file_path.clone(),
+                    urls_inserted: results.urls_inserted,
+                    visits_inserted: results.visits_inserted,
+                });
+                events::emit_history_updated(app_handle, events::HistoryChange::Imported);
+                
+                let rebuild = jobs::enqueue_job(db_conn, &jobs::JobRequest::RebuildGraph)
+                    .map_err(|e| AppError::wrap("Failed to queue graph rebuild", e))?;
+                events::emit_job_progress(app_handle, &rebuild);
+            }
+            serde_json::to_value(results)
+        },
+        jobs::JobRequest::Enrichment => {
+            serde_json::to_value(run_enrichment_queue(app_handle, db_conn, job)?)
+        },
+        jobs::JobRequest::RebuildGraph => {
+            serde_json::to_value(graph::rebuild_graph(db_conn)
+                .map_err(|e| AppError::wrap("Failed to rebuild graph", e))?)
+        },
+        jobs::JobRequest::Compact => {
+            serde_json::to_value(db::maintenance::compact_database(db_conn)
+                .map_err(|e| AppError::wrap("Failed to compact database", e))?)
+        },
+        jobs::JobRequest::NightlyScripts => {
+            let run = scripting::run_nightly(db_conn)
+                .map_err(|e| AppError::wrap("Failed to run nightly scripts", e))?;
+            if run.tagged > 0 {
+                events::emit_history_updated(app_handle, events::HistoryChange::Tagged);
+            }
+            serde_json::to_value(run)
+        },
+        jobs::JobRequest::ScheduledReports => {
+            let outputs = report::templates::run_due_templates(db_conn)
+                .map_err(|e| AppError::wrap("Failed to run scheduled reports", e))?;
+            serde_json::to_value(outputs)
+        },
+        jobs::JobRequest::ContentChecks => {
+            let settings = web::changes::get_content_check_settings(db_conn)
+                .map_err(|e| AppError::wrap("Failed to get content check settings", e))?;
+            let run = web::changes::check_content_changes(db_conn, settings.batch_size, settings.interval_days)
+                .map_err(|e| AppError::wrap("Failed to check pages for changes", e))?;
+            serde_json::to_value(run)
+        },
+        jobs::JobRequest::DomainEnrichment => {
+            let settings = web::provenance::get_domain_enrichment_settings(db_conn)
+                .map_err(|e| AppError::wrap("Failed to get domain enrichment settings", e))?;
+            let run = web::provenance::enrich_domains(db_conn, settings.batch_size, settings.interval_days)
+                .map_err(|e| AppError::wrap("Failed to enrich domains", e))?;
+            serde_json::to_value(run)
+        },
+    };
+    
+    result.map_err(|e| AppError::internal(format!("Failed to serialize job result: {}", e)))
+}
+
+// Helper function to work through the enrichment queue as a job, emitting
+// "enrichment-progress" events with the queue status and "enrichment-completed"
+// once the run ends
+fn run_enrichment_queue(
+    app_handle: &tauri::AppHandle,
+    db_conn: &db::DatabaseConnection,
+    job: &jobs::JobContext,
+) -> Result<enrichment::QueueStatus, AppError> {
+    let app_state = app_handle.state::<AppState>();
+    
+    let settings = enrichment::get_enrichment_settings(db_conn)
+        .map_err(|e| AppError::wrap("Failed to get enrichment settings", e))?;
+    
+    let provider: Arc<dyn enrichment::EnrichmentProvider> = Arc::from(
+        enrichment::create_provider(&settings)
+            .map_err(|e| AppError::wrap("Failed to create enrichment provider", e))?
+    );
+    
+    let on_progress = |status: &enrichment::QueueStatus| {
+        let _ = app_handle.emit_all("enrichment-progress", status.clone());
+        
+        let total = status.pending + status.running + status.done + status.failed;
+        if total > 0 {
+            job.progress((status.done + status.failed) as f64 / total as f64,
+                &format!("{} of {} pages enriched", status.done, total));
+        }
+        if job.is_cancelled() {
+            app_state.enrichment_queue.request_stop();
+        }
+    };
+    
+    let before = enrichment::queue::queue_status(db_conn, &app_state.enrichment_queue)
+        .map_err(|e| AppError::wrap("Failed to get enrichment queue status", e))?;
+    
+    enrichment::queue::run_queue(
+        &app_state.db_connection,
+        &app_state.enrichment_queue,
+        provider,
+        &settings.queue,
+        &on_progress,
+    ).map_err(|e| {
+        let _ = app_handle.emit_all("enrichment-error", e.to_string());
+        AppError::wrap("Failed to run enrichment queue", e)
+    })?;
     
-    serde_json::Value::Array(url_array)
+    let status = enrichment::queue::queue_status(db_conn, &app_state.enrichment_queue)
+        .map_err(|e| AppError::wrap("Failed to get enrichment queue status", e))?;
+    
+    // The counts are totals, so report what this run added
+    events::emit_enrichment_completed(
+        app_handle,
+        status.done.saturating_sub(before.done),
+        status.failed.saturating_sub(before.failed),
+    );
+    
+    Ok(status)
+}
+
+// Helper function to map the frontend grouping name to a timeline grouping
+fn parse_timeline_grouping(group_by: &str) -> db::operations::TimelineGrouping {
+    match group_by {
+        "hour" => db::operations::TimelineGrouping::Hour,
+        "domain" => db::operations::TimelineGrouping::Domain,
+        _ => db::operations::TimelineGrouping::Day, // Default to day
+    }
 }
 
 fn main() {
+    let context = tauri::generate_context!();
+    
+    // Must run first: a second launch opened by a link hands it over and exits
+    tauri_plugin_deep_link::prepare(&context.config().tauri.bundle.identifier);
+    
     // Build Tauri application
     tauri::Builder::default()
         .manage(AppState {
-            db_connection: Mutex::new(None),
+            db_connection: RwLock::new(None),
+            enrichment_queue: Arc::new(enrichment::QueueControl::default()),
+            erase_token: Mutex::new(None),
+            app_lock: lock::AppLock::default(),
+            init_lock: Mutex::new(()),
+            jobs: jobs::JobSignal::default(),
+            history_watcher: Mutex::new(None),
+            capture_server: Mutex::new(None),
+            capture_paused: AtomicBool::new(false),
+            mcp_sessions: Arc::new(mcp::McpSessions::default()),
+            mcp_server: Mutex::new(None),
+            lan_sync: Mutex::new(None),
+            global_shortcut: Mutex::new(None),
+            pending_deep_link: Mutex::new(None),
+            running_queries: db::interrupt::RunningQueries::default(),
+            streams: Arc::new(streaming::Streams::default()),
+        })
+        .setup(|app| {
+            events::set_app_handle(app.handle());
+            let handle = app.handle();
+            let on_link = move |link: String| {
+                if automation::is_automation_link(&link) {
+                    return run_automation_link(&handle, &link);
+                }
+                match deeplink::DeepLink::parse(&link) {
+                    Ok(link) => deeplink::open(&handle, link),
+                    Err(e) => {
+                        let _ = handle.emit_all("deep-link-error", e.message);
+                    },
+                }
+            };
+            
+            // Windows and Linux pass the link that launched the app as an argument
+            if let Some(link) = std::env::args().nth(1).filter(|arg| arg.starts_with(deeplink::SCHEME)) {
+                on_link(link);
+            }
+            // Without the scheme the app still works, links just don't reach it
+            let _ = tauri_plugin_deep_link::register(deeplink::SCHEME, on_link);
+            
+            // Keep the tray badge current across midnight, locking and unlocking
+            let handle = app.handle();
+            std::thread::spawn(move || loop {
+                tray::refresh_badge(&handle);
+                std::thread::sleep(TRAY_BADGE_INTERVAL);
+            });
+            Ok(())
         })
+        .system_tray(tray::build())
+        .on_system_tray_event(handle_tray_event)
         .invoke_handler(tauri::generate_handler![
+            take_pending_deep_link,
+            make_deep_link,
             initialize_database,
+            get_lock_status,
+            unlock,
+            lock_app,
+            enable_app_lock,
+            disable_app_lock,
+            is_encrypted,
+            enable_encryption,
+            disable_encryption,
             process_history_files,
+            get_import_history,
+            get_import_sources,
+            rollback_import,
+            inspect_dropped_files,
+            import_now,
+            queue_job,
+            list_jobs,
+            cancel_job,
+            get_database_health,
+            compact_database,
             get_history_stats,
+            get_recent,
+            get_device_stats,
+            get_devices,
+            rename_device,
+            merge_devices,
+            get_domains,
+            get_domain_details,
+            load_blocklist,
+            get_blocklists,
+            delete_blocklist,
+            get_tracker_domains,
+            get_domain_rollup,
+            set_domain_organization,
+            stream_domain_urls,
+            ack_stream,
+            cancel_stream,
+            get_top_pages,
+            get_url_sparklines,
+            get_campaign_stats,
+            get_origin_stats,
+            get_sentiment_stats,
+            get_skimmed_articles,
+            generate_reading_list,
+            get_trending,
+            get_revisitation_report,
+            get_on_this_day,
+            get_working_hours,
+            set_working_hours,
+            get_work_leisure_stats,
+            get_browsing_patterns,
+            generate_report,
+            generate_digest,
+            export_history,
+            stream_export_preview,
+            export_outline,
+            export_flashcards,
+            export_everything,
+            request_erase_token,
+            erase_all_data,
+            rebuild_graph,
+            get_co_visited_domains,
+            export_graph,
+            rebuild_graph_for_range,
+            rebuild_monthly_graph_snapshots,
+            get_graph_snapshots,
+            compute_graph_layout,
+            find_paths,
+            get_related,
+            get_settings,
+            update_settings,
+            list_plugins,
+            get_enrichment_settings,
+            set_enrichment_settings,
+            get_privacy_rules,
+            set_privacy_rules,
+            rotate_capture_token,
+            set_capture_paused,
+            list_mcp_sessions,
+            answer_mcp_session,
+            end_mcp_session,
+            list_scripts,
+            save_script,
+            delete_script,
+            test_script,
+            apply_privacy_rules,
+            enrich_urls,
+            enrich_all_unenriched,
+            embed_urls,
+            semantic_search,
+            ask_history,
+            generate_flashcards,
+            get_flashcards,
+            delete_flashcard,
+            query_history_nl,
+            run_readonly_sql,
+            list_report_templates,
+            save_report_template,
+            delete_report_template,
+            run_report_template,
+            get_report_outputs,
+            get_report_output,
+            categorize_urls,
+            get_category_stats,
+            get_top_keywords,
+            start_enrichment_queue,
+            stop_enrichment_queue,
+            get_enrichment_queue_status,
+            get_enrichment_usage,
+            capture_thumbnails,
+            get_thumbnail,
+            archive_urls,
+            get_archives,
+            check_content_changes,
+            get_changed_pages,
+            acknowledge_content_change,
+            enrich_domains,
+            link_wikidata,
+            set_wikidata_link,
+            score_sentiment,
+            expand_shortened_urls,
+            get_tags,
+            create_tag,
+            rename_tag,
+            merge_tags,
+            delete_tag,
+            tag_urls,
+            untag_urls,
+            set_favorite,
+            get_favorites,
+            get_collections,
+            create_collection,
+            update_collection,
+            delete_collection,
+            get_collection_items,
+            add_to_collection,
+            remove_from_collection,
+            reorder_collection,
+            set_collection_note,
+            update_url_title,
+            update_url_domain,
+            delete_visits,
+            secure_purge_urls,
+            add_manual_visit,
+            open_url,
+            copy_urls,
+            get_url_edits,
+            get_url_details,
+            find_duplicate_urls,
+            find_title_clusters,
+            merge_urls,
+            merge_database,
+            create_sync_bundle,
+            apply_sync_bundle,
+            get_lan_sync_status,
+            allow_lan_pairing,
+            request_lan_pairing,
+            answer_lan_pairing,
+            confirm_lan_pairing,
+            unpair_lan_peer,
+            sync_lan_peers_now,
+            get_operations,
+            undo_last_operation,
+            suggest,
+            toggle_spotlight,
             search_history,
+            cancel_query,
+            list_saved_searches,
+            save_search,
+            delete_saved_search,
+            run_saved_search,
             get_timeline_data,
+            get_timeline_bucket_urls,
         ])
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("Error running Tauri application");
 }
@@ -0,0 +1,49 @@
+// MCP Error Handling
+// Defines error types for the Model Context Protocol server
+
+use std::fmt;
+use std::error::Error;
+
+use crate::db::DatabaseError;
+
+/// Represents errors that can occur while serving assistants over MCP
+#[derive(Debug)]
+pub enum McpError {
+    /// Reading the history failed
+    Database(DatabaseError),
+    /// The listener could not be started
+    Server(String),
+    /// A tool was called with missing or malformed arguments
+    InvalidParams(String),
+    /// The session does not exist, or has ended
+    UnknownSession(String),
+}
+
+impl fmt::Display for McpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            McpError::Database(err) => write!(f, "Database error: {}", err),
+            McpError::Server(msg) => write!(f, "MCP server error: {}", msg),
+            McpError::InvalidParams(msg) => write!(f, "Invalid arguments: {}", msg),
+            McpError::UnknownSession(id) => write!(f, "Unknown MCP session '{}'", id),
+        }
+    }
+}
+
+impl Error for McpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            McpError::Database(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DatabaseError> for McpError {
+    fn from(err: DatabaseError) -> Self {
+        McpError::Database(err)
+    }
+}
+
+/// Result type for MCP operations
+pub type Result<T> = std::result::Result<T, McpError>;
@@ -0,0 +1,141 @@
+// MCP Module
+// Model Context Protocol server letting local assistants query the history,
+// with every client session approved by the user before its tools answer
+
+// Module organization:
+// - server.rs: Streamable HTTP transport and JSON-RPC dispatch
+// - tools.rs: search_history, get_page and get_timeline
+// - error.rs: Error handling
+
+pub mod server;
+pub mod tools;
+pub mod error;
+
+pub use error::{McpError, Result};
+pub use server::McpServer;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::settings::get_setting;
+use crate::db::DatabaseConnection;
+
+/// Settings key of the MCP server
+pub const MCP_SETTING: &str = "mcp";
+
+/// Port the server listens on unless configured
+pub const DEFAULT_MCP_PORT: u16 = 47616;
+
+/// MCP server configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct McpSettings {
+    /// Whether the server listens
+    pub enabled: bool,
+    /// Port on 127.0.0.1; clients connect to `http://127.0.0.1:<port>/mcp`
+    pub port: u16,
+}
+
+impl Default for McpSettings {
+    fn default() -> Self {
+        McpSettings {
+            enabled: false,
+            port: DEFAULT_MCP_PORT,
+        }
+    }
+}
+
+/// Gets the MCP server settings
+pub fn get_mcp_settings(conn: &DatabaseConnection) -> Result<McpSettings> {
+    Ok(conn.with_connection(|c| get_setting(c, MCP_SETTING))?.unwrap_or_default())
+}
+
+/// The user's answer to a session asking for the history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Consent {
+    /// Waiting for the user
+    Pending,
+    /// Tools may read the history
+    Granted,
+    /// Tools are refused
+    Denied,
+}
+
+/// A client connected to the MCP server
+#[derive(Debug, Clone, Serialize)]
+pub struct McpSession {
+    /// Session id the client sends in the `Mcp-Session-Id` header
+    pub id: String,
+    /// Name the client reported, e.g. "claude-desktop"
+    pub client_name: String,
+    /// Version the client reported
+    pub client_version: Option<String>,
+    /// Whether the user allowed the session
+    pub consent: Consent,
+    /// When the client connected
+    pub started_at: DateTime<Utc>,
+}
+
+/// Sessions of the running server. They live in memory only, so consent
+/// never outlasts the app or the server.
+#[derive(Debug, Default)]
+pub struct McpSessions {
+    sessions: Mutex<HashMap<String, McpSession>>,
+}
+
+impl McpSessions {
+    /// Locks the session map
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, McpSession>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Starts a session waiting for the user's consent
+    pub fn open(&self, client_name: &str, client_version: Option<&str>) -> McpSession {
+        let session = McpSession {
+            id: uuid::Uuid::new_v4().to_string(),
+            client_name: client_name.to_string(),
+            client_version: client_version.map(str::to_string),
+            consent: Consent::Pending,
+            started_at: Utc::now(),
+        };
+        self.lock().insert(session.id.clone(), session.clone());
+        session
+    }
+
+    /// Gets a session
+    pub fn get(&self, id: &str) -> Option<McpSession> {
+        self.lock().get(id).cloned()
+    }
+
+    /// Records the user's answer for a session
+    pub fn answer(&self, id: &str, granted: bool) -> Result<McpSession> {
+        let mut sessions = self.lock();
+        let session = sessions.get_mut(id)
+            .ok_or_else(|| McpError::UnknownSession(id.to_string()))?;
+        session.consent = if granted { Consent::Granted } else { Consent::Denied };
+        Ok(session.clone())
+    }
+
+    /// Ends a session; its client has to connect again
+    pub fn close(&self, id: &str) -> Result<()> {
+        self.lock().remove(id)
+            .map(|_| ())
+            .ok_or_else(|| McpError::UnknownSession(id.to_string()))
+    }
+
+    /// Ends every session
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Lists the sessions, oldest first
+    pub fn list(&self) -> Vec<McpSession> {
+        let mut sessions: Vec<McpSession> = self.lock().values().cloned().collect();
+        sessions.sort_by_key(|session| session.started_at);
+        sessions
+    }
+}
@@ -0,0 +1,229 @@
+// MCP - Server
+// Streamable HTTP transport on localhost answering JSON-RPC requests
+
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+use std::thread;
+
+use serde_json::{json, Value as JsonValue};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::db::DatabaseConnection;
+use super::error::{McpError, Result};
+use super::{tools, Consent, McpSession, McpSessions};
+
+/// Protocol revision the server speaks
+const PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// Largest request body accepted
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Running MCP server; it stops when dropped
+pub struct McpServer {
+    server: Arc<Server>,
+}
+
+/// What the request handler needs from the app
+struct Handler<D, S> {
+    sessions: Arc<McpSessions>,
+    /// Gives the database, or why the history can't be read right now
+    database: D,
+    /// Called when a client connects and asks for consent
+    on_session: S,
+}
+
+impl McpServer {
+    /// Listens on `http://127.0.0.1:<port>/mcp`. Tools only answer once the
+    /// user granted the calling session through `sessions`.
+    pub fn start<D, S>(port: u16, sessions: Arc<McpSessions>, database: D, on_session: S) -> Result<Self>
+    where
+        D: Fn() -> std::result::Result<DatabaseConnection, String> + Send + 'static,
+        S: Fn(&McpSession) + Send + 'static,
+    {
+        let server = Arc::new(Server::http(("127.0.0.1", port))
+            .map_err(|e| McpError::Server(format!("Failed to listen on port {}: {}", port, e)))?);
+
+        let handler = Handler { sessions, database, on_session };
+        let listener = Arc::clone(&server);
+        thread::spawn(move || {
+            // Ends once the server is unblocked on drop
+            for mut request in listener.incoming_requests() {
+                let response = handler.handle(&mut request);
+                let _ = request.respond(response);
+            }
+        });
+
+        Ok(McpServer { server })
+    }
+}
+
+impl Drop for McpServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+/// Builds a response with an optional JSON body
+fn response(status: u16, body: Option<JsonValue>) -> Response<Cursor<Vec<u8>>> {
+    let content_type = Header::from_bytes("Content-Type", "application/json")
+        .expect("static header is valid");
+    Response::from_string(body.map(|body| body.to_string()).unwrap_or_default())
+        .with_status_code(status)
+        .with_header(content_type)
+}
+
+/// Builds a JSON-RPC error response
+fn rpc_error(status: u16, id: &JsonValue, code: i64, message: &str) -> Response<Cursor<Vec<u8>>> {
+    response(status, Some(json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })))
+}
+
+/// Builds a JSON-RPC result response
+fn rpc_result(id: &JsonValue, result: JsonValue) -> Response<Cursor<Vec<u8>>> {
+    response(200, Some(json!({ "jsonrpc": "2.0", "id": id, "result": result })))
+}
+
+/// Builds a tool result holding text
+fn tool_text(text: String, is_error: bool) -> JsonValue {
+    json!({ "content": [{ "type": "text", "text": text }], "isError": is_error })
+}
+
+/// Gets a header's value
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request.headers().iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Returns true unless a browser page on another site sent the request
+fn is_local_origin(request: &Request) -> bool {
+    match header(request, "Origin") {
+        None => true,
+        Some(origin) => url::Url::parse(origin).ok()
+            .and_then(|origin| origin.host_str().map(str::to_string))
+            .is_some_and(|host| host == "localhost" || host == "127.0.0.1" || host == "[::1]"),
+    }
+}
+
+impl<D, S> Handler<D, S>
+where
+    D: Fn() -> std::result::Result<DatabaseConnection, String>,
+    S: Fn(&McpSession),
+{
+    /// Answers one HTTP request
+    fn handle(&self, request: &mut Request) -> Response<Cursor<Vec<u8>>> {
+        if request.url().split('?').next() != Some("/mcp") {
+            return response(404, None);
+        }
+        // Guards against DNS rebinding from web pages
+        if !is_local_origin(request) {
+            return response(403, None);
+        }
+
+        let session_id = header(request, "Mcp-Session-Id").map(str::to_string);
+        let method = request.method().clone();
+        match method {
+            Method::Post => self.handle_post(request, session_id),
+            Method::Delete => match session_id.map(|id| self.sessions.close(&id)) {
+                Some(Ok(())) => response(200, None),
+                _ => response(404, None),
+            },
+            // No server-initiated messages, so there is no event stream to open
+            _ => response(405, None),
+        }
+    }
+
+    /// Answers a JSON-RPC message
+    fn handle_post(&self, request: &mut Request, session_id: Option<String>) -> Response<Cursor<Vec<u8>>> {
+        let mut body = String::new();
+        if request.as_reader().take(MAX_BODY_BYTES + 1).read_to_string(&mut body).is_err()
+            || body.len() as u64 > MAX_BODY_BYTES
+        {
+            return rpc_error(400, &JsonValue::Null, PARSE_ERROR, "Body is too large or not UTF-8");
+        }
+        let message: JsonValue = match serde_json::from_str(&body) {
+            Ok(message) => message,
+            Err(_) => return rpc_error(400, &JsonValue::Null, PARSE_ERROR, "Body is not JSON"),
+        };
+
+        let method = message.get("method").and_then(JsonValue::as_str).unwrap_or_default();
+        let params = message.get("params").cloned().unwrap_or(JsonValue::Null);
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            // Notifications and responses need no answer
+            None => return response(202, None),
+        };
+
+        if method == "initialize" {
+            return self.initialize(&id, &params);
+        }
+
+        // Every other request belongs to a session started by initialize
+        let session = match session_id {
+            Some(session_id) => match self.sessions.get(&session_id) {
+                Some(session) => session,
+                None => return rpc_error(404, &id, INVALID_PARAMS, "Session has ended, initialize again"),
+            },
+            None => return rpc_error(400, &id, INVALID_PARAMS, "Missing Mcp-Session-Id header"),
+        };
+
+        match method {
+            "ping" => rpc_result(&id, json!({})),
+            "tools/list" => rpc_result(&id, json!({ "tools": tools::tool_list() })),
+            "tools/call" => rpc_result(&id, self.call_tool(&session, &params)),
+            _ => rpc_error(200, &id, METHOD_NOT_FOUND, &format!("Unknown method '{}'", method)),
+        }
+    }
+
+    /// Starts a session and asks the user for consent
+    fn initialize(&self, id: &JsonValue, params: &JsonValue) -> Response<Cursor<Vec<u8>>> {
+        let client = params.get("clientInfo");
+        let client_name = client.and_then(|c| c.get("name")).and_then(JsonValue::as_str).unwrap_or("unknown client");
+        let client_version = client.and_then(|c| c.get("version")).and_then(JsonValue::as_str);
+
+        let session = self.sessions.open(client_name, client_version);
+        (self.on_session)(&session);
+
+        let session_header = Header::from_bytes("Mcp-Session-Id", session.id.as_bytes())
+            .expect("session ids are valid header values");
+        rpc_result(id, json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "safari-history-knowledge-graph", "version": env!("CARGO_PKG_VERSION") },
+            "instructions": "Tools read the user's browsing history. The user approves each session in the app before tools answer.",
+        })).with_header(session_header)
+    }
+
+    /// Runs a tool if the user allowed the session
+    fn call_tool(&self, session: &McpSession, params: &JsonValue) -> JsonValue {
+        match session.consent {
+            Consent::Pending => return tool_text(
+                "The user has not approved this session yet. Ask them to allow access in the app, then try again.".to_string(),
+                true,
+            ),
+            Consent::Denied => return tool_text(
+                "The user declined access to their browsing history for this session.".to_string(),
+                true,
+            ),
+            Consent::Granted => {},
+        }
+
+        let name = params.get("name").and_then(JsonValue::as_str).unwrap_or_default();
+        let args = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+        let result = (self.database)()
+            .and_then(|conn| tools::call_tool(&conn, name, &args).map_err(|e| e.to_string()));
+
+        match result {
+            Ok(value) => tool_text(serde_json::to_string_pretty(&value).unwrap_or_default(), false),
+            Err(e) => tool_text(e, true),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+// MCP - Tools
+// History lookups offered to assistants, with their JSON schemas
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::OptionalExtension;
+use serde_json::{json, Value as JsonValue};
+
+use crate::db::operations::{self, SearchParams, TimelineGrouping, TimelineItem, TimelineParams};
+use crate::db::tags::get_url_tags;
+use crate::db::DatabaseConnection;
+use super::error::{McpError, Result};
+
+/// Results returned by search_history unless the assistant asks for fewer
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+/// Most results search_history returns
+const MAX_SEARCH_LIMIT: usize = 100;
+
+/// Visits listed by get_page
+const PAGE_RECENT_VISITS: usize = 20;
+
+/// Descriptions of the tools for `tools/list`
+pub fn tool_list() -> JsonValue {
+    let date = json!({
+        "type": "string",
+        "description": "Date as YYYY-MM-DD or an RFC 3339 timestamp",
+    });
+
+    json!([
+        {
+            "name": "search_history",
+            "description": "Search the user's browsing history by text in URLs, titles and summaries, optionally limited to a domain and date range. Returns pages with visit counts.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "Text to search for" },
+                    "domain": { "type": "string", "description": "Only pages on this domain" },
+                    "start_date": date,
+                    "end_date": date,
+                    "limit": { "type": "integer", "minimum": 1, "maximum": MAX_SEARCH_LIMIT },
+                },
+            },
+        },
+        {
+            "name": "get_page",
+            "description": "Get one page from the browsing history with its summary, keywords, tags and most recent visits.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string", "description": "Page id from search_history" },
+                    "url": { "type": "string", "description": "Exact URL of the page" },
+                },
+            },
+        },
+        {
+            "name": "get_timeline",
+            "description": "Count visits per day, per hour of day, or per domain over a date range.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "start_date": date,
+                    "end_date": date,
+                    "domain": { "type": "string", "description": "Only visits to this domain" },
+                    "group_by": { "type": "string", "enum": ["day", "hour", "domain"] },
+                },
+            },
+        },
+    ])
+}
+
+/// Reads an optional string argument
+fn string_arg(args: &JsonValue, name: &str) -> Result<Option<String>> {
+    match args.get(name) {
+        None | Some(JsonValue::Null) => Ok(None),
+        Some(JsonValue::String(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(McpError::InvalidParams(format!("'{}' must be a string", name))),
+    }
+}
+
+/// Reads an optional date argument
+fn date_arg(args: &JsonValue, name: &str) -> Result<Option<DateTime<Utc>>> {
+    string_arg(args, name)?.map(|value| {
+        DateTime::parse_from_rfc3339(&value)
+            .map(|dt| dt.with_timezone(&Utc))
+            .or_else(|_| NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()))
+            .map_err(|_| McpError::InvalidParams(format!("'{}' is not a date: {}", name, value)))
+    }).transpose()
+}
+
+/// Runs a tool with its arguments
+pub fn call_tool(conn: &DatabaseConnection, name: &str, args: &JsonValue) -> Result<JsonValue> {
+    match name {
+        "search_history" => search_history(conn, args),
+        "get_page" => get_page(conn, args),
+        "get_timeline" => get_timeline(conn, args),
+        other => Err(McpError::InvalidParams(format!("Unknown tool '{}'", other))),
+    }
+}
+
+/// Searches pages by text, domain and date
+fn search_history(conn: &DatabaseConnection, args: &JsonValue) -> Result<JsonValue> {
+    let limit = match args.get("limit") {
+        None | Some(JsonValue::Null) => DEFAULT_SEARCH_LIMIT,
+        Some(value) => value.as_u64()
+            .ok_or_else(|| McpError::InvalidParams("'limit' must be a positive integer".to_string()))?
+            .clamp(1, MAX_SEARCH_LIMIT as u64) as usize,
+    };
+
+    let results = operations::search_history(conn, &SearchParams {
+        query: string_arg(args, "query")?,
+        domain: string_arg(args, "domain")?,
+        category: None,
+        tag: None,
+        keyword: None,
+        walled: None,
+        start_date: date_arg(args, "start_date")?,
+        end_date: date_arg(args, "end_date")?,
+        limit: Some(limit),
+        offset: None,
+    })?;
+
+    let pages: Vec<JsonValue> = results.urls.into_iter().map(|result| json!({
+        "id": result.url.id.to_string(),
+        "url": result.url.url,
+        "title": result.url.title,
+        "domain": result.url.domain,
+        "summary": result.metadata.and_then(|m| m.summary),
+        "tags": result.tags,
+        "visit_count": result.visit_count,
+        "last_visit": result.last_visit.map(|dt| dt.to_rfc3339()),
+    })).collect();
+
+    Ok(json!({ "total_count": results.total_count, "pages": pages }))
+}
+
+/// Gets one page with its metadata and recent visits
+fn get_page(conn: &DatabaseConnection, args: &JsonValue) -> Result<JsonValue> {
+    let id = string_arg(args, "id")?;
+    let url = string_arg(args, "url")?;
+    if id.is_none() && url.is_none() {
+        return Err(McpError::InvalidParams("Either 'id' or 'url' is required".to_string()));
+    }
+
+    let page = conn.with_connection(|c| {
+        let page = c.query_row(
+            "SELECT page_id, page_url, title, domain, category, first_seen, last_seen,
+                    summary, keywords, topic, reading_time_sec
+             FROM history_pages
+             WHERE page_id = ?1 OR page_url = ?2",
+            rusqlite::params![id, url],
+            |row| Ok((row.get::<_, String>(0)?, json!({
+                "id": row.get::<_, String>(0)?,
+                "url": row.get::<_, String>(1)?,
+                "title": row.get::<_, Option<String>>(2)?,
+                "domain": row.get::<_, String>(3)?,
+                "category": row.get::<_, Option<String>>(4)?,
+                "first_seen": row.get::<_, Option<String>>(5)?,
+                "last_seen": row.get::<_, Option<String>>(6)?,
+                "summary": row.get::<_, Option<String>>(7)?,
+                "keywords": row.get::<_, Option<String>>(8)?
+                    .and_then(|keywords| serde_json::from_str::<JsonValue>(&keywords).ok()),
+                "topic": row.get::<_, Option<String>>(9)?,
+                "reading_time_sec": row.get::<_, Option<i64>>(10)?,
+            }))),
+        ).optional()?;
+
+        let (page_id, mut page) = match page {
+            Some(page) => page,
+            None => return Ok(None),
+        };
+
+        let visit_count: i64 = c.query_row(
+            "SELECT COUNT(*) FROM history_visits WHERE page_id = ?",
+            [&page_id],
+            |row| row.get(0),
+        )?;
+        let mut stmt = c.prepare(
+            "SELECT visited_at, duration_sec, device_name FROM history_visits
+             WHERE page_id = ? ORDER BY visited_ts DESC LIMIT ?",
+        )?;
+        let visits = stmt.query_map(rusqlite::params![page_id, PAGE_RECENT_VISITS as i64], |row| {
+            Ok(json!({
+                "visited_at": row.get::<_, String>(0)?,
+                "duration_sec": row.get::<_, Option<f64>>(1)?,
+                "device": row.get::<_, Option<String>>(2)?,
+            }))
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        page["tags"] = json!(get_url_tags(c, &page_id)?);
+        page["visit_count"] = json!(visit_count);
+        page["recent_visits"] = json!(visits);
+        Ok(Some(page))
+    })?;
+
+    page.ok_or_else(|| McpError::InvalidParams("No page with that id or URL".to_string()))
+}
+
+/// Counts visits per day, hour or domain
+fn get_timeline(conn: &DatabaseConnection, args: &JsonValue) -> Result<JsonValue> {
+    let group_by = match string_arg(args, "group_by")?.as_deref() {
+        None | Some("day") => TimelineGrouping::Day,
+        Some("hour") => TimelineGrouping::Hour,
+        Some("domain") => TimelineGrouping::Domain,
+        Some(other) => return Err(McpError::InvalidParams(format!("Unknown grouping '{}'", other))),
+    };
+
+    let items = operations::get_timeline_data(conn, &TimelineParams {
+        start_date: date_arg(args, "start_date")?,
+        end_date: date_arg(args, "end_date")?,
+        domain: string_arg(args, "domain")?,
+        group_by,
+    })?;
+
+    let buckets: Vec<JsonValue> = items.into_iter().map(|item| match item {
+        TimelineItem::Hourly { hour, count, .. } => json!({ "hour": hour, "visits": count }),
+        TimelineItem::Daily { date, count, .. } => json!({ "date": date.format("%Y-%m-%d").to_string(), "visits": count }),
+        TimelineItem::Domain { domain, count, .. } => json!({ "domain": domain, "visits": count }),
+    }).collect();
+
+    Ok(json!(buckets))
+}
@@ -0,0 +1,107 @@
+// Desktop Notifications
+// Native notifications when imports, enrichment runs or reports finish or
+// fail, so long work can be left running in the background
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tauri::api::notification::Notification;
+use tauri::Manager;
+
+use crate::db::settings::get_setting;
+use crate::jobs::{Job, JobRequest, JobState};
+
+/// Settings key of the notification switch
+pub const NOTIFICATIONS_SETTING: &str = "notifications";
+
+/// Which notifications are shown
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    /// Whether notifications are shown at all
+    pub enabled: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings { enabled: true }
+    }
+}
+
+/// Reads a count from a job result
+fn count(result: Option<&JsonValue>, field: &str) -> u64 {
+    result.and_then(|result| result.get(field)).and_then(JsonValue::as_u64).unwrap_or(0)
+}
+
+/// Title and message for a finished job, if it is worth interrupting the
+/// user for. Scheduled imports that found nothing new and routine
+/// maintenance only notify when they fail.
+pub fn job_message(job: &Job) -> Option<(String, String)> {
+    let result = job.result.as_ref();
+    match job.state {
+        JobState::Failed => {
+            let what = match &job.request {
+                JobRequest::Import { .. } | JobRequest::IncrementalImport { .. } => "Import",
+                JobRequest::Enrichment => "Enrichment",
+                JobRequest::RebuildGraph => "Graph rebuild",
+                JobRequest::Compact => "Database compaction",
+                JobRequest::NightlyScripts => "Nightly scripts",
+                JobRequest::ScheduledReports => "Scheduled reports",
+                JobRequest::ContentChecks => "Content check",
+                JobRequest::DomainEnrichment => "Domain enrichment",
+            };
+            let error = job.error.clone().unwrap_or_else(|| "Unknown error".to_string());
+            Some((format!("{} failed", what), error))
+        },
+        JobState::Done => match &job.request {
+            JobRequest::Import { .. } | JobRequest::IncrementalImport { .. } => {
+                let visits = count(result, "visits_inserted");
+                let is_scheduled = matches!(job.request, JobRequest::IncrementalImport { .. });
+                if is_scheduled && visits == 0 {
+                    return None;
+                }
+                let mut message = format!("Added {} visits and {} new pages", visits, count(result, "urls_inserted"));
+                let errors = result.and_then(|result| result.get("errors"))
+                    .and_then(JsonValue::as_array)
+                    .map_or(0, Vec::len);
+                if errors > 0 {
+                    message.push_str(&format!(", {} files had problems", errors));
+                }
+                Some(("Import finished".to_string(), message))
+            },
+            JobRequest::Enrichment => Some((
+                "Enrichment finished".to_string(),
+                format!("{} pages enriched, {} failed", count(result, "done"), count(result, "failed")),
+            )),
+            JobRequest::ContentChecks => match count(result, "changed") {
+                0 => None,
+                changed => Some(("Pages changed".to_string(), format!("{} pages you read have changed", changed))),
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Shows a notification unless the user turned them off
+pub fn show(app_handle: &tauri::AppHandle, title: &str, message: &str) {
+    let app_state = app_handle.state::<crate::AppState>();
+    let db_conn = app_state.db_connection.read().ok().and_then(|guard| guard.as_ref().cloned());
+    let settings: NotificationSettings = db_conn
+        .and_then(|db_conn| db_conn.with_connection(|c| get_setting(c, NOTIFICATIONS_SETTING)).ok().flatten())
+        .unwrap_or_default();
+    if !settings.enabled {
+        return;
+    }
+
+    let _ = Notification::new(&app_handle.config().tauri.bundle.identifier)
+        .title(title)
+        .body(message)
+        .show();
+}
+
+/// Shows a notification for a job that just finished, if it warrants one
+pub fn notify_job(app_handle: &tauri::AppHandle, job: &Job) {
+    if let Some((title, message)) = job_message(job) {
+        show(app_handle, &title, &message);
+    }
+}
@@ -0,0 +1,60 @@
+// Plugins Error Handling
+// Defines error types for loading and running plugins
+
+use std::fmt;
+use std::error::Error;
+use std::io;
+
+/// Represents errors that can occur while loading or calling a plugin
+#[derive(Debug)]
+pub enum PluginError {
+    /// The plugins directory or a plugin file could not be read
+    Io(io::Error),
+    /// A plugin's manifest is missing fields or targets another plugin API
+    Manifest(String),
+    /// No installed plugin has the given id and kind
+    NotFound(String),
+    /// The module failed to load, trapped, or ran out of fuel or memory
+    Wasm(String),
+    /// The plugin reported an error or answered with something unusable
+    Plugin(String),
+    /// The plugin tried something its manifest does not allow
+    Denied(String),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PluginError::Io(err) => write!(f, "I/O error: {}", err),
+            PluginError::Manifest(msg) => write!(f, "Invalid plugin manifest: {}", msg),
+            PluginError::NotFound(id) => write!(f, "Plugin '{}' is not installed", id),
+            PluginError::Wasm(msg) => write!(f, "Plugin runtime error: {}", msg),
+            PluginError::Plugin(msg) => write!(f, "Plugin error: {}", msg),
+            PluginError::Denied(msg) => write!(f, "Plugin not allowed to {}", msg),
+        }
+    }
+}
+
+impl Error for PluginError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PluginError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PluginError {
+    fn from(err: io::Error) -> Self {
+        PluginError::Io(err)
+    }
+}
+
+impl From<wasmtime::Error> for PluginError {
+    fn from(err: wasmtime::Error) -> Self {
+        PluginError::Wasm(err.to_string())
+    }
+}
+
+/// Result type for plugin operations
+pub type Result<T> = std::result::Result<T, PluginError>;
@@ -0,0 +1,84 @@
+// Plugins - Exporter
+// Lets an exporter plugin encode export rows; the host writes the file
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::export::error::{ExportError, Result as ExportResult};
+use crate::export::writers::RowWriter;
+use crate::export::ExportRow;
+use super::error::Result;
+use super::runtime::WasmPlugin;
+use super::{find_plugin, PluginKind};
+
+/// Rows handed to the plugin per call
+const ROWS_PER_CALL: usize = 500;
+
+/// Request sent to the plugin's `export_rows` export
+#[derive(Serialize)]
+struct RowsRequest<'a> {
+    rows: &'a [ExportRow],
+}
+
+/// Reply of the `export_begin`, `export_rows` and `export_finish` exports
+#[derive(Deserialize)]
+struct DataReply {
+    /// Text to append to the file
+    #[serde(default)]
+    data: String,
+}
+
+/// Export writer backed by a plugin exporting `export_begin`, `export_rows`
+/// and `export_finish`, each returning text to append to the file
+pub struct PluginWriter {
+    plugin: WasmPlugin,
+    out: BufWriter<File>,
+    buffer: Vec<ExportRow>,
+}
+
+impl PluginWriter {
+    /// Loads an installed exporter plugin and starts the file
+    pub fn create(id: &str, path: &Path) -> Result<Self> {
+        let (manifest, module) = find_plugin(id, PluginKind::Exporter)?;
+        let plugin = WasmPlugin::load(&manifest, &module)?;
+
+        let mut out = BufWriter::new(File::create(path)?);
+        let begin: DataReply = plugin.call("export_begin", &json!({}))?;
+        out.write_all(begin.data.as_bytes())?;
+
+        Ok(PluginWriter { plugin, out, buffer: Vec::with_capacity(ROWS_PER_CALL) })
+    }
+
+    /// Encodes the buffered rows
+    fn flush_rows(&mut self) -> ExportResult<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let reply: DataReply = self.plugin.call("export_rows", &RowsRequest { rows: &self.buffer })?;
+        self.out.write_all(reply.data.as_bytes())?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl RowWriter for PluginWriter {
+    fn write_row(&mut self, row: &ExportRow) -> ExportResult<()> {
+        self.buffer.push(row.clone());
+        if self.buffer.len() >= ROWS_PER_CALL {
+            self.flush_rows()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> ExportResult<()> {
+        self.flush_rows()?;
+        let end: DataReply = self.plugin.call("export_finish", &json!({}))?;
+        self.out.write_all(end.data.as_bytes())?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+// Plugins Module
+// Community enrichment providers and exporters, loaded as WebAssembly modules
+// from the plugins directory and run in a sandbox
+
+// Module organization:
+// - runtime.rs: Sandboxed WebAssembly runtime and the host functions
+// - provider.rs: Enrichment provider backed by a plugin
+// - exporter.rs: Export writer backed by a plugin
+// - error.rs: Error handling
+//
+// A plugin is a directory `<app data>/plugins/<id>/` holding `plugin.json`
+// (see `PluginManifest`) and a WebAssembly module. The module gets no file
+// system, clock or network of its own; it may only make HTTP requests to the
+// hosts its manifest lists, through the host.
+
+pub mod runtime;
+pub mod provider;
+pub mod exporter;
+pub mod error;
+
+pub use error::{PluginError, Result};
+pub use exporter::PluginWriter;
+pub use provider::PluginProvider;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::enrichment::provider::Pricing;
+
+/// Version of the plugin interface; plugins built for another one are refused
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+/// Name of the manifest in a plugin's directory
+pub const MANIFEST_FILE: &str = "plugin.json";
+
+/// What a plugin provides
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginKind {
+    /// Summaries, keywords and categories, like the built-in providers
+    Enrichment,
+    /// An export format
+    Exporter,
+}
+
+/// Contents of `plugin.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginManifest {
+    /// Identifier, also the name of the plugin's directory
+    pub id: String,
+    /// Name shown in the app
+    pub name: String,
+    /// Plugin version
+    pub version: String,
+    /// Plugin interface the module implements
+    pub api_version: u32,
+    /// What the plugin provides
+    pub kind: PluginKind,
+    /// What the plugin does
+    #[serde(default)]
+    pub description: Option<String>,
+    /// WebAssembly module, relative to the plugin's directory
+    #[serde(default = "default_module")]
+    pub module: String,
+    /// Hosts the plugin may send HTTP requests to, e.g. "api.anthropic.com"
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    /// Model name recorded with enrichments
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Token prices, used for cost tracking
+    #[serde(default)]
+    pub pricing: Pricing,
+    /// Extension of the files an exporter writes, e.g. "md"
+    #[serde(default)]
+    pub file_extension: Option<String>,
+}
+
+fn default_module() -> String {
+    "plugin.wasm".to_string()
+}
+
+/// An installed plugin, or why it can't be used
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginInfo {
+    /// Plugin directory
+    pub path: String,
+    /// Parsed manifest, if it could be read
+    pub manifest: Option<PluginManifest>,
+    /// Why the plugin can't be loaded
+    pub error: Option<String>,
+}
+
+/// Installed plugins and where to put new ones
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginDirectory {
+    /// Directory plugins are loaded from
+    pub path: String,
+    /// Installed plugins, by directory name
+    pub plugins: Vec<PluginInfo>,
+}
+
+/// Directory plugins are loaded from, created if missing
+pub fn plugins_dir() -> Result<PathBuf> {
+    let dir = tauri::api::path::app_data_dir(&tauri::Config::default())
+        .ok_or_else(|| PluginError::Manifest("No app data directory".to_string()))?
+        .join("plugins");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Reads and checks the manifest of a plugin directory
+fn read_manifest(dir: &Path) -> Result<PluginManifest> {
+    let json = fs::read_to_string(dir.join(MANIFEST_FILE))?;
+    let manifest: PluginManifest = serde_json::from_str(&json)
+        .map_err(|e| PluginError::Manifest(e.to_string()))?;
+
+    if manifest.api_version != PLUGIN_API_VERSION {
+        return Err(PluginError::Manifest(format!(
+            "Built for plugin API {}, this app supports {}", manifest.api_version, PLUGIN_API_VERSION,
+        )));
+    }
+    if dir.file_name().and_then(|name| name.to_str()) != Some(manifest.id.as_str()) {
+        return Err(PluginError::Manifest(format!("Id '{}' does not match its directory", manifest.id)));
+    }
+    // The module must stay inside the plugin's directory
+    if Path::new(&manifest.module).components().any(|c| !matches!(c, std::path::Component::Normal(_))) {
+        return Err(PluginError::Manifest(format!("Module path '{}' leaves the plugin directory", manifest.module)));
+    }
+    Ok(manifest)
+}
+
+/// Lists the installed plugins
+pub fn list_plugins() -> Result<PluginDirectory> {
+    let dir = plugins_dir()?;
+
+    let mut entries: Vec<PathBuf> = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    entries.sort();
+
+    let plugins = entries.into_iter().map(|path| {
+        let (manifest, error) = match read_manifest(&path) {
+            Ok(manifest) => (Some(manifest), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        PluginInfo { path: path.display().to_string(), manifest, error }
+    }).collect();
+
+    Ok(PluginDirectory { path: dir.display().to_string(), plugins })
+}
+
+/// Finds an installed plugin of the given kind
+pub fn find_plugin(id: &str, kind: PluginKind) -> Result<(PluginManifest, PathBuf)> {
+    // Ids name a directory, so they must not reach outside the plugins directory
+    if id.is_empty() || id.contains(|c: char| c == '/' || c == '\\') || id.starts_with('.') {
+        return Err(PluginError::NotFound(id.to_string()));
+    }
+
+    let dir = plugins_dir()?.join(id);
+    if !dir.is_dir() {
+        return Err(PluginError::NotFound(id.to_string()));
+    }
+    let manifest = read_manifest(&dir)?;
+    if manifest.kind != kind {
+        return Err(PluginError::NotFound(id.to_string()));
+    }
+
+    let module = dir.join(&manifest.module);
+    Ok((manifest, module))
+}
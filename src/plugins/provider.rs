@@ -0,0 +1,89 @@
+// Plugins - Enrichment Provider
+// Lets an enrichment plugin stand in for the built-in providers
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::enrichment::error::{EnrichmentError, Result as EnrichmentResult};
+use crate::enrichment::provider::{Completion, EnrichmentProvider, Pricing, TokenUsage};
+use super::error::Result;
+use super::runtime::WasmPlugin;
+use super::{find_plugin, PluginKind};
+
+/// Request sent to the plugin's `complete` export
+#[derive(Serialize)]
+struct CompleteRequest<'a> {
+    system: &'a str,
+    prompt: &'a str,
+    /// Whether the reply must be a JSON object
+    json: bool,
+    /// Options the user configured for the plugin, e.g. an API key
+    options: &'a JsonValue,
+}
+
+/// Reply of the plugin's `complete` export
+#[derive(Deserialize)]
+struct CompleteReply {
+    content: String,
+    #[serde(default)]
+    prompt_tokens: Option<u64>,
+    #[serde(default)]
+    completion_tokens: Option<u64>,
+}
+
+/// Enrichment provider backed by a plugin exporting `complete`
+pub struct PluginProvider {
+    plugin: WasmPlugin,
+    id: String,
+    model: String,
+    pricing: Pricing,
+    options: JsonValue,
+}
+
+impl PluginProvider {
+    /// Loads an installed enrichment plugin with the user's options
+    pub fn load(id: &str, options: JsonValue) -> Result<Self> {
+        let (manifest, module) = find_plugin(id, PluginKind::Enrichment)?;
+        let plugin = WasmPlugin::load(&manifest, &module)?;
+
+        Ok(PluginProvider {
+            plugin,
+            model: manifest.model.clone().unwrap_or_else(|| manifest.id.clone()),
+            pricing: manifest.pricing,
+            id: manifest.id,
+            options,
+        })
+    }
+}
+
+impl EnrichmentProvider for PluginProvider {
+    fn name(&self) -> &str {
+        &self.id
+    }
+
+    fn chat_model(&self) -> &str {
+        &self.model
+    }
+
+    fn pricing(&self) -> Pricing {
+        self.pricing
+    }
+
+    fn complete(&self, system: &str, prompt: &str, json: bool) -> EnrichmentResult<Completion> {
+        let reply: CompleteReply = self.plugin.call("complete", &CompleteRequest {
+            system,
+            prompt,
+            json,
+            options: &self.options,
+        }).map_err(|e| EnrichmentError::InvalidResponse(e.to_string()))?;
+
+        let usage = match (reply.prompt_tokens, reply.completion_tokens) {
+            (None, None) => None,
+            (prompt_tokens, completion_tokens) => Some(TokenUsage {
+                prompt_tokens: prompt_tokens.unwrap_or_default(),
+                completion_tokens: completion_tokens.unwrap_or_default(),
+            }),
+        };
+        Ok(Completion { content: reply.content, usage })
+    }
+}
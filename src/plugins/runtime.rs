@@ -0,0 +1,270 @@
+// Plugins - Runtime
+// Sandboxed WebAssembly instances and the few host functions they may call
+//
+// Calling convention: the module exports `memory`, `alloc(len) -> ptr` and
+// one function per call taking the (ptr, len) of a UTF-8 JSON request and
+// returning the (ptr << 32 | len) of a JSON reply, either `{"ok": ...}` or
+// `{"error": "..."}`. Host functions in the "host" namespace use the same
+// convention.
+
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use wasmtime::{Caller, Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+use super::error::{PluginError, Result};
+use super::PluginManifest;
+
+/// Instructions one call may run before it is stopped
+const FUEL_PER_CALL: u64 = 2_000_000_000;
+
+/// Largest linear memory a plugin may grow to
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Largest reply read back from a plugin or an HTTP request
+const MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Time allowed for a plugin's HTTP request
+const HTTP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Messages a plugin may log per `LOG_WINDOW`; the rest are counted and dropped
+const MAX_LOGS_PER_WINDOW: usize = 20;
+
+/// Period the log limit applies to
+const LOG_WINDOW: Duration = Duration::from_secs(10);
+
+/// State the host functions see
+struct HostState {
+    limits: StoreLimits,
+    plugin_id: String,
+    allowed_hosts: Vec<String>,
+    agent: ureq::Agent,
+    log_limit: LogLimit,
+}
+
+/// Messages logged in the current window
+struct LogLimit {
+    window_start: Instant,
+    logged: usize,
+    dropped: usize,
+}
+
+impl LogLimit {
+    /// Sends a plugin's message to the windows unless it logged too much
+    /// lately; once a window ends, says how many messages were dropped
+    fn log(&mut self, plugin_id: &str, message: &str) {
+        if self.window_start.elapsed() >= LOG_WINDOW {
+            if self.dropped > 0 {
+                crate::events::emit_plugin_log(plugin_id, &format!("{} messages dropped", self.dropped));
+            }
+            *self = LogLimit { window_start: Instant::now(), logged: 0, dropped: 0 };
+        }
+
+        if self.logged < MAX_LOGS_PER_WINDOW {
+            self.logged += 1;
+            crate::events::emit_plugin_log(plugin_id, message);
+        } else {
+            self.dropped += 1;
+        }
+    }
+}
+
+/// An HTTP request made by a plugin
+#[derive(Deserialize)]
+struct HttpRequest {
+    #[serde(default = "default_method")]
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+    #[serde(default)]
+    body: Option<String>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+/// The answer to a plugin's HTTP request
+#[derive(Serialize)]
+struct HttpResponse {
+    status: u16,
+    body: String,
+}
+
+/// Instance and the exports every call needs
+struct Loaded {
+    store: Store<HostState>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    instance: Instance,
+}
+
+/// A loaded plugin module. Calls are serialized; each one gets a fresh fuel budget.
+pub struct WasmPlugin {
+    id: String,
+    loaded: Mutex<Loaded>,
+}
+
+/// Splits a packed (ptr << 32 | len) reply
+fn unpack(packed: i64) -> (usize, usize) {
+    ((packed as u64 >> 32) as usize, (packed as u64 & 0xffff_ffff) as usize)
+}
+
+/// Reads bytes from a plugin's memory
+fn read_bytes(memory: &Memory, store: impl wasmtime::AsContext, ptr: usize, len: usize) -> Result<Vec<u8>> {
+    if len > MAX_MESSAGE_BYTES {
+        return Err(PluginError::Plugin(format!("Message of {} bytes is too large", len)));
+    }
+    let mut bytes = vec![0; len];
+    memory.read(store, ptr, &mut bytes)
+        .map_err(|_| PluginError::Plugin("Message lies outside the plugin's memory".to_string()))?;
+    Ok(bytes)
+}
+
+/// Returns true if the manifest lets the plugin reach the URL's host
+fn host_allowed(allowed_hosts: &[String], url: &str) -> bool {
+    let host = match url::Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "https" => parsed.host_str().map(str::to_lowercase),
+        _ => None,
+    };
+    host.is_some_and(|host| allowed_hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(&host)))
+}
+
+/// Performs an HTTP request for a plugin, if its manifest allows the host
+fn http_request(state: &HostState, request: &[u8]) -> JsonValue {
+    let request: HttpRequest = match serde_json::from_slice(request) {
+        Ok(request) => request,
+        Err(e) => return json!({ "error": format!("Invalid request: {}", e) }),
+    };
+    if !host_allowed(&state.allowed_hosts, &request.url) {
+        return json!({ "error": PluginError::Denied(format!("reach {}", request.url)).to_string() });
+    }
+
+    let mut call = state.agent.request(&request.method, &request.url);
+    for (name, value) in &request.headers {
+        call = call.set(name, value);
+    }
+    let response = match &request.body {
+        Some(body) => call.send_string(body),
+        None => call.call(),
+    };
+
+    // Error statuses are answers too; the plugin decides what they mean
+    let response = match response {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(e) => return json!({ "error": e.to_string() }),
+    };
+    let status = response.status();
+    let mut body = String::new();
+    if let Err(e) = response.into_reader().take(MAX_MESSAGE_BYTES as u64).read_to_string(&mut body) {
+        return json!({ "error": e.to_string() });
+    }
+    json!({ "ok": HttpResponse { status, body } })
+}
+
+/// Copies a reply into the plugin's memory through its `alloc`
+fn write_reply(caller: &mut Caller<'_, HostState>, reply: &JsonValue) -> wasmtime::Result<i64> {
+    let bytes = reply.to_string().into_bytes();
+    let memory = caller.get_export("memory").and_then(|export| export.into_memory())
+        .ok_or_else(|| wasmtime::Error::msg("Plugin exports no memory"))?;
+    let alloc = caller.get_export("alloc").and_then(|export| export.into_func())
+        .ok_or_else(|| wasmtime::Error::msg("Plugin exports no alloc"))?
+        .typed::<i32, i32>(&caller)?;
+
+    let ptr = alloc.call(&mut *caller, bytes.len() as i32)?;
+    memory.write(&mut *caller, ptr as usize, &bytes)?;
+    Ok(((ptr as u32 as i64) << 32) | bytes.len() as i64)
+}
+
+/// Registers the host functions plugins may import
+fn link_host(linker: &mut Linker<HostState>) -> Result<()> {
+    linker.func_wrap("host", "http_request", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> wasmtime::Result<i64> {
+        let memory = caller.get_export("memory").and_then(|export| export.into_memory())
+            .ok_or_else(|| wasmtime::Error::msg("Plugin exports no memory"))?;
+        let request = read_bytes(&memory, &caller, ptr as usize, len as usize)
+            .map_err(|e| wasmtime::Error::msg(e.to_string()))?;
+        let reply = http_request(caller.data(), &request);
+        write_reply(&mut caller, &reply)
+    })?;
+
+    linker.func_wrap("host", "log", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+        let memory = caller.get_export("memory").and_then(|export| export.into_memory())
+            .ok_or_else(|| wasmtime::Error::msg("Plugin exports no memory"))?;
+        let message = read_bytes(&memory, &caller, ptr as usize, len.min(4096) as usize)
+            .map_err(|e| wasmtime::Error::msg(e.to_string()))?;
+        let state = caller.data_mut();
+        state.log_limit.log(&state.plugin_id, &String::from_utf8_lossy(&message));
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+impl WasmPlugin {
+    /// Compiles and instantiates a plugin module
+    pub fn load(manifest: &PluginManifest, module_path: &Path) -> Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::from_file(&engine, module_path)?;
+
+        let mut linker = Linker::new(&engine);
+        link_host(&mut linker)?;
+
+        let state = HostState {
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).instances(1).build(),
+            plugin_id: manifest.id.clone(),
+            allowed_hosts: manifest.allowed_hosts.iter().map(|host| host.to_lowercase()).collect(),
+            agent: ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).redirects(0).build(),
+            log_limit: LogLimit { window_start: Instant::now(), logged: 0, dropped: 0 },
+        };
+        let mut store = Store::new(&engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(FUEL_PER_CALL)?;
+
+        let instance = linker.instantiate(&mut store, &module)?;
+        let memory = instance.get_memory(&mut store, "memory")
+            .ok_or_else(|| PluginError::Wasm("Module exports no memory".to_string()))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+
+        Ok(WasmPlugin {
+            id: manifest.id.clone(),
+            loaded: Mutex::new(Loaded { store, memory, alloc, instance }),
+        })
+    }
+
+    /// Calls an exported function with a JSON request and decodes its reply
+    pub fn call<T: DeserializeOwned>(&self, function: &str, request: &impl Serialize) -> Result<T> {
+        let request = serde_json::to_vec(request)
+            .map_err(|e| PluginError::Plugin(format!("Failed to encode request: {}", e)))?;
+
+        let mut guard = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        let loaded = &mut *guard;
+        loaded.store.set_fuel(FUEL_PER_CALL)?;
+
+        let func = loaded.instance.get_typed_func::<(i32, i32), i64>(&mut loaded.store, function)
+            .map_err(|_| PluginError::Plugin(format!("Plugin '{}' does not export {}", self.id, function)))?;
+        let ptr = loaded.alloc.call(&mut loaded.store, request.len() as i32)?;
+        loaded.memory.write(&mut loaded.store, ptr as usize, &request)
+            .map_err(|e| PluginError::Wasm(e.to_string()))?;
+
+        let (reply_ptr, reply_len) = unpack(func.call(&mut loaded.store, (ptr, request.len() as i32))?);
+        let reply = read_bytes(&loaded.memory, &loaded.store, reply_ptr, reply_len)?;
+
+        let reply: JsonValue = serde_json::from_slice(&reply)
+            .map_err(|e| PluginError::Plugin(format!("Reply is not JSON: {}", e)))?;
+        if let Some(error) = reply.get("error") {
+            return Err(PluginError::Plugin(error.as_str().map(str::to_string).unwrap_or_else(|| error.to_string())));
+        }
+        let ok = reply.get("ok").cloned()
+            .ok_or_else(|| PluginError::Plugin("Reply has neither 'ok' nor 'error'".to_string()))?;
+        serde_json::from_value(ok)
+            .map_err(|e| PluginError::Plugin(format!("Unexpected reply: {}", e)))
+    }
+}
@@ -0,0 +1,43 @@
+// Privacy Error Handling
+// Defines error types for privacy rules
+
+use std::fmt;
+use std::error::Error;
+
+use crate::db::DatabaseError;
+
+/// Represents errors that can occur while loading or applying privacy rules
+#[derive(Debug)]
+pub enum PrivacyError {
+    /// Reading or changing the history failed
+    Database(DatabaseError),
+    /// A rule is malformed, e.g. an invalid URL pattern
+    InvalidRule(String),
+}
+
+impl fmt::Display for PrivacyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrivacyError::Database(err) => write!(f, "Database error: {}", err),
+            PrivacyError::InvalidRule(msg) => write!(f, "Invalid privacy rule: {}", msg),
+        }
+    }
+}
+
+impl Error for PrivacyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PrivacyError::Database(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DatabaseError> for PrivacyError {
+    fn from(err: DatabaseError) -> Self {
+        PrivacyError::Database(err)
+    }
+}
+
+/// Result type for privacy operations
+pub type Result<T> = std::result::Result<T, PrivacyError>;
@@ -0,0 +1,176 @@
+// Privacy Module
+// Exclusion and redaction rules keeping sensitive browsing out of the database
+
+// Module organization:
+// - redact.rs: Applying the rules to already imported history
+// - error.rs: Error handling
+
+pub mod redact;
+pub mod error;
+
+pub use error::{PrivacyError, Result};
+
+use std::collections::HashSet;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use url::Url as UrlParser;
+
+use crate::db::settings::{get_setting, set_setting};
+use crate::db::DatabaseConnection;
+use crate::enrichment::category::{classify_url, domain_matches, Category};
+use crate::extractor::RawHistoryData;
+
+/// Settings key of the privacy rules
+pub const PRIVACY_SETTING: &str = "privacy";
+
+/// URLs kept out of the database, or stored without their query string
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivacyRules {
+    /// Domains excluded along with their subdomains
+    pub excluded_domains: Vec<String>,
+    /// Regular expressions matched against the full URL
+    pub excluded_url_patterns: Vec<String>,
+    /// Categories excluded, as assigned by the domain rules
+    pub excluded_categories: Vec<Category>,
+    /// Domains whose URLs are kept without query string and fragment
+    pub redacted_domains: Vec<String>,
+    /// Categories whose URLs are kept without query string and fragment
+    pub redacted_categories: Vec<Category>,
+}
+
+/// Privacy rules compiled for matching
+pub struct PrivacyFilter {
+    domains: Vec<String>,
+    patterns: Vec<Regex>,
+    categories: HashSet<Category>,
+    redacted_domains: Vec<String>,
+    redacted_categories: HashSet<Category>,
+}
+
+/// Number of URLs and visits dropped or redacted by the privacy rules
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ExcludedCounts {
+    /// URLs dropped
+    pub urls: usize,
+    /// Visits dropped
+    pub visits: usize,
+    /// URLs kept without their query string
+    pub redacted: usize,
+}
+
+/// Lowercases domain rules and drops blank ones
+fn domain_rules(domains: &[String]) -> Vec<String> {
+    domains.iter()
+        .map(|domain| domain.trim().trim_start_matches("www.").to_lowercase())
+        .filter(|domain| !domain.is_empty())
+        .collect()
+}
+
+impl PrivacyFilter {
+    /// Compiles the rules, failing on an invalid URL pattern
+    pub fn new(rules: &PrivacyRules) -> Result<Self> {
+        let patterns = rules.excluded_url_patterns.iter()
+            .map(|pattern| Regex::new(pattern)
+                .map_err(|e| PrivacyError::InvalidRule(format!("{}: {}", pattern, e))))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(PrivacyFilter {
+            domains: domain_rules(&rules.excluded_domains),
+            patterns,
+            categories: rules.excluded_categories.iter().copied().collect(),
+            redacted_domains: domain_rules(&rules.redacted_domains),
+            redacted_categories: rules.redacted_categories.iter().copied().collect(),
+        })
+    }
+
+    /// Returns true if no rule is set
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty() && self.patterns.is_empty() && self.categories.is_empty()
+            && self.redacted_domains.is_empty() && self.redacted_categories.is_empty()
+    }
+
+    /// Returns true if the URL matches any rule
+    pub fn is_excluded(&self, url: &str, domain: &str) -> bool {
+        let domain = domain.trim_start_matches("www.").to_lowercase();
+
+        self.domains.iter().any(|rule| domain_matches(&domain, rule))
+            || self.patterns.iter().any(|pattern| pattern.is_match(url))
+            || (!self.categories.is_empty()
+                && classify_url(url, &domain).is_some_and(|category| self.categories.contains(&category)))
+    }
+
+    /// Returns the URL without query string and fragment if a redaction rule
+    /// matches and there is something to strip
+    pub fn redact(&self, url: &str, domain: &str) -> Option<String> {
+        let domain = domain.trim_start_matches("www.").to_lowercase();
+        let matches = self.redacted_domains.iter().any(|rule| domain_matches(&domain, rule))
+            || (!self.redacted_categories.is_empty()
+                && classify_url(url, &domain).is_some_and(|category| self.redacted_categories.contains(&category)));
+        if !matches {
+            return None;
+        }
+
+        let mut parsed = UrlParser::parse(url).ok()?;
+        if parsed.query().is_none() && parsed.fragment().is_none() {
+            return None;
+        }
+        parsed.set_query(None);
+        parsed.set_fragment(None);
+        Some(parsed.to_string())
+    }
+
+    /// Drops the excluded URLs and their visits from extracted history, and
+    /// strips the redacted ones, before it is written to the database
+    pub fn apply(&self, data: &mut RawHistoryData) -> ExcludedCounts {
+        if self.is_empty() {
+            return ExcludedCounts::default();
+        }
+
+        let urls_before = data.urls.len();
+        let visits_before = data.visits.len();
+
+        let mut excluded = HashSet::new();
+        data.urls.retain(|url| {
+            let keep = !self.is_excluded(&url.url, &url.domain);
+            if !keep {
+                excluded.insert(url.id);
+            }
+            keep
+        });
+        data.visits.retain(|visit| !excluded.contains(&visit.url_id));
+
+        // Stripped URLs that collide are folded into one record on insert
+        let mut redacted = 0;
+        for url in &mut data.urls {
+            if let Some(stripped) = self.redact(&url.url, &url.domain) {
+                url.url = stripped;
+                redacted += 1;
+            }
+        }
+
+        ExcludedCounts {
+            urls: urls_before - data.urls.len(),
+            visits: visits_before - data.visits.len(),
+            redacted,
+        }
+    }
+}
+
+/// Loads the privacy rules, falling back to none
+pub fn get_privacy_rules(conn: &DatabaseConnection) -> Result<PrivacyRules> {
+    let rules = conn.with_connection(|c| get_setting(c, PRIVACY_SETTING))?;
+    Ok(rules.unwrap_or_default())
+}
+
+/// Validates and stores the privacy rules
+pub fn set_privacy_rules(conn: &DatabaseConnection, rules: &PrivacyRules) -> Result<()> {
+    PrivacyFilter::new(rules)?;
+    Ok(conn.with_connection(|c| set_setting(c, PRIVACY_SETTING, rules))?)
+}
+
+/// Loads and compiles the stored privacy rules
+pub fn load_filter(conn: &DatabaseConnection) -> Result<PrivacyFilter> {
+    PrivacyFilter::new(&get_privacy_rules(conn)?)
+}
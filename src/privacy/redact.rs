@@ -0,0 +1,87 @@
+// Privacy - Redaction
+// Applies the privacy rules to history imported before they were set
+
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+
+use crate::db::merge::merge_url_records;
+use crate::db::{maintenance, DatabaseConnection};
+use super::error::Result;
+use super::load_filter;
+
+/// What applying the privacy rules changed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PrivacyReport {
+    /// URLs deleted by the exclusion rules
+    pub urls_deleted: usize,
+    /// Visits deleted with them
+    pub visits_deleted: usize,
+    /// URLs whose query string and fragment were stripped
+    pub urls_redacted: usize,
+    /// Redacted URLs folded into an existing record with the stripped address
+    pub urls_merged: usize,
+}
+
+/// Runs the exclusion and redaction rules against the stored history.
+/// Excluded URLs are purged securely; redacted URLs lose their query string
+/// and fragment, merging into the stripped URL when it is already stored.
+/// The database file is vacuumed afterwards so the old values are gone.
+pub fn apply_privacy_rules(conn: &DatabaseConnection) -> Result<PrivacyReport> {
+    let filter = load_filter(conn)?;
+    let mut report = PrivacyReport::default();
+    if filter.is_empty() {
+        return Ok(report);
+    }
+
+    let urls: Vec<(String, String, String)> = conn.with_connection(|c| {
+        let mut stmt = c.prepare("SELECT id, url, domain FROM url")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    let mut excluded = Vec::new();
+    let mut redactions = Vec::new();
+    for (id, url, domain) in urls {
+        if filter.is_excluded(&url, &domain) {
+            excluded.push(id);
+        } else if let Some(stripped) = filter.redact(&url, &domain) {
+            redactions.push((id, url, stripped));
+        }
+    }
+
+    conn.transaction(|tx| {
+        for (id, url, stripped) in &redactions {
+            let existing: Option<String> = tx.query_row(
+                "SELECT id FROM url WHERE url = ? AND id != ?",
+                params![stripped, id],
+                |row| row.get(0),
+            ).optional()?;
+
+            match existing {
+                Some(target_id) => {
+                    merge_url_records(tx, id, &target_id)?;
+                    report.urls_merged += 1;
+                },
+                None => {
+                    tx.execute("UPDATE url SET url = ? WHERE id = ?", params![stripped, id])?;
+                },
+            }
+
+            // Deleted visits stay deleted under the new address
+            tx.execute("UPDATE OR IGNORE visit_tombstone SET url = ? WHERE url = ?", params![stripped, url])?;
+            tx.execute("DELETE FROM visit_tombstone WHERE url = ?", [url])?;
+            report.urls_redacted += 1;
+        }
+        Ok(())
+    })?;
+
+    if !excluded.is_empty() {
+        let purge = maintenance::secure_purge_urls(conn, &excluded)?;
+        report.urls_deleted = purge.urls_removed;
+        report.visits_deleted = purge.visits_removed;
+    } else if !redactions.is_empty() {
+        maintenance::compact_database(conn)?;
+    }
+
+    Ok(report)
+}
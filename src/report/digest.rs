@@ -0,0 +1,293 @@
+// Report - Email Digest
+// A self-contained HTML digest of a period, with the top reads, topics new to
+// the history and a few stats, styled inline so it survives being emailed
+
+use std::path::Path;
+
+use base64::Engine;
+use chrono::Duration;
+use rusqlite::OptionalExtension;
+
+use crate::db::{self, DatabaseConnection};
+use crate::db::query::QueryBuilder;
+use crate::deeplink::DeepLink;
+use super::error::Result;
+use super::render::escape_html;
+use super::ReportPeriod;
+
+/// Number of pages listed as top reads
+const DIGEST_TOP_READS: usize = 8;
+
+/// Number of new topics and of top domains listed
+const DIGEST_SECTION_SIZE: usize = 5;
+
+/// Largest thumbnail inlined; bigger ones are left out to keep the email small
+const MAX_INLINE_IMAGE_BYTES: u64 = 150 * 1024;
+
+/// A page in the digest
+pub struct DigestPage {
+    /// URL id, linked back to the app
+    pub url_id: String,
+    /// The URL
+    pub url: String,
+    /// Page title, if known
+    pub title: Option<String>,
+    /// Domain of the URL
+    pub domain: String,
+    /// Visits in the period
+    pub visit_count: usize,
+    /// Enrichment summary, if any
+    pub summary: Option<String>,
+    /// Thumbnail as a `data:` URI
+    pub thumbnail: Option<String>,
+}
+
+/// Everything a digest contains
+pub struct DigestData {
+    /// Period covered by the digest
+    pub period: ReportPeriod,
+    /// Visits in the period
+    pub visit_count: usize,
+    /// Visits in the period of the same length before it
+    pub previous_visit_count: usize,
+    /// Distinct pages visited
+    pub page_count: usize,
+    /// Distinct domains visited
+    pub domain_count: usize,
+    /// Most visited pages
+    pub top_reads: Vec<DigestPage>,
+    /// Topic clusters first visited in the period, with their visits
+    pub new_topics: Vec<(String, usize)>,
+    /// Most visited domains
+    pub top_domains: Vec<(String, usize)>,
+}
+
+/// Reads a stored thumbnail into a `data:` URI, if it is small enough
+fn inline_image(path: &str) -> Option<String> {
+    let path = Path::new(path);
+    if std::fs::metadata(path).ok()?.len() > MAX_INLINE_IMAGE_BYTES {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    Some(format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(bytes)))
+}
+
+/// Gathers the digest of the period
+pub fn build_digest_data(conn: &DatabaseConnection, period: ReportPeriod) -> Result<DigestData> {
+    let (start, end) = (Some(period.start), Some(period.end));
+    let previous_start = period.start - (period.end - period.start);
+
+    let visit_count: usize = db::analytics::get_hourly_distribution(conn, start, end)?.iter().sum();
+    let previous_visit_count: usize = db::analytics::get_hourly_distribution(
+        conn,
+        Some(previous_start),
+        Some(period.start - Duration::seconds(1)),
+    )?.iter().sum();
+    let top_domains = db::analytics::get_top_domains(conn, start, end, DIGEST_SECTION_SIZE)?;
+    let top_pages = db::analytics::get_top_pages(conn, &db::analytics::TopPagesParams {
+        start_date: start,
+        end_date: end,
+        domain: None,
+        limit: Some(DIGEST_TOP_READS),
+    })?;
+
+    let (page_count, domain_count, new_topics, details) = conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT COUNT(DISTINCT v.url_id), COUNT(DISTINCT u.domain)
+             FROM visit v
+             JOIN url u ON u.id = v.url_id"
+        );
+        query.date_range("v.visited_at", start, end);
+        let counts = query.fetch_all(c, |row| Ok((row.get::<_, i64>(0)? as usize, row.get::<_, i64>(1)? as usize)))?;
+        let (page_count, domain_count) = counts.into_iter().next().unwrap_or_default();
+
+        let mut stmt = c.prepare(
+            "SELECT m.topic_cluster, COUNT(*)
+             FROM visit v
+             JOIN metadata m ON m.url_id = v.url_id
+             WHERE v.visited_at >= ?1 AND v.visited_at <= ?2
+               AND m.topic_cluster IS NOT NULL AND m.topic_cluster <> ''
+               AND NOT EXISTS (
+                   SELECT 1 FROM visit pv
+                   JOIN metadata pm ON pm.url_id = pv.url_id
+                   WHERE pm.topic_cluster = m.topic_cluster AND pv.visited_at < ?1
+               )
+             GROUP BY m.topic_cluster
+             ORDER BY COUNT(*) DESC, m.topic_cluster
+             LIMIT ?3"
+        )?;
+        let rows = stmt.query_map(
+            rusqlite::params![period.start.timestamp(), period.end.timestamp(), DIGEST_SECTION_SIZE as i64],
+            |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)),
+        )?;
+        let new_topics = rows.collect::<rusqlite::Result<Vec<(String, usize)>>>()?;
+
+        let mut stmt = c.prepare("SELECT summary, thumbnail_path FROM metadata WHERE url_id = ?")?;
+        let mut details = Vec::with_capacity(top_pages.len());
+        for page in &top_pages {
+            let row: Option<(Option<String>, Option<String>)> = stmt
+                .query_row([page.url.id.to_string()], |row| Ok((row.get(0)?, row.get(1)?)))
+                .optional()?;
+            details.push(row.unwrap_or((None, None)));
+        }
+
+        Ok((page_count, domain_count, new_topics, details))
+    })?;
+
+    let top_reads = top_pages.into_iter().zip(details)
+        .map(|(page, (summary, thumbnail_path))| DigestPage {
+            url_id: page.url.id.to_string(),
+            url: page.url.url,
+            title: page.url.title,
+            domain: page.url.domain,
+            visit_count: page.visit_count,
+            summary: summary.filter(|summary| !summary.trim().is_empty()),
+            thumbnail: thumbnail_path.as_deref().and_then(inline_image),
+        })
+        .collect();
+
+    Ok(DigestData {
+        period,
+        visit_count,
+        previous_visit_count,
+        page_count,
+        domain_count,
+        top_reads,
+        new_topics,
+        top_domains,
+    })
+}
+
+/// Link opening a view of the app
+fn app_link(link: DeepLink) -> String {
+    escape_html(&link.to_link())
+}
+
+/// Change in visits against the previous period, e.g. "+12% on the period before"
+fn visit_change(data: &DigestData) -> Option<String> {
+    if data.previous_visit_count == 0 {
+        return None;
+    }
+    let change = (data.visit_count as f64 / data.previous_visit_count as f64 - 1.0) * 100.0;
+    Some(format!("{:+.0}% on the period before", change))
+}
+
+/// Renders the digest as an HTML email; styles are inline because mail
+/// clients drop style sheets
+pub fn to_digest_html(data: &DigestData) -> String {
+    const SECTION: &str = "margin: 28px 0 8px; font-size: 17px; color: #222;";
+    const MUTED: &str = "color: #777; font-size: 13px;";
+    const LINK: &str = "color: #0a5cc2; text-decoration: none;";
+
+    let title = format!(
+        "{} digest: {} to {}",
+        data.period.label,
+        data.period.start.format("%Y-%m-%d"),
+        data.period.end.format("%Y-%m-%d"),
+    );
+    let mut body = String::new();
+
+    body.push_str(&format!(
+        "<h1 style=\"margin: 0 0 4px; font-size: 22px; color: #222;\">{}</h1>\n\
+         <p style=\"margin: 0; {}\"><a href=\"{}\" style=\"{}\">Open the timeline</a></p>\n",
+        escape_html(&title),
+        MUTED,
+        app_link(DeepLink::Timeline { date: data.period.end.date_naive() }),
+        LINK,
+    ));
+
+    let stats = [
+        (data.visit_count.to_string(), "visits"),
+        (data.page_count.to_string(), "pages"),
+        (data.domain_count.to_string(), "sites"),
+    ];
+    body.push_str("<table role=\"presentation\" width=\"100%\" style=\"margin-top: 20px; border-collapse: collapse;\"><tr>\n");
+    for (value, label) in &stats {
+        body.push_str(&format!(
+            "<td style=\"padding: 12px; background: #f3f6fa; text-align: center;\">\
+             <div style=\"font-size: 22px; font-weight: bold; color: #222;\">{}</div>\
+             <div style=\"{}\">{}</div></td>\n",
+            value, MUTED, label,
+        ));
+    }
+    body.push_str("</tr></table>\n");
+    if let Some(change) = visit_change(data) {
+        body.push_str(&format!("<p style=\"margin: 6px 0 0; {}\">Visits {}</p>\n", MUTED, escape_html(&change)));
+    }
+
+    body.push_str(&format!("<h2 style=\"{}\">Top reads</h2>\n", SECTION));
+    if data.top_reads.is_empty() {
+        body.push_str(&format!("<p style=\"{}\">No visits in this period.</p>\n", MUTED));
+    }
+    body.push_str("<table role=\"presentation\" width=\"100%\" style=\"border-collapse: collapse;\">\n");
+    for page in &data.top_reads {
+        let label = page.title.as_deref().map(str::trim).filter(|title| !title.is_empty()).unwrap_or(&page.url);
+        let image = page.thumbnail.as_deref()
+            .map(|uri| format!("<img src=\"{}\" width=\"96\" alt=\"\" style=\"display: block; border-radius: 4px;\">", uri))
+            .unwrap_or_default();
+        let summary = page.summary.as_deref()
+            .map(|summary| format!("<div style=\"margin-top: 4px; font-size: 14px; color: #444;\">{}</div>", escape_html(summary)))
+            .unwrap_or_default();
+        body.push_str(&format!(
+            "<tr><td width=\"104\" valign=\"top\" style=\"padding: 10px 8px 10px 0;\">{}</td>\
+             <td valign=\"top\" style=\"padding: 10px 0; border-bottom: 1px solid #eee;\">\
+             <a href=\"{}\" style=\"{} font-size: 15px;\">{}</a>{}\
+             <div style=\"margin-top: 4px; {}\">{} &middot; {} visits &middot; <a href=\"{}\" style=\"{}\">details</a></div>\
+             </td></tr>\n",
+            image,
+            escape_html(&page.url),
+            LINK,
+            escape_html(label),
+            summary,
+            MUTED,
+            escape_html(&page.domain),
+            page.visit_count,
+            app_link(DeepLink::Url { id: Some(page.url_id.clone()), url: None }),
+            LINK,
+        ));
+    }
+    body.push_str("</table>\n");
+
+    body.push_str(&format!("<h2 style=\"{}\">New topics</h2>\n", SECTION));
+    if data.new_topics.is_empty() {
+        body.push_str(&format!("<p style=\"{}\">No new topics this period.</p>\n", MUTED));
+    }
+    for (topic, visits) in &data.new_topics {
+        body.push_str(&format!(
+            "<p style=\"margin: 4px 0;\"><a href=\"{}\" style=\"{}\">{}</a> <span style=\"{}\">{} visits</span></p>\n",
+            app_link(DeepLink::Search { query: topic.clone(), domain: None }),
+            LINK,
+            escape_html(topic),
+            MUTED,
+            visits,
+        ));
+    }
+
+    body.push_str(&format!("<h2 style=\"{}\">Top sites</h2>\n", SECTION));
+    for (domain, visits) in &data.top_domains {
+        body.push_str(&format!(
+            "<p style=\"margin: 4px 0;\"><a href=\"{}\" style=\"{}\">{}</a> <span style=\"{}\">{} visits</span></p>\n",
+            app_link(DeepLink::Search { query: String::new(), domain: Some(domain.clone()) }),
+            LINK,
+            escape_html(domain),
+            MUTED,
+            visits,
+        ));
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n</head>\n\
+         <body style=\"margin: 0; padding: 0; background: #ffffff;\">\n\
+         <div style=\"max-width: 600px; margin: 0 auto; padding: 24px 16px; \
+         font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; color: #222;\">\n{}</div>\n</body>\n</html>\n",
+        escape_html(&title),
+        body,
+    )
+}
+
+/// Builds the digest of the period as a single HTML document
+pub fn generate_digest(conn: &DatabaseConnection, period: ReportPeriod) -> Result<String> {
+    let data = build_digest_data(conn, period)?;
+    Ok(to_digest_html(&data))
+}
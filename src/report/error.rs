@@ -0,0 +1,57 @@
+// Report Error Handling
+// Defines error types for report generation
+
+use std::fmt;
+use std::error::Error;
+use std::io;
+
+use crate::db::DatabaseError;
+
+/// Represents errors that can occur while generating a report
+#[derive(Debug)]
+pub enum ReportError {
+    /// The underlying database query failed
+    Database(DatabaseError),
+    /// The report file could not be written
+    Io(io::Error),
+    /// The requested period is not supported
+    InvalidPeriod(String),
+    /// A report template or its parameters are invalid
+    InvalidTemplate(String),
+}
+
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReportError::Database(err) => write!(f, "Database error: {}", err),
+            ReportError::Io(err) => write!(f, "I/O error: {}", err),
+            ReportError::InvalidPeriod(period) => write!(f, "Invalid report period: {}", period),
+            ReportError::InvalidTemplate(msg) => write!(f, "Invalid report template: {}", msg),
+        }
+    }
+}
+
+impl Error for ReportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReportError::Database(err) => Some(err),
+            ReportError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DatabaseError> for ReportError {
+    fn from(err: DatabaseError) -> Self {
+        ReportError::Database(err)
+    }
+}
+
+impl From<io::Error> for ReportError {
+    fn from(err: io::Error) -> Self {
+        ReportError::Io(err)
+    }
+}
+
+/// Result type for report operations
+pub type Result<T> = std::result::Result<T, ReportError>;
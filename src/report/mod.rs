@@ -0,0 +1,202 @@
+// Report Module
+// Builds periodic summaries of browsing history and renders them to files
+
+// Module organization:
+// - render.rs: Markdown and HTML rendering
+// - digest.rs: Self-contained HTML digests for email
+// - templates.rs: Saved report queries, their scheduled runs and outputs
+// - error.rs: Error handling
+
+pub mod render;
+pub mod digest;
+pub mod templates;
+pub mod error;
+
+pub use error::{ReportError, Result};
+
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use url::Url as UrlParser;
+
+use crate::db::{self, DatabaseConnection, UrlWithVisits};
+use crate::db::query::QueryBuilder;
+
+/// Number of entries listed in each report section
+const REPORT_SECTION_SIZE: usize = 10;
+
+/// Search engines whose result pages are recognised, with the query parameter they use
+const SEARCH_ENGINES: &[(&str, &str)] = &[
+    ("google.", "q"),
+    ("bing.com", "q"),
+    ("duckduckgo.com", "q"),
+    ("search.yahoo.com", "p"),
+    ("ecosia.org", "q"),
+    ("kagi.com", "q"),
+    ("search.brave.com", "q"),
+];
+
+/// The time span a report covers
+#[derive(Debug, Clone)]
+pub struct ReportPeriod {
+    /// Human readable name of the period (e.g. "Weekly")
+    pub label: String,
+    /// Start of the period (inclusive)
+    pub start: DateTime<Utc>,
+    /// End of the period (inclusive)
+    pub end: DateTime<Utc>,
+}
+
+impl ReportPeriod {
+    /// Creates the period ending at `now` for "day", "week" or "month"
+    pub fn ending_at(kind: &str, now: DateTime<Utc>) -> Result<Self> {
+        let (label, days) = match kind {
+            "day" => ("Daily", 1),
+            "week" => ("Weekly", 7),
+            "month" => ("Monthly", 30),
+            other => return Err(ReportError::InvalidPeriod(other.to_string())),
+        };
+        
+        Ok(Self {
+            label: label.to_string(),
+            start: now - Duration::days(days),
+            end: now,
+        })
+    }
+}
+
+/// Output format of a generated report
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    /// Markdown document
+    Markdown,
+    /// Self-contained HTML page
+    Html,
+}
+
+/// A search performed on a known search engine
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    /// Search engine domain
+    pub engine: String,
+    /// The search terms
+    pub query: String,
+    /// How many times this search was run in the period
+    pub count: usize,
+}
+
+/// Everything a periodic report contains
+pub struct ReportData {
+    /// Period covered by the report
+    pub period: ReportPeriod,
+    /// Total visits in the period
+    pub visit_count: usize,
+    /// Most visited domains
+    pub top_domains: Vec<(String, usize)>,
+    /// Most visited pages
+    pub top_pages: Vec<UrlWithVisits>,
+    /// Pages visited for the first time in the period
+    pub new_discoveries: Vec<UrlWithVisits>,
+    /// Searches run in the period
+    pub search_queries: Vec<SearchQuery>,
+    /// Visits per hour of day (UTC)
+    pub hourly_distribution: Vec<usize>,
+}
+
+/// Extracts the search engine and query from a search results URL
+pub fn extract_search_query(url: &str) -> Option<(String, String)> {
+    let parsed = UrlParser::parse(url).ok()?;
+    let host = parsed.host_str()?.trim_start_matches("www.").to_string();
+    
+    let (_, param) = SEARCH_ENGINES.iter()
+        .find(|(engine, _)| host.starts_with(engine) || host.ends_with(engine))?;
+    
+    let query = parsed.query_pairs()
+        .find(|(key, _)| key == param)
+        .map(|(_, value)| value.trim().to_string())?;
+    
+    if query.is_empty() {
+        None
+    } else {
+        Some((host, query))
+    }
+}
+
+/// Collects the searches run within the period, most frequent first
+fn collect_search_queries(conn: &DatabaseConnection, period: &ReportPeriod) -> Result<Vec<SearchQuery>> {
+    let rows = conn.with_connection(|c| {
+        let mut query = QueryBuilder::new(
+            "SELECT u.url
+             FROM visit v
+             JOIN url u ON v.url_id = u.id"
+        );
+        query.date_range("v.visited_at", Some(period.start), Some(period.end))
+            .condition("(u.url LIKE '%?q=%' OR u.url LIKE '%&q=%' OR u.url LIKE '%?p=%' OR u.url LIKE '%&p=%')");
+        
+        query.fetch_all(c, |row| row.get::<_, String>(0))
+    })?;
+    
+    let mut queries: Vec<SearchQuery> = Vec::new();
+    for url in rows {
+        if let Some((engine, text)) = extract_search_query(&url) {
+            match queries.iter_mut().find(|q| q.engine == engine && q.query == text) {
+                Some(existing) => existing.count += 1,
+                None => queries.push(SearchQuery { engine, query: text, count: 1 }),
+            }
+        }
+    }
+    
+    queries.sort_by(|a, b| b.count.cmp(&a.count).then(a.query.cmp(&b.query)));
+    queries.truncate(REPORT_SECTION_SIZE * 2);
+    
+    Ok(queries)
+}
+
+/// Gathers all data needed for a report over the given period
+pub fn build_report_data(conn: &DatabaseConnection, period: ReportPeriod) -> Result<ReportData> {
+    let start = Some(period.start);
+    let end = Some(period.end);
+    
+    let top_domains = db::analytics::get_top_domains(conn, start, end, REPORT_SECTION_SIZE)?;
+    let top_pages = db::analytics::get_top_pages(conn, &db::analytics::TopPagesParams {
+        start_date: start,
+        end_date: end,
+        domain: None,
+        limit: Some(REPORT_SECTION_SIZE),
+    })?;
+    let new_discoveries = db::analytics::get_new_discoveries(conn, period.start, period.end, REPORT_SECTION_SIZE)?;
+    let hourly_distribution = db::analytics::get_hourly_distribution(conn, start, end)?;
+    let search_queries = collect_search_queries(conn, &period)?;
+    
+    Ok(ReportData {
+        visit_count: hourly_distribution.iter().sum(),
+        period,
+        top_domains,
+        top_pages,
+        new_discoveries,
+        search_queries,
+        hourly_distribution,
+    })
+}
+
+/// Builds a report for the period and writes it to `path` in the given format
+pub fn generate_report(
+    conn: &DatabaseConnection,
+    period: ReportPeriod,
+    format: ReportFormat,
+    path: &Path,
+) -> Result<()> {
+    let data = build_report_data(conn, period)?;
+    
+    let contents = match format {
+        ReportFormat::Markdown => render::to_markdown(&data),
+        ReportFormat::Html => render::to_html(&data),
+    };
+    
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, contents)?;
+    
+    Ok(())
+}
@@ -0,0 +1,170 @@
+// Report Rendering
+// Turns gathered report data into Markdown or HTML documents
+
+use super::ReportData;
+
+/// Width of the text bar chart used for the time distribution
+const BAR_WIDTH: usize = 30;
+
+/// Escapes characters that have a meaning in HTML
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Escapes characters that would break Markdown link text
+fn escape_markdown(text: &str) -> String {
+    text.replace('[', "\\[").replace(']', "\\]")
+}
+
+/// Percent-encodes the characters that would end a Markdown link target early
+fn markdown_url(url: &str) -> String {
+    let mut out = String::with_capacity(url.len());
+    for c in url.chars() {
+        match c {
+            '(' | ')' | ' ' | '\t' | '\n' | '\r' | '\x0C' => out.push_str(&format!("%{:02X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Returns the report title
+fn title(data: &ReportData) -> String {
+    format!(
+        "{} Browsing Report: {} to {}",
+        data.period.label,
+        data.period.start.format("%Y-%m-%d"),
+        data.period.end.format("%Y-%m-%d"),
+    )
+}
+
+/// Renders the report as Markdown
+pub fn to_markdown(data: &ReportData) -> String {
+    let mut out = String::new();
+    
+    out.push_str(&format!("# {}\n\n", title(data)));
+    out.push_str(&format!("Total visits: **{}**\n\n", data.visit_count));
+    
+    out.push_str("## Top Domains\n\n");
+    if data.top_domains.is_empty() {
+        out.push_str("_No visits in this period._\n");
+    }
+    for (i, (domain, count)) in data.top_domains.iter().enumerate() {
+        out.push_str(&format!("{}. {} ({} visits)\n", i + 1, domain, count));
+    }
+    
+    out.push_str("\n## Top Pages\n\n");
+    for (i, page) in data.top_pages.iter().enumerate() {
+        let label = page.url.title.as_deref().unwrap_or(&page.url.url);
+        out.push_str(&format!(
+            "{}. [{}]({}) ({} visits)\n",
+            i + 1,
+            escape_markdown(label),
+            markdown_url(&page.url.url),
+            page.visit_count,
+        ));
+    }
+    
+    out.push_str("\n## New Discoveries\n\n");
+    if data.new_discoveries.is_empty() {
+        out.push_str("_Nothing new this period._\n");
+    }
+    for page in &data.new_discoveries {
+        let label = page.url.title.as_deref().unwrap_or(&page.url.url);
+        out.push_str(&format!("- [{}]({}) — {}\n", escape_markdown(label), markdown_url(&page.url.url), page.url.domain));
+    }
+    
+    out.push_str("\n## Searches\n\n");
+    if data.search_queries.is_empty() {
+        out.push_str("_No searches recorded._\n");
+    }
+    for search in &data.search_queries {
+        out.push_str(&format!("- \"{}\" on {} (×{})\n", search.query, search.engine, search.count));
+    }
+    
+    out.push_str("\n## Time Distribution (UTC)\n\n```\n");
+    let max = data.hourly_distribution.iter().copied().max().unwrap_or(0).max(1);
+    for (hour, count) in data.hourly_distribution.iter().enumerate() {
+        let bar = "█".repeat(count * BAR_WIDTH / max);
+        out.push_str(&format!("{:02}:00 {:<width$} {}\n", hour, bar, count, width = BAR_WIDTH));
+    }
+    out.push_str("```\n");
+    
+    out
+}
+
+/// Renders the report as a self-contained HTML page
+pub fn to_html(data: &ReportData) -> String {
+    let mut body = String::new();
+    
+    body.push_str(&format!("<h1>{}</h1>\n", escape_html(&title(data))));
+    body.push_str(&format!("<p>Total visits: <strong>{}</strong></p>\n", data.visit_count));
+    
+    body.push_str("<h2>Top Domains</h2>\n<ol>\n");
+    for (domain, count) in &data.top_domains {
+        body.push_str(&format!("<li>{} ({} visits)</li>\n", escape_html(domain), count));
+    }
+    body.push_str("</ol>\n");
+    
+    body.push_str("<h2>Top Pages</h2>\n<ol>\n");
+    for page in &data.top_pages {
+        let label = page.url.title.as_deref().unwrap_or(&page.url.url);
+        body.push_str(&format!(
+            "<li><a href=\"{}\">{}</a> ({} visits)</li>\n",
+            escape_html(&page.url.url),
+            escape_html(label),
+            page.visit_count,
+        ));
+    }
+    body.push_str("</ol>\n");
+    
+    body.push_str("<h2>New Discoveries</h2>\n<ul>\n");
+    for page in &data.new_discoveries {
+        let label = page.url.title.as_deref().unwrap_or(&page.url.url);
+        body.push_str(&format!(
+            "<li><a href=\"{}\">{}</a> — {}</li>\n",
+            escape_html(&page.url.url),
+            escape_html(label),
+            escape_html(&page.url.domain),
+        ));
+    }
+    body.push_str("</ul>\n");
+    
+    body.push_str("<h2>Searches</h2>\n<ul>\n");
+    for search in &data.search_queries {
+        body.push_str(&format!(
+            "<li>&ldquo;{}&rdquo; on {} (&times;{})</li>\n",
+            escape_html(&search.query),
+            escape_html(&search.engine),
+            search.count,
+        ));
+    }
+    body.push_str("</ul>\n");
+    
+    body.push_str("<h2>Time Distribution (UTC)</h2>\n<table class=\"hours\">\n");
+    let max = data.hourly_distribution.iter().copied().max().unwrap_or(0).max(1);
+    for (hour, count) in data.hourly_distribution.iter().enumerate() {
+        body.push_str(&format!(
+            "<tr><td>{:02}:00</td><td><div class=\"bar\" style=\"width: {}%\"></div></td><td>{}</td></tr>\n",
+            hour,
+            count * 100 / max,
+            count,
+        ));
+    }
+    body.push_str("</table>\n");
+    
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(&title(data)),
+        "body { font-family: -apple-system, sans-serif; max-width: 760px; margin: 2em auto; color: #222; }
+a { color: #0a5cc2; text-decoration: none; }
+.hours { width: 100%; border-collapse: collapse; font-size: 0.85em; }
+.hours td:nth-child(2) { width: 80%; }
+.bar { background: #0a5cc2; height: 0.8em; }",
+        body,
+    )
+}
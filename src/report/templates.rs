@@ -0,0 +1,572 @@
+// Report Templates
+// Saved, parameterized report queries that run on demand or on a schedule,
+// keeping the output of each run
+//
+// A template is either read-only SQL over the console tables, with named
+// parameters like :since, or the filters of a history search, where a filter
+// given as :name takes that parameter's value.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::db::readonly::{self, ColumnKind, ConsoleColumn, ConsoleResult, CONSOLE_TABLES};
+use crate::db::operations::SearchParams;
+use crate::db::{self, DatabaseConnection, DatabaseError};
+use super::error::{ReportError, Result};
+
+/// Outputs kept per template; older ones are pruned after each run
+const MAX_OUTPUTS_PER_TEMPLATE: usize = 50;
+
+/// Parameter every SQL template can use: the run's time as a Unix timestamp
+const NOW_PARAMETER: &str = "now";
+
+/// What a template runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TemplateQuery {
+    /// A read-only SELECT over the console tables
+    Sql {
+        sql: String,
+    },
+    /// A history search
+    Filters(TemplateFilters),
+}
+
+/// Search filters of a template; text filters given as :name take the
+/// value of that parameter
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateFilters {
+    /// Text to search for
+    pub query: Option<String>,
+    /// Only this domain
+    pub domain: Option<String>,
+    /// Only this category
+    pub category: Option<String>,
+    /// Only URLs with this tag
+    pub tag: Option<String>,
+    /// Only URLs with this keyword
+    pub keyword: Option<String>,
+    /// Only visits in the last N days
+    pub days: Option<u32>,
+}
+
+/// A value asked for when a template runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateParameter {
+    /// Name used in the query, without the colon
+    pub name: String,
+    /// Label shown when asking for the value
+    pub label: Option<String>,
+    /// Value used when none is given; without one the parameter is required
+    pub default: Option<String>,
+}
+
+/// How often a scheduled template runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateSchedule {
+    Daily,
+    Weekly,
+}
+
+impl TemplateSchedule {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TemplateSchedule::Daily => "daily",
+            TemplateSchedule::Weekly => "weekly",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "daily" => Some(TemplateSchedule::Daily),
+            "weekly" => Some(TemplateSchedule::Weekly),
+            _ => None,
+        }
+    }
+
+    /// Time between scheduled runs
+    fn interval(&self) -> Duration {
+        match self {
+            TemplateSchedule::Daily => Duration::days(1),
+            TemplateSchedule::Weekly => Duration::days(7),
+        }
+    }
+}
+
+/// A stored report template
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportTemplate {
+    /// Template identifier
+    pub id: i64,
+    /// Name (unique, case-insensitive)
+    pub name: String,
+    /// What the report shows
+    pub description: Option<String>,
+    /// What the template runs
+    pub query: TemplateQuery,
+    /// Values asked for when it runs
+    pub parameters: Vec<TemplateParameter>,
+    /// Runs on this schedule besides on demand
+    pub schedule: Option<TemplateSchedule>,
+    /// Most rows an output keeps
+    pub max_rows: usize,
+    /// When the template last ran
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// When the template was saved
+    pub created_at: DateTime<Utc>,
+    /// When the template was last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A template as saved from the app; without an id it is created
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReportTemplateInput {
+    /// Template to update
+    pub id: Option<i64>,
+    /// Name (unique, case-insensitive)
+    pub name: String,
+    /// What the report shows
+    pub description: Option<String>,
+    /// What the template runs
+    pub query: TemplateQuery,
+    /// Values asked for when it runs
+    #[serde(default)]
+    pub parameters: Vec<TemplateParameter>,
+    /// Runs on this schedule besides on demand
+    pub schedule: Option<TemplateSchedule>,
+    /// Most rows an output keeps; the console's default when None
+    pub max_rows: Option<usize>,
+}
+
+/// What started a template run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunTrigger {
+    Manual,
+    Schedule,
+}
+
+impl RunTrigger {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RunTrigger::Manual => "manual",
+            RunTrigger::Schedule => "schedule",
+        }
+    }
+}
+
+/// The stored output of one template run
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportOutput {
+    /// Output identifier
+    pub id: i64,
+    /// Template that ran
+    pub template_id: i64,
+    /// "manual" or "schedule"
+    pub trigger: String,
+    /// Parameter values the run used
+    pub parameters: HashMap<String, String>,
+    /// When the run started
+    pub started_at: DateTime<Utc>,
+    /// Time the query took
+    pub elapsed_ms: u64,
+    /// Rows in the result
+    pub row_count: usize,
+    /// The rows; None when the run failed
+    pub result: Option<ConsoleResult>,
+    /// Why the run failed
+    pub error: Option<String>,
+}
+
+/// Columns of a template row
+const SELECT_TEMPLATE: &str =
+    "SELECT id, name, description, query, parameters, schedule, max_rows, last_run_at, created_at, updated_at
+     FROM report_template";
+
+/// Columns of an output row
+const SELECT_OUTPUT: &str =
+    "SELECT id, template_id, trigger, parameters, started_at, elapsed_ms, row_count, result, error
+     FROM report_output";
+
+fn parse_json<T: for<'de> Deserialize<'de>>(json: &str, what: &str) -> db::Result<T> {
+    serde_json::from_str(json).map_err(|e| DatabaseError::Data(format!("Invalid {}: {}", what, e)))
+}
+
+fn to_json<T: Serialize>(value: &T, what: &str) -> db::Result<String> {
+    serde_json::to_string(value).map_err(|e| DatabaseError::Data(format!("Failed to serialize {}: {}", what, e)))
+}
+
+/// Raw columns of a `SELECT_TEMPLATE` row, parsed by `template_from_raw`
+type RawTemplate = (i64, String, Option<String>, String, String, Option<String>, i64, Option<i64>, i64, i64);
+
+fn raw_template(row: &Row<'_>) -> rusqlite::Result<RawTemplate> {
+    Ok((
+        row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?,
+        row.get(5)?, row.get(6)?, row.get(7)?, row.get(8)?, row.get(9)?,
+    ))
+}
+
+fn template_from_raw(raw: RawTemplate) -> db::Result<ReportTemplate> {
+    let (id, name, description, query, parameters, schedule, max_rows, last_run_at, created_at, updated_at) = raw;
+    Ok(ReportTemplate {
+        id,
+        name,
+        description,
+        query: parse_json(&query, "template query")?,
+        parameters: parse_json(&parameters, "template parameters")?,
+        schedule: schedule.as_deref().and_then(TemplateSchedule::parse),
+        max_rows: max_rows.max(1) as usize,
+        last_run_at: last_run_at.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+        created_at: DateTime::from_timestamp(created_at, 0).unwrap_or_default(),
+        updated_at: DateTime::from_timestamp(updated_at, 0).unwrap_or_default(),
+    })
+}
+
+/// Loads a template by id
+fn load_template(c: &Connection, id: i64) -> db::Result<ReportTemplate> {
+    let raw = c.query_row(&format!("{} WHERE id = ?", SELECT_TEMPLATE), [id], raw_template)
+        .optional()?
+        .ok_or_else(|| DatabaseError::NotFound(format!("Report template {} does not exist", id)))?;
+    template_from_raw(raw)
+}
+
+/// Maps a database error on the unique name to a readable message
+fn name_conflict(name: &str, err: rusqlite::Error) -> DatabaseError {
+    match err {
+        rusqlite::Error::SqliteFailure(e, _) if e.code == rusqlite::ErrorCode::ConstraintViolation => {
+            DatabaseError::Data(format!("A report template named '{}' already exists", name))
+        },
+        e => DatabaseError::from(e),
+    }
+}
+
+/// Trims a text value, treating blank as none
+fn trimmed(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+}
+
+/// Checks a template's query and parameters, returning them cleaned up
+fn check_template(input: &ReportTemplateInput) -> Result<(TemplateQuery, Vec<TemplateParameter>)> {
+    let mut parameters = Vec::with_capacity(input.parameters.len());
+    for parameter in &input.parameters {
+        let name = parameter.name.trim().trim_start_matches([':', '@', '$']).to_string();
+        if name.is_empty() || !name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_') {
+            return Err(ReportError::InvalidTemplate(format!("Invalid parameter name '{}'", parameter.name)));
+        }
+        if name == NOW_PARAMETER {
+            return Err(ReportError::InvalidTemplate(format!(":{} is provided by every run", NOW_PARAMETER)));
+        }
+        if parameters.iter().any(|p: &TemplateParameter| p.name == name) {
+            return Err(ReportError::InvalidTemplate(format!("Parameter '{}' is declared twice", name)));
+        }
+        parameters.push(TemplateParameter {
+            name,
+            label: trimmed(&parameter.label),
+            default: parameter.default.clone(),
+        });
+    }
+
+    let query = match &input.query {
+        TemplateQuery::Sql { sql } => TemplateQuery::Sql {
+            sql: readonly::validate_select(sql, CONSOLE_TABLES)?,
+        },
+        TemplateQuery::Filters(filters) => TemplateQuery::Filters(TemplateFilters {
+            query: trimmed(&filters.query),
+            domain: trimmed(&filters.domain),
+            category: trimmed(&filters.category),
+            tag: trimmed(&filters.tag),
+            keyword: trimmed(&filters.keyword),
+            days: filters.days.filter(|days| *days > 0),
+        }),
+    };
+    Ok((query, parameters))
+}
+
+/// Lists every report template, alphabetically
+pub fn list_templates(conn: &DatabaseConnection) -> Result<Vec<ReportTemplate>> {
+    Ok(conn.with_connection(|c| {
+        let mut stmt = c.prepare(&format!("{} ORDER BY name COLLATE NOCASE", SELECT_TEMPLATE))?;
+        let rows = stmt.query_map([], raw_template)?.collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter().map(template_from_raw).collect::<db::Result<Vec<_>>>()
+    })?)
+}
+
+/// Creates or updates a report template
+pub fn save_template(conn: &DatabaseConnection, input: &ReportTemplateInput) -> Result<ReportTemplate> {
+    let name = input.name.trim().to_string();
+    if name.is_empty() {
+        return Err(ReportError::InvalidTemplate("Name cannot be empty".to_string()));
+    }
+    let (query, parameters) = check_template(input)?;
+    let query = to_json(&query, "template query")?;
+    let parameters = to_json(&parameters, "template parameters")?;
+    let description = trimmed(&input.description);
+    let schedule = input.schedule.map(|schedule| schedule.as_str());
+    let max_rows = input.max_rows.unwrap_or(readonly::DEFAULT_CONSOLE_ROWS).clamp(1, readonly::MAX_CONSOLE_ROWS) as i64;
+    let now = Utc::now().timestamp();
+
+    Ok(conn.with_connection(|c| {
+        let id = match input.id {
+            Some(id) => {
+                load_template(c, id)?;
+                c.execute(
+                    "UPDATE report_template SET name = ?, description = ?, query = ?, parameters = ?,
+                            schedule = ?, max_rows = ?, updated_at = ?
+                     WHERE id = ?",
+                    params![name, description, query, parameters, schedule, max_rows, now, id],
+                ).map_err(|e| name_conflict(&name, e))?;
+                id
+            },
+            None => {
+                c.execute(
+                    "INSERT INTO report_template (name, description, query, parameters, schedule, max_rows, created_at, updated_at)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                    params![name, description, query, parameters, schedule, max_rows, now, now],
+                ).map_err(|e| name_conflict(&name, e))?;
+                c.last_insert_rowid()
+            },
+        };
+        load_template(c, id)
+    })?)
+}
+
+/// Deletes a report template with its outputs
+pub fn delete_template(conn: &DatabaseConnection, id: i64) -> Result<()> {
+    conn.with_connection(|c| {
+        if c.execute("DELETE FROM report_template WHERE id = ?", [id])? == 0 {
+            return Err(DatabaseError::NotFound(format!("Report template {} does not exist", id)));
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Parameter values for a run: the given ones, else the defaults
+fn resolve_parameters(template: &ReportTemplate, given: &HashMap<String, String>) -> Result<HashMap<String, String>> {
+    let mut values = HashMap::new();
+    for parameter in &template.parameters {
+        let value = given.get(&parameter.name)
+            .or(parameter.default.as_ref())
+            .ok_or_else(|| {
+                let label = parameter.label.as_deref().unwrap_or(&parameter.name);
+                ReportError::InvalidTemplate(format!("A value for '{}' is required", label))
+            })?;
+        values.insert(parameter.name.clone(), value.clone());
+    }
+    Ok(values)
+}
+
+/// SQL value of a parameter: numbers bind as numbers so they compare with
+/// timestamps and counts, anything else as text
+fn sql_value(value: &str) -> Value {
+    let value = value.trim();
+    if let Ok(integer) = value.parse::<i64>() {
+        Value::Integer(integer)
+    } else if let Ok(real) = value.parse::<f64>() {
+        Value::Real(real)
+    } else {
+        Value::Text(value.to_string())
+    }
+}
+
+/// A filter's value, taking a parameter's value when given as :name
+fn substitute(filter: &Option<String>, values: &HashMap<String, String>) -> Option<String> {
+    let filter = filter.as_ref()?;
+    let value = match filter.strip_prefix(':') {
+        Some(name) => values.get(name)?.trim().to_string(),
+        None => filter.clone(),
+    };
+    Some(value).filter(|v| !v.is_empty())
+}
+
+/// Runs a search template and lays its results out like a console result
+fn run_filters(
+    conn: &DatabaseConnection,
+    filters: &TemplateFilters,
+    values: &HashMap<String, String>,
+    max_rows: usize,
+    now: DateTime<Utc>,
+) -> Result<ConsoleResult> {
+    let started = Instant::now();
+    let params = SearchParams {
+        query: substitute(&filters.query, values),
+        domain: substitute(&filters.domain, values),
+        category: substitute(&filters.category, values),
+        tag: substitute(&filters.tag, values),
+        keyword: substitute(&filters.keyword, values),
+        walled: None,
+        start_date: filters.days.map(|days| now - Duration::days(i64::from(days))),
+        end_date: None,
+        limit: Some(max_rows),
+        offset: None,
+    };
+    let results = db::search_history(conn, &params)?;
+
+    let column = |name: &str, kind: ColumnKind| ConsoleColumn { name: name.to_string(), declared_type: None, kind };
+    let rows = results.urls.iter().map(|result| vec![
+        serde_json::Value::from(result.url.url.clone()),
+        result.url.title.clone().map_or(serde_json::Value::Null, serde_json::Value::from),
+        serde_json::Value::from(result.url.domain.clone()),
+        serde_json::Value::from(result.visit_count),
+        result.last_visit.map_or(serde_json::Value::Null, |ts| serde_json::Value::from(ts.to_rfc3339())),
+        serde_json::Value::from(result.tags.join(", ")),
+    ]).collect::<Vec<_>>();
+
+    Ok(ConsoleResult {
+        columns: vec![
+            column("url", ColumnKind::Text),
+            column("title", ColumnKind::Text),
+            column("domain", ColumnKind::Text),
+            column("visit_count", ColumnKind::Integer),
+            column("last_visit", ColumnKind::Text),
+            column("tags", ColumnKind::Text),
+        ],
+        truncated: results.total_count > rows.len(),
+        rows,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// Runs a template and stores its output, failed runs included; fails only
+/// when the template or its parameters are invalid or nothing can be stored
+pub fn run_template(
+    conn: &DatabaseConnection,
+    id: i64,
+    given: &HashMap<String, String>,
+    trigger: RunTrigger,
+) -> Result<ReportOutput> {
+    let template = conn.with_connection(|c| load_template(c, id))?;
+    let values = resolve_parameters(&template, given)?;
+    let started_at = Utc::now();
+
+    let result = match &template.query {
+        TemplateQuery::Sql { sql } => {
+            let mut bound: Vec<(String, Value)> = values.iter()
+                .map(|(name, value)| (name.clone(), sql_value(value)))
+                .collect();
+            bound.push((NOW_PARAMETER.to_string(), Value::Integer(started_at.timestamp())));
+            readonly::run_console_query(conn, sql, &bound, template.max_rows, readonly::DEFAULT_CONSOLE_TIMEOUT)
+                .map_err(ReportError::from)
+        },
+        TemplateQuery::Filters(filters) => run_filters(conn, filters, &values, template.max_rows, started_at),
+    };
+
+    let (result, error) = match result {
+        Ok(result) => (Some(result), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let elapsed_ms = result.as_ref().map_or(0, |r| r.elapsed_ms);
+    let row_count = result.as_ref().map_or(0, |r| r.rows.len());
+    let result_json = result.as_ref().map(|r| to_json(r, "report output")).transpose()?;
+    let parameters_json = to_json(&values, "report parameters")?;
+
+    let output_id = conn.transaction(|tx| {
+        tx.execute(
+            "INSERT INTO report_output (template_id, trigger, parameters, started_at, elapsed_ms, row_count, result, error)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                id, trigger.as_str(), parameters_json, started_at.timestamp(),
+                elapsed_ms as i64, row_count as i64, result_json, error,
+            ],
+        )?;
+        let output_id = tx.last_insert_rowid();
+        tx.execute("UPDATE report_template SET last_run_at = ? WHERE id = ?", params![started_at.timestamp(), id])?;
+        tx.execute(
+            "DELETE FROM report_output WHERE template_id = ?1 AND id NOT IN (
+                 SELECT id FROM report_output WHERE template_id = ?1 ORDER BY started_at DESC, id DESC LIMIT ?2
+             )",
+            params![id, MAX_OUTPUTS_PER_TEMPLATE as i64],
+        )?;
+        Ok(output_id)
+    })?;
+
+    Ok(ReportOutput {
+        id: output_id,
+        template_id: id,
+        trigger: trigger.as_str().to_string(),
+        parameters: values,
+        started_at,
+        elapsed_ms,
+        row_count,
+        result,
+        error,
+    })
+}
+
+/// Raw columns of a `SELECT_OUTPUT` row
+type RawOutput = (i64, i64, String, String, i64, i64, i64, Option<String>, Option<String>);
+
+fn raw_output(row: &Row<'_>) -> rusqlite::Result<RawOutput> {
+    Ok((
+        row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?,
+        row.get(5)?, row.get(6)?, row.get(7)?, row.get(8)?,
+    ))
+}
+
+fn output_from_raw(raw: RawOutput, with_result: bool) -> db::Result<ReportOutput> {
+    let (id, template_id, trigger, parameters, started_at, elapsed_ms, row_count, result, error) = raw;
+    Ok(ReportOutput {
+        id,
+        template_id,
+        trigger,
+        parameters: parse_json(&parameters, "report parameters")?,
+        started_at: DateTime::from_timestamp(started_at, 0).unwrap_or_default(),
+        elapsed_ms: elapsed_ms.max(0) as u64,
+        row_count: row_count.max(0) as usize,
+        result: match result {
+            Some(json) if with_result => Some(parse_json(&json, "report output")?),
+            _ => None,
+        },
+        error,
+    })
+}
+
+/// Lists a template's run history, newest first, without the rows
+pub fn list_outputs(conn: &DatabaseConnection, template_id: i64, limit: usize) -> Result<Vec<ReportOutput>> {
+    Ok(conn.with_connection(|c| {
+        let mut stmt = c.prepare(&format!(
+            "{} WHERE template_id = ? ORDER BY started_at DESC, id DESC LIMIT ?",
+            SELECT_OUTPUT
+        ))?;
+        let rows = stmt.query_map(params![template_id, limit as i64], raw_output)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter().map(|raw| output_from_raw(raw, false)).collect::<db::Result<Vec<_>>>()
+    })?)
+}
+
+/// Loads one stored output with its rows
+pub fn get_output(conn: &DatabaseConnection, id: i64) -> Result<ReportOutput> {
+    Ok(conn.with_connection(|c| {
+        let raw = c.query_row(&format!("{} WHERE id = ?", SELECT_OUTPUT), [id], raw_output)
+            .optional()?
+            .ok_or_else(|| DatabaseError::NotFound(format!("Report output {} does not exist", id)))?;
+        output_from_raw(raw, true)
+    })?)
+}
+
+/// Templates whose schedule is due: never run, or last run a full interval ago
+pub fn due_templates(conn: &DatabaseConnection, now: DateTime<Utc>) -> Result<Vec<ReportTemplate>> {
+    Ok(list_templates(conn)?
+        .into_iter()
+        .filter(|template| match (template.schedule, template.last_run_at) {
+            (Some(schedule), Some(last)) => now - last >= schedule.interval(),
+            (Some(_), None) => true,
+            (None, _) => false,
+        })
+        .collect())
+}
+
+/// Runs every due template with its default parameters; returns the outputs
+pub fn run_due_templates(conn: &DatabaseConnection) -> Result<Vec<ReportOutput>> {
+    let mut outputs = Vec::new();
+    for template in due_templates(conn, Utc::now())? {
+        outputs.push(run_template(conn, template.id, &HashMap::new(), RunTrigger::Schedule)?);
+    }
+    Ok(outputs)
+}
@@ -0,0 +1,139 @@
+// Scripting - Engine
+// Sandboxed Rhai engine and the page values scripts read and change
+
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use url::Url as UrlParser;
+
+use super::error::{Result, ScriptError};
+
+/// Operations one script run may take before it is stopped
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// Longest string a script may build
+const MAX_STRING_SIZE: usize = 64 * 1024;
+
+/// Largest array or map a script may build
+const MAX_COLLECTION_SIZE: usize = 1_000;
+
+/// A page as scripts see it, in the `page` variable
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptPage {
+    /// URL id; empty before the page is inserted
+    pub id: String,
+    /// Full URL
+    pub url: String,
+    /// Page title, () in scripts when there is none
+    pub title: Option<String>,
+    /// Host of the URL
+    pub domain: String,
+    /// Set by pre-insert scripts to keep the page out of the database
+    pub skip: bool,
+    /// Set by post-import and nightly scripts to tag the page
+    pub tags: Vec<String>,
+}
+
+impl ScriptPage {
+    /// Converts the page to a Rhai object map
+    fn to_map(&self) -> Map {
+        let mut map = Map::new();
+        map.insert("id".into(), self.id.clone().into());
+        map.insert("url".into(), self.url.clone().into());
+        map.insert("title".into(), self.title.clone().map_or(Dynamic::UNIT, Dynamic::from));
+        map.insert("domain".into(), self.domain.clone().into());
+        map.insert("skip".into(), self.skip.into());
+        let tags: Array = self.tags.iter().cloned().map(Dynamic::from).collect();
+        map.insert("tags".into(), tags.into());
+        map
+    }
+
+    /// Reads back the fields a script may change
+    fn update_from(&mut self, map: &Map) -> Result<()> {
+        let string = |name: &str| map.get(name).and_then(|value| value.clone().into_string().ok());
+
+        let url = string("url")
+            .ok_or_else(|| ScriptError::Runtime("page.url must be a string".to_string()))?;
+        if url != self.url {
+            // Keep the domain in step unless the script set one itself
+            let domain = string("domain").unwrap_or_default();
+            self.domain = if domain != self.domain {
+                domain
+            } else {
+                UrlParser::parse(&url).ok()
+                    .and_then(|parsed| parsed.host_str().map(str::to_string))
+                    .unwrap_or(domain)
+            };
+            self.url = url;
+        } else if let Some(domain) = string("domain") {
+            self.domain = domain;
+        }
+
+        self.title = string("title");
+        self.skip = map.get("skip").and_then(|value| value.as_bool().ok()).unwrap_or(false);
+        self.tags = map.get("tags")
+            .and_then(|value| value.clone().try_cast::<Array>())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|tag| tag.into_string().ok())
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        Ok(())
+    }
+}
+
+/// Builds an engine with resource limits and the helper functions
+pub fn new_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_COLLECTION_SIZE);
+    engine.set_max_map_size(MAX_COLLECTION_SIZE);
+    engine.disable_symbol("eval");
+
+    // URL without query string and fragment
+    engine.register_fn("strip_query", |url: &str| -> String {
+        match UrlParser::parse(url) {
+            Ok(mut parsed) => {
+                parsed.set_query(None);
+                parsed.set_fragment(None);
+                parsed.to_string()
+            },
+            Err(_) => url.to_string(),
+        }
+    });
+    // Value of a query parameter, or () when it is missing
+    engine.register_fn("query_param", |url: &str, name: &str| -> Dynamic {
+        UrlParser::parse(url).ok()
+            .and_then(|parsed| parsed.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned()))
+            .map_or(Dynamic::UNIT, Dynamic::from)
+    });
+    // Regular expression match; an invalid pattern never matches
+    engine.register_fn("matches", |text: &str, pattern: &str| -> bool {
+        Regex::new(pattern).is_ok_and(|regex| regex.is_match(text))
+    });
+
+    engine
+}
+
+/// Compiles a script, reporting where it fails
+pub fn compile(engine: &Engine, source: &str) -> Result<AST> {
+    engine.compile(source).map_err(|e| ScriptError::Compile(e.to_string()))
+}
+
+/// Runs a compiled script on a page, which it may change through `page`
+pub fn run_on_page(engine: &Engine, ast: &AST, page: &mut ScriptPage) -> Result<()> {
+    let mut scope = Scope::new();
+    scope.push("page", page.to_map());
+
+    engine.run_ast_with_scope(&mut scope, ast)
+        .map_err(|e| ScriptError::Runtime(e.to_string()))?;
+
+    let map = scope.get_value::<Map>("page")
+        .ok_or_else(|| ScriptError::Runtime("page must stay an object map".to_string()))?;
+    page.update_from(&map)
+}
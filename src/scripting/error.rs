@@ -0,0 +1,52 @@
+// Scripting Error Handling
+// Defines error types for user scripts and their hooks
+
+use std::fmt;
+use std::error::Error;
+
+use crate::db::DatabaseError;
+
+/// Represents errors that can occur while storing or running user scripts
+#[derive(Debug)]
+pub enum ScriptError {
+    /// Reading scripts or writing their results failed
+    Database(DatabaseError),
+    /// A script does not compile
+    Compile(String),
+    /// A script failed while running
+    Runtime(String),
+    /// A script was saved with invalid values
+    Invalid(String),
+    /// No script has the given id
+    NotFound(i64),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::Database(err) => write!(f, "Database error: {}", err),
+            ScriptError::Compile(msg) => write!(f, "Script does not compile: {}", msg),
+            ScriptError::Runtime(msg) => write!(f, "Script failed: {}", msg),
+            ScriptError::Invalid(msg) => write!(f, "Invalid script: {}", msg),
+            ScriptError::NotFound(id) => write!(f, "No script with id {}", id),
+        }
+    }
+}
+
+impl Error for ScriptError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ScriptError::Database(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DatabaseError> for ScriptError {
+    fn from(err: DatabaseError) -> Self {
+        ScriptError::Database(err)
+    }
+}
+
+/// Result type for scripting operations
+pub type Result<T> = std::result::Result<T, ScriptError>;
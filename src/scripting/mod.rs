@@ -0,0 +1,386 @@
+// Scripting Module
+// User scripts written in Rhai that run at hook points to rewrite, skip or
+// tag pages without rebuilding the app
+
+// Module organization:
+// - engine.rs: Sandboxed engine and the page values scripts work on
+// - error.rs: Error handling
+
+pub mod engine;
+pub mod error;
+
+pub use engine::ScriptPage;
+pub use error::{Result, ScriptError};
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use rhai::{Engine, AST};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::db::tags::tag_urls;
+use crate::db::{DatabaseConnection, DatabaseError};
+use crate::extractor::RawHistoryData;
+
+/// Point in the app where scripts run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hook {
+    /// Each imported page before it is inserted; may change `page.url`,
+    /// `page.title` and `page.domain`, or set `page.skip`
+    PreInsert,
+    /// Each page an import added visits to; may push to `page.tags`
+    PostImport,
+    /// Every page once a night; may push to `page.tags`
+    Nightly,
+}
+
+impl Hook {
+    /// Name stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Hook::PreInsert => "pre_insert",
+            Hook::PostImport => "post_import",
+            Hook::Nightly => "nightly",
+        }
+    }
+
+    /// Parses a stored hook name
+    fn parse(value: &str) -> std::result::Result<Self, DatabaseError> {
+        match value {
+            "pre_insert" => Ok(Hook::PreInsert),
+            "post_import" => Ok(Hook::PostImport),
+            "nightly" => Ok(Hook::Nightly),
+            other => Err(DatabaseError::Data(format!("Unknown script hook '{}'", other))),
+        }
+    }
+}
+
+/// A stored user script
+#[derive(Debug, Clone, Serialize)]
+pub struct Script {
+    /// Script identifier
+    pub id: i64,
+    /// Unique name; scripts of a hook run in name order
+    pub name: String,
+    /// Where the script runs
+    pub hook: Hook,
+    /// Rhai source
+    pub source: String,
+    /// Whether the script runs
+    pub enabled: bool,
+    /// When the script was created
+    pub created_at: DateTime<Utc>,
+    /// When the script was last saved
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A script as saved from the editor; without an id it is created
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScriptInput {
+    /// Script to update
+    pub id: Option<i64>,
+    /// Unique name
+    pub name: String,
+    /// Where the script runs
+    pub hook: Hook,
+    /// Rhai source
+    pub source: String,
+    /// Whether the script runs
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Outcome of running the scripts of a hook
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScriptRun {
+    /// Pages the scripts looked at
+    pub pages: usize,
+    /// Pages whose URL or title a pre-insert script changed
+    pub changed: usize,
+    /// Pages a pre-insert script skipped
+    pub skipped: usize,
+    /// Visits dropped with the skipped pages
+    pub visits_skipped: usize,
+    /// New page/tag pairs
+    pub tagged: usize,
+    /// Failures, one per script and page, as "script: message"
+    pub errors: Vec<String>,
+}
+
+/// Columns of a script row
+const SELECT_SCRIPT: &str =
+    "SELECT id, name, hook, source, enabled, created_at, updated_at FROM script";
+
+/// Decodes a script row
+fn script_from_row(row: &rusqlite::Row) -> rusqlite::Result<Script> {
+    let hook: String = row.get(2)?;
+    let timestamp = |index: usize| -> rusqlite::Result<DateTime<Utc>> {
+        Ok(DateTime::from_timestamp(row.get(index)?, 0).unwrap_or_default())
+    };
+    Ok(Script {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        hook: Hook::parse(&hook).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
+            2, rusqlite::types::Type::Text, Box::new(e),
+        ))?,
+        source: row.get(3)?,
+        enabled: row.get(4)?,
+        created_at: timestamp(5)?,
+        updated_at: timestamp(6)?,
+    })
+}
+
+/// Lists every script, grouped by hook and then by name
+pub fn list_scripts(conn: &DatabaseConnection) -> Result<Vec<Script>> {
+    Ok(conn.with_connection(|c| {
+        let mut stmt = c.prepare(&format!("{} ORDER BY hook, name COLLATE NOCASE", SELECT_SCRIPT))?;
+        let scripts = stmt.query_map([], script_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(scripts)
+    })?)
+}
+
+/// Creates or updates a script once it compiles
+pub fn save_script(conn: &DatabaseConnection, input: &ScriptInput) -> Result<Script> {
+    let name = input.name.trim();
+    if name.is_empty() {
+        return Err(ScriptError::Invalid("Name cannot be empty".to_string()));
+    }
+    engine::compile(&engine::new_engine(), &input.source)?;
+
+    let now = Utc::now().timestamp();
+    let script = conn.with_connection(|c| {
+        let id = match input.id {
+            Some(id) => {
+                let updated = c.execute(
+                    "UPDATE script SET name = ?, hook = ?, source = ?, enabled = ?, updated_at = ? WHERE id = ?",
+                    params![name, input.hook.as_str(), input.source, input.enabled, now, id],
+                )?;
+                if updated == 0 {
+                    return Ok(None);
+                }
+                id
+            },
+            None => {
+                c.execute(
+                    "INSERT INTO script (name, hook, source, enabled, created_at, updated_at)
+                     VALUES (?, ?, ?, ?, ?, ?)",
+                    params![name, input.hook.as_str(), input.source, input.enabled, now, now],
+                )?;
+                c.last_insert_rowid()
+            },
+        };
+        Ok(c.query_row(&format!("{} WHERE id = ?", SELECT_SCRIPT), [id], script_from_row).optional()?)
+    })?;
+
+    script.ok_or_else(|| ScriptError::NotFound(input.id.unwrap_or_default()))
+}
+
+/// Deletes a script
+pub fn delete_script(conn: &DatabaseConnection, id: i64) -> Result<()> {
+    let deleted = conn.with_connection(|c| Ok(c.execute("DELETE FROM script WHERE id = ?", [id])?))?;
+    if deleted == 0 {
+        return Err(ScriptError::NotFound(id));
+    }
+    Ok(())
+}
+
+/// Returns true if any enabled script runs at the hook
+pub fn has_scripts(conn: &DatabaseConnection, hook: Hook) -> Result<bool> {
+    Ok(conn.with_connection(|c| {
+        Ok(c.query_row(
+            "SELECT EXISTS (SELECT 1 FROM script WHERE hook = ? AND enabled = 1)",
+            [hook.as_str()],
+            |row| row.get(0),
+        )?)
+    })?)
+}
+
+/// The enabled scripts of one hook, compiled and ready to run in name order
+pub struct HookScripts {
+    engine: Engine,
+    scripts: Vec<(String, AST)>,
+    /// Scripts that stopped compiling, e.g. after an engine upgrade
+    errors: Vec<String>,
+}
+
+impl HookScripts {
+    /// Loads and compiles the enabled scripts of a hook
+    pub fn load(conn: &DatabaseConnection, hook: Hook) -> Result<Self> {
+        let sources: Vec<(String, String)> = conn.with_connection(|c| {
+            let mut stmt = c.prepare(
+                "SELECT name, source FROM script WHERE hook = ? AND enabled = 1 ORDER BY name COLLATE NOCASE"
+            )?;
+            let rows = stmt.query_map([hook.as_str()], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows)
+        })?;
+
+        let engine = engine::new_engine();
+        let mut scripts = Vec::new();
+        let mut errors = Vec::new();
+        for (name, source) in sources {
+            match engine::compile(&engine, &source) {
+                Ok(ast) => scripts.push((name, ast)),
+                Err(e) => errors.push(format!("{}: {}", name, e)),
+            }
+        }
+
+        Ok(HookScripts { engine, scripts, errors })
+    }
+
+    /// Returns true if there is nothing to run
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    /// Scripts of the hook that don't compile and are left out
+    pub fn compile_errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// Runs every script on a page in turn. A failing script leaves the page
+    /// as the previous one left it; the same failure is reported once.
+    pub fn run(&self, page: &mut ScriptPage, errors: &mut Vec<String>) {
+        for (name, ast) in &self.scripts {
+            let mut changed = page.clone();
+            match engine::run_on_page(&self.engine, ast, &mut changed) {
+                Ok(()) => *page = changed,
+                Err(e) => {
+                    let message = format!("{}: {}", name, e);
+                    if !errors.contains(&message) {
+                        errors.push(message);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Runs the pre-insert scripts on extracted history, dropping skipped
+    /// pages with their visits
+    pub fn apply(&self, data: &mut RawHistoryData) -> ScriptRun {
+        let mut run = ScriptRun::default();
+        if self.is_empty() {
+            return run;
+        }
+
+        let mut skipped = HashSet::new();
+        for url in &mut data.urls {
+            let mut page = ScriptPage {
+                url: url.url.clone(),
+                title: url.title.clone(),
+                domain: url.domain.clone(),
+                ..ScriptPage::default()
+            };
+            self.run(&mut page, &mut run.errors);
+            run.pages += 1;
+
+            if page.skip {
+                skipped.insert(url.id);
+                continue;
+            }
+            if page.url != url.url || page.title != url.title {
+                run.changed += 1;
+            }
+            url.url = page.url;
+            url.title = page.title;
+            url.domain = page.domain;
+        }
+
+        // Rewritten URLs that collide are folded into one record on insert
+        data.urls.retain(|url| !skipped.contains(&url.id));
+        let visits_before = data.visits.len();
+        data.visits.retain(|visit| !skipped.contains(&visit.url_id));
+        run.skipped = skipped.len();
+        run.visits_skipped = visits_before - data.visits.len();
+        run
+    }
+
+    /// Runs the tagging scripts on pages and adds the tags they set
+    pub fn tag_pages(&self, conn: &DatabaseConnection, pages: Vec<ScriptPage>) -> Result<ScriptRun> {
+        let mut run = ScriptRun { errors: self.errors.clone(), ..ScriptRun::default() };
+        if self.is_empty() {
+            return Ok(run);
+        }
+
+        // Pages are grouped by tag so each tag is one undoable operation
+        let mut tagged: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for mut page in pages {
+            let existing: HashSet<String> = page.tags.iter().cloned().collect();
+            self.run(&mut page, &mut run.errors);
+            run.pages += 1;
+            for tag in page.tags.into_iter().filter(|tag| !existing.contains(tag)) {
+                tagged.entry(tag).or_default().push(page.id.clone());
+            }
+        }
+
+        for (tag, url_ids) in tagged {
+            match tag_urls(conn, &url_ids, &[tag.clone()]) {
+                Ok(added) => run.tagged += added,
+                Err(e) => run.errors.push(format!("Failed to add tag '{}': {}", tag, e)),
+            }
+        }
+        Ok(run)
+    }
+}
+
+/// Reads pages for the tagging hooks, with their current tags
+fn load_pages(conn: &DatabaseConnection, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<ScriptPage>> {
+    Ok(conn.with_connection(|c| {
+        let mut stmt = c.prepare(sql)?;
+        let mut pages = stmt.query_map(params, |row| Ok(ScriptPage {
+            id: row.get(0)?,
+            url: row.get(1)?,
+            title: row.get(2)?,
+            domain: row.get(3)?,
+            ..ScriptPage::default()
+        }))?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for page in &mut pages {
+            page.tags = crate::db::tags::get_url_tags(c, &page.id)?;
+        }
+        Ok(pages)
+    })?)
+}
+
+/// Runs the post-import scripts on the pages an import run added visits to
+pub fn run_post_import(conn: &DatabaseConnection, import_run_id: i64) -> Result<ScriptRun> {
+    let scripts = HookScripts::load(conn, Hook::PostImport)?;
+    if scripts.is_empty() {
+        return Ok(ScriptRun { errors: scripts.errors, ..ScriptRun::default() });
+    }
+
+    let pages = load_pages(
+        conn,
+        "SELECT DISTINCT u.id, u.url, u.title, u.domain
+         FROM url u JOIN visit v ON v.url_id = u.id
+         WHERE v.import_run_id = ?",
+        &[&import_run_id],
+    )?;
+    scripts.tag_pages(conn, pages)
+}
+
+/// Runs the nightly scripts on every page
+pub fn run_nightly(conn: &DatabaseConnection) -> Result<ScriptRun> {
+    let scripts = HookScripts::load(conn, Hook::Nightly)?;
+    if scripts.is_empty() {
+        return Ok(ScriptRun { errors: scripts.errors, ..ScriptRun::default() });
+    }
+
+    let pages = load_pages(conn, "SELECT id, url, title, domain FROM url", &[])?;
+    scripts.tag_pages(conn, pages)
+}
+
+/// Runs a script on a sample page without saving anything, for the editor
+pub fn test_script(source: &str, mut page: ScriptPage) -> Result<ScriptPage> {
+    let engine = engine::new_engine();
+    let ast = engine::compile(&engine, source)?;
+    engine::run_on_page(&engine, &ast, &mut page)?;
+    Ok(page)
+}
@@ -0,0 +1,43 @@
+// Settings Error Handling
+// Defines error types for reading and saving settings
+
+use std::fmt;
+use std::error::Error;
+
+use crate::db::DatabaseError;
+
+/// Represents errors that can occur while reading or saving settings
+#[derive(Debug)]
+pub enum SettingsError {
+    /// Reading or writing the settings table failed
+    Database(DatabaseError),
+    /// A setting has an invalid value
+    Invalid(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Database(err) => write!(f, "Database error: {}", err),
+            SettingsError::Invalid(msg) => write!(f, "Invalid setting: {}", msg),
+        }
+    }
+}
+
+impl Error for SettingsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SettingsError::Database(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DatabaseError> for SettingsError {
+    fn from(err: DatabaseError) -> Self {
+        SettingsError::Database(err)
+    }
+}
+
+/// Result type for settings operations
+pub type Result<T> = std::result::Result<T, SettingsError>;
@@ -0,0 +1,190 @@
+// Settings Module
+// Typed view over every persisted preference, stored per section in the
+// settings table under the key each feature already reads
+
+// Module organization:
+// - error.rs: Error handling
+
+pub mod error;
+
+pub use error::{Result, SettingsError};
+
+use chrono_tz::Tz;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::automation::{AutomationSettings, AUTOMATION_SETTING};
+use crate::capture::{CaptureSettings, CAPTURE_SETTING};
+use crate::db::imports::{ImportSettings, IMPORT_SETTING};
+use crate::db::maintenance::{RetentionSettings, RETENTION_SETTING};
+use crate::db::merge::{NormalizationSettings, NORMALIZATION_SETTING};
+use crate::db::settings::{get_setting, set_setting};
+use crate::db::trackers::{TrackerSettings, TRACKER_SETTING};
+use crate::db::DatabaseConnection;
+use crate::enrichment::openai::save_api_key;
+use crate::enrichment::{EnrichmentSettings, ENRICHMENT_SETTING};
+use crate::jobs::{AutoImportSettings, AUTO_IMPORT_SETTING};
+use crate::mcp::{McpSettings, MCP_SETTING};
+use crate::notifications::{NotificationSettings, NOTIFICATIONS_SETTING};
+use crate::privacy::{PrivacyFilter, PrivacyRules, PRIVACY_SETTING};
+use crate::spotlight::{SpotlightSettings, SPOTLIGHT_SETTING};
+use crate::sync::{LanSyncSettings, LAN_SYNC_SETTING};
+use crate::web::changes::{ContentCheckSettings, CONTENT_CHECK_SETTING};
+use crate::web::provenance::{DomainEnrichmentSettings, DOMAIN_ENRICHMENT_SETTING};
+use crate::web::tone::{SentimentSettings, SENTIMENT_SETTING};
+
+/// Settings key of the display timezone
+pub const TIMEZONE_SETTING: &str = "timezone";
+
+/// Every user preference
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    /// Rules for detecting duplicate URLs
+    pub normalization: NormalizationSettings,
+    /// URLs excluded or redacted at import
+    pub privacy: PrivacyRules,
+    /// AI providers, their keys and the queue
+    pub enrichment: EnrichmentSettings,
+    /// How long visits are kept
+    pub retention: RetentionSettings,
+    /// Periodic re-import of the local Safari history
+    pub auto_import: AutoImportSettings,
+    /// How imports write to the database
+    pub import: ImportSettings,
+    /// Endpoint receiving visits from the browser extension
+    pub capture: CaptureSettings,
+    /// Model Context Protocol server for local assistants
+    pub mcp: McpSettings,
+    /// Actions run from `historykg://run/` links, e.g. by Shortcuts
+    pub automation: AutomationSettings,
+    /// Leaving ad and tracker domains out of stats and the graph
+    pub trackers: TrackerSettings,
+    /// Global shortcut opening the search window
+    pub spotlight: SpotlightSettings,
+    /// Desktop notifications when long work finishes
+    pub notifications: NotificationSettings,
+    /// Sync with paired instances on the local network
+    pub lan_sync: LanSyncSettings,
+    /// Scheduled re-checks of fetched pages for content changes
+    pub content_checks: ContentCheckSettings,
+    /// Scheduled registrar and hosting lookups of browsed domains
+    pub domain_enrichment: DomainEnrichmentSettings,
+    /// Tone scoring of fetched pages
+    pub sentiment: SentimentSettings,
+    /// IANA timezone days are bucketed in, e.g. "Europe/Lisbon"; None is UTC
+    pub timezone: Option<String>,
+}
+
+/// Parses an IANA timezone name
+fn parse_timezone(name: &str) -> Result<Tz> {
+    name.parse::<Tz>()
+        .map_err(|_| SettingsError::Invalid(format!("Unknown timezone '{}'", name)))
+}
+
+/// Checks the values that can be invalid before anything is stored
+fn validate(settings: &AppSettings) -> Result<()> {
+    PrivacyFilter::new(&settings.privacy)
+        .map_err(|e| SettingsError::Invalid(e.to_string()))?;
+    if settings.auto_import.interval_minutes == 0 {
+        return Err(SettingsError::Invalid("Automatic import interval must be at least one minute".to_string()));
+    }
+    if settings.import.batch_size == 0 {
+        return Err(SettingsError::Invalid("Import batch size must be at least one row".to_string()));
+    }
+    settings.capture.validate()
+        .map_err(|e| SettingsError::Invalid(e.to_string()))?;
+    if settings.mcp.port == 0 {
+        return Err(SettingsError::Invalid("MCP port cannot be 0".to_string()));
+    }
+    if settings.spotlight.enabled && settings.spotlight.shortcut.trim().is_empty() {
+        return Err(SettingsError::Invalid("Search shortcut cannot be empty".to_string()));
+    }
+    settings.lan_sync.validate()
+        .map_err(|e| SettingsError::Invalid(e.to_string()))?;
+    if settings.content_checks.interval_days == 0 {
+        return Err(SettingsError::Invalid("Content check interval must be at least one day".to_string()));
+    }
+    if settings.content_checks.batch_size == 0 {
+        return Err(SettingsError::Invalid("Content check batch size must be at least one page".to_string()));
+    }
+    if settings.domain_enrichment.interval_days == 0 {
+        return Err(SettingsError::Invalid("Domain enrichment interval must be at least one day".to_string()));
+    }
+    if settings.domain_enrichment.batch_size == 0 {
+        return Err(SettingsError::Invalid("Domain enrichment batch size must be at least one domain".to_string()));
+    }
+    if let Some(timezone) = &settings.timezone {
+        parse_timezone(timezone)?;
+    }
+    Ok(())
+}
+
+/// Reads a section, falling back to its defaults
+fn section<T: serde::de::DeserializeOwned + Default>(c: &Connection, key: &str) -> crate::db::Result<T> {
+    Ok(get_setting(c, key)?.unwrap_or_default())
+}
+
+/// Loads every setting
+pub fn get_settings(conn: &DatabaseConnection) -> Result<AppSettings> {
+    Ok(conn.with_connection(|c| {
+        Ok(AppSettings {
+            normalization: section(c, NORMALIZATION_SETTING)?,
+            privacy: section(c, PRIVACY_SETTING)?,
+            enrichment: section(c, ENRICHMENT_SETTING)?,
+            retention: section(c, RETENTION_SETTING)?,
+            auto_import: section(c, AUTO_IMPORT_SETTING)?,
+            import: section(c, IMPORT_SETTING)?,
+            capture: section(c, CAPTURE_SETTING)?,
+            mcp: section(c, MCP_SETTING)?,
+            automation: section(c, AUTOMATION_SETTING)?,
+            trackers: section(c, TRACKER_SETTING)?,
+            spotlight: section(c, SPOTLIGHT_SETTING)?,
+            notifications: section(c, NOTIFICATIONS_SETTING)?,
+            lan_sync: section(c, LAN_SYNC_SETTING)?,
+            content_checks: section(c, CONTENT_CHECK_SETTING)?,
+            domain_enrichment: section(c, DOMAIN_ENRICHMENT_SETTING)?,
+            sentiment: section(c, SENTIMENT_SETTING)?,
+            timezone: get_setting::<Option<String>>(c, TIMEZONE_SETTING)?.flatten(),
+        })
+    })?)
+}
+
+/// Validates and stores every setting in one transaction
+pub fn set_settings(conn: &DatabaseConnection, settings: &AppSettings) -> Result<()> {
+    validate(settings)?;
+
+    // A new OpenAI API key goes to the keychain, not the settings table
+    let mut enrichment = settings.enrichment.clone();
+    save_api_key(&mut enrichment.openai).map_err(|e| SettingsError::Invalid(e.to_string()))?;
+
+    Ok(conn.transaction(|tx| {
+        set_setting(tx, NORMALIZATION_SETTING, &settings.normalization)?;
+        set_setting(tx, PRIVACY_SETTING, &settings.privacy)?;
+        set_setting(tx, ENRICHMENT_SETTING, &enrichment)?;
+        set_setting(tx, RETENTION_SETTING, &settings.retention)?;
+        set_setting(tx, AUTO_IMPORT_SETTING, &settings.auto_import)?;
+        set_setting(tx, IMPORT_SETTING, &settings.import)?;
+        set_setting(tx, CAPTURE_SETTING, &settings.capture)?;
+        set_setting(tx, MCP_SETTING, &settings.mcp)?;
+        set_setting(tx, AUTOMATION_SETTING, &settings.automation)?;
+        set_setting(tx, TRACKER_SETTING, &settings.trackers)?;
+        set_setting(tx, SPOTLIGHT_SETTING, &settings.spotlight)?;
+        set_setting(tx, NOTIFICATIONS_SETTING, &settings.notifications)?;
+        set_setting(tx, LAN_SYNC_SETTING, &settings.lan_sync)?;
+        set_setting(tx, CONTENT_CHECK_SETTING, &settings.content_checks)?;
+        set_setting(tx, DOMAIN_ENRICHMENT_SETTING, &settings.domain_enrichment)?;
+        set_setting(tx, SENTIMENT_SETTING, &settings.sentiment)?;
+        set_setting(tx, TIMEZONE_SETTING, &settings.timezone)?;
+        Ok(())
+    })?)
+}
+
+/// Gets the timezone days are bucketed in, UTC unless one is set
+pub fn get_timezone(conn: &DatabaseConnection) -> Result<Tz> {
+    let name: Option<Option<String>> = conn.with_connection(|c| get_setting(c, TIMEZONE_SETTING))?;
+    match name.flatten() {
+        Some(name) => parse_timezone(&name),
+        None => Ok(Tz::UTC),
+    }
+}
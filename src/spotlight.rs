@@ -0,0 +1,78 @@
+// Spotlight Search
+// Global shortcut opening a small always-on-top search window, so history can
+// be looked up without switching to the main window
+
+use serde::{Deserialize, Serialize};
+use tauri::{Manager, WindowBuilder, WindowEvent, WindowUrl};
+
+use crate::db::settings::get_setting;
+use crate::db::DatabaseConnection;
+use crate::error::AppError;
+
+/// Settings key of the shortcut
+pub const SPOTLIGHT_SETTING: &str = "spotlight";
+
+/// Label of the search window
+pub const SPOTLIGHT_WINDOW: &str = "spotlight";
+
+/// Shortcut unless configured
+pub const DEFAULT_SPOTLIGHT_SHORTCUT: &str = "CmdOrCtrl+Shift+H";
+
+/// Global shortcut configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpotlightSettings {
+    /// Whether the shortcut is registered
+    pub enabled: bool,
+    /// Accelerator, e.g. "CmdOrCtrl+Shift+H" or "Alt+Space"
+    pub shortcut: String,
+}
+
+impl Default for SpotlightSettings {
+    fn default() -> Self {
+        SpotlightSettings {
+            enabled: true,
+            shortcut: DEFAULT_SPOTLIGHT_SHORTCUT.to_string(),
+        }
+    }
+}
+
+/// Gets the shortcut settings
+pub fn get_spotlight_settings(conn: &DatabaseConnection) -> crate::db::Result<SpotlightSettings> {
+    conn.with_connection(|c| Ok(get_setting(c, SPOTLIGHT_SETTING)?.unwrap_or_default()))
+}
+
+/// Shows the search window, creating it the first time, or hides it when it
+/// is already in front
+pub fn toggle(app_handle: &tauri::AppHandle) -> Result<(), AppError> {
+    if let Some(window) = app_handle.get_window(SPOTLIGHT_WINDOW) {
+        if window.is_visible()? && window.is_focused()? {
+            window.hide()?;
+        } else {
+            window.show()?;
+            window.set_focus()?;
+        }
+        return Ok(());
+    }
+
+    // The frontend renders only the search box for this window
+    let window = WindowBuilder::new(app_handle, SPOTLIGHT_WINDOW, WindowUrl::App("index.html?window=spotlight".into()))
+        .title("Search History")
+        .inner_size(680.0, 420.0)
+        .resizable(false)
+        .decorations(false)
+        .always_on_top(true)
+        .skip_taskbar(true)
+        .center()
+        .focused(true)
+        .build()?;
+
+    // Like Spotlight, the window goes away once the user clicks elsewhere
+    let handle = window.clone();
+    window.on_window_event(move |event| {
+        if let WindowEvent::Focused(false) = event {
+            let _ = handle.hide();
+        }
+    });
+    Ok(())
+}
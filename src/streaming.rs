@@ -0,0 +1,226 @@
+// Streaming Results
+// Sends large result sets to the window that asked for them as batches of
+// rows over events, instead of one multi-megabyte response. The window
+// acknowledges every batch it has handled; the sender stops reading rows while
+// too many batches are unacknowledged, so a slow view holds back the query
+// rather than piling up events.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Event carrying a batch of rows; payload is a `StreamBatch`
+pub const STREAM_BATCH: &str = "stream-batch";
+
+/// Rows per batch unless the window asks for another size
+pub const DEFAULT_STREAM_BATCH_SIZE: usize = 500;
+
+/// Most rows per batch
+pub const MAX_STREAM_BATCH_SIZE: usize = 10_000;
+
+/// Batches sent ahead of the window's acknowledgements
+const MAX_UNACKNOWLEDGED: u64 = 4;
+
+/// A stream whose window acknowledges nothing for this long is abandoned,
+/// e.g. because the window was closed
+const ACK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Payload of `stream-batch`
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamBatch<T: Serialize> {
+    /// Stream the batch belongs to, as chosen by the window
+    pub stream_id: String,
+    /// Position of the batch in the stream, from 0; acknowledged by this number
+    pub seq: u64,
+    /// The rows
+    pub rows: Vec<T>,
+    /// Set on the last batch, which may hold no rows
+    pub done: bool,
+    /// Why the stream ended early
+    pub error: Option<String>,
+}
+
+/// How a stream ended, returned by the command that started it
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamSummary {
+    /// The stream
+    pub stream_id: String,
+    /// Rows sent
+    pub rows: usize,
+    /// Batches sent, the last one included
+    pub batches: u64,
+    /// True if the window cancelled the stream or stopped acknowledging it
+    pub cancelled: bool,
+}
+
+/// Acknowledgements and cancellation of one stream
+#[derive(Default)]
+struct StreamState {
+    /// Batches acknowledged so far
+    acknowledged: Mutex<u64>,
+    changed: Condvar,
+    cancelled: AtomicBool,
+}
+
+/// Streams in progress, by id
+#[derive(Default)]
+pub struct Streams {
+    streams: Mutex<HashMap<String, Arc<StreamState>>>,
+}
+
+impl Streams {
+    /// Starts a stream of `T` rows to `window`, replacing a stream still open
+    /// under the same id
+    pub fn open<T: Serialize + Clone>(
+        self: &Arc<Self>,
+        window: tauri::Window,
+        stream_id: &str,
+        batch_size: Option<usize>,
+    ) -> StreamSender<T> {
+        let state = Arc::new(StreamState::default());
+        if let Ok(mut streams) = self.streams.lock() {
+            if let Some(earlier) = streams.insert(stream_id.to_string(), Arc::clone(&state)) {
+                earlier.cancelled.store(true, Ordering::SeqCst);
+                earlier.changed.notify_all();
+            }
+        }
+
+        let batch_size = batch_size.unwrap_or(DEFAULT_STREAM_BATCH_SIZE).clamp(1, MAX_STREAM_BATCH_SIZE);
+        StreamSender {
+            streams: Arc::clone(self),
+            window,
+            stream_id: stream_id.to_string(),
+            state,
+            batch_size,
+            batch: Vec::with_capacity(batch_size),
+            seq: 0,
+            rows: 0,
+        }
+    }
+
+    /// Records that the window handled batch `seq`; returns false for an
+    /// unknown stream
+    pub fn acknowledge(&self, stream_id: &str, seq: u64) -> bool {
+        let Some(state) = self.get(stream_id) else {
+            return false;
+        };
+        if let Ok(mut acknowledged) = state.acknowledged.lock() {
+            *acknowledged = (*acknowledged).max(seq + 1);
+        }
+        state.changed.notify_all();
+        true
+    }
+
+    /// Stops a stream after the batch being read; returns false for an
+    /// unknown stream
+    pub fn cancel(&self, stream_id: &str) -> bool {
+        let Some(state) = self.get(stream_id) else {
+            return false;
+        };
+        state.cancelled.store(true, Ordering::SeqCst);
+        state.changed.notify_all();
+        true
+    }
+
+    fn get(&self, stream_id: &str) -> Option<Arc<StreamState>> {
+        self.streams.lock().ok()?.get(stream_id).cloned()
+    }
+}
+
+/// Sends the rows of one stream; dropping it closes the stream
+pub struct StreamSender<T: Serialize + Clone> {
+    streams: Arc<Streams>,
+    window: tauri::Window,
+    stream_id: String,
+    state: Arc<StreamState>,
+    batch_size: usize,
+    batch: Vec<T>,
+    seq: u64,
+    rows: usize,
+}
+
+impl<T: Serialize + Clone> StreamSender<T> {
+    /// True once the window cancelled the stream or stopped acknowledging it
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Adds a row, sending the batch once it is full; returns false when the
+    /// stream was cancelled and no more rows should be read
+    pub fn push(&mut self, row: T) -> bool {
+        if self.is_cancelled() {
+            return false;
+        }
+        self.batch.push(row);
+        if self.batch.len() >= self.batch_size {
+            self.send(false, None);
+        }
+        !self.is_cancelled()
+    }
+
+    /// Sends the remaining rows as the last batch, with the error that ended
+    /// the stream if any
+    pub fn finish(mut self, error: Option<String>) -> StreamSummary {
+        if !self.is_cancelled() {
+            self.send(true, error);
+        }
+        StreamSummary {
+            stream_id: self.stream_id.clone(),
+            rows: self.rows,
+            batches: self.seq,
+            cancelled: self.is_cancelled(),
+        }
+    }
+
+    /// Waits until the window is close enough behind, then emits the batch
+    fn send(&mut self, done: bool, error: Option<String>) {
+        if !self.wait_for_window() {
+            self.state.cancelled.store(true, Ordering::SeqCst);
+            return;
+        }
+
+        let rows = std::mem::replace(&mut self.batch, Vec::with_capacity(self.batch_size));
+        self.rows += rows.len();
+        let batch = StreamBatch { stream_id: self.stream_id.clone(), seq: self.seq, rows, done, error };
+        if self.window.emit(STREAM_BATCH, batch).is_err() {
+            self.state.cancelled.store(true, Ordering::SeqCst);
+        }
+        self.seq += 1;
+    }
+
+    /// Blocks while too many batches are unacknowledged; returns false if the
+    /// stream was cancelled or the window stopped answering
+    fn wait_for_window(&self) -> bool {
+        let Ok(mut acknowledged) = self.state.acknowledged.lock() else {
+            return false;
+        };
+        while self.seq >= *acknowledged + MAX_UNACKNOWLEDGED {
+            if self.is_cancelled() {
+                return false;
+            }
+            let (guard, timeout) = match self.state.changed.wait_timeout(acknowledged, ACK_TIMEOUT) {
+                Ok(result) => result,
+                Err(_) => return false,
+            };
+            acknowledged = guard;
+            if timeout.timed_out() && self.seq >= *acknowledged + MAX_UNACKNOWLEDGED {
+                return false;
+            }
+        }
+        !self.is_cancelled()
+    }
+}
+
+impl<T: Serialize + Clone> Drop for StreamSender<T> {
+    fn drop(&mut self) {
+        if let Ok(mut streams) = self.streams.streams.lock() {
+            // A newer stream may have taken over the id
+            if streams.get(&self.stream_id).is_some_and(|state| Arc::ptr_eq(state, &self.state)) {
+                streams.remove(&self.stream_id);
+            }
+        }
+    }
+}
@@ -0,0 +1,512 @@
+// Sync - Bundles
+// A bundle is a SQLCipher database encrypted with the user's sync passphrase,
+// holding the URLs, visits, deletions, tags and collection items changed
+// since a given time
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::db::DatabaseConnection;
+use super::error::{Result, SyncError};
+
+/// Version of the bundle layout; bundles in another one are refused
+pub const BUNDLE_FORMAT: i64 = 1;
+
+/// Shortest passphrase a bundle is encrypted with
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Tables of a bundle
+const BUNDLE_SCHEMA: &str = "
+    CREATE TABLE bundle.bundle_info (
+        format INTEGER NOT NULL,
+        created_at INTEGER NOT NULL,
+        since INTEGER
+    );
+    CREATE TABLE bundle.url (
+        id TEXT PRIMARY KEY,
+        url TEXT NOT NULL,
+        title TEXT,
+        domain TEXT NOT NULL,
+        first_seen INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL,
+        category TEXT,
+        is_favorite INTEGER NOT NULL
+    );
+    CREATE TABLE bundle.visit (
+        url_id TEXT NOT NULL,
+        visited_at INTEGER NOT NULL,
+        visit_count INTEGER NOT NULL,
+        source_file TEXT NOT NULL,
+        device_name TEXT,
+        duration_sec REAL
+    );
+    CREATE TABLE bundle.visit_tombstone (
+        url TEXT NOT NULL,
+        visited_at INTEGER NOT NULL,
+        deleted_at INTEGER NOT NULL
+    );
+    CREATE TABLE bundle.url_tag (
+        url_id TEXT NOT NULL,
+        tag TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE TABLE bundle.collection (
+        name TEXT NOT NULL,
+        description TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE bundle.collection_item (
+        collection TEXT NOT NULL,
+        url_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        note TEXT,
+        added_at INTEGER NOT NULL
+    );
+";
+
+/// What a new bundle holds
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleSummary {
+    /// Bundle file
+    pub path: String,
+    /// Changes since this time went in; None is the whole history
+    pub since: Option<DateTime<Utc>>,
+    /// URLs in the bundle
+    pub urls: usize,
+    /// Visits in the bundle
+    pub visits: usize,
+    /// Deleted visits in the bundle
+    pub deletions: usize,
+    /// Tag assignments in the bundle
+    pub tags: usize,
+    /// Collection items in the bundle
+    pub collection_items: usize,
+    /// Size of the bundle file
+    pub size_bytes: u64,
+}
+
+/// Two different notes for the same collection item
+#[derive(Debug, Clone, Serialize)]
+pub struct NoteConflict {
+    /// Collection name
+    pub collection: String,
+    /// Address of the item
+    pub url: String,
+    /// Note kept on this machine
+    pub local_note: String,
+    /// Note in the bundle
+    pub bundle_note: String,
+}
+
+/// What merging a bundle changed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ApplyBundleResult {
+    /// When the bundle was created
+    pub created_at: Option<DateTime<Utc>>,
+    /// URLs that were only in the bundle
+    pub urls_added: usize,
+    /// URLs both sides had, merged into one record
+    pub urls_merged: usize,
+    /// Visits that were only in the bundle
+    pub visits_added: usize,
+    /// Local visits removed because they were deleted on the other machine
+    pub visits_removed: usize,
+    /// Tag assignments that were only in the bundle
+    pub tags_added: usize,
+    /// Collection items that were only in the bundle
+    pub collection_items_added: usize,
+    /// Notes taken from the bundle
+    pub notes_updated: usize,
+    /// Notes that differ on both sides; the local ones were kept
+    pub conflicts: Vec<NoteConflict>,
+}
+
+/// Path of the partial bundle, renamed into place once complete so a synced
+/// folder never uploads half a file
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+/// Checks the sync passphrase
+fn check_passphrase(passphrase: &str) -> Result<()> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(SyncError::Invalid(format!(
+            "The sync passphrase must have at least {} characters", MIN_PASSPHRASE_LEN,
+        )));
+    }
+    Ok(())
+}
+
+/// Counts the rows of a bundle table
+fn count(c: &Connection, table: &str) -> Result<usize> {
+    let rows: i64 = c.query_row(&format!("SELECT COUNT(*) FROM bundle.{}", table), [], |row| row.get(0))?;
+    Ok(rows as usize)
+}
+
+/// Copies the changes since `since` into the attached bundle
+fn fill_bundle(c: &Connection, since: i64) -> Result<()> {
+    c.execute(
+        "INSERT INTO bundle.bundle_info (format, created_at, since) VALUES (?, ?, ?)",
+        params![BUNDLE_FORMAT, Utc::now().timestamp(), (since > 0).then_some(since)],
+    )?;
+
+    // Visits made since, or imported since from an older history file
+    c.execute(
+        "INSERT INTO bundle.visit (url_id, visited_at, visit_count, source_file, device_name, duration_sec)
+         SELECT v.url_id, v.visited_at, v.visit_count, v.source_file, v.device_name, v.duration_sec
+         FROM main.visit v
+         WHERE v.visited_at >= ?1
+            OR v.import_run_id IN (SELECT id FROM main.import_run WHERE started_at >= ?1)",
+        [since],
+    )?;
+    c.execute(
+        "INSERT INTO bundle.visit_tombstone (url, visited_at, deleted_at)
+         SELECT url, visited_at, deleted_at FROM main.visit_tombstone WHERE deleted_at >= ?",
+        [since],
+    )?;
+    c.execute(
+        "INSERT INTO bundle.url_tag (url_id, tag, created_at)
+         SELECT ut.url_id, t.name, ut.created_at
+         FROM main.url_tag ut JOIN main.tag t ON t.id = ut.tag_id
+         WHERE ut.created_at >= ?",
+        [since],
+    )?;
+
+    // A changed collection goes in whole, so notes edited since travel too
+    c.execute(
+        "INSERT INTO bundle.collection (name, description, created_at, updated_at)
+         SELECT name, description, created_at, updated_at FROM main.collection WHERE updated_at >= ?",
+        [since],
+    )?;
+    c.execute(
+        "INSERT INTO bundle.collection_item (collection, url_id, position, note, added_at)
+         SELECT c.name, i.url_id, i.position, i.note, i.added_at
+         FROM main.collection_item i JOIN main.collection c ON c.id = i.collection_id
+         WHERE c.updated_at >= ?",
+        [since],
+    )?;
+
+    // Every URL the rows above refer to, plus URLs seen since
+    c.execute(
+        "INSERT INTO bundle.url (id, url, title, domain, first_seen, last_seen, category, is_favorite)
+         SELECT id, url, title, domain, first_seen, last_seen, category, is_favorite
+         FROM main.url
+         WHERE last_seen >= ?
+            OR id IN (SELECT url_id FROM bundle.visit
+                      UNION SELECT url_id FROM bundle.url_tag
+                      UNION SELECT url_id FROM bundle.collection_item)",
+        [since],
+    )?;
+    Ok(())
+}
+
+/// Writes the history changed since `since` (everything when None) into a
+/// bundle encrypted with the passphrase
+pub fn create_sync_bundle(
+    conn: &DatabaseConnection,
+    since: Option<DateTime<Utc>>,
+    path: &Path,
+    passphrase: &str,
+) -> Result<BundleSummary> {
+    check_passphrase(passphrase)?;
+    if path == conn.path.as_path() {
+        return Err(SyncError::Invalid("A bundle cannot replace the database".to_string()));
+    }
+
+    let partial = partial_path(path);
+    let _ = fs::remove_file(&partial);
+
+    let c = conn.get()?;
+    c.execute(
+        "ATTACH DATABASE ?1 AS bundle KEY ?2",
+        params![partial.to_string_lossy(), passphrase],
+    ).map_err(|e| SyncError::Bundle(format!("Failed to create the bundle: {}", e)))?;
+
+    let filled = (|| {
+        // A single file, without a WAL beside it
+        c.execute_batch("PRAGMA bundle.journal_mode = DELETE;")?;
+        c.execute_batch(BUNDLE_SCHEMA)?;
+        fill_bundle(&c, since.map_or(0, |since| since.timestamp()))?;
+
+        Ok(BundleSummary {
+            path: path.display().to_string(),
+            since,
+            urls: count(&c, "url")?,
+            visits: count(&c, "visit")?,
+            deletions: count(&c, "visit_tombstone")?,
+            tags: count(&c, "url_tag")?,
+            collection_items: count(&c, "collection_item")?,
+            size_bytes: 0,
+        })
+    })();
+    c.execute_batch("DETACH DATABASE bundle;")?;
+
+    let mut summary = match filled {
+        Ok(summary) => summary,
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            return Err(e);
+        },
+    };
+    fs::rename(&partial, path)?;
+    summary.size_bytes = fs::metadata(path)?.len();
+    Ok(summary)
+}
+
+/// Merges the bundle's URLs into this database and maps bundle ids to local ids
+fn merge_urls(c: &Connection, result: &mut ApplyBundleResult) -> Result<()> {
+    c.execute_batch(
+        "DROP TABLE IF EXISTS temp.bundle_url_map;
+         CREATE TEMP TABLE bundle_url_map (bundle_id TEXT PRIMARY KEY, id TEXT NOT NULL);"
+    )?;
+
+    let mut stmt = c.prepare(
+        "SELECT id, url, title, domain, first_seen, last_seen, category, is_favorite FROM bundle.url"
+    )?;
+    let urls = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?,
+            row.get::<_, String>(3)?, row.get::<_, i64>(4)?, row.get::<_, i64>(5)?,
+            row.get::<_, Option<String>>(6)?, row.get::<_, i64>(7)?,
+        ))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut map = c.prepare("INSERT INTO temp.bundle_url_map (bundle_id, id) VALUES (?, ?)")?;
+    for (bundle_id, url, title, domain, first_seen, last_seen, category, is_favorite) in urls {
+        let existing: Option<String> = c.query_row(
+            "SELECT id FROM main.url WHERE url = ?", [&url], |row| row.get(0),
+        ).optional()?;
+
+        let id = match existing {
+            Some(id) => {
+                c.execute(
+                    "UPDATE main.url SET
+                         first_seen = MIN(first_seen, ?1),
+                         last_seen = MAX(last_seen, ?2),
+                         title = COALESCE(title, ?3),
+                         category = COALESCE(category, ?4),
+                         is_favorite = MAX(is_favorite, ?5)
+                     WHERE id = ?6",
+                    params![first_seen, last_seen, title, category, is_favorite, id],
+                )?;
+                result.urls_merged += 1;
+                id
+            },
+            None => {
+                // Machines that imported the same file share ids; never reuse a taken one
+                let taken: bool = c.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM main.url WHERE id = ?)", [&bundle_id], |row| row.get(0),
+                )?;
+                let id = if taken { Uuid::new_v4().to_string() } else { bundle_id.clone() };
+
+                c.execute(
+                    "INSERT INTO main.url (id, url, title, domain, first_seen, last_seen, category, is_favorite)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                    params![id, url, title, domain, first_seen, last_seen, category, is_favorite],
+                )?;
+                c.execute("INSERT OR IGNORE INTO main.metadata (url_id, is_enriched) VALUES (?, 0)", [&id])?;
+                result.urls_added += 1;
+                id
+            },
+        };
+        map.execute(params![bundle_id, id])?;
+    }
+    Ok(())
+}
+
+/// Applies the bundle's deletions, then adds the visits neither side deleted
+fn merge_visits(c: &Connection, result: &mut ApplyBundleResult) -> Result<()> {
+    c.execute(
+        "INSERT OR IGNORE INTO main.visit_tombstone (url, visited_at, deleted_at)
+         SELECT url, visited_at, deleted_at FROM bundle.visit_tombstone",
+        [],
+    )?;
+    result.visits_removed = c.execute(
+        "DELETE FROM main.visit WHERE EXISTS (
+             SELECT 1 FROM bundle.visit_tombstone t JOIN main.url u ON u.url = t.url
+             WHERE u.id = visit.url_id AND t.visited_at = visit.visited_at
+         )",
+        [],
+    )?;
+
+    let mut stmt = c.prepare(
+        "SELECT m.id, v.visited_at, v.visit_count, v.source_file, v.device_name, v.duration_sec
+         FROM bundle.visit v
+         JOIN temp.bundle_url_map m ON m.bundle_id = v.url_id
+         JOIN main.url u ON u.id = m.id
+         WHERE NOT EXISTS (
+             SELECT 1 FROM main.visit_tombstone t WHERE t.url = u.url AND t.visited_at = v.visited_at
+         )"
+    )?;
+    let visits = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?,
+            row.get::<_, String>(3)?, row.get::<_, Option<String>>(4)?, row.get::<_, Option<f64>>(5)?,
+        ))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut insert = c.prepare(
+        "INSERT INTO main.visit (id, url_id, visited_at, visit_count, source_file, device_name, duration_sec)
+         VALUES (?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT (url_id, visited_at, source_file) DO NOTHING"
+    )?;
+    for (url_id, visited_at, visit_count, source_file, device_name, duration_sec) in visits {
+        result.visits_added += insert.execute(params![
+            Uuid::new_v4().to_string(), url_id, visited_at, visit_count, source_file, device_name, duration_sec,
+        ])?;
+    }
+
+    // Visits from another machine's devices are attributed like imported ones
+    c.execute(
+        "INSERT OR IGNORE INTO main.device (name) SELECT DISTINCT device_name FROM bundle.visit WHERE device_name IS NOT NULL",
+        [],
+    )?;
+    c.execute(
+        "UPDATE main.visit SET device_id = (SELECT id FROM main.device d WHERE d.name = visit.device_name)
+         WHERE device_id IS NULL AND device_name IS NOT NULL",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Adds the bundle's tags and tag assignments
+fn merge_tags(c: &Connection, result: &mut ApplyBundleResult) -> Result<()> {
+    c.execute(
+        "INSERT OR IGNORE INTO main.tag (name, created_at)
+         SELECT tag, MIN(created_at) FROM bundle.url_tag GROUP BY tag",
+        [],
+    )?;
+    result.tags_added = c.execute(
+        "INSERT OR IGNORE INTO main.url_tag (url_id, tag_id, created_at)
+         SELECT m.id, t.id, bt.created_at
+         FROM bundle.url_tag bt
+         JOIN main.tag t ON t.name = bt.tag
+         JOIN temp.bundle_url_map m ON m.bundle_id = bt.url_id",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Adds the bundle's collections and items, and fills in notes
+fn merge_collections(c: &Connection, result: &mut ApplyBundleResult) -> Result<()> {
+    c.execute(
+        "INSERT OR IGNORE INTO main.collection (name, description, created_at, updated_at)
+         SELECT name, description, created_at, updated_at FROM bundle.collection",
+        [],
+    )?;
+
+    let mut stmt = c.prepare(
+        "SELECT lc.id, bi.collection, m.id, u.url, bi.note, bi.added_at
+         FROM bundle.collection_item bi
+         JOIN main.collection lc ON lc.name = bi.collection
+         JOIN temp.bundle_url_map m ON m.bundle_id = bi.url_id
+         JOIN main.url u ON u.id = m.id
+         ORDER BY bi.collection, bi.position"
+    )?;
+    let items = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?, row.get::<_, Option<String>>(4)?, row.get::<_, i64>(5)?,
+        ))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let now = Utc::now().timestamp();
+    for (collection_id, collection, url_id, url, note, added_at) in items {
+        let local_note: Option<Option<String>> = c.query_row(
+            "SELECT note FROM main.collection_item WHERE collection_id = ? AND url_id = ?",
+            params![collection_id, url_id],
+            |row| row.get(0),
+        ).optional()?;
+
+        match (local_note, note) {
+            // New here: appended after the local items
+            (None, note) => {
+                c.execute(
+                    "INSERT INTO main.collection_item (collection_id, url_id, position, note, added_at)
+                     VALUES (?1, ?2, (SELECT COALESCE(MAX(position) + 1, 0) FROM main.collection_item WHERE collection_id = ?1), ?3, ?4)",
+                    params![collection_id, url_id, note, added_at],
+                )?;
+                result.collection_items_added += 1;
+            },
+            (Some(None), Some(note)) => {
+                c.execute(
+                    "UPDATE main.collection_item SET note = ? WHERE collection_id = ? AND url_id = ?",
+                    params![note, collection_id, url_id],
+                )?;
+                result.notes_updated += 1;
+            },
+            (Some(Some(local_note)), Some(bundle_note)) if local_note != bundle_note => {
+                result.conflicts.push(NoteConflict { collection, url, local_note, bundle_note });
+                continue;
+            },
+            _ => continue,
+        }
+        c.execute("UPDATE main.collection SET updated_at = ? WHERE id = ?", params![now, collection_id])?;
+    }
+    Ok(())
+}
+
+/// Merges the attached bundle into the main database
+fn merge_bundle(c: &Connection) -> Result<ApplyBundleResult> {
+    let (format, created_at): (i64, i64) = c.query_row(
+        "SELECT format, created_at FROM bundle.bundle_info LIMIT 1",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).map_err(|_| SyncError::Bundle("The file is not a sync bundle".to_string()))?;
+    if format != BUNDLE_FORMAT {
+        return Err(SyncError::Bundle(format!(
+            "Bundle format {} is not supported (this app reads format {})", format, BUNDLE_FORMAT,
+        )));
+    }
+
+    let mut result = ApplyBundleResult {
+        created_at: DateTime::from_timestamp(created_at, 0),
+        ..ApplyBundleResult::default()
+    };
+    merge_urls(c, &mut result)?;
+    merge_visits(c, &mut result)?;
+    merge_tags(c, &mut result)?;
+    merge_collections(c, &mut result)?;
+    c.execute_batch("DROP TABLE temp.bundle_url_map;")?;
+
+    Ok(result)
+}
+
+/// Merges a bundle from another machine into this database
+pub fn apply_sync_bundle(conn: &DatabaseConnection, path: &Path, passphrase: &str) -> Result<ApplyBundleResult> {
+    if !path.is_file() {
+        return Err(SyncError::Invalid(format!("{} does not exist", path.display())));
+    }
+
+    let mut c = conn.get()?;
+    c.execute(
+        "ATTACH DATABASE ?1 AS bundle KEY ?2",
+        params![path.to_string_lossy(), passphrase],
+    ).map_err(|e| SyncError::Bundle(format!("Failed to open the bundle: {}", e)))?;
+
+    let result = (|| {
+        // SQLCipher only notices a wrong key when the first page is read
+        c.query_row("SELECT COUNT(*) FROM bundle.sqlite_master", [], |row| row.get::<_, i64>(0))
+            .map_err(|_| SyncError::Bundle("Wrong passphrase or not a sync bundle".to_string()))?;
+
+        let tx = c.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let result = merge_bundle(&tx)?;
+        tx.commit()?;
+        Ok(result)
+    })();
+
+    c.execute_batch("DETACH DATABASE bundle;")?;
+
+    result
+}
@@ -0,0 +1,66 @@
+// Sync Error Handling
+// Defines error types for sync bundles and LAN sync
+
+use std::fmt;
+use std::error::Error;
+use std::io;
+
+use crate::db::DatabaseError;
+
+/// Represents errors that can occur while creating or applying a sync bundle or syncing with a peer
+#[derive(Debug)]
+pub enum SyncError {
+    /// Reading or merging the history failed
+    Database(DatabaseError),
+    /// The bundle file could not be written or moved into place
+    Io(io::Error),
+    /// The bundle can't be opened with the passphrase, or isn't a bundle
+    Bundle(String),
+    /// A peer could not be reached or answered with an error
+    Network(String),
+    /// The request itself is invalid
+    Invalid(String),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncError::Database(err) => write!(f, "Database error: {}", err),
+            SyncError::Io(err) => write!(f, "IO error: {}", err),
+            SyncError::Bundle(msg) => write!(f, "Sync bundle error: {}", msg),
+            SyncError::Network(msg) => write!(f, "Network error: {}", msg),
+            SyncError::Invalid(msg) => write!(f, "Invalid sync request: {}", msg),
+        }
+    }
+}
+
+impl Error for SyncError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SyncError::Database(err) => Some(err),
+            SyncError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DatabaseError> for SyncError {
+    fn from(err: DatabaseError) -> Self {
+        SyncError::Database(err)
+    }
+}
+
+impl From<rusqlite::Error> for SyncError {
+    fn from(err: rusqlite::Error) -> Self {
+        SyncError::Database(DatabaseError::from(err))
+    }
+}
+
+impl From<io::Error> for SyncError {
+    fn from(err: io::Error) -> Self {
+        SyncError::Io(err)
+    }
+}
+
+/// Result type for sync operations
+pub type Result<T> = std::result::Result<T, SyncError>;
@@ -0,0 +1,265 @@
+// Sync - HTTPS
+// The small HTTP/1.1 listener peers talk to. Every connection must present a
+// client certificate, and each request carries the fingerprint of the one
+// seen in the handshake, so handlers can tell which instance they talk to
+// without trusting anything the peer writes in the body.
+//
+// One request per connection: the answer closes it.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use serde_json::json;
+
+use super::error::{Result, SyncError};
+use super::tls;
+
+/// Largest request body accepted
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+/// Largest request line plus headers accepted
+const MAX_HEAD_BYTES: u64 = 16 * 1024;
+
+/// Connections handled at once; more are closed straight away
+const MAX_CONNECTIONS: usize = 8;
+
+/// Time a peer may stay silent mid-request
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time a peer may take to read the answer, bundles included
+const WRITE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often the listener checks whether it should stop
+const ACCEPT_POLL: Duration = Duration::from_millis(200);
+
+/// A request from a peer
+pub struct PeerRequest {
+    /// `GET`, `POST`, ...
+    pub method: String,
+    /// Path and query string
+    pub target: String,
+    /// Body, at most `MAX_BODY_BYTES`
+    pub body: Vec<u8>,
+    /// Fingerprint of the client certificate presented in the handshake
+    pub fingerprint: String,
+    headers: Vec<(String, String)>,
+}
+
+impl PeerRequest {
+    /// Target without its query string
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    /// Value of a header, whatever its case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An answer to a peer
+pub struct PeerResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Box<dyn Read + Send>,
+    length: u64,
+}
+
+impl PeerResponse {
+    /// A JSON answer
+    pub fn json(status: u16, body: serde_json::Value) -> Self {
+        let body = body.to_string().into_bytes();
+        PeerResponse {
+            status,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            length: body.len() as u64,
+            body: Box::new(io::Cursor::new(body)),
+        }
+    }
+
+    /// An answer streamed from a reader of known length
+    pub fn stream(status: u16, headers: Vec<(String, String)>, body: Box<dyn Read + Send>, length: u64) -> Self {
+        PeerResponse { status, headers, body, length }
+    }
+}
+
+/// Running listener; it stops when dropped
+pub struct HttpsServer {
+    stopped: Arc<AtomicBool>,
+}
+
+impl HttpsServer {
+    /// Listens on every interface on `port`, answering each request with `handler`
+    pub fn start<H>(port: u16, config: Arc<ServerConfig>, handler: H) -> Result<Self>
+    where
+        H: Fn(&PeerRequest) -> PeerResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|e| SyncError::Network(format!("Failed to listen on port {}: {}", port, e)))?;
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let running = Arc::clone(&stopped);
+        let handler = Arc::new(handler);
+        let active = Arc::new(AtomicUsize::new(0));
+        thread::spawn(move || {
+            while !running.load(Ordering::SeqCst) {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    // Nothing waiting, or a connection that failed before it was accepted
+                    Err(_) => {
+                        thread::sleep(ACCEPT_POLL);
+                        continue;
+                    },
+                };
+                if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    continue;
+                }
+
+                let config = Arc::clone(&config);
+                let handler = Arc::clone(&handler);
+                let active = Arc::clone(&active);
+                thread::spawn(move || {
+                    let _ = serve(stream, config, handler.as_ref());
+                    active.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        Ok(HttpsServer { stopped })
+    }
+}
+
+impl Drop for HttpsServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Completes the handshake, reads one request and writes its answer
+fn serve<H>(stream: TcpStream, config: Arc<ServerConfig>, handler: &H) -> io::Result<()>
+where
+    H: Fn(&PeerRequest) -> PeerResponse,
+{
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+    let connection = ServerConnection::new(config)
+        .map_err(io::Error::other)?;
+    let mut tls_stream = StreamOwned::new(connection, stream);
+    while tls_stream.conn.is_handshaking() {
+        tls_stream.conn.complete_io(&mut tls_stream.sock)?;
+    }
+    // The verifier makes a client certificate mandatory
+    let fingerprint = tls_stream.conn.peer_certificates()
+        .and_then(|certificates| certificates.first())
+        .map(|certificate| tls::fingerprint(&certificate.0))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::PermissionDenied, "no client certificate"))?;
+
+    let mut reader = BufReader::new(tls_stream);
+    let response = match read_request(&mut reader, fingerprint) {
+        Ok(request) => handler(&request),
+        Err(response) => response,
+    };
+    let mut tls_stream = reader.into_inner();
+    write_response(&mut tls_stream, response)?;
+    tls_stream.flush()?;
+    tls_stream.conn.send_close_notify();
+    tls_stream.conn.complete_io(&mut tls_stream.sock).map(|_| ())
+}
+
+/// Parses the request line, headers and body
+fn read_request<R: BufRead>(reader: &mut R, fingerprint: String) -> std::result::Result<PeerRequest, PeerResponse> {
+    let bad_request = |message: &str| PeerResponse::json(400, json!({ "error": message }));
+
+    let mut head = reader.by_ref().take(MAX_HEAD_BYTES);
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        match head.read_line(&mut line) {
+            Ok(0) => return Err(bad_request("Request ended early")),
+            Ok(_) if !line.ends_with('\n') => return Err(bad_request("Request headers are too large")),
+            Ok(_) => {},
+            Err(_) => return Err(bad_request("Failed to read the request")),
+        }
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line);
+    }
+
+    let mut lines = lines.into_iter();
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+            (method.to_string(), target.to_string())
+        },
+        _ => return Err(bad_request("Malformed request line")),
+    };
+    let mut headers = Vec::new();
+    for line in lines {
+        match line.split_once(':') {
+            Some((field, value)) => headers.push((field.trim().to_string(), value.trim().to_string())),
+            None => return Err(bad_request("Malformed header")),
+        }
+    }
+
+    let mut request = PeerRequest { method, target, body: Vec::new(), fingerprint, headers };
+    if request.header("Transfer-Encoding").is_some() {
+        return Err(PeerResponse::json(411, json!({ "error": "A Content-Length is required" })));
+    }
+    let length = match request.header("Content-Length").map(str::parse::<u64>) {
+        None => 0,
+        Some(Ok(length)) => length,
+        Some(Err(_)) => return Err(bad_request("Malformed Content-Length")),
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(PeerResponse::json(413, json!({ "error": "Body is too large" })));
+    }
+    request.body = vec![0; length as usize];
+    if reader.read_exact(&mut request.body).is_err() {
+        return Err(bad_request("Request ended early"));
+    }
+    Ok(request)
+}
+
+/// Reason phrase of the statuses the handlers use
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Writes the status line, headers and body, then closes the connection
+fn write_response<W: Write>(writer: &mut W, mut response: PeerResponse) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason(response.status));
+    for (field, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", field, value));
+    }
+    head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", response.length));
+    writer.write_all(head.as_bytes())?;
+    io::copy(&mut response.body.by_ref().take(response.length), writer)?;
+    Ok(())
+}
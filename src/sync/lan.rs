@@ -0,0 +1,772 @@
+// Sync - LAN
+// Service keeping paired instances on the same network converged: announces
+// itself over mDNS, pairs with a code both users compare, and pulls sync
+// bundles from every paired peer it can see
+//
+// Each instance pulls the other's changes, so after a round both hold the
+// union. Requests travel over mutual TLS, each side pinned to the
+// certificate the other presented at pairing, and carry the shared secret,
+// which also encrypts the bundles.
+//
+// Pairing takes three requests to the instance that allows it:
+// - `POST /pair/request` sends a commitment to the requester's nonce and
+//   gets the responder's nonce back
+// - `POST /pair/reveal` sends the nonce itself; both sides now derive the
+//   code from the two certificates and the two nonces
+// - `POST /pair/confirm`, once the users compared the codes, hands over the
+//   shared secret
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::capture::server::tokens_equal;
+use crate::db::DatabaseConnection;
+use super::bundle::{apply_sync_bundle, create_sync_bundle, ApplyBundleResult};
+use super::error::{Result, SyncError};
+use super::https::{HttpsServer, PeerRequest, PeerResponse};
+use super::peers::{self, LanSyncSettings, SyncIdentity, SyncPeer};
+use super::tls;
+
+/// mDNS service type instances announce
+pub const SERVICE_TYPE: &str = "_historykg-sync._tcp.local.";
+
+/// How long an instance accepts a pairing request after the user allows it
+const PAIRING_WINDOW: Duration = Duration::from_secs(120);
+
+/// Changes are pulled again from this long before the last bundle was made,
+/// covering visits written while it was being created
+const PULL_OVERLAP_SECS: i64 = 60;
+
+/// Time given to discovery before the first round of syncs
+const FIRST_SYNC_DELAY: Duration = Duration::from_secs(15);
+
+/// Header naming the instance a sync request comes from
+const PEER_HEADER: &str = "X-Sync-Peer";
+
+/// Header telling when a bundle was made
+const CREATED_HEADER: &str = "X-Bundle-Created";
+
+/// An instance seen on the network
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredPeer {
+    /// Its instance id
+    pub id: String,
+    /// Name it announces
+    pub name: String,
+    /// Fingerprint of its certificate, as announced
+    pub fingerprint: String,
+    /// Addresses it can be reached at
+    pub addresses: Vec<String>,
+    /// Port of its service
+    pub port: u16,
+    /// Whether it is paired with this instance
+    pub paired: bool,
+    /// mDNS name, to notice it leaving
+    #[serde(skip)]
+    full_name: String,
+}
+
+/// A pairing request waiting for this instance's user
+#[derive(Debug, Clone, Serialize)]
+pub struct PairingRequest {
+    /// Instance id of the requester
+    pub id: String,
+    /// Name it announced
+    pub name: String,
+    /// Code the requester shows too; empty until it revealed its nonce
+    pub code: String,
+    /// Whether the user accepted it
+    pub accepted: bool,
+    /// Fingerprint of the certificate the requester presented
+    #[serde(skip)]
+    fingerprint: String,
+    /// The requester's commitment to its nonce
+    #[serde(skip)]
+    commitment: String,
+    /// This instance's nonce
+    #[serde(skip)]
+    nonce: String,
+    /// Secret handed over once the requester confirms
+    #[serde(skip)]
+    secret: String,
+}
+
+/// Code to compare with the one the other instance shows
+#[derive(Debug, Clone, Serialize)]
+pub struct PairingCode {
+    /// Instance id of the other side
+    pub peer_id: String,
+    /// Its name
+    pub peer_name: String,
+    /// Six digits
+    pub code: String,
+}
+
+/// Something the frontend should hear about
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LanSyncEvent {
+    /// Another instance asks to pair; show the code and ask the user
+    PairingRequested { request: PairingRequest },
+    /// A pairing completed
+    Paired { peer: String },
+    /// Changes were pulled from a peer
+    Synced { peer: String, result: ApplyBundleResult },
+    /// Syncing with a peer failed
+    Failed { peer: String, error: String },
+}
+
+/// Outcome of pulling from one peer
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncOutcome {
+    /// Peer name
+    pub peer: String,
+    /// What was merged, if the pull succeeded
+    pub result: Option<ApplyBundleResult>,
+    /// Why it failed
+    pub error: Option<String>,
+}
+
+/// LAN sync as the settings screen shows it
+#[derive(Debug, Clone, Serialize)]
+pub struct LanSyncStatus {
+    /// Whether the service is running
+    pub running: bool,
+    /// This instance's id, once the service has run
+    pub instance_id: Option<String>,
+    /// Paired instances
+    pub peers: Vec<SyncPeer>,
+    /// Instances seen on the network
+    pub discovered: Vec<DiscoveredPeer>,
+    /// Pairing request waiting for the user
+    pub pairing: Option<PairingRequest>,
+}
+
+/// State of the pairing window
+#[derive(Default)]
+struct Pairing {
+    /// Requests are accepted until then
+    open_until: Option<Instant>,
+    /// Request from another instance
+    incoming: Option<PairingRequest>,
+}
+
+/// State shared by the listener, discovery and sync threads
+struct Shared {
+    identity: SyncIdentity,
+    name: String,
+    interval: Duration,
+    discovered: Mutex<HashMap<String, DiscoveredPeer>>,
+    pairing: Mutex<Pairing>,
+    stopped: AtomicBool,
+    database: Box<dyn Fn() -> Option<DatabaseConnection> + Send + Sync>,
+    on_event: Box<dyn Fn(LanSyncEvent) + Send + Sync>,
+}
+
+/// Running service; it stops when dropped
+pub struct LanSync {
+    _server: HttpsServer,
+    mdns: ServiceDaemon,
+    shared: Arc<Shared>,
+}
+
+/// Body of `POST /pair/request`
+#[derive(Serialize, Deserialize)]
+struct PairingHello {
+    id: String,
+    name: String,
+    /// Commitment to the nonce revealed next
+    commitment: String,
+}
+
+/// Answer to a pairing request
+#[derive(Serialize, Deserialize)]
+struct PairingStarted {
+    id: String,
+    name: String,
+    nonce: String,
+}
+
+/// Body of `POST /pair/reveal`
+#[derive(Serialize, Deserialize)]
+struct PairingReveal {
+    id: String,
+    nonce: String,
+}
+
+/// Body of `POST /pair/confirm`
+#[derive(Serialize, Deserialize)]
+struct PairingConfirm {
+    id: String,
+}
+
+/// Answer to a confirmed pairing
+#[derive(Serialize, Deserialize)]
+struct PairingAccepted {
+    id: String,
+    name: String,
+    secret: String,
+}
+
+/// A bundle file removed once it has been sent
+struct TempBundle {
+    file: File,
+    path: PathBuf,
+}
+
+impl Read for TempBundle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Drop for TempBundle {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Path of a bundle exchanged with a peer
+fn temp_bundle_path() -> PathBuf {
+    std::env::temp_dir().join(format!("historykg-sync-{}.bundle", Uuid::new_v4().simple()))
+}
+
+/// Generates the secret shared with a new peer
+fn new_secret() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+impl LanSync {
+    /// Listens on every interface on the configured port, announces this
+    /// instance and starts syncing with paired peers. `database` returns the
+    /// open database, or None while it is closed or the app is locked.
+    pub fn start<D, E>(settings: &LanSyncSettings, identity: SyncIdentity, database: D, on_event: E) -> Result<Self>
+    where
+        D: Fn() -> Option<DatabaseConnection> + Send + Sync + 'static,
+        E: Fn(LanSyncEvent) + Send + Sync + 'static,
+    {
+        let config = tls::server_config(&identity)?;
+        let shared = Arc::new(Shared {
+            identity,
+            name: settings.display_name(),
+            interval: Duration::from_secs(u64::from(settings.interval_minutes) * 60),
+            discovered: Mutex::new(HashMap::new()),
+            pairing: Mutex::new(Pairing::default()),
+            stopped: AtomicBool::new(false),
+            database: Box::new(database),
+            on_event: Box::new(on_event),
+        });
+
+        let listener = Arc::clone(&shared);
+        let server = HttpsServer::start(settings.port, config, move |request| handle(request, &listener))?;
+
+        let mdns = ServiceDaemon::new()
+            .map_err(|e| SyncError::Network(format!("Failed to start discovery: {}", e)))?;
+        let service = LanSync { _server: server, mdns, shared };
+        service.announce(settings.port)?;
+        service.browse()?;
+
+        let shared = Arc::clone(&service.shared);
+        thread::spawn(move || sync_loop(&shared));
+
+        Ok(service)
+    }
+
+    /// Registers this instance with mDNS
+    fn announce(&self, port: u16) -> Result<()> {
+        let host = hostname::get().ok()
+            .and_then(|name| name.into_string().ok())
+            .unwrap_or_else(|| self.shared.identity.instance_id.clone());
+        let properties = [
+            ("id", self.shared.identity.instance_id.as_str()),
+            ("name", self.shared.name.as_str()),
+            ("fp", self.shared.identity.fingerprint.as_str()),
+        ];
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            &self.shared.identity.instance_id,
+            &format!("{}.local.", host.trim_end_matches(".local")),
+            "",
+            port,
+            &properties[..],
+        )
+        .map_err(|e| SyncError::Network(format!("Failed to announce this instance: {}", e)))?
+        .enable_addr_auto();
+
+        self.mdns.register(info)
+            .map_err(|e| SyncError::Network(format!("Failed to announce this instance: {}", e)))
+    }
+
+    /// Keeps the list of instances on the network up to date
+    fn browse(&self) -> Result<()> {
+        let events = self.mdns.browse(SERVICE_TYPE)
+            .map_err(|e| SyncError::Network(format!("Failed to browse the network: {}", e)))?;
+
+        let shared = Arc::clone(&self.shared);
+        thread::spawn(move || {
+            // Ends once the daemon shuts down on drop
+            while let Ok(event) = events.recv() {
+                let mut discovered = match shared.discovered.lock() {
+                    Ok(discovered) => discovered,
+                    Err(_) => break,
+                };
+                match event {
+                    ServiceEvent::ServiceResolved(info) => {
+                        let property = |key: &str| info.get_property_val_str(key).map(str::to_string);
+                        let (id, fingerprint) = match (property("id"), property("fp")) {
+                            (Some(id), Some(fingerprint)) => (id, fingerprint),
+                            _ => continue,
+                        };
+                        if id == shared.identity.instance_id {
+                            continue;
+                        }
+                        let mut addresses: Vec<String> = info.get_addresses().iter().map(|ip| ip.to_string()).collect();
+                        addresses.sort();
+                        discovered.insert(id.clone(), DiscoveredPeer {
+                            name: property("name").unwrap_or_else(|| id.clone()),
+                            id,
+                            fingerprint,
+                            addresses,
+                            port: info.get_port(),
+                            paired: false,
+                            full_name: info.get_fullname().to_string(),
+                        });
+                    },
+                    ServiceEvent::ServiceRemoved(_, full_name) => {
+                        discovered.retain(|_, peer| peer.full_name != full_name);
+                    },
+                    _ => {},
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Instances seen on the network, marking the paired ones
+    pub fn discovered(&self, paired: &[SyncPeer]) -> Vec<DiscoveredPeer> {
+        let mut peers: Vec<DiscoveredPeer> = self.shared.discovered.lock()
+            .map(|discovered| discovered.values().cloned().collect())
+            .unwrap_or_default();
+        for peer in &mut peers {
+            peer.paired = paired.iter().any(|p| p.id == peer.id);
+        }
+        peers.sort_by(|a, b| a.name.cmp(&b.name));
+        peers
+    }
+
+    /// This instance's id
+    pub fn instance_id(&self) -> &str {
+        &self.shared.identity.instance_id
+    }
+
+    /// The pairing request waiting for the user, if any
+    pub fn pending_pairing(&self) -> Option<PairingRequest> {
+        let pairing = self.shared.pairing.lock().ok()?;
+        pairing.incoming.clone()
+            .filter(|incoming| !incoming.code.is_empty())
+            .filter(|_| pairing.open_until.is_some_and(|until| Instant::now() < until))
+    }
+
+    /// Lets another instance ask to pair for the next two minutes
+    pub fn allow_pairing(&self) {
+        if let Ok(mut pairing) = self.shared.pairing.lock() {
+            pairing.open_until = Some(Instant::now() + PAIRING_WINDOW);
+            pairing.incoming = None;
+        }
+    }
+
+    /// Accepts or refuses the waiting pairing request, after the user checked
+    /// the code matches the other screen
+    pub fn answer_pairing(&self, accept: bool) -> Result<()> {
+        let mut pairing = self.shared.pairing.lock()
+            .map_err(|_| SyncError::Invalid("Pairing state is unavailable".to_string()))?;
+        match pairing.incoming.as_mut() {
+            Some(request) if request.code.is_empty() => {
+                return Err(SyncError::Invalid("No instance asked to pair".to_string()));
+            },
+            Some(request) if accept => request.accepted = true,
+            Some(_) => *pairing = Pairing::default(),
+            None => return Err(SyncError::Invalid("No instance asked to pair".to_string())),
+        }
+        Ok(())
+    }
+
+    /// Finds a discovered instance
+    fn find_discovered(&self, id: &str) -> Result<DiscoveredPeer> {
+        self.shared.discovered.lock().ok()
+            .and_then(|discovered| discovered.get(id).cloned())
+            .ok_or_else(|| SyncError::Network(format!("Instance {} is not on the network", id)))
+    }
+
+    /// Asks a discovered instance to pair; returns the code both users compare
+    pub fn request_pairing(&self, id: &str) -> Result<PairingCode> {
+        let peer = self.find_discovered(id)?;
+        let (agent, presented) = tls::peer_agent(&self.shared.identity, Some(&peer.fingerprint))?;
+        let nonce = tls::new_nonce();
+        let hello = PairingHello {
+            id: self.shared.identity.instance_id.clone(),
+            name: self.shared.name.clone(),
+            commitment: tls::nonce_commitment(&nonce),
+        };
+        let started: PairingStarted = serde_json::from_str(&post(&agent, &peer, "/pair/request", &hello)?)
+            .map_err(|e| SyncError::Network(format!("Unexpected answer: {}", e)))?;
+        if started.id != peer.id {
+            return Err(SyncError::Network("Another instance answered".to_string()));
+        }
+        let reveal = PairingReveal { id: self.shared.identity.instance_id.clone(), nonce: nonce.clone() };
+        post(&agent, &peer, "/pair/reveal", &reveal)?;
+
+        // The verifier refused any other certificate than the announced one
+        let fingerprint = presented.lock().ok().and_then(|seen| seen.clone())
+            .ok_or_else(|| SyncError::Network("The instance presented no certificate".to_string()))?;
+        Ok(PairingCode {
+            peer_id: peer.id,
+            peer_name: peer.name,
+            code: tls::pairing_code(&self.shared.identity.fingerprint, &fingerprint, &nonce, &started.nonce),
+        })
+    }
+
+    /// Completes a pairing once the user checked the codes match and the
+    /// other instance accepted; returns the new peer's name
+    pub fn confirm_pairing(&self, id: &str) -> Result<String> {
+        let peer = self.find_discovered(id)?;
+        let (agent, _) = tls::peer_agent(&self.shared.identity, Some(&peer.fingerprint))?;
+        let confirm = PairingConfirm { id: self.shared.identity.instance_id.clone() };
+        let accepted: PairingAccepted = serde_json::from_str(&post(&agent, &peer, "/pair/confirm", &confirm)?)
+            .map_err(|e| SyncError::Network(format!("Unexpected answer: {}", e)))?;
+        if accepted.id != peer.id {
+            return Err(SyncError::Network("Another instance answered".to_string()));
+        }
+
+        let conn = (self.shared.database)()
+            .ok_or_else(|| SyncError::Invalid("The database is not open".to_string()))?;
+        peers::save_peer(&conn, &peer.id, &accepted.name, &peer.fingerprint, &accepted.secret)?;
+        (self.shared.on_event)(LanSyncEvent::Paired { peer: accepted.name.clone() });
+        Ok(accepted.name)
+    }
+
+    /// Pulls changes from every paired instance on the network now
+    pub fn sync_now(&self) -> Result<Vec<SyncOutcome>> {
+        let conn = (self.shared.database)()
+            .ok_or_else(|| SyncError::Invalid("The database is not open".to_string()))?;
+        sync_round(&self.shared, &conn)
+    }
+}
+
+/// Paired and discovered instances, with or without a running service
+pub fn lan_sync_status(service: Option<&LanSync>, conn: &DatabaseConnection) -> Result<LanSyncStatus> {
+    let peers = peers::list_peers(conn)?;
+    Ok(LanSyncStatus {
+        running: service.is_some(),
+        instance_id: service.map(|service| service.instance_id().to_string()),
+        discovered: service.map(|service| service.discovered(&peers)).unwrap_or_default(),
+        pairing: service.and_then(LanSync::pending_pairing),
+        peers,
+    })
+}
+
+impl Drop for LanSync {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        let _ = self.mdns.shutdown();
+    }
+}
+
+/// Base URL of a discovered instance
+fn peer_url(peer: &DiscoveredPeer, path: &str) -> Result<String> {
+    let address = peer.addresses.first()
+        .ok_or_else(|| SyncError::Network(format!("{} announced no address", peer.name)))?;
+    let host = if address.contains(':') { format!("[{}]", address) } else { address.clone() };
+    Ok(format!("https://{}:{}{}", host, peer.port, path))
+}
+
+/// Turns a failed request into an error carrying the peer's message
+fn request_error(peer: &DiscoveredPeer, error: ureq::Error) -> SyncError {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let message = response.into_json::<serde_json::Value>().ok()
+                .and_then(|body| body.get("error").and_then(|e| e.as_str()).map(str::to_string))
+                .unwrap_or_else(|| format!("HTTP {}", status));
+            SyncError::Network(format!("{}: {}", peer.name, message))
+        },
+        e => SyncError::Network(format!("Failed to reach {}: {}", peer.name, e)),
+    }
+}
+
+/// Posts a pairing message to a peer and returns the body of its answer
+fn post<T: Serialize>(agent: &ureq::Agent, peer: &DiscoveredPeer, path: &str, body: &T) -> Result<String> {
+    let response = agent.post(&peer_url(peer, path)?)
+        .send_json(body)
+        .map_err(|e| request_error(peer, e))?;
+    response.into_string()
+        .map_err(|e| SyncError::Network(format!("Failed to read the answer of {}: {}", peer.name, e)))
+}
+
+/// Pulls the changes a peer made since the last sync and merges them
+fn pull(shared: &Shared, conn: &DatabaseConnection, peer: &SyncPeer, found: &DiscoveredPeer) -> Result<ApplyBundleResult> {
+    let (agent, _) = tls::peer_agent(&shared.identity, Some(&peer.fingerprint))?;
+    let since = peer.pulled_until.map_or(0, |since| since.timestamp());
+    let response = agent.get(&peer_url(found, &format!("/sync/bundle?since={}", since))?)
+        .set("Authorization", &format!("Bearer {}", peer.secret))
+        .set(PEER_HEADER, &shared.identity.instance_id)
+        .call()
+        .map_err(|e| request_error(found, e))?;
+    let created = response.header(CREATED_HEADER)
+        .and_then(|value| value.parse::<i64>().ok())
+        .and_then(|ts| DateTime::from_timestamp(ts, 0))
+        .ok_or_else(|| SyncError::Network(format!("{} sent no bundle time", found.name)))?;
+
+    let path = temp_bundle_path();
+    let written = File::create(&path)
+        .and_then(|mut file| io::copy(&mut response.into_reader(), &mut file));
+    let result = match written {
+        Ok(_) => apply_sync_bundle(conn, &path, &peer.secret),
+        Err(e) => Err(SyncError::Io(e)),
+    };
+    let _ = fs::remove_file(&path);
+
+    let result = result?;
+    peers::record_sync(conn, &peer.id, Ok(created - chrono::Duration::seconds(PULL_OVERLAP_SECS)))?;
+    Ok(result)
+}
+
+/// Pulls from every paired peer currently on the network
+fn sync_round(shared: &Shared, conn: &DatabaseConnection) -> Result<Vec<SyncOutcome>> {
+    let discovered: HashMap<String, DiscoveredPeer> = shared.discovered.lock()
+        .map(|discovered| discovered.clone())
+        .unwrap_or_default();
+
+    let mut outcomes = Vec::new();
+    for peer in peers::list_peers(conn)? {
+        let found = match discovered.get(&peer.id) {
+            Some(found) => found,
+            None => continue,
+        };
+        match pull(shared, conn, &peer, found) {
+            Ok(result) => {
+                (shared.on_event)(LanSyncEvent::Synced { peer: peer.name.clone(), result: result.clone() });
+                outcomes.push(SyncOutcome { peer: peer.name, result: Some(result), error: None });
+            },
+            Err(e) => {
+                let error = e.to_string();
+                let _ = peers::record_sync(conn, &peer.id, Err(error.clone()));
+                (shared.on_event)(LanSyncEvent::Failed { peer: peer.name.clone(), error: error.clone() });
+                outcomes.push(SyncOutcome { peer: peer.name, result: None, error: Some(error) });
+            },
+        }
+    }
+    Ok(outcomes)
+}
+
+/// Syncs with the paired peers every interval until the service stops
+fn sync_loop(shared: &Shared) {
+    let mut next = Instant::now() + FIRST_SYNC_DELAY;
+    while !shared.stopped.load(Ordering::SeqCst) {
+        if Instant::now() >= next {
+            if let Some(conn) = (shared.database)() {
+                let _ = sync_round(shared, &conn);
+            }
+            next = Instant::now() + shared.interval;
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Builds a JSON response
+fn json_response(status: u16, body: serde_json::Value) -> PeerResponse {
+    PeerResponse::json(status, body)
+}
+
+/// Parses a small JSON body
+fn read_json<T: serde::de::DeserializeOwned>(request: &PeerRequest) -> std::result::Result<T, PeerResponse> {
+    serde_json::from_slice(&request.body)
+        .map_err(|e| json_response(400, json!({ "error": format!("Invalid body: {}", e) })))
+}
+
+/// Records a pairing request while the user allows pairing and answers with
+/// this instance's nonce; the code is only known once the requester reveals its own
+fn pairing_requested(request: &PeerRequest, shared: &Shared) -> PeerResponse {
+    let hello: PairingHello = match read_json(request) {
+        Ok(hello) => hello,
+        Err(response) => return response,
+    };
+
+    let mut pairing = match shared.pairing.lock() {
+        Ok(pairing) => pairing,
+        Err(_) => return json_response(500, json!({ "error": "Pairing state is unavailable" })),
+    };
+    if pairing.open_until.is_none_or(|until| Instant::now() >= until) {
+        return json_response(403, json!({ "error": "Pairing is not allowed on this instance right now" }));
+    }
+    // One requester per window, so a second device can't slip in beside the first
+    let other = pairing.incoming.as_ref()
+        .is_some_and(|incoming| incoming.id != hello.id || incoming.fingerprint != request.fingerprint);
+    if other {
+        return json_response(409, json!({ "error": "Another instance is already pairing" }));
+    }
+
+    let incoming = PairingRequest {
+        id: hello.id,
+        name: hello.name,
+        code: String::new(),
+        accepted: false,
+        fingerprint: request.fingerprint.clone(),
+        commitment: hello.commitment,
+        nonce: tls::new_nonce(),
+        secret: new_secret(),
+    };
+    let started = PairingStarted {
+        id: shared.identity.instance_id.clone(),
+        name: shared.name.clone(),
+        nonce: incoming.nonce.clone(),
+    };
+    pairing.incoming = Some(incoming);
+    json_response(202, json!(started))
+}
+
+/// Checks the requester's nonce against its commitment and shows the code
+fn pairing_revealed(request: &PeerRequest, shared: &Shared) -> PeerResponse {
+    let reveal: PairingReveal = match read_json(request) {
+        Ok(reveal) => reveal,
+        Err(response) => return response,
+    };
+
+    let mut pairing = match shared.pairing.lock() {
+        Ok(pairing) => pairing,
+        Err(_) => return json_response(500, json!({ "error": "Pairing state is unavailable" })),
+    };
+    let open = pairing.open_until.is_some_and(|until| Instant::now() < until);
+    let incoming = match pairing.incoming.as_mut() {
+        Some(incoming) if open && incoming.id == reveal.id && incoming.fingerprint == request.fingerprint => incoming,
+        _ => return json_response(404, json!({ "error": "No pairing request from this instance" })),
+    };
+    if !incoming.code.is_empty() {
+        return json_response(409, json!({ "error": "The nonce was already revealed" }));
+    }
+    if !tokens_equal(&tls::nonce_commitment(&reveal.nonce), &incoming.commitment) {
+        // A requester that changes its nonce after seeing ours gets no second try
+        pairing.incoming = None;
+        return json_response(400, json!({ "error": "The nonce does not match its commitment" }));
+    }
+
+    incoming.code = tls::pairing_code(&request.fingerprint, &shared.identity.fingerprint, &reveal.nonce, &incoming.nonce);
+    let incoming = incoming.clone();
+    drop(pairing);
+
+    (shared.on_event)(LanSyncEvent::PairingRequested { request: incoming });
+    json_response(200, json!({ "id": shared.identity.instance_id }))
+}
+
+/// Hands the secret to the requester once the user here accepted
+fn pairing_confirmed(request: &PeerRequest, shared: &Shared) -> PeerResponse {
+    let confirm: PairingConfirm = match read_json(request) {
+        Ok(confirm) => confirm,
+        Err(response) => return response,
+    };
+
+    let mut pairing = match shared.pairing.lock() {
+        Ok(pairing) => pairing,
+        Err(_) => return json_response(500, json!({ "error": "Pairing state is unavailable" })),
+    };
+    let open = pairing.open_until.is_some_and(|until| Instant::now() < until);
+    let incoming = match pairing.incoming.clone() {
+        Some(incoming) if open && incoming.id == confirm.id && incoming.fingerprint == request.fingerprint => incoming,
+        _ => return json_response(404, json!({ "error": "No pairing request from this instance" })),
+    };
+    if !incoming.accepted {
+        return json_response(409, json!({ "error": format!("Waiting for {} to accept the pairing", shared.name) }));
+    }
+
+    let conn = match (shared.database)() {
+        Some(conn) => conn,
+        None => return json_response(503, json!({ "error": "The database is not open" })),
+    };
+    if let Err(e) = peers::save_peer(&conn, &incoming.id, &incoming.name, &incoming.fingerprint, &incoming.secret) {
+        return json_response(500, json!({ "error": e.to_string() }));
+    }
+    *pairing = Pairing::default();
+    drop(pairing);
+
+    (shared.on_event)(LanSyncEvent::Paired { peer: incoming.name.clone() });
+    let accepted = PairingAccepted {
+        id: shared.identity.instance_id.clone(),
+        name: shared.name.clone(),
+        secret: incoming.secret,
+    };
+    json_response(200, json!(accepted))
+}
+
+/// Sends a paired peer the changes made here since the time it asks for
+fn bundle_requested(request: &PeerRequest, shared: &Shared) -> PeerResponse {
+    let conn = match (shared.database)() {
+        Some(conn) => conn,
+        None => return json_response(503, json!({ "error": "The database is not open" })),
+    };
+    let peer = match request.header(PEER_HEADER).map(|id| peers::get_peer(&conn, id)) {
+        Some(Ok(Some(peer))) => peer,
+        Some(Err(e)) => return json_response(500, json!({ "error": e.to_string() })),
+        _ => return json_response(401, json!({ "error": "Not paired with this instance" })),
+    };
+    // The secret alone is not enough: the connection must come from the paired certificate
+    let authorized = peer.fingerprint == request.fingerprint
+        && request.header("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|sent| tokens_equal(sent.trim(), &peer.secret));
+    if !authorized {
+        return json_response(401, json!({ "error": "Not paired with this instance" }));
+    }
+
+    let since = request.target.split_once("since=")
+        .and_then(|(_, value)| value.split('&').next())
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|since| *since > 0)
+        .and_then(|since| DateTime::from_timestamp(since, 0));
+
+    let created = Utc::now();
+    let path = temp_bundle_path();
+    let bundle = create_sync_bundle(&conn, since, &path, &peer.secret)
+        .and_then(|_| Ok(TempBundle { file: File::open(&path)?, path: path.clone() }));
+    let bundle = bundle.and_then(|bundle| {
+        let length = bundle.file.metadata()?.len();
+        Ok((bundle, length))
+    });
+    let (bundle, length) = match bundle {
+        Ok(bundle) => bundle,
+        Err(e) => {
+            let _ = fs::remove_file(&path);
+            return json_response(500, json!({ "error": e.to_string() }));
+        },
+    };
+
+    let headers = vec![
+        ("Content-Type".to_string(), "application/octet-stream".to_string()),
+        (CREATED_HEADER.to_string(), created.timestamp().to_string()),
+    ];
+    PeerResponse::stream(200, headers, Box::new(bundle), length)
+}
+
+/// Answers one request
+fn handle(request: &PeerRequest, shared: &Shared) -> PeerResponse {
+    match (request.method.as_str(), request.path()) {
+        ("POST", "/pair/request") => pairing_requested(request, shared),
+        ("POST", "/pair/reveal") => pairing_revealed(request, shared),
+        ("POST", "/pair/confirm") => pairing_confirmed(request, shared),
+        ("GET", "/sync/bundle") => bundle_requested(request, shared),
+        (_, "/pair/request") | (_, "/pair/reveal") | (_, "/pair/confirm") | (_, "/sync/bundle") => {
+            json_response(405, json!({ "error": "Method not allowed" }))
+        },
+        _ => json_response(404, json!({ "error": "Not found" })),
+    }
+}
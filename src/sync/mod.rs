@@ -0,0 +1,34 @@
+// Sync Module
+// Moves history changes between machines without a server: an encrypted
+// bundle file carried over iCloud Drive, AirDrop or a USB stick, or the same
+// bundles exchanged automatically with paired instances on the local network
+
+// Module organization:
+// - bundle.rs: Creating and merging sync bundles
+// - peers.rs: LAN sync settings, identity and paired instances
+// - tls.rs: Pinned certificates and pairing codes
+// - https.rs: Listener requiring a client certificate from every peer
+// - lan.rs: Discovery, pairing and periodic sync over the local network
+// - error.rs: Error handling
+//
+// Conflicts are resolved the same way on every machine, so applying bundles
+// in any order converges:
+// - URLs are matched by address; the seen dates widen, a favorite on either
+//   side stays a favorite, and the local title and category are kept unless
+//   missing here
+// - Visits are unioned; a visit deleted on either machine stays deleted
+// - Tags and collection items are unioned; removals are not carried over
+// - A collection note missing here is taken from the bundle; when both sides
+//   wrote different notes the local one is kept and the conflict reported
+
+pub mod bundle;
+pub mod peers;
+pub mod tls;
+pub mod https;
+pub mod lan;
+pub mod error;
+
+pub use bundle::{apply_sync_bundle, create_sync_bundle, ApplyBundleResult, BundleSummary, NoteConflict};
+pub use error::{Result, SyncError};
+pub use lan::{lan_sync_status, LanSync, LanSyncEvent, LanSyncStatus, PairingCode, SyncOutcome};
+pub use peers::{LanSyncSettings, SyncPeer, LAN_SYNC_SETTING};
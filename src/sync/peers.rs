@@ -0,0 +1,190 @@
+// Sync - Peers
+// LAN sync settings, this instance's identity and the instances paired with it
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::settings::{get_setting, set_setting};
+use crate::db::DatabaseConnection;
+use super::error::{Result, SyncError};
+use super::tls;
+
+/// Settings key of the LAN sync service
+pub const LAN_SYNC_SETTING: &str = "lan_sync";
+
+/// Settings key of this instance's id and TLS certificate
+const IDENTITY_SETTING: &str = "lan_sync_identity";
+
+/// Port the service listens on unless configured
+pub const DEFAULT_LAN_SYNC_PORT: u16 = 47617;
+
+/// LAN sync configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanSyncSettings {
+    /// Whether the service listens and syncs with paired peers
+    pub enabled: bool,
+    /// Port on every network interface
+    pub port: u16,
+    /// Name other instances see; the host name when None
+    pub device_name: Option<String>,
+    /// Minutes between syncs with each peer
+    pub interval_minutes: u32,
+}
+
+impl Default for LanSyncSettings {
+    fn default() -> Self {
+        LanSyncSettings {
+            enabled: false,
+            port: DEFAULT_LAN_SYNC_PORT,
+            device_name: None,
+            interval_minutes: 15,
+        }
+    }
+}
+
+impl LanSyncSettings {
+    /// Checks the settings before they are stored
+    pub fn validate(&self) -> Result<()> {
+        if self.port == 0 {
+            return Err(SyncError::Invalid("LAN sync port cannot be 0".to_string()));
+        }
+        if self.interval_minutes == 0 {
+            return Err(SyncError::Invalid("LAN sync interval must be at least one minute".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Name announced to other instances
+    pub fn display_name(&self) -> String {
+        self.device_name.clone()
+            .filter(|name| !name.trim().is_empty())
+            .or_else(|| hostname::get().ok().and_then(|name| name.into_string().ok()))
+            .unwrap_or_else(|| "History Knowledge Graph".to_string())
+    }
+}
+
+/// Gets the LAN sync settings
+pub fn get_lan_sync_settings(conn: &DatabaseConnection) -> Result<LanSyncSettings> {
+    Ok(conn.with_connection(|c| get_setting(c, LAN_SYNC_SETTING))?.unwrap_or_default())
+}
+
+/// This instance as its peers know it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncIdentity {
+    /// Instance id, announced over mDNS
+    pub instance_id: String,
+    /// Self-signed TLS certificate, PEM
+    pub certificate: String,
+    /// Its private key, PEM; never leaves the database
+    pub private_key: String,
+    /// SHA-256 of the certificate, lowercase hex
+    pub fingerprint: String,
+}
+
+/// Gets this instance's identity, creating it on first use
+pub fn get_or_create_identity(conn: &DatabaseConnection) -> Result<SyncIdentity> {
+    if let Some(identity) = conn.with_connection(|c| get_setting::<SyncIdentity>(c, IDENTITY_SETTING))? {
+        return Ok(identity);
+    }
+
+    let instance_id = Uuid::new_v4().to_string();
+    let (certificate, private_key, fingerprint) = tls::generate_certificate(&instance_id)?;
+    let identity = SyncIdentity { instance_id, certificate, private_key, fingerprint };
+    conn.with_connection(|c| set_setting(c, IDENTITY_SETTING, &identity))?;
+    Ok(identity)
+}
+
+/// An instance paired with this one
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncPeer {
+    /// The peer's instance id
+    pub id: String,
+    /// Name the peer announced when paired
+    pub name: String,
+    /// SHA-256 of the peer's certificate
+    pub fingerprint: String,
+    /// Shared secret; not sent to the frontend
+    #[serde(skip)]
+    pub secret: String,
+    /// When the peers were paired
+    pub paired_at: DateTime<Utc>,
+    /// Changes made on the peer up to this time have been pulled
+    pub pulled_until: Option<DateTime<Utc>>,
+    /// Last successful sync
+    pub last_synced_at: Option<DateTime<Utc>>,
+    /// Why the last sync failed, cleared by the next successful one
+    pub last_error: Option<String>,
+}
+
+const PEER_COLUMNS: &str = "id, name, fingerprint, secret, paired_at, pulled_until, last_synced_at, last_error";
+
+fn peer_from_row(row: &Row) -> rusqlite::Result<SyncPeer> {
+    Ok(SyncPeer {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        fingerprint: row.get(2)?,
+        secret: row.get(3)?,
+        paired_at: DateTime::from_timestamp(row.get(4)?, 0).unwrap_or_default(),
+        pulled_until: row.get::<_, Option<i64>>(5)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+        last_synced_at: row.get::<_, Option<i64>>(6)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+        last_error: row.get(7)?,
+    })
+}
+
+/// Lists the paired instances
+pub fn list_peers(conn: &DatabaseConnection) -> Result<Vec<SyncPeer>> {
+    Ok(conn.with_connection(|c| {
+        let mut stmt = c.prepare(&format!("SELECT {} FROM sync_peer ORDER BY name", PEER_COLUMNS))?;
+        let peers = stmt.query_map([], peer_from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(peers)
+    })?)
+}
+
+/// Finds a paired instance
+pub fn get_peer(conn: &DatabaseConnection, id: &str) -> Result<Option<SyncPeer>> {
+    Ok(conn.with_connection(|c| {
+        Ok(c.query_row(
+            &format!("SELECT {} FROM sync_peer WHERE id = ?", PEER_COLUMNS),
+            [id],
+            peer_from_row,
+        ).optional()?)
+    })?)
+}
+
+/// Stores a newly paired instance, replacing an earlier pairing with it
+pub fn save_peer(conn: &DatabaseConnection, id: &str, name: &str, fingerprint: &str, secret: &str) -> Result<()> {
+    conn.with_connection(|c| {
+        c.execute(
+            "INSERT INTO sync_peer (id, name, fingerprint, secret, paired_at) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (id) DO UPDATE SET
+                 name = ?2, fingerprint = ?3, secret = ?4, paired_at = ?5,
+                 pulled_until = NULL, last_synced_at = NULL, last_error = NULL",
+            params![id, name, fingerprint, secret, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Forgets a paired instance; returns false if it wasn't paired
+pub fn remove_peer(conn: &DatabaseConnection, id: &str) -> Result<bool> {
+    Ok(conn.with_connection(|c| Ok(c.execute("DELETE FROM sync_peer WHERE id = ?", [id])? > 0))?)
+}
+
+/// Records a sync with a peer: how far its changes were pulled, or why it failed
+pub fn record_sync(conn: &DatabaseConnection, id: &str, outcome: std::result::Result<DateTime<Utc>, String>) -> Result<()> {
+    conn.with_connection(|c| {
+        match outcome {
+            Ok(pulled_until) => c.execute(
+                "UPDATE sync_peer SET pulled_until = ?, last_synced_at = ?, last_error = NULL WHERE id = ?",
+                params![pulled_until.timestamp(), Utc::now().timestamp(), id],
+            )?,
+            Err(error) => c.execute("UPDATE sync_peer SET last_error = ? WHERE id = ?", params![error, id])?,
+        };
+        Ok(())
+    })?;
+    Ok(())
+}
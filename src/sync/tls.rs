@@ -0,0 +1,174 @@
+// Sync - TLS
+// Self-signed certificates pinned by fingerprint on both ends of a
+// connection, and the short code both sides of a pairing show so the users
+// can check they see each other
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use base64::Engine;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::server::{ClientCertVerified, ClientCertVerifier};
+use rustls::{Certificate, ClientConfig, DistinguishedName, PrivateKey, ServerConfig, ServerName};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::error::{Result, SyncError};
+use super::peers::SyncIdentity;
+
+/// Time allowed for a request to a peer, bundles included
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Hex SHA-256 of a DER certificate
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Creates a self-signed certificate; returns its PEM, its key's PEM and its fingerprint
+pub fn generate_certificate(instance_id: &str) -> Result<(String, String, String)> {
+    let certificate = rcgen::generate_simple_self_signed(vec![format!("{}.historykg.local", instance_id)])
+        .map_err(|e| SyncError::Network(format!("Failed to create a certificate: {}", e)))?;
+    let der = certificate.serialize_der()
+        .map_err(|e| SyncError::Network(format!("Failed to encode the certificate: {}", e)))?;
+    let pem = certificate.serialize_pem()
+        .map_err(|e| SyncError::Network(format!("Failed to encode the certificate: {}", e)))?;
+    Ok((pem, certificate.serialize_private_key_pem(), fingerprint(&der)))
+}
+
+/// Random value each side of a pairing contributes to the code
+pub fn new_nonce() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Hex SHA-256 binding the requester to its nonce before it sees the other one
+pub fn nonce_commitment(nonce: &str) -> String {
+    Sha256::new()
+        .chain_update(b"historykg-pairing-commitment")
+        .chain_update(nonce.as_bytes())
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Six digits derived from the certificates both sides presented during the
+/// handshake and a nonce from each. A machine in the middle has a certificate
+/// of its own, so the two screens would show different codes; the requester
+/// commits to its nonce before learning the responder's, so neither side can
+/// pick one that makes the codes agree.
+pub fn pairing_code(requester: &str, responder: &str, requester_nonce: &str, responder_nonce: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(b"historykg-pairing")
+        .chain_update(requester.as_bytes())
+        .chain_update(responder.as_bytes())
+        .chain_update(requester_nonce.as_bytes())
+        .chain_update(responder_nonce.as_bytes())
+        .finalize();
+    let value = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+    format!("{:06}", value % 1_000_000)
+}
+
+/// DER contents of a PEM block
+fn pem_der(pem: &str) -> Result<Vec<u8>> {
+    let body: String = pem.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+        .collect();
+    base64::engine::general_purpose::STANDARD.decode(body)
+        .map_err(|e| SyncError::Network(format!("The sync certificate is damaged: {}", e)))
+}
+
+/// This instance's certificate and key, as rustls takes them
+fn identity_der(identity: &SyncIdentity) -> Result<(Vec<Certificate>, PrivateKey)> {
+    Ok((vec![Certificate(pem_der(&identity.certificate)?)], PrivateKey(pem_der(&identity.private_key)?)))
+}
+
+/// Accepts the one certificate a peer was paired with, or any certificate
+/// while pairing, remembering which one was presented
+struct PinnedCertificate {
+    expected: Option<String>,
+    presented: Arc<Mutex<Option<String>>>,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let presented = fingerprint(&end_entity.0);
+        if let Ok(mut seen) = self.presented.lock() {
+            *seen = Some(presented.clone());
+        }
+        match &self.expected {
+            Some(expected) if *expected != presented => Err(rustls::Error::General(
+                "The peer presented a different certificate than when it was paired".to_string(),
+            )),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+}
+
+/// An HTTPS client for one peer, presenting this instance's certificate.
+/// With `expected` set, only the certificate with that fingerprint is
+/// accepted; the fingerprint actually presented is readable from the
+/// returned slot after a request.
+pub fn peer_agent(identity: &SyncIdentity, expected: Option<&str>) -> Result<(ureq::Agent, Arc<Mutex<Option<String>>>)> {
+    let presented = Arc::new(Mutex::new(None));
+    let verifier = PinnedCertificate {
+        expected: expected.map(str::to_string),
+        presented: Arc::clone(&presented),
+    };
+    let (certificates, key) = identity_der(identity)?;
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_client_auth_cert(certificates, key)
+        .map_err(|e| SyncError::Network(format!("The sync certificate is unusable: {}", e)))?;
+
+    let agent = ureq::AgentBuilder::new()
+        .tls_config(Arc::new(config))
+        .timeout(REQUEST_TIMEOUT)
+        .redirects(0)
+        .build();
+    Ok((agent, presented))
+}
+
+/// Requires every client to present a certificate. Any self-signed one is
+/// accepted here; the handlers compare its fingerprint with the paired one.
+struct AnyClientCertificate;
+
+impl ClientCertVerifier for AnyClientCertificate {
+    fn client_auth_mandatory(&self) -> bool {
+        true
+    }
+
+    fn client_auth_root_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _now: SystemTime,
+    ) -> std::result::Result<ClientCertVerified, rustls::Error> {
+        Ok(ClientCertVerified::assertion())
+    }
+}
+
+/// TLS settings of the sync listener: this instance's certificate, and a
+/// client certificate required from every peer
+pub fn server_config(identity: &SyncIdentity) -> Result<Arc<ServerConfig>> {
+    let (certificates, key) = identity_der(identity)?;
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(Arc::new(AnyClientCertificate))
+        .with_single_cert(certificates, key)
+        .map_err(|e| SyncError::Network(format!("The sync certificate is unusable: {}", e)))?;
+    Ok(Arc::new(config))
+}
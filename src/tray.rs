@@ -0,0 +1,75 @@
+// System Tray
+// Tray menu with quick actions, and today's visit count as a badge next to
+// the icon (macOS) and in its tooltip
+
+use chrono::Utc;
+use chrono_tz::Tz;
+use tauri::{CustomMenuItem, Manager, SystemTray, SystemTrayMenu, SystemTrayMenuItem};
+
+use crate::db;
+use crate::settings;
+
+/// Menu item queuing an import of the local Safari history
+pub const IMPORT_NOW: &str = "import_now";
+
+/// Menu item pausing or resuming the browser extension endpoint
+pub const PAUSE_CAPTURE: &str = "pause_capture";
+
+/// Menu item opening the search view
+pub const OPEN_SEARCH: &str = "open_search";
+
+/// Builds the tray icon and its menu
+pub fn build() -> SystemTray {
+    let menu = SystemTrayMenu::new()
+        .add_item(CustomMenuItem::new(OPEN_SEARCH, "Open Search"))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(IMPORT_NOW, "Import Now"))
+        .add_item(CustomMenuItem::new(PAUSE_CAPTURE, "Pause Capture"));
+
+    SystemTray::new().with_menu(menu).with_tooltip("History")
+}
+
+/// Labels the pause item for the current state
+pub fn set_capture_paused(app_handle: &tauri::AppHandle, paused: bool) {
+    let title = if paused { "Resume Capture" } else { "Pause Capture" };
+    let _ = app_handle.tray_handle().get_item(PAUSE_CAPTURE).set_title(title);
+}
+
+/// Visits since midnight in the display timezone
+fn visits_today(db_conn: &db::DatabaseConnection) -> Option<usize> {
+    let tz = settings::get_timezone(db_conn).unwrap_or(Tz::UTC);
+    let midnight = Utc::now().with_timezone(&tz).date_naive().and_hms_opt(0, 0, 0)?
+        .and_local_timezone(tz).earliest()?
+        .with_timezone(&Utc);
+    db::operations::count_visits_since(db_conn, midnight).ok()
+}
+
+/// Updates the badge with today's visit count, or clears it while the
+/// database is closed or the app is locked
+pub fn refresh_badge(app_handle: &tauri::AppHandle) {
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let app_state = app_handle.state::<crate::AppState>();
+        let db_conn = app_state.db_connection.read().ok().and_then(|guard| guard.as_ref().cloned());
+        // The status, unlike a check, doesn't count as activity and delay auto-lock
+        let unlocked = app_state.app_lock.status().is_ok_and(|status| !status.locked);
+        let count = match db_conn {
+            Some(db_conn) if unlocked => visits_today(&db_conn),
+            _ => None,
+        };
+
+        let tray = app_handle.tray_handle();
+        match count {
+            Some(count) => {
+                #[cfg(target_os = "macos")]
+                let _ = tray.set_title(&count.to_string());
+                let _ = tray.set_tooltip(&format!("History: {} visits today", count));
+            },
+            None => {
+                #[cfg(target_os = "macos")]
+                let _ = tray.set_title("");
+                let _ = tray.set_tooltip("History");
+            },
+        }
+    });
+}
@@ -0,0 +1,126 @@
+// Web - Access Walls
+// Recognizes fetched pages that only show a paywall or a login form, so
+// enrichment skips them and search can find the pages that couldn't be read
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use url::Url as UrlParser;
+
+use super::html::{find_tags, meta_content};
+use super::reading::ReadingStats;
+
+/// Below this many words, a page asking for a password is a login wall
+const LOGIN_WALL_WORDS: u64 = 150;
+
+/// Below this many words, a page with paywall markup only shows a teaser
+const PAYWALL_TEASER_WORDS: u64 = 400;
+
+/// Path segments of the sign-in pages gated sites redirect to
+const LOGIN_PATH_SEGMENTS: &[&str] = &["login", "log-in", "signin", "sign-in", "sign_in", "sso"];
+
+/// Host prefixes of sign-in services, e.g. accounts.google.com
+const LOGIN_HOST_PREFIXES: &[&str] = &["login.", "signin.", "accounts.", "auth.", "sso."];
+
+/// Class and id fragments of paywall overlays from common publishing platforms
+const PAYWALL_MARKERS: &[&str] = &["paywall", "regwall", "piano-", "tp-modal", "subscriber-only", "premium-content", "meter-"];
+
+/// What kept a page from being read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessWall {
+    /// Only subscribers can read the page
+    Paywall,
+    /// The page needs an account
+    Login,
+}
+
+impl AccessWall {
+    /// Returns the value stored in `metadata.access_wall`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccessWall::Paywall => "paywall",
+            AccessWall::Login => "login",
+        }
+    }
+
+    /// Parses a stored value
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "paywall" => Some(AccessWall::Paywall),
+            "login" => Some(AccessWall::Login),
+            _ => None,
+        }
+    }
+}
+
+/// The wall an HTTP error status stands for, if any
+pub fn wall_for_status(status: u16) -> Option<AccessWall> {
+    match status {
+        401 | 407 => Some(AccessWall::Login),
+        402 => Some(AccessWall::Paywall),
+        _ => None,
+    }
+}
+
+/// Whether the page declares its content locked in the structured data
+/// publishers give search engines (`"isAccessibleForFree": false`)
+fn declares_locked(html: &str) -> bool {
+    let lower = html.to_ascii_lowercase();
+    let key = "\"isaccessibleforfree\"";
+    let declared_free = lower.match_indices(key).any(|(start, _)| {
+        let value = lower[start + key.len()..].trim_start();
+        value.strip_prefix(':')
+            .map(|value| value.trim_start().trim_start_matches('"').starts_with("false"))
+            .unwrap_or(false)
+    });
+
+    declared_free || meta_content(html, &["article:content_tier"])
+        .is_some_and(|tier| tier.eq_ignore_ascii_case("locked") || tier.eq_ignore_ascii_case("metered"))
+}
+
+/// Whether any element carries a paywall class or id
+fn has_paywall_markup(html: &str) -> bool {
+    ["div", "section", "aside"].iter()
+        .flat_map(|name| find_tags(html, name))
+        .flat_map(|tag| ["class", "id"].map(|name| tag.attribute(name).map(str::to_ascii_lowercase)))
+        .flatten()
+        .any(|value| PAYWALL_MARKERS.iter().any(|marker| value.contains(marker)))
+}
+
+/// Whether the page ended up on a sign-in page after redirects
+fn is_login_url(final_url: &str) -> bool {
+    let Ok(parsed) = UrlParser::parse(final_url) else { return false };
+
+    let host = parsed.host_str().unwrap_or_default().to_ascii_lowercase();
+    let on_login_host = LOGIN_HOST_PREFIXES.iter().any(|prefix| host.starts_with(prefix));
+    let on_login_path = parsed.path_segments().into_iter().flatten()
+        .any(|segment| LOGIN_PATH_SEGMENTS.contains(&segment.to_ascii_lowercase().as_str()));
+
+    on_login_host || on_login_path
+}
+
+/// Checks whether a fetched HTML page is a paywall or login wall rather than
+/// the content, from where it redirected to, its markup and how little text
+/// it shows
+pub fn detect_access_wall(final_url: &str, html: &str, stats: &ReadingStats) -> Option<AccessWall> {
+    if declares_locked(html) || (stats.word_count < PAYWALL_TEASER_WORDS && has_paywall_markup(html)) {
+        return Some(AccessWall::Paywall);
+    }
+
+    let asks_password = find_tags(html, "input").iter()
+        .any(|input| input.attribute("type").is_some_and(|kind| kind.eq_ignore_ascii_case("password")));
+    if is_login_url(final_url) || (asks_password && stats.word_count < LOGIN_WALL_WORDS) {
+        return Some(AccessWall::Login);
+    }
+
+    None
+}
+
+/// Stores the wall found on a page, or clears it once the page is readable
+pub fn save_access_wall(c: &Connection, url_id: &str, wall: Option<AccessWall>) -> rusqlite::Result<()> {
+    c.execute(
+        "UPDATE metadata SET access_wall = ? WHERE url_id = ?",
+        params![wall.map(|wall| wall.as_str()), url_id],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,311 @@
+// Web - Archives
+// Saves pages as self-contained HTML files so they survive link rot
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+
+use crate::db::DatabaseConnection;
+use super::error::{Result, WebError};
+use super::fetch::{agent, fetch, Fetched, MAX_PAGE_BYTES};
+use super::html::{find_elements, find_tags, resolve_url, Tag};
+use super::reading::{reading_stats, save_reading_stats, ReadingStats};
+use super::access::{detect_access_wall, save_access_wall, wall_for_status, AccessWall};
+use super::changes::{content_fingerprint, record_fingerprint, ContentFingerprint};
+use super::tone::{get_sentiment_settings, save_sentiment, sentiment_score, Sentiment};
+
+/// Largest stylesheet or image inlined into an archive
+const MAX_ASSET_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Most assets inlined into a single archive
+const MAX_ASSETS: usize = 100;
+
+/// A stored page snapshot
+#[derive(Debug, Clone, Serialize)]
+pub struct Archive {
+    /// Archive identifier
+    pub id: i64,
+    /// Archived URL id
+    pub url_id: String,
+    /// URL actually archived, after redirects
+    pub final_url: String,
+    /// Path of the snapshot file
+    pub path: String,
+    /// Content type of the original page
+    pub content_type: String,
+    /// Size of the snapshot file
+    pub size_bytes: u64,
+    /// When the snapshot was taken
+    pub created_at: DateTime<Utc>,
+}
+
+/// Outcome of an archive run
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveRun {
+    /// Snapshots that were stored
+    pub archives: Vec<Archive>,
+    /// One message per URL that could not be archived
+    pub errors: Vec<String>,
+}
+
+/// Escapes a value for use inside a double-quoted attribute
+fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+/// Rebuilds a tag from its attributes, replacing or dropping some of them
+fn rebuild_tag(name: &str, tag: &Tag, overrides: &[(&str, Option<&str>)]) -> String {
+    let mut out = format!("<{}", name);
+
+    for (attribute, value) in &tag.attributes {
+        if overrides.iter().any(|(name, _)| name == attribute) {
+            continue;
+        }
+        out.push_str(&format!(" {}=\"{}\"", attribute, escape_attribute(value)));
+    }
+    for (attribute, value) in overrides {
+        if let Some(value) = value {
+            out.push_str(&format!(" {}=\"{}\"", attribute, escape_attribute(value)));
+        }
+    }
+
+    out.push('>');
+    out
+}
+
+/// Returns a file extension for a non-HTML content type
+fn extension_for(content_type: &str) -> &'static str {
+    match content_type.split(';').next().unwrap_or("").trim() {
+        "application/pdf" => "pdf",
+        "text/plain" => "txt",
+        "application/json" => "json",
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/svg+xml" => "svg",
+        _ => "bin",
+    }
+}
+
+/// Turns a page into a single HTML file: scripts are removed, stylesheets and
+/// images are inlined and a `<base>` keeps the remaining links working
+fn inline_page(agent: &ureq::Agent, page: &Fetched) -> String {
+    let html = page.text();
+    let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+    let mut assets: HashMap<String, Option<Fetched>> = HashMap::new();
+
+    // Downloads each asset once, giving up after MAX_ASSETS downloads
+    let mut load = |href: &str| -> Option<Fetched> {
+        let url = resolve_url(&page.final_url, href)?;
+        if !assets.contains_key(&url) && assets.len() >= MAX_ASSETS {
+            return None;
+        }
+        assets.entry(url.clone())
+            .or_insert_with(|| fetch(agent, &url, MAX_ASSET_BYTES).ok())
+            .clone()
+    };
+
+    for script in find_elements(&html, "script") {
+        replacements.push((script, String::new()));
+    }
+
+    for link in find_tags(&html, "link") {
+        let is_stylesheet = link.attribute("rel")
+            .is_some_and(|rel| rel.split_whitespace().any(|r| r.eq_ignore_ascii_case("stylesheet")));
+        if !is_stylesheet {
+            continue;
+        }
+        if let Some(css) = link.attribute("href").and_then(&mut load) {
+            replacements.push((link.span.clone(), format!("<style>\n{}\n</style>", css.text())));
+        }
+    }
+
+    for img in find_tags(&html, "img") {
+        if let Some(image) = img.attribute("src").and_then(&mut load) {
+            let data_uri = format!(
+                "data:{};base64,{}",
+                image.content_type,
+                base64::engine::general_purpose::STANDARD.encode(&image.body),
+            );
+            // srcset would still point at the live site
+            let tag = rebuild_tag("img", &img, &[("src", Some(&data_uri)), ("srcset", None)]);
+            replacements.push((img.span.clone(), tag));
+        }
+    }
+
+    // Resolve everything we didn't inline against the original page
+    let base = format!(
+        "<base href=\"{}\"><meta name=\"archived-from\" content=\"{}\"><meta name=\"archived-at\" content=\"{}\">",
+        escape_attribute(&page.final_url),
+        escape_attribute(&page.final_url),
+        Utc::now().to_rfc3339(),
+    );
+    match find_tags(&html, "head").first() {
+        Some(head) => replacements.push((head.span.end..head.span.end, base)),
+        None => replacements.push((0..0, base)),
+    }
+
+    // Apply replacements front to back, skipping any nested in an earlier one
+    replacements.sort_by_key(|(range, _)| (range.start, range.end));
+
+    let mut out = String::with_capacity(html.len());
+    let mut position = 0;
+    for (range, replacement) in replacements {
+        if range.start < position {
+            continue;
+        }
+        out.push_str(&html[position..range.start]);
+        out.push_str(&replacement);
+        position = range.end;
+    }
+    out.push_str(&html[position..]);
+
+    out
+}
+
+/// A page written to disk
+struct ArchivedPage {
+    page: Fetched,
+    path: PathBuf,
+    size_bytes: u64,
+    stats: Option<ReadingStats>,
+    wall: Option<AccessWall>,
+    fingerprint: Option<ContentFingerprint>,
+    sentiment: Option<Sentiment>,
+}
+
+/// Downloads a page and writes its snapshot into `dir`
+fn archive_page(agent: &ureq::Agent, url: &str, file_stem: &str, dir: &Path, score_tone: bool) -> Result<ArchivedPage> {
+    let page = fetch(agent, url, MAX_PAGE_BYTES)?;
+
+    let (path, contents, stats, wall, fingerprint, sentiment) = if page.is_html() {
+        let html = page.text();
+        let stats = reading_stats(&html);
+        let wall = detect_access_wall(&page.final_url, &html, &stats);
+        let (fingerprint, sentiment) = match wall {
+            None => (content_fingerprint(&html), score_tone.then(|| sentiment_score(&html)).flatten()),
+            Some(_) => (None, None),
+        };
+        let contents = inline_page(agent, &page).into_bytes();
+        (dir.join(format!("{}.html", file_stem)), contents, Some(stats), wall, fingerprint, sentiment)
+    } else {
+        let path = dir.join(format!("{}.{}", file_stem, extension_for(&page.content_type)));
+        (path, page.body.clone(), None, None, None, None)
+    };
+
+    std::fs::write(&path, &contents)?;
+
+    Ok(ArchivedPage {
+        page,
+        path,
+        size_bytes: contents.len() as u64,
+        stats,
+        wall,
+        fingerprint,
+        sentiment,
+    })
+}
+
+/// Archives the given URLs into `dir`, recording each snapshot in the archive table
+pub fn archive_urls(conn: &DatabaseConnection, dir: &Path, url_ids: &[String]) -> Result<ArchiveRun> {
+    std::fs::create_dir_all(dir)?;
+
+    let score_tone = get_sentiment_settings(conn)?.enabled;
+    let agent = agent();
+    let mut run = ArchiveRun::default();
+
+    for url_id in url_ids {
+        let url: Option<String> = conn.with_connection(|c| {
+            Ok(c.query_row("SELECT url FROM url WHERE id = ?", [url_id], |row| row.get(0)).optional()?)
+        })?;
+
+        let url = match url {
+            Some(url) => url,
+            None => {
+                run.errors.push(format!("Unknown URL id: {}", url_id));
+                continue;
+            },
+        };
+
+        let created_at = Utc::now();
+        let file_stem = format!("{}-{}", url_id, created_at.format("%Y%m%d%H%M%S"));
+
+        match archive_page(&agent, &url, &file_stem, dir, score_tone) {
+            Ok(ArchivedPage { page, path, size_bytes, stats, wall, fingerprint, sentiment }) => {
+                let path = path.to_string_lossy().into_owned();
+                let id = conn.with_connection(|c| {
+                    c.execute(
+                        "INSERT INTO archive (url_id, final_url, path, content_type, size_bytes, created_at)
+                         VALUES (?, ?, ?, ?, ?, ?)",
+                        params![url_id, page.final_url, path, page.content_type, size_bytes as i64, created_at.timestamp()],
+                    )?;
+                    if let Some(stats) = &stats {
+                        save_reading_stats(c, url_id, stats)?;
+                        save_access_wall(c, url_id, wall)?;
+                    }
+                    if let Some(fingerprint) = &fingerprint {
+                        record_fingerprint(c, url_id, fingerprint)?;
+                    }
+                    if let Some(sentiment) = &sentiment {
+                        save_sentiment(c, url_id, sentiment)?;
+                    }
+                    Ok(c.last_insert_rowid())
+                })?;
+
+                run.archives.push(Archive {
+                    id,
+                    url_id: url_id.clone(),
+                    final_url: page.final_url,
+                    path,
+                    content_type: page.content_type,
+                    size_bytes,
+                    created_at,
+                });
+            },
+            Err(e) => {
+                // A page refused with 401 or 402 is walled
+                let wall = match e {
+                    WebError::Status(code) => wall_for_status(code),
+                    _ => None,
+                };
+                if wall.is_some() {
+                    conn.with_connection(|c| Ok(save_access_wall(c, url_id, wall)?))?;
+                }
+                run.errors.push(format!("{}: {}", url, e));
+            },
+        }
+    }
+
+    Ok(run)
+}
+
+/// Lists archived snapshots, newest first, optionally for a single URL
+pub fn list_archives(conn: &DatabaseConnection, url_id: Option<&str>) -> Result<Vec<Archive>> {
+    let archives = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT id, url_id, final_url, path, content_type, size_bytes, created_at
+             FROM archive
+             WHERE ?1 IS NULL OR url_id = ?1
+             ORDER BY created_at DESC, id DESC"
+        )?;
+        let rows = stmt.query_map([url_id], |row| {
+            Ok(Archive {
+                id: row.get(0)?,
+                url_id: row.get(1)?,
+                final_url: row.get(2)?,
+                path: row.get(3)?,
+                content_type: row.get(4)?,
+                size_bytes: row.get::<_, i64>(5)? as u64,
+                created_at: DateTime::from_timestamp(row.get(6)?, 0).unwrap_or_default(),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    Ok(archives)
+}
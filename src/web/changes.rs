@@ -0,0 +1,301 @@
+// Web - Content Changes
+// Fingerprints the main text of fetched pages and re-checks them on a
+// schedule, flagging pages whose content changed materially since they were read
+
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::db::settings::get_setting;
+use crate::db::{DatabaseConnection, DatabaseError};
+use super::access::detect_access_wall;
+use super::error::{Result, WebError};
+use super::fetch::{agent, fetch, MAX_PAGE_BYTES};
+use super::html::visible_text;
+use super::reading::{main_content, reading_stats};
+
+/// Settings key of the scheduled content checks
+pub const CONTENT_CHECK_SETTING: &str = "content_checks";
+
+/// Default number of pages returned by `get_changed_pages`
+pub const DEFAULT_CHANGED_PAGES: usize = 100;
+
+/// Fingerprint bits that may differ before a change counts as material; a new
+/// date, a counter or a reworded sentence stays below it
+const MATERIAL_CHANGE_BITS: u32 = 4;
+
+/// Words per shingle hashed into a fingerprint
+const SHINGLE_WORDS: usize = 3;
+
+/// Schedule for re-checking fetched pages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContentCheckSettings {
+    /// Whether pages are re-checked on a schedule
+    pub enabled: bool,
+    /// Days between two checks of the same page
+    pub interval_days: u32,
+    /// Most pages fetched by one scheduled run
+    pub batch_size: usize,
+}
+
+impl Default for ContentCheckSettings {
+    fn default() -> Self {
+        ContentCheckSettings {
+            enabled: false,
+            interval_days: 7,
+            batch_size: 50,
+        }
+    }
+}
+
+/// Gets the content check schedule
+pub fn get_content_check_settings(conn: &DatabaseConnection) -> crate::db::Result<ContentCheckSettings> {
+    conn.with_connection(|c| Ok(get_setting(c, CONTENT_CHECK_SETTING)?.unwrap_or_default()))
+}
+
+/// Fingerprint of a page's main text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentFingerprint {
+    /// SimHash of the text's word shingles
+    pub hash: u64,
+    /// Number of words hashed
+    pub words: u64,
+}
+
+/// 64-bit FNV-1a, stable across builds unlike the standard library hasher
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Fingerprints a page's main text. Similar texts get fingerprints differing
+/// in few bits; numbers are left out so dates and counters don't count.
+pub fn content_fingerprint(html: &str) -> Option<ContentFingerprint> {
+    let text = visible_text(main_content(html)).to_lowercase();
+    let words: Vec<&str> = text.split(|ch: char| !ch.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !word.chars().all(|ch| ch.is_ascii_digit()))
+        .collect();
+    if words.is_empty() {
+        return None;
+    }
+
+    let mut weights = [0i64; 64];
+    for shingle in words.windows(SHINGLE_WORDS.min(words.len())) {
+        let hash = fnv1a(&shingle.join(" "));
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if (hash >> bit) & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    let hash = weights.iter().enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0u64, |hash, (bit, _)| hash | (1 << bit));
+
+    Some(ContentFingerprint { hash, words: words.len() as u64 })
+}
+
+/// Number of bits two fingerprints differ in
+fn distance(a: i64, b: i64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Records the fingerprint of a fetched page. The first fetch, and the first
+/// after the user visited the page again, becomes the baseline later fetches
+/// are compared with.
+pub fn record_fingerprint(c: &Connection, url_id: &str, fingerprint: &ContentFingerprint) -> rusqlite::Result<()> {
+    let now = Utc::now().timestamp();
+    let (hash, words) = (fingerprint.hash as i64, fingerprint.words as i64);
+
+    let stored: Option<(i64, bool)> = c.query_row(
+        "SELECT f.baseline_hash,
+                COALESCE((SELECT MAX(v.visited_at) FROM visit v WHERE v.url_id = f.url_id), 0) > f.baseline_at
+         FROM content_fingerprint f
+         WHERE f.url_id = ?",
+        [url_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional()?;
+
+    match stored {
+        Some((baseline, false)) => {
+            let changed = distance(baseline, hash) > MATERIAL_CHANGE_BITS;
+            c.execute(
+                "UPDATE content_fingerprint
+                 SET latest_hash = ?1, latest_words = ?2, checked_at = ?3,
+                     changed_at = CASE WHEN ?4 THEN COALESCE(changed_at, ?3) END
+                 WHERE url_id = ?5",
+                params![hash, words, now, changed, url_id],
+            )?;
+        },
+        _ => {
+            c.execute(
+                "INSERT INTO content_fingerprint
+                     (url_id, baseline_hash, baseline_words, baseline_at, latest_hash, latest_words, checked_at, changed_at)
+                 VALUES (?1, ?2, ?3, ?4, ?2, ?3, ?4, NULL)
+                 ON CONFLICT (url_id) DO UPDATE SET
+                     baseline_hash = ?2, baseline_words = ?3, baseline_at = ?4,
+                     latest_hash = ?2, latest_words = ?3, checked_at = ?4, changed_at = NULL",
+                params![url_id, hash, words, now],
+            )?;
+        },
+    }
+    Ok(())
+}
+
+/// Outcome of a content check run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ContentCheckRun {
+    /// Pages fetched and compared
+    pub checked: usize,
+    /// Pages found changed since they were read
+    pub changed: usize,
+    /// Pages that could not be fetched or showed a wall
+    pub failed: usize,
+    /// One message per failed page
+    pub errors: Vec<String>,
+}
+
+/// Re-fetches up to `limit` fingerprinted pages not checked for `interval_days`,
+/// least recently checked first, and flags the ones that changed materially
+pub fn check_content_changes(conn: &DatabaseConnection, limit: usize, interval_days: u32) -> Result<ContentCheckRun> {
+    let due_before = (Utc::now() - Duration::days(i64::from(interval_days))).timestamp();
+    let pages: Vec<(String, String, bool)> = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT f.url_id, u.url, f.changed_at IS NOT NULL
+             FROM content_fingerprint f
+             JOIN url u ON u.id = f.url_id
+             WHERE f.checked_at <= ?
+             ORDER BY f.checked_at
+             LIMIT ?"
+        )?;
+        let rows = stmt.query_map(params![due_before, limit as i64], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    let agent = agent();
+    let mut run = ContentCheckRun::default();
+
+    for (url_id, url, was_changed) in pages {
+        let fingerprint = fetch(&agent, &url, MAX_PAGE_BYTES).and_then(|page| {
+            if !page.is_html() {
+                return Ok(None);
+            }
+            let html = page.text();
+            match detect_access_wall(&page.final_url, &html, &reading_stats(&html)) {
+                Some(wall) => Err(WebError::Content(format!("Page shows a {} wall", wall.as_str()))),
+                None => Ok(content_fingerprint(&html)),
+            }
+        });
+
+        let is_changed = conn.with_connection(|c| {
+            match &fingerprint {
+                Ok(Some(fingerprint)) => record_fingerprint(c, &url_id, fingerprint)?,
+                // Pages that can't be compared wait for the next interval too
+                _ => {
+                    c.execute(
+                        "UPDATE content_fingerprint SET checked_at = ? WHERE url_id = ?",
+                        params![Utc::now().timestamp(), url_id],
+                    )?;
+                },
+            }
+            Ok(c.query_row(
+                "SELECT changed_at IS NOT NULL FROM content_fingerprint WHERE url_id = ?",
+                [&url_id],
+                |row| row.get::<_, bool>(0),
+            )?)
+        })?;
+
+        match fingerprint {
+            Ok(Some(_)) => {
+                run.checked += 1;
+                if is_changed && !was_changed {
+                    run.changed += 1;
+                }
+            },
+            Ok(None) => {
+                run.failed += 1;
+                run.errors.push(format!("{}: no readable text", url));
+            },
+            Err(e) => {
+                run.failed += 1;
+                run.errors.push(format!("{}: {}", url, e));
+            },
+        }
+    }
+
+    Ok(run)
+}
+
+/// A page whose content changed since the user read it
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedPage {
+    /// URL identifier
+    pub url_id: String,
+    /// The URL
+    pub url: String,
+    /// Page title
+    pub title: Option<String>,
+    /// Domain of the URL
+    pub domain: String,
+    /// Latest visit
+    pub last_visit: Option<DateTime<Utc>>,
+    /// When the content the change is measured against was fetched
+    pub baseline_at: DateTime<Utc>,
+    /// When the change was first found
+    pub changed_at: DateTime<Utc>,
+    /// Words in the baseline
+    pub baseline_words: u64,
+    /// Words now
+    pub latest_words: u64,
+    /// Fingerprint bits that differ, out of 64; higher is a bigger change
+    pub distance: u32,
+}
+
+/// Lists the pages that changed since they were last read, most recent change
+/// first; pages visited again after the change are left out
+pub fn get_changed_pages(conn: &DatabaseConnection, limit: usize) -> Result<Vec<ChangedPage>> {
+    Ok(conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT * FROM (
+                 SELECT f.url_id, u.url, u.title, u.domain,
+                        (SELECT MAX(v.visited_at) FROM visit v WHERE v.url_id = f.url_id) AS last_visit,
+                        f.baseline_at, f.changed_at, f.baseline_words, f.latest_words, f.baseline_hash, f.latest_hash
+                 FROM content_fingerprint f
+                 JOIN url u ON u.id = f.url_id
+                 WHERE f.changed_at IS NOT NULL
+             )
+             WHERE COALESCE(last_visit, 0) < changed_at
+             ORDER BY changed_at DESC, url_id
+             LIMIT ?"
+        )?;
+        let rows = stmt.query_map([limit as i64], |row| {
+            Ok(ChangedPage {
+                url_id: row.get(0)?,
+                url: row.get(1)?,
+                title: row.get(2)?,
+                domain: row.get(3)?,
+                last_visit: row.get::<_, Option<i64>>(4)?.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+                baseline_at: DateTime::from_timestamp(row.get(5)?, 0).unwrap_or_default(),
+                changed_at: DateTime::from_timestamp(row.get(6)?, 0).unwrap_or_default(),
+                baseline_words: row.get::<_, i64>(7)? as u64,
+                latest_words: row.get::<_, i64>(8)? as u64,
+                distance: distance(row.get(9)?, row.get(10)?),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?)
+}
+
+/// Accepts a page's current content as read, clearing its change flag
+pub fn acknowledge_content_change(conn: &DatabaseConnection, url_id: &str) -> Result<()> {
+    Ok(conn.with_connection(|c| {
+        let updated = c.execute(
+            "UPDATE content_fingerprint
+             SET baseline_hash = latest_hash, baseline_words = latest_words, baseline_at = checked_at, changed_at = NULL
+             WHERE url_id = ? AND changed_at IS NOT NULL",
+            [url_id],
+        )?;
+        if updated == 0 {
+            return Err(DatabaseError::NotFound(format!("Content change of URL {} does not exist", url_id)));
+        }
+        Ok(())
+    })?)
+}
@@ -0,0 +1,75 @@
+// Web Error Handling
+// Defines error types for fetching and processing web pages
+
+use std::fmt;
+use std::error::Error;
+use std::io;
+
+use crate::db::DatabaseError;
+
+/// Represents errors that can occur while fetching or processing pages
+#[derive(Debug)]
+pub enum WebError {
+    /// Reading or saving page data failed
+    Database(DatabaseError),
+    /// The page could not be fetched
+    Http(String),
+    /// The server answered with an error status
+    Status(u16),
+    /// A file could not be written
+    Io(io::Error),
+    /// The content could not be processed
+    Content(String),
+}
+
+impl fmt::Display for WebError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebError::Database(err) => write!(f, "Database error: {}", err),
+            WebError::Http(msg) => write!(f, "Request failed: {}", msg),
+            WebError::Status(code) => write!(f, "Request failed: HTTP {}", code),
+            WebError::Io(err) => write!(f, "I/O error: {}", err),
+            WebError::Content(msg) => write!(f, "Invalid content: {}", msg),
+        }
+    }
+}
+
+impl Error for WebError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WebError::Database(err) => Some(err),
+            WebError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DatabaseError> for WebError {
+    fn from(err: DatabaseError) -> Self {
+        WebError::Database(err)
+    }
+}
+
+impl From<rusqlite::Error> for WebError {
+    fn from(err: rusqlite::Error) -> Self {
+        WebError::Database(DatabaseError::from(err))
+    }
+}
+
+impl From<io::Error> for WebError {
+    fn from(err: io::Error) -> Self {
+        WebError::Io(err)
+    }
+}
+
+impl From<ureq::Error> for WebError {
+    fn from(err: ureq::Error) -> Self {
+        match err {
+            ureq::Error::Status(code, _) => WebError::Status(code),
+            ureq::Error::Transport(transport) => WebError::Http(transport.to_string()),
+        }
+    }
+}
+
+/// Result type for web operations
+pub type Result<T> = std::result::Result<T, WebError>;
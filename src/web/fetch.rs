@@ -0,0 +1,66 @@
+// Web - Fetching
+// Downloads pages and files with size limits and a browser-like user agent
+
+use std::io::Read;
+use std::time::Duration;
+
+use super::error::{Result, WebError};
+
+/// Largest HTML document we download
+pub const MAX_PAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Time allowed for a single request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// User agent sent with every request
+const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 14_0) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Safari/605.1.15";
+
+/// A downloaded resource
+#[derive(Debug, Clone)]
+pub struct Fetched {
+    /// URL after following redirects
+    pub final_url: String,
+    /// Content type reported by the server
+    pub content_type: String,
+    /// Response body
+    pub body: Vec<u8>,
+}
+
+impl Fetched {
+    /// Returns the body as text, replacing invalid UTF-8
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// Returns true if the body is an HTML document
+    pub fn is_html(&self) -> bool {
+        self.content_type.contains("html")
+    }
+}
+
+/// Creates an HTTP agent with our timeout and user agent
+pub fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(USER_AGENT)
+        .build()
+}
+
+/// Downloads a URL, following redirects, reading at most `max_bytes`
+pub fn fetch(agent: &ureq::Agent, url: &str, max_bytes: u64) -> Result<Fetched> {
+    let response = agent.get(url).call()?;
+
+    let final_url = response.get_url().to_string();
+    let content_type = response.content_type().to_lowercase();
+
+    let mut body = Vec::new();
+    response.into_reader()
+        .take(max_bytes + 1)
+        .read_to_end(&mut body)?;
+
+    if body.len() as u64 > max_bytes {
+        return Err(WebError::Content(format!("{} is larger than {} bytes", url, max_bytes)));
+    }
+
+    Ok(Fetched { final_url, content_type, body })
+}
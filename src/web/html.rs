@@ -0,0 +1,178 @@
+// Web - HTML Helpers
+// Minimal tag scanning for metadata; we never need a full DOM
+
+use std::ops::Range;
+
+use url::Url as UrlParser;
+
+/// Parses the attributes of a tag's source, e.g. `meta property="og:image" content="..."`
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag;
+
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].split_whitespace().last().unwrap_or("").to_lowercase();
+        let after = rest[eq + 1..].trim_start();
+
+        let (value, remaining) = match after.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let body = &after[1..];
+                match body.find(quote) {
+                    Some(end) => (&body[..end], &body[end + 1..]),
+                    None => (body, ""),
+                }
+            },
+            _ => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            },
+        };
+
+        if !name.is_empty() {
+            attributes.push((name, decode_entities(value)));
+        }
+        rest = remaining;
+    }
+
+    attributes
+}
+
+/// Decodes the handful of entities that commonly appear in attribute values
+pub fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// An occurrence of a tag in a document
+#[derive(Debug, Clone)]
+pub struct Tag {
+    /// Byte range of the tag source, from `<` to `>` inclusive
+    pub span: Range<usize>,
+    /// Attribute names (lowercased) and decoded values
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Tag {
+    /// Returns the value of an attribute
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Finds every occurrence of a tag (e.g. "meta", "link") in a document
+pub fn find_tags(html: &str, name: &str) -> Vec<Tag> {
+    // ASCII lowercasing keeps byte offsets valid for the original document
+    let lower = html.to_ascii_lowercase();
+    let open = format!("<{}", name);
+
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(start) = lower[from..].find(&open) {
+        let start = from + start;
+        let after = start + open.len();
+
+        // Make sure we matched the whole tag name (<meta, not <metadata)
+        let boundary = lower[after..].chars().next().is_some_and(|c| c.is_whitespace() || c == '/' || c == '>');
+        let end = lower[after..].find('>').map(|end| after + end);
+
+        match end {
+            Some(end) if boundary => {
+                found.push(Tag {
+                    span: start..end + 1,
+                    attributes: parse_attributes(&html[after..end]),
+                });
+                from = end;
+            },
+            Some(_) => from = after,
+            None => break,
+        }
+    }
+
+    found
+}
+
+/// Finds the byte ranges of every element with the given name, including its
+/// content and closing tag (e.g. whole `<script>...</script>` blocks)
+pub fn find_elements(html: &str, name: &str) -> Vec<Range<usize>> {
+    let lower = html.to_ascii_lowercase();
+    let close = format!("</{}", name);
+
+    find_tags(html, name).into_iter()
+        .filter_map(|tag| {
+            let content_start = tag.span.end;
+            let close_start = content_start + lower[content_start..].find(&close)?;
+            let close_end = close_start + lower[close_start..].find('>')? + 1;
+            Some(tag.span.start..close_end)
+        })
+        .collect()
+}
+
+/// Finds the content of the first `<meta>` whose name or property is one of `keys`
+pub fn meta_content(html: &str, keys: &[&str]) -> Option<String> {
+    let metas = find_tags(html, "meta");
+
+    // Respect the order of `keys`, so callers can list preferred sources first
+    keys.iter().find_map(|key| {
+        metas.iter().find_map(|meta| {
+            let matches = meta.attributes.iter()
+                .any(|(name, value)| (name == "property" || name == "name") && value.eq_ignore_ascii_case(key));
+            if !matches {
+                return None;
+            }
+            meta.attribute("content")
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        })
+    })
+}
+
+/// Resolves a possibly relative link against the page URL
+pub fn resolve_url(base: &str, href: &str) -> Option<String> {
+    let base = UrlParser::parse(base).ok()?;
+    base.join(href).ok().map(|url| url.to_string())
+}
+
+/// Extracts the readable text of a document, without scripts, styles or markup
+pub fn visible_text(html: &str) -> String {
+    let mut hidden: Vec<Range<usize>> = ["head", "script", "style", "noscript", "template", "svg"]
+        .iter()
+        .flat_map(|name| find_elements(html, name))
+        .collect();
+    hidden.sort_by_key(|range| range.start);
+
+    let mut text = String::with_capacity(html.len() / 2);
+    let mut position = 0;
+    for range in hidden {
+        if range.start >= position {
+            strip_tags(&html[position..range.start], &mut text);
+            position = range.end;
+        } else {
+            position = position.max(range.end);
+        }
+    }
+    strip_tags(&html[position..], &mut text);
+
+    decode_entities(&text)
+}
+
+/// Appends `html` to `out` with every tag replaced by a space
+fn strip_tags(html: &str, out: &mut String) {
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(' ');
+            },
+            _ if !in_tag => out.push(c),
+            _ => {},
+        }
+    }
+}
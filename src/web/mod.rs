@@ -0,0 +1,34 @@
+// Web Module
+// Fetches live pages to capture content the browser history doesn't include
+
+// Module organization:
+// - fetch.rs: HTTP downloads with size limits
+// - html.rs: Minimal HTML tag and metadata scanning
+// - thumbnail.rs: Preview image capture for enriched pages
+// - archive.rs: Single-file HTML snapshots of pages
+// - shortener.rs: Short link resolution and merging
+// - reading.rs: Word count and reading time estimation
+// - access.rs: Paywall and login wall detection
+// - changes.rs: Content fingerprints and scheduled change checks
+// - provenance.rs: Domain registration and hosting lookups
+// - wikidata.rs: Wikidata items of entities and domains
+// - tone.rs: Word-list sentiment scoring of page text
+// - error.rs: Error handling
+
+pub mod fetch;
+pub mod html;
+pub mod thumbnail;
+pub mod archive;
+pub mod shortener;
+pub mod reading;
+pub mod access;
+pub mod changes;
+pub mod provenance;
+pub mod wikidata;
+pub mod tone;
+pub mod error;
+
+pub use error::{Result, WebError};
+pub use archive::{archive_urls, list_archives, Archive, ArchiveRun};
+pub use shortener::{apply_known_redirects, expand_shortened_urls, ExpandRun, DEFAULT_EXPAND_LIMIT};
+pub use thumbnail::{capture_thumbnails, get_thumbnail_path, ThumbnailRun, DEFAULT_THUMBNAIL_LIMIT};
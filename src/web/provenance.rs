@@ -0,0 +1,197 @@
+// Web - Domain Provenance
+// Looks up who registered each browsed domain and where it is hosted, from
+// RDAP registries and DNS, for the domain detail view
+
+use std::collections::HashMap;
+use std::net::{IpAddr, ToSocketAddrs};
+
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::domains::registrable_domain;
+use crate::db::settings::get_setting;
+use crate::db::DatabaseConnection;
+use super::error::{Result, WebError};
+use super::fetch::{agent, fetch};
+
+/// Settings key of the domain enrichment job
+pub const DOMAIN_ENRICHMENT_SETTING: &str = "domain_enrichment";
+
+/// RDAP bootstrap service redirecting to the registry of a domain or address
+const RDAP_SERVICE: &str = "https://rdap.org";
+
+/// Largest RDAP response read
+const MAX_RDAP_BYTES: u64 = 512 * 1024;
+
+/// Schedule of the domain enrichment job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DomainEnrichmentSettings {
+    /// Whether domains are looked up on a schedule
+    pub enabled: bool,
+    /// Days before a domain is looked up again
+    pub interval_days: u32,
+    /// Most domains looked up by one scheduled run
+    pub batch_size: usize,
+}
+
+impl Default for DomainEnrichmentSettings {
+    fn default() -> Self {
+        DomainEnrichmentSettings {
+            enabled: false,
+            interval_days: 90,
+            batch_size: 25,
+        }
+    }
+}
+
+/// Gets the domain enrichment schedule
+pub fn get_domain_enrichment_settings(conn: &DatabaseConnection) -> crate::db::Result<DomainEnrichmentSettings> {
+    conn.with_connection(|c| Ok(get_setting(c, DOMAIN_ENRICHMENT_SETTING)?.unwrap_or_default()))
+}
+
+/// Outcome of a domain enrichment run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DomainEnrichmentRun {
+    /// Domains looked up
+    pub checked: usize,
+    /// Domains no registration or address was found for
+    pub failed: usize,
+    /// One message per failed domain
+    pub errors: Vec<String>,
+}
+
+/// Registrar and first registration date of a registrable domain
+#[derive(Debug, Clone, Default)]
+struct Registration {
+    registrar: Option<String>,
+    registered_at: Option<DateTime<Utc>>,
+}
+
+/// Fetches and parses an RDAP object
+fn rdap(agent: &ureq::Agent, path: &str) -> Result<Value> {
+    let response = fetch(agent, &format!("{}/{}", RDAP_SERVICE, path), MAX_RDAP_BYTES)?;
+    serde_json::from_slice(&response.body)
+        .map_err(|e| WebError::Content(format!("Invalid RDAP response: {}", e)))
+}
+
+/// Full name in an entity's jCard, e.g. `["fn", {}, "text", "MarkMonitor Inc."]`
+fn vcard_name(entity: &Value) -> Option<String> {
+    entity["vcardArray"][1].as_array()?.iter()
+        .find(|property| property[0].as_str() == Some("fn"))
+        .and_then(|property| property[3].as_str())
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// Reads the registrar entity and the registration event of an RDAP domain
+fn parse_registration(domain: &Value) -> Registration {
+    let registrar = domain["entities"].as_array().into_iter().flatten()
+        .find(|entity| entity["roles"].as_array().is_some_and(|roles| roles.iter().any(|role| role == "registrar")))
+        .and_then(vcard_name);
+    let registered_at = domain["events"].as_array().into_iter().flatten()
+        .find(|event| event["eventAction"] == "registration")
+        .and_then(|event| event["eventDate"].as_str())
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+        .map(|date| date.with_timezone(&Utc));
+
+    Registration { registrar, registered_at }
+}
+
+/// Resolves a host, preferring an IPv4 address
+fn resolve_host(host: &str) -> Option<IpAddr> {
+    let addresses: Vec<IpAddr> = (host, 443).to_socket_addrs().ok()?.map(|address| address.ip()).collect();
+    addresses.iter().find(|address| address.is_ipv4()).or_else(|| addresses.first()).copied()
+}
+
+/// Country the network of an address is registered in
+fn address_country(agent: &ureq::Agent, address: &IpAddr) -> Result<Option<String>> {
+    let network = rdap(agent, &format!("ip/{}", address))?;
+    Ok(network["country"].as_str()
+        .map(str::trim)
+        .filter(|country| country.len() == 2)
+        .map(str::to_ascii_uppercase))
+}
+
+/// Looks up up to `limit` domains never looked up or not looked up for
+/// `interval_days`, most visited first, and stores their registrar,
+/// registration date, address and hosting country
+pub fn enrich_domains(conn: &DatabaseConnection, limit: usize, interval_days: u32) -> Result<DomainEnrichmentRun> {
+    let due_before = (Utc::now() - Duration::days(i64::from(interval_days))).timestamp();
+    let domains: Vec<String> = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT s.domain
+             FROM domain_stats s
+             LEFT JOIN domain_provenance p ON p.domain = s.domain
+             WHERE s.domain LIKE '%.%' AND (p.domain IS NULL OR p.checked_at <= ?)
+             ORDER BY p.checked_at IS NOT NULL, s.visit_count DESC, s.domain
+             LIMIT ?"
+        )?;
+        let rows = stmt.query_map(params![due_before, limit as i64], |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    let agent = agent();
+    let mut registrations: HashMap<String, std::result::Result<Registration, String>> = HashMap::new();
+    let mut run = DomainEnrichmentRun::default();
+
+    for domain in domains {
+        let registrable = registrable_domain(&domain);
+        let is_address = registrable.parse::<IpAddr>().is_ok();
+
+        // Subdomains share the registration of their registrable domain
+        let registration = if is_address {
+            Ok(Registration::default())
+        } else {
+            registrations.entry(registrable.clone())
+                .or_insert_with(|| rdap(&agent, &format!("domain/{}", registrable))
+                    .map(|object| parse_registration(&object))
+                    .map_err(|e| format!("registration: {}", e)))
+                .clone()
+        };
+        let address = if is_address { registrable.parse().ok() } else { resolve_host(&domain) };
+        let country = match &address {
+            Some(address) => address_country(&agent, address).map_err(|e| format!("hosting: {}", e)),
+            None => Err("hosting: domain does not resolve".to_string()),
+        };
+
+        let errors: Vec<&str> = [registration.as_ref().err(), country.as_ref().err()]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        let error = (!errors.is_empty()).then(|| errors.join("; "));
+        let registration = registration.unwrap_or_default();
+        let country = country.ok().flatten();
+
+        conn.with_connection(|c| {
+            c.execute(
+                "INSERT OR REPLACE INTO domain_provenance
+                     (domain, registrable_domain, registrar, registered_at, ip_address, country, checked_at, error)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    domain,
+                    registrable,
+                    registration.registrar,
+                    registration.registered_at.map(|at| at.timestamp()),
+                    address.map(|address| address.to_string()),
+                    country,
+                    Utc::now().timestamp(),
+                    error,
+                ],
+            )?;
+            Ok(())
+        })?;
+
+        run.checked += 1;
+        if registration.registrar.is_none() && registration.registered_at.is_none() && address.is_none() {
+            run.failed += 1;
+            run.errors.push(format!("{}: {}", domain, error.unwrap_or_default()));
+        }
+    }
+
+    Ok(run)
+}
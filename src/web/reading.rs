@@ -0,0 +1,57 @@
+// Web - Reading Time
+// Measures article length from fetched page content
+
+use rusqlite::{params, Connection};
+
+use super::html::{find_elements, visible_text};
+
+/// Average adult reading speed for online text
+const WORDS_PER_MINUTE: u64 = 230;
+
+/// Length of a page's readable text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReadingStats {
+    /// Number of words in the main content
+    pub word_count: u64,
+    /// Estimated time to read the page in seconds
+    pub reading_time_sec: u64,
+}
+
+impl ReadingStats {
+    /// Computes reading stats from a word count
+    pub fn from_word_count(word_count: u64) -> Self {
+        Self {
+            word_count,
+            reading_time_sec: (word_count * 60 + WORDS_PER_MINUTE / 2) / WORDS_PER_MINUTE,
+        }
+    }
+}
+
+/// The page's largest `<article>` or `<main>` element, leaving out navigation
+/// and footers, or the whole page when it has neither
+pub fn main_content(html: &str) -> &str {
+    ["article", "main"].iter()
+        .filter_map(|name| find_elements(html, name).into_iter().max_by_key(|range| range.len()))
+        .next()
+        .map(|range| &html[range])
+        .unwrap_or(html)
+}
+
+/// Counts the words of a page's main content
+pub fn reading_stats(html: &str) -> ReadingStats {
+    let word_count = visible_text(main_content(html))
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count();
+
+    ReadingStats::from_word_count(word_count as u64)
+}
+
+/// Stores the reading stats of a page in its metadata
+pub fn save_reading_stats(c: &Connection, url_id: &str, stats: &ReadingStats) -> rusqlite::Result<()> {
+    c.execute(
+        "UPDATE metadata SET word_count = ?, reading_time_sec = ? WHERE url_id = ?",
+        params![stats.word_count as i64, stats.reading_time_sec as i64, url_id],
+    )?;
+    Ok(())
+}
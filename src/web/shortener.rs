@@ -0,0 +1,216 @@
+// Web - Shortened URLs
+// Resolves link shorteners and merges short links into the page they point to
+
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use url::Url as UrlParser;
+
+use crate::db::DatabaseConnection;
+use crate::db::merge::merge_url_records;
+use super::error::{Result, WebError};
+use super::fetch::{agent, fetch};
+use super::html::find_tags;
+
+/// Default number of short links resolved by a single run
+pub const DEFAULT_EXPAND_LIMIT: usize = 200;
+
+/// Largest response read while following a short link
+const MAX_REDIRECT_PAGE_BYTES: u64 = 256 * 1024;
+
+/// Most HTML (meta refresh) redirects followed on top of HTTP redirects
+const MAX_META_REDIRECTS: usize = 3;
+
+/// Visits to the short link this close to a visit of its destination are the
+/// redirect itself, not a separate visit
+const REDIRECT_WINDOW_SECS: i64 = 10;
+
+/// Known link shortener domains
+const SHORTENER_DOMAINS: &[&str] = &[
+    "t.co", "bit.ly", "bitly.com", "tinyurl.com", "goo.gl", "ow.ly", "buff.ly", "is.gd",
+    "dlvr.it", "lnkd.in", "fb.me", "youtu.be", "amzn.to", "amzn.eu", "t.ly", "rebrand.ly",
+    "cutt.ly", "shorturl.at", "tiny.cc", "rb.gy", "trib.al", "ift.tt", "aka.ms", "wp.me",
+    "flip.it", "s.id", "redd.it", "spoti.fi", "apple.co", "g.co",
+];
+
+/// Outcome of an expansion run
+#[derive(Debug, Clone, Default)]
+pub struct ExpandRun {
+    /// Short links rewritten to their destination
+    pub expanded: usize,
+    /// Short links merged into an existing record for their destination
+    pub merged: usize,
+    /// Short links that could not be resolved
+    pub failed: usize,
+    /// One message per failed short link
+    pub errors: Vec<String>,
+}
+
+/// Returns true if the domain belongs to a link shortener
+pub fn is_shortener(domain: &str) -> bool {
+    let domain = domain.trim_start_matches("www.").to_lowercase();
+    SHORTENER_DOMAINS.contains(&domain.as_str())
+}
+
+/// Extracts the target of a `<meta http-equiv="refresh" content="0; url=...">`
+fn meta_refresh_target(html: &str) -> Option<String> {
+    find_tags(html, "meta").into_iter()
+        .filter(|meta| meta.attribute("http-equiv").is_some_and(|v| v.eq_ignore_ascii_case("refresh")))
+        .find_map(|meta| {
+            let content = meta.attribute("content")?;
+            let position = content.to_ascii_lowercase().find("url=")?;
+            let target = content[position + 4..].trim().trim_matches(|c| c == '\'' || c == '"');
+            Some(target.to_string()).filter(|target| !target.is_empty())
+        })
+}
+
+/// Follows HTTP and meta refresh redirects, returning the destination URL
+pub fn resolve(agent: &ureq::Agent, url: &str) -> Result<String> {
+    let mut current = url.to_string();
+
+    for _ in 0..=MAX_META_REDIRECTS {
+        let page = fetch(agent, &current, MAX_REDIRECT_PAGE_BYTES)?;
+        current = page.final_url.clone();
+
+        // Some shorteners (t.co) answer browsers with an HTML page instead of a 301
+        let domain = UrlParser::parse(&current).ok()
+            .and_then(|parsed| parsed.host_str().map(str::to_string))
+            .unwrap_or_default();
+        if !page.is_html() || !is_shortener(&domain) {
+            return Ok(current);
+        }
+
+        match meta_refresh_target(&page.text()).and_then(|target| super::html::resolve_url(&current, &target)) {
+            Some(target) => current = target,
+            None => return Ok(current),
+        }
+    }
+
+    Err(WebError::Content(format!("Too many redirects for {}", url)))
+}
+
+/// Removes tracking fragments and normalises the destination of a short link
+fn canonicalize(url: &str) -> Option<(String, String)> {
+    let mut parsed = UrlParser::parse(url).ok()?;
+    parsed.set_fragment(None);
+    let domain = parsed.host_str()?.to_string();
+    Some((parsed.to_string(), domain))
+}
+
+/// Merges the URL record `source_id` into the one for `target_url`, creating
+/// it by rewriting the source when the destination isn't in the history yet.
+/// Returns true when an existing record absorbed the source.
+fn merge_into_target(c: &Connection, source_id: &str, target_url: &str, target_domain: &str) -> rusqlite::Result<bool> {
+    let target_id: Option<String> = c.query_row(
+        "SELECT id FROM url WHERE url = ? AND id != ?",
+        params![target_url, source_id],
+        |row| row.get(0),
+    ).optional()?;
+
+    let target_id = match target_id {
+        Some(target_id) => target_id,
+        None => {
+            // Unknown destination: the short link's record becomes the destination's
+            c.execute(
+                "UPDATE url SET url = ?, domain = ?, category = NULL WHERE id = ?",
+                params![target_url, target_domain, source_id],
+            )?;
+            return Ok(false);
+        },
+    };
+
+    // Drop the redirect hop; the remaining visits move with the record
+    c.execute(
+        "DELETE FROM visit WHERE url_id = ?1 AND EXISTS (
+             SELECT 1 FROM visit t
+             WHERE t.url_id = ?2
+               AND t.device_name IS visit.device_name
+               AND t.visited_at BETWEEN visit.visited_at AND visit.visited_at + ?3
+         )",
+        params![source_id, target_id, REDIRECT_WINDOW_SECS],
+    )?;
+
+    merge_url_records(c, source_id, &target_id)?;
+
+    Ok(true)
+}
+
+/// Merges short links whose destination was already resolved, without any
+/// network requests; used after imports. Returns the number of short links applied.
+pub fn apply_known_redirects(conn: &DatabaseConnection) -> Result<usize> {
+    let merged = conn.transaction(|tx| {
+        let mut stmt = tx.prepare(
+            "SELECT u.id, r.target_url FROM url u JOIN url_redirect r ON r.source_url = u.url"
+        )?;
+        let pending = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut merged = 0;
+        for (source_id, target_url) in pending {
+            if let Some((target_url, target_domain)) = canonicalize(&target_url) {
+                merge_into_target(tx, &source_id, &target_url, &target_domain)?;
+                merged += 1;
+            }
+        }
+        Ok(merged)
+    })?;
+
+    Ok(merged)
+}
+
+/// Resolves up to `limit` short links in the history and merges each one into
+/// the record for its destination
+pub fn expand_shortened_urls(conn: &DatabaseConnection, limit: usize) -> Result<ExpandRun> {
+    let candidates: Vec<(String, String, String)> = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT u.id, u.url, u.domain FROM url u
+             WHERE NOT EXISTS (SELECT 1 FROM url_redirect r WHERE r.source_url = u.url)"
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    let agent = agent();
+    let mut run = ExpandRun::default();
+
+    let short_links = candidates.into_iter()
+        .filter(|(_, _, domain)| is_shortener(domain))
+        .take(limit);
+
+    for (url_id, url, _) in short_links {
+        let destination = resolve(&agent, &url).and_then(|target| {
+            canonicalize(&target).ok_or_else(|| WebError::Content(format!("Invalid destination: {}", target)))
+        });
+
+        let (target_url, target_domain) = match destination {
+            Ok(destination) => destination,
+            Err(e) => {
+                run.failed += 1;
+                run.errors.push(format!("{}: {}", url, e));
+                continue;
+            },
+        };
+
+        // Shorteners that only redirect to themselves (e.g. an expired link)
+        if target_url == url || is_shortener(&target_domain) {
+            run.failed += 1;
+            run.errors.push(format!("{}: did not resolve to another site", url));
+            continue;
+        }
+
+        let merged = conn.transaction(|tx| {
+            tx.execute(
+                "INSERT OR REPLACE INTO url_redirect (source_url, target_url, resolved_at) VALUES (?, ?, ?)",
+                params![url, target_url, Utc::now().timestamp()],
+            )?;
+            Ok(merge_into_target(tx, &url_id, &target_url, &target_domain)?)
+        })?;
+
+        if merged {
+            run.merged += 1;
+        } else {
+            run.expanded += 1;
+        }
+    }
+
+    Ok(run)
+}
@@ -0,0 +1,177 @@
+// Web - Thumbnails
+// Captures a preview image for enriched pages from their OpenGraph metadata
+
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use rusqlite::{params, OptionalExtension};
+
+use crate::db::DatabaseConnection;
+use super::error::{Result, WebError};
+use super::fetch::{agent, fetch, Fetched, MAX_PAGE_BYTES};
+use super::html::{find_tags, meta_content, resolve_url};
+use super::reading::{reading_stats, save_reading_stats};
+use super::access::{detect_access_wall, save_access_wall, wall_for_status};
+use super::changes::{content_fingerprint, record_fingerprint};
+use super::tone::{get_sentiment_settings, save_sentiment, sentiment_score};
+
+/// Default number of pages visited by a single run
+pub const DEFAULT_THUMBNAIL_LIMIT: usize = 50;
+
+/// Largest width or height of a stored thumbnail
+const THUMBNAIL_SIZE: u32 = 400;
+
+/// Largest preview image we download
+const MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Meta tags holding a preview image, in order of preference
+const IMAGE_META_KEYS: &[&str] = &["og:image:secure_url", "og:image", "og:image:url", "twitter:image", "twitter:image:src"];
+
+/// Outcome of a thumbnail run
+#[derive(Debug, Clone, Default)]
+pub struct ThumbnailRun {
+    /// Pages for which a thumbnail was stored
+    pub captured: usize,
+    /// Pages without a preview image
+    pub missing: usize,
+    /// Pages that could not be fetched or decoded
+    pub failed: usize,
+    /// One message per failed page
+    pub errors: Vec<String>,
+}
+
+/// Finds the preview image of an HTML page, resolved against the page URL
+fn find_preview_image(html: &str, page_url: &str) -> Option<String> {
+    let href = meta_content(html, IMAGE_META_KEYS).or_else(|| {
+        find_tags(html, "link").into_iter()
+            .filter(|link| link.attribute("rel").is_some_and(|rel| rel.eq_ignore_ascii_case("image_src")))
+            .find_map(|link| link.attribute("href").map(str::to_string))
+    })?;
+
+    resolve_url(page_url, &href)
+}
+
+/// Downloads the preview image of a fetched page and stores it as a PNG
+/// thumbnail, returning None when the page has no preview image
+fn save_thumbnail(agent: &ureq::Agent, page: &Fetched, html: &str, path: &Path) -> Result<Option<PathBuf>> {
+    let image_url = match find_preview_image(html, &page.final_url) {
+        Some(image_url) => image_url,
+        None => return Ok(None),
+    };
+
+    let image = fetch(agent, &image_url, MAX_IMAGE_BYTES)?;
+    let decoded = image::load_from_memory(&image.body)
+        .map_err(|e| WebError::Content(format!("{}: {}", image_url, e)))?;
+
+    decoded.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|e| WebError::Content(format!("Failed to save thumbnail: {}", e)))?;
+
+    Ok(Some(path.to_path_buf()))
+}
+
+/// Captures thumbnails for up to `limit` enriched pages that have not been checked
+/// yet, saving them as `<url_id>.png` in `dir`
+pub fn capture_thumbnails(conn: &DatabaseConnection, dir: &Path, limit: usize) -> Result<ThumbnailRun> {
+    std::fs::create_dir_all(dir)?;
+
+    let pages: Vec<(String, String)> = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT u.id, u.url
+             FROM metadata m
+             JOIN url u ON m.url_id = u.id
+             WHERE m.is_enriched = 1 AND m.thumbnail_checked_at IS NULL
+             LIMIT ?"
+        )?;
+        let rows = stmt.query_map([limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    let score_tone = get_sentiment_settings(conn)?.enabled;
+    let agent = agent();
+    let mut run = ThumbnailRun::default();
+
+    for (url_id, url) in pages {
+        let path = dir.join(format!("{}.png", url_id));
+
+        // Measure, fingerprint, score and look for a paywall while we have
+        // the page's content; `access` stays None when the page couldn't be checked
+        let mut stats = None;
+        let mut access = None;
+        let mut fingerprint = None;
+        let mut sentiment = None;
+        let captured = fetch(&agent, &url, MAX_PAGE_BYTES).and_then(|page| {
+            if !page.is_html() {
+                return Ok(None);
+            }
+            let html = page.text();
+            let page_stats = reading_stats(&html);
+            let wall = detect_access_wall(&page.final_url, &html, &page_stats);
+            if wall.is_none() {
+                fingerprint = content_fingerprint(&html);
+                sentiment = score_tone.then(|| sentiment_score(&html)).flatten();
+            }
+            access = Some(wall);
+            stats = Some(page_stats);
+            save_thumbnail(&agent, &page, &html, &path)
+        });
+
+        let thumbnail_path = match captured {
+            Ok(Some(path)) => {
+                run.captured += 1;
+                Some(path.to_string_lossy().into_owned())
+            },
+            Ok(None) => {
+                run.missing += 1;
+                None
+            },
+            Err(e) => {
+                // A page refused with 401 or 402 is walled; a failed image download says nothing
+                if let (None, WebError::Status(code)) = (&stats, &e) {
+                    access = wall_for_status(*code).map(Some);
+                }
+                run.failed += 1;
+                run.errors.push(format!("{}: {}", url, e));
+                None
+            },
+        };
+
+        // Failed pages are marked as checked too, so one bad site doesn't block every run
+        conn.with_connection(|c| {
+            c.execute(
+                "UPDATE metadata SET thumbnail_path = ?, thumbnail_checked_at = ? WHERE url_id = ?",
+                params![thumbnail_path, Utc::now().timestamp(), url_id],
+            )?;
+            if let Some(stats) = &stats {
+                save_reading_stats(c, &url_id, stats)?;
+            }
+            if let Some(wall) = access {
+                save_access_wall(c, &url_id, wall)?;
+            }
+            if let Some(fingerprint) = &fingerprint {
+                record_fingerprint(c, &url_id, fingerprint)?;
+            }
+            if let Some(sentiment) = &sentiment {
+                save_sentiment(c, &url_id, sentiment)?;
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(run)
+}
+
+/// Returns the stored thumbnail path of a URL, if it has one
+pub fn get_thumbnail_path(conn: &DatabaseConnection, url_id: &str) -> Result<Option<String>> {
+    let path = conn.with_connection(|c| {
+        let path: Option<Option<String>> = c.query_row(
+            "SELECT thumbnail_path FROM metadata WHERE url_id = ?",
+            [url_id],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(path.flatten())
+    })?;
+
+    // The file may have been removed by the user since it was captured
+    Ok(path.filter(|path| Path::new(path).exists()))
+}
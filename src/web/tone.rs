@@ -0,0 +1,227 @@
+// Web - Tone Scoring
+// Scores the sentiment of fetched article text with a word list, so analytics
+// can show whether the pages read lean negative or positive
+
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::db::settings::get_setting;
+use crate::db::DatabaseConnection;
+use super::access::detect_access_wall;
+use super::error::Result;
+use super::fetch::{agent, fetch, MAX_PAGE_BYTES};
+use super::html::visible_text;
+use super::reading::{main_content, reading_stats};
+
+/// Settings key of the tone scoring options
+pub const SENTIMENT_SETTING: &str = "sentiment";
+
+/// Default number of pages fetched and scored by `score_sentiment`
+pub const DEFAULT_SENTIMENT_LIMIT: usize = 50;
+
+/// Fewer words than this are too little text to judge
+const MIN_SCORED_WORDS: usize = 50;
+
+/// Scored words added to every page as if neutral, so a page with a handful
+/// of charged words doesn't come out at an extreme
+const NEUTRAL_PRIOR_WORDS: f64 = 10.0;
+
+/// Words this close before a scored word invert it ("not good")
+const NEGATION_WINDOW: usize = 3;
+
+/// How much of a word's valence is left, inverted, after a negation
+const NEGATION_FACTOR: f64 = -0.5;
+
+/// Words that negate the words after them; contractions are split at the
+/// apostrophe, so "isn't" shows up as "isn"
+const NEGATIONS: &[&str] = &[
+    "aren", "cannot", "couldn", "didn", "doesn", "don", "hardly", "isn", "neither", "never",
+    "no", "nobody", "nor", "not", "nothing", "shouldn", "wasn", "weren", "without", "wouldn",
+];
+
+/// Valence of opinion and news words from -3 (very negative) to 3 (very
+/// positive), sorted for binary search
+const LEXICON: &[(&str, i8)] = &[
+    ("abuse", -3), ("abused", -3), ("accept", 1), ("accepted", 1), ("accused", -2), ("achieve", 2),
+    ("achieved", 2), ("achievement", 2), ("afraid", -2), ("agree", 2), ("agreement", 2),
+    ("amazing", 3), ("anger", -2), ("angry", -2), ("approve", 1), ("approved", 1), ("arrest", -2),
+    ("arrested", -2), ("attack", -2), ("attacked", -2), ("attacks", -2), ("award", 2),
+    ("awarded", 2), ("awful", -3), ("bad", -2), ("beautiful", 2), ("benefit", 2), ("benefits", 2),
+    ("best", 2), ("better", 2), ("boost", 2), ("boosted", 2), ("breakthrough", 3), ("brilliant", 3),
+    ("broken", -2), ("brutal", -3), ("calm", 1), ("catastrophe", -3), ("catastrophic", -3),
+    ("celebrate", 2), ("celebrated", 2), ("collapse", -2), ("collapsed", -2), ("complain", -1),
+    ("complained", -1), ("concern", -1), ("concerned", -1), ("concerns", -1), ("conflict", -2),
+    ("controversial", -1), ("controversy", -1), ("corruption", -2), ("crash", -2), ("crashed", -2),
+    ("crime", -2), ("criminal", -2), ("crisis", -2), ("criticism", -1), ("criticized", -1),
+    ("damage", -2), ("damaged", -2), ("danger", -2), ("dangerous", -2), ("dead", -3), ("death", -3),
+    ("deaths", -3), ("decline", -1), ("declined", -1), ("delay", -1), ("delayed", -1),
+    ("delighted", 3), ("devastated", -3), ("devastating", -3), ("difficult", -1), ("disaster", -3),
+    ("disastrous", -3), ("disease", -2), ("dispute", -2), ("doubt", -1), ("doubts", -1),
+    ("drought", -2), ("easy", 1), ("effective", 2), ("efficient", 2), ("enjoy", 2), ("enjoyed", 2),
+    ("evil", -3), ("excellent", 3), ("fail", -2), ("failed", -2), ("fails", -2), ("failure", -2),
+    ("famine", -3), ("fantastic", 3), ("favorable", 1), ("fear", -2), ("feared", -2), ("flood", -2),
+    ("flooded", -2), ("fraud", -2), ("friendly", 1), ("fun", 2), ("gain", 2), ("gains", 2),
+    ("generous", 2), ("genocide", -3), ("glad", 2), ("good", 2), ("great", 2), ("growth", 2),
+    ("happy", 2), ("hate", -2), ("hated", -2), ("healthy", 2), ("helpful", 2), ("hope", 2),
+    ("hopeful", 2), ("horrible", -3), ("horrific", -3), ("hurt", -2), ("illness", -2),
+    ("impressive", 2), ("improve", 2), ("improved", 2), ("improvement", 2), ("inflation", -2),
+    ("injured", -2), ("injury", -2), ("innovative", 2), ("inspired", 2), ("inspiring", 2),
+    ("interesting", 1), ("kill", -3), ("killed", -3), ("killing", -3), ("lawsuit", -2),
+    ("layoffs", -2), ("lose", -2), ("loss", -2), ("losses", -2), ("lost", -2), ("love", 2),
+    ("loved", 2), ("loves", 2), ("massacre", -3), ("masterpiece", 3), ("murder", -3),
+    ("murdered", -3), ("nice", 1), ("ok", 1), ("okay", 1), ("opportunities", 2), ("opportunity", 2),
+    ("optimistic", 2), ("outbreak", -2), ("outstanding", 3), ("pandemic", -3), ("peace", 2),
+    ("peaceful", 2), ("pleased", 2), ("poor", -2), ("popular", 1), ("positive", 2), ("praise", 2),
+    ("praised", 2), ("problem", -1), ("problematic", -2), ("problems", -1), ("progress", 2),
+    ("promising", 1), ("protest", -2), ("protests", -2), ("proud", 2), ("recession", -2),
+    ("recover", 2), ("recovered", 2), ("recovery", 2), ("reliable", 2), ("relief", 1),
+    ("relieved", 1), ("rescue", 2), ("rescued", 2), ("risk", -1), ("risks", -1), ("sad", -2),
+    ("safe", 2), ("safety", 2), ("scandal", -2), ("secure", 1), ("shortage", -2), ("sick", -2),
+    ("slow", -1), ("solution", 2), ("solved", 2), ("stable", 1), ("steady", 1), ("strength", 2),
+    ("strong", 2), ("success", 2), ("successful", 2), ("sued", -2), ("suicide", -3), ("superb", 3),
+    ("support", 2), ("supported", 2), ("terrible", -3), ("terror", -3), ("terrorism", -3),
+    ("terrorist", -3), ("threat", -2), ("threatened", -2), ("threats", -2), ("thrilled", 3),
+    ("thriving", 2), ("toxic", -2), ("tragedy", -3), ("tragic", -3), ("triumph", 3),
+    ("uncertain", -1), ("uncertainty", -1), ("unemployment", -2), ("useful", 1), ("victim", -2),
+    ("victims", -2), ("victory", 2), ("violence", -2), ("violent", -2), ("war", -3), ("warn", -2),
+    ("warned", -2), ("warning", -2), ("weak", -1), ("welcome", 2), ("welcomed", 2), ("win", 2),
+    ("winning", 2), ("wins", 2), ("won", 2), ("wonderful", 3), ("worried", -1), ("worry", -1),
+    ("worse", -2), ("worst", -3), ("wrong", -2),
+];
+
+/// Whether pages are scored while they are fetched
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SentimentSettings {
+    /// Score the tone of pages fetched for thumbnails, archives and checks
+    pub enabled: bool,
+}
+
+/// Gets the tone scoring options
+pub fn get_sentiment_settings(conn: &DatabaseConnection) -> crate::db::Result<SentimentSettings> {
+    conn.with_connection(|c| Ok(get_setting(c, SENTIMENT_SETTING)?.unwrap_or_default()))
+}
+
+/// Tone of a page's text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sentiment {
+    /// From -1 (negative) through 0 (neutral) to 1 (positive)
+    pub score: f64,
+    /// Number of words that carried a valence
+    pub scored_words: u64,
+}
+
+/// Valence of a lowercase word, if it carries one
+fn valence(word: &str) -> Option<i8> {
+    LEXICON.binary_search_by(|(entry, _)| (*entry).cmp(word))
+        .ok()
+        .map(|index| LEXICON[index].1)
+}
+
+/// Scores the tone of a page's main text; None when it has too little text
+pub fn sentiment_score(html: &str) -> Option<Sentiment> {
+    let text = visible_text(main_content(html)).to_lowercase();
+    let words: Vec<&str> = text.split(|ch: char| !ch.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.len() < MIN_SCORED_WORDS {
+        return None;
+    }
+
+    let mut total = 0.0;
+    let mut scored_words = 0;
+    for (index, word) in words.iter().enumerate() {
+        let Some(value) = valence(word) else { continue };
+        let negated = words[index.saturating_sub(NEGATION_WINDOW)..index].iter()
+            .any(|before| NEGATIONS.contains(before));
+        total += f64::from(value) * if negated { NEGATION_FACTOR } else { 1.0 };
+        scored_words += 1;
+    }
+
+    let score = total / (3.0 * (scored_words as f64 + NEUTRAL_PRIOR_WORDS));
+    Some(Sentiment { score: score.clamp(-1.0, 1.0), scored_words })
+}
+
+/// Stores the tone of a page in its metadata
+pub fn save_sentiment(c: &Connection, url_id: &str, sentiment: &Sentiment) -> rusqlite::Result<()> {
+    c.execute(
+        "UPDATE metadata SET sentiment = ?, sentiment_words = ?, sentiment_at = ? WHERE url_id = ?",
+        params![sentiment.score, sentiment.scored_words as i64, Utc::now().timestamp(), url_id],
+    )?;
+    Ok(())
+}
+
+/// Outcome of a scoring run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SentimentRun {
+    /// Pages scored
+    pub scored: usize,
+    /// Pages with too little readable text, or behind a wall
+    pub skipped: usize,
+    /// Pages that could not be fetched
+    pub failed: usize,
+    /// One message per failed page
+    pub errors: Vec<String>,
+}
+
+/// Fetches and scores up to `limit` enriched pages not scored yet, most
+/// visited first; pages behind a paywall or login wall are left out
+pub fn score_sentiment(conn: &DatabaseConnection, limit: usize) -> Result<SentimentRun> {
+    let pages: Vec<(String, String)> = conn.with_connection(|c| {
+        let mut stmt = c.prepare(
+            "SELECT u.id, u.url
+             FROM metadata m
+             JOIN url u ON u.id = m.url_id
+             WHERE m.is_enriched = 1 AND m.sentiment_at IS NULL AND m.access_wall IS NULL
+             ORDER BY (SELECT COUNT(*) FROM visit v WHERE v.url_id = u.id) DESC, u.id
+             LIMIT ?"
+        )?;
+        let rows = stmt.query_map([limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    })?;
+
+    let agent = agent();
+    let mut run = SentimentRun::default();
+
+    for (url_id, url) in pages {
+        let sentiment = fetch(&agent, &url, MAX_PAGE_BYTES).map(|page| {
+            if !page.is_html() {
+                return None;
+            }
+            let html = page.text();
+            match detect_access_wall(&page.final_url, &html, &reading_stats(&html)) {
+                Some(_) => None,
+                None => sentiment_score(&html),
+            }
+        });
+
+        match sentiment {
+            Ok(sentiment) => {
+                // Pages without a score are stamped too, so they aren't fetched again
+                conn.with_connection(|c| {
+                    match &sentiment {
+                        Some(sentiment) => save_sentiment(c, &url_id, sentiment)?,
+                        None => {
+                            c.execute(
+                                "UPDATE metadata SET sentiment_at = ? WHERE url_id = ?",
+                                params![Utc::now().timestamp(), url_id],
+                            )?;
+                        },
+                    }
+                    Ok(())
+                })?;
+                match sentiment {
+                    Some(_) => run.scored += 1,
+                    None => run.skipped += 1,
+                }
+            },
+            Err(e) => {
+                run.failed += 1;
+                run.errors.push(format!("{}: {}", url, e));
+            },
+        }
+    }
+
+    Ok(run)
+}
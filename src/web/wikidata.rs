@@ -0,0 +1,250 @@
+// Web - Wikidata Links
+// Links entity keywords and the most visited domains to Wikidata items, so
+// graph nodes carry identifiers shared with external knowledge bases
+
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url as UrlParser;
+
+use crate::db::domains::registrable_domain;
+use crate::db::DatabaseConnection;
+use crate::graph::builder::apply_wikidata_ids;
+use crate::graph::models::NodeType;
+use super::error::{Result, WebError};
+use super::fetch::{agent, fetch};
+
+/// Default number of entities and of domains looked up by one run
+pub const DEFAULT_WIKIDATA_LIMIT: usize = 100;
+
+/// Days before a key without a matching item is looked up again
+const RELINK_UNMATCHED_DAYS: i64 = 30;
+
+/// Wikidata search API, used for entities
+const SEARCH_API: &str = "https://www.wikidata.org/w/api.php";
+
+/// Wikidata SPARQL endpoint, used to find domains by official website
+const SPARQL_ENDPOINT: &str = "https://query.wikidata.org/sparql";
+
+/// Prefix of item IRIs in SPARQL results
+const ENTITY_IRI: &str = "http://www.wikidata.org/entity/";
+
+/// Largest API response read
+const MAX_RESPONSE_BYTES: u64 = 1024 * 1024;
+
+/// Outcome of a linking run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WikidataRun {
+    /// Keys linked to an item
+    pub linked: usize,
+    /// Keys no item was found for
+    pub unmatched: usize,
+    /// Keys whose lookup failed
+    pub failed: usize,
+    /// One message per failed key
+    pub errors: Vec<String>,
+}
+
+/// Wikidata item a key was linked to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WikidataItem {
+    /// Item identifier, e.g. Q95
+    pub qid: String,
+    /// English label
+    pub label: Option<String>,
+    /// English description
+    pub description: Option<String>,
+}
+
+/// Returns true for identifiers shaped like Q42
+fn is_qid(value: &str) -> bool {
+    value.len() > 1 && value.starts_with('Q') && value[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Fetches and parses a JSON response
+fn get_json(agent: &ureq::Agent, base: &str, query: &[(&str, &str)]) -> Result<Value> {
+    let url = UrlParser::parse_with_params(base, query)
+        .map_err(|e| WebError::Content(format!("Invalid Wikidata URL: {}", e)))?;
+    let response = fetch(agent, url.as_str(), MAX_RESPONSE_BYTES)?;
+    serde_json::from_slice(&response.body)
+        .map_err(|e| WebError::Content(format!("Invalid Wikidata response: {}", e)))
+}
+
+/// Reads the best match of a `wbsearchentities` response
+fn parse_search(response: &Value) -> Option<WikidataItem> {
+    let best = response["search"].get(0)?;
+    let qid = best["id"].as_str().filter(|id| is_qid(id))?;
+    Some(WikidataItem {
+        qid: qid.to_string(),
+        label: best["label"].as_str().map(str::to_string),
+        description: best["description"].as_str().map(str::to_string),
+    })
+}
+
+/// Reads the first binding of a SPARQL response selecting ?item
+fn parse_sparql(response: &Value) -> Option<WikidataItem> {
+    let binding = response["results"]["bindings"].get(0)?;
+    let qid = binding["item"]["value"].as_str()?.strip_prefix(ENTITY_IRI).filter(|id| is_qid(id))?;
+    let literal = |name: &str| binding[name]["value"].as_str()
+        .filter(|value| *value != qid)
+        .map(str::to_string);
+    Some(WikidataItem {
+        qid: qid.to_string(),
+        label: literal("itemLabel"),
+        description: literal("itemDescription"),
+    })
+}
+
+/// Searches the item an entity keyword names, taking the best match
+fn search_entity(agent: &ureq::Agent, name: &str) -> Result<Option<WikidataItem>> {
+    let response = get_json(agent, SEARCH_API, &[
+        ("action", "wbsearchentities"),
+        ("search", name),
+        ("language", "en"),
+        ("uselang", "en"),
+        ("type", "item"),
+        ("limit", "1"),
+        ("format", "json"),
+    ])?;
+    Ok(parse_search(&response))
+}
+
+/// Finds the item whose official website (P856) is the domain's home page
+fn find_domain(agent: &ureq::Agent, domain: &str) -> Result<Option<WikidataItem>> {
+    if !domain.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'-') {
+        return Ok(None);
+    }
+    let sites: Vec<String> = ["https", "http"].iter()
+        .flat_map(|scheme| [domain.to_string(), format!("www.{}", domain)].map(|host| (scheme, host)))
+        .flat_map(|(scheme, host)| [format!("<{}://{}>", scheme, host), format!("<{}://{}/>", scheme, host)])
+        .collect();
+    let query = format!(
+        "SELECT ?item ?itemLabel ?itemDescription WHERE {{
+           VALUES ?site {{ {} }}
+           ?item wdt:P856 ?site .
+           SERVICE wikibase:label {{ bd:serviceParam wikibase:language \"en\". }}
+         }} LIMIT 1",
+        sites.join(" "),
+    );
+    let response = get_json(agent, SPARQL_ENDPOINT, &[("query", query.as_str()), ("format", "json")])?;
+    Ok(parse_sparql(&response))
+}
+
+/// Stores the outcome of a lookup
+fn save_link(c: &Connection, node_type: NodeType, key: &str, item: Option<&WikidataItem>, user_defined: bool) -> rusqlite::Result<()> {
+    c.execute(
+        "INSERT OR REPLACE INTO wikidata_link (node_type, key, qid, label, description, user_defined, linked_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+        params![
+            node_type.as_str(),
+            key,
+            item.map(|item| &item.qid),
+            item.and_then(|item| item.label.as_ref()),
+            item.and_then(|item| item.description.as_ref()),
+            user_defined,
+            Utc::now().timestamp(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Links up to `limit` of the most mentioned entities and of the most visited
+/// domains not linked yet, then copies the identifiers onto graph nodes
+pub fn link_wikidata(conn: &DatabaseConnection, limit: usize) -> Result<WikidataRun> {
+    let relink_before = (Utc::now() - Duration::days(RELINK_UNMATCHED_DAYS)).timestamp();
+    let (entities, domains): (Vec<String>, Vec<String>) = conn.with_connection(|c| {
+        let unlinked = "NOT EXISTS (
+            SELECT 1 FROM wikidata_link w
+            WHERE w.node_type = ?1 AND w.key = {key} AND (w.qid IS NOT NULL OR w.user_defined = 1 OR w.linked_at > ?2)
+        )";
+
+        let mut stmt = c.prepare(&format!(
+            "SELECT k.name FROM keyword k
+             JOIN url_keyword uk ON uk.keyword_id = k.id
+             WHERE {}
+             GROUP BY k.id
+             ORDER BY COUNT(*) DESC, k.name
+             LIMIT ?3",
+            unlinked.replace("{key}", "k.name"),
+        ))?;
+        let entities = stmt.query_map(params![NodeType::Entity.as_str(), relink_before, limit as i64], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = c.prepare(&format!(
+            "SELECT d.domain FROM domain_stats d
+             WHERE d.domain LIKE '%.%' AND {}
+             ORDER BY d.visit_count DESC, d.domain
+             LIMIT ?3",
+            unlinked.replace("{key}", "d.domain"),
+        ))?;
+        let domains = stmt.query_map(params![NodeType::Domain.as_str(), relink_before, limit as i64], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok((entities, domains))
+    })?;
+
+    let agent = agent();
+    let mut run = WikidataRun::default();
+    // Subdomains are looked up through the domain they are registered under
+    let mut registrations: HashMap<String, Option<WikidataItem>> = HashMap::new();
+
+    let lookups = entities.into_iter().map(|key| (NodeType::Entity, key))
+        .chain(domains.into_iter().map(|key| (NodeType::Domain, key)));
+    for (node_type, key) in lookups {
+        let item = match node_type {
+            NodeType::Domain => {
+                let registrable = registrable_domain(&key);
+                match registrations.get(&registrable) {
+                    Some(item) => Ok(item.clone()),
+                    None => {
+                        let item = find_domain(&agent, &registrable);
+                        if let Ok(item) = &item {
+                            registrations.insert(registrable, item.clone());
+                        }
+                        item
+                    },
+                }
+            },
+            _ => search_entity(&agent, &key),
+        };
+
+        match item {
+            Ok(item) => {
+                conn.with_connection(|c| Ok(save_link(c, node_type, &key, item.as_ref(), false)?))?;
+                match item {
+                    Some(_) => run.linked += 1,
+                    None => run.unmatched += 1,
+                }
+            },
+            Err(e) => {
+                run.failed += 1;
+                run.errors.push(format!("{}: {}", key, e));
+            },
+        }
+    }
+
+    conn.with_connection(|c| apply_wikidata_ids(c, None))?;
+    Ok(run)
+}
+
+/// Links an entity or domain to a Wikidata item by hand, or marks it as
+/// having none when `qid` is None; later runs keep hand-made links
+pub fn set_wikidata_link(conn: &DatabaseConnection, node_type: NodeType, key: &str, qid: Option<&str>) -> Result<()> {
+    if !matches!(node_type, NodeType::Entity | NodeType::Domain) {
+        return Err(WebError::Content(format!("Only entities and domains link to Wikidata, not {}", node_type.as_str())));
+    }
+    let item = match qid.map(|qid| qid.trim().to_uppercase()) {
+        Some(qid) if !is_qid(&qid) => {
+            return Err(WebError::Content(format!("{} is not a Wikidata item identifier", qid)));
+        },
+        qid => qid.map(|qid| WikidataItem { qid, label: None, description: None }),
+    };
+
+    Ok(conn.transaction(|tx| {
+        save_link(tx, node_type, key, item.as_ref(), true)?;
+        apply_wikidata_ids(tx, None)
+    })?)
+}